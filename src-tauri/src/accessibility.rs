@@ -0,0 +1,251 @@
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+/// Smallest contrast ratio WCAG AA allows for body text
+pub const MIN_CONTRAST_RATIO: f64 = 4.5;
+
+/// Longest excerpt of an element or rule kept in an issue
+const MAX_CONTEXT_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessibilityIssueKind {
+    MissingAltText,
+    SkippedHeadingLevel,
+    LowContrast,
+    MissingLanguage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessibilityIssue {
+    pub kind: AccessibilityIssueKind,
+    pub message: String,
+    /// The image source, heading text or CSS selector the issue is about
+    pub context: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccessibilityReport {
+    pub issues: Vec<AccessibilityIssue>,
+    pub images: usize,
+    pub headings: usize,
+}
+
+impl AccessibilityReport {
+    pub fn passed(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn push(&mut self, kind: AccessibilityIssueKind, message: String, context: &str) {
+        let context = context.trim();
+        let mut excerpt: String = context.chars().take(MAX_CONTEXT_CHARS).collect();
+        if excerpt.len() < context.len() {
+            excerpt.push('…');
+        }
+        self.issues.push(AccessibilityIssue { kind, message, context: excerpt });
+    }
+}
+
+/// Check an exported HTML document for problems screen reader users and
+/// low-vision readers would run into
+///
+/// Contrast is checked for CSS rules in the document's `<style>` elements
+/// that set a text or background color, against the page's colors for
+/// whichever one the rule leaves out.
+pub fn audit_html(html: &str) -> AccessibilityReport {
+    let document = Html::parse_document(html);
+    let mut report = AccessibilityReport::default();
+
+    let lang = document.select(&selector("html")).next().and_then(|root| root.value().attr("lang"));
+    if !lang.is_some_and(|lang| !lang.trim().is_empty()) {
+        report.push(AccessibilityIssueKind::MissingLanguage, "The document doesn't declare its language".to_string(), "<html>");
+    }
+
+    for image in document.select(&selector("img")) {
+        report.images += 1;
+        if !image.value().attr("alt").is_some_and(|alt| !alt.trim().is_empty()) {
+            let src = image.value().attr("src").unwrap_or_default();
+            report.push(AccessibilityIssueKind::MissingAltText, "Image has no alt text".to_string(), src);
+        }
+    }
+
+    let mut previous: Option<usize> = None;
+    for heading in document.select(&selector("h1, h2, h3, h4, h5, h6")) {
+        report.headings += 1;
+        let level = heading.value().name()[1..].parse::<usize>().unwrap_or(1);
+        if let Some(previous) = previous.filter(|previous| level > previous + 1) {
+            let text: String = heading.text().collect();
+            report.push(
+                AccessibilityIssueKind::SkippedHeadingLevel,
+                format!("Heading jumps from h{} to h{}", previous, level),
+                &text,
+            );
+        }
+        previous = Some(level);
+    }
+
+    let css: String = document.select(&selector("style")).flat_map(|style| style.text()).collect();
+    for (rule, ratio) in low_contrast_rules(&css) {
+        report.push(
+            AccessibilityIssueKind::LowContrast,
+            format!("Text contrast is {:.2}:1, below {}:1", ratio, MIN_CONTRAST_RATIO),
+            &rule,
+        );
+    }
+
+    report
+}
+
+type Rgb = [f64; 3];
+
+/// Selectors of rules whose colors contrast too little, with the ratio
+fn low_contrast_rules(css: &str) -> Vec<(String, f64)> {
+    let rules = css_rules(css);
+    let (mut page_text, mut page_background) = ([0.0; 3], [255.0; 3]);
+    for (selector, declarations) in &rules {
+        if selector.split(',').any(|part| matches!(part.trim(), "body" | "html")) {
+            let (text, background) = rule_colors(declarations);
+            page_text = text.unwrap_or(page_text);
+            page_background = background.unwrap_or(page_background);
+        }
+    }
+
+    rules.iter()
+        .filter_map(|(selector, declarations)| {
+            let (text, background) = rule_colors(declarations);
+            if text.is_none() && background.is_none() {
+                return None;
+            }
+            let ratio = contrast_ratio(text.unwrap_or(page_text), background.unwrap_or(page_background));
+            (ratio < MIN_CONTRAST_RATIO).then(|| (selector.clone(), ratio))
+        })
+        .collect()
+}
+
+/// `(selector, declarations)` for each rule, with rules inside `@media`
+/// blocks flattened and comments removed
+fn css_rules(css: &str) -> Vec<(String, String)> {
+    let mut without_comments = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        without_comments.push_str(&rest[..start]);
+        rest = rest[start + 2..].split_once("*/").map_or("", |(_, after)| after);
+    }
+    without_comments.push_str(rest);
+
+    without_comments.split('}')
+        .filter_map(|chunk| {
+            let (selector, declarations) = chunk.rsplit_once('{')?;
+            let selector = selector.rsplit('{').next().unwrap_or(selector).trim();
+            (!selector.is_empty() && !selector.starts_with('@')).then(|| (selector.to_string(), declarations.to_string()))
+        })
+        .collect()
+}
+
+/// The text and background colors a rule sets, where they can be read
+fn rule_colors(declarations: &str) -> (Option<Rgb>, Option<Rgb>) {
+    let (mut text, mut background) = (None, None);
+    for declaration in declarations.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else { continue };
+        match property.trim().to_ascii_lowercase().as_str() {
+            "color" => text = parse_color(value.trim()).or(text),
+            "background" | "background-color" => {
+                background = value.split_whitespace().find_map(parse_color).or(background);
+            }
+            _ => {}
+        }
+    }
+    (text, background)
+}
+
+/// `#rgb`, `#rrggbb`, `rgb(r, g, b)`, `white` and `black`
+fn parse_color(value: &str) -> Option<Rgb> {
+    let value = value.trim().trim_end_matches("!important").trim().to_ascii_lowercase();
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).ok().map(f64::from);
+        return match hex.len() {
+            3 => {
+                let digits: Vec<String> = hex.chars().map(|c| format!("{0}{0}", c)).collect();
+                Some([channel(&digits[0])?, channel(&digits[1])?, channel(&digits[2])?])
+            }
+            6 => Some([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+            _ => None,
+        };
+    }
+    if let Some(channels) = value.strip_prefix("rgb(").and_then(|rest| rest.strip_suffix(')')) {
+        let channels: Vec<f64> = channels.split([',', ' ']).filter(|c| !c.is_empty()).map(|c| c.parse().ok()).collect::<Option<_>>()?;
+        return match channels[..] {
+            [r, g, b] => Some([r, g, b]),
+            _ => None,
+        };
+    }
+    match value.as_str() {
+        "white" => Some([255.0; 3]),
+        "black" => Some([0.0; 3]),
+        _ => None,
+    }
+}
+
+/// WCAG contrast ratio of two colors, from 1 to 21
+pub fn contrast_ratio(a: Rgb, b: Rgb) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn relative_luminance(rgb: Rgb) -> f64 {
+    let linear = |channel: f64| {
+        let channel = channel / 255.0;
+        if channel <= 0.03928 { channel / 12.92 } else { ((channel + 0.055) / 1.055).powf(2.4) }
+    };
+    0.2126 * linear(rgb[0]) + 0.7152 * linear(rgb[1]) + 0.0722 * linear(rgb[2])
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("valid selector")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_finds_issues() {
+        let html = r#"<!DOCTYPE html><html><head><style>
+            body { color: #333; background: #fff; }
+            /* .note { color: #eee; } */
+            .faint { color: #bbbbbb; }
+            @media print { .badge { background: #ffff00; color: white !important; } }
+        </style></head><body>
+            <h1>Title</h1><h3>Deep</h3><h2>Back up</h2><h3>Fine</h3>
+            <img src="chart.png" alt="Sales by month"><img src="logo.png"><img src="x.png" alt=" ">
+        </body></html>"#;
+
+        let report = audit_html(html);
+        let kinds: Vec<_> = report.issues.iter().map(|issue| (issue.kind, issue.context.as_str())).collect();
+        assert_eq!(kinds, vec![
+            (AccessibilityIssueKind::MissingLanguage, "<html>"),
+            (AccessibilityIssueKind::MissingAltText, "logo.png"),
+            (AccessibilityIssueKind::MissingAltText, "x.png"),
+            (AccessibilityIssueKind::SkippedHeadingLevel, "Deep"),
+            (AccessibilityIssueKind::LowContrast, ".faint"),
+            (AccessibilityIssueKind::LowContrast, ".badge"),
+        ]);
+        assert_eq!((report.images, report.headings), (3, 4));
+        assert!(report.issues[3].message.contains("h1 to h3"));
+    }
+
+    #[test]
+    fn test_contrast_and_clean_document() {
+        assert!((contrast_ratio([0.0; 3], [255.0; 3]) - 21.0).abs() < 1e-9);
+        assert_eq!(parse_color("#0366D6"), Some([3.0, 102.0, 214.0]));
+        assert_eq!(parse_color("rgb(10, 20, 30)"), Some([10.0, 20.0, 30.0]));
+        assert_eq!(parse_color("inherit"), None);
+
+        let html = "<html lang=\"de\"><head><style>body { background: #111; color: #eee; } .dim { color: #555; }</style></head>\
+            <body><h2>Start</h2><h3>Sub</h3><img src=\"a.png\" alt=\"A\"></body></html>";
+        let report = audit_html(html);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].context, ".dim");
+        assert!(!report.passed());
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use axum::extract::{Query, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tracing::{debug, error, info};
+
+use crate::export::{ExportOptions, ExportResult};
+use crate::file_service::SearchMatch;
+use crate::parser::ParsedDocument;
+
+/// Keychain entry holding the bearer token clients must send
+pub const API_TOKEN_KEY: &str = "api-token";
+
+pub const DEFAULT_API_PORT: u16 = 27183;
+
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+const MAX_SEARCH_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvertRequest {
+    pub markdown: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRequest {
+    pub markdown: String,
+    /// Where to write the file; defaults to a new file in the export scratch directory
+    pub output_path: Option<PathBuf>,
+    #[serde(default)]
+    pub options: ExportOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchRequest {
+    pub q: String,
+    /// Folder to search; defaults to the active workspace
+    pub dir: Option<PathBuf>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiServerInfo {
+    pub port: u16,
+    pub url: String,
+}
+
+/// The app functionality exposed over the API
+#[async_trait]
+pub trait ApiBackend: Send + Sync + 'static {
+    async fn convert(&self, request: ConvertRequest) -> Result<ParsedDocument>;
+    async fn export(&self, request: ExportRequest) -> Result<ExportResult>;
+    async fn search(&self, request: SearchRequest) -> Result<Vec<SearchMatch>>;
+}
+
+#[derive(Clone)]
+struct ApiState {
+    backend: Arc<dyn ApiBackend>,
+    token: Arc<str>,
+}
+
+struct RunningServer {
+    info: ApiServerInfo,
+    shutdown: oneshot::Sender<()>,
+}
+
+/// Token-guarded REST API on localhost for scripts and other apps
+#[derive(Default)]
+pub struct ApiServer {
+    running: tokio::sync::Mutex<Option<RunningServer>>,
+}
+
+impl ApiServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start serving, replacing any running server so a new port or token takes effect
+    pub async fn start(&self, port: Option<u16>, token: String, backend: Arc<dyn ApiBackend>) -> Result<ApiServerInfo> {
+        if token.is_empty() {
+            return Err(anyhow::anyhow!("The API needs an access token"));
+        }
+
+        let mut running = self.running.lock().await;
+        if let Some(server) = running.take() {
+            let _ = server.shutdown.send(());
+        }
+
+        // Only this machine may connect; the token keeps other local users and web pages out
+        let port = port.unwrap_or(DEFAULT_API_PORT);
+        let listener = std::net::TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port))
+            .with_context(|| format!("Failed to open API port {}", port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        let state = ApiState { backend, token: token.into() };
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let server = axum::Server::from_tcp(listener)?
+            .serve(router(state).into_make_service())
+            .with_graceful_shutdown(async {
+                let _ = shutdown_rx.await;
+            });
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!("API server stopped with an error: {}", e);
+            }
+        });
+
+        let info = ApiServerInfo { port, url: format!("http://127.0.0.1:{}/v1/", port) };
+        info!("Automation API listening on port {}", port);
+        *running = Some(RunningServer { info: info.clone(), shutdown });
+        Ok(info)
+    }
+
+    /// Stop the server; stopping a server that isn't running is a no-op
+    pub async fn stop(&self) {
+        if let Some(server) = self.running.lock().await.take() {
+            let _ = server.shutdown.send(());
+            info!("Automation API on port {} stopped", server.info.port);
+        }
+    }
+
+    pub async fn info(&self) -> Option<ApiServerInfo> {
+        self.running.lock().await.as_ref().map(|server| server.info.clone())
+    }
+}
+
+/// A fresh random access token
+pub fn generate_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/v1/convert", post(convert))
+        .route("/v1/export", post(export))
+        .route("/v1/search", get(search))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+async fn require_token<B>(State(state): State<ApiState>, request: Request<B>, next: Next<B>) -> Response {
+    let authorized = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| tokens_match(token.trim(), &state.token));
+
+    if !authorized {
+        debug!("Rejected API request to {} without a valid token", request.uri().path());
+        return ApiError::new(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token").into_response();
+    }
+    next.run(request).await
+}
+
+/// Compare without exiting early, so response timing doesn't reveal the token
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+async fn convert(State(state): State<ApiState>, Json(request): Json<ConvertRequest>) -> Result<Json<ParsedDocument>, ApiError> {
+    debug!("API convert ({} chars)", request.markdown.len());
+    Ok(Json(state.backend.convert(request).await?))
+}
+
+async fn export(State(state): State<ApiState>, Json(request): Json<ExportRequest>) -> Result<Json<ExportResult>, ApiError> {
+    debug!("API export to {:?} as {:?}", request.output_path, request.options.format);
+    Ok(Json(state.backend.export(request).await?))
+}
+
+async fn search(State(state): State<ApiState>, Query(mut request): Query<SearchRequest>) -> Result<Json<Vec<SearchMatch>>, ApiError> {
+    debug!("API search for {:?}", request.q);
+    request.limit = Some(request.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT));
+    Ok(Json(state.backend.search(request).await?))
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// A JSON `{"error": ...}` response
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        error!("API request failed: {:#}", error);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", error))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(ErrorBody { error: self.message })).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EchoBackend;
+
+    #[async_trait]
+    impl ApiBackend for EchoBackend {
+        async fn convert(&self, request: ConvertRequest) -> Result<ParsedDocument> {
+            crate::parser::MarkdownParser::new().parse(&request.markdown)
+        }
+
+        async fn export(&self, _request: ExportRequest) -> Result<ExportResult> {
+            Err(anyhow::anyhow!("Exports are disabled"))
+        }
+
+        async fn search(&self, request: SearchRequest) -> Result<Vec<SearchMatch>> {
+            Ok(vec![SearchMatch {
+                path: PathBuf::from("notes.md"),
+                line: request.limit.unwrap_or_default(),
+                text: request.q,
+            }])
+        }
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc124", "abc123"));
+        assert!(!tokens_match("abc", "abc123"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_the_token() {
+        let server = ApiServer::new();
+        let info = server.start(Some(0), "secret".to_string(), Arc::new(EchoBackend)).await.unwrap();
+        let client = reqwest::Client::new();
+        let body = serde_json::json!({ "markdown": "# Title" });
+
+        let rejected = client.post(format!("{}convert", info.url)).json(&body).send().await.unwrap();
+        assert_eq!(rejected.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let converted: serde_json::Value = client.post(format!("{}convert", info.url))
+            .bearer_auth("secret").json(&body).send().await.unwrap().json().await.unwrap();
+        assert!(converted["html"].as_str().unwrap().contains("Title</h1>"));
+
+        let found: serde_json::Value = client.get(format!("{}search?q=todo&limit=9999", info.url))
+            .bearer_auth("secret").send().await.unwrap().json().await.unwrap();
+        assert_eq!(found[0]["text"], "todo");
+        assert_eq!(found[0]["line"], MAX_SEARCH_LIMIT);
+
+        let failed = client.post(format!("{}export", info.url))
+            .bearer_auth("secret").json(&body).send().await.unwrap();
+        assert_eq!(failed.status(), reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(failed.json::<serde_json::Value>().await.unwrap()["error"], "Exports are disabled");
+
+        server.stop().await;
+    }
+}
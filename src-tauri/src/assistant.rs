@@ -0,0 +1,150 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::settings::{AiProviderKind, AiSettings};
+
+/// Keychain entry holding the API key of the configured provider
+pub const AI_API_KEY_KEY: &str = "ai-api-key";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum AssistAction {
+    Summarize,
+    Rewrite,
+    FixGrammar,
+    Continue,
+}
+
+impl AssistAction {
+    fn instructions(&self) -> &'static str {
+        match self {
+            AssistAction::Summarize => "Summarize the following Markdown text concisely. Reply with Markdown only.",
+            AssistAction::Rewrite => "Rewrite the following Markdown text to be clearer while keeping its meaning and formatting. Reply with the rewritten Markdown only.",
+            AssistAction::FixGrammar => "Fix spelling and grammar mistakes in the following Markdown text without changing its meaning or formatting. Reply with the corrected Markdown only.",
+            AssistAction::Continue => "Continue writing the following Markdown text in the same voice and style. Reply with the continuation only.",
+        }
+    }
+}
+
+/// Proxy for AI writing assistance, keeping provider credentials on the backend
+pub struct AssistantService {
+    client: reqwest::Client,
+}
+
+impl Default for AssistantService {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .unwrap_or_default();
+
+        Self { client }
+    }
+}
+
+impl AssistantService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run an assist action on the selected text using the configured provider
+    pub async fn assist(&self, action: AssistAction, text: &str, settings: &AiSettings, api_key: Option<&str>) -> Result<String> {
+        let api_key = api_key
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("No AI assistant API key configured"))?;
+
+        debug!("Sending {:?} request to {} ({} chars)", action, settings.endpoint, text.len());
+
+        let request = self.client
+            .post(&settings.endpoint)
+            .json(&build_request_body(settings, action, text));
+        let request = match settings.provider {
+            AiProviderKind::OpenAiCompatible => request.bearer_auth(api_key),
+            AiProviderKind::Anthropic => request
+                .header("x-api-key", api_key)
+                .header("anthropic-version", "2023-06-01"),
+        };
+
+        let response: Value = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach AI endpoint: {}", settings.endpoint))?
+            .error_for_status()
+            .context("AI endpoint returned an error")?
+            .json()
+            .await
+            .context("Invalid response from AI endpoint")?;
+
+        let output = extract_response_text(&settings.provider, &response)?;
+        info!("AI assist {:?} complete ({} chars)", action, output.len());
+        Ok(output)
+    }
+}
+
+fn build_request_body(settings: &AiSettings, action: AssistAction, text: &str) -> Value {
+    match settings.provider {
+        AiProviderKind::OpenAiCompatible => json!({
+            "model": settings.model,
+            "messages": [
+                { "role": "system", "content": action.instructions() },
+                { "role": "user", "content": text },
+            ],
+        }),
+        AiProviderKind::Anthropic => json!({
+            "model": settings.model,
+            "max_tokens": 2048,
+            "system": action.instructions(),
+            "messages": [
+                { "role": "user", "content": text },
+            ],
+        }),
+    }
+}
+
+fn extract_response_text(provider: &AiProviderKind, response: &Value) -> Result<String> {
+    let text = match provider {
+        AiProviderKind::OpenAiCompatible => response["choices"][0]["message"]["content"].as_str(),
+        AiProviderKind::Anthropic => response["content"][0]["text"].as_str(),
+    };
+
+    text.map(|text| text.trim().to_string())
+        .ok_or_else(|| anyhow::anyhow!("AI response did not contain any text"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_body_per_provider() {
+        let mut settings = AiSettings::default();
+        let body = build_request_body(&settings, AssistAction::Summarize, "Some text");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "Some text");
+
+        settings.provider = AiProviderKind::Anthropic;
+        let body = build_request_body(&settings, AssistAction::Summarize, "Some text");
+        assert_eq!(body["system"], AssistAction::Summarize.instructions());
+        assert_eq!(body["messages"][0]["content"], "Some text");
+    }
+
+    #[test]
+    fn test_extract_response_text() {
+        let openai = json!({ "choices": [{ "message": { "content": " Fixed text\n" } }] });
+        let anthropic = json!({ "content": [{ "type": "text", "text": "Summary" }] });
+
+        assert_eq!(extract_response_text(&AiProviderKind::OpenAiCompatible, &openai).unwrap(), "Fixed text");
+        assert_eq!(extract_response_text(&AiProviderKind::Anthropic, &anthropic).unwrap(), "Summary");
+        assert!(extract_response_text(&AiProviderKind::Anthropic, &openai).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_assist_requires_api_key() {
+        let service = AssistantService::new();
+
+        assert!(service.assist(AssistAction::Rewrite, "text", &AiSettings::default(), None).await.is_err());
+        assert!(service.assist(AssistAction::Rewrite, "text", &AiSettings::default(), Some("")).await.is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frontmatter::FrontMatter;
+
+/// Front matter key setting a document's direction: `rtl`, `ltr` or `auto`
+pub const DIRECTION_KEY: &str = "dir";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl TextDirection {
+    /// Value for an HTML `dir` attribute
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TextDirection::Ltr => "ltr",
+            TextDirection::Rtl => "rtl",
+        }
+    }
+}
+
+/// The direction a character forces on the text around it, if any
+///
+/// Letters of right-to-left scripts (Hebrew, Arabic, Syriac, Thaana, N'Ko and
+/// their presentation forms) are RTL; other letters are LTR. Digits,
+/// punctuation and symbols are neutral.
+pub fn strong_direction(c: char) -> Option<TextDirection> {
+    let rtl = matches!(c as u32,
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF);
+    if rtl && c.is_alphabetic() {
+        Some(TextDirection::Rtl)
+    } else if c.is_alphabetic() {
+        Some(TextDirection::Ltr)
+    } else {
+        None
+    }
+}
+
+/// Direction of the first strongly directional character, as browsers do for `dir="auto"`
+pub fn first_strong_direction(text: &str) -> Option<TextDirection> {
+    text.chars().find_map(strong_direction)
+}
+
+/// Direction most of the text's letters are written in, so an RTL document
+/// keeps its direction despite an English title or code samples
+pub fn dominant_direction(text: &str) -> Option<TextDirection> {
+    let (mut ltr, mut rtl) = (0usize, 0usize);
+    for direction in text.chars().filter_map(strong_direction) {
+        match direction {
+            TextDirection::Ltr => ltr += 1,
+            TextDirection::Rtl => rtl += 1,
+        }
+    }
+    match (ltr, rtl) {
+        (0, 0) => None,
+        _ if rtl > ltr => Some(TextDirection::Rtl),
+        _ => Some(TextDirection::Ltr),
+    }
+}
+
+/// A document's direction: its `dir` front matter, or detected from its text
+pub fn document_direction(markdown: &str) -> TextDirection {
+    let (front_matter, body) = FrontMatter::parse(markdown).unwrap_or_else(|_| (FrontMatter::default(), markdown));
+    match front_matter.get_str(DIRECTION_KEY).map(|dir| dir.trim().to_lowercase()).as_deref() {
+        Some("rtl") => TextDirection::Rtl,
+        Some("ltr") => TextDirection::Ltr,
+        _ => dominant_direction(body).unwrap_or_default(),
+    }
+}
+
+/// Invisible characters that only steer bidirectional layout, e.g. the
+/// right-to-left mark, which shouldn't make a word out of punctuation
+pub fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_direction() {
+        assert_eq!(first_strong_direction("123 «שלום» world"), Some(TextDirection::Rtl));
+        assert_eq!(first_strong_direction("- 42."), None);
+        assert_eq!(strong_direction('ب'), Some(TextDirection::Rtl));
+        assert_eq!(strong_direction('٣'), None); // Arabic-Indic digits are neutral
+
+        // An English heading doesn't outweigh an Arabic body
+        assert_eq!(dominant_direction("# Notes\n\nمرحبا بالعالم، هذه وثيقة عربية"), Some(TextDirection::Rtl));
+        assert_eq!(dominant_direction("Hello, שלום"), Some(TextDirection::Ltr));
+    }
+
+    #[test]
+    fn test_document_direction() {
+        assert_eq!(document_direction("---\ndir: rtl\n---\nPlain English"), TextDirection::Rtl);
+        assert_eq!(document_direction("---\ndir: auto\n---\nשלום עולם"), TextDirection::Rtl);
+        assert_eq!(document_direction("---\ndir: ltr\n---\nשלום עולם"), TextDirection::Ltr);
+        assert_eq!(document_direction("123"), TextDirection::Ltr);
+    }
+}
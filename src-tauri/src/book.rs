@@ -0,0 +1,230 @@
+use anyhow::{Result, Context};
+use html_escape::encode_text;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::export::ExportOptions;
+use crate::merge::{merge_documents, MergeOptions};
+
+/// Manifest at the root of a book project
+pub const MANIFEST_FILE: &str = "book.toml";
+
+/// Folder exports go to when the manifest doesn't name an output file
+const DEFAULT_OUTPUT_DIR: &str = "book";
+
+/// The `[book]` table of a manifest
+///
+/// ```toml
+/// [book]
+/// title = "Field Guide"
+/// authors = ["Ada Lovelace"]
+/// chapters = ["intro.md", "chapters/setup.md"]
+///
+/// [export]
+/// format = "Pdf"
+/// page_size = "Letter"
+///
+/// [merge]
+/// page_breaks = false
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    /// Chapter files relative to the project root, in reading order
+    pub chapters: Vec<PathBuf>,
+    /// Export file relative to the project root; defaults to `book/<title>.<ext>`
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    book: BookInfo,
+    /// Overrides on top of the default export options
+    #[serde(default)]
+    export: Option<toml::Table>,
+    #[serde(default)]
+    merge: MergeOptions,
+}
+
+/// A loaded book project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookProject {
+    pub root: PathBuf,
+    pub book: BookInfo,
+    pub export: ExportOptions,
+    pub merge: MergeOptions,
+    /// Chapters listed in the manifest that don't exist
+    pub missing_chapters: Vec<PathBuf>,
+}
+
+impl BookProject {
+    pub async fn load(root: &Path) -> Result<Self> {
+        let path = root.join(MANIFEST_FILE);
+        let content = tokio::fs::read_to_string(&path).await
+            .with_context(|| format!("No {} in {:?}", MANIFEST_FILE, root))?;
+        let manifest: Manifest = toml::from_str(&content)
+            .with_context(|| format!("Invalid book manifest: {:?}", path))?;
+
+        for chapter in &manifest.book.chapters {
+            validate_chapter_path(chapter)?;
+        }
+        let mut missing_chapters = Vec::new();
+        for chapter in &manifest.book.chapters {
+            if !tokio::fs::try_exists(root.join(chapter)).await.unwrap_or(false) {
+                missing_chapters.push(chapter.clone());
+            }
+        }
+
+        debug!("Loaded book {:?} with {} chapters", manifest.book.title, manifest.book.chapters.len());
+        Ok(Self {
+            root: root.to_path_buf(),
+            export: export_options(manifest.export)?,
+            book: manifest.book,
+            merge: manifest.merge,
+            missing_chapters,
+        })
+    }
+
+    /// Absolute paths of the chapters, in reading order
+    pub fn chapter_paths(&self) -> Vec<PathBuf> {
+        self.book.chapters.iter().map(|chapter| self.root.join(chapter)).collect()
+    }
+
+    pub fn output_path(&self) -> PathBuf {
+        match &self.book.output {
+            Some(output) => self.root.join(output),
+            None => {
+                let name: String = self.book.title.chars()
+                    .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+                    .collect();
+                let name = if name.trim_matches('_').is_empty() { "book".to_string() } else { name };
+                self.root.join(DEFAULT_OUTPUT_DIR).join(format!("{}.{}", name, self.export.format.extension()))
+            }
+        }
+    }
+
+    /// The whole book as one markdown document: a title page, then the chapters
+    /// (path and markdown, in reading order) merged with the project's options
+    pub fn assemble(&self, chapters: &[(PathBuf, String)]) -> String {
+        let merged = merge_documents(chapters, &self.merge);
+        let mut markdown = String::from("<div class=\"title-page\">\n");
+        markdown.push_str(&format!("<p class=\"book-title\">{}</p>\n", encode_text(&self.book.title)));
+        if !self.book.authors.is_empty() {
+            markdown.push_str(&format!("<p class=\"book-authors\">{}</p>\n", encode_text(&self.book.authors.join(", "))));
+        }
+        if let Some(description) = &self.book.description {
+            markdown.push_str(&format!("<p class=\"book-description\">{}</p>\n", encode_text(description)));
+        }
+        markdown.push_str("</div>\n\n<div style=\"break-before: page; page-break-before: always;\"></div>\n\n");
+        markdown.push_str(&merged.markdown);
+        markdown
+    }
+}
+
+/// Rewrite the manifest's chapter list, keeping the rest of it
+///
+/// The manifest is re-serialized, so comments in it are not kept.
+pub async fn set_book_chapters(root: &Path, chapters: Vec<PathBuf>) -> Result<BookProject> {
+    for chapter in &chapters {
+        validate_chapter_path(chapter)?;
+    }
+    let path = root.join(MANIFEST_FILE);
+    let content = tokio::fs::read_to_string(&path).await
+        .with_context(|| format!("No {} in {:?}", MANIFEST_FILE, root))?;
+    let mut manifest: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Invalid book manifest: {:?}", path))?;
+
+    let book = manifest.entry("book").or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    let book = book.as_table_mut().context("[book] in the manifest must be a table")?;
+    let list = chapters.iter()
+        .map(|chapter| toml::Value::String(chapter.to_string_lossy().replace('\\', "/")))
+        .collect();
+    book.insert("chapters".to_string(), toml::Value::Array(list));
+
+    tokio::fs::write(&path, toml::to_string(&manifest)?).await
+        .with_context(|| format!("Failed to write book manifest: {:?}", path))?;
+    info!("Saved {} chapters to {:?}", chapters.len(), path);
+    BookProject::load(root).await
+}
+
+/// Chapters must stay inside the project
+fn validate_chapter_path(chapter: &Path) -> Result<()> {
+    let escapes = chapter.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes || chapter.as_os_str().is_empty() {
+        anyhow::bail!("Chapter paths must be relative to the book folder: {:?}", chapter);
+    }
+    Ok(())
+}
+
+/// Default export options with the manifest's `[export]` table laid over them
+fn export_options(overrides: Option<toml::Table>) -> Result<ExportOptions> {
+    let Some(overrides) = overrides else {
+        return Ok(ExportOptions::default());
+    };
+    let mut options = serde_json::to_value(ExportOptions::default())?;
+    if let (Some(fields), serde_json::Value::Object(overrides)) = (options.as_object_mut(), serde_json::to_value(overrides)?) {
+        fields.extend(overrides);
+    }
+    serde_json::from_value(options).context("Invalid [export] options in the book manifest")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportFormat;
+    use tempfile::TempDir;
+
+    const MANIFEST: &str = r#"
+[book]
+title = "Field Guide"
+authors = ["Ada", "Grace"]
+chapters = ["intro.md", "chapters/setup.md", "missing.md"]
+
+[export]
+format = "Html"
+include_toc = false
+
+[merge]
+page_breaks = false
+"#;
+
+    #[tokio::test]
+    async fn test_load_and_reorder_book() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(MANIFEST_FILE), MANIFEST).unwrap();
+        std::fs::create_dir(dir.path().join("chapters")).unwrap();
+        std::fs::write(dir.path().join("intro.md"), "# Intro\n").unwrap();
+        std::fs::write(dir.path().join("chapters/setup.md"), "# Setup\n").unwrap();
+
+        let project = BookProject::load(dir.path()).await.unwrap();
+        assert_eq!(project.book.authors, vec!["Ada", "Grace"]);
+        assert!(matches!(project.export.format, ExportFormat::Html));
+        assert!(!project.export.include_toc && !project.merge.page_breaks && project.merge.number_chapters);
+        assert_eq!(project.missing_chapters, vec![PathBuf::from("missing.md")]);
+        assert_eq!(project.output_path(), dir.path().join("book/Field_Guide.html"));
+
+        let reordered = set_book_chapters(dir.path(), vec![PathBuf::from("chapters/setup.md"), PathBuf::from("intro.md")]).await.unwrap();
+        assert_eq!(reordered.chapter_paths(), vec![dir.path().join("chapters/setup.md"), dir.path().join("intro.md")]);
+        assert!(reordered.missing_chapters.is_empty());
+        assert!(std::fs::read_to_string(dir.path().join(MANIFEST_FILE)).unwrap().contains("include_toc = false"));
+
+        assert!(set_book_chapters(dir.path(), vec![PathBuf::from("../secrets.md")]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_assemble_adds_title_page() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(MANIFEST_FILE), "[book]\ntitle = \"Notes & Essays\"\n").unwrap();
+        let project = BookProject::load(dir.path()).await.unwrap();
+
+        let markdown = project.assemble(&[(PathBuf::from("one.md"), "# One\n\nText.\n".to_string())]);
+        assert!(markdown.starts_with("<div class=\"title-page\">\n<p class=\"book-title\">Notes &amp; Essays</p>\n</div>\n"));
+        assert!(markdown.ends_with("# 1. One\n\nText.\n\n"));
+    }
+}
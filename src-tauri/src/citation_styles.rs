@@ -0,0 +1,181 @@
+use anyhow::{Result, Context};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::frontmatter::FrontMatter;
+use crate::settings::app_config_dir;
+
+/// Front matter key choosing a document's citation style, as in pandoc
+pub const CSL_KEY: &str = "csl";
+
+/// Folder of `.csl` files users can choose from
+pub fn citation_styles_dir() -> PathBuf {
+    app_config_dir().join("csl")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CitationStyle {
+    /// File name without `.csl`, which documents use to pick the style
+    pub name: String,
+    pub title: String,
+    /// The style's CSL id, e.g. `http://www.zotero.org/styles/apa`
+    pub id: String,
+    pub path: PathBuf,
+}
+
+/// The `.csl` files in `dir`, by title; files that aren't valid styles are skipped
+pub async fn citation_styles_in(dir: &Path) -> Result<Vec<CitationStyle>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read citation styles folder: {:?}", dir)),
+    };
+
+    let mut styles = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csl")) {
+            continue;
+        }
+        match load_citation_style(&path).await {
+            Ok(style) => styles.push(style),
+            Err(e) => warn!("Skipping citation style {:?}: {}", path, e),
+        }
+    }
+    styles.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
+    debug!("Found {} citation styles in {:?}", styles.len(), dir);
+    Ok(styles)
+}
+
+pub async fn load_citation_style(path: &Path) -> Result<CitationStyle> {
+    let xml = tokio::fs::read_to_string(path).await
+        .with_context(|| format!("Failed to read citation style: {:?}", path))?;
+    let (id, title) = parse_style_info(&xml)?;
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(CitationStyle { title: title.unwrap_or_else(|| name.clone()), name, id, path: path.to_path_buf() })
+}
+
+/// CSL id of the style to format a document's bibliography with
+///
+/// The document's `csl` front matter wins over `default` (the style in the
+/// Zotero settings). Either may name a `.csl` file, looked up next to the
+/// document and then in `styles_dir`, or give a style id directly. Styles are
+/// formatted by Zotero, so they need to be installed there too.
+pub async fn document_citation_style(markdown: &str, document: Option<&Path>, styles_dir: &Path, default: Option<&str>) -> Result<Option<String>> {
+    let (front_matter, _) = FrontMatter::parse(markdown)?;
+    let chosen = front_matter.get_str(CSL_KEY)
+        .or_else(|| default.map(str::to_string))
+        .map(|style| style.trim().to_string())
+        .filter(|style| !style.is_empty());
+    let Some(style) = chosen else {
+        return Ok(None);
+    };
+    if style.contains("://") {
+        return Ok(Some(style));
+    }
+
+    let mut file = PathBuf::from(&style);
+    if !file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csl")) {
+        file.set_extension("csl");
+    }
+    let path = document.and_then(Path::parent).into_iter()
+        .chain(std::iter::once(styles_dir))
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .with_context(|| format!("Citation style not found next to the document or in the styles folder: {}", style))?;
+    Ok(Some(load_citation_style(&path).await?.id))
+}
+
+/// The `<id>` and `<title>` from a style's `<info>` block
+fn parse_style_info(xml: &str) -> Result<(String, Option<String>)> {
+    let mut reader = Reader::from_str(xml);
+    let (mut id, mut title) = (None::<String>, None::<String>);
+    let mut in_info = false;
+    let mut field: Option<&'static str> = None;
+    let mut is_style = false;
+
+    loop {
+        match reader.read_event().context("Invalid CSL file")? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"style" => is_style = true,
+                b"info" => in_info = true,
+                b"id" if in_info => field = Some("id"),
+                b"title" if in_info => field = Some("title"),
+                _ => {}
+            },
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                match field {
+                    Some("id") => id.get_or_insert_with(String::new).push_str(text.trim()),
+                    Some(_) => title.get_or_insert_with(String::new).push_str(text.trim()),
+                    None => {}
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"info" => break,
+                b"id" | b"title" => field = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !is_style {
+        anyhow::bail!("Not a CSL style");
+    }
+    let id = id.filter(|id| !id.is_empty()).context("CSL style has no id")?;
+    Ok((id, title.filter(|title| !title.is_empty())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn style(id: &str, title: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<style xmlns=\"http://purl.org/net/xbiblio/csl\" class=\"in-text\" version=\"1.0\">\n  <info>\n    <title>{}</title>\n    <id>{}</id>\n  </info>\n  <citation><layout/></citation>\n</style>\n",
+            title, id,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_list_citation_styles() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("ieee.csl"), style("http://www.zotero.org/styles/ieee", "IEEE")).unwrap();
+        std::fs::write(dir.path().join("apa.csl"), style("http://www.zotero.org/styles/apa", "American Psychological Association 7th edition")).unwrap();
+        std::fs::write(dir.path().join("broken.csl"), "<html></html>").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "# Not a style").unwrap();
+
+        let styles = citation_styles_in(dir.path()).await.unwrap();
+        let names: Vec<_> = styles.iter().map(|style| style.name.as_str()).collect();
+        assert_eq!(names, vec!["apa", "ieee"]);
+        assert_eq!(styles[1].id, "http://www.zotero.org/styles/ieee");
+        assert!(citation_styles_in(&dir.path().join("missing")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_document_citation_style() {
+        let dir = TempDir::new().unwrap();
+        let styles = dir.path().join("csl");
+        std::fs::create_dir(&styles).unwrap();
+        std::fs::write(styles.join("chicago.csl"), style("http://www.zotero.org/styles/chicago-author-date", "Chicago")).unwrap();
+        std::fs::write(dir.path().join("local.csl"), style("http://example.com/styles/local", "Local")).unwrap();
+        let document = dir.path().join("paper.md");
+
+        let chosen = |markdown: &'static str, default: Option<&'static str>| {
+            let (document, styles) = (document.clone(), styles.clone());
+            async move { document_citation_style(markdown, Some(&document), &styles, default).await }
+        };
+        assert_eq!(chosen("---\ncsl: chicago\n---\n", None).await.unwrap().as_deref(), Some("http://www.zotero.org/styles/chicago-author-date"));
+        assert_eq!(chosen("---\ncsl: local.csl\n---\n", Some("chicago")).await.unwrap().as_deref(), Some("http://example.com/styles/local"));
+        assert_eq!(chosen("# No front matter\n", Some("chicago")).await.unwrap().as_deref(), Some("http://www.zotero.org/styles/chicago-author-date"));
+        assert_eq!(chosen("# Zotero id\n", Some("http://www.zotero.org/styles/apa")).await.unwrap().as_deref(), Some("http://www.zotero.org/styles/apa"));
+        assert_eq!(chosen("# Default\n", None).await.unwrap(), None);
+        assert!(chosen("---\ncsl: missing\n---\n", None).await.is_err());
+    }
+}
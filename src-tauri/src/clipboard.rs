@@ -0,0 +1,43 @@
+use anyhow::{Result, Context};
+use std::sync::Mutex;
+use tracing::debug;
+
+/// System clipboard access supporting rich (HTML) and plain text flavors
+///
+/// The underlying handle is kept alive for the lifetime of the service since
+/// on X11 the clipboard contents are served by the owning process.
+#[derive(Default)]
+pub struct ClipboardService {
+    clipboard: Mutex<Option<arboard::Clipboard>>,
+}
+
+impl ClipboardService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Place HTML on the clipboard, with `alt_text` for apps that only accept plain text
+    pub fn copy_html(&self, html: &str, alt_text: &str) -> Result<()> {
+        debug!("Copying HTML to clipboard ({} bytes)", html.len());
+        self.with_clipboard(|clipboard| clipboard.set_html(html, Some(alt_text)))
+    }
+
+    /// Place plain text on the clipboard
+    pub fn copy_text(&self, text: &str) -> Result<()> {
+        debug!("Copying text to clipboard ({} bytes)", text.len());
+        self.with_clipboard(|clipboard| clipboard.set_text(text))
+    }
+
+    fn with_clipboard<F>(&self, action: F) -> Result<()>
+    where
+        F: FnOnce(&mut arboard::Clipboard) -> Result<(), arboard::Error>,
+    {
+        let mut guard = self.clipboard.lock().unwrap();
+        if guard.is_none() {
+            *guard = Some(arboard::Clipboard::new().context("Failed to access the system clipboard")?);
+        }
+
+        let clipboard = guard.as_mut().expect("clipboard initialized above");
+        action(clipboard).context("Failed to write to the clipboard")
+    }
+}
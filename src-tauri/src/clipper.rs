@@ -0,0 +1,528 @@
+use anyhow::{Result, Context};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::frontmatter::FrontMatter;
+use crate::import::{strip_element, ImportService};
+use crate::static_site::{rewrite_destinations, rewrite_image_paths, slugify};
+
+/// Page furniture removed from the extracted article before conversion
+const BOILERPLATE_ELEMENTS: &[&str] = &["nav", "aside", "footer", "form", "button", "iframe", "svg"];
+
+/// Class and id fragments that mark page furniture rather than content
+const NEGATIVE_HINTS: &[&str] = &["comment", "footer", "sidebar", "nav", "menu", "share", "related", "promo", "banner", "ad-", "social"];
+const POSITIVE_HINTS: &[&str] = &["article", "content", "entry", "main", "post", "story", "text", "body"];
+
+/// How long a pasted link's page gets to answer before the URL is kept bare
+const LINK_TITLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most of a page read looking for its title; titles sit in the `<head>`
+const MAX_LINK_TITLE_BYTES: usize = 256 * 1024;
+
+/// Largest page that is clipped
+const MAX_PAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Largest image saved with a clipping; bigger ones keep their original URL
+const MAX_IMAGE_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipResult {
+    pub title: String,
+    pub markdown: String,
+    /// Where the note was saved, when an output directory was given
+    pub output_path: Option<PathBuf>,
+    /// Images downloaded next to the note
+    pub images: Vec<PathBuf>,
+}
+
+/// A pasted URL and the markdown link made from its page's title
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LinkTitle {
+    pub url: String,
+    pub title: Option<String>,
+    /// `[Title](url)`, or `<url>` when the page has no title or couldn't be read
+    pub markdown: String,
+    /// The page couldn't be reached, e.g. without a network connection
+    pub offline: bool,
+}
+
+impl LinkTitle {
+    fn new(url: &str, title: Option<String>, offline: bool) -> Self {
+        let destination = if url.contains([' ', '(', ')', '<', '>']) { format!("<{}>", url.replace(' ', "%20")) } else { url.to_string() };
+        let markdown = match &title {
+            Some(title) => format!("[{}]({})", escape_link_text(title), destination),
+            None => format!("<{}>", url),
+        };
+        Self { url: url.to_string(), title, markdown, offline }
+    }
+}
+
+/// The readable part of a page
+#[derive(Debug, Clone, PartialEq)]
+pub struct Article {
+    pub title: Option<String>,
+    pub content_html: String,
+}
+
+/// Saves web pages and local HTML files as markdown notes
+pub struct WebClipper {
+    client: reqwest::Client,
+    import_service: ImportService,
+}
+
+impl Default for WebClipper {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("Typora-Lite/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            import_service: ImportService::new(),
+        }
+    }
+}
+
+impl WebClipper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Import an `http(s)` URL or a local HTML file as markdown
+    ///
+    /// With an `output_dir`, the note is written as `<title>.md` and its images
+    /// are downloaded into `assets/<title>/`; otherwise image references are
+    /// left pointing at their absolute source URLs.
+    pub async fn import(&self, source: &str, output_dir: Option<&Path>) -> Result<ClipResult> {
+        let (html, base_url) = self.load(source).await?;
+        let article = extract_article(&html);
+        let title = article.title.clone().unwrap_or_else(|| "Untitled".to_string());
+        debug!("Extracted article '{}' ({} bytes) from {}", title, article.content_html.len(), source);
+
+        let mut content = article.content_html;
+        for element in BOILERPLATE_ELEMENTS {
+            content = strip_element(&content, element);
+        }
+        let markdown = self.import_service.html_to_markdown(&content)?;
+
+        // Relative links and images only make sense against the page they came from
+        let markdown = rewrite_destinations(&markdown, false, |dest| {
+            if dest.starts_with('#') || dest.starts_with("data:") {
+                return None;
+            }
+            base_url.join(dest).ok().map(|url| url.to_string()).filter(|url| url != dest)
+        });
+
+        let Some(output_dir) = output_dir else {
+            return Ok(ClipResult { title, markdown, output_path: None, images: Vec::new() });
+        };
+
+        let slug = Some(slugify(&title)).filter(|slug| !slug.is_empty()).unwrap_or_else(|| "clipping".to_string());
+        let assets_dir = output_dir.join("assets").join(&slug);
+        // A web page must not get local files copied into the clipping
+        let local_images = base_url.scheme() == "file";
+        let (markdown, images) = self.download_images(&markdown, &assets_dir, &format!("assets/{}", slug), local_images).await;
+
+        let mut front_matter = FrontMatter::default();
+        front_matter.set("title", title.as_str());
+        front_matter.set("source", base_url.as_str());
+        front_matter.set("clipped", chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false));
+
+        tokio::fs::create_dir_all(output_dir).await
+            .with_context(|| format!("Failed to create directory: {:?}", output_dir))?;
+        let output_path = output_dir.join(format!("{}.md", slug));
+        tokio::fs::write(&output_path, front_matter.render(&markdown)?).await
+            .with_context(|| format!("Failed to write note: {:?}", output_path))?;
+
+        info!("Clipped {} to {:?} ({} images)", source, output_path, images.len());
+        Ok(ClipResult { title, markdown, output_path: Some(output_path), images })
+    }
+
+    /// Title of the page at an `http(s)` URL, for turning a pasted URL into a link
+    ///
+    /// Only the start of the page is read. Pages that can't be reached in time
+    /// come back without a title and marked offline rather than as an error.
+    pub async fn link_title(&self, url: &str) -> Result<LinkTitle> {
+        let url = url.trim();
+        let parsed = Url::parse(url).with_context(|| format!("Invalid URL: {}", url))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            anyhow::bail!("Only http and https links have page titles: {}", url);
+        }
+
+        let mut response = match self.client.get(parsed).timeout(LINK_TITLE_TIMEOUT).send().await {
+            Ok(response) => response,
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                debug!("Could not reach {} for its title: {}", url, e);
+                return Ok(LinkTitle::new(url, None, true));
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to fetch {}", url)),
+        };
+        if !response.status().is_success() {
+            warn!("No title for {}: HTTP {}", url, response.status());
+            return Ok(LinkTitle::new(url, None, false));
+        }
+        let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+        if content_type.is_some_and(|value| !value.contains("html")) {
+            return Ok(LinkTitle::new(url, None, false));
+        }
+
+        let mut head = Vec::new();
+        loop {
+            let chunk = match tokio::time::timeout(LINK_TITLE_TIMEOUT, response.chunk()).await {
+                Ok(Ok(Some(chunk))) => chunk,
+                Ok(Ok(None)) | Err(_) => break,
+                Ok(Err(e)) => {
+                    warn!("Stopped reading {} for its title: {}", url, e);
+                    break;
+                }
+            };
+            head.extend_from_slice(&chunk);
+            let read = String::from_utf8_lossy(&head).to_ascii_lowercase();
+            if head.len() >= MAX_LINK_TITLE_BYTES || read.contains("</head") || read.contains("<body") {
+                break;
+            }
+        }
+        head.truncate(MAX_LINK_TITLE_BYTES);
+
+        let title = page_title(&Html::parse_document(&String::from_utf8_lossy(&head)));
+        debug!("Title of {}: {:?}", url, title);
+        Ok(LinkTitle::new(url, title, false))
+    }
+
+    /// Read the page and work out the URL its relative references resolve against
+    async fn load(&self, source: &str) -> Result<(String, Url)> {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let url = Url::parse(source).with_context(|| format!("Invalid URL: {}", source))?;
+            let response = self.client.get(url.clone()).send().await
+                .with_context(|| format!("Failed to fetch {}", source))?
+                .error_for_status()
+                .with_context(|| format!("Failed to fetch {}", source))?;
+            // Follow redirects for the base so relative paths resolve on the final host
+            let base = response.url().clone();
+            let html = read_limited(response, MAX_PAGE_BYTES).await
+                .with_context(|| format!("Failed to read {}", source))?;
+            return Ok((String::from_utf8_lossy(&html).into_owned(), base));
+        }
+
+        let path = Path::new(source.strip_prefix("file://").unwrap_or(source));
+        let path = tokio::fs::canonicalize(path).await
+            .with_context(|| format!("HTML file not found: {}", source))?;
+        check_file_size(&path, MAX_PAGE_BYTES).await?;
+        let html = tokio::fs::read_to_string(&path).await
+            .with_context(|| format!("Failed to read HTML file: {:?}", path))?;
+        let base = Url::from_file_path(&path)
+            .map_err(|_| anyhow::anyhow!("Cannot build a file URL for {:?}", path))?;
+        Ok((html, base))
+    }
+
+    /// Save each distinct image locally and point the markdown at the copies
+    ///
+    /// `file:` images are only copied when `local_images` is set, for pages read
+    /// from disk. Images that fail to download keep their original URL.
+    async fn download_images(&self, markdown: &str, assets_dir: &Path, link_prefix: &str, local_images: bool) -> (String, Vec<PathBuf>) {
+        let mut sources = Vec::new();
+        rewrite_image_paths(markdown, |dest| {
+            let fetchable = dest.starts_with("http") || (local_images && dest.starts_with("file:"));
+            if fetchable && !sources.iter().any(|s| s == dest) {
+                sources.push(dest.to_string());
+            }
+            None
+        });
+
+        let mut local_names: HashMap<String, String> = HashMap::new();
+        let mut images = Vec::new();
+        for (index, source) in sources.iter().enumerate() {
+            let name = image_file_name(source, index, local_names.values());
+            let target = assets_dir.join(&name);
+            match self.fetch_image(source, &target).await {
+                Ok(()) => {
+                    local_names.insert(source.clone(), name);
+                    images.push(target);
+                }
+                Err(e) => warn!("Keeping remote image {}: {}", source, e),
+            }
+        }
+
+        let markdown = rewrite_image_paths(markdown, |dest| {
+            local_names.get(dest).map(|name| format!("{}/{}", link_prefix, name.replace(' ', "%20")))
+        });
+        (markdown, images)
+    }
+
+    async fn fetch_image(&self, source: &str, target: &Path) -> Result<()> {
+        let url = Url::parse(source)?;
+        let data = if url.scheme() == "file" {
+            let path = url.to_file_path().map_err(|_| anyhow::anyhow!("Invalid file URL"))?;
+            check_file_size(&path, MAX_IMAGE_BYTES).await?;
+            tokio::fs::read(&path).await.with_context(|| format!("Failed to read {:?}", path))?
+        } else {
+            read_limited(self.client.get(url).send().await?.error_for_status()?, MAX_IMAGE_BYTES).await?
+        };
+
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(target, data).await
+            .with_context(|| format!("Failed to write image: {:?}", target))
+    }
+}
+
+/// The body of `response`, failing once it grows past `max` bytes
+async fn read_limited(mut response: reqwest::Response, max: usize) -> Result<Vec<u8>> {
+    if response.content_length().is_some_and(|len| len > max as u64) {
+        anyhow::bail!("Larger than {} MB", max / (1024 * 1024));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max {
+            anyhow::bail!("Larger than {} MB", max / (1024 * 1024));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+async fn check_file_size(path: &Path, max: usize) -> Result<()> {
+    let metadata = tokio::fs::metadata(path).await
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    if metadata.len() > max as u64 {
+        anyhow::bail!("{:?} is larger than {} MB", path, max / (1024 * 1024));
+    }
+    Ok(())
+}
+
+/// A unique local file name for an image URL
+fn image_file_name<'a>(source: &str, index: usize, taken: impl Iterator<Item = &'a String>) -> String {
+    let taken: Vec<&String> = taken.collect();
+    let last_segment = Url::parse(source).ok()
+        .and_then(|url| url.path_segments()?.next_back().map(|s| s.replace("%20", " ")))
+        .filter(|name| !name.is_empty() && name.contains('.'));
+
+    match last_segment {
+        Some(name) if !taken.contains(&&name) => name,
+        Some(name) => format!("{}-{}", index + 1, name),
+        None => format!("image-{}", index + 1),
+    }
+}
+
+/// Find the main article of a page, readability-style
+///
+/// A lone `<article>` wins outright. Otherwise paragraphs vote for their
+/// parent (full score) and grandparent (half score) by length and comma count,
+/// class/id hints adjust each candidate, and link-heavy candidates are
+/// penalized. Falls back to `<body>`.
+pub fn extract_article(html: &str) -> Article {
+    let document = Html::parse_document(html);
+    let title = page_title(&document);
+
+    let articles: Vec<ElementRef> = document.select(&selector("article")).collect();
+    if articles.len() == 1 {
+        return Article { title, content_html: articles[0].inner_html() };
+    }
+
+    let mut scores: HashMap<ego_tree::NodeId, (ElementRef, f64)> = HashMap::new();
+    for paragraph in document.select(&selector("p, pre, td")) {
+        let text: String = paragraph.text().collect();
+        let text = text.trim();
+        if text.chars().count() < 25 {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (text.chars().count() as f64 / 100.0).min(3.0);
+
+        let parent = paragraph.parent().and_then(ElementRef::wrap);
+        let grandparent = parent.and_then(|p| p.parent()).and_then(ElementRef::wrap);
+        for (candidate, share) in [(parent, 1.0), (grandparent, 0.5)] {
+            if let Some(candidate) = candidate {
+                scores.entry(candidate.id())
+                    .or_insert_with(|| (candidate, hint_score(candidate)))
+                    .1 += score * share;
+            }
+        }
+    }
+
+    let best = scores
+        .into_values()
+        .map(|(element, score)| (element, score * (1.0 - link_density(element))))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(element, _)| element);
+
+    let content_html = best
+        .or_else(|| document.select(&selector("body")).next())
+        .map(|element| element.inner_html())
+        .unwrap_or_else(|| html.to_string());
+
+    Article { title, content_html }
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("valid selector")
+}
+
+fn page_title(document: &Html) -> Option<String> {
+    let meta = document
+        .select(&selector(r#"meta[property="og:title"], meta[name="twitter:title"]"#))
+        .find_map(|meta| meta.value().attr("content").map(str::to_string));
+    let element_text = |css: &str| {
+        document
+            .select(&selector(css))
+            .next()
+            .map(|element| element.text().collect::<String>())
+    };
+
+    meta.or_else(|| element_text("title"))
+        .or_else(|| element_text("h1"))
+        .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|title| !title.is_empty())
+}
+
+/// Escape what would end or break a link's text
+fn escape_link_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '[' | ']' | '\\' | '*' | '_' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Starting score from the element's tag, class and id
+fn hint_score(element: ElementRef) -> f64 {
+    let value = element.value();
+    let hints = format!("{} {}", value.attr("class").unwrap_or(""), value.attr("id").unwrap_or("")).to_lowercase();
+
+    let mut score = match value.name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "td" | "blockquote" | "pre" => 3.0,
+        "nav" | "aside" | "footer" | "header" | "form" | "ul" | "ol" => -5.0,
+        _ => 0.0,
+    };
+    if NEGATIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        score -= 25.0;
+    }
+    if POSITIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        score += 25.0;
+    }
+    score
+}
+
+/// Fraction of the element's text that sits inside links
+fn link_density(element: ElementRef) -> f64 {
+    let text_len = element.text().map(str::len).sum::<usize>();
+    if text_len == 0 {
+        return 0.0;
+    }
+    let link_len: usize = element.select(&selector("a")).flat_map(|link| link.text()).map(str::len).sum();
+    link_len as f64 / text_len as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const PAGE: &str = r#"<html><head><title>Ignored Title</title>
+        <meta property="og:title" content="Growing Tomatoes"></head>
+        <body>
+          <nav class="menu"><a href="/">Home</a> <a href="/blog">Blog</a></nav>
+          <div class="sidebar"><p>Subscribe to our newsletter, follow us, and share this with friends.</p></div>
+          <div class="post-content">
+            <h2>Soil</h2>
+            <p>Tomatoes like rich, well-drained soil, plenty of sun, and regular watering throughout summer.</p>
+            <p>See the <a href="guides/compost.html">compost guide</a>, which covers mixing, timing, and storage.</p>
+            <img src="img/seedling.png" alt="Seedling">
+          </div>
+          <footer><p>Copyright 2024, Example Gardening Blog, all rights reserved forever.</p></footer>
+        </body></html>"#;
+
+    #[test]
+    fn test_extract_article_prefers_content() {
+        let article = extract_article(PAGE);
+
+        assert_eq!(article.title.as_deref(), Some("Growing Tomatoes"));
+        assert!(article.content_html.contains("well-drained soil"));
+        assert!(!article.content_html.contains("newsletter"));
+        assert!(!article.content_html.contains("Copyright"));
+    }
+
+    #[tokio::test]
+    async fn test_link_title() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let _ = stream.read(&mut request).await.unwrap();
+            let body = "<html><head><title>\n  Rust [Book] &amp; more\n</title></head><body>Hi</body></html>";
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let clipper = WebClipper::new();
+        let url = format!("http://{}/book", address);
+        let link = clipper.link_title(&url).await.unwrap();
+        assert_eq!(link.title.as_deref(), Some("Rust [Book] & more"));
+        assert_eq!(link.markdown, format!("[Rust \\[Book\\] & more]({})", url));
+
+        // Nothing listens on a port that was just given up
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let url = format!("http://{}/", closed);
+        let offline = clipper.link_title(&url).await.unwrap();
+        assert!(offline.offline && offline.title.is_none());
+        assert_eq!(offline.markdown, format!("<{}>", url));
+
+        assert!(clipper.link_title("file:///etc/passwd").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_local_file_copies_images() {
+        let source = TempDir::new().unwrap();
+        let notes = TempDir::new().unwrap();
+        std::fs::create_dir(source.path().join("img")).unwrap();
+        std::fs::write(source.path().join("img/seedling.png"), b"png").unwrap();
+        let page = source.path().join("tomatoes.html");
+        std::fs::write(&page, PAGE).unwrap();
+
+        let result = WebClipper::new()
+            .import(page.to_str().unwrap(), Some(notes.path()))
+            .await
+            .unwrap();
+
+        assert_eq!(result.output_path, Some(notes.path().join("growing-tomatoes.md")));
+        assert_eq!(result.images, vec![notes.path().join("assets/growing-tomatoes/seedling.png")]);
+        assert!(result.markdown.contains("![Seedling](assets/growing-tomatoes/seedling.png)"));
+        assert!(result.markdown.contains("[compost guide](file://"));
+
+        let saved = std::fs::read_to_string(result.output_path.unwrap()).unwrap();
+        let (front_matter, _) = FrontMatter::parse(&saved).unwrap();
+        assert_eq!(front_matter.title().as_deref(), Some("Growing Tomatoes"));
+        assert!(front_matter.get_str("source").unwrap().ends_with("tomatoes.html"));
+    }
+
+    #[tokio::test]
+    async fn test_web_pages_dont_copy_local_files() {
+        let source = TempDir::new().unwrap();
+        let notes = TempDir::new().unwrap();
+        let secret = source.path().join("secret.png");
+        std::fs::write(&secret, b"png").unwrap();
+        let url = Url::from_file_path(&secret).unwrap();
+        let markdown = format!("![Secret]({})", url);
+
+        let clipper = WebClipper::new();
+        let (kept, images) = clipper.download_images(&markdown, notes.path(), "assets", false).await;
+        assert_eq!((kept.trim(), images.len()), (markdown.as_str(), 0));
+        let (_, images) = clipper.download_images(&markdown, notes.path(), "assets", true).await;
+        assert_eq!(images, vec![notes.path().join("secret.png")]);
+    }
+}
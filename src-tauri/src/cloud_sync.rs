@@ -0,0 +1,701 @@
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::secrets::SecretStore;
+use crate::settings::{SyncProviderKind, SyncSettings};
+use crate::sync::{is_hidden, SyncProvider};
+
+/// Fixed loopback port for OAuth redirects, since Dropbox only accepts pre-registered redirect URIs
+const LOOPBACK_PORT: u16 = 53682;
+
+/// How long to wait for the user to finish signing in
+const AUTHORIZE_TIMEOUT: Duration = Duration::from_secs(300);
+
+const DRIVE_FOLDER_MIME: &str = "application/vnd.google-apps.folder";
+
+/// OAuth tokens as stored (JSON-encoded) in the keychain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Unix timestamp after which the access token must be refreshed
+    pub expires_at: Option<u64>,
+}
+
+/// Where a provider's OAuth flow happens and what it asks for
+pub struct OAuthEndpoints {
+    authorize_url: &'static str,
+    token_url: &'static str,
+    scope: Option<&'static str>,
+    extra_params: &'static [(&'static str, &'static str)],
+    /// Keychain entry holding the provider's [`OAuthTokens`]
+    pub keychain_key: &'static str,
+}
+
+pub const DROPBOX_OAUTH: OAuthEndpoints = OAuthEndpoints {
+    authorize_url: "https://www.dropbox.com/oauth2/authorize",
+    token_url: "https://api.dropboxapi.com/oauth2/token",
+    scope: None,
+    extra_params: &[("token_access_type", "offline")],
+    keychain_key: "dropbox-oauth",
+};
+
+pub const GOOGLE_DRIVE_OAUTH: OAuthEndpoints = OAuthEndpoints {
+    authorize_url: "https://accounts.google.com/o/oauth2/v2/auth",
+    token_url: "https://oauth2.googleapis.com/token",
+    // Only files the app created, not the rest of the user's Drive
+    scope: Some("https://www.googleapis.com/auth/drive.file"),
+    extra_params: &[("access_type", "offline"), ("prompt", "consent")],
+    keychain_key: "google-drive-oauth",
+};
+
+impl SyncProviderKind {
+    /// OAuth configuration for providers that sign in through the browser
+    pub fn oauth(&self) -> Option<&'static OAuthEndpoints> {
+        match self {
+            SyncProviderKind::WebDav => None,
+            SyncProviderKind::Dropbox => Some(&DROPBOX_OAUTH),
+            SyncProviderKind::GoogleDrive => Some(&GOOGLE_DRIVE_OAUTH),
+        }
+    }
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap_or_default()
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Run the OAuth authorization-code flow with PKCE and store the tokens in the keychain
+///
+/// `open_browser` is handed the sign-in URL; the provider redirects back to a
+/// one-shot listener on `http://localhost:53682/`, which must be registered as
+/// a redirect URI for the user's OAuth client.
+pub async fn authorize<F>(
+    endpoints: &OAuthEndpoints,
+    settings: &SyncSettings,
+    secrets: &SecretStore,
+    open_browser: F,
+) -> Result<()>
+where
+    F: FnOnce(&str) -> Result<()>,
+{
+    if settings.oauth_client_id.trim().is_empty() {
+        return Err(anyhow::anyhow!("No OAuth client id configured"));
+    }
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", LOOPBACK_PORT)).await
+        .with_context(|| format!("Port {} is needed for sign-in but is already in use", LOOPBACK_PORT))?;
+    let redirect_uri = format!("http://localhost:{}/", LOOPBACK_PORT);
+    let verifier = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let expected_state = uuid::Uuid::new_v4().simple().to_string();
+
+    let mut params = vec![
+        ("client_id", settings.oauth_client_id.trim()),
+        ("response_type", "code"),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("state", expected_state.as_str()),
+        ("code_challenge_method", "S256"),
+    ];
+    let challenge = pkce_challenge(&verifier);
+    params.push(("code_challenge", challenge.as_str()));
+    if let Some(scope) = endpoints.scope {
+        params.push(("scope", scope));
+    }
+    params.extend_from_slice(endpoints.extra_params);
+    let url = Url::parse_with_params(endpoints.authorize_url, &params)?;
+
+    debug!("Opening browser for OAuth sign-in at {}", endpoints.authorize_url);
+    open_browser(url.as_str())?;
+
+    let code = tokio::time::timeout(AUTHORIZE_TIMEOUT, receive_redirect(&listener, &expected_state))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out waiting for sign-in"))??;
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("client_id", settings.oauth_client_id.trim()),
+        ("code_verifier", verifier.as_str()),
+    ];
+    if let Some(secret) = settings.oauth_client_secret.as_deref().filter(|s| !s.is_empty()) {
+        form.push(("client_secret", secret));
+    }
+    let tokens = request_tokens(&http_client(), endpoints.token_url, &form, None).await?;
+
+    secrets.set(endpoints.keychain_key, &serde_json::to_string(&tokens)?)?;
+    info!("Connected sync provider ({})", endpoints.keychain_key);
+    Ok(())
+}
+
+/// Accept the browser's redirect and return the authorization code
+async fn receive_redirect(listener: &tokio::net::TcpListener, expected_state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buffer = vec![0; 8192];
+        let read = stream.read(&mut buffer).await?;
+        let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+
+        // Browsers also ask for /favicon.ico and the like; only the redirect carries a state
+        let result = match parse_redirect_request(&request) {
+            Some(result) => result,
+            None => {
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+                continue;
+            }
+        };
+
+        let message = if result.is_ok() { "Signed in. You can close this window." } else { "Sign-in failed. You can close this window." };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            message.len(),
+            message
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+
+        let (code, state) = result?;
+        if state != expected_state {
+            return Err(anyhow::anyhow!("OAuth state mismatch, sign-in aborted"));
+        }
+        return Ok(code);
+    }
+}
+
+/// The `code` and `state` from a raw `GET /?code=...&state=...` request, or the provider's error
+fn parse_redirect_request(request: &str) -> Option<Result<(String, String)>> {
+    let target = request.lines().next()?.split_whitespace().nth(1)?;
+    let url = Url::parse(&format!("http://localhost{}", target)).ok()?;
+    let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+    if let Some(error) = params.get("error") {
+        let description = params.get("error_description").map(String::as_str).unwrap_or(error);
+        return Some(Err(anyhow::anyhow!("Sign-in was not completed: {}", description)));
+    }
+    let state = params.get("state")?.clone();
+    let code = params.get("code")?.clone();
+    Some(Ok((code, state)))
+}
+
+/// RFC 7636 S256 code challenge
+fn pkce_challenge(verifier: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+async fn request_tokens(
+    client: &reqwest::Client,
+    token_url: &str,
+    form: &[(&str, &str)],
+    previous_refresh_token: Option<String>,
+) -> Result<OAuthTokens> {
+    let response: Value = client.post(token_url).form(form).send().await
+        .context("Failed to reach the OAuth token endpoint")?
+        .error_for_status()
+        .context("The OAuth token request was rejected")?
+        .json()
+        .await?;
+
+    let access_token = response["access_token"].as_str()
+        .ok_or_else(|| anyhow::anyhow!("OAuth response has no access token"))?
+        .to_string();
+    Ok(OAuthTokens {
+        access_token,
+        // Refresh responses usually omit the refresh token, which stays valid
+        refresh_token: response["refresh_token"].as_str().map(str::to_string).or(previous_refresh_token),
+        expires_at: response["expires_in"].as_u64().map(|seconds| unix_now() + seconds),
+    })
+}
+
+/// Hands out access tokens, refreshing and re-storing them as they expire
+struct TokenSource {
+    client: reqwest::Client,
+    endpoints: &'static OAuthEndpoints,
+    client_id: String,
+    client_secret: Option<String>,
+    secrets: SecretStore,
+    tokens: tokio::sync::Mutex<OAuthTokens>,
+}
+
+impl TokenSource {
+    fn load(endpoints: &'static OAuthEndpoints, settings: &SyncSettings, provider_name: &str) -> Result<Self> {
+        let secrets = SecretStore::new();
+        let stored = secrets.get(endpoints.keychain_key)?
+            .ok_or_else(|| anyhow::anyhow!("Connect {} before syncing", provider_name))?;
+        let tokens = serde_json::from_str(&stored)
+            .with_context(|| format!("Stored {} credentials are invalid; connect again", provider_name))?;
+
+        Ok(Self {
+            client: http_client(),
+            endpoints,
+            client_id: settings.oauth_client_id.trim().to_string(),
+            client_secret: settings.oauth_client_secret.clone().filter(|s| !s.is_empty()),
+            secrets,
+            tokens: tokio::sync::Mutex::new(tokens),
+        })
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        let mut tokens = self.tokens.lock().await;
+        let expiring = tokens.expires_at.is_some_and(|at| at <= unix_now() + 60);
+        let Some(refresh_token) = tokens.refresh_token.clone().filter(|_| expiring) else {
+            return Ok(tokens.access_token.clone());
+        };
+
+        debug!("Refreshing OAuth access token ({})", self.endpoints.keychain_key);
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", self.client_id.as_str()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        *tokens = request_tokens(&self.client, self.endpoints.token_url, &form, Some(refresh_token.clone())).await?;
+
+        if let Err(e) = self.secrets.set(self.endpoints.keychain_key, &serde_json::to_string(&*tokens)?) {
+            warn!("Failed to store refreshed OAuth token: {}", e);
+        }
+        Ok(tokens.access_token.clone())
+    }
+}
+
+/// A folder in the user's Dropbox
+pub struct DropboxProvider {
+    client: reqwest::Client,
+    tokens: TokenSource,
+    /// Normalized Dropbox path, e.g. `/Notes`, or empty for the Dropbox root
+    folder: String,
+}
+
+impl DropboxProvider {
+    pub fn new(settings: &SyncSettings) -> Result<Self> {
+        let folder = settings.remote_folder.trim().trim_matches('/');
+        Ok(Self {
+            client: http_client(),
+            tokens: TokenSource::load(&DROPBOX_OAUTH, settings, "Dropbox")?,
+            folder: if folder.is_empty() { String::new() } else { format!("/{}", folder) },
+        })
+    }
+
+    fn full_path(&self, relative: &str) -> String {
+        format!("{}/{}", self.folder, relative)
+    }
+
+    /// POST a JSON RPC call; `Ok(None)` means Dropbox answered with an error containing `tolerated`
+    async fn rpc(&self, endpoint: &str, body: Value, tolerated: Option<&str>) -> Result<Option<Value>> {
+        let response = self.client
+            .post(format!("https://api.dropboxapi.com/2/{}", endpoint))
+            .bearer_auth(self.tokens.access_token().await?)
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to reach Dropbox")?;
+
+        // Dropbox reports API errors such as missing paths as 409 with a JSON summary
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            let error = response.text().await.unwrap_or_default();
+            if tolerated.is_some_and(|tag| error.contains(tag)) {
+                return Ok(None);
+            }
+            return Err(anyhow::anyhow!("Dropbox error in {}: {}", endpoint, error));
+        }
+        let value = response.error_for_status()
+            .with_context(|| format!("Dropbox rejected {}", endpoint))?
+            .json()
+            .await?;
+        Ok(Some(value))
+    }
+}
+
+#[async_trait]
+impl SyncProvider for DropboxProvider {
+    fn remote_id(&self) -> String {
+        format!("dropbox:{}", self.folder.to_lowercase())
+    }
+
+    async fn list(&self) -> Result<BTreeMap<String, String>> {
+        let mut files = BTreeMap::new();
+        let mut page = self.rpc(
+            "files/list_folder",
+            json!({ "path": self.folder, "recursive": true }),
+            Some("not_found"),
+        ).await?;
+
+        while let Some(result) = page {
+            for entry in result["entries"].as_array().into_iter().flatten() {
+                if entry[".tag"] != "file" {
+                    continue;
+                }
+                let (Some(lower), Some(display), Some(rev)) =
+                    (entry["path_lower"].as_str(), entry["path_display"].as_str(), entry["rev"].as_str())
+                else {
+                    continue;
+                };
+                if let Some(relative) = dropbox_relative_path(&self.folder, lower, display) {
+                    if !relative.split('/').any(is_hidden) {
+                        files.insert(relative, rev.to_string());
+                    }
+                }
+            }
+
+            page = match (result["has_more"].as_bool(), result["cursor"].as_str()) {
+                (Some(true), Some(cursor)) => self.rpc("files/list_folder/continue", json!({ "cursor": cursor }), None).await?,
+                _ => None,
+            };
+        }
+
+        Ok(files)
+    }
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>> {
+        let response = self.client
+            .post("https://content.dropboxapi.com/2/files/download")
+            .bearer_auth(self.tokens.access_token().await?)
+            .header("Dropbox-API-Arg", dropbox_api_arg(&json!({ "path": self.full_path(path) })))
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to download {} from Dropbox", path))?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<String> {
+        let arg = json!({ "path": self.full_path(path), "mode": "overwrite", "mute": true });
+        let metadata: Value = self.client
+            .post("https://content.dropboxapi.com/2/files/upload")
+            .bearer_auth(self.tokens.access_token().await?)
+            .header("Dropbox-API-Arg", dropbox_api_arg(&arg))
+            .header("Content-Type", "application/octet-stream")
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to upload {} to Dropbox", path))?
+            .json()
+            .await?;
+        Ok(metadata["rev"].as_str().unwrap_or_default().to_string())
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.rpc("files/delete_v2", json!({ "path": self.full_path(path) }), Some("not_found")).await?;
+        Ok(())
+    }
+}
+
+/// JSON for the `Dropbox-API-Arg` header, which must be pure ASCII
+fn dropbox_api_arg(value: &Value) -> String {
+    let mut output = String::new();
+    for c in value.to_string().chars() {
+        if c.is_ascii() {
+            output.push(c);
+        } else {
+            let mut units = [0u16; 2];
+            for unit in c.encode_utf16(&mut units) {
+                output.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    output
+}
+
+/// Path of a listed entry relative to the synced folder, keeping the display casing
+///
+/// Dropbox paths are case-insensitive, so the folder prefix is matched on `path_lower`.
+fn dropbox_relative_path(folder: &str, path_lower: &str, path_display: &str) -> Option<String> {
+    let prefix = format!("{}/", folder.to_lowercase());
+    if !path_lower.starts_with(&prefix) || path_display.len() != path_lower.len() {
+        return path_lower.strip_prefix(&prefix).map(str::to_string);
+    }
+    Some(path_display[prefix.len()..].to_string())
+}
+
+/// File and folder ids discovered while listing, since Drive addresses everything by id
+#[derive(Default)]
+struct DriveIndex {
+    /// Relative folder path (empty for the synced folder itself) to id
+    folders: HashMap<String, String>,
+    files: HashMap<String, String>,
+}
+
+/// A top-level folder in the user's Google Drive
+pub struct GoogleDriveProvider {
+    client: reqwest::Client,
+    tokens: TokenSource,
+    folder_name: String,
+    index: tokio::sync::Mutex<DriveIndex>,
+}
+
+impl GoogleDriveProvider {
+    pub fn new(settings: &SyncSettings) -> Result<Self> {
+        let folder_name = settings.remote_folder.trim().trim_matches('/');
+        Ok(Self {
+            client: http_client(),
+            tokens: TokenSource::load(&GOOGLE_DRIVE_OAUTH, settings, "Google Drive")?,
+            folder_name: if folder_name.is_empty() { "Typora-Lite".to_string() } else { folder_name.to_string() },
+            index: tokio::sync::Mutex::new(DriveIndex::default()),
+        })
+    }
+
+    /// All pages of a `files.list` query
+    async fn query(&self, q: &str) -> Result<Vec<Value>> {
+        let mut files = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut params = vec![
+                ("q", q.to_string()),
+                ("fields", "nextPageToken, files(id, name, mimeType, md5Checksum, version)".to_string()),
+                ("pageSize", "1000".to_string()),
+            ];
+            if let Some(token) = &page_token {
+                params.push(("pageToken", token.clone()));
+            }
+
+            let page: Value = self.client
+                .get("https://www.googleapis.com/drive/v3/files")
+                .bearer_auth(self.tokens.access_token().await?)
+                .query(&params)
+                .send()
+                .await
+                .context("Failed to reach Google Drive")?
+                .error_for_status()
+                .context("Google Drive rejected the listing")?
+                .json()
+                .await?;
+
+            files.extend(page["files"].as_array().cloned().unwrap_or_default());
+            match page["nextPageToken"].as_str() {
+                Some(token) => page_token = Some(token.to_string()),
+                None => return Ok(files),
+            }
+        }
+    }
+
+    /// Id of the folder at `relative`, creating it (and its parents) when missing
+    async fn ensure_folder(&self, relative: &str) -> Result<String> {
+        let mut folder_id = match self.cached_folder("").await {
+            Some(id) => id,
+            None => self.create_folder("root", &self.folder_name, "").await?,
+        };
+        let mut path = String::new();
+        for segment in relative.split('/').filter(|s| !s.is_empty()) {
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(segment);
+            folder_id = match self.cached_folder(&path).await {
+                Some(id) => id,
+                None => self.create_folder(&folder_id, segment, &path).await?,
+            };
+        }
+        Ok(folder_id)
+    }
+
+    async fn cached_folder(&self, relative: &str) -> Option<String> {
+        self.index.lock().await.folders.get(relative).cloned()
+    }
+
+    async fn create_folder(&self, parent_id: &str, name: &str, relative: &str) -> Result<String> {
+        let created: Value = self.client
+            .post("https://www.googleapis.com/drive/v3/files")
+            .bearer_auth(self.tokens.access_token().await?)
+            .query(&[("fields", "id")])
+            .json(&json!({ "name": name, "mimeType": DRIVE_FOLDER_MIME, "parents": [parent_id] }))
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to create Drive folder {}", name))?
+            .json()
+            .await?;
+
+        let id = created["id"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Google Drive did not return an id for folder {}", name))?
+            .to_string();
+        self.index.lock().await.folders.insert(relative.to_string(), id.clone());
+        Ok(id)
+    }
+}
+
+#[async_trait]
+impl SyncProvider for GoogleDriveProvider {
+    fn remote_id(&self) -> String {
+        format!("gdrive:{}", self.folder_name)
+    }
+
+    async fn list(&self) -> Result<BTreeMap<String, String>> {
+        let mut index = DriveIndex::default();
+        let mut files = BTreeMap::new();
+
+        let root_query = format!(
+            "name = {} and mimeType = '{}' and 'root' in parents and trashed = false",
+            drive_query_literal(&self.folder_name),
+            DRIVE_FOLDER_MIME
+        );
+        let mut pending: Vec<(String, String)> = self.query(&root_query).await?
+            .first()
+            .and_then(|folder| folder["id"].as_str())
+            .map(|id| vec![(String::new(), id.to_string())])
+            .unwrap_or_default();
+
+        while let Some((dir, id)) = pending.pop() {
+            index.folders.insert(dir.clone(), id.clone());
+            let children = self.query(&format!("{} in parents and trashed = false", drive_query_literal(&id))).await?;
+
+            for child in children {
+                let (Some(name), Some(child_id)) = (child["name"].as_str(), child["id"].as_str()) else {
+                    continue;
+                };
+                if is_hidden(name) {
+                    continue;
+                }
+                let relative = if dir.is_empty() { name.to_string() } else { format!("{}/{}", dir, name) };
+                if child["mimeType"] == DRIVE_FOLDER_MIME {
+                    pending.push((relative, child_id.to_string()));
+                } else {
+                    // Google Docs have no checksum; their version still tracks edits
+                    let revision = child["md5Checksum"].as_str().or(child["version"].as_str()).unwrap_or_default();
+                    index.files.insert(relative.clone(), child_id.to_string());
+                    files.insert(relative, revision.to_string());
+                }
+            }
+        }
+
+        *self.index.lock().await = index;
+        Ok(files)
+    }
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>> {
+        let id = self.index.lock().await.files.get(path).cloned()
+            .ok_or_else(|| anyhow::anyhow!("{} is not on Google Drive", path))?;
+        let response = self.client
+            .get(format!("https://www.googleapis.com/drive/v3/files/{}", id))
+            .bearer_auth(self.tokens.access_token().await?)
+            .query(&[("alt", "media")])
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to download {} from Google Drive", path))?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<String> {
+        let existing = self.index.lock().await.files.get(path).cloned();
+        let fields = [("fields", "id, md5Checksum, version")];
+
+        let request = match existing {
+            Some(id) => self.client
+                .patch(format!("https://www.googleapis.com/upload/drive/v3/files/{}", id))
+                .query(&[("uploadType", "media")])
+                .body(data),
+            None => {
+                let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+                let parent = self.ensure_folder(dir).await?;
+                let boundary = uuid::Uuid::new_v4().simple().to_string();
+                let metadata = json!({ "name": name, "parents": [parent] });
+
+                let mut body = format!(
+                    "--{b}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{}\r\n--{b}\r\nContent-Type: application/octet-stream\r\n\r\n",
+                    metadata,
+                    b = boundary
+                ).into_bytes();
+                body.extend_from_slice(&data);
+                body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+                self.client
+                    .post("https://www.googleapis.com/upload/drive/v3/files")
+                    .query(&[("uploadType", "multipart")])
+                    .header("Content-Type", format!("multipart/related; boundary={}", boundary))
+                    .body(body)
+            }
+        };
+
+        let file: Value = request
+            .query(&fields)
+            .bearer_auth(self.tokens.access_token().await?)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to upload {} to Google Drive", path))?
+            .json()
+            .await?;
+
+        if let Some(id) = file["id"].as_str() {
+            self.index.lock().await.files.insert(path.to_string(), id.to_string());
+        }
+        Ok(file["md5Checksum"].as_str().or(file["version"].as_str()).unwrap_or_default().to_string())
+    }
+
+    /// Moves the file to the Drive trash rather than deleting it outright
+    async fn delete(&self, path: &str) -> Result<()> {
+        let Some(id) = self.index.lock().await.files.remove(path) else {
+            return Ok(());
+        };
+
+        self.client
+            .patch(format!("https://www.googleapis.com/drive/v3/files/{}", id))
+            .bearer_auth(self.tokens.access_token().await?)
+            .json(&json!({ "trashed": true }))
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to delete {} from Google Drive", path))?;
+        Ok(())
+    }
+}
+
+/// Quote a value for a Drive `q` expression
+fn drive_query_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pkce_challenge() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mJ92K9ZZ2xnsBhEhhHsxYrG0TSVgPM"),
+            "fuiCpXuLAuD4jqqo1kJqh-yVwpULJew_zhEo9fJtxYI"
+        );
+    }
+
+    #[test]
+    fn test_parse_redirect_request() {
+        let (code, state) = parse_redirect_request("GET /?code=abc%2F1&state=xyz HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!((code.as_str(), state.as_str()), ("abc/1", "xyz"));
+
+        assert!(parse_redirect_request("GET /favicon.ico HTTP/1.1\r\n\r\n").is_none());
+        assert!(parse_redirect_request("GET /?error=access_denied HTTP/1.1\r\n\r\n").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_dropbox_paths_and_quoting() {
+        assert_eq!(dropbox_api_arg(&json!({ "path": "/Notes/Café.md" })), r#"{"path":"/Notes/Caf\u00e9.md"}"#);
+        assert_eq!(
+            dropbox_relative_path("/Notes", "/notes/daily/today.md", "/Notes/Daily/Today.md").as_deref(),
+            Some("Daily/Today.md")
+        );
+        assert_eq!(dropbox_relative_path("/Notes", "/other/x.md", "/Other/x.md"), None);
+        assert_eq!(drive_query_literal("Ada's notes"), r"'Ada\'s notes'");
+    }
+}
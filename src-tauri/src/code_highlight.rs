@@ -0,0 +1,158 @@
+use anyhow::{Result, Context};
+use std::sync::OnceLock;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use tracing::warn;
+
+/// Theme code blocks are highlighted with when the export options name none
+pub const DEFAULT_CODE_THEME: &str = "InspiredGitHub";
+
+/// Token classes carry a prefix so they can't clash with the document's own classes
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Highlights fenced code blocks of exported documents, which have no script to do
+/// it when they're printed
+///
+/// The syntax definitions and themes bundled with syntect are loaded on first use.
+#[derive(Default)]
+pub struct CodeHighlighter {
+    syntaxes: OnceLock<SyntaxSet>,
+    themes: OnceLock<ThemeSet>,
+}
+
+/// A `<pre><code class="language-…">` block in rendered HTML
+pub(crate) struct CodeBlock<'a> {
+    pub(crate) language: &'a str,
+    /// The code, HTML-escaped
+    pub(crate) code: &'a str,
+    /// Length of the whole block in the HTML
+    pub(crate) len: usize,
+}
+
+impl CodeHighlighter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn syntaxes(&self) -> &SyntaxSet {
+        self.syntaxes.get_or_init(SyntaxSet::load_defaults_newlines)
+    }
+
+    fn theme_set(&self) -> &ThemeSet {
+        self.themes.get_or_init(ThemeSet::load_defaults)
+    }
+
+    /// Names of the themes code can be highlighted with
+    pub fn themes(&self) -> Vec<String> {
+        self.theme_set().themes.keys().cloned().collect()
+    }
+
+    /// Stylesheet coloring highlighted code with `theme`, matched case-insensitively
+    pub fn theme_css(&self, theme: &str) -> Result<String> {
+        css_for_theme_with_class_style(self.theme(theme)?, CLASS_STYLE)
+            .with_context(|| format!("Failed to generate the stylesheet of code theme {}", theme))
+    }
+
+    fn theme(&self, name: &str) -> Result<&Theme> {
+        self.theme_set().themes.iter()
+            .find(|(theme, _)| theme.eq_ignore_ascii_case(name.trim()))
+            .map(|(_, theme)| theme)
+            .with_context(|| format!("Unknown code theme: {}", name))
+    }
+
+    /// `html` with the code of every fenced block in a known language split into
+    /// classed spans, for the stylesheet from [`Self::theme_css`] to color
+    pub fn highlight_code_blocks(&self, html: &str) -> String {
+        let mut highlighted = String::with_capacity(html.len() * 2);
+        let mut rest = html;
+        while let Some(at) = rest.find("<pre") {
+            highlighted.push_str(&rest[..at]);
+            rest = &rest[at..];
+            let Some(block) = code_block(rest) else {
+                highlighted.push_str("<pre");
+                rest = &rest["<pre".len()..];
+                continue;
+            };
+            match self.highlight(&block) {
+                Some(html) => highlighted.push_str(&html),
+                None => highlighted.push_str(&rest[..block.len]),
+            }
+            rest = &rest[block.len..];
+        }
+        highlighted.push_str(rest);
+        highlighted
+    }
+
+    /// The block as highlighted HTML; `None` when its language is unknown
+    fn highlight(&self, block: &CodeBlock) -> Option<String> {
+        let token = block.language.split(|c: char| c == ',' || c.is_whitespace()).next()?;
+        let syntaxes = self.syntaxes();
+        let syntax = syntaxes.find_syntax_by_token(token)?;
+        let code = html_escape::decode_html_entities(block.code);
+
+        let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes, CLASS_STYLE);
+        for line in LinesWithEndings::from(&code) {
+            if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+                warn!("Failed to highlight {} code block: {}", token, e);
+                return None;
+            }
+        }
+        Some(format!(
+            "<pre class=\"language-{} hl-code\"><code class=\"language-{}\">{}</code></pre>",
+            block.language,
+            block.language,
+            generator.finalize()
+        ))
+    }
+}
+
+/// The code block `html` starts with, if it starts with one
+pub(crate) fn code_block(html: &str) -> Option<CodeBlock<'_>> {
+    let pre_end = html.find('>')?;
+    if !(pre_end == "<pre".len() || html[..pre_end].starts_with("<pre ")) {
+        return None;
+    }
+    let code_tag = html[pre_end + 1..].strip_prefix("<code class=\"language-")?;
+    let language = &code_tag[..code_tag.find('"')?];
+    let code_start = code_tag.find('>')? + 1;
+    let code_len = code_tag[code_start..].find("</code>")?;
+    let tail = code_tag[code_start + code_len..].strip_prefix("</code>")?;
+    tail.strip_prefix("</pre>")?;
+
+    Some(CodeBlock {
+        language,
+        code: &code_tag[code_start..code_start + code_len],
+        len: html.len() - tail.len() + "</pre>".len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_code_blocks() {
+        let highlighter = CodeHighlighter::new();
+        let html = "<p>Before <pre>x</pre></p>\
+            <pre class=\"language-rust\"><code class=\"language-rust\">fn main() { let s = &quot;a &lt; b&quot;; }\n</code></pre>\
+            <pre><code class=\"language-klingon\">qapla&#39;\n</code></pre>";
+        let highlighted = highlighter.highlight_code_blocks(html);
+
+        assert!(highlighted.starts_with("<p>Before <pre>x</pre></p><pre class=\"language-rust hl-code\"><code class=\"language-rust\">"));
+        assert!(highlighted.contains("<span class=\"hl-storage hl-type hl-function hl-rust\">fn</span>"));
+        assert!(highlighted.contains("a &lt; b"));
+        assert!(highlighted.ends_with("<pre><code class=\"language-klingon\">qapla&#39;\n</code></pre>"));
+    }
+
+    #[test]
+    fn test_code_theme_css() {
+        let highlighter = CodeHighlighter::new();
+        assert!(highlighter.themes().contains(&DEFAULT_CODE_THEME.to_string()));
+
+        let css = highlighter.theme_css("base16-ocean.DARK").unwrap();
+        assert!(css.contains(".hl-code {\n color: #c0c5ce;\n background-color: #2b303b;\n}"));
+        assert!(highlighter.theme_css("Neon").is_err());
+    }
+}
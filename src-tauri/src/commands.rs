@@ -1,14 +1,76 @@
-use anyhow::Result;
+use anyhow::{Result, Context};
 use serde::Serialize;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use tauri::{command, Window, State};
-use tracing::{debug, info, warn, error};
+use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tauri::{command, AppHandle, GlobalShortcutManager, Manager, Runtime, Window, State};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn, error};
 
-use crate::parser::{MarkdownParser, ParsedDocument};
-use crate::export::{ExportService, ExportOptions, ExportResult};
-use crate::file_service::{FileService, FileMetadata, FileChangeEvent};
+use crate::parser::{DocumentStats, FoldingRange, MarkdownParser, OutlineItem, ParsedDocument, ParserLimits, SourceBlock};
+use crate::export::{document_options_path, ExportFormat, ExportProgress, ExportService, ExportOptions, ExportResult, ExportTheme, ProgressFn};
+use crate::file_service::{CoalesceMode, FileService, FileLines, FileMetadata, FileChangeEvent, SearchMatch, TaskProgress, WatchHandle, WatchOptions};
+use crate::clipboard::ClipboardService;
+use crate::import::ImportService;
+use crate::notion::{NotionImportResult, NotionImporter};
+use crate::docx::{DocxImportResult, DocxImporter};
+use crate::clipper::{ClipResult, LinkTitle, WebClipper};
+use crate::spellcheck::{SpellChecker, SpellingIssue};
+use crate::tables::{format_table_at, table_in_range, TableEdit};
+use crate::lists::{list_continuation, ListContinuation};
+use crate::links::HeadingLink;
+use crate::stylesheets::{load_document_css, themes_dir};
+use crate::compare::{diff_documents, DiffLayout, DocumentComparison};
+use crate::accessibility::{audit_html, AccessibilityReport};
+use crate::conflicts::ConflictSide;
+use crate::merge::{merge_documents, sort_chapters, MergeOptions};
+use crate::book::{set_book_chapters, BookProject, MANIFEST_FILE};
+use crate::versions::{git_head_version, DiffBase, DocumentDiff, SnapshotInfo, SnapshotStore, DIFF_CONTEXT_LINES};
+use crate::grammar::{GrammarChecker, GrammarIssue};
+use crate::assistant::{AssistAction, AssistantService, AI_API_KEY_KEY};
+use crate::palette::{CommandRegistry, PaletteCommand};
+use crate::plugins::{PluginHook, PluginInfo, PluginManager};
+use crate::site_builder::{SiteBuildOptions, SiteBuildResult, SiteBuilder};
+use crate::feed::{FeedGenerator, FeedResult};
+use crate::journal::{validate_journal_settings, DailyNote, JournalService};
+use crate::scratchpad::{validate_scratchpad_settings, QuickNote, Scratchpad};
+use crate::zotero::{append_bibliography, cited_keys, ZoteroReference, ZoteroService};
+use crate::citation_styles::{citation_styles_dir, citation_styles_in, document_citation_style, CitationStyle};
+use crate::bidi::document_direction;
+use crate::frontmatter::FrontMatter;
+use crate::diagnostics::{disk_space, system_locale, DiskSpace};
+use crate::holders::{find_holders, FileHoldersEvent};
+use crate::export_queue::{export_jobs, ExportJob, ExportJobFailure, ExportQueue};
+use crate::presets::{ExportPreset, ExportPresets};
+use crate::export_jobs::{ExportJobs, ExportProgressEvent};
+use crate::problems::{check_links, finish_problems, lint_markdown, spelling_problems, Problem};
+use crate::settings::{app_config_dir, AiSettings, ApiSettings, FilterStage, HtmlFilter, ConfluenceSettings, EmailSettings, ExportOnSaveRule, ExportSettings, FileAccessSettings, FootnoteSettings, GrammarSettings, ParserSettings, PdfSigningSettings, GoalSettings, ScratchpadSettings, SecuritySettings, WorkerSettings, PublishSettings, SettingsService, StaticSiteSettings, FeedSettings, JournalSettings, SyncProviderKind, SyncSettings, ZoteroSettings};
+use crate::filters::HtmlFilterRunner;
+use crate::email::{mailto_url, EmailDelivery, EmailRequest, EmailResult, EmailService, SMTP_PASSWORD_KEY};
+use crate::confluence::{render_storage_format, ConfluenceExportResult, ConfluenceService, CONFLUENCE_TOKEN_KEY};
+use crate::gist::{GistRequest, GistResult, GistService, GITHUB_TOKEN_KEY};
+use crate::publish::{Post, PublishResult, PublishService, PublishTarget, PublishTargetInfo};
+use crate::secrets::SecretStore;
+use crate::encryption::{encrypted_path, is_encrypted_path, DOCUMENT_PASSWORD_KEY};
+use crate::pdf_signing::{PdfSigner, SignatureDetails};
+use crate::static_site::{SiteExportResult, StaticSiteExporter};
+use crate::sessions::{SessionStats, SessionTracker};
+use crate::goals::{document_word_goal, GoalProgressEvent, GoalTracker};
+use crate::api_server::{generate_token, ApiBackend, ApiServer, ApiServerInfo, ConvertRequest, ExportRequest, SearchRequest, API_TOKEN_KEY};
+use crate::preview_diff::{PreviewDiffer, PreviewUpdate};
+use crate::documents::{DocumentChange, DocumentEdit, DocumentInfo, DocumentStore};
+use crate::profiling::{OperationKind, PerformanceProfile, PhaseTimer, Profiler};
+use crate::parse_scheduler::{ParseComplete, ParseFailed, ParseScheduler, ParseTicket};
+use crate::preview_server::{PreviewServer, PreviewServerInfo, PreviewServerOptions};
+use crate::sync::{provider_for, SyncReport, SyncService, WEBDAV_PASSWORD_KEY};
+use crate::cloud_sync;
+use crate::workspace::{Workspace, WorkspaceManager, WorkspaceSettings};
+use crate::shortcuts::{self, Shortcut};
+use crate::worker_pool::WorkerPool;
+use crate::logging::LogController;
+use crate::metrics::{CommandMetric, CommandMetrics};
+use crate::csp::{strip_event_handlers, PREVIEW_CSP};
 
 // Application state
 #[derive(Default)]
@@ -16,10 +78,72 @@ pub struct AppState {
     pub parser: MarkdownParser,
     pub export_service: ExportService,
     pub file_service: FileService,
-    pub current_file: Arc<Mutex<Option<PathBuf>>>,
-    pub watchers: Arc<Mutex<HashMap<PathBuf, bool>>>,
+    pub settings: SettingsService,
+    pub clipboard: ClipboardService,
+    pub import_service: ImportService,
+    pub notion_importer: NotionImporter,
+    pub docx_importer: DocxImporter,
+    pub web_clipper: WebClipper,
+    pub spellchecker: SpellChecker,
+    pub grammar_checker: GrammarChecker,
+    pub assistant: AssistantService,
+    pub command_registry: CommandRegistry,
+    pub plugins: PluginManager,
+    pub html_filters: HtmlFilterRunner,
+    pub workspaces: WorkspaceManager,
+    pub sessions: SessionTracker,
+    pub goals: GoalTracker,
+    pub secrets: SecretStore,
+    pub gist_service: GistService,
+    pub publish_service: PublishService,
+    pub static_site_exporter: StaticSiteExporter,
+    pub confluence: ConfluenceService,
+    pub email_service: EmailService,
+    pub sync_service: SyncService,
+    pub preview_server: PreviewServer,
+    pub preview_differ: PreviewDiffer,
+    pub parse_scheduler: ParseScheduler,
+    pub documents: DocumentStore,
+    pub snapshots: SnapshotStore,
+    pub profiler: Profiler,
+    pub api_server: ApiServer,
+    pub zotero: ZoteroService,
+    pub journal: JournalService,
+    pub scratchpad: Scratchpad,
+    pub feed_generator: FeedGenerator,
+    pub site_builder: SiteBuilder,
+    pub workers: WorkerPool,
+    pub logging: LogController,
+    pub command_metrics: CommandMetrics,
+    pub export_queue: ExportQueue,
+    pub export_presets: ExportPresets,
+    pub export_jobs: ExportJobs,
+    // Async locks, since commands run on the async runtime; never hold a guard across an await
+    pub current_file: Arc<RwLock<Option<PathBuf>>>,
+    pub watchers: Arc<RwLock<HashMap<PathBuf, bool>>>,
+    pub dirty_windows: Arc<RwLock<HashSet<String>>>,
 }
 
+impl AppState {
+    /// Whether the given window has unsaved changes
+    pub async fn is_dirty(&self, window_label: &str) -> bool {
+        self.dirty_windows.read().await.contains(window_label)
+    }
+
+    /// Record whether the given window has unsaved changes
+    pub async fn set_dirty(&self, window_label: &str, dirty: bool) {
+        let mut dirty_windows = self.dirty_windows.write().await;
+        if dirty {
+            dirty_windows.insert(window_label.to_string());
+        } else {
+            dirty_windows.remove(window_label);
+        }
+    }
+}
+
+/// Spell-check language used when the frontend doesn't specify one
+const DEFAULT_LANGUAGE: &str = "en_US";
+
 // Command result types
 #[derive(Debug, Serialize)]
 pub struct CommandResult<T> {
@@ -49,7 +173,8 @@ impl<T> CommandResult<T> {
 // Command implementations
 
 #[command]
-pub async fn open_file_dialog() -> CommandResult<Option<PathBuf>> {
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn open_file_dialog(state: State<'_, AppState>) -> Result<CommandResult<Option<PathBuf>>, String> {
     debug!("Opening file dialog");
     
     match tauri::api::dialog::blocking::FileDialogBuilder::new()
@@ -59,16 +184,23 @@ pub async fn open_file_dialog() -> CommandResult<Option<PathBuf>> {
     {
         Some(path) => {
             info!("File selected: {:?}", path);
-            CommandResult::ok(Some(path))
+            // The user chose it, so file commands may use it even outside the workspace,
+            // also after a restart
+            state.file_service.allow_file(&path);
+            if let Err(e) = state.settings.update(|current| current.file_access.record_opened_file(&path)).await {
+                warn!("Failed to remember opened file {:?}: {}", path, e);
+            }
+            Ok(CommandResult::ok(Some(path)))
         }
         None => {
             debug!("No file selected");
-            CommandResult::ok(None)
+            Ok(CommandResult::ok(None))
         }
     }
 }
 
 #[command]
+#[instrument(target = "command", level = "trace", skip_all)]
 pub async fn read_markdown_file(
     path: PathBuf,
     state: State<'_, AppState>,
@@ -77,8 +209,11 @@ pub async fn read_markdown_file(
 
     match state.file_service.read_file(&path).await {
         Ok(content) => {
+            if let Err(e) = state.workspaces.record_recent_file(&path).await {
+                warn!("Failed to record recent file {:?}: {}", path, e);
+            }
             // Update current file in state
-            *state.current_file.lock().unwrap() = Some(path);
+            *state.current_file.write().await = Some(path);
             Ok(CommandResult::ok(content))
         }
         Err(e) => {
@@ -88,15 +223,48 @@ pub async fn read_markdown_file(
     }
 }
 
+/// Read a window of lines, for paging through files too big to open whole
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn read_file_lines(
+    path: PathBuf,
+    start_line: usize,
+    count: usize,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<FileLines>, String> {
+    debug!("Reading lines {}+{} of {:?}", start_line, count, path);
+    Ok(handle_command_error(state.file_service.read_lines(&path, start_line, count).await))
+}
+
+/// Render markdown for the preview
+///
+/// With `incremental`, successive calls from the same window return only the
+/// blocks that changed in `patch` (leaving `html` empty), falling back to full
+/// HTML whenever a patch wouldn't help.
 #[command]
+#[instrument(target = "command", level = "trace", skip_all)]
 pub async fn parse_markdown(
     content: String,
+    incremental: Option<bool>,
+    window: Window,
     state: State<'_, AppState>,
 ) -> Result<CommandResult<ParsedDocument>, String> {
     debug!("Parsing markdown content ({} chars)", content.len());
 
-    match state.parser.parse(&content) {
-        Ok(parsed) => {
+    // Re-renders follow edits, so they count as activity on the open document
+    let current = state.current_file.read().await.clone();
+    if let Some(current) = current {
+        state.sessions.record_activity(&current);
+    }
+
+    let goals = goal_targets(&state, &content);
+    match render_markdown(&state, content).await {
+        Ok(mut parsed) => {
+            if state.preview_server.info().await.is_some() {
+                update_preview_page(&state, &parsed.html).await;
+            }
+            diff_preview(&state, &mut parsed, incremental.unwrap_or(false), window.label());
+            emit_goal_progress(&window, &state, goals, parsed.word_count).await;
             info!("Markdown parsed successfully: {} words, {} headings", 
                   parsed.word_count, parsed.toc.len());
             Ok(CommandResult::ok(parsed))
@@ -108,253 +276,3449 @@ pub async fn parse_markdown(
     }
 }
 
+/// Queue a preview render in the background, returning its request id
+///
+/// A newer request for the same document cancels this one; otherwise the result
+/// arrives as a `parse-complete` (or `parse-failed`) event carrying the id.
+/// `document` defaults to the calling window; without `content`, its open
+/// buffer is rendered.
 #[command]
-pub async fn export_to_pdf(
-    html_content: String,
-    output_path: PathBuf,
-    options: Option<ExportOptions>,
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn schedule_parse(
+    content: Option<String>,
+    document: Option<String>,
+    incremental: Option<bool>,
+    window: Window,
     state: State<'_, AppState>,
-) -> Result<CommandResult<ExportResult>, String> {
-    debug!("Exporting to PDF: {:?}", output_path);
+) -> Result<CommandResult<u64>, String> {
+    let document = document.unwrap_or_else(|| window.label().to_string());
+    let Some(content) = content.or_else(|| state.documents.text(&document)) else {
+        return Ok(CommandResult::err(format!("Document is not open: {}", document)));
+    };
+    debug!("Scheduling parse of {} ({} chars)", document, content.len());
 
-    let export_options = options.unwrap_or_default();
-    
-    match state.export_service.export(&html_content, &output_path, export_options).await {
-        Ok(result) => {
-            info!("PDF export completed: {:?} ({} bytes)", 
-                  result.output_path, result.file_size);
-            Ok(CommandResult::ok(result))
-        }
+    let current = state.current_file.read().await.clone();
+    if let Some(current) = current {
+        state.sessions.record_activity(&current);
+    }
+
+    let ticket = state.parse_scheduler.begin(&document);
+    let id = ticket.id;
+    tauri::async_runtime::spawn(run_scheduled_parse(window, ticket, content, incremental.unwrap_or(false)));
+
+    Ok(CommandResult::ok(id))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_document_stats(
+    path: Option<PathBuf>,
+    content: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentStats>, String> {
+    debug!("Getting document stats for {:?}", path);
+
+    let content = match resolve_content(path.clone(), content, &state).await {
+        Ok(content) => content,
         Err(e) => {
-            error!("Failed to export PDF: {}", e);
-            Ok(CommandResult::err(e.to_string()))
+            error!("Failed to load document for stats: {}", e);
+            return Ok(CommandResult::err(e.to_string()));
+        }
+    };
+
+    let mut stats = match state.parser.document_stats(&content) {
+        Ok(stats) => stats,
+        Err(e) => return Ok(CommandResult::err(e.to_string())),
+    };
+
+    // Report the on-disk size when the document is backed by a file
+    if let Some(path) = &path {
+        if let Ok(size) = state.file_service.get_file_size(path).await {
+            stats.file_size = size;
         }
     }
+
+    Ok(CommandResult::ok(stats))
 }
 
 #[command]
-pub async fn save_file(
-    path: PathBuf,
-    content: String,
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_outline(
+    path: Option<PathBuf>,
+    content: Option<String>,
     state: State<'_, AppState>,
-) -> Result<CommandResult<()>, String> {
-    debug!("Saving file: {:?}", path);
+) -> Result<CommandResult<Vec<OutlineItem>>, String> {
+    debug!("Getting outline for {:?}", path);
 
-    match state.file_service.write_file(&path, &content).await {
-        Ok(()) => {
-            info!("File saved successfully: {:?}", path);
-            Ok(CommandResult::ok(()))
+    let content = match resolve_content(path, content, &state).await {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Failed to load document for outline: {}", e);
+            return Ok(CommandResult::err(e.to_string()));
         }
+    };
+
+    Ok(handle_command_error(state.parser.outline(&content)))
+}
+
+/// Foldable source ranges for heading sections, lists and code blocks
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_folding_ranges(
+    path: Option<PathBuf>,
+    content: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<FoldingRange>>, String> {
+    debug!("Getting folding ranges for {:?}", path);
+
+    let result = resolve_content(path, content, &state).await
+        .and_then(|content| state.parser.folding_ranges(&content));
+    Ok(handle_command_error(result))
+}
+
+/// In-document anchor for a heading, for cross-references pasted into other notes
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_heading_anchor(
+    content: String,
+    heading: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<HeadingLink>, String> {
+    debug!("Getting anchor for heading {:?}", heading);
+
+    let anchor = match state.parser.heading_anchor(&content, &heading) {
+        Ok(Some(anchor)) => anchor,
+        Ok(None) => return Ok(CommandResult::err(format!("No heading named \"{}\" in the document", heading))),
+        Err(e) => return Ok(CommandResult::err(e.to_string())),
+    };
+    Ok(CommandResult::ok(HeadingLink::new(&anchor)))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_scroll_map(
+    content: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<SourceBlock>>, String> {
+    debug!("Building scroll map ({} chars)", content.len());
+    Ok(handle_command_error(state.parser.scroll_map(&content)))
+}
+
+/// Task list completion per file and in total under `dir`, by default the open workspace
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_task_progress(
+    dir: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<TaskProgress>, String> {
+    debug!("Getting task progress for {:?}", dir);
+
+    let result = async {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => state.workspaces.active()
+                .map(|workspace| workspace.root)
+                .ok_or_else(|| anyhow::anyhow!("No workspace is open; pass a folder to summarize"))?,
+        };
+        state.file_service.check_access(&dir)?;
+        state.file_service.task_progress(&dir, &state.workers).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Re-align the pipe table containing `cursor_offset` (a byte offset); no edit
+/// when the cursor isn't in a table
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn format_table(
+    content: String,
+    cursor_offset: usize,
+) -> Result<CommandResult<Option<TableEdit>>, String> {
+    debug!("Formatting table at offset {}", cursor_offset);
+    Ok(CommandResult::ok(format_table_at(&content, cursor_offset)))
+}
+
+/// What Enter does at `cursor_offset`: continue the list item there with the
+/// next marker, or end the list on an empty item; `None` outside lists
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn continue_list(
+    content: String,
+    cursor_offset: usize,
+) -> Result<CommandResult<Option<ListContinuation>>, String> {
+    debug!("Continuing list at offset {}", cursor_offset);
+    Ok(CommandResult::ok(list_continuation(&content, cursor_offset)))
+}
+
+/// Write the table in the source range `start..end` of a document to a CSV file;
+/// returns the number of rows written, header included
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn export_table_csv(
+    path: Option<PathBuf>,
+    content: Option<String>,
+    start: usize,
+    end: usize,
+    output_path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<usize>, String> {
+    debug!("Exporting table at {}..{} of {:?} to {:?}", start, end, path, output_path);
+
+    let result = async {
+        let markdown = resolve_content(path, content, &state).await?;
+        let table = table_in_range(&markdown, start, end)?;
+        state.file_service.check_access(&output_path)?;
+        tokio::fs::write(&output_path, table.to_csv()?).await
+            .with_context(|| format!("Failed to write {:?}", output_path))?;
+        info!("Exported a {}-row table to {:?}", table.rows.len(), output_path);
+        Ok(table.rows.len())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn check_text(
+    content: String,
+    language: Option<String>,
+    start: Option<usize>,
+    end: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<SpellingIssue>>, String> {
+    let language = language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    let range = Some((start.unwrap_or(0), end.unwrap_or(content.len())));
+    debug!("Spell-checking {} chars ({})", content.len(), language);
+
+    match state.spellchecker.check(&content, &language, range) {
+        Ok(issues) => Ok(CommandResult::ok(issues)),
         Err(e) => {
-            error!("Failed to save file {:?}: {}", path, e);
+            error!("Spell-check failed: {}", e);
             Ok(CommandResult::err(e.to_string()))
         }
     }
 }
 
 #[command]
-pub async fn get_app_config_dir() -> CommandResult<PathBuf> {
-    debug!("Getting app config directory");
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn suggest(
+    word: String,
+    language: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<String>>, String> {
+    let language = language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    debug!("Getting spelling suggestions for {:?} ({})", word, language);
 
-    match directories::ProjectDirs::from("com", "typolite", "Typora-Lite") {
-        Some(proj_dirs) => {
-            let config_dir = proj_dirs.config_dir().to_path_buf();
-            info!("Config directory: {:?}", config_dir);
-            CommandResult::ok(config_dir)
-        }
-        None => {
-            let fallback = PathBuf::from("./config");
-            warn!("Could not determine config directory, using fallback: {:?}", fallback);
-            CommandResult::ok(fallback)
+    match state.spellchecker.suggest(&word, &language) {
+        Ok(suggestions) => Ok(CommandResult::ok(suggestions)),
+        Err(e) => {
+            error!("Failed to get spelling suggestions: {}", e);
+            Ok(CommandResult::err(e.to_string()))
         }
     }
 }
 
 #[command]
-pub async fn watch_file(
-    path: PathBuf,
-    window: Window,
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn add_to_dictionary(
+    word: String,
+    language: Option<String>,
     state: State<'_, AppState>,
 ) -> Result<CommandResult<()>, String> {
-    info!("Starting file watch: {:?}", path);
-
-    let window_clone = window.clone();
-    let _path_clone = path.clone();
-    
-    let callback = move |event: FileChangeEvent| {
-        debug!("File change detected: {:?}", event);
-        
-        if let Err(e) = window_clone.emit("file-changed", &event) {
-            error!("Failed to emit file-changed event: {}", e);
-        }
-    };
+    let language = language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    debug!("Adding {:?} to user dictionary ({})", word, language);
 
-    match state.file_service.watch_file(path.clone(), callback).await {
-        Ok(()) => {
-            // Track the watcher
-            state.watchers.lock().unwrap().insert(path, true);
-            Ok(CommandResult::ok(()))
-        }
+    match state.spellchecker.add_user_word(&language, &word) {
+        Ok(()) => Ok(CommandResult::ok(())),
         Err(e) => {
-            error!("Failed to start watching file {:?}: {}", path, e);
+            error!("Failed to add word to user dictionary: {}", e);
             Ok(CommandResult::err(e.to_string()))
         }
     }
 }
 
 #[command]
-pub async fn unwatch_file(
-    path: PathBuf,
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn remove_from_dictionary(
+    word: String,
+    language: Option<String>,
     state: State<'_, AppState>,
 ) -> Result<CommandResult<()>, String> {
-    info!("Stopping file watch: {:?}", path);
+    let language = language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    debug!("Removing {:?} from user dictionary ({})", word, language);
 
-    match state.file_service.unwatch_file(&path) {
-        Ok(()) => {
-            // Remove from tracking
-            state.watchers.lock().unwrap().remove(&path);
-            Ok(CommandResult::ok(()))
-        }
+    match state.spellchecker.remove_user_word(&language, &word) {
+        Ok(()) => Ok(CommandResult::ok(())),
         Err(e) => {
-            error!("Failed to stop watching file {:?}: {}", path, e);
+            error!("Failed to remove word from user dictionary: {}", e);
             Ok(CommandResult::err(e.to_string()))
         }
     }
 }
 
 #[command]
-pub async fn get_file_metadata(
-    path: PathBuf,
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_user_dictionary(
+    language: Option<String>,
     state: State<'_, AppState>,
-) -> Result<CommandResult<FileMetadata>, String> {
-    debug!("Getting file metadata: {:?}", path);
+) -> Result<CommandResult<Vec<String>>, String> {
+    let language = language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    debug!("Listing user dictionary ({})", language);
 
-    match state.file_service.get_metadata(&path).await {
-        Ok(metadata) => {
-            Ok(CommandResult::ok(metadata))
-        }
+    match state.spellchecker.user_dictionary(&language) {
+        Ok(words) => Ok(CommandResult::ok(words)),
         Err(e) => {
-            error!("Failed to get file metadata {:?}: {}", path, e);
+            error!("Failed to read user dictionary: {}", e);
             Ok(CommandResult::err(e.to_string()))
         }
     }
 }
 
+/// Spelling, lint rules and local links checked in one pass, for the Problems panel
 #[command]
-pub async fn list_recent_files(
-    dir: Option<PathBuf>,
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn check_document(
+    path: Option<PathBuf>,
+    content: Option<String>,
+    language: Option<String>,
     state: State<'_, AppState>,
-) -> Result<CommandResult<Vec<FileMetadata>>, String> {
-    let search_dir = dir.unwrap_or_else(|| {
-        directories::UserDirs::new()
-            .and_then(|dirs| Some(dirs.document_dir()?.to_path_buf()))
-            .unwrap_or_else(|| PathBuf::from("."))
-    });
+) -> Result<CommandResult<Vec<Problem>>, String> {
+    let language = language.unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    debug!("Checking document {:?} ({})", path, language);
 
-    debug!("Listing recent files in: {:?}", search_dir);
+    let result = async {
+        let document = match &path {
+            Some(path) => Some(path.clone()),
+            None => state.current_file.read().await.clone(),
+        };
+        let content = resolve_content(path, content, &state).await?;
+        state.parser.check_limits(&content)?;
 
-    match state.file_service.list_markdown_files(&search_dir).await {
-        Ok(files) => {
-            info!("Found {} markdown files", files.len());
-            Ok(CommandResult::ok(files))
+        let mut problems = lint_markdown(&content);
+        problems.extend(check_links(&content, document.as_deref(), &state.parser).await?);
+        match state.spellchecker.check(&content, &language, None) {
+            Ok(issues) => problems.extend(spelling_problems(issues)),
+            Err(e) => warn!("Checking the document without spelling: {}", e),
         }
+        Ok(finish_problems(&content, problems))
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn check_grammar(
+    content: String,
+    language: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<GrammarIssue>>, String> {
+    // LanguageTool uses hyphenated language codes, e.g. en-US
+    let language = language.unwrap_or_else(|| DEFAULT_LANGUAGE.replace('_', "-"));
+    debug!("Checking grammar for {} chars ({})", content.len(), language);
+
+    let settings = state.settings.get().grammar;
+    match state.grammar_checker.check(&content, &language, &settings).await {
+        Ok(issues) => Ok(CommandResult::ok(issues)),
         Err(e) => {
-            error!("Failed to list files in {:?}: {}", search_dir, e);
+            error!("Grammar check failed: {}", e);
             Ok(CommandResult::err(e.to_string()))
         }
     }
 }
 
+/// Turn grammar checking on or off and choose the LanguageTool server it uses
 #[command]
-pub async fn get_app_version() -> CommandResult<String> {
-    let version = env!("CARGO_PKG_VERSION").to_string();
-    debug!("App version: {}", version);
-    CommandResult::ok(version)
-}
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_grammar_settings(
+    settings: GrammarSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating grammar settings (enabled: {}, {})", settings.enabled, settings.server_url);
 
-#[command]
-pub async fn get_system_info() -> CommandResult<SystemInfo> {
-    debug!("Getting system info");
-    
-    let info = SystemInfo {
-        os: std::env::consts::OS.to_string(),
-        arch: std::env::consts::ARCH.to_string(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
-    };
-    
-    CommandResult::ok(info)
-}
+    let result = async {
+        let url = url::Url::parse(settings.server_url.trim())
+            .with_context(|| format!("Invalid LanguageTool server URL: {}", settings.server_url))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            anyhow::bail!("The LanguageTool server URL must start with http:// or https://");
+        }
+        state.settings.update(|current| current.grammar = settings).await
+    }.await;
 
-#[derive(Debug, Serialize)]
-pub struct SystemInfo {
-    pub os: String,
-    pub arch: String,
-    pub version: String,
+    Ok(handle_command_error(result.map(|_| ())))
 }
 
-// Utility functions for commands
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn ai_assist(
+    action: AssistAction,
+    text: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<String>, String> {
+    debug!("AI assist {:?} ({} chars)", action, text.len());
 
-pub fn handle_command_error<T>(result: Result<T>) -> CommandResult<T> {
+    let settings = state.settings.get().ai;
+    let result = async {
+        let api_key = state.secrets.get(AI_API_KEY_KEY)?;
+        state.assistant.assist(action, &text, &settings, api_key.as_deref()).await
+    }.await;
     match result {
-        Ok(data) => CommandResult::ok(data),
+        Ok(output) => Ok(CommandResult::ok(output)),
         Err(e) => {
-            error!("Command error: {}", e);
-            CommandResult::err(e.to_string())
+            error!("AI assist failed: {}", e);
+            Ok(CommandResult::err(e.to_string()))
         }
     }
 }
 
-pub fn validate_markdown_file(path: &Path) -> Result<()> {
-    if !path.exists() {
-        return Err(anyhow::anyhow!("File does not exist: {:?}", path));
-    }
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_ai_settings(
+    settings: AiSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating AI assistant settings ({:?})", settings.provider);
 
-    if !path.is_file() {
-        return Err(anyhow::anyhow!("Path is not a file: {:?}", path));
+    let result = async {
+        let current = state.settings.get().ai;
+        // An omitted key keeps the one in the keychain so the frontend never needs to
+        // read it back, unless it would now be sent somewhere else
+        match &settings.api_key {
+            Some(api_key) => state.secrets.set(AI_API_KEY_KEY, api_key.trim())?,
+            None if settings.endpoint != current.endpoint || settings.provider != current.provider => {
+                info!("AI endpoint changed, forgetting the API key");
+                state.secrets.set(AI_API_KEY_KEY, "")?;
+            }
+            None => {}
+        }
+        state.settings.update(|current| current.ai = AiSettings { api_key: None, ..settings }).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Move an AI API key saved in settings.json by an older version into the keychain
+pub async fn move_ai_key_to_keychain(state: &AppState) {
+    let Some(api_key) = state.settings.get().ai.api_key else {
+        return;
+    };
+    let result = async {
+        state.secrets.set(AI_API_KEY_KEY, api_key.trim())?;
+        // Saving drops the key from the file
+        state.settings.update(|current| current.ai.api_key = None).await?;
+        anyhow::Ok(())
+    }.await;
+    match result {
+        Ok(()) => info!("Moved the AI API key from settings to the keychain"),
+        Err(e) => warn!("Failed to move the AI API key to the keychain: {:#}", e),
     }
+}
 
-    let extension = path.extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("");
+/// Save the HTML filters, once the user has confirmed in a native dialog the exact
+/// command line of every filter that wasn't saved before
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_html_filters(
+    filters: Vec<HtmlFilter>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating HTML filters ({} declared)", filters.len());
 
-    if !matches!(extension.to_lowercase().as_str(), "md" | "markdown" | "mdown" | "mkd") {
-        return Err(anyhow::anyhow!("Not a markdown file: {:?}", path));
+    if let Some(filter) = filters.iter().find(|f| f.command.trim().is_empty()) {
+        return Ok(CommandResult::err(format!("Filter '{}' has no command", filter.name)));
     }
 
-    Ok(())
+    let result = async {
+        let current = state.settings.get().html_filters;
+        let new_commands: Vec<String> = filters.iter()
+            .filter(|filter| !current.iter().any(|saved| saved.command == filter.command && saved.args == filter.args))
+            .map(|filter| format!("{}: {}", filter.name, filter.command_line()))
+            .collect();
+        confirm_natively(&window, "HTML Filters", "Let Typolite run these commands on rendered documents?", &new_commands)?;
+        state.settings.update(|current| current.html_filters = filters).await?;
+        Ok(())
+    }.await;
+    Ok(handle_command_error(result))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::NamedTempFile;
-    use std::io::Write;
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn publish_gist(
+    path: Option<PathBuf>,
+    content: Option<String>,
+    include_html: bool,
+    public: bool,
+    description: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<GistResult>, String> {
+    debug!("Publishing gist for {:?}", path);
 
-    #[test]
-    fn test_validate_markdown_file() {
-        // Test valid markdown file
-        let mut temp_file = NamedTempFile::with_suffix(".md").unwrap();
-        write!(temp_file, "# Test").unwrap();
-        
-        assert!(validate_markdown_file(temp_file.path()).is_ok());
+    let token = match state.secrets.get(GITHUB_TOKEN_KEY) {
+        Ok(Some(token)) => token,
+        Ok(None) => return Ok(CommandResult::err("No GitHub token configured".to_string())),
+        Err(e) => {
+            error!("Failed to read GitHub token: {}", e);
+            return Ok(CommandResult::err(e.to_string()));
+        }
+    };
 
-        // Test invalid extension
-        let mut temp_file = NamedTempFile::with_suffix(".txt").unwrap();
-        write!(temp_file, "# Test").unwrap();
-        
-        assert!(validate_markdown_file(temp_file.path()).is_err());
-    }
+    let current = state.current_file.read().await.clone();
+    let file_name = path.clone()
+        .or(current)
+        .and_then(|p| p.file_name().map(|name| name.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "document.md".to_string());
 
-    #[test]
-    fn test_command_result() {
-        let success = CommandResult::ok("test data");
-        assert!(success.success);
-        assert_eq!(success.data, Some("test data"));
-        assert!(success.error.is_none());
+    let result = async {
+        let markdown = resolve_content(path, content, &state).await?;
+        let html = if include_html {
+            Some(state.parser.parse(&markdown)?.html)
+        } else {
+            None
+        };
 
-        let error = CommandResult::<String>::err("test error".to_string());
-        assert!(!error.success);
-        assert!(error.data.is_none());
+        let request = GistRequest { file_name, markdown, html, description, public };
+        state.gist_service.publish(&request, &token).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_github_token(
+    token: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating GitHub token");
+    Ok(handle_command_error(state.secrets.set(GITHUB_TOKEN_KEY, token.trim())))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_publish_targets(state: State<'_, AppState>) -> Result<CommandResult<Vec<PublishTargetInfo>>, String> {
+    debug!("Listing publish targets");
+
+    let settings = state.settings.get().publishing;
+    let targets = PublishTarget::ALL
+        .into_iter()
+        .map(|target| {
+            let has_credential = matches!(state.secrets.get(target.credential_key()), Ok(Some(_)));
+            let has_site = target != PublishTarget::Ghost || settings.ghost_url.is_some();
+            PublishTargetInfo {
+                target,
+                name: target.display_name().to_string(),
+                configured: has_credential && has_site,
+            }
+        })
+        .collect();
+
+    Ok(CommandResult::ok(targets))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn publish_document(
+    target: PublishTarget,
+    path: Option<PathBuf>,
+    content: Option<String>,
+    publish: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<PublishResult>, String> {
+    debug!("Publishing {:?} to {:?}", path, target);
+
+    let result = async {
+        let credential = state.secrets.get(target.credential_key())?
+            .ok_or_else(|| anyhow::anyhow!("No credentials configured for {}", target.display_name()))?;
+        let markdown = resolve_content(path, content, &state).await?;
+        let post = Post::from_document(&markdown, publish.unwrap_or(false), |body| {
+            Ok(state.parser.parse(body)?.html)
+        })?;
+
+        let settings = state.settings.get().publishing;
+        state.publish_service.publish(target, &post, &credential, &settings).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_publish_credential(
+    target: PublishTarget,
+    credential: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating credentials for {:?}", target);
+    Ok(handle_command_error(state.secrets.set(target.credential_key(), credential.trim())))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_publish_settings(
+    settings: PublishSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating publish settings");
+
+    let result = async {
+        // A credential must not follow its target to another site
+        let current = state.settings.get().publishing;
+        for target in PublishTarget::ALL {
+            if target.site_url(&settings) != target.site_url(&current) {
+                info!("{} site changed, forgetting its credential", target.display_name());
+                state.secrets.set(target.credential_key(), "")?;
+            }
+        }
+        state.settings.update(|current| current.publishing = settings).await
+    }.await;
+
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn email_document(
+    html_content: String,
+    file_name: String,
+    format: ExportFormat,
+    request: EmailRequest,
+    delivery: EmailDelivery,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<EmailResult>, String> {
+    debug!("Emailing {} as {:?} via {:?}", file_name, format, delivery);
+
+    let result = async {
+        if request.to.iter().all(|to| to.trim().is_empty()) {
+            return Err(anyhow::anyhow!("At least one recipient is required"));
+        }
+
+        // Export into a fresh scratch folder so the attachment keeps its readable name
+        let stem = Path::new(&file_name).file_stem().map(|s| s.to_string_lossy().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "document".to_string());
+        let export_dir = state.export_service.temp_dir().join(uuid::Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&export_dir).await
+            .with_context(|| format!("Failed to create {:?}", export_dir))?;
+        let attachment = export_dir.join(format!("{}.{}", stem, format.extension()));
+
+        let html_content = with_bibliography(&state, html_content, None).await;
+        let html_content = state.plugins.run_hook(PluginHook::PreExport, html_content);
+        let html_content = state.html_filters
+            .apply(&state.settings.get().html_filters, FilterStage::Export, html_content)
+            .await;
+        let options = ExportOptions { format, ..ExportOptions::default() };
+        state.export_service.export(&html_content, &attachment, options).await?;
+
+        match delivery {
+            EmailDelivery::Smtp => {
+                let password = state.secrets.get(SMTP_PASSWORD_KEY)?;
+                let settings = state.settings.get().email;
+                state.email_service.send(&request, &attachment, &settings, password).await?;
+                Ok(EmailResult { delivery, attachment, mailto_url: None })
+            }
+            EmailDelivery::Mailto => {
+                let url = mailto_url(&request, Some(&attachment));
+                tauri::api::shell::open(&app.shell_scope(), &url, None)
+                    .context("Failed to open the mail client")?;
+                Ok(EmailResult { delivery, attachment, mailto_url: Some(url) })
+            }
+        }
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_email_settings(
+    settings: EmailSettings,
+    password: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating email settings for {}", settings.smtp_host);
+
+    let result = async {
+        // An omitted password keeps the one already in the keychain, unless it
+        // would now be sent to another server
+        match password {
+            Some(password) => state.secrets.set(SMTP_PASSWORD_KEY, &password)?,
+            None if settings.smtp_host != state.settings.get().email.smtp_host => {
+                info!("SMTP host changed, forgetting the password");
+                state.secrets.set(SMTP_PASSWORD_KEY, "")?;
+            }
+            None => {}
+        }
+        state.settings.update(|current| current.email = settings).await
+    }.await;
+
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn copy_as_html(
+    markdown_fragment: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Copying selection as HTML ({} chars)", markdown_fragment.len());
+
+    let result = state.parser.parse(&markdown_fragment)
+        .and_then(|parsed| state.clipboard.copy_html(&parsed.html, &markdown_fragment));
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn copy_as_plain(
+    markdown_fragment: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Copying selection as plain text ({} chars)", markdown_fragment.len());
+
+    let plain_text = state.parser.render_plain_text(&markdown_fragment);
+    Ok(handle_command_error(state.clipboard.copy_text(&plain_text)))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn convert_html_to_markdown(
+    html: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<String>, String> {
+    debug!("Converting pasted HTML to markdown ({} bytes)", html.len());
+
+    match state.import_service.html_to_markdown(&html) {
+        Ok(markdown) => Ok(CommandResult::ok(markdown)),
+        Err(e) => {
+            error!("Failed to convert HTML to markdown: {}", e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn import_notion_export(
+    source: PathBuf,
+    output_dir: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<NotionImportResult>, String> {
+    debug!("Importing Notion export {:?} into {:?}", source, output_dir);
+
+    let checked = state.file_service.check_access(&source)
+        .and_then(|_| state.file_service.check_access(&output_dir));
+    if let Err(e) = checked {
+        return Ok(CommandResult::err(e.to_string()));
+    }
+
+    match state.notion_importer.import(&source, &output_dir).await {
+        Ok(result) => {
+            info!("Imported {} documents from Notion", result.documents.len());
+            Ok(CommandResult::ok(result))
+        }
+        Err(e) => {
+            error!("Failed to import Notion export {:?}: {}", source, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn import_docx(
+    source: PathBuf,
+    output_path: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocxImportResult>, String> {
+    debug!("Importing DOCX {:?}", source);
+
+    let checked = std::iter::once(source.as_path()).chain(output_path.as_deref())
+        .try_for_each(|path| state.file_service.check_access(path));
+    if let Err(e) = checked {
+        return Ok(CommandResult::err(e.to_string()));
+    }
+
+    match state.docx_importer.import(&source, output_path.as_deref()).await {
+        Ok(result) => Ok(CommandResult::ok(result)),
+        Err(e) => {
+            error!("Failed to import DOCX {:?}: {}", source, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn import_html(
+    url_or_file: String,
+    output_dir: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<ClipResult>, String> {
+    debug!("Importing HTML page {}", url_or_file);
+
+    let is_web_page = url_or_file.starts_with("http://") || url_or_file.starts_with("https://");
+    let local_file = Some(Path::new(url_or_file.strip_prefix("file://").unwrap_or(url_or_file.as_str()))).filter(|_| !is_web_page);
+    let checked = local_file.into_iter().chain(output_dir.as_deref())
+        .try_for_each(|path| state.file_service.check_access(path));
+    if let Err(e) = checked {
+        return Ok(CommandResult::err(e.to_string()));
+    }
+
+    match state.web_clipper.import(&url_or_file, output_dir.as_deref()).await {
+        Ok(result) => Ok(CommandResult::ok(result)),
+        Err(e) => {
+            error!("Failed to import {}: {}", url_or_file, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+/// Title of a pasted URL's page, so the editor can paste `[Title](url)`
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn fetch_link_title(
+    url: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<LinkTitle>, String> {
+    debug!("Fetching link title for {}", url);
+    Ok(handle_command_error(state.web_clipper.link_title(&url).await))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn export_to_pdf(
+    html_content: String,
+    output_path: PathBuf,
+    options: Option<ExportOptions>,
+    document_path: Option<PathBuf>,
+    preset: Option<String>,
+    job_id: Option<String>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<ExportResult>, String> {
+    debug!("Exporting to PDF: {:?}", output_path);
+
+    if let Err(e) = check_export_access(&state.file_service, &output_path, document_path.as_deref()) {
+        return Ok(CommandResult::err(e.to_string()));
+    }
+
+    let options = match (options, preset) {
+        (None, Some(name)) => match state.export_presets.get(&name) {
+            Some(preset) => Some(preset.options),
+            None => return Ok(CommandResult::err(format!("Unknown export preset: {}", name))),
+        },
+        (options, _) => options,
+    };
+    // Without explicit options, reuse whatever was last used for this document
+    let saved_options = match (&options, &document_path) {
+        (None, Some(document)) => state.export_service.load_document_options(document).await
+            .unwrap_or_else(|e| {
+                warn!("Ignoring saved export options for {:?}: {}", document, e);
+                None
+            }),
+        _ => None,
+    };
+    let mut export_options = options.or(saved_options).unwrap_or_default();
+    let remembered_options = document_path.as_ref().map(|_| export_options.clone());
+    let mut style = None;
+    if let Some(document) = &document_path {
+        match state.file_service.read_file(document).await {
+            Ok(markdown) => {
+                export_options.document_css = document_css(&markdown, Some(document)).await;
+                export_options.base_dir = document.parent().map(Path::to_path_buf);
+                export_options.file_name = document.file_name().map(|name| name.to_string_lossy().to_string());
+                export_options.direction = export_options.direction.or(Some(document_direction(&markdown)));
+                style = citation_style(&state, &markdown, Some(document)).await;
+                front_matter_metadata(&mut export_options, &markdown);
+                export_options.markdown = Some(markdown);
+            }
+            Err(e) => warn!("Exporting without the document's stylesheet and citation style: {}", e),
+        }
+    }
+    let input_bytes = html_content.len();
+    let mut timer = PhaseTimer::start();
+    let html_content = with_bibliography(&state, html_content, style).await;
+    timer.lap("bibliography");
+    let html_content = state.plugins.run_hook(PluginHook::PreExport, html_content);
+    timer.lap("plugins");
+    let html_content = state.html_filters
+        .apply(&state.settings.get().html_filters, FilterStage::Export, html_content)
+        .await;
+    timer.lap("filters");
+
+    let job = state.export_jobs.begin(job_id);
+    let progress = export_progress(&window, job.id());
+    match state.export_service.export_job(&html_content, &output_path, export_options, &progress, job.token()).await {
+        Ok(result) => {
+            timer.extend(&result.timings);
+            state.profiler.record(OperationKind::Export, &output_path.display().to_string(), input_bytes, timer.finish());
+
+            if let (Some(document), Some(options)) = (&document_path, &remembered_options) {
+                if let Err(e) = state.export_service.save_document_options(document, options).await {
+                    warn!("Failed to remember export options for {:?}: {}", document, e);
+                }
+            }
+            info!("PDF export completed: {:?} ({} bytes)", 
+                  result.output_path, result.file_size);
+            Ok(CommandResult::ok(result))
+        }
+        Err(e) => {
+            error!("Failed to export PDF: {}", e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+/// Export several markdown files as one document, each file a chapter, with one
+/// table of contents and chapter numbering running across them all
+///
+/// `files` are taken in the order given; otherwise every markdown file in
+/// `folder` is used, in name order. The first file's stylesheet applies.
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn export_merged_documents(
+    files: Option<Vec<PathBuf>>,
+    folder: Option<PathBuf>,
+    output_path: PathBuf,
+    options: Option<ExportOptions>,
+    merge: Option<MergeOptions>,
+    job_id: Option<String>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<ExportResult>, String> {
+    debug!("Exporting merged documents to {:?}", output_path);
+
+    let result = async {
+        state.file_service.check_access(&output_path)?;
+        let paths = match (files, folder) {
+            (Some(files), _) if !files.is_empty() => files,
+            (_, Some(folder)) => {
+                state.file_service.check_access(&folder)?;
+                let mut paths: Vec<PathBuf> = state.file_service.list_markdown_files(&folder, &state.workers).await?
+                    .into_iter()
+                    .map(|file| file.path)
+                    .collect();
+                sort_chapters(&mut paths);
+                paths
+            }
+            _ => anyhow::bail!("Choose the files or the folder to merge"),
+        };
+        if paths.is_empty() {
+            anyhow::bail!("No markdown files to merge");
+        }
+
+        let mut documents = Vec::with_capacity(paths.len());
+        for path in paths {
+            let markdown = state.file_service.read_file(&path).await?;
+            documents.push((path, markdown));
+        }
+        let merged = merge_documents(&documents, &merge.unwrap_or_default());
+        info!("Merged {} files for export", merged.chapters.len());
+
+        let mut options = options.unwrap_or_default();
+        let (first_path, first_markdown) = &documents[0];
+        options.document_css = document_css(first_markdown, Some(first_path)).await;
+        options.base_dir = first_path.parent().map(Path::to_path_buf);
+        let job = state.export_jobs.begin(job_id);
+        export_markdown(&state, merged.markdown, &output_path, options, &export_progress(&window, job.id()), job.token()).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Load the `book.toml` project in `root`, the active workspace by default
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn load_book_project(
+    root: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<BookProject>, String> {
+    debug!("Loading book project in {:?}", root);
+
+    let result = async {
+        let root = book_root(&state, root)?;
+        state.file_service.check_access(&root.join(MANIFEST_FILE))?;
+        BookProject::load(&root).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Save a new chapter order (or set of chapters) to the project's manifest
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn reorder_book_chapters(
+    root: Option<PathBuf>,
+    chapters: Vec<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<BookProject>, String> {
+    debug!("Reordering {} book chapters", chapters.len());
+
+    let result = async {
+        let root = book_root(&state, root)?;
+        state.file_service.check_access(&root.join(MANIFEST_FILE))?;
+        set_book_chapters(&root, chapters).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Export the whole book: a title page, then every chapter in manifest order,
+/// with the manifest's export options
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn export_book(
+    root: Option<PathBuf>,
+    output_path: Option<PathBuf>,
+    job_id: Option<String>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<ExportResult>, String> {
+    debug!("Exporting book in {:?}", root);
+
+    let result = async {
+        let root = book_root(&state, root)?;
+        state.file_service.check_access(&root.join(MANIFEST_FILE))?;
+        let project = BookProject::load(&root).await?;
+        if project.book.chapters.is_empty() {
+            anyhow::bail!("Add chapters to {} before exporting", MANIFEST_FILE);
+        }
+        if let Some(missing) = project.missing_chapters.first() {
+            anyhow::bail!("Chapter not found: {:?}", missing);
+        }
+
+        let mut chapters = Vec::with_capacity(project.book.chapters.len());
+        for path in project.chapter_paths() {
+            let markdown = state.file_service.read_file(&path).await?;
+            chapters.push((path, markdown));
+        }
+        let output_path = output_path.unwrap_or_else(|| project.output_path());
+        state.file_service.check_access(&output_path)?;
+        if let Some(parent) = output_path.parent() {
+            tokio::fs::create_dir_all(parent).await
+                .with_context(|| format!("Failed to create output folder: {:?}", parent))?;
+        }
+
+        let mut options = project.export.clone();
+        let (first_path, first_markdown) = &chapters[0];
+        options.document_css = document_css(first_markdown, Some(first_path)).await;
+        options.base_dir = first_path.parent().map(Path::to_path_buf);
+        options.title = options.title.or_else(|| Some(project.book.title.clone()).filter(|title| !title.is_empty()));
+        if options.authors.is_empty() {
+            options.authors = project.book.authors.clone();
+        }
+        options.language = options.language.or_else(|| project.book.language.clone());
+        info!("Exporting book {:?} ({} chapters)", project.book.title, chapters.len());
+        let job = state.export_jobs.begin(job_id);
+        export_markdown(&state, project.assemble(&chapters), &output_path, options, &export_progress(&window, job.id()), job.token()).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Refuse an export written outside the allowed folders, or one whose document's
+/// saved options (read and written next to it) are outside them
+fn check_export_access(file_service: &FileService, output_path: &Path, document_path: Option<&Path>) -> Result<()> {
+    file_service.check_access(output_path)?;
+    if let Some(document) = document_path {
+        file_service.check_access(&document_options_path(document))?;
+    }
+    Ok(())
+}
+
+fn book_root(state: &AppState, root: Option<PathBuf>) -> Result<PathBuf> {
+    match root {
+        Some(root) => Ok(root),
+        None => state.workspaces.active()
+            .map(|workspace| workspace.root)
+            .context("Open a workspace or choose the book folder"),
+    }
+}
+
+/// Diff two markdown files for review; the HTML can go straight to `export_to_pdf`
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn compare_documents(
+    old_path: PathBuf,
+    new_path: PathBuf,
+    layout: Option<DiffLayout>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentComparison>, String> {
+    debug!("Comparing {:?} with {:?}", old_path, new_path);
+
+    let result = async {
+        let old = state.file_service.read_file(&old_path).await?;
+        let new = state.file_service.read_file(&new_path).await?;
+        let label = |path: &Path| path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        Ok(diff_documents(&old, &new, &label(&old_path), &label(&new_path), layout.unwrap_or_default()))
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Check a document for accessibility problems before publishing: an exported
+/// HTML file, or rendered HTML wrapped the way an export with `options` would be
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn audit_accessibility(
+    html_content: Option<String>,
+    export_path: Option<PathBuf>,
+    options: Option<ExportOptions>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<AccessibilityReport>, String> {
+    debug!("Auditing accessibility of {:?}", export_path);
+
+    let result = async {
+        let html = match (export_path, html_content) {
+            (Some(path), _) => {
+                state.file_service.check_access(&path)?;
+                tokio::fs::read_to_string(&path).await
+                    .with_context(|| format!("Failed to read exported document: {:?}", path))?
+            }
+            (None, Some(content)) => {
+                let mut options = options.unwrap_or_default();
+                options.fonts.load(options.base_dir.as_deref()).await?;
+                state.export_service.create_complete_html(&content, &options)?
+            }
+            (None, None) => anyhow::bail!("Nothing to audit: pass an exported file or HTML content"),
+        };
+        Ok(audit_html(&html))
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Changes in the editor's content against the saved file, a snapshot or the
+/// last Git commit, as unified-diff hunks for reviewing before save or commit
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_document_diff(
+    current_content: String,
+    base: Option<DiffBase>,
+    path: Option<PathBuf>,
+    context_lines: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentDiff>, String> {
+    let base = base.unwrap_or(DiffBase::Disk);
+    debug!("Diffing document against {:?}", base);
+
+    let path = match path {
+        Some(path) => path,
+        None => match state.current_file.read().await.clone() {
+            Some(path) => path,
+            None => return Ok(CommandResult::err("Save the document before comparing it".to_string())),
+        },
+    };
+
+    let result = async {
+        let old = match &base {
+            DiffBase::Disk => state.file_service.read_file(&path).await?,
+            DiffBase::Snapshot { id } => state.snapshots.read(&path, id).await?,
+            DiffBase::GitHead => git_head_version(&path).await?,
+        };
+        Ok(DocumentDiff::new(base, &old, &current_content, context_lines.unwrap_or(DIFF_CONTEXT_LINES)))
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn create_snapshot(
+    path: PathBuf,
+    content: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<SnapshotInfo>, String> {
+    debug!("Creating snapshot of {:?}", path);
+    Ok(handle_command_error(state.snapshots.create(&path, &content).await))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_snapshots(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<SnapshotInfo>>, String> {
+    debug!("Listing snapshots of {:?}", path);
+    Ok(handle_command_error(state.snapshots.list(&path).await))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_document_export_options(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Option<ExportOptions>>, String> {
+    debug!("Loading export options for {:?}", path);
+
+    let result = async {
+        state.file_service.check_access(&document_options_path(&path))?;
+        state.export_service.load_document_options(&path).await
+    }.await;
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_document_export_options(
+    path: PathBuf,
+    options: ExportOptions,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Saving export options for {:?}", path);
+
+    let result = async {
+        state.file_service.check_access(&document_options_path(&path))?;
+        state.export_service.save_document_options(&path, &options).await
+    }.await;
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn export_to_static_site(
+    path: Option<PathBuf>,
+    content: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<SiteExportResult>, String> {
+    debug!("Exporting {:?} to static site", path);
+
+    let result = async {
+        let markdown = resolve_content(path.clone(), content, &state).await?;
+        let settings = state.settings.get().static_site;
+        state.static_site_exporter.export(&markdown, path.as_deref(), &settings).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_static_site_settings(
+    settings: StaticSiteSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating static site settings ({:?})", settings.generator);
+
+    let result = state.settings.update(|current| current.static_site = settings).await;
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn export_to_confluence(
+    path: Option<PathBuf>,
+    content: Option<String>,
+    output_path: Option<PathBuf>,
+    publish: bool,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<ConfluenceExportResult>, String> {
+    debug!("Exporting {:?} to Confluence storage format (publish: {})", path, publish);
+
+    let result = async {
+        let markdown = resolve_content(path, content, &state).await?;
+        let post = Post::from_document(&markdown, false, |body| Ok(render_storage_format(body)))?;
+
+        if let Some(output_path) = &output_path {
+            state.file_service.check_access(output_path)?;
+            tokio::fs::write(output_path, &post.html).await
+                .with_context(|| format!("Failed to write {:?}", output_path))?;
+            info!("Wrote Confluence storage format to {:?}", output_path);
+        }
+
+        let page = if publish {
+            let token = state.secrets.get(CONFLUENCE_TOKEN_KEY)?
+                .ok_or_else(|| anyhow::anyhow!("No Confluence API token configured"))?;
+            let settings = state.settings.get().confluence;
+            Some(state.confluence.publish(&post.title, &post.html, &settings, &token).await?)
+        } else {
+            None
+        };
+
+        Ok(ConfluenceExportResult { storage: post.html, output_path, page })
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_confluence_settings(
+    settings: ConfluenceSettings,
+    token: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating Confluence settings for {}", settings.base_url);
+
+    let result = async {
+        // An omitted token keeps the one already in the keychain, unless it would
+        // now be sent to another site
+        match token {
+            Some(token) => state.secrets.set(CONFLUENCE_TOKEN_KEY, token.trim())?,
+            None if settings.base_url != state.settings.get().confluence.base_url => {
+                info!("Confluence site changed, forgetting the API token");
+                state.secrets.set(CONFLUENCE_TOKEN_KEY, "")?;
+            }
+            None => {}
+        }
+        state.settings.update(|current| current.confluence = settings).await
+    }.await;
+
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn generate_print_preview(
+    html_content: String,
+    options: Option<ExportOptions>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<String>, String> {
+    debug!("Generating print preview ({} bytes)", html_content.len());
+
+    let mut export_options = options.unwrap_or_default();
+    // Preview exactly what the exporter would receive
+    let html_content = state.plugins.run_hook(PluginHook::PreExport, html_content);
+    let html_content = state.html_filters
+        .apply(&state.settings.get().html_filters, FilterStage::Export, html_content)
+        .await;
+
+    let preview = match export_options.fonts.load(export_options.base_dir.as_deref()).await {
+        Ok(()) => state.export_service.render_print_preview(&html_content, &export_options),
+        Err(e) => Err(e),
+    };
+    match preview {
+        Ok(preview) => Ok(CommandResult::ok(preview)),
+        Err(e) => {
+            error!("Failed to generate print preview: {}", e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn save_file(
+    path: PathBuf,
+    content: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Saving file: {:?}", path);
+
+    match state.file_service.write_file(&path, &content).await {
+        Ok(()) => {
+            info!("File saved successfully: {:?}", path);
+            state.sessions.record_activity(&path);
+            if let Err(e) = state.sessions.save().await {
+                warn!("Failed to save session history: {}", e);
+            }
+            if let Err(e) = state.goals.save().await {
+                warn!("Failed to save goal progress: {}", e);
+            }
+            queue_exports_on_save(&state, &path);
+            Ok(CommandResult::ok(()))
+        }
+        Err(e) => {
+            error!("Failed to save file {:?}: {}", path, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+/// Store the password for encrypted (`.md.enc`) documents in the keychain;
+/// an empty password removes it
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_document_password(
+    password: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating the document password");
+
+    let result = state.secrets.set(DOCUMENT_PASSWORD_KEY, &password);
+    if result.is_ok() {
+        state.file_service.set_encryption_password(Some(password));
+    }
+    Ok(handle_command_error(result))
+}
+
+/// Replace a plain document with an encrypted copy next to it; returns the new path
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn encrypt_document(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<PathBuf>, String> {
+    debug!("Encrypting {:?}", path);
+
+    let result = async {
+        if is_encrypted_path(&path) {
+            anyhow::bail!("{:?} is already encrypted", path);
+        }
+        let target = encrypted_path(&path);
+        replace_document(&state, &path, &target).await?;
+        info!("Encrypted {:?} as {:?}", path, target);
+        Ok(target)
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Replace an encrypted document with a plain copy next to it; returns the new path
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn decrypt_document(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<PathBuf>, String> {
+    debug!("Decrypting {:?}", path);
+
+    let result = async {
+        if !is_encrypted_path(&path) {
+            anyhow::bail!("{:?} is not encrypted", path);
+        }
+        let target = path.with_extension("");
+        replace_document(&state, &path, &target).await?;
+        info!("Decrypted {:?} to {:?}", path, target);
+        Ok(target)
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Write `source`'s content to `target` (encrypting or decrypting by extension),
+/// then remove `source`
+async fn replace_document(state: &AppState, source: &Path, target: &Path) -> Result<()> {
+    if target.exists() {
+        anyhow::bail!("{:?} already exists", target);
+    }
+    let content = state.file_service.read_file(source).await?;
+    state.file_service.write_file(target, &content).await?;
+    tokio::fs::remove_file(source).await
+        .with_context(|| format!("Failed to remove {:?}", source))?;
+
+    let mut current = state.current_file.write().await;
+    if current.as_deref() == Some(source) {
+        *current = Some(target.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Load a document into a backend buffer, from `content` or else from `path`
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn open_document(
+    document: String,
+    path: Option<PathBuf>,
+    content: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentInfo>, String> {
+    debug!("Opening document {} from {:?}", document, path);
+
+    let result = async {
+        let content = match (content, &path) {
+            (Some(content), _) => content,
+            (None, Some(path)) => state.file_service.read_file(path).await?,
+            (None, None) => String::new(),
+        };
+        Ok(state.documents.open(&document, &content, path))
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Apply the editor's changes to a document buffer
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn apply_edit(
+    document: String,
+    version: Option<u64>,
+    edits: Vec<DocumentEdit>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentInfo>, String> {
+    debug!("Applying {} edits to {}", edits.len(), document);
+
+    if let Some(path) = state.documents.info(&document).and_then(|info| info.path) {
+        state.sessions.record_activity(&path);
+    }
+    Ok(handle_command_error(state.documents.apply_edits(&document, version, &edits)))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn close_document(
+    document: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<bool>, String> {
+    debug!("Closing document {}", document);
+    state.parse_scheduler.cancel(&document);
+    if let Some(path) = state.documents.info(&document).and_then(|info| info.path) {
+        state.file_service.release_mapped_file(&path);
+    }
+    Ok(CommandResult::ok(state.documents.close(&document)))
+}
+
+/// Replace every occurrence of `find` in a document buffer
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn replace_in_document(
+    document: String,
+    find: String,
+    replace: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentChange>, String> {
+    debug!("Replacing {:?} in {}", find, document);
+    Ok(handle_command_error(state.documents.replace_all(&document, &find, &replace)))
+}
+
+/// Check or uncheck the task list item on a 1-based line of a document buffer
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn toggle_task(
+    document: String,
+    line: usize,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentChange>, String> {
+    debug!("Toggling task on line {} of {}", line, document);
+    Ok(handle_command_error(state.documents.toggle_task(&document, line)))
+}
+
+/// Settle a merge conflict in a document buffer by keeping one side (or both)
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn resolve_conflict(
+    document: String,
+    index: usize,
+    side: ConflictSide,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentChange>, String> {
+    debug!("Resolving conflict {} of {} with {:?}", index, document, side);
+    Ok(handle_command_error(state.documents.resolve_conflict(&document, index, side)))
+}
+
+/// Write a document buffer to disk, to `path` or where it was opened from
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn save_document(
+    document: String,
+    path: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DocumentInfo>, String> {
+    debug!("Saving document {} to {:?}", document, path);
+
+    let result = async {
+        let (open_path, content) = state.documents.snapshot(&document)
+            .with_context(|| format!("Document is not open: {}", document))?;
+        let path = path.or(open_path)
+            .with_context(|| format!("Document {} has never been saved, so it needs a path", document))?;
+
+        state.file_service.write_file(&path, &content).await?;
+        state.documents.set_path(&document, path.clone());
+        state.sessions.record_activity(&path);
+        if let Err(e) = state.sessions.save().await {
+            warn!("Failed to save session history: {}", e);
+        }
+        if let Err(e) = state.goals.save().await {
+            warn!("Failed to save goal progress: {}", e);
+        }
+
+        info!("Document {} saved to {:?}", document, path);
+        state.documents.info(&document).context("Document was closed while saving")
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Timing breakdowns of the last `limit` parses and exports, for attaching to bug reports
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_performance_profile(
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<PerformanceProfile>, String> {
+    debug!("Getting performance profile for the last {:?} operations", limit);
+    Ok(CommandResult::ok(state.profiler.profile(limit.unwrap_or(20))))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_session_stats(
+    path: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<SessionStats>>, String> {
+    debug!("Getting session stats for {:?}", path);
+    Ok(CommandResult::ok(state.sessions.stats(path.as_deref())))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn mark_dirty(window: Window, state: State<'_, AppState>) -> Result<CommandResult<()>, String> {
+    debug!("Marking window {} as dirty", window.label());
+    state.set_dirty(window.label(), true).await;
+    Ok(CommandResult::ok(()))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn mark_clean(window: Window, state: State<'_, AppState>) -> Result<CommandResult<()>, String> {
+    debug!("Marking window {} as clean", window.label());
+    state.set_dirty(window.label(), false).await;
+    Ok(CommandResult::ok(()))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn start_preview_server(
+    options: Option<PreviewServerOptions>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<PreviewServerInfo>, String> {
+    debug!("Starting preview server with {:?}", options);
+    Ok(handle_command_error(state.preview_server.start(&options.unwrap_or_default()).await))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn stop_preview_server(state: State<'_, AppState>) -> Result<CommandResult<()>, String> {
+    debug!("Stopping preview server");
+    state.preview_server.stop().await;
+    Ok(CommandResult::ok(()))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_api_settings(
+    settings: ApiSettings,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Option<ApiServerInfo>>, String> {
+    debug!("Updating API settings (enabled: {})", settings.enabled);
+
+    let result = async {
+        state.settings.update(|current| current.api = settings).await?;
+        apply_api_settings(&app).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Change where exports keep scratch files and how long they're kept
+///
+/// A changed browser or mermaid-cli executable is only saved once the user
+/// confirms it in a native dialog, since exports run them.
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_export_settings(
+    settings: ExportSettings,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating export settings (temp dir: {:?})", settings.temp_dir);
+
+    let result = async {
+        let current = state.settings.get().export;
+        let mut executables = Vec::new();
+        if let Some(browser) = settings.browser_path.as_ref().filter(|path| current.browser_path.as_ref() != Some(*path)) {
+            executables.push(format!("Print PDFs with {}", browser.display()));
+        }
+        if let Some(cli) = settings.mermaid_cli.as_ref().filter(|path| current.mermaid_cli.as_ref() != Some(*path)) {
+            executables.push(format!("Draw mermaid diagrams with {}", cli.display()));
+        }
+        confirm_natively(&window, "Export Programs", "Let Typolite run these programs when exporting?", &executables)?;
+
+        if let Some(dir) = &settings.temp_dir {
+            tokio::fs::create_dir_all(dir).await
+                .with_context(|| format!("Failed to create export temp folder: {:?}", dir))?;
+        }
+        state.export_service.set_temp_dir(settings.temp_dir.clone());
+        state.export_service.set_browser_path(settings.browser_path.clone());
+        state.export_service.set_mermaid_cli(settings.mermaid_cli.clone());
+        state.settings.update(|current| current.export = settings).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Replace the global export-on-save rules; workspace rules are part of the workspace settings
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_export_on_save_rules(
+    rules: Vec<ExportOnSaveRule>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating export-on-save rules ({} rules)", rules.len());
+
+    let result = state.settings.update(|current| current.export_on_save = rules).await;
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+/// Saved export presets, by name
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_export_presets(state: State<'_, AppState>) -> Result<CommandResult<Vec<ExportPreset>>, String> {
+    debug!("Listing export presets");
+    Ok(CommandResult::ok(state.export_presets.list()))
+}
+
+/// Save export options under a name, replacing a preset of the same name
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn save_export_preset(
+    preset: ExportPreset,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<ExportPreset>, String> {
+    debug!("Saving export preset: {}", preset.name);
+
+    let result = state.export_presets.save(preset).await;
+    state.command_registry.set_export_presets(&state.export_presets.list());
+    Ok(handle_command_error(result))
+}
+
+/// Delete an export preset; false when there was none by that name
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn delete_export_preset(
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<bool>, String> {
+    debug!("Deleting export preset: {}", name);
+
+    let result = state.export_presets.delete(&name).await;
+    state.command_registry.set_export_presets(&state.export_presets.list());
+    Ok(handle_command_error(result))
+}
+
+/// Stop a running export started with `job_id`; false when no such export is running
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn cancel_export(
+    job_id: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<bool>, String> {
+    debug!("Cancelling export job: {}", job_id);
+    Ok(CommandResult::ok(state.export_jobs.cancel(&job_id)))
+}
+
+/// Built-in export themes and the user's own from the themes folder
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_export_themes(state: State<'_, AppState>) -> Result<CommandResult<Vec<ExportTheme>>, String> {
+    debug!("Listing export themes");
+    Ok(CommandResult::ok(state.export_service.list_themes()))
+}
+
+/// Color schemes exports can highlight code blocks with
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_code_themes(state: State<'_, AppState>) -> Result<CommandResult<Vec<String>>, String> {
+    debug!("Listing code themes");
+    Ok(CommandResult::ok(state.export_service.code_themes()))
+}
+
+/// The stylesheet of an export theme, for previewing it
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_theme_css(
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<String>, String> {
+    debug!("Loading export theme: {}", name);
+    Ok(handle_command_error(state.export_service.theme_css(&name)))
+}
+
+/// Set the daily and per-document word goals reported in `goal-progress` events
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_goal_settings(
+    settings: GoalSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating goal settings ({:?})", settings);
+    let result = state.settings.update(|current| current.goals = settings).await;
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+/// Set the certificate and key that sign PDFs exported with `sign`
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_pdf_signing_settings(
+    settings: PdfSigningSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating PDF signing settings (certificate: {:?})", settings.certificate_path);
+
+    let result = async {
+        state.export_service.set_pdf_signer(pdf_signer(&settings)?);
+        state.settings.update(|current| current.pdf_signing = settings).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// The signer the settings describe, or `None` until both files are set
+pub fn pdf_signer(settings: &PdfSigningSettings) -> Result<Option<PdfSigner>> {
+    let (Some(certificate_path), Some(key_path)) = (&settings.certificate_path, &settings.key_path) else {
+        return Ok(None);
+    };
+    let certificate = std::fs::read_to_string(certificate_path)
+        .with_context(|| format!("Failed to read signing certificate: {:?}", certificate_path))?;
+    let key = std::fs::read_to_string(key_path)
+        .with_context(|| format!("Failed to read signing key: {:?}", key_path))?;
+
+    let signer = PdfSigner::from_pem(&certificate, &key)?.with_details(SignatureDetails {
+        name: settings.name.clone(),
+        reason: settings.reason.clone(),
+        location: settings.location.clone(),
+        contact_info: settings.contact_info.clone(),
+    });
+    Ok(Some(signer))
+}
+
+/// Turn the CSP and event handler stripping for previews and exports on or off
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_security_settings(
+    settings: SecuritySettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating security settings (CSP: {})", settings.content_security_policy);
+
+    let result = async {
+        state.export_service.set_content_security(settings.content_security_policy);
+        state.settings.update(|current| current.security = settings).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Change the size, nesting and time limits documents are rendered under
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_parser_settings(
+    settings: ParserSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating parser limits: {:?}", settings);
+
+    let result = async {
+        state.parser.set_limits(parser_limits(&settings));
+        state.settings.update(|current| current.parser = settings).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Change how footnotes are numbered, titled and placed in previews and exports
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_footnote_settings(
+    settings: FootnoteSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating footnote settings: {:?}", settings);
+
+    let result = async {
+        state.parser.set_footnotes(settings.clone());
+        state.settings.update(|current| current.footnotes = settings).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+pub fn parser_limits(settings: &ParserSettings) -> ParserLimits {
+    ParserLimits {
+        max_input_bytes: settings.max_input_bytes,
+        max_nesting_depth: settings.max_nesting_depth,
+        timeout: std::time::Duration::from_millis(settings.timeout_ms),
+    }
+}
+
+/// Change which folders file commands may touch beyond the workspace and opened files
+///
+/// Anything that widens access has to be confirmed in a native dialog, which a
+/// script in the webview can't answer.
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_file_access_settings(
+    mut settings: FileAccessSettings,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating file access settings (restrict: {}, {} roots)", settings.restrict, settings.allowed_roots.len());
+
+    let result = async {
+        let current = state.settings.get().file_access;
+        // Only the open dialog adds to these
+        settings.opened_files = current.opened_files.clone();
+        let changes = current.loosened_by(&settings);
+        confirm_natively(&window, "File Access", "Allow Typolite to access more of your files?", &changes)?;
+        state.file_service.set_access_policy(settings.restrict, &settings.allowed_roots);
+        state.settings.update(|current| current.file_access = settings).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Apply the saved file access policy, with the active workspace as an allowed folder
+pub fn apply_file_access_settings(state: &AppState) {
+    let settings = state.settings.get().file_access;
+    state.file_service.set_access_policy(settings.restrict, &settings.allowed_roots);
+    for path in settings.opened_files.iter().filter(|path| path.exists()) {
+        state.file_service.allow_file(path);
+    }
+    state.file_service.set_workspace_root(state.workspaces.active().as_ref().map(|workspace| workspace.root.as_path()));
+}
+
+/// Resize the worker pool used for site builds, indexing and workspace search
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_worker_settings(
+    settings: WorkerSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating worker settings (parallelism: {:?})", settings.parallelism);
+
+    let result = async {
+        state.workers.set_parallelism(settings.parallelism)?;
+        state.settings.update(|current| current.workers = settings).await?;
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Call counts and p50/p95 latencies of every command invoked since startup
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_command_metrics(state: State<'_, AppState>) -> Result<CommandResult<Vec<CommandMetric>>, String> {
+    debug!("Getting command metrics");
+    Ok(CommandResult::ok(state.command_metrics.snapshot()))
+}
+
+/// Change the log filter until the app exits, e.g. `debug` or `info,typolite::sync=trace`
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_log_level(
+    level: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Setting log level to {}", level);
+    Ok(handle_command_error(state.logging.set_level(&level)))
+}
+
+/// Point exports at the configured scratch folder and clear out what earlier runs left there
+pub async fn prepare_export_temp_dir(state: &AppState) -> Result<usize> {
+    let settings = state.settings.get().export;
+    state.export_service.set_temp_dir(settings.temp_dir);
+    let max_age = std::time::Duration::from_secs(settings.temp_max_age_hours * 60 * 60);
+    state.export_service.cleanup_stale_temp_files(max_age).await
+}
+
+/// The token scripts must send as `Authorization: Bearer <token>`, created on first use
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_api_token(state: State<'_, AppState>) -> Result<CommandResult<String>, String> {
+    debug!("Getting API token");
+    Ok(handle_command_error(api_token(&state)))
+}
+
+/// Replace the API token, locking out every client using the old one
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn regenerate_api_token(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<String>, String> {
+    debug!("Regenerating API token");
+
+    let result = async {
+        let token = generate_token();
+        state.secrets.set(API_TOKEN_KEY, &token)?;
+        if state.api_server.info().await.is_some() {
+            apply_api_settings(&app).await?;
+        }
+        Ok(token)
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn search_zotero(
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<ZoteroReference>>, String> {
+    debug!("Searching Zotero for {:?}", query);
+    let settings = state.settings.get().zotero;
+    Ok(handle_command_error(state.zotero.search(&query, &settings).await))
+}
+
+/// Let the user choose references in Zotero's picker; resolves to `None` if they cancel
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn pick_zotero_citation(state: State<'_, AppState>) -> Result<CommandResult<Option<String>>, String> {
+    debug!("Picking a Zotero citation");
+    let settings = state.settings.get().zotero;
+    Ok(handle_command_error(state.zotero.pick_citation(&settings).await))
+}
+
+/// The `.csl` styles in the config folder's `csl` directory, which documents can
+/// pick with `csl: <name>` in their front matter
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_citation_styles() -> Result<CommandResult<Vec<CitationStyle>>, String> {
+    let dir = citation_styles_dir();
+    debug!("Listing citation styles in {:?}", dir);
+    Ok(handle_command_error(citation_styles_in(&dir).await))
+}
+
+/// Bibliography HTML for the citations in a document, in its citation style,
+/// for showing references in the preview
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_bibliography(
+    content: String,
+    path: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Option<String>>, String> {
+    debug!("Formatting bibliography for {:?}", path);
+
+    let result = async {
+        let mut settings = state.settings.get().zotero;
+        let style = document_citation_style(&content, path.as_deref(), &citation_styles_dir(), settings.style.as_deref()).await?;
+        let html = render_markdown(&state, content).await?.html;
+        let citekeys = cited_keys(&html);
+        if citekeys.is_empty() {
+            return Ok(None);
+        }
+        settings.style = style;
+        state.zotero.bibliography(&citekeys, &settings).await.map(Some)
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_zotero_settings(
+    settings: ZoteroSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating Zotero settings");
+
+    let result = state.settings.update(|current| current.zotero = settings).await;
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+/// Open today's journal note, creating it from the template on first use
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn open_daily_note(state: State<'_, AppState>) -> Result<CommandResult<DailyNote>, String> {
+    debug!("Opening today's note");
+    let today = chrono::Local::now().date_naive();
+    Ok(handle_command_error(open_journal_note(&state, today).await))
+}
+
+/// Open the journal note for a `YYYY-MM-DD` date, creating it if needed
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn open_date_note(
+    date: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<DailyNote>, String> {
+    debug!("Opening note for {}", date);
+
+    let result = async {
+        let date = chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .with_context(|| format!("Invalid date: {}", date))?;
+        open_journal_note(&state, date).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_journal_settings(
+    settings: JournalSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating journal settings ({:?})", settings.folder);
+
+    let result = async {
+        validate_journal_settings(&settings)?;
+        state.settings.update(|current| current.journal = settings.clone()).await?;
+        state.command_registry.set_templates(&settings);
+        anyhow::Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+/// Append a timestamped entry to the inbox, creating it if needed; called by the
+/// window the `quick-capture` global shortcut opens, so nothing has to be opened first
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn append_quick_note(
+    text: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<QuickNote>, String> {
+    debug!("Capturing quick note ({} chars)", text.len());
+
+    let result = async {
+        let settings = state.settings.get().scratchpad;
+        let inbox = if settings.inbox.is_absolute() {
+            settings.inbox.clone()
+        } else {
+            let base = match state.workspaces.active() {
+                Some(workspace) => workspace.root,
+                None => directories::UserDirs::new()
+                    .and_then(|dirs| dirs.document_dir().map(Path::to_path_buf))
+                    .context("Open a workspace or set an absolute inbox path")?,
+            };
+            base.join(&settings.inbox)
+        };
+        state.file_service.check_access(&inbox)?;
+        state.scratchpad.append(&inbox, &text, chrono::Local::now().naive_local(), &settings).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_scratchpad_settings(
+    settings: ScratchpadSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating scratchpad settings ({:?})", settings.inbox);
+
+    let result = async {
+        validate_scratchpad_settings(&settings)?;
+        state.settings.update(|current| current.scratchpad = settings).await
+    }.await;
+
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+/// Write an RSS or Atom feed for the posts in a folder
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn generate_feed(
+    posts_dir: Option<PathBuf>,
+    output_path: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<FeedResult>, String> {
+    debug!("Generating feed for {:?}", posts_dir);
+
+    let result = async {
+        let settings = state.settings.get().feed;
+        let posts_dir = posts_dir
+            .or_else(|| settings.posts_dir.clone())
+            .or_else(|| state.workspaces.active().map(|workspace| workspace.root))
+            .ok_or_else(|| anyhow::anyhow!("Choose a posts folder or open a workspace"))?;
+        state.file_service.check_access(&posts_dir)?;
+        if let Some(output_path) = &output_path {
+            state.file_service.check_access(output_path)?;
+        }
+        state.feed_generator.generate(&posts_dir, output_path.as_deref(), &settings).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_feed_settings(
+    settings: FeedSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating feed settings ({:?})", settings.format);
+
+    let result = state.settings.update(|current| current.feed = settings).await;
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+/// Publish the active workspace as a browsable HTML site in `output_dir`
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn generate_site(
+    output_dir: PathBuf,
+    options: Option<SiteBuildOptions>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<SiteBuildResult>, String> {
+    debug!("Generating site into {:?}", output_dir);
+
+    let result = async {
+        let workspace = state.workspaces.active()
+            .ok_or_else(|| anyhow::anyhow!("Open a workspace to generate a site"))?;
+        state.file_service.check_access(&output_dir)?;
+        state.site_builder.build(&workspace.root, &output_dir, &options.unwrap_or_default(), &state.workers).await
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_app_config_dir() -> CommandResult<PathBuf> {
+    debug!("Getting app config directory");
+
+    match directories::ProjectDirs::from("com", "typolite", "Typora-Lite") {
+        Some(proj_dirs) => {
+            let config_dir = proj_dirs.config_dir().to_path_buf();
+            info!("Config directory: {:?}", config_dir);
+            CommandResult::ok(config_dir)
+        }
+        None => {
+            let fallback = PathBuf::from("./config");
+            warn!("Could not determine config directory, using fallback: {:?}", fallback);
+            CommandResult::ok(fallback)
+        }
+    }
+}
+
+/// Watch a file, emitting `file-changed` (or `files-changed` in batch mode) when it changes
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn watch_file(
+    path: PathBuf,
+    options: Option<WatchOptions>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<WatchHandle>, String> {
+    info!("Starting file watch: {:?}", path);
+
+    let options = options.unwrap_or_default();
+    let forwarder = file_change_forwarder(&window, options.coalesce, true);
+    match state.file_service.watch_file(path.clone(), &options, forwarder).await {
+        Ok(handle) => {
+            // Track the watcher
+            state.watchers.write().await.insert(path, true);
+            Ok(CommandResult::ok(handle))
+        }
+        Err(e) => {
+            error!("Failed to start watching file {:?}: {}", path, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+/// Watch a whole folder, e.g. a workspace, emitting change events for anything under it
+///
+/// Slow network drives or tools that rewrite many files at once are best
+/// watched with a longer `debounce_ms` and the `batch` coalescing mode.
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn watch_directory(
+    path: PathBuf,
+    options: Option<WatchOptions>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<WatchHandle>, String> {
+    info!("Starting directory watch: {:?}", path);
+
+    let options = options.unwrap_or_default();
+    let forwarder = file_change_forwarder(&window, options.coalesce, false);
+    Ok(handle_command_error(state.file_service.watch_directory(path, &options, forwarder).await))
+}
+
+/// End a single `watch_file` or `watch_directory` subscription
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn remove_watch(
+    handle: WatchHandle,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    info!("Removing watch {} on {:?}", handle.id, handle.path);
+    Ok(handle_command_error(state.file_service.unwatch(&handle)))
+}
+
+/// Emit file changes to the window, optionally re-rendering the changed file onto the preview server
+///
+/// Single changes go out as `file-changed`; batches as one `files-changed` event.
+fn file_change_forwarder(
+    window: &Window,
+    coalesce: CoalesceMode,
+    refresh_preview: bool,
+) -> impl Fn(Vec<FileChangeEvent>) + Send + Sync + 'static {
+    let window = window.clone();
+    let app = window.app_handle();
+
+    move |events: Vec<FileChangeEvent>| {
+        debug!("File changes detected: {:?}", events);
+
+        let emitted = match coalesce {
+            CoalesceMode::Latest => events.iter().try_for_each(|event| window.emit("file-changed", event)),
+            CoalesceMode::Batch => window.emit("files-changed", &events),
+        };
+        if let Err(e) = emitted {
+            error!("Failed to emit file change event: {}", e);
+        }
+
+        // Browsers on the preview server follow edits made outside the app as well
+        if let Some(event) = events.into_iter().next_back().filter(|_| refresh_preview) {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                refresh_preview_from_disk(&app.state::<AppState>(), &event.path).await;
+            });
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn unwatch_file(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    info!("Stopping file watch: {:?}", path);
+
+    match state.file_service.unwatch_file(&path) {
+        Ok(()) => {
+            // Remove from tracking
+            state.watchers.write().await.remove(&path);
+            Ok(CommandResult::ok(()))
+        }
+        Err(e) => {
+            error!("Failed to stop watching file {:?}: {}", path, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_file_metadata(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<FileMetadata>, String> {
+    debug!("Getting file metadata: {:?}", path);
+
+    match state.file_service.get_metadata(&path).await {
+        Ok(metadata) => {
+            Ok(CommandResult::ok(metadata))
+        }
+        Err(e) => {
+            error!("Failed to get file metadata {:?}: {}", path, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn reveal_in_explorer(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Revealing file in explorer: {:?}", path);
+
+    match state.file_service.reveal_in_file_manager(&path) {
+        Ok(()) => Ok(CommandResult::ok(())),
+        Err(e) => {
+            error!("Failed to reveal file {:?}: {}", path, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn open_with_default_app(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Opening file with default app: {:?}", path);
+
+    match state.file_service.open_with_default_app(&path) {
+        Ok(()) => Ok(CommandResult::ok(())),
+        Err(e) => {
+            error!("Failed to open file {:?}: {}", path, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_recent_files(
+    dir: Option<PathBuf>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<FileMetadata>>, String> {
+    let workspace = state.workspaces.active();
+    let search_dir = dir.clone()
+        .or_else(|| workspace.as_ref().map(|w| w.root.clone()))
+        .unwrap_or_else(|| {
+            directories::UserDirs::new()
+                .and_then(|dirs| Some(dirs.document_dir()?.to_path_buf()))
+                .unwrap_or_else(|| PathBuf::from("."))
+        });
+
+    debug!("Listing recent files in: {:?}", search_dir);
+
+    match state.file_service.list_markdown_files(&search_dir, &state.workers).await {
+        Ok(mut files) => {
+            if let Some(workspace) = workspace.filter(|_| dir.is_none()) {
+                if let Err(e) = state.workspaces.save_index(&workspace.id, &files).await {
+                    warn!("Failed to update workspace index: {}", e);
+                }
+                // Files opened in this workspace come first, most recent first
+                files.sort_by_key(|f| {
+                    workspace.recent_files.iter().position(|p| p == &f.path).unwrap_or(usize::MAX)
+                });
+            }
+            info!("Found {} markdown files", files.len());
+            Ok(CommandResult::ok(files))
+        }
+        Err(e) => {
+            error!("Failed to list files in {:?}: {}", search_dir, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn open_workspace(
+    root: PathBuf,
+    name: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Workspace>, String> {
+    debug!("Opening workspace: {:?}", root);
+
+    match state.workspaces.open(&root, name).await {
+        Ok(workspace) => {
+            state.file_service.set_workspace_root(Some(&workspace.root));
+            refresh_workspace_index(&workspace, &state).await;
+            Ok(CommandResult::ok(workspace))
+        }
+        Err(e) => {
+            error!("Failed to open workspace {:?}: {}", root, e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_workspaces(state: State<'_, AppState>) -> Result<CommandResult<Vec<Workspace>>, String> {
+    debug!("Listing workspaces");
+    Ok(CommandResult::ok(state.workspaces.list()))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn switch_workspace(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Workspace>, String> {
+    debug!("Switching to workspace: {}", id);
+
+    match state.workspaces.switch(&id).await {
+        Ok(workspace) => {
+            state.file_service.set_workspace_root(Some(&workspace.root));
+            refresh_workspace_index(&workspace, &state).await;
+            Ok(CommandResult::ok(workspace))
+        }
+        Err(e) => {
+            error!("Failed to switch workspace: {}", e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_workspace_settings(
+    id: String,
+    settings: WorkspaceSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Workspace>, String> {
+    debug!("Updating settings for workspace: {}", id);
+    Ok(handle_command_error(state.workspaces.update_settings(&id, settings).await))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn sync_now(app: AppHandle, state: State<'_, AppState>) -> Result<CommandResult<SyncReport>, String> {
+    debug!("Syncing active workspace");
+
+    let result = sync_active_workspace(&state).await;
+    if let Ok(report) = &result {
+        if let Err(e) = app.emit_all("sync-completed", report) {
+            error!("Failed to emit sync-completed event: {}", e);
+        }
+    }
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_sync_settings(
+    settings: SyncSettings,
+    password: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Updating sync settings ({:?})", settings.provider);
+
+    let result = async {
+        let current = state.settings.get().sync;
+        // An omitted password keeps the one already in the keychain, unless it
+        // would now be sent to another server
+        match password {
+            Some(password) => state.secrets.set(WEBDAV_PASSWORD_KEY, &password)?,
+            None if settings.webdav_url != current.webdav_url || settings.provider != current.provider => {
+                info!("Sync server changed, forgetting the WebDAV password");
+                state.secrets.set(WEBDAV_PASSWORD_KEY, "")?;
+            }
+            None => {}
+        }
+        state.settings.update(|current| current.sync = settings).await
+    }.await;
+
+    Ok(handle_command_error(result.map(|_| ())))
+}
+
+/// Sign in to Dropbox or Google Drive in the browser and keep the tokens in the keychain
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn connect_sync_provider(
+    provider: SyncProviderKind,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Connecting sync provider {:?}", provider);
+
+    let Some(endpoints) = provider.oauth() else {
+        return Ok(CommandResult::err(format!("{:?} does not use browser sign-in", provider)));
+    };
+    let settings = state.settings.get().sync;
+    let result = cloud_sync::authorize(endpoints, &settings, &state.secrets, |url| {
+        tauri::api::shell::open(&app.shell_scope(), url, None).context("Failed to open the browser")
+    }).await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn disconnect_sync_provider(
+    provider: SyncProviderKind,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Disconnecting sync provider {:?}", provider);
+
+    let key = provider.oauth().map_or(WEBDAV_PASSWORD_KEY, |endpoints| endpoints.keychain_key);
+    Ok(handle_command_error(state.secrets.set(key, "")))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_shortcuts(state: State<'_, AppState>) -> Result<CommandResult<Vec<Shortcut>>, String> {
+    debug!("Getting keyboard shortcuts");
+
+    let overrides = state.settings.get().shortcuts;
+    Ok(CommandResult::ok(shortcuts::effective_shortcuts(&overrides)))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn set_shortcuts(
+    shortcuts: BTreeMap<String, String>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<Vec<Shortcut>>, String> {
+    debug!("Updating {} keyboard shortcuts", shortcuts.len());
+
+    let overrides = match shortcuts::validate_shortcuts(&shortcuts) {
+        Ok(overrides) => overrides,
+        Err(e) => {
+            warn!("Rejected shortcut update: {}", e);
+            return Ok(CommandResult::err(e.to_string()));
+        }
+    };
+
+    match state.settings.update(|settings| settings.shortcuts = overrides).await {
+        Ok(settings) => {
+            let effective = shortcuts::effective_shortcuts(&settings.shortcuts);
+            if let Err(e) = register_global_shortcuts(&app, &effective) {
+                error!("Failed to register global shortcuts: {}", e);
+                return Ok(CommandResult::err(e.to_string()));
+            }
+            info!("Keyboard shortcuts updated");
+            Ok(CommandResult::ok(effective))
+        }
+        Err(e) => {
+            error!("Failed to save shortcuts: {}", e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_commands(state: State<'_, AppState>) -> Result<CommandResult<Vec<PaletteCommand>>, String> {
+    debug!("Listing palette commands");
+
+    let overrides = state.settings.get().shortcuts;
+    Ok(CommandResult::ok(state.command_registry.list(&overrides)))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn run_command(
+    id: String,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Running palette command: {}", id);
+
+    if !state.command_registry.contains(&id) {
+        warn!("Unknown palette command: {}", id);
+        return Ok(CommandResult::err(format!("Unknown command: {}", id)));
+    }
+
+    if id == "quick-capture" {
+        return Ok(handle_command_error(show_quick_capture(&window.app_handle())));
+    }
+
+    // Actions are dispatched by the window that owns the document and editor state
+    match window.emit("palette-command", &id) {
+        Ok(()) => Ok(CommandResult::ok(())),
+        Err(e) => {
+            error!("Failed to emit palette-command event: {}", e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn list_plugins(state: State<'_, AppState>) -> Result<CommandResult<Vec<PluginInfo>>, String> {
+    debug!("Listing plugins");
+    Ok(CommandResult::ok(state.plugins.list_plugins()))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn reload_plugins(state: State<'_, AppState>) -> Result<CommandResult<Vec<PluginInfo>>, String> {
+    debug!("Reloading plugins");
+
+    match state.plugins.load_plugins() {
+        Ok(plugins) => {
+            info!("Reloaded {} plugins", plugins.len());
+            Ok(CommandResult::ok(plugins))
+        }
+        Err(e) => {
+            error!("Failed to reload plugins: {}", e);
+            Ok(CommandResult::err(e.to_string()))
+        }
+    }
+}
+
+/// Export a document with an exporter plugin, writing whatever it makes of the
+/// document's HTML to `output_path`
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn export_with_plugin(
+    plugin: String,
+    path: Option<PathBuf>,
+    content: Option<String>,
+    output_path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<CommandResult<()>, String> {
+    debug!("Exporting {:?} to {:?} with plugin {}", path, output_path, plugin);
+
+    let result = async {
+        let markdown = resolve_content(path, content, &state).await?;
+        let style = citation_style(&state, &markdown, None).await;
+        let parsed = render_markdown(&state, markdown).await?;
+        let html = with_bibliography(&state, parsed.html, style).await;
+        let html = state.plugins.run_hook(PluginHook::PreExport, html);
+        let html = state.html_filters
+            .apply(&state.settings.get().html_filters, FilterStage::Export, html)
+            .await;
+        let bytes = state.plugins.export_document(&plugin, &html)?;
+
+        state.file_service.check_access(&output_path)?;
+        tokio::fs::write(&output_path, &bytes).await
+            .with_context(|| format!("Failed to write {:?}", output_path))?;
+        info!("Plugin {} exported {} bytes to {:?}", plugin, bytes.len(), output_path);
+        Ok(())
+    }.await;
+
+    Ok(handle_command_error(result))
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_app_version() -> CommandResult<String> {
+    let version = env!("CARGO_PKG_VERSION").to_string();
+    debug!("App version: {}", version);
+    CommandResult::ok(version)
+}
+
+#[command]
+#[instrument(target = "command", level = "trace", skip_all)]
+pub async fn get_system_info(window: Window, state: State<'_, AppState>) -> Result<CommandResult<SystemInfo>, String> {
+    debug!("Getting system info");
+
+    let disk = |path: &Path| match disk_space(path) {
+        Ok(space) => Some(space),
+        Err(e) => {
+            warn!("{:#}", e);
+            None
+        }
+    };
+    let info = SystemInfo {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        locale: system_locale(),
+        dark_mode: window.theme().ok().map(|theme| matches!(theme, tauri::Theme::Dark)),
+        webview_version: tauri::webview_version().ok(),
+        config_disk: disk(&app_config_dir()),
+        workspace_disk: state.workspaces.active().and_then(|workspace| disk(&workspace.root)),
+        parser_extensions: state.parser.extensions().into_iter().map(str::to_string).collect(),
+    };
+
+    Ok(CommandResult::ok(info))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SystemInfo {
+    pub os: String,
+    pub arch: String,
+    pub version: String,
+    /// BCP 47 tag like `de-DE`, when the environment sets one
+    pub locale: Option<String>,
+    /// Whether the window follows a dark system theme; unknown on some platforms
+    pub dark_mode: Option<bool>,
+    pub webview_version: Option<String>,
+    pub config_disk: Option<DiskSpace>,
+    /// Disk of the open workspace, if one is open
+    pub workspace_disk: Option<DiskSpace>,
+    pub parser_extensions: Vec<String>,
+}
+
+// Utility functions for commands
+
+pub fn handle_command_error<T>(result: Result<T>) -> CommandResult<T> {
+    match result {
+        Ok(data) => CommandResult::ok(data),
+        Err(e) => {
+            error!("Command error: {}", e);
+            CommandResult::err(e.to_string())
+        }
+    }
+}
+
+/// Use the given content, or read it from `path` when the frontend only sends a file
+pub async fn resolve_content(path: Option<PathBuf>, content: Option<String>, state: &AppState) -> Result<String> {
+    match (content, path) {
+        (Some(content), _) => Ok(content),
+        (None, Some(path)) => state.file_service.read_file(&path).await,
+        (None, None) => Err(anyhow::anyhow!("Either a path or content is required")),
+    }
+}
+
+async fn sync_active_workspace(state: &AppState) -> Result<SyncReport> {
+    let workspace = state.workspaces.active()
+        .ok_or_else(|| anyhow::anyhow!("Open a workspace to sync"))?;
+    let provider = provider_for(&state.settings.get().sync, &state.secrets)?;
+
+    state.sync_service
+        .sync(&workspace.root, &state.workspaces.sync_state_path(&workspace.id), provider.as_ref())
+        .await
+}
+
+/// How long windows get to save their buffers after `app-quitting` before quitting goes on
+const AUTOSAVE_GRACE: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Longest quitting waits for unfinished file writes and exports
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Quit without cutting off work in progress
+///
+/// Windows are told to flush their autosave buffers, watchers and servers are
+/// stopped, pending writes and exports are given time to finish, and session
+/// stats are saved before the process exits.
+pub async fn graceful_shutdown(app: AppHandle) {
+    info!("Shutting down");
+    let state = app.state::<AppState>();
+
+    if let Err(e) = app.emit_all("app-quitting", ()) {
+        error!("Failed to emit app-quitting event: {}", e);
+    }
+    tokio::time::sleep(AUTOSAVE_GRACE).await;
+
+    state.file_service.unwatch_all();
+    state.watchers.write().await.clear();
+    state.api_server.stop().await;
+    state.preview_server.stop().await;
+
+    if !state.file_service.pending_writes().wait_idle(SHUTDOWN_TIMEOUT).await {
+        warn!("Quitting with {} file writes unfinished", state.file_service.pending_writes().count());
+    }
+    if !state.export_service.active_jobs().wait_idle(SHUTDOWN_TIMEOUT).await {
+        warn!("Quitting with {} exports unfinished", state.export_service.active_jobs().count());
+    }
+
+    if let Err(e) = state.sessions.save().await {
+        warn!("Failed to save writing sessions: {}", e);
+    }
+    if let Err(e) = state.goals.save().await {
+        warn!("Failed to save goal progress: {}", e);
+    }
+
+    info!("Shutdown complete");
+    app.exit(0);
+}
+
+/// Sync the active workspace every `interval_minutes`, emitting `sync-completed` or `sync-failed`
+///
+/// The interval is re-read every minute so settings changes apply without a restart.
+pub async fn run_background_sync(app: AppHandle) {
+    let mut last_sync = std::time::Instant::now();
+
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+
+        let state = app.state::<AppState>();
+        let settings = state.settings.get().sync;
+        let interval = std::time::Duration::from_secs(u64::from(settings.interval_minutes) * 60);
+        if settings.interval_minutes == 0 || last_sync.elapsed() < interval {
+            continue;
+        }
+        last_sync = std::time::Instant::now();
+
+        match sync_active_workspace(&state).await {
+            Ok(report) => {
+                if let Err(e) = app.emit_all("sync-completed", &report) {
+                    error!("Failed to emit sync-completed event: {}", e);
+                }
+            }
+            Err(e) => {
+                warn!("Background sync failed: {}", e);
+                if let Err(e) = app.emit_all("sync-failed", e.to_string()) {
+                    error!("Failed to emit sync-failed event: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Seconds between checks for other applications holding the open document
+const HOLDER_POLL_SECS: u64 = 5;
+
+/// Watch for other applications opening or locking the current document and
+/// emit `file-holders-changed` when that changes, so users syncing through
+/// Dropbox or OneDrive hear about it before edits conflict
+pub async fn run_holder_watch(app: AppHandle) {
+    let mut last: Option<FileHoldersEvent> = None;
+
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(HOLDER_POLL_SECS)).await;
+
+        let state = app.state::<AppState>();
+        let Some(path) = state.current_file.read().await.clone() else {
+            last = None;
+            continue;
+        };
+        let lookup = path.clone();
+        let holders = match tokio::task::spawn_blocking(move || find_holders(&lookup)).await {
+            Ok(holders) => holders,
+            Err(e) => {
+                warn!("Failed to check for other applications holding {:?}: {}", path, e);
+                continue;
+            }
+        };
+
+        // A newly opened document no one else holds isn't news
+        let changed = match &last {
+            Some(last) if last.path == path => last.holders != holders,
+            _ => !holders.is_empty(),
+        };
+        let event = FileHoldersEvent { path, holders };
+        if changed {
+            debug!("{} other holders of {:?}", event.holders.len(), event.path);
+            if let Err(e) = app.emit_all("file-holders-changed", &event) {
+                error!("Failed to emit file-holders-changed event: {}", e);
+            }
+        }
+        last = Some(event);
+    }
+}
+
+/// Queue the exports the global and workspace export-on-save rules ask for
+/// once `document` has been saved
+fn queue_exports_on_save(state: &AppState, document: &Path) {
+    // Exporting would put the plain text of an encrypted document next to it
+    if is_encrypted_path(document) {
+        return;
+    }
+    let folder = document.parent().unwrap_or(Path::new("."));
+    let mut jobs = export_jobs(document, &state.settings.get().export_on_save, folder);
+    for workspace in state.workspaces.list().iter().filter(|workspace| workspace.contains(document)) {
+        jobs.extend(export_jobs(document, &workspace.settings.export_on_save, &workspace.root));
+    }
+
+    for job in jobs {
+        let output_path = job.output_path.clone();
+        if !state.export_queue.push(job) {
+            debug!("Export to {:?} is already queued", output_path);
+        }
+    }
+}
+
+/// Run queued export-on-save jobs one at a time, emitting
+/// `export-on-save-completed` or `export-on-save-failed` for each
+pub async fn run_export_queue(app: AppHandle) {
+    let Some(mut jobs) = app.state::<AppState>().export_queue.take_jobs() else {
+        error!("The export queue is already running");
+        return;
+    };
+
+    while let Some(job) = jobs.next().await {
+        let state = app.state::<AppState>();
+        match run_export_job(&state, &job).await {
+            Ok(result) => {
+                info!("Exported {:?} on save to {:?}", job.document, result.output_path);
+                if let Err(e) = app.emit_all("export-on-save-completed", &result) {
+                    error!("Failed to emit export-on-save-completed event: {}", e);
+                }
+            }
+            Err(e) => {
+                warn!("Export on save of {:?} to {:?} failed: {:#}", job.document, job.output_path, e);
+                let failure = ExportJobFailure {
+                    document: job.document,
+                    output_path: job.output_path,
+                    error: format!("{:#}", e),
+                };
+                if let Err(e) = app.emit_all("export-on-save-failed", &failure) {
+                    error!("Failed to emit export-on-save-failed event: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Export the saved document as it is on disk, with the options last used for it
+async fn run_export_job(state: &AppState, job: &ExportJob) -> Result<ExportResult> {
+    let markdown = state.file_service.read_file(&job.document).await?;
+    let saved_options = state.export_service.load_document_options(&job.document).await
+        .unwrap_or_else(|e| {
+            warn!("Ignoring saved export options for {:?}: {}", job.document, e);
+            None
+        });
+    let mut options = saved_options.unwrap_or_default();
+    options.format = job.format.clone();
+    options.document_css = document_css(&markdown, Some(&job.document)).await;
+    options.base_dir = job.document.parent().map(Path::to_path_buf);
+    options.file_name = job.document.file_name().map(|name| name.to_string_lossy().to_string());
+
+    if let Some(dir) = job.output_path.parent() {
+        tokio::fs::create_dir_all(dir).await
+            .with_context(|| format!("Failed to create export folder: {:?}", dir))?;
+    }
+    export_markdown(state, markdown, &job.output_path, options, &|_| {}, &CancellationToken::new()).await
+}
+
+/// Parse markdown into preview HTML, running plugin hooks and preview filters
+async fn render_markdown(state: &AppState, content: String) -> Result<ParsedDocument> {
+    let input_bytes = content.len();
+    let mut timer = PhaseTimer::start();
+
+    let content = state.plugins.run_hook(PluginHook::PreParse, content);
+    timer.lap("plugins");
+    let current_file = state.current_file.read().await.clone();
+    let base_dir = current_file.as_deref().and_then(Path::parent);
+    let mut parsed = state.parser.parse_preview(&content, base_dir)?;
+    timer.extend(&parsed.timings);
+    parsed.css = document_css(&content, current_file.as_deref()).await;
+    parsed.html = state.plugins.run_hook(PluginHook::PostHtml, parsed.html);
+    timer.lap("plugins");
+    let settings = state.settings.get();
+    parsed.html = state.html_filters
+        .apply(&settings.html_filters, FilterStage::Preview, parsed.html)
+        .await;
+    timer.lap("filters");
+    if settings.security.content_security_policy {
+        parsed.html = strip_event_handlers(&parsed.html);
+        for block in &mut parsed.blocks {
+            block.html = strip_event_handlers(&block.html);
+        }
+        timer.lap("sanitize");
+    }
+
+    state.profiler.record(OperationKind::Parse, &current_file_label(state).await, input_bytes, timer.finish());
+    Ok(parsed)
+}
+
+/// Stylesheet named in the document's front matter, skipped with a warning when it can't be used
+async fn document_css(markdown: &str, document: Option<&Path>) -> Option<String> {
+    load_document_css(markdown, document, &themes_dir()).await
+        .unwrap_or_else(|e| {
+            warn!("Ignoring document stylesheet: {}", e);
+            None
+        })
+}
+
+async fn current_file_label(state: &AppState) -> String {
+    state.current_file.read().await.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "untitled".to_string())
+}
+
+/// Swap the preview HTML for a patch against what `view` already shows, when that helps
+fn diff_preview(state: &AppState, parsed: &mut ParsedDocument, incremental: bool, view: &str) {
+    // Plugins and filters rewrite the whole page, so their output can't be patched
+    if incremental && !rewrites_preview_html(state) {
+        match state.preview_differ.update(view, &parsed.blocks) {
+            PreviewUpdate::Full(html) => parsed.html = html,
+            PreviewUpdate::Patch(patch) => {
+                parsed.html.clear();
+                parsed.patch = Some(patch);
+            }
+        }
+    } else {
+        state.preview_differ.reset(view);
+    }
+}
+
+/// Render a scheduled parse and emit the result, unless a newer edit superseded it
+async fn run_scheduled_parse(window: Window, ticket: ParseTicket, content: String, incremental: bool) {
+    let state = window.state::<AppState>();
+
+    let goals = goal_targets(&state, &content);
+    let result = match state.parse_scheduler.run(&ticket, render_markdown(&state, content)).await {
+        Some(result) => result,
+        None => {
+            debug!("Dropped superseded parse {} of {}", ticket.id, ticket.document);
+            return;
+        }
+    };
+
+    match result {
+        Ok(mut parsed) => {
+            if state.preview_server.info().await.is_some() {
+                update_preview_page(&state, &parsed.html).await;
+            }
+            diff_preview(&state, &mut parsed, incremental, window.label());
+            emit_goal_progress(&window, &state, goals, parsed.word_count).await;
+
+            let event = ParseComplete { request_id: ticket.id, document: ticket.document, parsed };
+            if let Err(e) = window.emit("parse-complete", &event) {
+                error!("Failed to emit parse-complete event: {}", e);
+            }
+        }
+        Err(e) => {
+            error!("Failed to parse markdown: {}", e);
+            let event = ParseFailed { request_id: ticket.id, document: ticket.document, error: e.to_string() };
+            if let Err(e) = window.emit("parse-failed", &event) {
+                error!("Failed to emit parse-failed event: {}", e);
+            }
+        }
+    }
+}
+
+/// Word goals that apply to `markdown`: the document's own, then the daily one
+fn goal_targets(state: &AppState, markdown: &str) -> (Option<usize>, Option<usize>) {
+    let settings = state.settings.get().goals;
+    (document_word_goal(markdown, &settings), settings.daily_words)
+}
+
+/// Count a finished parse towards the writing goals, emitting `goal-progress`
+/// so the frontend can show progress and celebrate without polling
+async fn emit_goal_progress(window: &Window, state: &AppState, targets: (Option<usize>, Option<usize>), word_count: usize) {
+    if targets == (None, None) {
+        return;
+    }
+    let document = match state.current_file.read().await.as_ref() {
+        Some(path) => path.display().to_string(),
+        None => window.label().to_string(),
+    };
+    let goals = state.goals.record(&document, word_count, targets.0, targets.1);
+    if let Err(e) = window.emit("goal-progress", &GoalProgressEvent { document, goals }) {
+        error!("Failed to emit goal-progress event: {}", e);
+    }
+}
+
+/// Whether plugins or filters post-process preview HTML
+fn rewrites_preview_html(state: &AppState) -> bool {
+    state.plugins.has_hook(PluginHook::PostHtml)
+        || state.settings.get().html_filters.iter().any(|filter| filter.enabled && filter.stage.includes(FilterStage::Preview))
+}
+
+/// Re-render a changed file onto the preview server, if it's running
+async fn refresh_preview_from_disk(state: &AppState, path: &Path) {
+    if state.preview_server.info().await.is_none() {
+        return;
+    }
+
+    let content = match state.file_service.read_file(path).await {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Failed to read {:?} for live preview: {}", path, e);
+            return;
+        }
+    };
+    match render_markdown(state, content).await {
+        Ok(parsed) => update_preview_page(state, &parsed.html).await,
+        Err(e) => warn!("Failed to render {:?} for live preview: {}", path, e),
+    }
+}
+
+/// Append a Zotero bibliography for the citations in an export, leaving the
+/// document untouched when nothing is cited or Zotero can't be reached;
+/// `style` is the document's own citation style
+async fn with_bibliography(state: &AppState, html: String, style: Option<String>) -> String {
+    let mut settings = state.settings.get().zotero;
+    if !settings.bibliography_on_export {
+        return html;
+    }
+    let citekeys = cited_keys(&html);
+    if citekeys.is_empty() {
+        return html;
+    }
+
+    settings.style = style.or(settings.style);
+    match state.zotero.bibliography(&citekeys, &settings).await {
+        Ok(bibliography) => append_bibliography(&html, &bibliography),
+        Err(e) => {
+            warn!("Exporting without a bibliography: {}", e);
+            html
+        }
+    }
+}
+
+/// Citation style for a document's bibliography, from its `csl` front matter or
+/// the Zotero settings; `None` (with a warning) when it can't be resolved
+async fn citation_style(state: &AppState, markdown: &str, document: Option<&Path>) -> Option<String> {
+    let default = state.settings.get().zotero.style;
+    document_citation_style(markdown, document, &citation_styles_dir(), default.as_deref()).await
+        .unwrap_or_else(|e| {
+            warn!("Ignoring citation style: {}", e);
+            None
+        })
+}
+
+/// Open or create a journal note and make it the current file
+async fn open_journal_note(state: &AppState, date: chrono::NaiveDate) -> Result<DailyNote> {
+    let settings = state.settings.get().journal;
+    let folder = if settings.folder.is_absolute() {
+        settings.folder.clone()
+    } else {
+        state.workspaces.active()
+            .map(|workspace| workspace.root.join(&settings.folder))
+            .ok_or_else(|| anyhow::anyhow!("Open a workspace or set an absolute journal folder"))?
+    };
+
+    let note = state.journal.open(date, &folder, &settings).await?;
+    if let Err(e) = state.workspaces.record_recent_file(&note.path).await {
+        warn!("Failed to record recent file {:?}: {}", note.path, e);
+    }
+    *state.current_file.write().await = Some(note.path.clone());
+    Ok(note)
+}
+
+/// Start or stop the automation API to match the saved settings
+pub async fn apply_api_settings(app: &AppHandle) -> Result<Option<ApiServerInfo>> {
+    let state = app.state::<AppState>();
+    let settings = state.settings.get().api;
+    if !settings.enabled {
+        state.api_server.stop().await;
+        return Ok(None);
+    }
+
+    let token = api_token(&state)?;
+    let backend = Arc::new(AppApiBackend(app.clone()));
+    state.api_server.start(settings.port, token, backend).await.map(Some)
+}
+
+/// Ask the user to confirm `changes` a command received, in a native dialog a
+/// script in the webview can't answer; errors when they decline
+fn confirm_natively(window: &Window, title: &str, question: &str, changes: &[String]) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let message = format!("{}\n\n{}", question, changes.join("\n"));
+    if !tauri::api::dialog::blocking::ask(Some(window), title, message) {
+        anyhow::bail!("{} change was not confirmed", title);
+    }
+    info!("{} change confirmed: {}", title, changes.join("; "));
+    Ok(())
+}
+
+fn api_token(state: &AppState) -> Result<String> {
+    if let Some(token) = state.secrets.get(API_TOKEN_KEY)?.filter(|token| !token.is_empty()) {
+        return Ok(token);
+    }
+    let token = generate_token();
+    state.secrets.set(API_TOKEN_KEY, &token)?;
+    Ok(token)
+}
+
+/// Answers automation API requests with the same pipeline the editor uses
+struct AppApiBackend(AppHandle);
+
+#[async_trait::async_trait]
+impl ApiBackend for AppApiBackend {
+    async fn convert(&self, request: ConvertRequest) -> Result<ParsedDocument> {
+        render_markdown(&self.0.state::<AppState>(), request.markdown).await
+    }
+
+    async fn export(&self, request: ExportRequest) -> Result<ExportResult> {
+        let state = self.0.state::<AppState>();
+        let mut options = request.options;
+        options.document_css = document_css(&request.markdown, None).await;
+        let output_path = request.output_path.unwrap_or_else(|| {
+            let name = format!("{}.{}", uuid::Uuid::new_v4(), options.format.extension());
+            state.export_service.temp_dir().join(name)
+        });
+        export_markdown(&state, request.markdown, &output_path, options, &|_| {}, &CancellationToken::new()).await
+    }
+
+    async fn search(&self, request: SearchRequest) -> Result<Vec<SearchMatch>> {
+        let state = self.0.state::<AppState>();
+        let dir = match request.dir {
+            Some(dir) => dir,
+            None => state.workspaces.active()
+                .map(|workspace| workspace.root)
+                .ok_or_else(|| anyhow::anyhow!("No workspace is open; pass a dir to search"))?,
+        };
+        state.file_service
+            .search_markdown_files(&dir, &request.q, request.limit.unwrap_or(usize::MAX), &state.workers)
+            .await
+    }
+}
+
+/// Fill the document metadata the options leave unset from the front matter
+fn front_matter_metadata(options: &mut ExportOptions, markdown: &str) {
+    let Ok((front_matter, _)) = FrontMatter::parse(markdown) else {
+        return;
+    };
+    options.title = options.title.take().or_else(|| front_matter.title());
+    if options.authors.is_empty() {
+        options.authors = [front_matter.get_list("author"), front_matter.get_list("authors")].concat();
+    }
+    options.language = options.language.take()
+        .or_else(|| front_matter.get_str("lang"))
+        .or_else(|| front_matter.get_str("language"));
+    options.subject = options.subject.take()
+        .or_else(|| front_matter.get_str("subject"))
+        .or_else(|| front_matter.get_str("description"));
+    if options.keywords.is_empty() {
+        options.keywords = match front_matter.get_list("keywords") {
+            keywords if keywords.is_empty() => front_matter.tags(),
+            keywords => keywords,
+        };
+    }
+    options.created = options.created.take().or_else(|| front_matter.get_str("date"));
+}
+
+/// Render markdown and export it through the same bibliography, plugin and filter
+/// steps as the editor's exports
+async fn export_markdown(
+    state: &AppState,
+    markdown: String,
+    output_path: &Path,
+    mut options: ExportOptions,
+    progress: &ProgressFn,
+    cancel: &CancellationToken,
+) -> Result<ExportResult> {
+    front_matter_metadata(&mut options, &markdown);
+    let style = citation_style(state, &markdown, None).await;
+    options.markdown = Some(markdown.clone());
+    let parsed = render_markdown(state, markdown).await?;
+    options.direction = options.direction.or(Some(parsed.direction));
+    let html = with_bibliography(state, parsed.html, style).await;
+    let html = state.plugins.run_hook(PluginHook::PreExport, html);
+    let html = state.html_filters
+        .apply(&state.settings.get().html_filters, FilterStage::Export, html)
+        .await;
+    state.export_service.export_job(&html, output_path, options, progress, cancel).await
+}
+
+/// Forward the progress of export job `job_id` to `window` as `export-progress` events
+fn export_progress(window: &Window, job_id: &str) -> impl Fn(ExportProgress) + Send + Sync {
+    let (window, job_id) = (window.clone(), job_id.to_string());
+    move |progress| {
+        let event = ExportProgressEvent { job_id: job_id.clone(), progress };
+        if let Err(e) = window.emit("export-progress", &event) {
+            error!("Failed to emit export-progress event: {}", e);
+        }
+    }
+}
+
+/// Serve freshly rendered HTML from the preview server, with assets relative to the open file
+async fn update_preview_page(state: &AppState, html: &str) {
+    let options = ExportOptions { include_toc: false, ..ExportOptions::default() };
+    let csp = state.settings.get().security.content_security_policy.then_some(PREVIEW_CSP);
+    match state.export_service.create_complete_html_with_csp(html, &options, csp) {
+        Ok(page) => {
+            let asset_root = state.current_file.read().await.as_ref()
+                .and_then(|file| file.parent().map(Path::to_path_buf));
+            state.preview_server.update(page, asset_root);
+        }
+        Err(e) => warn!("Failed to render preview page: {}", e),
+    }
+}
+
+/// Rebuild the cached file index of a workspace, logging rather than failing
+async fn refresh_workspace_index(workspace: &Workspace, state: &AppState) {
+    match state.file_service.list_markdown_files(&workspace.root, &state.workers).await {
+        Ok(files) => {
+            if let Err(e) = state.workspaces.save_index(&workspace.id, &files).await {
+                warn!("Failed to save index for workspace {}: {}", workspace.id, e);
+            }
+        }
+        Err(e) => warn!("Failed to index workspace {:?}: {}", workspace.root, e),
+    }
+}
+
+/// Label of the small window the `quick-capture` shortcut brings up over other apps
+pub const QUICK_CAPTURE_WINDOW: &str = "quick-capture";
+
+/// Show and focus the quick capture window, creating it on first use; it saves
+/// what's typed with `append_quick_note`, so capturing doesn't need a document window
+pub fn show_quick_capture<R: Runtime>(app: &AppHandle<R>) -> Result<()> {
+    let window = match app.get_window(QUICK_CAPTURE_WINDOW) {
+        Some(window) => window,
+        None => tauri::WindowBuilder::new(app, QUICK_CAPTURE_WINDOW, tauri::WindowUrl::App("quick-capture.html".into()))
+            .title("Quick Capture")
+            .inner_size(480.0, 180.0)
+            .resizable(false)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .center()
+            .build()
+            .context("Failed to create the quick capture window")?,
+    };
+    window.show()?;
+    window.set_focus()?;
+    Ok(())
+}
+
+/// (Re)register OS-wide accelerators for global shortcuts
+///
+/// `quick-capture` opens its window directly; other actions are emitted as
+/// `global-shortcut` for the document windows to handle.
+pub fn register_global_shortcuts<R: Runtime>(app: &AppHandle<R>, shortcuts: &[Shortcut]) -> Result<()> {
+    let mut manager = app.global_shortcut_manager();
+    manager.unregister_all()?;
+
+    for shortcut in shortcuts.iter().filter(|s| s.global) {
+        let handle = app.clone();
+        let action = shortcut.action.clone();
+        manager.register(&shortcut.accelerator, move || {
+            debug!("Global shortcut triggered: {}", action);
+            if action == "quick-capture" {
+                if let Err(e) = show_quick_capture(&handle) {
+                    error!("Failed to show quick capture: {:#}", e);
+                }
+            } else if let Err(e) = handle.emit_all("global-shortcut", &action) {
+                error!("Failed to emit global-shortcut event: {}", e);
+            }
+        })?;
+        info!("Registered global shortcut {} for {}", shortcut.accelerator, shortcut.action);
+    }
+
+    Ok(())
+}
+
+pub fn validate_markdown_file(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Err(anyhow::anyhow!("File does not exist: {:?}", path));
+    }
+
+    if !path.is_file() {
+        return Err(anyhow::anyhow!("Path is not a file: {:?}", path));
+    }
+
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+
+    if !matches!(extension.to_lowercase().as_str(), "md" | "markdown" | "mdown" | "mkd") {
+        return Err(anyhow::anyhow!("Not a markdown file: {:?}", path));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+    use std::io::Write;
+
+    #[test]
+    fn test_validate_markdown_file() {
+        // Test valid markdown file
+        let mut temp_file = NamedTempFile::with_suffix(".md").unwrap();
+        write!(temp_file, "# Test").unwrap();
+        
+        assert!(validate_markdown_file(temp_file.path()).is_ok());
+
+        // Test invalid extension
+        let mut temp_file = NamedTempFile::with_suffix(".txt").unwrap();
+        write!(temp_file, "# Test").unwrap();
+        
+        assert!(validate_markdown_file(temp_file.path()).is_err());
+    }
+
+    #[test]
+    fn test_export_access() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let workspace = dir.path().join("notes");
+        std::fs::create_dir_all(&workspace).unwrap();
+        let file_service = FileService::new();
+        file_service.set_access_policy(true, &[]);
+        file_service.set_workspace_root(Some(&workspace));
+
+        let document = workspace.join("report.md");
+        assert!(check_export_access(&file_service, &workspace.join("report.pdf"), Some(&document)).is_ok());
+        assert!(check_export_access(&file_service, &dir.path().join(".bashrc"), None).is_err());
+        assert!(check_export_access(&file_service, &workspace.join("../.bashrc"), Some(&document)).is_err());
+        assert!(check_export_access(&file_service, &workspace.join("report.pdf"), Some(&dir.path().join("other.md"))).is_err());
+    }
+
+    #[test]
+    fn test_command_result() {
+        let success = CommandResult::ok("test data");
+        assert!(success.success);
+        assert_eq!(success.data, Some("test data"));
+        assert!(success.error.is_none());
+
+        let error = CommandResult::<String>::err("test error".to_string());
+        assert!(!error.success);
+        assert!(error.data.is_none());
         assert_eq!(error.error, Some("test error".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_dirty_tracking() {
+        let state = AppState::default();
+        assert!(!state.is_dirty("main").await);
+
+        state.set_dirty("main", true).await;
+        assert!(state.is_dirty("main").await);
+        assert!(!state.is_dirty("other").await);
+
+        state.set_dirty("main", false).await;
+        assert!(!state.is_dirty("main").await);
+    }
 }
@@ -0,0 +1,223 @@
+use html_escape::encode_text;
+use serde::{Deserialize, Serialize};
+
+use crate::text_diff::{diff, split_lines, split_words, DiffOp, DiffTag};
+
+/// Unchanged lines kept around each change; longer unchanged runs are folded
+const CONTEXT_LINES: usize = 3;
+
+const DIFF_CSS: &str = r#"
+.diff { width: 100%; border-collapse: collapse; table-layout: fixed; font-family: ui-monospace, SFMono-Regular, Menlo, monospace; font-size: 0.85em; }
+.diff th { text-align: left; padding: 0.4em; border-bottom: 1px solid #d0d7de; }
+.diff td { padding: 0 0.4em; vertical-align: top; white-space: pre-wrap; word-break: break-word; }
+.diff .diff-line { width: 3.5em; text-align: right; color: #8c959f; user-select: none; }
+.diff .diff-delete, .diff .diff-replace .diff-old, .diff-inline tr.diff-old { background: #ffebe9; }
+.diff .diff-insert, .diff .diff-replace .diff-new, .diff-inline tr.diff-new { background: #e6ffec; }
+.diff del { background: #ffc1c0; text-decoration: none; }
+.diff ins { background: #abf2bc; text-decoration: none; }
+.diff .diff-skip td { text-align: center; color: #57606a; background: #f6f8fa; }
+.diff-summary { margin-bottom: 0.5em; }
+.diff-summary .diff-added { color: #1a7f37; }
+.diff-summary .diff-removed { color: #cf222e; }
+"#;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLayout {
+    /// Old text on the left, new on the right
+    #[default]
+    SideBySide,
+    /// One column, with removals and additions marked in place
+    Inline,
+}
+
+/// Rendered comparison of two documents, ready for the preview or for export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentComparison {
+    /// Self-contained HTML, styles included
+    pub html: String,
+    pub added_lines: usize,
+    pub removed_lines: usize,
+}
+
+/// Line diff of two markdown sources, with changed lines diffed word by word
+pub fn diff_documents(old: &str, new: &str, old_label: &str, new_label: &str, layout: DiffLayout) -> DocumentComparison {
+    let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+    let ops = diff(&old_lines, &new_lines);
+    let added_lines = ops.iter().filter(|op| op.tag != DiffTag::Equal).map(|op| op.new.len()).sum();
+    let removed_lines = ops.iter().filter(|op| op.tag != DiffTag::Equal).map(|op| op.old.len()).sum();
+
+    let mut html = format!(
+        "<style>{}</style>\n<div class=\"diff-summary\"><span class=\"diff-added\">+{}</span> <span class=\"diff-removed\">&minus;{}</span></div>\n",
+        DIFF_CSS, added_lines, removed_lines,
+    );
+    let (class, header) = match layout {
+        DiffLayout::SideBySide => ("diff-side-by-side", format!(
+            "<th colspan=\"2\">{}</th><th colspan=\"2\">{}</th>", encode_text(old_label), encode_text(new_label),
+        )),
+        DiffLayout::Inline => ("diff-inline", format!(
+            "<th colspan=\"3\">{} &rarr; {}</th>", encode_text(old_label), encode_text(new_label),
+        )),
+    };
+    html.push_str(&format!("<table class=\"diff {}\">\n<thead><tr>{}</tr></thead>\n<tbody>\n", class, header));
+
+    let mut rows = Rows { html: &mut html, layout };
+    for (index, op) in ops.iter().enumerate() {
+        match op.tag {
+            DiffTag::Equal => {
+                let (first, last) = (index == 0, index == ops.len() - 1);
+                let head = if first { 0 } else { CONTEXT_LINES.min(op.old.len()) };
+                let tail = if last { 0 } else { CONTEXT_LINES.min(op.old.len() - head) };
+                let shown = |i: usize| i < head || i >= op.old.len() - tail;
+                let mut skipped = 0;
+                for i in 0..op.old.len() {
+                    if !shown(i) {
+                        skipped += 1;
+                        continue;
+                    }
+                    if skipped > 0 {
+                        rows.skip(skipped);
+                        skipped = 0;
+                    }
+                    let line = old_lines[op.old.start + i];
+                    rows.equal(op.old.start + i, op.new.start + i, line);
+                }
+                if skipped > 0 {
+                    rows.skip(skipped);
+                }
+            }
+            _ => changed_rows(&mut rows, op, &old_lines, &new_lines),
+        }
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    DocumentComparison { html, added_lines, removed_lines }
+}
+
+/// Pair removed lines with the added lines that replaced them, so each pair can
+/// show which words changed
+fn changed_rows(rows: &mut Rows, op: &DiffOp, old_lines: &[&str], new_lines: &[&str]) {
+    for i in 0..op.old.len().max(op.new.len()) {
+        let old = (i < op.old.len()).then(|| (op.old.start + i, old_lines[op.old.start + i]));
+        let new = (i < op.new.len()).then(|| (op.new.start + i, new_lines[op.new.start + i]));
+        match (old, new) {
+            (Some((old_number, old)), Some((new_number, new))) => rows.replaced(old_number, new_number, old, new),
+            (Some((number, line)), None) => rows.deleted(number, line),
+            (None, Some((number, line))) => rows.inserted(number, line),
+            (None, None) => {}
+        }
+    }
+}
+
+/// Table rows for one layout; line numbers are 0-based in, 1-based out
+struct Rows<'a> {
+    html: &'a mut String,
+    layout: DiffLayout,
+}
+
+impl Rows<'_> {
+    fn equal(&mut self, old_number: usize, new_number: usize, line: &str) {
+        let text = encode_text(line);
+        self.row("diff-equal", Some(old_number), Some(new_number), &text, &text);
+    }
+
+    fn deleted(&mut self, number: usize, line: &str) {
+        let text = format!("<del>{}</del>", encode_text(line));
+        self.row("diff-delete", Some(number), None, &text, "");
+    }
+
+    fn inserted(&mut self, number: usize, line: &str) {
+        let text = format!("<ins>{}</ins>", encode_text(line));
+        self.row("diff-insert", None, Some(number), "", &text);
+    }
+
+    fn replaced(&mut self, old_number: usize, new_number: usize, old: &str, new: &str) {
+        let (old_words, new_words) = (split_words(old), split_words(new));
+        let (mut old_html, mut new_html) = (String::new(), String::new());
+        for op in diff(&old_words, &new_words) {
+            let removed = encode_text(&old_words[op.old.clone()].concat()).to_string();
+            let added = encode_text(&new_words[op.new.clone()].concat()).to_string();
+            match op.tag {
+                DiffTag::Equal => {
+                    old_html.push_str(&removed);
+                    new_html.push_str(&added);
+                }
+                _ => {
+                    if !removed.is_empty() {
+                        old_html.push_str(&format!("<del>{}</del>", removed));
+                    }
+                    if !added.is_empty() {
+                        new_html.push_str(&format!("<ins>{}</ins>", added));
+                    }
+                }
+            }
+        }
+
+        match self.layout {
+            DiffLayout::SideBySide => self.row("diff-replace", Some(old_number), Some(new_number), &old_html, &new_html),
+            DiffLayout::Inline => {
+                self.row("diff-replace diff-old", Some(old_number), None, &old_html, "");
+                self.row("diff-replace diff-new", None, Some(new_number), "", &new_html);
+            }
+        }
+    }
+
+    fn skip(&mut self, count: usize) {
+        let columns = match self.layout {
+            DiffLayout::SideBySide => 4,
+            DiffLayout::Inline => 3,
+        };
+        let noun = if count == 1 { "line" } else { "lines" };
+        self.html.push_str(&format!(
+            "<tr class=\"diff-skip\"><td colspan=\"{}\">&#8943; {} unchanged {}</td></tr>\n", columns, count, noun,
+        ));
+    }
+
+    fn row(&mut self, class: &str, old_number: Option<usize>, new_number: Option<usize>, old: &str, new: &str) {
+        let number = |n: Option<usize>| n.map(|n| (n + 1).to_string()).unwrap_or_default();
+        let row = match self.layout {
+            DiffLayout::SideBySide => format!(
+                "<tr class=\"{}\"><td class=\"diff-line\">{}</td><td class=\"diff-text diff-old\">{}</td><td class=\"diff-line\">{}</td><td class=\"diff-text diff-new\">{}</td></tr>\n",
+                class, number(old_number), old, number(new_number), new,
+            ),
+            DiffLayout::Inline => format!(
+                "<tr class=\"{}\"><td class=\"diff-line\">{}</td><td class=\"diff-line\">{}</td><td class=\"diff-text\">{}</td></tr>\n",
+                class, number(old_number), number(new_number), if new_number.is_some() { new } else { old },
+            ),
+        };
+        self.html.push_str(&row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_side_by_side_word_highlighting() {
+        let old = "# Plan\n\nShip <b>v1</b> in May.\n";
+        let new = "# Plan\n\nShip <b>v1</b> in June.\nThen rest.\n";
+        let comparison = diff_documents(old, new, "draft-1.md", "draft-2.md", DiffLayout::SideBySide);
+
+        assert_eq!((comparison.added_lines, comparison.removed_lines), (2, 1));
+        assert!(comparison.html.contains("<th colspan=\"2\">draft-1.md</th>"));
+        assert!(comparison.html.contains("<td class=\"diff-text diff-old\">Ship &lt;b&gt;v1&lt;/b&gt; in <del>May</del>.</td>"));
+        assert!(comparison.html.contains("<td class=\"diff-text diff-new\">Ship &lt;b&gt;v1&lt;/b&gt; in <ins>June</ins>.</td>"));
+        assert!(comparison.html.contains("<tr class=\"diff-insert\"><td class=\"diff-line\"></td><td class=\"diff-text diff-old\"></td><td class=\"diff-line\">4</td><td class=\"diff-text diff-new\"><ins>Then rest.</ins></td></tr>"));
+    }
+
+    #[test]
+    fn test_inline_layout_folds_unchanged_lines() {
+        let old: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let new = old.replace("line 10\n", "line ten\n");
+        let comparison = diff_documents(&old, &new, "a.md", "b.md", DiffLayout::Inline);
+
+        assert!(comparison.html.contains("<th colspan=\"3\">a.md &rarr; b.md</th>"));
+        assert!(comparison.html.contains("<td class=\"diff-text\">line <del>10</del></td>"));
+        assert!(comparison.html.contains("<td class=\"diff-text\">line <ins>ten</ins></td>"));
+        assert!(comparison.html.contains("&#8943; 6 unchanged lines"));
+        assert!(comparison.html.contains("&#8943; 7 unchanged lines"));
+        assert!(!comparison.html.contains(">line 1<"));
+        assert!(comparison.html.contains(">line 7<"));
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+
+/// Start of the HTML comments that stand in for conflict marker lines while
+/// rendering, e.g. `<!--typolite-conflict:open:0-->`
+pub const CONFLICT_COMMENT: &str = "<!--typolite-conflict:";
+
+/// A Git merge conflict left in a document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MergeConflict {
+    /// Position among the document's conflicts
+    pub index: usize,
+    /// Source byte range, from the `<<<<<<<` line through the `>>>>>>>` line
+    pub start: usize,
+    pub end: usize,
+    /// 1-based lines of the opening and closing markers
+    pub start_line: usize,
+    pub end_line: usize,
+    /// What follows the markers, usually a branch name or commit
+    pub ours_label: String,
+    pub theirs_label: String,
+    pub ours: String,
+    pub theirs: String,
+    /// The common ancestor, in diff3-style conflicts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictSide {
+    Ours,
+    Theirs,
+    /// Ours followed by theirs
+    Both,
+}
+
+impl MergeConflict {
+    /// The text that replaces the conflict when resolving it to `side`
+    pub fn resolution(&self, side: ConflictSide) -> String {
+        match side {
+            ConflictSide::Ours => self.ours.clone(),
+            ConflictSide::Theirs => self.theirs.clone(),
+            ConflictSide::Both => format!("{}{}", self.ours, self.theirs),
+        }
+    }
+}
+
+/// Which marker line a rendering comment replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictMarker {
+    Open,
+    Base,
+    Separator,
+    Close,
+}
+
+impl ConflictMarker {
+    fn name(self) -> &'static str {
+        match self {
+            ConflictMarker::Open => "open",
+            ConflictMarker::Base => "base",
+            ConflictMarker::Separator => "separator",
+            ConflictMarker::Close => "close",
+        }
+    }
+}
+
+/// Complete conflicts in `markdown`; marker lines without the full
+/// `<<<<<<<` / `=======` / `>>>>>>>` sequence are left alone
+pub fn find_conflicts(markdown: &str) -> Vec<MergeConflict> {
+    struct Open {
+        start: usize,
+        line: usize,
+        label: String,
+        base_at: Option<usize>,
+        separator_at: Option<usize>,
+        // Byte offsets where each side's text starts and ends
+        ours: (usize, usize),
+        base: (usize, usize),
+    }
+
+    let mut conflicts = Vec::new();
+    let mut open: Option<Open> = None;
+    let mut offset = 0;
+    for (number, line) in markdown.split_inclusive('\n').enumerate() {
+        let (start, end) = (offset, offset + line.len());
+        offset = end;
+        let marker = line_marker(line);
+
+        match (&mut open, marker) {
+            (_, Some((ConflictMarker::Open, label))) => {
+                // A new opening marker abandons an unfinished conflict
+                open = Some(Open { start, line: number + 1, label, base_at: None, separator_at: None, ours: (end, end), base: (end, end) });
+            }
+            (Some(current), Some((ConflictMarker::Base, _))) if current.base_at.is_none() && current.separator_at.is_none() => {
+                current.ours.1 = start;
+                current.base_at = Some(end);
+            }
+            (Some(current), Some((ConflictMarker::Separator, _))) if current.separator_at.is_none() => {
+                match current.base_at {
+                    Some(base_start) => current.base = (base_start, start),
+                    None => current.ours.1 = start,
+                }
+                current.separator_at = Some(end);
+            }
+            (Some(current), Some((ConflictMarker::Close, label))) if current.separator_at.is_some() => {
+                let theirs_start = current.separator_at.unwrap_or(start);
+                conflicts.push(MergeConflict {
+                    index: conflicts.len(),
+                    start: current.start,
+                    end,
+                    start_line: current.line,
+                    end_line: number + 1,
+                    ours_label: current.label.clone(),
+                    theirs_label: label,
+                    ours: markdown[current.ours.0..current.ours.1].to_string(),
+                    theirs: markdown[theirs_start..start].to_string(),
+                    base: current.base_at.map(|_| markdown[current.base.0..current.base.1].to_string()),
+                });
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    conflicts
+}
+
+/// `markdown` with each conflict's marker lines swapped for HTML comments, so
+/// they can't merge into the text around them (`=======` would otherwise make
+/// the line above a heading); line numbers stay the same
+pub fn mark_conflicts(markdown: &str, conflicts: &[MergeConflict]) -> String {
+    let mut marked = String::with_capacity(markdown.len() + conflicts.len() * 4 * CONFLICT_COMMENT.len());
+    let mut conflicts = conflicts.iter().peekable();
+    let mut offset = 0;
+    for line in markdown.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        while conflicts.next_if(|conflict| conflict.end <= start).is_some() {}
+
+        let marker = conflicts.peek()
+            .filter(|conflict| conflict.start <= start)
+            .and_then(|conflict| Some((conflict.index, line_marker(line)?.0)));
+        match marker {
+            Some((index, marker)) => {
+                let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+                marked.push_str(&format!("{}{}:{}-->{}", CONFLICT_COMMENT, marker.name(), index, ending));
+            }
+            None => marked.push_str(line),
+        }
+    }
+    marked
+}
+
+/// The marker and conflict index in a comment written by `mark_conflicts`
+pub fn parse_conflict_comment(html: &str) -> Option<(ConflictMarker, usize)> {
+    let rest = html.trim_end().strip_prefix(CONFLICT_COMMENT)?.strip_suffix("-->")?;
+    let (name, index) = rest.split_once(':')?;
+    let marker = [ConflictMarker::Open, ConflictMarker::Base, ConflictMarker::Separator, ConflictMarker::Close]
+        .into_iter()
+        .find(|marker| marker.name() == name)?;
+    Some((marker, index.parse().ok()?))
+}
+
+/// A conflict marker line: seven marker characters, then nothing or a space and a label
+fn line_marker(line: &str) -> Option<(ConflictMarker, String)> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let markers = [
+        ("<<<<<<<", ConflictMarker::Open),
+        ("|||||||", ConflictMarker::Base),
+        ("=======", ConflictMarker::Separator),
+        (">>>>>>>", ConflictMarker::Close),
+    ];
+    markers.into_iter().find_map(|(prefix, marker)| {
+        let rest = line.strip_prefix(prefix)?;
+        match marker {
+            ConflictMarker::Separator if !rest.is_empty() => None,
+            _ if rest.is_empty() || rest.starts_with(' ') => Some((marker, rest.trim().to_string())),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFLICTED: &str = "# Notes\n\n<<<<<<< HEAD\nShip in May.\n=======\nShip in June.\n>>>>>>> feature/dates\n\nSetext\n=======\n";
+
+    #[test]
+    fn test_find_and_resolve_conflicts() {
+        let conflicts = find_conflicts(CONFLICTED);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!((conflict.start_line, conflict.end_line), (3, 7));
+        assert_eq!((conflict.ours_label.as_str(), conflict.theirs_label.as_str()), ("HEAD", "feature/dates"));
+        assert_eq!((conflict.ours.as_str(), conflict.theirs.as_str()), ("Ship in May.\n", "Ship in June.\n"));
+        assert_eq!(&CONFLICTED[conflict.start..conflict.end], "<<<<<<< HEAD\nShip in May.\n=======\nShip in June.\n>>>>>>> feature/dates\n");
+        assert_eq!(conflict.resolution(ConflictSide::Both), "Ship in May.\nShip in June.\n");
+
+        let diff3 = "<<<<<<< ours\r\na\r\n||||||| base\r\nb\r\n=======\r\nc\r\n>>>>>>> theirs\r\n";
+        let conflict = &find_conflicts(diff3)[0];
+        assert_eq!((conflict.ours.as_str(), conflict.base.as_deref(), conflict.theirs.as_str()), ("a\r\n", Some("b\r\n"), "c\r\n"));
+
+        assert!(find_conflicts("<<<<<<< HEAD\nunfinished\n=======\n").is_empty());
+    }
+
+    #[test]
+    fn test_mark_conflicts_keeps_lines() {
+        let conflicts = find_conflicts(CONFLICTED);
+        let marked = mark_conflicts(CONFLICTED, &conflicts);
+        assert_eq!(marked.lines().count(), CONFLICTED.lines().count());
+        assert!(marked.contains("\n<!--typolite-conflict:open:0-->\nShip in May.\n<!--typolite-conflict:separator:0-->\n"));
+        // The setext underline outside the conflict is untouched
+        assert!(marked.ends_with("Setext\n=======\n"));
+        assert_eq!(parse_conflict_comment("<!--typolite-conflict:close:0-->\n"), Some((ConflictMarker::Close, 0)));
+    }
+}
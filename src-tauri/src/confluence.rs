@@ -0,0 +1,298 @@
+use anyhow::{Result, Context};
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::settings::ConfluenceSettings;
+
+/// Keychain entry holding the Confluence API token
+pub const CONFLUENCE_TOKEN_KEY: &str = "confluence-token";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfluencePage {
+    pub id: String,
+    pub url: Option<String>,
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfluenceExportResult {
+    pub storage: String,
+    pub output_path: Option<PathBuf>,
+    /// The published page, when publishing was requested
+    pub page: Option<ConfluencePage>,
+}
+
+/// Render markdown as Confluence storage-format XHTML
+///
+/// Fenced code becomes the `code` macro and GitHub-style alerts
+/// (`> [!NOTE]`, `> [!WARNING]`, ...) become info/tip/note/warning panels.
+/// Raw HTML is escaped, since storage format must be well-formed XML.
+pub fn render_storage_format(markdown: &str) -> String {
+    let parser = Parser::new_ext(markdown, Options::all()).into_offset_iter();
+    let mut events = Vec::new();
+    let mut quotes: Vec<bool> = Vec::new(); // whether each open blockquote is a callout
+    let mut marker_chars = 0; // callout marker text still to be dropped
+    let mut drop_break = false;
+    let mut code: Option<(String, String)> = None; // language and body of the open code block
+    let mut in_image = false;
+
+    for (event, range) in parser {
+        if let Some((language, body)) = code.as_mut() {
+            match event {
+                Event::Text(text) => body.push_str(&text),
+                Event::End(Tag::CodeBlock(_)) => {
+                    events.push(Event::Html(code_macro(language, body).into()));
+                    code = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        if in_image {
+            // Alt text is carried in the opening tag; skip the inline content
+            in_image = !matches!(event, Event::End(Tag::Image(..)));
+            continue;
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code = Some((language, String::new()));
+            }
+            Event::Start(Tag::BlockQuote) => match callout_kind(&markdown[range]) {
+                Some((panel, marker_len)) => {
+                    quotes.push(true);
+                    marker_chars = marker_len;
+                    events.push(Event::Html(format!(
+                        "<ac:structured-macro ac:name=\"{}\"><ac:rich-text-body>",
+                        panel
+                    ).into()));
+                }
+                None => {
+                    quotes.push(false);
+                    events.push(Event::Start(Tag::BlockQuote));
+                }
+            },
+            Event::End(Tag::BlockQuote) => {
+                if quotes.pop().unwrap_or(false) {
+                    events.push(Event::Html("</ac:rich-text-body></ac:structured-macro>".into()));
+                } else {
+                    events.push(Event::End(Tag::BlockQuote));
+                }
+            }
+            Event::Text(text) if marker_chars > 0 => {
+                let len = text.chars().count();
+                if len <= marker_chars {
+                    marker_chars -= len;
+                    drop_break = marker_chars == 0;
+                } else {
+                    let rest: String = text.chars().skip(marker_chars).collect();
+                    marker_chars = 0;
+                    events.push(Event::Text(rest.trim_start().to_string().into()));
+                }
+            }
+            Event::SoftBreak | Event::HardBreak if drop_break => drop_break = false,
+            Event::Start(Tag::Image(_, dest, title)) => {
+                in_image = true;
+                let target = if dest.contains("://") {
+                    format!("<ri:url ri:value=\"{}\" />", html_escape::encode_double_quoted_attribute(&dest))
+                } else {
+                    // Local images are expected as page attachments with the same file name
+                    let file_name = dest.rsplit('/').next().unwrap_or(&dest).replace("%20", " ");
+                    format!("<ri:attachment ri:filename=\"{}\" />", html_escape::encode_double_quoted_attribute(&file_name))
+                };
+                events.push(Event::Html(format!(
+                    "<ac:image ac:title=\"{}\">{}</ac:image>",
+                    html_escape::encode_double_quoted_attribute(&title),
+                    target
+                ).into()));
+            }
+            Event::Html(raw) | Event::Text(raw) => {
+                drop_break = false;
+                events.push(Event::Text(raw));
+            }
+            other => {
+                drop_break = false;
+                events.push(other);
+            }
+        }
+    }
+
+    let mut output = String::with_capacity(markdown.len() * 2);
+    html::push_html(&mut output, events.into_iter());
+    output
+}
+
+/// Confluence panel macro and marker length for a `> [!TYPE]` blockquote
+fn callout_kind(source: &str) -> Option<(&'static str, usize)> {
+    let first_line = source.lines().next()?;
+    let content = first_line.trim_start().strip_prefix('>')?.trim_start();
+    let kind = content.strip_prefix("[!")?.split(']').next()?;
+
+    let panel = match kind.to_ascii_uppercase().as_str() {
+        "NOTE" => "info",
+        "TIP" => "tip",
+        "IMPORTANT" => "note",
+        "WARNING" | "CAUTION" => "warning",
+        _ => return None,
+    };
+    Some((panel, kind.chars().count() + 3))
+}
+
+fn code_macro(language: &str, body: &str) -> String {
+    let mut output = String::from("<ac:structured-macro ac:name=\"code\">");
+    if !language.is_empty() {
+        output.push_str(&format!(
+            "<ac:parameter ac:name=\"language\">{}</ac:parameter>",
+            html_escape::encode_text(language)
+        ));
+    }
+    // `]]>` can't appear inside CDATA, so split it across two sections
+    output.push_str(&format!(
+        "<ac:plain-text-body><![CDATA[{}]]></ac:plain-text-body></ac:structured-macro>",
+        body.trim_end_matches('\n').replace("]]>", "]]]]><![CDATA[>")
+    ));
+    output
+}
+
+/// Client for the Confluence REST content API
+pub struct ConfluenceService {
+    client: reqwest::Client,
+}
+
+impl Default for ConfluenceService {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+
+        Self { client }
+    }
+}
+
+impl ConfluenceService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the page, or add a new version if a page with this title already exists in the space
+    pub async fn publish(
+        &self,
+        title: &str,
+        storage: &str,
+        settings: &ConfluenceSettings,
+        token: &str,
+    ) -> Result<ConfluencePage> {
+        let base_url = settings.base_url.trim_end_matches('/');
+        if base_url.is_empty() || settings.space_key.is_empty() {
+            return Err(anyhow::anyhow!("Confluence site URL and space key must be configured"));
+        }
+        let content_url = format!("{}/rest/api/content", base_url);
+
+        debug!("Looking up Confluence page '{}' in space {}", title, settings.space_key);
+        let existing: Value = self.client
+            .get(&content_url)
+            .basic_auth(&settings.username, Some(token))
+            .query(&[("spaceKey", settings.space_key.as_str()), ("title", title), ("expand", "version")])
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Confluence at {}", base_url))?
+            .error_for_status()
+            .context("Confluence rejected the page lookup")?
+            .json()
+            .await?;
+
+        let existing = &existing["results"][0];
+        let request = match existing["id"].as_str() {
+            Some(id) => {
+                let version = existing["version"]["number"].as_u64().unwrap_or(1) + 1;
+                self.client
+                    .put(format!("{}/{}", content_url, id))
+                    .json(&build_page_body(title, storage, settings, Some(version)))
+            }
+            None => self.client
+                .post(&content_url)
+                .json(&build_page_body(title, storage, settings, None)),
+        };
+
+        let page: Value = request
+            .basic_auth(&settings.username, Some(token))
+            .send()
+            .await
+            .context("Failed to publish to Confluence")?
+            .error_for_status()
+            .context("Confluence rejected the page")?
+            .json()
+            .await?;
+
+        let page = ConfluencePage {
+            id: page["id"].as_str().unwrap_or_default().to_string(),
+            url: page["_links"]["webui"].as_str().map(|path| format!("{}{}", base_url, path)),
+            version: page["version"]["number"].as_u64().unwrap_or(1),
+        };
+
+        info!("Published Confluence page {} (version {})", page.id, page.version);
+        Ok(page)
+    }
+}
+
+fn build_page_body(title: &str, storage: &str, settings: &ConfluenceSettings, version: Option<u64>) -> Value {
+    let mut body = json!({
+        "type": "page",
+        "title": title,
+        "space": { "key": settings.space_key },
+        "body": { "storage": { "value": storage, "representation": "storage" } },
+    });
+
+    if let Some(parent) = settings.parent_page_id.as_ref().filter(|id| !id.is_empty()) {
+        body["ancestors"] = json!([{ "id": parent }]);
+    }
+    if let Some(version) = version {
+        body["version"] = json!({ "number": version });
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_blocks_become_macros() {
+        let storage = render_storage_format("Intro\n\n```rust\nfn main() {}\n```\n");
+
+        assert!(storage.contains("<p>Intro</p>"));
+        assert!(storage.contains(
+            "<ac:structured-macro ac:name=\"code\"><ac:parameter ac:name=\"language\">rust</ac:parameter>\
+             <ac:plain-text-body><![CDATA[fn main() {}]]></ac:plain-text-body></ac:structured-macro>"
+        ));
+    }
+
+    #[test]
+    fn test_callouts_and_quotes() {
+        let storage = render_storage_format("> [!WARNING]\n> Back up first.\n\n> Plain quote\n");
+
+        assert!(storage.contains(
+            "<ac:structured-macro ac:name=\"warning\"><ac:rich-text-body>\n<p>Back up first.</p>\n</ac:rich-text-body></ac:structured-macro>"
+        ));
+        assert!(storage.contains("<blockquote>\n<p>Plain quote</p>\n</blockquote>"));
+        assert!(!storage.contains("[!WARNING]"));
+    }
+
+    #[test]
+    fn test_images_and_raw_html() {
+        let storage = render_storage_format("![Chart](img/chart.png) <b>raw</b>");
+
+        assert!(storage.contains("<ac:image ac:title=\"\"><ri:attachment ri:filename=\"chart.png\" /></ac:image>"));
+        assert!(storage.contains("&lt;b&gt;raw&lt;/b&gt;"));
+    }
+}
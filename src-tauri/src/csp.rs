@@ -0,0 +1,147 @@
+/// Policy for rendered documents: no scripts, plugins, frames or form targets;
+/// styles, images, fonts and media from the page itself, data URLs or https
+pub const STRICT_CSP: &str = "default-src 'none'; script-src 'none'; object-src 'none'; frame-src 'none'; \
+    form-action 'none'; base-uri 'none'; style-src 'self' 'unsafe-inline'; img-src 'self' data: https: asset:; \
+    font-src 'self' data:; media-src 'self' data: https: asset:";
+
+/// [`STRICT_CSP`] plus the preview server's own live-reload script and socket
+pub const PREVIEW_CSP: &str = "default-src 'none'; script-src 'self'; connect-src 'self'; object-src 'none'; \
+    frame-src 'none'; form-action 'none'; base-uri 'none'; style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data: https:; font-src 'self' data:; media-src 'self' data: https:";
+
+/// Add a `Content-Security-Policy` meta tag as the first thing in the document's head
+///
+/// Documents without a `<head>` get the tag prepended, which browsers still honour.
+pub fn inject_csp(html: &str, policy: &str) -> String {
+    let meta = format!(
+        "<meta http-equiv=\"Content-Security-Policy\" content=\"{}\">",
+        html_escape::encode_double_quoted_attribute(policy)
+    );
+
+    let head = html.find("<head>").or_else(|| html.find("<HEAD>"));
+    match head {
+        Some(start) => {
+            let end = start + "<head>".len();
+            format!("{}\n    {}{}", &html[..end], meta, &html[end..])
+        }
+        None => format!("{}\n{}", meta, html),
+    }
+}
+
+/// Drop every `on*` attribute (`onclick`, `onerror`, ...) from the tags in `html`,
+/// leaving the rest of the markup byte for byte as it was
+pub fn strip_event_handlers(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        output.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        // Only start tags carry attributes; closing tags, comments and stray `<` pass through
+        if !tag[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            output.push('<');
+            rest = &tag[1..];
+            continue;
+        }
+        let consumed = copy_tag_without_handlers(tag, &mut output);
+        rest = &tag[consumed..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Copy the start tag at the beginning of `tag` into `output`, skipping event
+/// handler attributes; returns how many bytes the tag took up
+///
+/// Attributes are split the way browsers do, so a `>` inside a quoted value
+/// doesn't end the tag early and let a handler through.
+fn copy_tag_without_handlers(tag: &str, output: &mut String) -> usize {
+    let bytes = tag.as_bytes();
+    let is_space = |b: u8| b.is_ascii_whitespace();
+
+    let mut i = 1;
+    while i < bytes.len() && !is_space(bytes[i]) && !matches!(bytes[i], b'/' | b'>') {
+        i += 1;
+    }
+    output.push_str(&tag[..i]);
+
+    loop {
+        let gap = i;
+        while i < bytes.len() && (is_space(bytes[i]) || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            output.push_str(&tag[gap..]);
+            return bytes.len();
+        }
+        if bytes[i] == b'>' {
+            output.push_str(&tag[gap..=i]);
+            return i + 1;
+        }
+
+        let name_start = i;
+        while i < bytes.len() && !is_space(bytes[i]) && !matches!(bytes[i], b'=' | b'/' | b'>') {
+            i += 1;
+        }
+        let name = &tag[name_start..i];
+
+        let mut value = i;
+        while value < bytes.len() && is_space(bytes[value]) {
+            value += 1;
+        }
+        if value < bytes.len() && bytes[value] == b'=' {
+            value += 1;
+            while value < bytes.len() && is_space(bytes[value]) {
+                value += 1;
+            }
+            i = match bytes.get(value) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => tag[value + 1..]
+                    .find(quote as char)
+                    .map_or(bytes.len(), |end| value + 1 + end + 1),
+                _ => {
+                    let mut end = value;
+                    while end < bytes.len() && !is_space(bytes[end]) && bytes[end] != b'>' {
+                        end += 1;
+                    }
+                    end
+                }
+            };
+        }
+
+        if !name.get(..2).is_some_and(|prefix| prefix.eq_ignore_ascii_case("on")) {
+            output.push_str(&tag[gap..i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_event_handlers() {
+        let html = r#"<p class="x" onclick="steal()">Hi <b>there</b></p>
+<img src="a.png" ONERROR=alert(1) alt='2 > 1' onload = 'go()'/>
+<a title="onclick=no" href="/top">top</a> 3 < 4 <!-- <x onclick=1> -->"#;
+
+        assert_eq!(
+            strip_event_handlers(html),
+            r#"<p class="x">Hi <b>there</b></p>
+<img src="a.png" alt='2 > 1'/>
+<a title="onclick=no" href="/top">top</a> 3 < 4 <!-- <x> -->"#
+        );
+        assert_eq!(strip_event_handlers("<svg/onload=alert(1)>"), "<svg>");
+        assert_eq!(strip_event_handlers("<p>Grüße ✓</p>"), "<p>Grüße ✓</p>");
+    }
+
+    #[test]
+    fn test_inject_csp() {
+        let page = inject_csp("<html>\n<head>\n<title>x</title></head></html>", STRICT_CSP);
+        assert!(page.starts_with("<html>\n<head>\n    <meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'none';"));
+        assert!(page.ends_with("\n<title>x</title></head></html>"));
+
+        let fragment = inject_csp("<p>hi</p>", "default-src 'none'");
+        assert_eq!(fragment, "<meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'none'\">\n<p>hi</p>");
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Free and total space of the disk holding a folder
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DiskSpace {
+    pub path: PathBuf,
+    /// Space this user can still write, which may be less than what's free
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Space on the disk holding `path`, measured at its nearest existing
+/// ancestor so folders the app hasn't created yet still report
+pub fn disk_space(path: &Path) -> Result<DiskSpace> {
+    let existing = path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .with_context(|| format!("No existing folder above {:?}", path))?;
+    let stats = fs2::statvfs(existing)
+        .with_context(|| format!("Failed to read disk space for {:?}", existing))?;
+    Ok(DiskSpace {
+        path: path.to_path_buf(),
+        available_bytes: stats.available_space(),
+        total_bytes: stats.total_space(),
+    })
+}
+
+/// The user's locale as a BCP 47 tag like `de-DE`, from the standard locale
+/// environment variables
+pub fn system_locale() -> Option<String> {
+    locale_from(|name| std::env::var(name).ok())
+}
+
+fn locale_from(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let value = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|name| var(name))
+        .find(|value| !value.trim().is_empty())?;
+    // `de_DE.UTF-8@euro` -> `de-DE`; `C` and `POSIX` mean no locale was chosen
+    let tag = value.split(['.', '@']).next().unwrap_or_default().replace('_', "-");
+    (!tag.is_empty() && tag != "C" && tag != "POSIX").then_some(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_locale_from_environment() {
+        let env = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            locale_from(move |name| vars.get(name).cloned())
+        };
+        assert_eq!(env(&[("LANG", "de_DE.UTF-8@euro")]).as_deref(), Some("de-DE"));
+        assert_eq!(env(&[("LANG", "en_US.UTF-8"), ("LC_ALL", "fr_CA")]).as_deref(), Some("fr-CA"));
+        assert_eq!(env(&[("LC_ALL", ""), ("LANG", "ja_JP.UTF-8")]).as_deref(), Some("ja-JP"));
+        assert_eq!(env(&[("LANG", "C.UTF-8")]), None);
+        assert_eq!(env(&[]), None);
+    }
+
+    #[test]
+    fn test_disk_space_of_missing_folder() {
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("not/created/yet");
+
+        let space = disk_space(&missing).unwrap();
+        assert_eq!(space.path, missing);
+        assert!(space.total_bytes > 0);
+        assert!(space.available_bytes <= space.total_bytes);
+    }
+}
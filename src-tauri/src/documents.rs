@@ -0,0 +1,317 @@
+use anyhow::{Result, Context};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::debug;
+
+use crate::conflicts::{find_conflicts, ConflictSide};
+
+/// A change to a document buffer
+///
+/// Offsets count UTF-16 code units, as JavaScript strings do, so the editor can
+/// send its own change positions unconverted.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DocumentEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DocumentInfo {
+    pub document: String,
+    pub path: Option<PathBuf>,
+    /// Bumped on every change, so stale edits can be rejected
+    pub version: u64,
+    pub length: usize, // in UTF-16 code units
+    pub line_count: usize,
+}
+
+/// The result of a buffer-side change: the edits that make it, for the editor to replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentChange {
+    pub info: DocumentInfo,
+    /// Applied in order, each against the text left by the previous one
+    pub edits: Vec<DocumentEdit>,
+}
+
+struct Buffer {
+    rope: Rope,
+    path: Option<PathBuf>,
+    version: u64,
+}
+
+impl Buffer {
+    fn info(&self, document: &str) -> DocumentInfo {
+        DocumentInfo {
+            document: document.to_string(),
+            path: self.path.clone(),
+            version: self.version,
+            length: self.rope.len_utf16_cu(),
+            line_count: self.rope.len_lines(),
+        }
+    }
+
+    fn utf16_of_byte(&self, byte: usize) -> usize {
+        self.rope.char_to_utf16_cu(self.rope.byte_to_char(byte))
+    }
+}
+
+/// Open documents held as ropes, so edits travel as deltas instead of whole texts
+#[derive(Default)]
+pub struct DocumentStore {
+    buffers: Mutex<HashMap<String, Buffer>>,
+}
+
+impl DocumentStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load `text` as `document`, replacing any buffer already open under that id
+    pub fn open(&self, document: &str, text: &str, path: Option<PathBuf>) -> DocumentInfo {
+        debug!("Opening document buffer {} ({} bytes)", document, text.len());
+        let buffer = Buffer { rope: Rope::from_str(text), path, version: 0 };
+        let info = buffer.info(document);
+        self.buffers.lock().unwrap().insert(document.to_string(), buffer);
+        info
+    }
+
+    pub fn close(&self, document: &str) -> bool {
+        self.buffers.lock().unwrap().remove(document).is_some()
+    }
+
+    pub fn info(&self, document: &str) -> Option<DocumentInfo> {
+        self.buffers.lock().unwrap().get(document).map(|buffer| buffer.info(document))
+    }
+
+    pub fn text(&self, document: &str) -> Option<String> {
+        self.buffers.lock().unwrap().get(document).map(|buffer| buffer.rope.to_string())
+    }
+
+    /// Where the document is saved, with its current text
+    pub fn snapshot(&self, document: &str) -> Option<(Option<PathBuf>, String)> {
+        self.buffers.lock().unwrap().get(document).map(|buffer| (buffer.path.clone(), buffer.rope.to_string()))
+    }
+
+    pub fn set_path(&self, document: &str, path: PathBuf) {
+        if let Some(buffer) = self.buffers.lock().unwrap().get_mut(document) {
+            buffer.path = Some(path);
+        }
+    }
+
+    /// Apply edits in order; none are applied if any is out of range
+    ///
+    /// With `expected_version`, edits made against an older version are refused
+    /// so the editor can resync instead of corrupting the buffer.
+    pub fn apply_edits(&self, document: &str, expected_version: Option<u64>, edits: &[DocumentEdit]) -> Result<DocumentInfo> {
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.get_mut(document)
+            .with_context(|| format!("Document is not open: {}", document))?;
+
+        if let Some(expected) = expected_version.filter(|expected| *expected != buffer.version) {
+            anyhow::bail!("Document {} is at version {}, edits were made against {}", document, buffer.version, expected);
+        }
+
+        // Ropes share structure, so editing a copy costs little and keeps a failed batch atomic
+        let mut rope = buffer.rope.clone();
+        for edit in edits {
+            apply_edit(&mut rope, edit)?;
+        }
+
+        buffer.rope = rope;
+        buffer.version += 1;
+        Ok(buffer.info(document))
+    }
+
+    /// Replace every occurrence of `find`, returning the edits that did it
+    pub fn replace_all(&self, document: &str, find: &str, replace: &str) -> Result<DocumentChange> {
+        if find.is_empty() {
+            anyhow::bail!("Nothing to find");
+        }
+
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.get_mut(document)
+            .with_context(|| format!("Document is not open: {}", document))?;
+
+        let text = buffer.rope.to_string();
+        // Last match first, so every offset still refers to the original text
+        let edits: Vec<DocumentEdit> = text.match_indices(find)
+            .map(|(start, found)| DocumentEdit {
+                start: buffer.utf16_of_byte(start),
+                end: buffer.utf16_of_byte(start + found.len()),
+                text: replace.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+
+        if !edits.is_empty() {
+            for edit in &edits {
+                apply_edit(&mut buffer.rope, edit)?;
+            }
+            buffer.version += 1;
+        }
+
+        Ok(DocumentChange { info: buffer.info(document), edits })
+    }
+
+    /// Check or uncheck the task list item on `line` (1-based)
+    pub fn toggle_task(&self, document: &str, line: usize) -> Result<DocumentChange> {
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.get_mut(document)
+            .with_context(|| format!("Document is not open: {}", document))?;
+
+        let index = line.checked_sub(1).filter(|index| *index < buffer.rope.len_lines())
+            .with_context(|| format!("Line {} is outside the document", line))?;
+        let text = buffer.rope.line(index).to_string();
+        let (offset, checked) = task_marker(&text)
+            .with_context(|| format!("Line {} is not a task list item", line))?;
+
+        let start = buffer.rope.char_to_utf16_cu(buffer.rope.line_to_char(index)) + offset;
+        let edit = DocumentEdit {
+            start,
+            end: start + 1,
+            text: if checked { " " } else { "x" }.to_string(),
+        };
+        apply_edit(&mut buffer.rope, &edit)?;
+        buffer.version += 1;
+
+        Ok(DocumentChange { info: buffer.info(document), edits: vec![edit] })
+    }
+
+    /// Replace merge conflict `index` (counted as in `find_conflicts`) with the side to keep
+    pub fn resolve_conflict(&self, document: &str, index: usize, side: ConflictSide) -> Result<DocumentChange> {
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.get_mut(document)
+            .with_context(|| format!("Document is not open: {}", document))?;
+
+        let conflict = find_conflicts(&buffer.rope.to_string()).into_iter().nth(index)
+            .with_context(|| format!("Document has no merge conflict {}", index))?;
+        let edit = DocumentEdit {
+            start: buffer.utf16_of_byte(conflict.start),
+            end: buffer.utf16_of_byte(conflict.end),
+            text: conflict.resolution(side),
+        };
+        apply_edit(&mut buffer.rope, &edit)?;
+        buffer.version += 1;
+
+        Ok(DocumentChange { info: buffer.info(document), edits: vec![edit] })
+    }
+}
+
+fn apply_edit(rope: &mut Rope, edit: &DocumentEdit) -> Result<()> {
+    let length = rope.len_utf16_cu();
+    if edit.start > edit.end || edit.end > length {
+        anyhow::bail!("Edit {}..{} is outside the document ({} code units)", edit.start, edit.end, length);
+    }
+
+    let start = rope.utf16_cu_to_char(edit.start);
+    let end = rope.utf16_cu_to_char(edit.end);
+    rope.remove(start..end);
+    rope.insert(start, &edit.text);
+    Ok(())
+}
+
+/// Offset (in UTF-16 code units) of the character between a task's brackets, and whether it's checked
+fn task_marker(line: &str) -> Option<(usize, bool)> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+
+    let after_bullet = if let Some(rest) = rest.strip_prefix(['-', '*', '+']) {
+        rest
+    } else {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        rest[digits..].strip_prefix(['.', ')'])?
+    };
+
+    let marker = after_bullet.strip_prefix(' ')?;
+    let checked = match marker.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+
+    // Everything before the marker is ASCII, so bytes and code units agree
+    Some((line.len() - marker.len() + 1, checked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_edits_in_utf16() {
+        let store = DocumentStore::new();
+        store.open("notes", "Café 😀 ok\nsecond\n", None);
+
+        // The emoji is two UTF-16 code units, so "ok" starts at 8
+        let edits = vec![
+            DocumentEdit { start: 8, end: 10, text: "fine".to_string() },
+            DocumentEdit { start: 0, end: 0, text: "# ".to_string() },
+        ];
+        let info = store.apply_edits("notes", Some(0), &edits).unwrap();
+
+        assert_eq!(store.text("notes").unwrap(), "# Café 😀 fine\nsecond\n");
+        assert_eq!(info.version, 1);
+        assert_eq!(info.line_count, 3);
+
+        // Stale and out-of-range edits leave the buffer untouched
+        assert!(store.apply_edits("notes", Some(0), &edits).is_err());
+        let bad = vec![edits[1].clone(), DocumentEdit { start: 90, end: 91, text: String::new() }];
+        assert!(store.apply_edits("notes", None, &bad).is_err());
+        assert_eq!(store.info("notes").unwrap().version, 1);
+    }
+
+    #[test]
+    fn test_replace_all_returns_replayable_edits() {
+        let store = DocumentStore::new();
+        let original = "one ✓ two, one three one";
+        store.open("notes", original, None);
+
+        let change = store.replace_all("notes", "one", "1").unwrap();
+        assert_eq!(change.edits.len(), 3);
+        assert_eq!(store.text("notes").unwrap(), "1 ✓ two, 1 three 1");
+
+        // Replaying the edits on the editor's copy gives the same text
+        let mut replayed = Rope::from_str(original);
+        for edit in &change.edits {
+            apply_edit(&mut replayed, edit).unwrap();
+        }
+        assert_eq!(replayed.to_string(), store.text("notes").unwrap());
+    }
+
+    #[test]
+    fn test_toggle_task() {
+        let store = DocumentStore::new();
+        store.open("todo", "# Todo\n- [ ] write\n  1. [X] nested\nplain\n", None);
+
+        let change = store.toggle_task("todo", 2).unwrap();
+        assert_eq!(change.edits, vec![DocumentEdit { start: 10, end: 11, text: "x".to_string() }]);
+        store.toggle_task("todo", 3).unwrap();
+
+        assert_eq!(store.text("todo").unwrap(), "# Todo\n- [x] write\n  1. [ ] nested\nplain\n");
+        assert!(store.toggle_task("todo", 4).is_err());
+        assert!(store.toggle_task("todo", 0).is_err());
+    }
+
+    #[test]
+    fn test_resolve_conflict() {
+        let store = DocumentStore::new();
+        let text = "Intro ✓\n<<<<<<< HEAD\nmine\n=======\ntheirs\n>>>>>>> main\n<<<<<<< HEAD\nA\n=======\nB\n>>>>>>> main\n";
+        store.open("merge", text, None);
+
+        let change = store.resolve_conflict("merge", 1, ConflictSide::Theirs).unwrap();
+        assert_eq!(change.edits[0].start, 54);
+        store.resolve_conflict("merge", 0, ConflictSide::Both).unwrap();
+
+        assert_eq!(store.text("merge").unwrap(), "Intro ✓\nmine\ntheirs\nB\n");
+        assert!(store.resolve_conflict("merge", 0, ConflictSide::Ours).is_err());
+    }
+}
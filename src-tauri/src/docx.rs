@@ -0,0 +1,643 @@
+use anyhow::{Result, Context};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocxImportResult {
+    pub markdown: String,
+    pub output_path: Option<PathBuf>,
+    /// Images extracted from the document
+    pub images: Vec<PathBuf>,
+}
+
+/// Converts Word documents to markdown without an external converter
+///
+/// Headings, bulleted and numbered lists, tables, links, bold/italic/strike
+/// runs and code-styled paragraphs are mapped to markdown; embedded images are
+/// extracted next to the output and linked relatively.
+#[derive(Default)]
+pub struct DocxImporter;
+
+impl DocxImporter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Convert `source`, writing the markdown to `output_path` if given
+    ///
+    /// Images are written to an `assets` folder beside the output file, or
+    /// beside the source document when there is no output file.
+    pub async fn import(&self, source: &Path, output_path: Option<&Path>) -> Result<DocxImportResult> {
+        debug!("Importing DOCX: {:?}", source);
+
+        let bytes = tokio::fs::read(source).await
+            .with_context(|| format!("Failed to read DOCX file: {:?}", source))?;
+        let converted = tokio::task::spawn_blocking(move || convert_docx(&bytes, "assets"))
+            .await
+            .context("DOCX conversion task panicked")??;
+
+        let base_dir = output_path.or(Some(source)).and_then(Path::parent).unwrap_or(Path::new("."));
+        let assets_dir = base_dir.join("assets");
+        let mut images = Vec::new();
+        if !converted.images.is_empty() {
+            tokio::fs::create_dir_all(&assets_dir).await
+                .with_context(|| format!("Failed to create assets directory: {:?}", assets_dir))?;
+        }
+        for (name, data) in converted.images {
+            let path = assets_dir.join(&name);
+            tokio::fs::write(&path, data).await
+                .with_context(|| format!("Failed to write image: {:?}", path))?;
+            images.push(path);
+        }
+
+        if let Some(output_path) = output_path {
+            tokio::fs::write(output_path, &converted.markdown).await
+                .with_context(|| format!("Failed to write markdown: {:?}", output_path))?;
+        }
+
+        info!("Imported DOCX {:?} ({} chars, {} images)", source, converted.markdown.len(), images.len());
+        Ok(DocxImportResult {
+            markdown: converted.markdown,
+            output_path: output_path.map(Path::to_path_buf),
+            images,
+        })
+    }
+}
+
+struct ConvertedDocument {
+    markdown: String,
+    images: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Block {
+    Paragraph(String),
+    Heading(usize, String),
+    ListItem { level: usize, ordered: bool, text: String },
+    Code(String),
+    Table(Vec<Vec<String>>),
+}
+
+#[derive(Default)]
+struct Paragraph {
+    style: Option<String>,
+    outline_level: Option<usize>,
+    list: Option<(usize, String)>, // level and numbering id
+    runs: Vec<Run>,
+}
+
+#[derive(Default, Clone, PartialEq)]
+struct RunStyle {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+}
+
+struct Run {
+    text: String,
+    style: RunStyle,
+    /// Already-formatted markdown such as links and images, emitted verbatim
+    literal: bool,
+}
+
+/// Archive parts the converter needs besides `word/document.xml`
+struct DocumentParts {
+    relationships: HashMap<String, String>,
+    style_names: HashMap<String, String>,
+    ordered_lists: HashMap<String, Vec<bool>>, // numbering id -> ordered per level
+}
+
+fn convert_docx(bytes: &[u8], image_prefix: &str) -> Result<ConvertedDocument> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("Not a valid DOCX file")?;
+    let document = read_part(&mut archive, "word/document.xml")?
+        .ok_or_else(|| anyhow::anyhow!("DOCX file has no word/document.xml"))?;
+
+    let parts = DocumentParts {
+        relationships: read_part(&mut archive, "word/_rels/document.xml.rels")?
+            .map(|xml| parse_relationships(&xml))
+            .transpose()?
+            .unwrap_or_default(),
+        style_names: read_part(&mut archive, "word/styles.xml")?
+            .map(|xml| parse_style_names(&xml))
+            .transpose()?
+            .unwrap_or_default(),
+        ordered_lists: read_part(&mut archive, "word/numbering.xml")?
+            .map(|xml| parse_numbering(&xml))
+            .transpose()?
+            .unwrap_or_default(),
+    };
+
+    let mut image_targets = Vec::new();
+    let blocks = parse_document(&document, &parts, image_prefix, &mut image_targets)?;
+
+    let mut images = Vec::new();
+    for target in image_targets {
+        let name = target.rsplit('/').next().unwrap_or(&target).to_string();
+        let part = format!("word/{}", target.trim_start_matches('/').trim_start_matches("word/"));
+        let mut data = Vec::new();
+        match archive.by_name(&part) {
+            Ok(mut file) => {
+                file.read_to_end(&mut data)?;
+                images.push((name, data));
+            }
+            Err(_) => warn!("Image part missing from DOCX: {}", part),
+        }
+    }
+
+    Ok(ConvertedDocument {
+        markdown: render_blocks(&blocks),
+        images,
+    })
+}
+
+fn read_part(archive: &mut zip::ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(Some(content))
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == name)
+        .and_then(|attr| attr.unescape_value().ok().map(|value| value.to_string()))
+}
+
+/// A boolean run property like `<w:b/>` is on unless its value is `0`/`false`
+fn toggle(element: &BytesStart) -> bool {
+    !matches!(attribute(element, b"val").as_deref(), Some("0" | "false" | "none"))
+}
+
+fn parse_relationships(xml: &str) -> Result<HashMap<String, String>> {
+    let mut reader = Reader::from_str(xml);
+    let mut relationships = HashMap::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+                if let (Some(id), Some(target)) = (attribute(&e, b"Id"), attribute(&e, b"Target")) {
+                    relationships.insert(id, target);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(relationships)
+}
+
+/// Map style ids to their lowercase names, e.g. `Heading1` -> `heading 1`
+fn parse_style_names(xml: &str) -> Result<HashMap<String, String>> {
+    let mut reader = Reader::from_str(xml);
+    let mut names = HashMap::new();
+    let mut current = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"style" => current = attribute(&e, b"styleId"),
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"name" => {
+                if let (Some(id), Some(name)) = (current.clone(), attribute(&e, b"val")) {
+                    names.insert(id, name.to_lowercase());
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == b"style" => current = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(names)
+}
+
+/// Work out which numbering ids are ordered lists, per indentation level
+fn parse_numbering(xml: &str) -> Result<HashMap<String, Vec<bool>>> {
+    let mut reader = Reader::from_str(xml);
+    let mut abstract_formats: HashMap<String, Vec<bool>> = HashMap::new();
+    let mut num_to_abstract = HashMap::new();
+    let mut current_abstract = None;
+    let mut current_num = None;
+    let mut current_level = 0;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"abstractNum" => current_abstract = attribute(&e, b"abstractNumId"),
+                b"lvl" => current_level = attribute(&e, b"ilvl").and_then(|l| l.parse().ok()).unwrap_or(0),
+                b"numFmt" => {
+                    if let Some(id) = &current_abstract {
+                        let levels = abstract_formats.entry(id.clone()).or_default();
+                        if levels.len() <= current_level {
+                            levels.resize(current_level + 1, false);
+                        }
+                        levels[current_level] = attribute(&e, b"val").is_some_and(|fmt| fmt != "bullet" && fmt != "none");
+                    }
+                }
+                b"num" => current_num = attribute(&e, b"numId"),
+                b"abstractNumId" => {
+                    if let (Some(num), Some(abstract_id)) = (current_num.clone(), attribute(&e, b"val")) {
+                        num_to_abstract.insert(num, abstract_id);
+                    }
+                }
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"abstractNum" => current_abstract = None,
+                b"num" => current_num = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(num_to_abstract
+        .into_iter()
+        .filter_map(|(num, abstract_id)| Some((num, abstract_formats.get(&abstract_id)?.clone())))
+        .collect())
+}
+
+fn parse_document(
+    xml: &str,
+    parts: &DocumentParts,
+    image_prefix: &str,
+    image_targets: &mut Vec<String>,
+) -> Result<Vec<Block>> {
+    let mut reader = Reader::from_str(xml);
+    let mut blocks = Vec::new();
+
+    let mut paragraph: Option<Paragraph> = None;
+    let mut run_style = RunStyle::default();
+    let mut in_run_properties = false;
+    let mut in_text = false;
+    let mut link: Option<(String, Vec<Run>)> = None; // target and runs of the open hyperlink
+    let mut table_depth = 0;
+    let mut table: Vec<Vec<String>> = Vec::new();
+    let mut cell: Vec<String> = Vec::new();
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                let is_empty = matches!(event, Event::Empty(_));
+                match e.local_name().as_ref() {
+                    b"tbl" if !is_empty => {
+                        table_depth += 1;
+                        if table_depth == 1 {
+                            table.clear();
+                        }
+                    }
+                    b"tr" if table_depth == 1 && !is_empty => table.push(Vec::new()),
+                    b"tc" if table_depth == 1 && !is_empty => cell.clear(),
+                    b"p" => {
+                        paragraph = Some(Paragraph::default());
+                        if is_empty {
+                            paragraph = None;
+                        }
+                    }
+                    b"pStyle" => {
+                        if let Some(p) = paragraph.as_mut() {
+                            p.style = attribute(e, b"val");
+                        }
+                    }
+                    b"outlineLvl" => {
+                        if let Some(p) = paragraph.as_mut() {
+                            p.outline_level = attribute(e, b"val").and_then(|v| v.parse().ok());
+                        }
+                    }
+                    b"ilvl" => {
+                        if let Some(p) = paragraph.as_mut() {
+                            let level = attribute(e, b"val").and_then(|v| v.parse().ok()).unwrap_or(0);
+                            let id = p.list.take().map(|(_, id)| id).unwrap_or_default();
+                            p.list = Some((level, id));
+                        }
+                    }
+                    b"numId" => {
+                        if let Some(p) = paragraph.as_mut() {
+                            let level = p.list.take().map_or(0, |(level, _)| level);
+                            p.list = Some((level, attribute(e, b"val").unwrap_or_default()));
+                        }
+                    }
+                    b"r" if !is_empty => run_style = RunStyle::default(),
+                    b"rPr" if !is_empty => in_run_properties = true,
+                    b"b" if in_run_properties => run_style.bold = toggle(e),
+                    b"i" if in_run_properties => run_style.italic = toggle(e),
+                    b"strike" if in_run_properties => run_style.strike = toggle(e),
+                    b"t" if !is_empty => in_text = true,
+                    b"tab" if !in_run_properties => push_run(&mut paragraph, &mut link, " ", &run_style, false),
+                    b"br" | b"cr" => {
+                        let line_break = if table_depth > 0 { "<br>" } else { "  \n" };
+                        push_run(&mut paragraph, &mut link, line_break, &RunStyle::default(), true);
+                    }
+                    b"hyperlink" if !is_empty => {
+                        let target = attribute(e, b"id")
+                            .and_then(|id| parts.relationships.get(&id).cloned())
+                            .or_else(|| attribute(e, b"anchor").map(|anchor| format!("#{}", anchor)));
+                        link = target.map(|target| (target, Vec::new()));
+                    }
+                    b"blip" => {
+                        if let Some(target) = attribute(e, b"embed").and_then(|id| parts.relationships.get(&id).cloned()) {
+                            let name = target.rsplit('/').next().unwrap_or(&target).to_string();
+                            let image = format!("![{}]({}/{})", name, image_prefix, name.replace(' ', "%20"));
+                            image_targets.push(target);
+                            push_run(&mut paragraph, &mut link, &image, &RunStyle::default(), true);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(text) if in_text => {
+                let text = text.unescape()?;
+                push_run(&mut paragraph, &mut link, &text, &run_style.clone(), false);
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"rPr" => in_run_properties = false,
+                b"hyperlink" => {
+                    if let Some((target, runs)) = link.take() {
+                        let text = render_runs(&runs);
+                        push_run(&mut paragraph, &mut None, &format!("[{}]({})", text, target), &RunStyle::default(), true);
+                    }
+                }
+                b"p" => {
+                    if let Some(p) = paragraph.take() {
+                        if table_depth > 0 {
+                            let text = render_runs(&p.runs).replace('|', "\\|");
+                            if !text.trim().is_empty() {
+                                cell.push(text.trim().to_string());
+                            }
+                        } else if let Some(block) = paragraph_block(p, parts) {
+                            blocks.push(block);
+                        }
+                    }
+                }
+                b"tc" if table_depth == 1 => {
+                    if let Some(row) = table.last_mut() {
+                        row.push(cell.join("<br>"));
+                    }
+                }
+                b"tbl" => {
+                    table_depth -= 1;
+                    if table_depth == 0 && !table.is_empty() {
+                        blocks.push(Block::Table(std::mem::take(&mut table)));
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(blocks)
+}
+
+fn push_run(paragraph: &mut Option<Paragraph>, link: &mut Option<(String, Vec<Run>)>, text: &str, style: &RunStyle, literal: bool) {
+    let run = Run { text: text.to_string(), style: style.clone(), literal };
+    match (link.as_mut(), paragraph.as_mut()) {
+        (Some((_, runs)), _) => runs.push(run),
+        (None, Some(p)) => p.runs.push(run),
+        (None, None) => {}
+    }
+}
+
+/// Render runs to inline markdown, merging neighbours with the same formatting
+fn render_runs(runs: &[Run]) -> String {
+    let mut merged: Vec<Run> = Vec::new();
+    for run in runs {
+        match merged.last_mut() {
+            Some(last) if !last.literal && !run.literal && last.style == run.style => last.text.push_str(&run.text),
+            _ => merged.push(Run { text: run.text.clone(), style: run.style.clone(), literal: run.literal }),
+        }
+    }
+
+    let mut output = String::new();
+    for run in merged {
+        if run.literal {
+            output.push_str(&run.text);
+            continue;
+        }
+
+        let text = escape_markdown(&run.text);
+        let mut marker = String::new();
+        if run.style.strike {
+            marker.push_str("~~");
+        }
+        if run.style.bold {
+            marker.push_str("**");
+        }
+        if run.style.italic {
+            marker.push('*');
+        }
+
+        // Emphasis can't open or close on whitespace, so keep it outside the markers
+        let trimmed = text.trim();
+        if marker.is_empty() || trimmed.is_empty() {
+            output.push_str(&text);
+        } else {
+            let leading = &text[..text.len() - text.trim_start().len()];
+            let trailing = &text[text.trim_end().len()..];
+            let closing: String = marker.chars().rev().collect();
+            output.push_str(&format!("{}{}{}{}{}", leading, marker, trimmed, closing, trailing));
+        }
+    }
+    output
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '_' | '`' | '[' | ']' | '<' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn paragraph_block(paragraph: Paragraph, parts: &DocumentParts) -> Option<Block> {
+    let style_name = paragraph.style.as_ref()
+        .map(|id| parts.style_names.get(id).cloned().unwrap_or_else(|| id.to_lowercase()))
+        .unwrap_or_default();
+
+    if style_name.contains("code") || style_name.contains("source") || style_name == "html preformatted" {
+        let code: String = paragraph.runs.iter().map(|run| run.text.as_str()).collect();
+        return Some(Block::Code(code));
+    }
+
+    let text = render_runs(&paragraph.runs).trim().to_string();
+    if text.is_empty() {
+        return None;
+    }
+
+    let heading_level = if style_name == "title" {
+        Some(1)
+    } else {
+        style_name
+            .strip_prefix("heading")
+            .and_then(|level| level.trim().parse::<usize>().ok())
+            .or(paragraph.outline_level.map(|level| level + 1))
+    };
+    if let Some(level) = heading_level.filter(|level| (1..=6).contains(level)) {
+        return Some(Block::Heading(level, text));
+    }
+
+    if let Some((level, id)) = &paragraph.list {
+        let ordered = parts.ordered_lists.get(id)
+            .and_then(|levels| levels.get(*level).copied())
+            .unwrap_or(false);
+        return Some(Block::ListItem { level: *level, ordered, text });
+    }
+    if style_name.starts_with("list bullet") || style_name.starts_with("list number") {
+        return Some(Block::ListItem { level: 0, ordered: style_name.starts_with("list number"), text });
+    }
+
+    Some(Block::Paragraph(text))
+}
+
+fn render_blocks(blocks: &[Block]) -> String {
+    let mut output = String::new();
+    let mut previous: Option<&Block> = None;
+
+    for block in blocks {
+        // List items and code lines continue the block before them
+        let separator = match (previous, block) {
+            (None, _) => "",
+            (Some(Block::ListItem { .. }), Block::ListItem { .. }) => "\n",
+            (Some(Block::Code(_)), Block::Code(_)) => "\n",
+            _ => "\n\n",
+        };
+        if matches!(previous, Some(Block::Code(_))) && !matches!(block, Block::Code(_)) {
+            output.push_str("\n```");
+        }
+        output.push_str(separator);
+
+        match block {
+            Block::Paragraph(text) => output.push_str(text),
+            Block::Heading(level, text) => output.push_str(&format!("{} {}", "#".repeat(*level), text)),
+            Block::ListItem { level, ordered, text } => {
+                let bullet = if *ordered { "1." } else { "-" };
+                output.push_str(&format!("{}{} {}", "    ".repeat(*level), bullet, text));
+            }
+            Block::Code(code) => {
+                if !matches!(previous, Some(Block::Code(_))) {
+                    output.push_str("```\n");
+                }
+                output.push_str(code);
+            }
+            Block::Table(rows) => output.push_str(&render_table(rows)),
+        }
+        previous = Some(block);
+    }
+
+    if matches!(previous, Some(Block::Code(_))) {
+        output.push_str("\n```");
+    }
+    output.push('\n');
+    output
+}
+
+/// The first row becomes the header, as Word has no separate header concept
+fn render_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0).max(1);
+    let line = |row: &[String]| {
+        let cells: Vec<&str> = (0..columns).map(|i| row.get(i).map_or("", String::as_str)).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![line(&rows[0]), format!("|{}", " --- |".repeat(columns))];
+    lines.extend(rows[1..].iter().map(|row| line(row)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"
+            xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"
+            xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main">
+  <w:body>
+    <w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Quarterly Report</w:t></w:r></w:p>
+    <w:p>
+      <w:r><w:t xml:space="preserve">Revenue is </w:t></w:r>
+      <w:r><w:rPr><w:b/></w:rPr><w:t>up</w:t></w:r>
+      <w:r><w:t xml:space="preserve"> and see </w:t></w:r>
+      <w:hyperlink r:id="rId2"><w:r><w:t>details</w:t></w:r></w:hyperlink>
+    </w:p>
+    <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>First</w:t></w:r></w:p>
+    <w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Nested</w:t></w:r></w:p>
+    <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="2"/></w:numPr></w:pPr><w:r><w:t>Step</w:t></w:r></w:p>
+    <w:tbl>
+      <w:tr><w:tc><w:p><w:r><w:t>Region</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Sales</w:t></w:r></w:p></w:tc></w:tr>
+      <w:tr><w:tc><w:p><w:r><w:t>EU</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>10</w:t></w:r></w:p></w:tc></w:tr>
+    </w:tbl>
+    <w:p><w:r><w:drawing><a:blip r:embed="rId3"/></w:drawing></w:r></w:p>
+  </w:body>
+</w:document>"#;
+
+    const RELS: &str = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Id="rId2" Type="hyperlink" Target="https://example.com/q3" TargetMode="External"/>
+  <Relationship Id="rId3" Type="image" Target="media/image1.png"/>
+</Relationships>"#;
+
+    const NUMBERING: &str = r#"<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:numFmt w:val="bullet"/></w:lvl><w:lvl w:ilvl="1"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum>
+  <w:abstractNum w:abstractNumId="1"><w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl></w:abstractNum>
+  <w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+  <w:num w:numId="2"><w:abstractNumId w:val="1"/></w:num>
+</w:numbering>"#;
+
+    fn build_docx() -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in [
+            ("word/document.xml", DOCUMENT.as_bytes()),
+            ("word/_rels/document.xml.rels", RELS.as_bytes()),
+            ("word/numbering.xml", NUMBERING.as_bytes()),
+            ("word/media/image1.png", b"png-bytes".as_slice()),
+        ] {
+            zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_convert_docx() {
+        let converted = convert_docx(&build_docx(), "assets").unwrap();
+
+        assert_eq!(
+            converted.markdown,
+            "# Quarterly Report\n\n\
+             Revenue is **up** and see [details](https://example.com/q3)\n\n\
+             - First\n    - Nested\n1. Step\n\n\
+             | Region | Sales |\n| --- | --- |\n| EU | 10 |\n\n\
+             ![image1.png](assets/image1.png)\n"
+        );
+        assert_eq!(converted.images, vec![("image1.png".to_string(), b"png-bytes".to_vec())]);
+    }
+
+    #[test]
+    fn test_render_runs_keeps_spaces_outside_emphasis() {
+        let runs = vec![
+            Run { text: "Plain ".to_string(), style: RunStyle::default(), literal: false },
+            Run { text: "bold ".to_string(), style: RunStyle { bold: true, ..RunStyle::default() }, literal: false },
+            Run { text: "text*".to_string(), style: RunStyle::default(), literal: false },
+        ];
+
+        assert_eq!(render_runs(&runs), "Plain **bold** text\\*");
+    }
+}
@@ -0,0 +1,774 @@
+use anyhow::{Result, Context};
+use ego_tree::NodeRef;
+use percent_encoding::percent_decode_str;
+use scraper::{ElementRef, Html, Node};
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use tracing::{debug, warn};
+
+use crate::bidi::TextDirection;
+use crate::export::ExportOptions;
+use crate::image_size::{image_data_dimensions, read_image_source};
+
+const TWIPS_PER_INCH: f32 = 1440.0;
+const EMUS_PER_INCH: f32 = 914_400.0;
+/// Images without a readable size are taken to be 96 DPI
+const EMUS_PER_PIXEL: u64 = 9525;
+/// Word only lists this many levels
+const MAX_LIST_LEVEL: usize = 8;
+
+/// Numbering instance shared by every bulleted list
+const BULLET_NUM_ID: u32 = 1;
+
+const NAMESPACES: &str = concat!(
+    "xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" ",
+    "xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" ",
+    "xmlns:wp=\"http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing\" ",
+    "xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" ",
+    "xmlns:pic=\"http://schemas.openxmlformats.org/drawingml/2006/picture\"",
+);
+
+/// Build a Word document from rendered document HTML
+///
+/// Headings (with bookmarks for in-document links), paragraphs, nested lists,
+/// tables, code blocks, block quotes, links and images become native Word
+/// content using the styles in `styles.xml`, so the file can be restyled in
+/// Word. Local and embedded images are included; remote ones are left out
+/// with their alt text in their place. Relative image paths are resolved
+/// against `options.base_dir`.
+pub fn html_to_docx(html: &str, options: &ExportOptions) -> Result<Vec<u8>> {
+    let fragment = Html::parse_fragment(html);
+    let mut writer = DocxWriter::new(options);
+    writer.children(*fragment.root_element(), RunStyle::default());
+    writer.finish_paragraph();
+    debug!("Built DOCX with {} images and {} links", writer.media.len(), writer.relationships.len());
+    writer.package()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct RunStyle {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    code: bool,
+    superscript: bool,
+    subscript: bool,
+    link: bool,
+}
+
+struct Paragraph {
+    properties: String,
+    content: String,
+    /// Whitespace is collapsed as in HTML, except in code blocks
+    last_was_space: bool,
+}
+
+/// The list items being written, innermost last
+struct ListLevel {
+    num_id: u32,
+    /// The item's first paragraph carries its number or bullet
+    item_started: bool,
+}
+
+struct DocxWriter<'a> {
+    options: &'a ExportOptions,
+    body: String,
+    paragraph: Option<Paragraph>,
+    lists: Vec<ListLevel>,
+    /// `(numId, start)` of every numbered list
+    ordered_lists: Vec<(u32, u32)>,
+    quote_depth: usize,
+    /// Paragraph alignment inside table cells
+    alignment: Option<&'static str>,
+    /// `(id, type, target, external)`
+    relationships: Vec<(String, &'static str, String, bool)>,
+    media: Vec<(String, Vec<u8>)>,
+    images: HashMap<String, Option<(String, u32, u32)>>,
+    bookmarks: u32,
+    drawings: u32,
+}
+
+impl<'a> DocxWriter<'a> {
+    fn new(options: &'a ExportOptions) -> Self {
+        Self {
+            options,
+            body: String::new(),
+            paragraph: None,
+            lists: Vec::new(),
+            ordered_lists: Vec::new(),
+            quote_depth: 0,
+            alignment: None,
+            relationships: Vec::new(),
+            media: Vec::new(),
+            images: HashMap::new(),
+            bookmarks: 0,
+            drawings: 0,
+        }
+    }
+
+    fn children(&mut self, node: NodeRef<Node>, style: RunStyle) {
+        for child in node.children() {
+            self.node(child, style);
+        }
+    }
+
+    fn node(&mut self, node: NodeRef<Node>, style: RunStyle) {
+        match node.value() {
+            Node::Text(text) => self.text(text, style),
+            Node::Element(_) => {
+                if let Some(element) = ElementRef::wrap(node) {
+                    self.element(element, style);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn element(&mut self, element: ElementRef, mut style: RunStyle) {
+        let name = element.value().name();
+        let has_class = |class: &str| element.value().classes().any(|c| c == class);
+        match name {
+            "script" | "style" | "template" | "svg" | "button" => {}
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.finish_paragraph();
+                self.start_paragraph(Some(&format!("Heading{}", &name[1..])));
+                let bookmark = element.value().attr("id").map(|id| {
+                    self.bookmarks += 1;
+                    (self.bookmarks, bookmark_name(id))
+                });
+                if let Some((id, name)) = &bookmark {
+                    self.push_xml(&format!("<w:bookmarkStart w:id=\"{}\" w:name=\"{}\"/>", id, escape(name)));
+                }
+                self.children(*element, style);
+                if let Some((id, _)) = bookmark {
+                    self.push_xml(&format!("<w:bookmarkEnd w:id=\"{}\"/>", id));
+                }
+                self.finish_paragraph();
+            }
+            "p" => {
+                self.finish_paragraph();
+                self.children(*element, style);
+                self.finish_paragraph();
+            }
+            "ul" | "ol" => self.list(element, style),
+            "pre" => self.code_block(element),
+            "blockquote" => {
+                self.finish_paragraph();
+                self.quote_depth += 1;
+                self.children(*element, style);
+                self.finish_paragraph();
+                self.quote_depth -= 1;
+            }
+            "table" => self.table(element),
+            "hr" => {
+                self.finish_paragraph();
+                self.body.push_str("<w:p><w:pPr><w:pBdr><w:bottom w:val=\"single\" w:sz=\"6\" w:space=\"1\" w:color=\"auto\"/></w:pBdr></w:pPr></w:p>");
+            }
+            "br" => self.push_xml("<w:r><w:br/></w:r>"),
+            "img" => self.image(element),
+            "input" if element.value().attr("type") == Some("checkbox") => {
+                let checked = element.value().attr("checked").is_some();
+                self.text(if checked { "\u{2612} " } else { "\u{2610} " }, style);
+            }
+            "span" if has_class("katex-display") => {
+                self.finish_paragraph();
+                self.math(element, style);
+                self.finish_paragraph();
+            }
+            "span" if has_class("katex") => self.math(element, style),
+            "div" | "section" | "article" | "header" | "footer" | "main" | "nav" | "aside" | "figure"
+            | "figcaption" | "details" | "summary" | "dl" | "dt" | "dd" | "li" => {
+                self.finish_paragraph();
+                self.children(*element, style);
+                self.finish_paragraph();
+            }
+            "a" => self.link(element, style),
+            _ => {
+                match name {
+                    "strong" | "b" => style.bold = true,
+                    "em" | "i" | "cite" => style.italic = true,
+                    "del" | "s" | "strike" => style.strike = true,
+                    "code" | "kbd" | "samp" => style.code = true,
+                    "sup" => style.superscript = true,
+                    "sub" => style.subscript = true,
+                    _ => {}
+                }
+                self.children(*element, style);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str, style: RunStyle) {
+        if self.paragraph.is_none() && text.trim().is_empty() {
+            return;
+        }
+        let paragraph = self.paragraph();
+        let mut collapsed = String::with_capacity(text.len());
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !paragraph.last_was_space {
+                    collapsed.push(' ');
+                }
+                paragraph.last_was_space = true;
+            } else {
+                collapsed.push(c);
+                paragraph.last_was_space = false;
+            }
+        }
+        if !collapsed.is_empty() {
+            paragraph.content.push_str(&run(&collapsed, &style));
+        }
+    }
+
+    fn list(&mut self, list: ElementRef, style: RunStyle) {
+        self.finish_paragraph();
+        let num_id = if list.value().name() == "ol" {
+            let num_id = BULLET_NUM_ID + 1 + self.ordered_lists.len() as u32;
+            let start = list.value().attr("start").and_then(|start| start.parse().ok()).unwrap_or(1);
+            self.ordered_lists.push((num_id, start));
+            num_id
+        } else {
+            BULLET_NUM_ID
+        };
+
+        self.lists.push(ListLevel { num_id, item_started: false });
+        for item in list.children() {
+            match ElementRef::wrap(item) {
+                Some(element) if element.value().name() == "li" => {
+                    self.finish_paragraph();
+                    if let Some(level) = self.lists.last_mut() {
+                        level.item_started = false;
+                    }
+                    self.children(item, style);
+                    self.finish_paragraph();
+                }
+                _ => self.node(item, style),
+            }
+        }
+        self.lists.pop();
+    }
+
+    fn code_block(&mut self, pre: ElementRef) {
+        self.finish_paragraph();
+        let code: String = pre.text().collect();
+        let style = RunStyle { code: true, ..RunStyle::default() };
+        for line in code.trim_end_matches('\n').split('\n') {
+            self.start_paragraph(Some("Code"));
+            let line = line.trim_end_matches('\r');
+            if !line.is_empty() {
+                self.push_xml(&run(line, &style));
+            }
+            self.finish_paragraph();
+        }
+    }
+
+    /// KaTeX output, as its TeX source
+    fn math(&mut self, element: ElementRef, style: RunStyle) {
+        let tex = element.descendants()
+            .filter_map(ElementRef::wrap)
+            .find(|child| child.value().name() == "annotation")
+            .map(|annotation| annotation.text().collect::<String>());
+        match tex {
+            Some(tex) => self.text(tex.trim(), RunStyle { italic: true, ..style }),
+            None => self.children(*element, style),
+        }
+    }
+
+    fn link(&mut self, link: ElementRef, style: RunStyle) {
+        let href = link.value().attr("href").unwrap_or_default();
+        let target = if let Some(anchor) = href.strip_prefix('#') {
+            Some(format!("w:anchor=\"{}\"", escape(&bookmark_name(&percent_decode_str(anchor).decode_utf8_lossy()))))
+        } else if !href.is_empty() {
+            let id = self.relationship("hyperlink", href.to_string(), true);
+            Some(format!("r:id=\"{}\"", id))
+        } else {
+            None
+        };
+
+        match target {
+            Some(target) => {
+                self.paragraph();
+                self.push_xml(&format!("<w:hyperlink {} w:history=\"1\">", target));
+                self.children(*link, RunStyle { link: true, ..style });
+                self.push_xml("</w:hyperlink>");
+            }
+            None => self.children(*link, style),
+        }
+    }
+
+    fn table(&mut self, table: ElementRef) {
+        self.finish_paragraph();
+        let rows: Vec<ElementRef> = table.descendants()
+            .filter_map(ElementRef::wrap)
+            .filter(|element| element.value().name() == "tr")
+            .collect();
+        let columns = rows.iter()
+            .map(|row| row.children().filter_map(ElementRef::wrap).filter(|cell| is_cell(cell)).count())
+            .max()
+            .unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+
+        // Laid out by Word, but the grid must be there
+        let column_width = (self.text_width() * TWIPS_PER_INCH) as u32 / columns as u32;
+        let mut xml = String::from("<w:tbl><w:tblPr><w:tblStyle w:val=\"TableGrid\"/><w:tblW w:w=\"0\" w:type=\"auto\"/></w:tblPr><w:tblGrid>");
+        for _ in 0..columns {
+            xml.push_str(&format!("<w:gridCol w:w=\"{}\"/>", column_width));
+        }
+        xml.push_str("</w:tblGrid>");
+
+        let outer_body = std::mem::take(&mut self.body);
+        let outer_lists = std::mem::take(&mut self.lists);
+        let outer_quote = std::mem::replace(&mut self.quote_depth, 0);
+        for row in rows {
+            let cells: Vec<ElementRef> = row.children().filter_map(ElementRef::wrap).filter(is_cell).collect();
+            let header = cells.iter().all(|cell| cell.value().name() == "th");
+            xml.push_str(if header { "<w:tr><w:trPr><w:tblHeader/></w:trPr>" } else { "<w:tr>" });
+            for index in 0..columns {
+                xml.push_str("<w:tc><w:tcPr><w:tcW w:w=\"0\" w:type=\"auto\"/></w:tcPr>");
+                if let Some(cell) = cells.get(index) {
+                    self.alignment = cell_alignment(cell);
+                    let style = RunStyle { bold: header, ..RunStyle::default() };
+                    self.children(**cell, style);
+                    self.finish_paragraph();
+                    self.alignment = None;
+                }
+                let content = std::mem::take(&mut self.body);
+                // Every cell needs a paragraph, even an empty one
+                xml.push_str(if content.is_empty() { "<w:p/>" } else { &content });
+                xml.push_str("</w:tc>");
+            }
+            xml.push_str("</w:tr>");
+        }
+        xml.push_str("</w:tbl>");
+
+        self.body = outer_body;
+        self.lists = outer_lists;
+        self.quote_depth = outer_quote;
+        self.body.push_str(&xml);
+        // Word merges tables that touch, so keep a paragraph between them
+        self.body.push_str("<w:p/>");
+    }
+
+    fn image(&mut self, image: ElementRef) {
+        let src = image.value().attr("src").unwrap_or_default().to_string();
+        let alt = image.value().attr("alt").unwrap_or_default().to_string();
+        if !self.images.contains_key(&src) {
+            let embedded = self.load_image(&src);
+            self.images.insert(src.clone(), embedded);
+        }
+        let Some((rel_id, width, height)) = self.images[&src].clone() else {
+            if !alt.is_empty() {
+                self.text(&format!("[{}]", alt), RunStyle { italic: true, ..RunStyle::default() });
+            }
+            return;
+        };
+
+        // Sized as in the page: the width and height attributes, else the image's own
+        // pixels, scaled down to fit between the margins
+        let attribute = |name: &str| image.value().attr(name).and_then(|value| value.trim_end_matches("px").parse::<u32>().ok());
+        let (width, height) = match (attribute("width"), attribute("height")) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, (w as u64 * height as u64 / width.max(1) as u64) as u32),
+            _ => (width, height),
+        };
+        let mut cx = width as u64 * EMUS_PER_PIXEL;
+        let mut cy = height as u64 * EMUS_PER_PIXEL;
+        let max_width = (self.text_width() * EMUS_PER_INCH) as u64;
+        if cx > max_width {
+            cy = cy * max_width / cx;
+            cx = max_width;
+        }
+
+        self.drawings += 1;
+        let id = self.drawings;
+        self.paragraph();
+        self.push_xml(&format!(
+            "<w:r><w:drawing><wp:inline distT=\"0\" distB=\"0\" distL=\"0\" distR=\"0\">\
+             <wp:extent cx=\"{cx}\" cy=\"{cy}\"/><wp:docPr id=\"{id}\" name=\"Picture {id}\" descr=\"{alt}\"/>\
+             <wp:cNvGraphicFramePr><a:graphicFrameLocks noChangeAspect=\"1\"/></wp:cNvGraphicFramePr>\
+             <a:graphic><a:graphicData uri=\"http://schemas.openxmlformats.org/drawingml/2006/picture\">\
+             <pic:pic><pic:nvPicPr><pic:cNvPr id=\"{id}\" name=\"Picture {id}\"/><pic:cNvPicPr/></pic:nvPicPr>\
+             <pic:blipFill><a:blip r:embed=\"{rel_id}\"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill>\
+             <pic:spPr><a:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"{cx}\" cy=\"{cy}\"/></a:xfrm>\
+             <a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></pic:spPr></pic:pic>\
+             </a:graphicData></a:graphic></wp:inline></w:drawing></w:r>",
+            cx = cx, cy = cy, id = id, alt = escape(&alt), rel_id = rel_id,
+        ));
+        if let Some(paragraph) = &mut self.paragraph {
+            paragraph.last_was_space = false;
+        }
+    }
+
+    /// Add the image at `src` to the package: its relationship id and pixel size
+    fn load_image(&mut self, src: &str) -> Option<(String, u32, u32)> {
+        let data = match read_image_source(src, self.options.base_dir.as_deref()) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Leaving image {} out of the DOCX export: {:#}", truncated(src), e);
+                return None;
+            }
+        };
+
+        let Some(extension) = image_extension(&data) else {
+            warn!("Leaving image {} out of the DOCX export: Word can't show its format", truncated(src));
+            return None;
+        };
+        let (width, height) = image_data_dimensions(&data).unwrap_or((300, 200));
+        let name = format!("image{}.{}", self.media.len() + 1, extension);
+        let id = self.relationship("image", format!("media/{}", name), false);
+        self.media.push((name, data));
+        Some((id, width.max(1), height.max(1)))
+    }
+
+    fn relationship(&mut self, kind: &'static str, target: String, external: bool) -> String {
+        // rId1 and rId2 are the styles and numbering parts
+        let id = format!("rId{}", self.relationships.len() + 3);
+        self.relationships.push((id.clone(), kind, target, external));
+        id
+    }
+
+    /// The paragraph being written, started with the context's style if there is none
+    fn paragraph(&mut self) -> &mut Paragraph {
+        if self.paragraph.is_none() {
+            self.start_paragraph(None);
+        }
+        self.paragraph.as_mut().unwrap()
+    }
+
+    fn start_paragraph(&mut self, style: Option<&str>) {
+        let mut properties = String::new();
+        let in_list = !self.lists.is_empty();
+        let style = style.or(if self.quote_depth > 0 {
+            Some("Quote")
+        } else if in_list {
+            Some("ListParagraph")
+        } else {
+            None
+        });
+        if let Some(style) = style {
+            properties.push_str(&format!("<w:pStyle w:val=\"{}\"/>", style));
+        }
+        let level = self.lists.len().saturating_sub(1).min(MAX_LIST_LEVEL);
+        if let Some(list) = self.lists.last_mut().filter(|list| !list.item_started) {
+            list.item_started = true;
+            properties.push_str(&format!("<w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>", level, list.num_id));
+        } else if in_list {
+            // Later paragraphs of an item line up with its text
+            properties.push_str(&format!("<w:ind w:left=\"{}\"/>", 720 * (level + 1)));
+        }
+        if self.options.direction == Some(TextDirection::Rtl) {
+            properties.push_str("<w:bidi/>");
+        }
+        if let Some(alignment) = self.alignment {
+            properties.push_str(&format!("<w:jc w:val=\"{}\"/>", alignment));
+        }
+        self.paragraph = Some(Paragraph { properties, content: String::new(), last_was_space: true });
+    }
+
+    fn push_xml(&mut self, xml: &str) {
+        self.paragraph().content.push_str(xml);
+    }
+
+    fn finish_paragraph(&mut self) {
+        let Some(paragraph) = self.paragraph.take() else { return };
+        self.body.push_str("<w:p>");
+        if !paragraph.properties.is_empty() {
+            self.body.push_str(&format!("<w:pPr>{}</w:pPr>", paragraph.properties));
+        }
+        self.body.push_str(&paragraph.content);
+        self.body.push_str("</w:p>");
+    }
+
+    /// Page width between the margins, in inches
+    fn text_width(&self) -> f32 {
+        let (width, _) = self.options.page_size.dimensions();
+        (width - self.options.margins.left - self.options.margins.right).max(1.0)
+    }
+
+    fn section_properties(&self) -> String {
+        let (width, height) = self.options.page_size.dimensions();
+        let twips = |inches: f32| (inches * TWIPS_PER_INCH).round() as u32;
+        let margins = &self.options.margins;
+        format!(
+            "<w:sectPr><w:pgSz w:w=\"{}\" w:h=\"{}\"/><w:pgMar w:top=\"{}\" w:right=\"{}\" w:bottom=\"{}\" w:left=\"{}\" w:header=\"720\" w:footer=\"720\" w:gutter=\"0\"/></w:sectPr>",
+            twips(width), twips(height), twips(margins.top), twips(margins.right), twips(margins.bottom), twips(margins.left),
+        )
+    }
+
+    /// Zip the parts into a `.docx` package
+    fn package(self) -> Result<Vec<u8>> {
+        let document = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:document {}><w:body>{}{}</w:body></w:document>",
+            NAMESPACES, self.body, self.section_properties(),
+        );
+
+        let mut relationships = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+            "<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" Target=\"styles.xml\"/>",
+            "<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering\" Target=\"numbering.xml\"/>",
+        ));
+        for (id, kind, target, external) in &self.relationships {
+            relationships.push_str(&format!(
+                "<Relationship Id=\"{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/{}\" Target=\"{}\"{}/>",
+                id, kind, escape(target), if *external { " TargetMode=\"External\"" } else { "" },
+            ));
+        }
+        relationships.push_str("</Relationships>");
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let parts = [
+            ("[Content_Types].xml", CONTENT_TYPES.to_string()),
+            ("_rels/.rels", PACKAGE_RELATIONSHIPS.to_string()),
+            ("word/document.xml", document),
+            ("word/_rels/document.xml.rels", relationships),
+            ("word/styles.xml", STYLES.to_string()),
+            ("word/numbering.xml", numbering(&self.ordered_lists)),
+        ];
+        for (name, content) in parts {
+            zip.start_file(name, options).with_context(|| format!("Failed to add {} to the DOCX", name))?;
+            zip.write_all(content.as_bytes())?;
+        }
+        for (name, data) in &self.media {
+            zip.start_file(format!("word/media/{}", name), options)?;
+            zip.write_all(data)?;
+        }
+        Ok(zip.finish().context("Failed to write the DOCX package")?.into_inner())
+    }
+}
+
+fn run(text: &str, style: &RunStyle) -> String {
+    let mut properties = String::new();
+    if style.code {
+        properties.push_str("<w:rStyle w:val=\"CodeChar\"/>");
+    } else if style.link {
+        properties.push_str("<w:rStyle w:val=\"Hyperlink\"/>");
+    }
+    if style.bold {
+        properties.push_str("<w:b/>");
+    }
+    if style.italic {
+        properties.push_str("<w:i/>");
+    }
+    if style.strike {
+        properties.push_str("<w:strike/>");
+    }
+    if style.superscript {
+        properties.push_str("<w:vertAlign w:val=\"superscript\"/>");
+    } else if style.subscript {
+        properties.push_str("<w:vertAlign w:val=\"subscript\"/>");
+    }
+    let properties = if properties.is_empty() { properties } else { format!("<w:rPr>{}</w:rPr>", properties) };
+    format!("<w:r>{}<w:t xml:space=\"preserve\">{}</w:t></w:r>", properties, escape(text))
+}
+
+fn is_cell(element: &ElementRef) -> bool {
+    matches!(element.value().name(), "td" | "th")
+}
+
+/// Word alignment of a cell from its `text-align` style or `align` attribute
+fn cell_alignment(cell: &ElementRef) -> Option<&'static str> {
+    let style = cell.value().attr("style").unwrap_or_default();
+    let align = cell.value().attr("align").unwrap_or_default();
+    ["center", "right"].into_iter()
+        .find(|value| align == *value || style.contains(&format!("text-align: {}", value)))
+        .map(|value| if value == "right" { "right" } else { "center" })
+}
+
+/// Word bookmark names allow only letters, digits and underscores, and at
+/// most 40 of them; the leading underscore keeps them out of Word's bookmark list
+fn bookmark_name(id: &str) -> String {
+    let name: String = id.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+    format!("_{}", name).chars().take(40).collect()
+}
+
+/// Extensions of the image formats Word can show
+fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        Some("jpeg")
+    } else if data.starts_with(b"GIF8") {
+        Some("gif")
+    } else if data.starts_with(b"BM") {
+        Some("bmp")
+    } else {
+        None
+    }
+}
+
+fn truncated(src: &str) -> &str {
+    src.char_indices().nth(80).map_or(src, |(at, _)| &src[..at])
+}
+
+fn escape(text: &str) -> String {
+    html_escape::encode_double_quoted_attribute(text).replace('\'', "&apos;")
+}
+
+/// Bullets for every list level, and a numbered list definition with one
+/// instance per list so each restarts at its own start number
+fn numbering(ordered_lists: &[(u32, u32)]) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:numbering {}>",
+        NAMESPACES,
+    );
+    for (abstract_id, ordered) in [(0, false), (1, true)] {
+        xml.push_str(&format!("<w:abstractNum w:abstractNumId=\"{}\"><w:multiLevelType w:val=\"hybridMultilevel\"/>", abstract_id));
+        for level in 0..=MAX_LIST_LEVEL {
+            let (format, text) = if ordered {
+                ("decimal", format!("%{}.", level + 1))
+            } else {
+                ("bullet", ["\u{2022}", "\u{25E6}", "\u{25AA}"][level % 3].to_string())
+            };
+            xml.push_str(&format!(
+                "<w:lvl w:ilvl=\"{}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{}\"/><w:lvlText w:val=\"{}\"/><w:lvlJc w:val=\"left\"/>\
+                 <w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                level, format, text, 720 * (level + 1),
+            ));
+        }
+        xml.push_str("</w:abstractNum>");
+    }
+    xml.push_str(&format!("<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"0\"/></w:num>", BULLET_NUM_ID));
+    for (num_id, start) in ordered_lists {
+        xml.push_str(&format!("<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"1\"/>", num_id));
+        for level in 0..=MAX_LIST_LEVEL {
+            xml.push_str(&format!("<w:lvlOverride w:ilvl=\"{}\"><w:startOverride w:val=\"{}\"/></w:lvlOverride>", level, start));
+        }
+        xml.push_str("</w:num>");
+    }
+    xml.push_str("</w:numbering>");
+    xml
+}
+
+const CONTENT_TYPES: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">",
+    "<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>",
+    "<Default Extension=\"xml\" ContentType=\"application/xml\"/>",
+    "<Default Extension=\"png\" ContentType=\"image/png\"/>",
+    "<Default Extension=\"jpeg\" ContentType=\"image/jpeg\"/>",
+    "<Default Extension=\"gif\" ContentType=\"image/gif\"/>",
+    "<Default Extension=\"bmp\" ContentType=\"image/bmp\"/>",
+    "<Override PartName=\"/word/document.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml\"/>",
+    "<Override PartName=\"/word/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml\"/>",
+    "<Override PartName=\"/word/numbering.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml\"/>",
+    "</Types>",
+);
+
+const PACKAGE_RELATIONSHIPS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    "<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"word/document.xml\"/>",
+    "</Relationships>",
+);
+
+const STYLES: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<w:styles xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">",
+    "<w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii=\"Calibri\" w:hAnsi=\"Calibri\" w:eastAsia=\"Calibri\" w:cs=\"Calibri\"/>",
+    "<w:sz w:val=\"22\"/><w:szCs w:val=\"22\"/></w:rPr></w:rPrDefault>",
+    "<w:pPrDefault><w:pPr><w:spacing w:after=\"160\" w:line=\"276\" w:lineRule=\"auto\"/></w:pPr></w:pPrDefault></w:docDefaults>",
+    "<w:style w:type=\"paragraph\" w:default=\"1\" w:styleId=\"Normal\"><w:name w:val=\"Normal\"/><w:qFormat/></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading1\"><w:name w:val=\"heading 1\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"360\" w:after=\"120\"/><w:outlineLvl w:val=\"0\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"36\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading2\"><w:name w:val=\"heading 2\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"300\" w:after=\"120\"/><w:outlineLvl w:val=\"1\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"30\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading3\"><w:name w:val=\"heading 3\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"80\"/><w:outlineLvl w:val=\"2\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"26\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading4\"><w:name w:val=\"heading 4\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"80\"/><w:outlineLvl w:val=\"3\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"24\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading5\"><w:name w:val=\"heading 5\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"200\" w:after=\"80\"/><w:outlineLvl w:val=\"4\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"22\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading6\"><w:name w:val=\"heading 6\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"200\" w:after=\"80\"/><w:outlineLvl w:val=\"5\"/></w:pPr><w:rPr><w:b/><w:i/><w:sz w:val=\"22\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Quote\"><w:name w:val=\"Quote\"/><w:basedOn w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:pBdr><w:left w:val=\"single\" w:sz=\"18\" w:space=\"8\" w:color=\"D0D7DE\"/></w:pBdr><w:ind w:left=\"360\"/></w:pPr>",
+    "<w:rPr><w:color w:val=\"57606A\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"ListParagraph\"><w:name w:val=\"List Paragraph\"/><w:basedOn w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:spacing w:after=\"60\"/><w:ind w:left=\"720\"/><w:contextualSpacing/></w:pPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Code\"><w:name w:val=\"Code\"/><w:basedOn w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F6F8FA\"/><w:spacing w:after=\"0\" w:line=\"240\" w:lineRule=\"auto\"/><w:contextualSpacing/></w:pPr>",
+    "<w:rPr><w:rFonts w:ascii=\"Consolas\" w:hAnsi=\"Consolas\" w:cs=\"Consolas\"/><w:sz w:val=\"19\"/></w:rPr></w:style>",
+    "<w:style w:type=\"character\" w:styleId=\"CodeChar\"><w:name w:val=\"Code Char\"/>",
+    "<w:rPr><w:rFonts w:ascii=\"Consolas\" w:hAnsi=\"Consolas\" w:cs=\"Consolas\"/><w:sz w:val=\"19\"/><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F0F0F0\"/></w:rPr></w:style>",
+    "<w:style w:type=\"character\" w:styleId=\"Hyperlink\"><w:name w:val=\"Hyperlink\"/><w:rPr><w:color w:val=\"0366D6\"/><w:u w:val=\"single\"/></w:rPr></w:style>",
+    "<w:style w:type=\"table\" w:styleId=\"TableGrid\"><w:name w:val=\"Table Grid\"/><w:tblPr><w:tblBorders>",
+    "<w:top w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/><w:left w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/>",
+    "<w:bottom w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/><w:right w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/>",
+    "<w:insideH w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/><w:insideV w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/>",
+    "</w:tblBorders><w:tblCellMar><w:left w:w=\"108\" w:type=\"dxa\"/><w:right w:w=\"108\" w:type=\"dxa\"/></w:tblCellMar></w:tblPr></w:style>",
+    "</w:styles>",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    fn part(docx: &[u8], name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(docx)).unwrap();
+        let mut content = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_html_to_docx_blocks() {
+        let html = "<h1 id=\"intro-2\">Intro &amp; <em>more</em></h1>\n<p>See <a href=\"https://example.com/?a=1&amp;b=2\">the site</a> or <a href=\"#intro-2\">the top</a>,\n  with <code>code</code>.</p>\n\
+            <ol start=\"3\"><li>three<ul><li>nested</li></ul></li><li><p>four</p><p>more</p></li></ol>\n\
+            <pre><code class=\"language-rust\"><span>fn main() {</span>\n    <span>x</span>\n}\n</code></pre>\n\
+            <table><thead><tr><th>Name</th><th style=\"text-align: right\">Qty</th></tr></thead><tbody><tr><td>Tea</td></tr></tbody></table>";
+        let docx = html_to_docx(html, &ExportOptions::default()).unwrap();
+
+        let document = part(&docx, "word/document.xml");
+        assert!(document.contains("<w:pStyle w:val=\"Heading1\"/></w:pPr><w:bookmarkStart w:id=\"1\" w:name=\"_intro_2\"/><w:r><w:t xml:space=\"preserve\">Intro &amp; </w:t></w:r><w:r><w:rPr><w:i/></w:rPr><w:t xml:space=\"preserve\">more</w:t></w:r><w:bookmarkEnd w:id=\"1\"/>"));
+        assert!(document.contains("<w:hyperlink r:id=\"rId3\" w:history=\"1\"><w:r><w:rPr><w:rStyle w:val=\"Hyperlink\"/></w:rPr><w:t xml:space=\"preserve\">the site</w:t></w:r></w:hyperlink>"));
+        assert!(document.contains("<w:hyperlink w:anchor=\"_intro_2\""));
+        assert!(document.contains("<w:t xml:space=\"preserve\">, with </w:t>"));
+        assert!(document.contains("<w:numPr><w:ilvl w:val=\"0\"/><w:numId w:val=\"2\"/></w:numPr></w:pPr><w:r><w:t xml:space=\"preserve\">three</w:t>"));
+        assert!(document.contains("<w:numPr><w:ilvl w:val=\"1\"/><w:numId w:val=\"1\"/></w:numPr></w:pPr><w:r><w:t xml:space=\"preserve\">nested</w:t>"));
+        assert!(document.contains("<w:ind w:left=\"720\"/></w:pPr><w:r><w:t xml:space=\"preserve\">more</w:t>"));
+        assert!(document.contains("<w:pStyle w:val=\"Code\"/></w:pPr><w:r><w:rPr><w:rStyle w:val=\"CodeChar\"/></w:rPr><w:t xml:space=\"preserve\">    x</w:t>"));
+        assert_eq!(document.matches("<w:pStyle w:val=\"Code\"/>").count(), 3);
+        assert!(document.contains("<w:tr><w:trPr><w:tblHeader/></w:trPr>"));
+        assert!(document.contains("<w:jc w:val=\"right\"/></w:pPr><w:r><w:rPr><w:b/></w:rPr><w:t xml:space=\"preserve\">Qty</w:t>"));
+        // The short row is padded to the table's width
+        assert_eq!(document.matches("<w:tc>").count(), 4);
+        assert!(document.contains("<w:pgSz w:w=\"11909\" w:h=\"16834\"/>"));
+
+        let relationships = part(&docx, "word/_rels/document.xml.rels");
+        assert!(relationships.contains("Id=\"rId3\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink\" Target=\"https://example.com/?a=1&amp;b=2\" TargetMode=\"External\""));
+        let numbering = part(&docx, "word/numbering.xml");
+        assert!(numbering.contains("<w:num w:numId=\"2\"><w:abstractNumId w:val=\"1\"/><w:lvlOverride w:ilvl=\"0\"><w:startOverride w:val=\"3\"/>"));
+    }
+
+    #[test]
+    fn test_html_to_docx_images() {
+        let dir = TempDir::new().unwrap();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&2000u32.to_be_bytes());
+        png.extend_from_slice(&1000u32.to_be_bytes());
+        std::fs::create_dir(dir.path().join("img")).unwrap();
+        std::fs::write(dir.path().join("img/wide chart.png"), &png).unwrap();
+
+        let options = ExportOptions { base_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+        let html = "<p><img src=\"img/wide%20chart.png\" alt=\"Chart\"> <img src=\"https://example.com/a.png\" alt=\"Remote\"> <img src=\"img/wide%20chart.png\" alt=\"Again\"></p>";
+        let docx = html_to_docx(html, &options).unwrap();
+
+        let document = part(&docx, "word/document.xml");
+        // Scaled down to the 6.27in between A4's margins, keeping the aspect ratio
+        assert!(document.contains("<wp:extent cx=\"5733288\" cy=\"2866644\"/><wp:docPr id=\"1\" name=\"Picture 1\" descr=\"Chart\"/>"));
+        assert!(document.contains("<w:t xml:space=\"preserve\">[Remote]</w:t>"));
+        assert_eq!(document.matches("r:embed=\"rId3\"").count(), 2);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(&docx[..])).unwrap();
+        let mut embedded = Vec::new();
+        archive.by_name("word/media/image1.png").unwrap().read_to_end(&mut embedded).unwrap();
+        assert_eq!(embedded, png);
+        assert!(archive.by_name("word/media/image2.png").is_err());
+    }
+}
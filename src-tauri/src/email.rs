@@ -0,0 +1,200 @@
+use anyhow::{Result, Context};
+use lettre::message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::settings::{EmailSettings, SmtpSecurity};
+
+/// Keychain entry holding the SMTP password
+pub const SMTP_PASSWORD_KEY: &str = "smtp-password";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum EmailDelivery {
+    /// Open the user's mail client with a prefilled message
+    Mailto,
+    /// Send directly through the configured SMTP server
+    Smtp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailRequest {
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    pub subject: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailResult {
+    pub delivery: EmailDelivery,
+    /// The exported file that was attached (or should be attached by hand)
+    pub attachment: PathBuf,
+    pub mailto_url: Option<String>,
+}
+
+#[derive(Default)]
+pub struct EmailService;
+
+impl EmailService {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Send the message with `attachment` through the configured SMTP server
+    pub async fn send(
+        &self,
+        request: &EmailRequest,
+        attachment: &Path,
+        settings: &EmailSettings,
+        password: Option<String>,
+    ) -> Result<()> {
+        if settings.smtp_host.trim().is_empty() {
+            return Err(anyhow::anyhow!("No SMTP server configured"));
+        }
+
+        let data = tokio::fs::read(attachment).await
+            .with_context(|| format!("Failed to read attachment: {:?}", attachment))?;
+        let message = build_message(request, attachment, data, settings)?;
+
+        let host = settings.smtp_host.trim();
+        let mut transport = match settings.security {
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        if let Some(port) = settings.smtp_port {
+            transport = transport.port(port);
+        }
+        if let Some(password) = password.filter(|p| !p.is_empty()) {
+            let username = if settings.username.is_empty() { &settings.from } else { &settings.username };
+            transport = transport.credentials(Credentials::new(username.clone(), password));
+        }
+
+        debug!("Sending email via {} to {:?}", host, request.to);
+        transport.build().send(message).await
+            .with_context(|| format!("Failed to send email through {}", host))?;
+
+        info!("Emailed {:?} to {} recipients", attachment, request.to.len() + request.cc.len());
+        Ok(())
+    }
+}
+
+fn build_message(request: &EmailRequest, attachment: &Path, data: Vec<u8>, settings: &EmailSettings) -> Result<Message> {
+    let parse = |address: &str| -> Result<Mailbox> {
+        address.trim().parse().with_context(|| format!("Invalid email address: {}", address))
+    };
+
+    let mut builder = Message::builder()
+        .from(parse(&settings.from)?)
+        .subject(request.subject.clone());
+    for address in &request.to {
+        builder = builder.to(parse(address)?);
+    }
+    for address in &request.cc {
+        builder = builder.cc(parse(address)?);
+    }
+
+    let file_name = attachment.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "document".to_string());
+    let content_type = match attachment.extension().and_then(|ext| ext.to_str()) {
+        Some("pdf") => ContentType::parse("application/pdf")?,
+        Some("html") => ContentType::TEXT_HTML,
+        _ => ContentType::parse("application/octet-stream")?,
+    };
+
+    let message = builder.multipart(
+        MultiPart::mixed()
+            .singlepart(SinglePart::plain(request.body.clone()))
+            .singlepart(Attachment::new(file_name).body(data, content_type)),
+    )?;
+    Ok(message)
+}
+
+/// A `mailto:` link prefilled with the request
+///
+/// `mailto:` has no standard way to attach files, so the attachment path is
+/// passed as the `attachment` parameter some clients honour and is otherwise
+/// left for the user to attach.
+pub fn mailto_url(request: &EmailRequest, attachment: Option<&Path>) -> String {
+    let mut params = vec![("subject", request.subject.clone()), ("body", request.body.clone())];
+    if !request.cc.is_empty() {
+        params.push(("cc", request.cc.join(",")));
+    }
+    if let Some(attachment) = attachment {
+        params.push(("attachment", attachment.to_string_lossy().to_string()));
+    }
+
+    let recipients: Vec<String> = request.to.iter().map(|to| encode_component(to.trim())).collect();
+    let query: Vec<String> = params
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, encode_component(&value)))
+        .collect();
+
+    let mut url = format!("mailto:{}", recipients.join(","));
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(&query.join("&"));
+    }
+    url
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters and `@`
+fn encode_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'@' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> EmailRequest {
+        EmailRequest {
+            to: vec!["ada@example.com".to_string()],
+            cc: vec![],
+            subject: "Draft & notes".to_string(),
+            body: "See attached.\nThanks".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_mailto_url() {
+        let url = mailto_url(&request(), Some(Path::new("/tmp/Draft.pdf")));
+
+        assert_eq!(
+            url,
+            "mailto:ada@example.com?subject=Draft%20%26%20notes&body=See%20attached.%0AThanks&attachment=%2Ftmp%2FDraft.pdf"
+        );
+    }
+
+    #[test]
+    fn test_build_message_attaches_file() {
+        let settings = EmailSettings {
+            from: "Me <me@example.com>".to_string(),
+            ..EmailSettings::default()
+        };
+
+        let message = build_message(&request(), Path::new("Draft.pdf"), b"%PDF".to_vec(), &settings).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+
+        assert!(formatted.contains("To: ada@example.com"));
+        assert!(formatted.contains("Content-Type: application/pdf"));
+        assert!(formatted.contains("filename=\"Draft.pdf\""));
+
+        let invalid = EmailRequest { to: vec!["not an address".to_string()], ..request() };
+        assert!(build_message(&invalid, Path::new("Draft.pdf"), Vec::new(), &settings).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use base64::Engine;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::settings::app_config_dir;
+
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+const KEYCAP: char = '\u{20E3}';
+
+/// Folder of emoji images named the way twemoji names them, e.g. `1f600.svg`
+/// or `1f469-200d-1f4bb.png`; twemoji's `assets/svg` folder can be copied in as is
+pub fn emoji_dir() -> PathBuf {
+    app_config_dir().join("emoji")
+}
+
+/// Emoji replaced by images in one pass over a document
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EmojiSubstitution {
+    pub html: String,
+    pub replaced: usize,
+    /// Emoji left as text because the folder has no image for them
+    pub missing: Vec<String>,
+}
+
+/// Replace emoji in the text of `html` with `<img class="emoji">` glyphs from
+/// `dir`, embedded as data URLs so renderers without a color emoji font still
+/// show them. Tags and their attributes are left alone.
+pub async fn substitute_emoji(html: &str, dir: &Path) -> EmojiSubstitution {
+    let mut images: HashMap<String, Option<String>> = HashMap::new();
+    let mut result = EmojiSubstitution { html: String::with_capacity(html.len()), ..Default::default() };
+    let mut in_tag = false;
+    let mut rest = html;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ => {}
+        }
+        let Some(len) = emoji_len(rest).filter(|_| !in_tag) else {
+            result.html.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+        let (piece, after) = rest.split_at(len);
+        rest = after;
+
+        let name = twemoji_name(piece);
+        if !images.contains_key(&name) {
+            let image = load_image(dir, &name).await;
+            images.insert(name.clone(), image);
+        }
+        match &images[&name] {
+            Some(src) => {
+                result.html.push_str(&format!("<img class=\"emoji\" alt=\"{}\" src=\"{}\">", piece, src));
+                result.replaced += 1;
+            }
+            None => {
+                result.html.push_str(piece);
+                if !result.missing.iter().any(|missing| missing == piece) {
+                    result.missing.push(piece.to_string());
+                }
+            }
+        }
+    }
+
+    if !result.missing.is_empty() {
+        warn!("No images in {:?} for {} emoji, leaving them as text", dir, result.missing.len());
+    }
+    debug!("Replaced {} emoji with images", result.replaced);
+    result
+}
+
+/// Twemoji's file name for an emoji: its code points in hex, joined with `-`,
+/// without the emoji variation selector unless the sequence has a joiner
+pub fn twemoji_name(emoji: &str) -> String {
+    let keep_selector = emoji.contains(ZERO_WIDTH_JOINER);
+    emoji.chars()
+        .filter(|&c| keep_selector || c != VARIATION_SELECTOR)
+        .map(|c| format!("{:x}", c as u32))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The image for `name` as a data URL, preferring SVG
+async fn load_image(dir: &Path, name: &str) -> Option<String> {
+    for (extension, mime) in [("svg", "image/svg+xml"), ("png", "image/png")] {
+        let path = dir.join(format!("{}.{}", name, extension));
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+                return Some(format!("data:{};base64,{}", mime, encoded));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to read emoji image {:?}: {}", path, e),
+        }
+    }
+    None
+}
+
+/// Length in bytes of the emoji sequence `text` starts with: a flag, a keycap,
+/// or a pictograph with its selector, skin tone and joined pictographs
+fn emoji_len(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    let (_, first) = chars.next()?;
+
+    if is_regional_indicator(first) {
+        return match chars.next() {
+            Some((at, c)) if is_regional_indicator(c) => Some(at + c.len_utf8()),
+            _ => None,
+        };
+    }
+    if first.is_ascii_digit() || first == '#' || first == '*' {
+        // Keycaps: an optional selector, then U+20E3
+        let mut next = chars.next();
+        if matches!(next, Some((_, VARIATION_SELECTOR))) {
+            next = chars.next();
+        }
+        return match next {
+            Some((at, KEYCAP)) => Some(at + KEYCAP.len_utf8()),
+            _ => None,
+        };
+    }
+    if !is_pictographic(first) {
+        return None;
+    }
+    // Symbols like © and ↔ are text unless the selector asks for an emoji
+    if is_text_by_default(first) && !matches!(chars.peek(), Some((_, VARIATION_SELECTOR))) {
+        return None;
+    }
+
+    let mut end = first.len_utf8();
+    while let Some(&(at, c)) = chars.peek() {
+        if c == VARIATION_SELECTOR || is_skin_tone(c) {
+            end = at + c.len_utf8();
+            chars.next();
+        } else if c == ZERO_WIDTH_JOINER {
+            chars.next();
+            match chars.next() {
+                Some((next_at, next)) if is_pictographic(next) => end = next_at + next.len_utf8(),
+                _ => break,
+            }
+        } else {
+            break;
+        }
+    }
+    Some(end)
+}
+
+/// Characters twemoji draws, roughly the Unicode pictographic blocks
+fn is_pictographic(c: char) -> bool {
+    matches!(c as u32,
+        0x00A9 | 0x00AE | 0x203C | 0x2049 | 0x2122 | 0x2139 | 0x2194..=0x21AA
+        | 0x231A..=0x23FF | 0x24C2 | 0x25AA..=0x25FE | 0x2600..=0x27BF | 0x2934 | 0x2935
+        | 0x2B05..=0x2B55 | 0x3030 | 0x303D | 0x3297 | 0x3299 | 0x1F000..=0x1F1E5 | 0x1F200..=0x1FAFF)
+}
+
+fn is_text_by_default(c: char) -> bool {
+    matches!(c as u32, 0x00A9 | 0x00AE | 0x203C | 0x2049 | 0x2122 | 0x2139 | 0x2194..=0x21AA | 0x24C2 | 0x25AA..=0x25FE)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_skin_tone(c: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_twemoji_names() {
+        assert_eq!(twemoji_name("😀"), "1f600");
+        assert_eq!(twemoji_name("❤️"), "2764");
+        assert_eq!(twemoji_name("👩‍💻"), "1f469-200d-1f4bb");
+        assert_eq!(twemoji_name("🏳️‍🌈"), "1f3f3-fe0f-200d-1f308");
+
+        assert_eq!(emoji_len("🇩🇪 flag"), Some("🇩🇪".len()));
+        assert_eq!(emoji_len("👍🏽!"), Some("👍🏽".len()));
+        assert_eq!(emoji_len("1️⃣ one"), Some("1️⃣".len()));
+        assert_eq!(emoji_len("© 2024"), None);
+        assert_eq!(emoji_len("12"), None);
+    }
+
+    #[tokio::test]
+    async fn test_substitute_emoji() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("1f600.svg"), "<svg/>").unwrap();
+        std::fs::write(dir.path().join("2764.png"), [0x89, b'P', b'N', b'G']).unwrap();
+
+        let html = "<p title=\"😀\">Hi 😀, I ❤️ 2 🦀</p>";
+        let result = substitute_emoji(html, dir.path()).await;
+        assert_eq!(result.replaced, 2);
+        assert_eq!(result.missing, vec!["🦀"]);
+        assert!(result.html.starts_with("<p title=\"😀\">Hi <img class=\"emoji\" alt=\"😀\" src=\"data:image/svg+xml;base64,PHN2Zy8+\">, I "));
+        assert!(result.html.contains("<img class=\"emoji\" alt=\"❤️\" src=\"data:image/png;base64,"));
+        assert!(result.html.ends_with(" 2 🦀</p>"));
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Result, Context};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use tracing::debug;
+
+/// Keychain entry holding the password for encrypted documents
+pub const DOCUMENT_PASSWORD_KEY: &str = "document-password";
+
+/// Extension added to encrypted documents, e.g. `journal.md.enc`
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Start of every encrypted document, followed by the key derivation rounds,
+/// the salt, the nonce and the sealed content
+const MAGIC: &[u8] = b"typolite-encrypted-v1\n";
+
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + 4 + SALT_LEN + NONCE_LEN;
+
+/// PBKDF2-HMAC-SHA256 rounds for new files; existing files record their own
+const DEFAULT_ITERATIONS: u32 = 600_000;
+
+/// Upper bound on the rounds a file may ask for, so a crafted header can't hang a read
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+pub fn is_encrypted_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(ENCRYPTED_EXTENSION))
+}
+
+/// `notes.md` -> `notes.md.enc`
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Salt and rounds a key was derived with
+type KeyParams = ([u8; SALT_LEN], u32);
+
+/// Password-based encryption of documents at rest, with ChaCha20-Poly1305 under
+/// a key derived from the password
+///
+/// Deriving a key is deliberately slow, so keys are kept per salt and every file
+/// written in a session shares one salt (each with its own nonce).
+pub struct DocumentCipher {
+    password: RwLock<Option<String>>,
+    iterations: u32,
+    keys: Mutex<HashMap<KeyParams, [u8; KEY_LEN]>>,
+    write_salt: Mutex<Option<[u8; SALT_LEN]>>,
+    rng: SystemRandom,
+}
+
+impl Default for DocumentCipher {
+    fn default() -> Self {
+        Self {
+            password: RwLock::new(None),
+            iterations: DEFAULT_ITERATIONS,
+            keys: Mutex::new(HashMap::new()),
+            write_salt: Mutex::new(None),
+            rng: SystemRandom::new(),
+        }
+    }
+}
+
+impl DocumentCipher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Key derivation rounds for files written from now on
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// Replace the password, forgetting every key derived from the old one
+    pub fn set_password(&self, password: Option<String>) {
+        *self.password.write().unwrap() = password.filter(|password| !password.is_empty());
+        self.keys.lock().unwrap().clear();
+        *self.write_salt.lock().unwrap() = None;
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let salt = {
+            let mut write_salt = self.write_salt.lock().unwrap();
+            match *write_salt {
+                Some(salt) => salt,
+                None => {
+                    let mut salt = [0u8; SALT_LEN];
+                    self.rng.fill(&mut salt).map_err(|_| anyhow::anyhow!("Failed to generate a salt"))?;
+                    *write_salt = Some(salt);
+                    salt
+                }
+            }
+        };
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| anyhow::anyhow!("Failed to generate a nonce"))?;
+
+        let mut output = Vec::with_capacity(HEADER_LEN + plaintext.len() + CHACHA20_POLY1305.tag_len());
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&self.iterations.to_be_bytes());
+        output.extend_from_slice(&salt);
+        output.extend_from_slice(&nonce);
+
+        let mut sealed = plaintext.to_vec();
+        // The header is authenticated too, so its rounds and salt can't be swapped
+        self.key(salt, self.iterations)?
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(&output[..]), &mut sealed)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt the document"))?;
+        output.extend_from_slice(&sealed);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+            anyhow::bail!("Not an encrypted document");
+        }
+        let (header, sealed) = data.split_at(HEADER_LEN);
+        let iterations = u32::from_be_bytes(header[MAGIC.len()..MAGIC.len() + 4].try_into()?);
+        if iterations > MAX_ITERATIONS {
+            anyhow::bail!("Unsupported key derivation rounds in the document: {}", iterations);
+        }
+        let salt: [u8; SALT_LEN] = header[MAGIC.len() + 4..MAGIC.len() + 4 + SALT_LEN].try_into()?;
+        let nonce: [u8; NONCE_LEN] = header[HEADER_LEN - NONCE_LEN..].try_into()?;
+
+        let mut plaintext = sealed.to_vec();
+        let len = self.key(salt, iterations)?
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(header), &mut plaintext)
+            .map_err(|_| anyhow::anyhow!("Wrong password, or the document is damaged"))?
+            .len();
+        plaintext.truncate(len);
+        Ok(plaintext)
+    }
+
+    fn key(&self, salt: [u8; SALT_LEN], iterations: u32) -> Result<LessSafeKey> {
+        let password = self.password.read().unwrap().clone()
+            .context("Set a document password to open and save encrypted documents")?;
+
+        let key = *self.keys.lock().unwrap().entry((salt, iterations)).or_insert_with(|| {
+            debug!("Deriving a document key ({} rounds)", iterations);
+            let mut key = [0u8; KEY_LEN];
+            let rounds = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+            pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, rounds, &salt, password.as_bytes(), &mut key);
+            key
+        });
+
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+            .map_err(|_| anyhow::anyhow!("Invalid document key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_wrong_password() {
+        let cipher = DocumentCipher::new().with_iterations(1000);
+        assert!(cipher.encrypt(b"secret").is_err());
+
+        cipher.set_password(Some("correct horse".to_string()));
+        let first = cipher.encrypt("# Diary\n\nÜber alles".as_bytes()).unwrap();
+        let second = cipher.encrypt("# Diary\n\nÜber alles".as_bytes()).unwrap();
+        assert!(first.starts_with(MAGIC));
+        assert_ne!(first, second);
+        assert_eq!(cipher.decrypt(&first).unwrap(), "# Diary\n\nÜber alles".as_bytes());
+
+        let mut tampered = first.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.decrypt(&tampered).is_err());
+
+        cipher.set_password(Some("wrong".to_string()));
+        let error = cipher.decrypt(&first).unwrap_err().to_string();
+        assert!(error.contains("Wrong password"), "{}", error);
+    }
+
+    #[test]
+    fn test_encrypted_path() {
+        let path = encrypted_path(Path::new("/notes/journal.md"));
+        assert_eq!(path, Path::new("/notes/journal.md.enc"));
+        assert!(is_encrypted_path(&path));
+        assert!(!is_encrypted_path(Path::new("/notes/journal.md")));
+    }
+}
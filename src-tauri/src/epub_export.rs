@@ -0,0 +1,493 @@
+use anyhow::{Result, Context};
+use ego_tree::NodeRef;
+use scraper::{ElementRef, Html, Node};
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use tracing::{debug, warn};
+
+use crate::bidi::TextDirection;
+use crate::export::ExportOptions;
+use crate::image_size::{image_media_type, read_image_source};
+
+const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// Elements XHTML writes as `<name/>`
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Reading systems bring their own typography, so this only covers what
+/// they tend to leave out
+const EPUB_CSS: &str = "\
+body { line-height: 1.5; }
+h1, h2, h3, h4, h5, h6 { line-height: 1.25; page-break-after: avoid; }
+img, svg { max-width: 100%; height: auto; }
+pre { white-space: pre-wrap; font-size: 0.85em; background: #f6f8fa; padding: 0.75em; }
+code { font-family: monospace; }
+blockquote { margin-left: 0; padding-left: 1em; border-left: 0.25em solid #d0d7de; color: #57606a; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #d0d7de; padding: 0.25em 0.5em; }
+nav ol { list-style: none; }
+";
+
+/// A built EPUB file
+pub struct EpubPackage {
+    pub data: Vec<u8>,
+    pub chapters: usize,
+}
+
+/// Build an EPUB 3 book from rendered document HTML
+///
+/// Every top-level H1 and H2 starts a chapter file, listed in the navigation
+/// document with H2 chapters nested under the H1 before them. Local and
+/// embedded images are packaged with the book (relative paths resolve against
+/// `options.base_dir`); remote ones are replaced by their alt text. Links to
+/// headings follow them into whichever chapter they ended up in.
+pub fn html_to_epub(html: &str, options: &ExportOptions) -> Result<EpubPackage> {
+    let fragment = Html::parse_fragment(html);
+    let chapters = split_chapters(*fragment.root_element());
+    let title = options.title.clone()
+        .or_else(|| chapters.iter().find(|chapter| chapter.level == 1).map(|chapter| chapter.title.clone()))
+        .unwrap_or_else(|| "Untitled".to_string());
+
+    // Where every id ended up, so `#id` links can point into the right chapter
+    let mut anchors = HashMap::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        for node in &chapter.nodes {
+            for element in node.descendants().filter_map(ElementRef::wrap) {
+                if let Some(id) = element.value().attr("id") {
+                    anchors.entry(id.to_string()).or_insert(index);
+                }
+            }
+        }
+    }
+
+    let mut writer = EpubWriter { options, anchors, images: Vec::new(), sources: HashMap::new() };
+    let mut files = Vec::with_capacity(chapters.len());
+    for (index, chapter) in chapters.iter().enumerate() {
+        let mut body = String::new();
+        let mut features = ChapterFeatures::default();
+        for node in &chapter.nodes {
+            writer.node(*node, XHTML_NS, &mut body, &mut features);
+        }
+        let chapter_title = if chapter.title.is_empty() { title.as_str() } else { chapter.title.as_str() };
+        files.push((chapter_file(index), xhtml_page(chapter_title, &body, options), features));
+    }
+
+    let nav = nav_document(&title, &chapters, options);
+    let package = package_document(&title, &files, &writer.images, options);
+    debug!("Built EPUB with {} chapters and {} images", files.len(), writer.images.len());
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    // The mimetype must come first, uncompressed, for readers to recognize the file
+    let stored = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let deflated = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let mut stylesheet = EPUB_CSS.to_string();
+    if let Some(css) = &options.document_css {
+        stylesheet.push_str(css);
+    }
+    let parts = [
+        ("META-INF/container.xml".to_string(), CONTAINER.to_string()),
+        ("OEBPS/content.opf".to_string(), package),
+        ("OEBPS/nav.xhtml".to_string(), nav),
+        ("OEBPS/style.css".to_string(), stylesheet),
+    ];
+    let chapters_count = files.len();
+    let pages = files.into_iter().map(|(name, page, _)| (format!("OEBPS/{}", name), page));
+    for (name, content) in parts.into_iter().chain(pages) {
+        zip.start_file(name.as_str(), deflated).with_context(|| format!("Failed to add {} to the EPUB", name))?;
+        zip.write_all(content.as_bytes())?;
+    }
+    for image in &writer.images {
+        zip.start_file(format!("OEBPS/{}", image.href), deflated)?;
+        zip.write_all(&image.data)?;
+    }
+    let data = zip.finish().context("Failed to write the EPUB container")?.into_inner();
+
+    Ok(EpubPackage { data, chapters: chapters_count })
+}
+
+struct Chapter<'a> {
+    /// Heading level that started the chapter; 0 for content before the first heading
+    level: u8,
+    title: String,
+    nodes: Vec<NodeRef<'a, Node>>,
+}
+
+/// Group the top-level nodes into chapters at every H1 and H2
+fn split_chapters(root: NodeRef<Node>) -> Vec<Chapter> {
+    let mut chapters = vec![Chapter { level: 0, title: String::new(), nodes: Vec::new() }];
+    for node in root.children() {
+        let heading = ElementRef::wrap(node).and_then(|element| match element.value().name() {
+            "h1" => Some((1, element)),
+            "h2" => Some((2, element)),
+            _ => None,
+        });
+        if let Some((level, heading)) = heading {
+            let title = heading.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ");
+            chapters.push(Chapter { level, title, nodes: Vec::new() });
+        }
+        chapters.last_mut().unwrap().nodes.push(node);
+    }
+
+    // Only whitespace before the first heading isn't worth a chapter of its own
+    let has_preface = chapters[0].nodes.iter().any(|node| match node.value() {
+        Node::Text(text) => !text.trim().is_empty(),
+        Node::Element(_) => true,
+        _ => false,
+    });
+    if !has_preface && chapters.len() > 1 {
+        chapters.remove(0);
+    }
+    chapters
+}
+
+struct EpubImage {
+    id: String,
+    href: String,
+    media_type: &'static str,
+    data: Vec<u8>,
+}
+
+/// Content a chapter's manifest item has to declare
+#[derive(Default)]
+struct ChapterFeatures {
+    svg: bool,
+    mathml: bool,
+}
+
+struct EpubWriter<'a> {
+    options: &'a ExportOptions,
+    anchors: HashMap<String, usize>,
+    images: Vec<EpubImage>,
+    /// Image `src` to its packaged path, or `None` when it couldn't be packaged
+    sources: HashMap<String, Option<String>>,
+}
+
+impl EpubWriter<'_> {
+    /// Write `node` as XHTML
+    fn node(&mut self, node: NodeRef<Node>, parent_ns: &str, out: &mut String, features: &mut ChapterFeatures) {
+        let element = match node.value() {
+            Node::Text(text) => {
+                out.push_str(&escape_text(text));
+                return;
+            }
+            Node::Element(element) => element,
+            _ => return,
+        };
+        let name = element.name();
+        // Scripts and forms don't belong in a book
+        if matches!(name, "script" | "noscript" | "template" | "button" | "iframe" | "object") {
+            return;
+        }
+
+        let ns: &str = &element.name.ns;
+        let mut src = None;
+        if name == "img" && ns == XHTML_NS {
+            let image = element.attr("src").unwrap_or_default();
+            match self.image(image) {
+                Some(href) => src = Some(href),
+                None => {
+                    let alt = element.attr("alt").unwrap_or_default();
+                    if !alt.is_empty() {
+                        out.push_str(&format!("<span class=\"missing-image\">[{}]</span>", escape_text(alt)));
+                    }
+                    return;
+                }
+            }
+        }
+
+        let tag = qualified_name(element.name.prefix.as_deref(), name);
+        out.push('<');
+        out.push_str(&tag);
+        if ns != parent_ns {
+            out.push_str(&format!(" xmlns=\"{}\"", ns));
+            if ns == SVG_NS {
+                out.push_str(" xmlns:xlink=\"http://www.w3.org/1999/xlink\"");
+                features.svg = true;
+            } else if ns == MATHML_NS {
+                features.mathml = true;
+            }
+        }
+        // Sorted, as the parser doesn't keep their order
+        let mut attributes: Vec<_> = element.attrs.iter()
+            .map(|(attribute, value)| (qualified_name(attribute.prefix.as_deref(), &attribute.local), value))
+            .collect();
+        attributes.sort();
+        for (attribute_name, value) in attributes {
+            if !is_xml_name(&attribute_name) || attribute_name.starts_with("on") || attribute_name.starts_with("xmlns") {
+                continue;
+            }
+            let value = match (attribute_name.as_str(), &src) {
+                ("src", Some(href)) => href.clone(),
+                ("href", _) => self.link(value),
+                _ => value.to_string(),
+            };
+            out.push_str(&format!(" {}=\"{}\"", attribute_name, escape_attribute(&value)));
+        }
+
+        if ns == XHTML_NS && VOID_ELEMENTS.contains(&name) {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        for child in node.children() {
+            self.node(child, ns, out, features);
+        }
+        out.push_str(&format!("</{}>", tag));
+    }
+
+    /// Point in-document links at the chapter their target ended up in
+    fn link(&self, href: &str) -> String {
+        match href.strip_prefix('#').and_then(|id| self.anchors.get(id)) {
+            Some(&chapter) => format!("{}{}", chapter_file(chapter), href),
+            None => href.to_string(),
+        }
+    }
+
+    /// Package the image at `src`, returning its path in the book
+    fn image(&mut self, src: &str) -> Option<String> {
+        if let Some(href) = self.sources.get(src) {
+            return href.clone();
+        }
+        let href = match read_image_source(src, self.options.base_dir.as_deref()) {
+            Ok(data) => match image_media_type(&data) {
+                Some((extension, media_type)) => {
+                    let index = self.images.len() + 1;
+                    let href = format!("images/image{}.{}", index, extension);
+                    self.images.push(EpubImage { id: format!("image{}", index), href: href.clone(), media_type, data });
+                    Some(href)
+                }
+                None => {
+                    warn!("Leaving image {} out of the EPUB export: unsupported format", src);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("Leaving image {} out of the EPUB export: {:#}", src, e);
+                None
+            }
+        };
+        self.sources.insert(src.to_string(), href.clone());
+        href
+    }
+}
+
+fn chapter_file(index: usize) -> String {
+    format!("chapter-{}.xhtml", index + 1)
+}
+
+fn language(options: &ExportOptions) -> &str {
+    options.language.as_deref().unwrap_or("en")
+}
+
+fn xhtml_page(title: &str, body: &str, options: &ExportOptions) -> String {
+    let dir = if options.direction == Some(TextDirection::Rtl) { " dir=\"rtl\"" } else { "" };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{lang}\" lang=\"{lang}\"{dir}>\n\
+         <head>\n<meta charset=\"UTF-8\"/>\n<title>{title}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n\
+         <body>\n{body}\n</body>\n</html>\n",
+        lang = escape_attribute(language(options)),
+        dir = dir,
+        title = escape_text(title),
+        body = body,
+    )
+}
+
+/// The navigation document, with H2 chapters nested under the H1 chapter before them
+fn nav_document(title: &str, chapters: &[Chapter], options: &ExportOptions) -> String {
+    let mut list = String::from("<ol>\n");
+    let mut nested = false;
+    for (index, chapter) in chapters.iter().enumerate() {
+        let label = if chapter.title.is_empty() { title } else { chapter.title.as_str() };
+        let entry = format!("<a href=\"{}\">{}</a>", chapter_file(index), escape_text(label));
+        let under_h1 = chapter.level == 2 && chapters[..index].iter().any(|earlier| earlier.level == 1);
+        if under_h1 {
+            list.push_str(if nested { "</li>\n<li>" } else { "\n<ol>\n<li>" });
+            nested = true;
+        } else {
+            if nested {
+                list.push_str("</li>\n</ol>\n");
+                nested = false;
+            }
+            if index > 0 {
+                list.push_str("</li>\n");
+            }
+            list.push_str("<li>");
+        }
+        list.push_str(&entry);
+    }
+    if nested {
+        list.push_str("</li>\n</ol>\n");
+    }
+    if !chapters.is_empty() {
+        list.push_str("</li>\n");
+    }
+    list.push_str("</ol>");
+
+    let body = format!("<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n{}\n</nav>", escape_text(title), list);
+    xhtml_page(title, &body, options)
+}
+
+fn package_document(title: &str, chapters: &[(String, String, ChapterFeatures)], images: &[EpubImage], options: &ExportOptions) -> String {
+    let mut metadata = format!(
+        "<dc:identifier id=\"book-id\">urn:uuid:{}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:language>{}</dc:language>\n",
+        uuid::Uuid::new_v4(),
+        escape_text(title),
+        escape_text(language(options)),
+    );
+    for author in &options.authors {
+        metadata.push_str(&format!("<dc:creator>{}</dc:creator>\n", escape_text(author)));
+    }
+    metadata.push_str(&format!(
+        "<meta property=\"dcterms:modified\">{}</meta>\n",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+    ));
+
+    let mut manifest = String::from(concat!(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+        "<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    ));
+    let mut spine = String::new();
+    for (index, (file, _, features)) in chapters.iter().enumerate() {
+        let properties: Vec<&str> = [(features.svg, "svg"), (features.mathml, "mathml")].into_iter()
+            .filter_map(|(present, property)| present.then_some(property))
+            .collect();
+        let properties = if properties.is_empty() { String::new() } else { format!(" properties=\"{}\"", properties.join(" ")) };
+        manifest.push_str(&format!(
+            "<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"{}/>\n",
+            index + 1, file, properties,
+        ));
+        spine.push_str(&format!("<itemref idref=\"chapter-{}\"/>\n", index + 1));
+    }
+    for image in images {
+        manifest.push_str(&format!("<item id=\"{}\" href=\"{}\" media-type=\"{}\"/>\n", image.id, image.href, image.media_type));
+    }
+
+    let progression = if options.direction == Some(TextDirection::Rtl) { " page-progression-direction=\"rtl\"" } else { "" };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{}\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}</metadata>\n\
+         <manifest>\n{}</manifest>\n<spine{}>\n{}</spine>\n</package>\n",
+        escape_attribute(language(options)), metadata, manifest, progression, spine,
+    )
+}
+
+fn qualified_name(prefix: Option<&str>, local: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}:{}", prefix, local),
+        None => local.to_string(),
+    }
+}
+
+/// Attribute names HTML accepts but XML doesn't, such as `@click`, are dropped
+fn is_xml_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+fn escape_text(text: &str) -> String {
+    html_escape::encode_text(text).into_owned()
+}
+
+fn escape_attribute(text: &str) -> String {
+    html_escape::encode_double_quoted_attribute(text).into_owned()
+}
+
+const CONTAINER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n",
+    "<rootfiles>\n<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n</rootfiles>\n",
+    "</container>\n",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    fn part(epub: &[u8], name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub)).unwrap();
+        let mut content = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_html_to_epub_chapters() {
+        let html = "<p>Foreword<br>text</p>\n<h1 id=\"one\">Part One</h1>\n<p>See <a href=\"#setup\">setup</a>.</p>\n\
+            <h2 id=\"setup\">Setup &amp; Install</h2>\n<p><input type=\"checkbox\" checked disabled> done</p>\n<h3>Detail</h3>\n\
+            <h2>Usage</h2>\n<hr>\n<h1>Part Two</h1>\n<script>alert(1)</script>";
+        let options = ExportOptions {
+            authors: vec!["Ada Lovelace".to_string()],
+            language: Some("en-GB".to_string()),
+            ..Default::default()
+        };
+        let book = html_to_epub(html, &options).unwrap();
+        assert_eq!(book.chapters, 5);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(&book.data[..])).unwrap();
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+        drop(mimetype);
+
+        let preface = part(&book.data, "OEBPS/chapter-1.xhtml");
+        assert!(preface.contains("<title>Part One</title>"));
+        assert!(preface.contains("<p>Foreword<br/>text</p>"));
+        let part_one = part(&book.data, "OEBPS/chapter-2.xhtml");
+        assert!(part_one.contains("<a href=\"chapter-3.xhtml#setup\">setup</a>"));
+        let setup = part(&book.data, "OEBPS/chapter-3.xhtml");
+        assert!(setup.contains("<h2 id=\"setup\">Setup &amp; Install</h2>"));
+        assert!(setup.contains("<input checked=\"\" disabled=\"\" type=\"checkbox\"/> done"));
+        assert!(setup.contains("<h3>Detail</h3>"));
+        assert!(!part(&book.data, "OEBPS/chapter-5.xhtml").contains("alert"));
+
+        let nav = part(&book.data, "OEBPS/nav.xhtml");
+        assert!(nav.contains(concat!(
+            "<li><a href=\"chapter-2.xhtml\">Part One</a>\n<ol>\n",
+            "<li><a href=\"chapter-3.xhtml\">Setup &amp; Install</a></li>\n",
+            "<li><a href=\"chapter-4.xhtml\">Usage</a></li>\n</ol>\n</li>\n",
+            "<li><a href=\"chapter-5.xhtml\">Part Two</a></li>\n</ol>",
+        )));
+
+        let package = part(&book.data, "OEBPS/content.opf");
+        assert!(package.contains("<dc:title>Part One</dc:title>"));
+        assert!(package.contains("<dc:creator>Ada Lovelace</dc:creator>"));
+        assert!(package.contains("<dc:language>en-GB</dc:language>"));
+        assert_eq!(package.matches("<itemref idref=\"chapter-").count(), 5);
+    }
+
+    #[test]
+    fn test_html_to_epub_embeds_images() {
+        let dir = TempDir::new().unwrap();
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x01\0\0\0\x01".to_vec();
+        std::fs::write(dir.path().join("dot.png"), &png).unwrap();
+
+        let options = ExportOptions { base_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+        let html = "<h1>Pictures</h1>\n<p><img src=\"dot.png\" alt=\"Dot\"><img src=\"https://example.com/a.png\" alt=\"Remote\"><img src=\"dot.png\"></p>\n\
+            <p><svg viewBox=\"0 0 10 10\"><use xlink:href=\"#a\"></use></svg></p>";
+        let book = html_to_epub(html, &options).unwrap();
+
+        let chapter = part(&book.data, "OEBPS/chapter-1.xhtml");
+        assert!(chapter.contains("<img alt=\"Dot\" src=\"images/image1.png\"/><span class=\"missing-image\">[Remote]</span><img src=\"images/image1.png\"/>"));
+        assert!(chapter.contains("<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" viewBox=\"0 0 10 10\"><use xlink:href=\"#a\"></use></svg>"));
+
+        let package = part(&book.data, "OEBPS/content.opf");
+        assert!(package.contains("<item id=\"chapter-1\" href=\"chapter-1.xhtml\" media-type=\"application/xhtml+xml\" properties=\"svg\"/>"));
+        assert!(package.contains("<item id=\"image1\" href=\"images/image1.png\" media-type=\"image/png\"/>"));
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(&book.data[..])).unwrap();
+        let mut embedded = Vec::new();
+        archive.by_name("OEBPS/images/image1.png").unwrap().read_to_end(&mut embedded).unwrap();
+        assert_eq!(embedded, png);
+    }
+}
@@ -1,24 +1,310 @@
 use anyhow::{Result, Context};
+use base64::Engine;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, warn, error};
 
+use crate::bidi::{dominant_direction, TextDirection};
+use crate::code_highlight::{CodeHighlighter, DEFAULT_CODE_THEME};
+use crate::emoji::{emoji_dir, substitute_emoji};
+use crate::csp::{strip_event_handlers, STRICT_CSP};
+use crate::docx_export::html_to_docx;
+use crate::epub_export::html_to_epub;
+use crate::feed::parse_date;
+use crate::image_size::{image_media_type, read_image_source};
+use crate::latex_export::markdown_to_latex;
+use crate::math::render_math_html;
+use crate::mermaid::{find_mermaid_cli, has_mermaid_blocks, render_mermaid_blocks};
+use crate::pdf_edit::PdfFile;
+use crate::pdf_renderer::{find_browser, render_pdf_async, PdfRendererProcess};
+use crate::pdf_metadata::{write_metadata, PdfMetadata};
+use crate::pdf_outline::{document_headings, heading_pages, outline_anchors, outline_headings, write_outline};
+use crate::pdf_signing::PdfSigner;
+use crate::profiling::{PhaseTimer, PhaseTiming};
+use crate::shutdown::InFlight;
+use crate::stylesheets::themes_dir;
+
+/// Closes the document opened by `ExportService::document_head`
+const DOCUMENT_TAIL: &str = "\n        </div>\n    </div>\n</body>\n</html>";
+
+/// Base styles mirrored for right-to-left documents
+const RTL_CSS: &str = r#"
+        body {
+            direction: rtl;
+        }
+        
+        blockquote {
+            border-left: none;
+            border-right: 4px solid #dfe2e5;
+        }
+        
+        th, td {
+            text-align: right;
+        }
+        
+        .toc ul {
+            padding-right: 0;
+        }
+        "#;
+
+/// Themes shipped with the app, which `css_theme` can name
+const BUILT_IN_THEMES: &[(&str, &str)] = &[
+    ("github", GITHUB_THEME),
+    ("academic", ACADEMIC_THEME),
+    ("newspaper", NEWSPAPER_THEME),
+    ("dark", DARK_THEME),
+];
+
+const GITHUB_THEME: &str = r#"
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", "Noto Sans", Helvetica, Arial, sans-serif;
+            font-size: 11pt;
+            color: #1f2328;
+        }
+        
+        h1, h2 {
+            padding-bottom: 0.3em;
+            border-bottom: 1px solid #d1d9e0;
+        }
+        
+        a {
+            color: #0969da;
+        }
+        
+        code {
+            background-color: rgba(175, 184, 193, 0.2);
+            border-radius: 6px;
+        }
+        
+        pre {
+            background-color: #f6f8fa;
+            border-radius: 6px;
+        }
+        
+        th, td {
+            border-color: #d1d9e0;
+        }
+        
+        tr:nth-child(2n) {
+            background-color: #f6f8fa;
+        }
+        "#;
+
+const ACADEMIC_THEME: &str = r#"
+        body {
+            font-family: "Latin Modern Roman", "Computer Modern", Georgia, "Times New Roman", serif;
+            font-size: 11pt;
+            line-height: 1.5;
+            color: #000;
+            text-align: justify;
+            hyphens: auto;
+        }
+        
+        h1 {
+            text-align: center;
+            font-size: 18pt;
+        }
+        
+        h2 { font-size: 14pt; }
+        h3 { font-size: 12pt; }
+        
+        h1, h2, h3, h4, h5, h6 {
+            font-weight: bold;
+        }
+        
+        p + p {
+            text-indent: 1.5em;
+        }
+        
+        blockquote {
+            border-left: none;
+            margin: 1em 2em;
+            font-size: 10pt;
+        }
+        
+        table {
+            border-top: 2px solid #000;
+            border-bottom: 2px solid #000;
+        }
+        
+        th, td {
+            border: none;
+        }
+        
+        th {
+            border-bottom: 1px solid #000;
+            background: none;
+        }
+        
+        .footnotes {
+            font-size: 9pt;
+        }
+        "#;
+
+const NEWSPAPER_THEME: &str = r#"
+        body {
+            font-family: Georgia, "Times New Roman", serif;
+            font-size: 10pt;
+            line-height: 1.4;
+            color: #111;
+        }
+        
+        .content {
+            column-count: 2;
+            column-gap: 2em;
+            column-rule: 1px solid #ccc;
+            text-align: justify;
+        }
+        
+        h1 {
+            column-span: all;
+            font-size: 28pt;
+            text-align: center;
+            text-transform: uppercase;
+            letter-spacing: 0.05em;
+            border-top: 3px double #111;
+            border-bottom: 3px double #111;
+            padding: 0.2em 0;
+        }
+        
+        h2, h3 {
+            font-family: "Helvetica Neue", Arial, sans-serif;
+        }
+        
+        img, pre, table {
+            break-inside: avoid;
+        }
+        "#;
+
+const DARK_THEME: &str = r#"
+        html, body {
+            background-color: #0d1117;
+            color: #e6edf3;
+            -webkit-print-color-adjust: exact;
+            print-color-adjust: exact;
+        }
+        
+        a, .toc a {
+            color: #4493f8;
+        }
+        
+        h1, h2 {
+            border-bottom-color: #3d444d;
+        }
+        
+        code, pre {
+            background-color: #161b22;
+            color: #e6edf3;
+        }
+        
+        blockquote {
+            border-color: #3d444d;
+            color: #9198a1;
+        }
+        
+        th, td {
+            border-color: #3d444d;
+        }
+        
+        th {
+            background-color: #161b22;
+        }
+        "#;
+
+/// Largest piece of the document body handed to the file in one write
+const EXPORT_CHUNK_BYTES: usize = 256 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportOptions {
     pub format: ExportFormat,
     pub include_toc: bool,
     pub page_size: PageSize,
     pub margins: Margins,
+    /// Printed at the top and bottom of every page; `{page}`, `{pages}`, `{title}`,
+    /// `{date}` and `{filename}` are filled in
     pub header: Option<String>,
     pub footer: Option<String>,
+    /// Name of a built-in or user export theme
     pub css_theme: Option<String>,
+    /// Sign PDFs with the certificate set up in the signing settings
+    #[serde(default)]
+    pub sign: bool,
+    /// The document's own stylesheet from its front matter, applied after the theme
+    #[serde(skip)]
+    pub document_css: Option<String>,
+    /// Text direction of the document; detected from its text when unset
+    #[serde(default)]
+    pub direction: Option<TextDirection>,
+    /// Draw emoji with images from the emoji folder, for PDF engines without a color emoji font
+    #[serde(default)]
+    pub emoji_images: bool,
+    /// Folder relative image paths are resolved against, for formats that embed images
+    #[serde(skip)]
+    pub base_dir: Option<PathBuf>,
+    /// The document's markdown, for formats converted from it rather than from HTML
+    #[serde(skip)]
+    pub markdown: Option<String>,
+    /// File name of the exported document, for the `{filename}` template variable
+    #[serde(skip)]
+    pub file_name: Option<String>,
+    /// Book metadata for formats that carry it; taken from the front matter when unset
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Document properties written into PDFs alongside the title and authors
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Creation date, as RFC 3339 or `YYYY-MM-DD`; the time of export when unset
+    #[serde(default)]
+    pub created: Option<String>,
+    /// Stamped across every page, such as "DRAFT" or a company logo
+    #[serde(default)]
+    pub watermark: Option<Watermark>,
+    /// Color scheme of fenced code blocks in PDF and HTML exports; the default
+    /// code theme when unset
+    #[serde(default)]
+    pub code_theme: Option<String>,
+    /// Page each heading starts on, by anchor, for the PDF table of contents;
+    /// filled in from a first printing of the document
+    #[serde(skip)]
+    pub toc_pages: HashMap<String, u32>,
+    /// Families the text is set in, replacing the theme's
+    #[serde(default)]
+    pub fonts: ExportFonts,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExportFormat {
     Pdf,
     Html,
-    Docx, // Future implementation
+    Docx,
+    Epub,
+    Latex,
+}
+
+impl ExportFormat {
+    /// File extension for exports in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Html => "html",
+            ExportFormat::Docx => "docx",
+            ExportFormat::Epub => "epub",
+            ExportFormat::Latex => "tex",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +316,30 @@ pub enum PageSize {
     A5,
 }
 
+impl PageSize {
+    /// CSS `@page` size keyword
+    pub fn css_name(&self) -> &'static str {
+        match self {
+            PageSize::A4 => "A4",
+            PageSize::Letter => "letter",
+            PageSize::Legal => "legal",
+            PageSize::A3 => "A3",
+            PageSize::A5 => "A5",
+        }
+    }
+
+    /// Portrait width and height in inches
+    pub fn dimensions(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (8.27, 11.69),
+            PageSize::Letter => (8.5, 11.0),
+            PageSize::Legal => (8.5, 14.0),
+            PageSize::A3 => (11.69, 16.54),
+            PageSize::A5 => (5.83, 8.27),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Margins {
     pub top: f32,    // in inches
@@ -38,6 +348,83 @@ pub struct Margins {
     pub left: f32,
 }
 
+/// Text or an image drawn faintly over the middle of every page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Watermark {
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Image path, relative to the document, or data URL; drawn instead of the text
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f32,
+    /// Degrees clockwise
+    #[serde(default = "default_watermark_rotation")]
+    pub rotation: f32,
+}
+
+/// Font families for each kind of text; the theme's are kept for those unset
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportFonts {
+    pub body: Option<ExportFont>,
+    pub heading: Option<ExportFont>,
+    pub mono: Option<ExportFont>,
+}
+
+/// A font family, with the file it comes from when readers may not have it installed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportFont {
+    pub family: String,
+    /// TrueType, OpenType, WOFF or WOFF2 file, relative to the document; embedded in
+    /// the export so it looks the same everywhere
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Contents of `file`, read by [`ExportFonts::load`] before the document is built
+    #[serde(skip)]
+    pub data: Option<Vec<u8>>,
+}
+
+impl ExportFonts {
+    /// Read the chosen font files, relative to `base_dir`, so building the
+    /// document's stylesheet doesn't touch the disk
+    pub async fn load(&mut self, base_dir: Option<&Path>) -> Result<()> {
+        for font in [&mut self.body, &mut self.heading, &mut self.mono].into_iter().flatten() {
+            let (Some(file), None) = (&font.file, &font.data) else {
+                continue;
+            };
+            let path = match base_dir {
+                Some(base_dir) => base_dir.join(file),
+                None => file.clone(),
+            };
+            font_format(&path)
+                .with_context(|| format!("Unsupported font file, expected TTF, OTF, WOFF or WOFF2: {:?}", path))?;
+            font.data = Some(tokio::fs::read(&path).await
+                .with_context(|| format!("Failed to read font file: {:?}", path))?);
+        }
+        Ok(())
+    }
+}
+
+fn default_watermark_opacity() -> f32 {
+    0.15
+}
+
+fn default_watermark_rotation() -> f32 {
+    -45.0
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self {
+            text: Some("DRAFT".to_string()),
+            image: None,
+            opacity: default_watermark_opacity(),
+            rotation: default_watermark_rotation(),
+        }
+    }
+}
+
 impl Default for ExportOptions {
     fn default() -> Self {
         Self {
@@ -53,32 +440,168 @@ impl Default for ExportOptions {
             header: None,
             footer: Some("Page {page} of {pages}".to_string()),
             css_theme: None,
+            sign: false,
+            document_css: None,
+            direction: None,
+            emoji_images: false,
+            base_dir: None,
+            markdown: None,
+            file_name: None,
+            title: None,
+            authors: Vec::new(),
+            language: None,
+            subject: None,
+            keywords: Vec::new(),
+            created: None,
+            watermark: None,
+            code_theme: None,
+            toc_pages: HashMap::new(),
+            fonts: ExportFonts::default(),
         }
     }
 }
 
+/// An export theme `css_theme` can name: a built-in one, or a `.css` file in the
+/// themes folder, which replaces a built-in theme of the same name
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExportTheme {
+    pub name: String,
+    pub built_in: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportResult {
     pub output_path: PathBuf,
     pub file_size: u64,
     pub pages: u32,
     pub export_time_ms: u64,
+    /// How long each export phase took, for performance profiles
+    #[serde(skip)]
+    pub timings: Vec<PhaseTiming>,
+}
+
+/// Stage an export has reached, reported in `export-progress` events
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStage {
+    RenderingHtml,
+    GeneratingPages,
+    WritingFile,
+}
+
+impl ExportStage {
+    /// Rough share of the export done when the stage starts
+    fn fraction(self) -> f32 {
+        match self {
+            ExportStage::RenderingHtml => 0.0,
+            ExportStage::GeneratingPages => 0.2,
+            ExportStage::WritingFile => 0.9,
+        }
+    }
+}
+
+/// How far an export to `output_path` has got; `fraction` reaches 1 when it's done
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProgress {
+    pub output_path: PathBuf,
+    pub stage: ExportStage,
+    pub fraction: f32,
+}
+
+/// Receives the progress of an export as it moves through its stages
+pub type ProgressFn = dyn Fn(ExportProgress) + Send + Sync;
+
+/// Error of an export stopped through its cancellation token
+const EXPORT_CANCELLED: &str = "Export cancelled";
+
+/// Progress reporting and cancellation for one export
+struct JobControl<'a> {
+    output_path: &'a Path,
+    report: &'a ProgressFn,
+    cancel: &'a CancellationToken,
+}
+
+impl JobControl<'_> {
+    /// Report that the export reached `stage`, unless it has been cancelled
+    fn stage(&self, stage: ExportStage) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            anyhow::bail!(EXPORT_CANCELLED);
+        }
+        self.send(stage, stage.fraction());
+        Ok(())
+    }
+
+    fn done(&self) {
+        self.send(ExportStage::WritingFile, 1.0);
+    }
+
+    fn send(&self, stage: ExportStage, fraction: f32) {
+        (self.report)(ExportProgress { output_path: self.output_path.to_path_buf(), stage, fraction });
+    }
+}
+
+/// Scratch space for a single export, removed with everything in it when dropped
+///
+/// Holding one for the length of a job means intermediate files go away even
+/// when the export fails part-way.
+pub struct ExportJobDir {
+    path: PathBuf,
+}
+
+impl ExportJobDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ExportJobDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            warn!("Failed to clean up export scratch folder {:?}: {}", self.path, e);
+        }
+    }
 }
 
 pub struct ExportService {
-    temp_dir: PathBuf,
+    temp_dir: RwLock<PathBuf>,
+    /// Add a strict CSP to exported documents and strip event handlers from their content
+    content_security: AtomicBool,
+    /// Renders PDFs out of process when set; otherwise they're rendered in-process
+    pdf_renderer: Option<PdfRendererProcess>,
+    /// Browser PDFs are printed with; found automatically when unset
+    browser_path: RwLock<Option<PathBuf>>,
+    /// mermaid-cli diagrams are drawn with; found automatically when unset
+    mermaid_cli: RwLock<Option<PathBuf>>,
+    pdf_signer: RwLock<Option<Arc<PdfSigner>>>,
+    /// Where `emoji_images` finds its glyphs
+    emoji_dir: PathBuf,
+    /// User themes, as `<name>.css`
+    themes_dir: PathBuf,
+    highlighter: CodeHighlighter,
+    jobs: InFlight,
 }
 
 impl Default for ExportService {
     fn default() -> Self {
-        let temp_dir = std::env::temp_dir().join("typolite-exports");
+        let temp_dir = default_temp_dir();
         if !temp_dir.exists() {
             if let Err(e) = std::fs::create_dir_all(&temp_dir) {
                 error!("Failed to create temp export directory: {}", e);
             }
         }
         
-        Self { temp_dir }
+        Self {
+            temp_dir: RwLock::new(temp_dir),
+            content_security: AtomicBool::new(true),
+            pdf_renderer: None,
+            browser_path: RwLock::new(None),
+            mermaid_cli: RwLock::new(None),
+            pdf_signer: RwLock::new(None),
+            emoji_dir: emoji_dir(),
+            themes_dir: themes_dir(),
+            highlighter: CodeHighlighter::new(),
+            jobs: InFlight::new(),
+        }
     }
 }
 
@@ -87,29 +610,207 @@ impl ExportService {
         Self::default()
     }
 
-    pub fn with_temp_dir(mut self, temp_dir: PathBuf) -> Self {
-        self.temp_dir = temp_dir;
+    pub fn with_temp_dir(self, temp_dir: PathBuf) -> Self {
+        self.set_temp_dir(Some(temp_dir));
+        self
+    }
+
+    pub fn with_emoji_dir(mut self, emoji_dir: PathBuf) -> Self {
+        self.emoji_dir = emoji_dir;
+        self
+    }
+
+    pub fn with_themes_dir(mut self, themes_dir: PathBuf) -> Self {
+        self.themes_dir = themes_dir;
+        self
+    }
+
+    pub fn with_pdf_renderer(mut self, renderer: PdfRendererProcess) -> Self {
+        self.set_pdf_renderer(Some(renderer));
         self
     }
 
+    pub fn set_pdf_renderer(&mut self, renderer: Option<PdfRendererProcess>) {
+        self.pdf_renderer = renderer;
+    }
+
+    pub fn with_browser_path(self, browser_path: PathBuf) -> Self {
+        self.set_browser_path(Some(browser_path));
+        self
+    }
+
+    /// Chrome, Chromium or Edge executable to print PDFs with
+    pub fn set_browser_path(&self, browser_path: Option<PathBuf>) {
+        *self.browser_path.write().unwrap() = browser_path;
+    }
+
+    /// mermaid-cli executable to draw diagrams with; found on `PATH` when unset
+    pub fn set_mermaid_cli(&self, mermaid_cli: Option<PathBuf>) {
+        *self.mermaid_cli.write().unwrap() = mermaid_cli;
+    }
+
+    /// Certificate and key for PDFs exported with `sign`
+    pub fn set_pdf_signer(&self, signer: Option<PdfSigner>) {
+        *self.pdf_signer.write().unwrap() = signer.map(Arc::new);
+    }
+
+    /// Scratch directory for intermediate and throwaway export files
+    pub fn temp_dir(&self) -> PathBuf {
+        self.temp_dir.read().unwrap().clone()
+    }
+
+    /// Exports still running, so quitting can wait for them
+    pub fn active_jobs(&self) -> &InFlight {
+        &self.jobs
+    }
+
+    /// Move scratch files to `temp_dir`, or back to the system temp folder
+    pub fn set_temp_dir(&self, temp_dir: Option<PathBuf>) {
+        *self.temp_dir.write().unwrap() = temp_dir.unwrap_or_else(default_temp_dir);
+    }
+
+    /// Add a strict CSP to exported documents and strip event handlers from their content
+    pub fn set_content_security(&self, enabled: bool) {
+        self.content_security.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Policy for exported documents, if they get one
+    fn csp(&self) -> Option<&'static str> {
+        self.content_security.load(Ordering::Relaxed).then_some(STRICT_CSP)
+    }
+
+    /// A fresh scratch folder for one export job
+    pub async fn create_job_dir(&self) -> Result<ExportJobDir> {
+        let path = self.temp_dir().join(format!("job-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&path).await
+            .with_context(|| format!("Failed to create export scratch folder: {:?}", path))?;
+        Ok(ExportJobDir { path })
+    }
+
+    /// Remove scratch files older than `max_age`, e.g. left behind by a crash
+    ///
+    /// Only entries exports create are touched, since the folder may be one the
+    /// user keeps other files in. Returns how many files and folders were removed.
+    pub async fn cleanup_stale_temp_files(&self, max_age: Duration) -> Result<usize> {
+        let temp_dir = self.temp_dir();
+        let mut entries = match tokio::fs::read_dir(&temp_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read export scratch folder: {:?}", temp_dir)),
+        };
+
+        let mut removed = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let metadata = entry.metadata().await?;
+            if !is_scratch_entry(&entry.file_name().to_string_lossy(), metadata.is_dir()) {
+                continue;
+            }
+            let age = metadata.modified().ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .unwrap_or_default();
+            if age < max_age {
+                continue;
+            }
+
+            let result = if metadata.is_dir() {
+                tokio::fs::remove_dir_all(&path).await
+            } else {
+                tokio::fs::remove_file(&path).await
+            };
+            match result {
+                Ok(()) => removed += 1,
+                Err(e) => warn!("Failed to remove stale export file {:?}: {}", path, e),
+            }
+        }
+
+        if removed > 0 {
+            info!("Removed {} stale export files from {:?}", removed, temp_dir);
+        }
+        Ok(removed)
+    }
+
     /// Export markdown content to the specified format
     pub async fn export(
         &self,
         html_content: &str,
         output_path: &Path,
         options: ExportOptions,
+    ) -> Result<ExportResult> {
+        self.export_job(html_content, output_path, options, &|_| {}, &CancellationToken::new()).await
+    }
+
+    /// Like [`Self::export`], passing `progress` each stage the export reaches and
+    /// giving up at the next stage, or during PDF rendering, once `cancel` is cancelled
+    pub async fn export_job(
+        &self,
+        html_content: &str,
+        output_path: &Path,
+        mut options: ExportOptions,
+        progress: &ProgressFn,
+        cancel: &CancellationToken,
     ) -> Result<ExportResult> {
         let start_time = std::time::Instant::now();
+        let mut timer = PhaseTimer::start();
+        let _in_flight = self.jobs.begin();
+        let job = JobControl { output_path, report: progress, cancel };
         
         debug!("Starting export to {:?} with format {:?}", output_path, options.format);
+        job.stage(ExportStage::RenderingHtml)?;
+        options.fonts.load(options.base_dir.as_deref()).await?;
 
-        let result = match options.format {
-            ExportFormat::Pdf => self.export_to_pdf(html_content, output_path, &options).await,
-            ExportFormat::Html => self.export_to_html(html_content, output_path, &options).await,
-            ExportFormat::Docx => {
-                return Err(anyhow::anyhow!("DOCX export not yet implemented"));
+        let hardened;
+        let html_content = if self.content_security.load(Ordering::Relaxed) {
+            hardened = strip_event_handlers(html_content);
+            hardened.as_str()
+        } else {
+            html_content
+        };
+        let with_emoji;
+        let html_content = if options.emoji_images {
+            with_emoji = substitute_emoji(html_content, &self.emoji_dir).await.html;
+            timer.lap("emoji");
+            with_emoji.as_str()
+        } else {
+            html_content
+        };
+        let with_math;
+        let html_content = match options.format {
+            ExportFormat::Latex => html_content,
+            _ => {
+                let html = html_content.to_string();
+                with_math = tokio::task::spawn_blocking(move || render_math_html(&html)).await?;
+                timer.lap("math");
+                with_math.as_str()
+            }
+        };
+        let with_diagrams;
+        let html_content = match options.format {
+            ExportFormat::Pdf | ExportFormat::Html | ExportFormat::Epub if has_mermaid_blocks(html_content) => {
+                with_diagrams = self.render_diagrams(html_content, &job).await?;
+                timer.lap("diagrams");
+                with_diagrams.as_str()
             }
+            _ => html_content,
+        };
+        let highlighted;
+        let html_content = match options.format {
+            ExportFormat::Pdf | ExportFormat::Html => {
+                highlighted = self.highlighter.highlight_code_blocks(html_content);
+                timer.lap("highlighting");
+                highlighted.as_str()
+            }
+            _ => html_content,
+        };
+
+        let result = match options.format {
+            ExportFormat::Pdf => self.export_to_pdf(html_content, output_path, &options, &mut timer, &job).await,
+            ExportFormat::Html => self.export_to_html(html_content, output_path, &options, &mut timer, &job).await,
+            ExportFormat::Docx => self.export_to_docx(html_content, output_path, &options, &mut timer, &job).await,
+            ExportFormat::Epub => self.export_to_epub(html_content, output_path, &options, &mut timer, &job).await,
+            ExportFormat::Latex => self.export_to_latex(output_path, &options, &mut timer, &job).await,
         }?;
+        job.done();
 
         let export_time_ms = start_time.elapsed().as_millis() as u64;
         
@@ -120,6 +821,7 @@ impl ExportService {
             file_size: result.file_size,
             pages: result.pages,
             export_time_ms,
+            timings: timer.finish(),
         })
     }
 
@@ -129,24 +831,54 @@ impl ExportService {
         html_content: &str,
         output_path: &Path,
         options: &ExportOptions,
+        timer: &mut PhaseTimer,
+        job: &JobControl<'_>,
     ) -> Result<ExportResult> {
-        // Create a complete HTML document with CSS
-        let full_html = self.create_complete_html(html_content, options)?;
-        
-        // Write HTML to temporary file
-        let temp_html_path = self.temp_dir.join(format!("export-{}.html", uuid::Uuid::new_v4()));
-        tokio::fs::write(&temp_html_path, full_html).await
-            .with_context(|| "Failed to write temporary HTML file")?;
+        // Stream the HTML to a temporary file for the renderer, removed with the job
+        // folder however the export ends
+        let job_dir = self.create_job_dir().await?;
+        let temp_html_path = job_dir.path().join("export.html");
+        self.write_complete_html(html_content, options, &temp_html_path).await
+            .context("Failed to write temporary HTML file")?;
+        timer.lap("html");
+
+        job.stage(ExportStage::GeneratingPages)?;
+        let mut result = self.generate_pdf(&temp_html_path, output_path, job).await?;
+        timer.lap("pdf");
+
+        // The renderer can't number pages from CSS, so the table of contents takes its
+        // page numbers from the first printing and the document is printed again
+        if options.include_toc {
+            let pdf = tokio::fs::read(output_path).await
+                .with_context(|| format!("Failed to read rendered PDF: {:?}", output_path))?;
+            match heading_pages(pdf, &outline_headings(html_content)) {
+                Ok(pages) if !pages.is_empty() => {
+                    let numbered = ExportOptions { toc_pages: pages, ..options.clone() };
+                    self.write_complete_html(html_content, &numbered, &temp_html_path).await
+                        .context("Failed to write temporary HTML file")?;
+                    result = self.generate_pdf(&temp_html_path, output_path, job).await?;
+                    timer.lap("toc");
+                }
+                Ok(_) => warn!("No headings were found in {:?} to number the table of contents", output_path),
+                Err(e) => warn!("Leaving the table of contents of {:?} without page numbers: {}", output_path, e),
+            }
+        }
+
+        job.stage(ExportStage::WritingFile)?;
+        result.file_size = self.write_pdf_metadata(html_content, output_path, options).await?;
+        timer.lap("metadata");
 
-        // For now, we'll simulate PDF generation
-        // In a real implementation, you would use a library like wkhtmltopdf, Chromium Headless, or similar
-        let result = self.generate_pdf_mock(&temp_html_path, output_path).await?;
+        result.file_size = self.write_pdf_outline(html_content, output_path).await?;
+        timer.lap("outline");
 
-        // Clean up temporary file
-        if let Err(e) = tokio::fs::remove_file(&temp_html_path).await {
-            warn!("Failed to clean up temporary HTML file: {}", e);
+        if options.sign {
+            result.file_size = self.sign_pdf(output_path).await?;
+            timer.lap("sign");
         }
 
+        drop(job_dir);
+        timer.lap("io");
+
         Ok(result)
     }
 
@@ -156,35 +888,311 @@ impl ExportService {
         html_content: &str,
         output_path: &Path,
         options: &ExportOptions,
+        timer: &mut PhaseTimer,
+        job: &JobControl<'_>,
     ) -> Result<ExportResult> {
-        let full_html = self.create_complete_html(html_content, options)?;
-        
-        tokio::fs::write(output_path, full_html).await
-            .with_context(|| format!("Failed to write HTML file: {:?}", output_path))?;
-
-        let file_size = tokio::fs::metadata(output_path).await?.len();
+        job.stage(ExportStage::WritingFile)?;
+        let file_size = self.write_complete_html(html_content, options, output_path).await?;
+        timer.lap("html");
 
         Ok(ExportResult {
             output_path: output_path.to_path_buf(),
             file_size,
             pages: 1, // HTML is single "page"
             export_time_ms: 0, // Will be calculated by caller
+            timings: Vec::new(),
+        })
+    }
+
+    /// Export to a Word document
+    async fn export_to_docx(
+        &self,
+        html_content: &str,
+        output_path: &Path,
+        options: &ExportOptions,
+        timer: &mut PhaseTimer,
+        job: &JobControl<'_>,
+    ) -> Result<ExportResult> {
+        let mut options = options.clone();
+        options.direction = options.direction.or_else(|| dominant_direction(&html_text(html_content)));
+        let html = html_content.to_string();
+        let docx = tokio::task::spawn_blocking(move || html_to_docx(&html, &options)).await??;
+        timer.lap("docx");
+
+        job.stage(ExportStage::WritingFile)?;
+        tokio::fs::write(output_path, &docx).await
+            .with_context(|| format!("Failed to write DOCX file: {:?}", output_path))?;
+        timer.lap("io");
+
+        Ok(ExportResult {
+            output_path: output_path.to_path_buf(),
+            file_size: docx.len() as u64,
+            pages: 1, // Word lays out the pages when the file is opened
+            export_time_ms: 0,
+            timings: Vec::new(),
+        })
+    }
+
+    /// Export to an EPUB book, one chapter per H1 or H2
+    async fn export_to_epub(
+        &self,
+        html_content: &str,
+        output_path: &Path,
+        options: &ExportOptions,
+        timer: &mut PhaseTimer,
+        job: &JobControl<'_>,
+    ) -> Result<ExportResult> {
+        let mut options = options.clone();
+        options.direction = options.direction.or_else(|| dominant_direction(&html_text(html_content)));
+        let html = html_content.to_string();
+        let book = tokio::task::spawn_blocking(move || html_to_epub(&html, &options)).await??;
+        timer.lap("epub");
+
+        job.stage(ExportStage::WritingFile)?;
+        tokio::fs::write(output_path, &book.data).await
+            .with_context(|| format!("Failed to write EPUB file: {:?}", output_path))?;
+        timer.lap("io");
+
+        Ok(ExportResult {
+            output_path: output_path.to_path_buf(),
+            file_size: book.data.len() as u64,
+            pages: book.chapters as u32, // Reading systems paginate, so count chapters
+            export_time_ms: 0,
+            timings: Vec::new(),
+        })
+    }
+
+    /// Export to a LaTeX source file, converted from the document's markdown
+    async fn export_to_latex(
+        &self,
+        output_path: &Path,
+        options: &ExportOptions,
+        timer: &mut PhaseTimer,
+        job: &JobControl<'_>,
+    ) -> Result<ExportResult> {
+        let markdown = options.markdown.as_deref()
+            .context("LaTeX export needs the document's markdown")?;
+        let latex = markdown_to_latex(markdown, options);
+        timer.lap("latex");
+
+        job.stage(ExportStage::WritingFile)?;
+        tokio::fs::write(output_path, &latex).await
+            .with_context(|| format!("Failed to write LaTeX file: {:?}", output_path))?;
+        timer.lap("io");
+
+        Ok(ExportResult {
+            output_path: output_path.to_path_buf(),
+            file_size: latex.len() as u64,
+            pages: 1, // TeX lays out the pages when the file is compiled
+            export_time_ms: 0,
+            timings: Vec::new(),
         })
     }
 
+    /// Render the document as it would be printed, for display before a PDF export or print job
+    ///
+    /// The page content is laid out on a sheet matching the configured page size and
+    /// margins, with a guide line drawn at every page boundary.
+    pub fn render_print_preview(&self, html_content: &str, options: &ExportOptions) -> Result<String> {
+        debug!("Rendering print preview with page size {:?}", options.page_size);
+
+        let (width, height) = options.page_size.dimensions();
+        let margins = &options.margins;
+        let preview_css = format!(
+            r#"<style>
+        @media screen {{
+            html {{ background: #e5e5e5; }}
+            body {{ padding: 24px 0; }}
+            .document {{
+                box-sizing: border-box;
+                width: {width}in;
+                min-height: {height}in;
+                margin: 0 auto;
+                padding: {top}in {right}in {bottom}in {left}in;
+                background-color: #fff;
+                background-image: repeating-linear-gradient(to bottom, transparent 0, transparent calc({height}in - 1px), #bbb calc({height}in - 1px), #bbb {height}in);
+                box-shadow: 0 2px 8px rgba(0, 0, 0, 0.2);
+            }}
+        }}
+    </style>
+</head>"#,
+            width = width,
+            height = height,
+            top = margins.top,
+            right = margins.right,
+            bottom = margins.bottom,
+            left = margins.left
+        );
+
+        let html_content = self.highlighter.highlight_code_blocks(&render_math_html(html_content));
+        let full_html = self.create_complete_html(&html_content, options)?;
+        let preview = full_html
+            .replacen("</head>", &preview_css, 1)
+            .replacen(
+                "<div class=\"document\">",
+                &format!(
+                    "<div class=\"document\" data-page-size=\"{}\" data-page-width=\"{}in\" data-page-height=\"{}in\">",
+                    options.page_size.css_name(),
+                    width,
+                    height
+                ),
+                1,
+            );
+
+        Ok(preview)
+    }
+
+    /// Export options last used for a document, if any were saved
+    pub async fn load_document_options(&self, document: &Path) -> Result<Option<ExportOptions>> {
+        let sidecar = document_options_path(document);
+        match tokio::fs::read_to_string(&sidecar).await {
+            Ok(content) => {
+                let options = serde_json::from_str(&content)
+                    .with_context(|| format!("Invalid export options file: {:?}", sidecar))?;
+                Ok(Some(options))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read export options: {:?}", sidecar)),
+        }
+    }
+
+    /// Built-in themes, then the user's own, by name
+    pub fn list_themes(&self) -> Vec<ExportTheme> {
+        let user_themes = self.user_themes();
+        let mut themes: Vec<_> = BUILT_IN_THEMES.iter()
+            .filter(|(name, _)| !user_themes.iter().any(|(user, _)| user.eq_ignore_ascii_case(name)))
+            .map(|(name, _)| ExportTheme { name: name.to_string(), built_in: true })
+            .collect();
+        themes.extend(user_themes.into_iter().map(|(name, _)| ExportTheme { name, built_in: false }));
+        themes
+    }
+
+    /// Themes `code_theme` can name
+    pub fn code_themes(&self) -> Vec<String> {
+        self.highlighter.themes()
+    }
+
+    /// CSS for the theme `css_theme` names, ignoring case: a user theme in the
+    /// themes folder, else a built-in one. Stylesheet text is used as it is, as
+    /// older settings stored the theme's CSS itself.
+    pub fn theme_css(&self, theme: &str) -> Result<String> {
+        if theme.contains('{') {
+            return Ok(theme.to_string());
+        }
+        let name = theme.trim();
+        let name = name.strip_suffix(".css").unwrap_or(name);
+
+        if let Some((_, path)) = self.user_themes().into_iter().find(|(user, _)| user.eq_ignore_ascii_case(name)) {
+            let css = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read export theme: {:?}", path))?;
+            // It goes into a <style> element, which it mustn't be able to close
+            if css.to_ascii_lowercase().contains("</style") {
+                anyhow::bail!("Export theme {:?} contains </style>", path);
+            }
+            return Ok(css);
+        }
+        BUILT_IN_THEMES.iter()
+            .find(|(built_in, _)| built_in.eq_ignore_ascii_case(name))
+            .map(|(_, css)| css.to_string())
+            .with_context(|| format!("Unknown export theme: {}", theme))
+    }
+
+    /// The `.css` files in the themes folder, by name
+    fn user_themes(&self) -> Vec<(String, PathBuf)> {
+        let mut themes: Vec<_> = std::fs::read_dir(&self.themes_dir)
+            .map(|entries| entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("css")))
+                .filter_map(|path| Some((path.file_stem()?.to_string_lossy().to_string(), path)))
+                .collect())
+            .unwrap_or_default();
+        themes.sort_by_key(|(name, _)| name.to_lowercase());
+        themes
+    }
+
+    /// Remember the export options for a document in a sidecar file next to it
+    pub async fn save_document_options(&self, document: &Path, options: &ExportOptions) -> Result<()> {
+        let sidecar = document_options_path(document);
+        let json = serde_json::to_string_pretty(options)?;
+        tokio::fs::write(&sidecar, json).await
+            .with_context(|| format!("Failed to write export options: {:?}", sidecar))?;
+
+        debug!("Saved export options for {:?} to {:?}", document, sidecar);
+        Ok(())
+    }
+
     /// Create a complete HTML document with styling
-    fn create_complete_html(&self, content: &str, options: &ExportOptions) -> Result<String> {
-        let css = self.get_export_css(options)?;
+    pub fn create_complete_html(&self, content: &str, options: &ExportOptions) -> Result<String> {
+        self.create_complete_html_with_csp(content, options, self.csp())
+    }
+
+    /// Like [`Self::create_complete_html`], with `csp` as the page's policy instead
+    /// of the export one
+    pub fn create_complete_html_with_csp(&self, content: &str, options: &ExportOptions, csp: Option<&str>) -> Result<String> {
+        let head = self.document_head(content, options, csp)?;
+        let mut html = String::with_capacity(head.len() + content.len() + DOCUMENT_TAIL.len());
+        html.push_str(&head);
+        html.push_str(content);
+        html.push_str(DOCUMENT_TAIL);
+        Ok(html)
+    }
+
+    /// Write the same document as [`Self::create_complete_html`] to `path`, streaming
+    /// the body in chunks instead of assembling a second copy of it in memory
+    ///
+    /// Returns the number of bytes written.
+    pub async fn write_complete_html(&self, content: &str, options: &ExportOptions, path: &Path) -> Result<u64> {
+        let head = self.document_head(content, options, self.csp())?;
+        let file = tokio::fs::File::create(path).await
+            .with_context(|| format!("Failed to create HTML file: {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+
+        writer.write_all(head.as_bytes()).await?;
+        for chunk in content.as_bytes().chunks(EXPORT_CHUNK_BYTES) {
+            writer.write_all(chunk).await
+                .with_context(|| format!("Failed to write HTML file: {:?}", path))?;
+        }
+        writer.write_all(DOCUMENT_TAIL.as_bytes()).await?;
+        writer.flush().await
+            .with_context(|| format!("Failed to write HTML file: {:?}", path))?;
+
+        Ok((head.len() + content.len() + DOCUMENT_TAIL.len()) as u64)
+    }
+
+    /// Everything in the exported document up to the body content
+    fn document_head(&self, content: &str, options: &ExportOptions, csp: Option<&str>) -> Result<String> {
+        let direction = options.direction
+            .or_else(|| dominant_direction(&html_text(content)))
+            .unwrap_or_default();
+        let title = options.title.clone().or_else(|| first_heading(content)).unwrap_or_default();
+        let values = TemplateValues {
+            title: &title,
+            date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+            file_name: options.file_name.as_deref().unwrap_or_default(),
+        };
+        let css = self.get_export_css(options, direction, &values)?;
         let toc = if options.include_toc {
-            self.generate_toc_from_html(content)?
+            let pages = matches!(options.format, ExportFormat::Pdf).then_some(&options.toc_pages);
+            self.generate_toc_from_html(content, pages)?
         } else {
             String::new()
         };
+        let watermark = options.watermark.as_ref()
+            .map(|watermark| watermark_html(watermark, options.base_dir.as_deref()))
+            .unwrap_or_default();
+        let outline_anchors = match options.format {
+            ExportFormat::Pdf => outline_anchors(&outline_headings(content)),
+            _ => String::new(),
+        };
+
+        let csp = csp
+            .map(|policy| format!("\n    <meta http-equiv=\"Content-Security-Policy\" content=\"{}\">", policy))
+            .unwrap_or_default();
 
-        let html = format!(
+        Ok(format!(
             r#"<!DOCTYPE html>
-<html lang="en">
-<head>
+<html lang="en" dir="{}">
+<head>{}
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
     <title>Exported Document</title>
@@ -194,29 +1202,39 @@ impl ExportService {
 </head>
 <body>
     <div class="document">
-        {}
+        {}{}{}
         <div class="content">
-            {}
-        </div>
-    </div>
-</body>
-</html>"#,
+            "#,
+            direction.as_str(),
+            csp,
             css,
-            toc,
-            content
-        );
-
-        Ok(html)
+            watermark,
+            outline_anchors,
+            toc
+        ))
     }
 
     /// Get CSS styles for export
-    fn get_export_css(&self, options: &ExportOptions) -> Result<String> {
+    fn get_export_css(&self, options: &ExportOptions, direction: TextDirection, values: &TemplateValues) -> Result<String> {
+        let margin_boxes: String = [("top-center", &options.header), ("bottom-center", &options.footer)].into_iter()
+            .filter_map(|(margin_box, template)| Some((margin_box, template.as_deref().filter(|t| !t.trim().is_empty())?)))
+            .map(|(margin_box, template)| format!(
+                "\n            @{} {{ content: {}; font-size: 9pt; color: #666; }}",
+                margin_box,
+                margin_box_content(template, values)
+            ))
+            .collect();
+        let page_css = format!(
+            "@page {{\n            size: {};\n            margin: {}in {}in {}in {}in;{}\n        }}",
+            options.page_size.css_name(),
+            options.margins.top,
+            options.margins.right,
+            options.margins.bottom,
+            options.margins.left,
+            margin_boxes
+        );
+
         let base_css = r#"
-        @page {
-            margin: 1in;
-            size: A4;
-        }
-        
         body {
             font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, "Helvetica Neue", Arial, sans-serif;
             font-size: 12pt;
@@ -303,6 +1321,36 @@ impl ExportService {
             page-break-inside: avoid;
         }
         
+        .qrcode {
+            margin: 1em 0;
+            page-break-inside: avoid;
+        }
+        
+        .mermaid-diagram {
+            margin: 1em 0;
+            text-align: center;
+            page-break-inside: avoid;
+        }
+        
+        .mermaid-diagram svg {
+            max-width: 100%;
+            height: auto;
+        }
+        
+        .katex-display {
+            display: block;
+            margin: 1em 0;
+            text-align: center;
+            page-break-inside: avoid;
+        }
+        
+        img.emoji {
+            height: 1em;
+            width: 1em;
+            margin: 0 0.05em 0 0.1em;
+            vertical-align: -0.1em;
+        }
+        
         .toc {
             page-break-after: always;
             margin-bottom: 2em;
@@ -330,6 +1378,75 @@ impl ExportService {
             text-decoration: underline;
         }
         
+        .toc li a {
+            display: flex;
+            align-items: baseline;
+        }
+        
+        .toc-leader {
+            flex: 1;
+            margin: 0 0.4em;
+            border-bottom: 1px dotted currentColor;
+        }
+        
+        .toc-page {
+            min-width: 2em;
+            text-align: end;
+        }
+        
+        .toc .toc-level-2 { padding-inline-start: 1.5em; }
+        .toc .toc-level-3 { padding-inline-start: 3em; }
+        .toc .toc-level-4 { padding-inline-start: 4.5em; }
+        .toc .toc-level-5 { padding-inline-start: 6em; }
+        .toc .toc-level-6 { padding-inline-start: 7.5em; }
+        
+        .watermark {
+            position: fixed;
+            top: 50%;
+            left: 50%;
+            z-index: 1000;
+            pointer-events: none;
+            white-space: nowrap;
+            font-size: 96pt;
+            font-weight: 700;
+            color: #888;
+        }
+        
+        .watermark img {
+            display: block;
+            max-width: 60vw;
+            max-height: 60vh;
+        }
+        
+        .pdf-outline {
+            position: absolute;
+            width: 1px;
+            height: 1px;
+            overflow: hidden;
+            clip: rect(0 0 0 0);
+        }
+        
+        .footnotes {
+            margin-top: 2em;
+            font-size: 0.9em;
+        }
+        
+        .footnote-definition {
+            display: flex;
+            gap: 0.5em;
+            margin: 0.5em 0;
+        }
+        
+        .footnote-definition p {
+            margin: 0;
+        }
+        
+        .footnote-reference a,
+        .footnote-backref {
+            text-decoration: none;
+            color: #0366d6;
+        }
+        
         @media print {
             .no-print {
                 display: none;
@@ -337,100 +1454,359 @@ impl ExportService {
         }
         "#;
 
-        // Apply custom theme CSS if provided
-        let css = if let Some(theme_css) = &options.css_theme {
-            format!("{}\n\n/* Custom Theme */\n{}", base_css, theme_css)
+        // Mirror the sides the base styles put rules and indents on
+        let direction_css = match direction {
+            TextDirection::Rtl => RTL_CSS,
+            TextDirection::Ltr => "",
+        };
+
+        // Apply the theme if one is chosen
+        let mut css = if let Some(theme) = options.css_theme.as_deref().filter(|theme| !theme.trim().is_empty()) {
+            format!("{}\n{}{}\n\n/* Theme */\n{}", page_css, base_css, direction_css, self.theme_css(theme)?)
         } else {
-            base_css.to_string()
+            format!("{}\n{}{}", page_css, base_css, direction_css)
         };
+        // After the theme, so code keeps the background its colors were chosen for
+        let code_theme = options.code_theme.as_deref()
+            .filter(|theme| !theme.trim().is_empty())
+            .unwrap_or(DEFAULT_CODE_THEME);
+        css.push_str(&format!("\n\n/* Code */\n{}", self.highlighter.theme_css(code_theme)?));
+        let fonts = font_css(&options.fonts)?;
+        if !fonts.is_empty() {
+            css.push_str(&format!("\n\n/* Fonts */{}", fonts));
+        }
+        if let Some(document_css) = &options.document_css {
+            css.push_str(&format!("\n\n/* Document */\n{}", document_css));
+        }
 
         Ok(css)
     }
 
     /// Generate table of contents from HTML content
-    fn generate_toc_from_html(&self, html: &str) -> Result<String> {
-        // Simple TOC generation - in a real implementation, you'd use an HTML parser
-        let mut toc_items = Vec::new();
-        
-        for line in html.lines() {
-            if let Some(heading) = self.extract_heading_from_line(line) {
-                toc_items.push(heading);
-            }
+    ///
+    /// With `pages`, each entry ends in leader dots and the page its heading is on;
+    /// the space for the number is kept while the pages aren't known yet, so filling
+    /// them in doesn't move anything.
+    fn generate_toc_from_html(&self, html: &str, pages: Option<&HashMap<String, u32>>) -> Result<String> {
+        let headings = document_headings(html);
+        if headings.is_empty() {
+            return Ok(String::new());
         }
 
-        if toc_items.is_empty() {
-            return Ok(String::new());
+        let mut toc_items = String::new();
+        for heading in &headings {
+            let title = html_escape::encode_text(&heading.title);
+            let entry = match pages {
+                Some(pages) => format!(
+                    "<span class=\"toc-title\">{}</span><span class=\"toc-leader\"></span><span class=\"toc-page\">{}</span>",
+                    title,
+                    pages.get(&heading.anchor).map(u32::to_string).unwrap_or_default()
+                ),
+                None => format!("<span class=\"toc-title\">{}</span>", title),
+            };
+            // Headings without an id have nothing to link to
+            let entry = if heading.anchor.is_empty() {
+                entry
+            } else {
+                format!("<a href=\"#{}\">{}</a>", html_escape::encode_double_quoted_attribute(&heading.anchor), entry)
+            };
+            let _ = write!(toc_items, "\n                    <li class=\"toc-level-{}\">{}</li>", heading.level, entry);
         }
 
         let toc_html = format!(
             r#"<div class="toc">
                 <h2>Table of Contents</h2>
-                <ul>
-                    {}
+                <ul>{}
                 </ul>
             </div>"#,
-            toc_items.join("\n")
+            toc_items
         );
 
         Ok(toc_html)
     }
 
-    /// Extract heading information from HTML line
-    fn extract_heading_from_line(&self, line: &str) -> Option<String> {
-        // This is a simplified implementation
-        // In practice, you'd use a proper HTML parser like scraper or html5ever
-        
-        if line.trim_start().starts_with("<h") && line.contains('>') {
-            // Extract heading level and content
-            if let Some(start) = line.find('>') {
-                if let Some(end) = line.find("</h") {
-                    let content = &line[start + 1..end];
-                    let level: usize = if line.contains("<h1") { 1 }
-                    else if line.contains("<h2") { 2 }
-                    else if line.contains("<h3") { 3 }
-                    else if line.contains("<h4") { 4 }
-                    else if line.contains("<h5") { 5 }
-                    else if line.contains("<h6") { 6 }
-                    else { return None; };
-                    
-                    let indent = "  ".repeat(level.saturating_sub(1));
-                    return Some(format!("{}<li><a href=\"#{}\">{}</a></li>", 
-                                       indent, 
-                                       content.to_lowercase().replace(' ', "-"),
-                                       content));
-                }
-            }
+    /// Write the document properties into the PDF at `path`, returning its new size
+    async fn write_pdf_metadata(&self, html_content: &str, path: &Path, options: &ExportOptions) -> Result<u64> {
+        let now = chrono::Local::now().fixed_offset();
+        let created = options.created.as_deref()
+            .and_then(|date| parse_date(date).or_else(|| {
+                warn!("Using the export time for unrecognised creation date: {}", date);
+                None
+            }))
+            .unwrap_or(now);
+        let metadata = PdfMetadata {
+            title: options.title.clone().or_else(|| first_heading(html_content)),
+            authors: options.authors.clone(),
+            subject: options.subject.clone(),
+            keywords: options.keywords.clone(),
+            created,
+        };
+        let pdf = tokio::fs::read(path).await
+            .with_context(|| format!("Failed to read PDF for its metadata: {:?}", path))?;
+        let updated = tokio::task::spawn_blocking(move || write_metadata(pdf, &metadata, now)).await??;
+        tokio::fs::write(path, &updated).await
+            .with_context(|| format!("Failed to write PDF metadata: {:?}", path))?;
+        Ok(updated.len() as u64)
+    }
+
+    /// Add bookmarks for the document's headings to the PDF at `path`, returning its new size
+    async fn write_pdf_outline(&self, html_content: &str, path: &Path) -> Result<u64> {
+        let headings = outline_headings(html_content);
+        let pdf = tokio::fs::read(path).await
+            .with_context(|| format!("Failed to read PDF for its outline: {:?}", path))?;
+        let updated = tokio::task::spawn_blocking(move || write_outline(pdf, &headings)).await??;
+        tokio::fs::write(path, &updated).await
+            .with_context(|| format!("Failed to write PDF outline: {:?}", path))?;
+        Ok(updated.len() as u64)
+    }
+
+    /// Sign the PDF at `path` in place, returning its new size
+    async fn sign_pdf(&self, path: &Path) -> Result<u64> {
+        let signer = self.pdf_signer.read().unwrap().clone()
+            .context("Set up a signing certificate to export signed PDFs")?;
+        let pdf = tokio::fs::read(path).await
+            .with_context(|| format!("Failed to read PDF for signing: {:?}", path))?;
+        let signed = tokio::task::spawn_blocking(move || signer.sign(pdf, chrono::Utc::now())).await??;
+        tokio::fs::write(path, &signed).await
+            .with_context(|| format!("Failed to write signed PDF: {:?}", path))?;
+
+        info!("Signed PDF: {:?}", path);
+        Ok(signed.len() as u64)
+    }
+
+    /// `html` with its mermaid diagrams drawn, or as it is when mermaid-cli isn't
+    /// installed
+    async fn render_diagrams(&self, html: &str, job: &JobControl<'_>) -> Result<String> {
+        let configured = self.mermaid_cli.read().unwrap().clone();
+        let Some(cli) = find_mermaid_cli(configured.as_deref())? else {
+            warn!("mermaid-cli (mmdc) isn't installed; exporting diagrams as code");
+            return Ok(html.to_string());
+        };
+        let configured_browser = self.browser_path.read().unwrap().clone();
+        let browser = find_browser(configured_browser.as_deref()).ok();
+        let job_dir = self.create_job_dir().await?;
+        // Dropping the render kills mermaid-cli
+        tokio::select! {
+            rendered = render_mermaid_blocks(html, &cli, browser.as_deref(), job_dir.path()) => rendered,
+            _ = job.cancel.cancelled() => anyhow::bail!(EXPORT_CANCELLED),
         }
-        None
     }
 
-    /// Mock PDF generation (placeholder implementation)
-    async fn generate_pdf_mock(
+    /// Print the HTML file to PDF with a headless browser, in the renderer
+    /// process if there is one
+    async fn generate_pdf(
         &self,
-        _html_path: &Path,
+        html_path: &Path,
         output_path: &Path,
+        job: &JobControl<'_>,
     ) -> Result<ExportResult> {
-        // In a real implementation, this would call a PDF generation library
-        // For now, we'll just create a placeholder file
-        
-        let placeholder_pdf = b"%PDF-1.4\n1 0 obj\n<<\n/Type /Catalog\n/Pages 2 0 R\n>>\nendobj\n2 0 obj\n<<\n/Type /Pages\n/Kids [3 0 R]\n/Count 1\n>>\nendobj\n3 0 obj\n<<\n/Type /Page\n/Parent 2 0 R\n/MediaBox [0 0 612 792]\n/Contents 4 0 R\n>>\nendobj\n4 0 obj\n<<\n/Length 44\n>>\nstream\nBT\n/F1 12 Tf\n72 720 Td\n(Typora-Lite Export) Tj\nET\nendstream\nendobj\nxref\n0 5\n0000000000 65535 f \n0000000009 00000 n \n0000000058 00000 n \n0000000115 00000 n \n0000000206 00000 n \ntrailer\n<<\n/Size 5\n/Root 1 0 R\n>>\nstartxref\n299\n%%EOF";
-        
-        tokio::fs::write(output_path, placeholder_pdf).await
-            .with_context(|| format!("Failed to write PDF file: {:?}", output_path))?;
+        let configured = self.browser_path.read().unwrap().clone();
+        let browser = find_browser(configured.as_deref())?;
+        let render = async {
+            match &self.pdf_renderer {
+                Some(renderer) => renderer.render(html_path, output_path, &browser).await,
+                None => render_pdf_async(html_path, output_path, &browser).await,
+            }
+        };
+        // Dropping the render kills the browser or renderer process
+        tokio::select! {
+            rendered = render => rendered?,
+            _ = job.cancel.cancelled() => anyhow::bail!(EXPORT_CANCELLED),
+        }
 
-        let file_size = tokio::fs::metadata(output_path).await?.len();
+        let pdf = tokio::fs::read(output_path).await
+            .with_context(|| format!("Failed to read rendered PDF: {:?}", output_path))?;
+        let file_size = pdf.len() as u64;
+        let pages = match PdfFile::parse(pdf).and_then(|pdf| pdf.page_count()) {
+            Ok(pages) => pages as u32,
+            Err(e) => {
+                warn!("Failed to count the pages of {:?}: {}", output_path, e);
+                1
+            }
+        };
 
-        info!("Generated mock PDF: {:?} ({} bytes)", output_path, file_size);
+        info!("Generated PDF: {:?} ({} pages, {} bytes)", output_path, pages, file_size);
 
         Ok(ExportResult {
             output_path: output_path.to_path_buf(),
             file_size,
-            pages: 1, // Mock single page
+            pages,
             export_time_ms: 0, // Will be calculated by caller
+            timings: Vec::new(),
         })
     }
 }
 
+/// `typolite-exports` in the system temp folder
+pub fn default_temp_dir() -> PathBuf {
+    std::env::temp_dir().join("typolite-exports")
+}
+
+/// Whether an entry of the scratch folder was created by an export: a `job-<uuid>`
+/// folder, a `<uuid>` folder of an emailed attachment or a `<uuid>.<ext>` API export
+fn is_scratch_entry(name: &str, is_dir: bool) -> bool {
+    let id = match name.split_once('.') {
+        None if is_dir => name.strip_prefix("job-").unwrap_or(name),
+        Some((id, _)) if !is_dir => id,
+        _ => return false,
+    };
+    uuid::Uuid::parse_str(id).is_ok()
+}
+
+/// Hidden sidecar holding a document's export options, e.g. `notes/.todo.md.export.json`
+pub fn document_options_path(document: &Path) -> PathBuf {
+    let file_name = document.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    document.with_file_name(format!(".{}.export.json", file_name))
+}
+
+/// Values of the header and footer template variables other than the page numbers
+struct TemplateValues<'a> {
+    title: &'a str,
+    date: String,
+    file_name: &'a str,
+}
+
+/// A header or footer template as the `content` of a page margin box, where
+/// the browser fills in `{page}` and `{pages}` as it lays out the pages.
+/// Unknown variables are printed as written.
+fn margin_box_content(template: &str, values: &TemplateValues) -> String {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else { break };
+        text.push_str(&rest[..open]);
+        match &rest[open + 1..close] {
+            counter @ ("page" | "pages") => {
+                if !text.is_empty() {
+                    parts.push(css_string(&std::mem::take(&mut text)));
+                }
+                parts.push(format!("counter({})", counter));
+            }
+            "title" => text.push_str(values.title),
+            "date" => text.push_str(&values.date),
+            "filename" => text.push_str(values.file_name),
+            _ => text.push_str(&rest[open..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(css_string(&text));
+    }
+    parts.join(" ")
+}
+
+/// The watermark element; fixed positioning repeats it on every printed page.
+/// Local images are inlined, as exports are rendered away from the document.
+fn watermark_html(watermark: &Watermark, base_dir: Option<&Path>) -> String {
+    let image = watermark.image.as_deref().filter(|src| !src.trim().is_empty()).and_then(|src| {
+        let data = read_image_source(src, base_dir)
+            .map_err(|e| warn!("Leaving out watermark image {}: {}", src, e))
+            .ok()?;
+        let Some((_, media_type)) = image_media_type(&data) else {
+            warn!("Leaving out watermark image {}: unsupported image format", src);
+            return None;
+        };
+        let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+        Some(format!("<img src=\"data:{};base64,{}\" alt=\"\">", media_type, encoded))
+    });
+    let content = match (image, watermark.text.as_deref().filter(|text| !text.trim().is_empty())) {
+        (Some(image), _) => image,
+        (None, Some(text)) => html_escape::encode_text(text).into_owned(),
+        (None, None) => return String::new(),
+    };
+    format!(
+        "<div class=\"watermark\" aria-hidden=\"true\" style=\"opacity: {}; transform: translate(-50%, -50%) rotate({}deg);\">{}</div>",
+        watermark.opacity.clamp(0.0, 1.0),
+        watermark.rotation,
+        content
+    )
+}
+
+/// `@font-face` rules embedding the chosen font files as data URLs, which PDF
+/// printing then embeds too, and rules setting the chosen families
+///
+/// The files must have been read with [`ExportFonts::load`].
+fn font_css(fonts: &ExportFonts) -> Result<String> {
+    let slots = [
+        (&fonts.body, "body", "sans-serif"),
+        (&fonts.heading, "h1, h2, h3, h4, h5, h6", "sans-serif"),
+        (&fonts.mono, "code, pre, kbd, samp", "monospace"),
+    ];
+    let mut css = String::new();
+    for (font, selector, generic) in slots {
+        let Some(font) = font.as_ref().filter(|font| !font.family.trim().is_empty()) else {
+            continue;
+        };
+        let family = css_string(font.family.trim());
+        if let Some(file) = &font.file {
+            let (media_type, format) = font_format(file)
+                .with_context(|| format!("Unsupported font file, expected TTF, OTF, WOFF or WOFF2: {:?}", file))?;
+            let data = font.data.as_ref()
+                .with_context(|| format!("Font file was not loaded: {:?}", file))?;
+            let _ = write!(
+                css,
+                "\n@font-face {{ font-family: {}; src: url(\"data:{};base64,{}\") format(\"{}\"); }}",
+                family,
+                media_type,
+                base64::engine::general_purpose::STANDARD.encode(data),
+                format
+            );
+        }
+        let _ = write!(css, "\n{} {{ font-family: {}, {}; }}", selector, family, generic);
+    }
+    Ok(css)
+}
+
+/// Media type and CSS `format()` of a font file, by its extension
+fn font_format(path: &Path) -> Option<(&'static str, &'static str)> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "ttf" => Some(("font/ttf", "truetype")),
+        "otf" => Some(("font/otf", "opentype")),
+        "woff" => Some(("font/woff", "woff")),
+        "woff2" => Some(("font/woff2", "woff2")),
+        _ => None,
+    }
+}
+
+/// `text` as a quoted CSS string, safe inside a `<style>` element: `<` is escaped
+/// so a `</style>` in it can't end the element
+fn css_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\A ")
+        .replace('<', "\\3C ");
+    format!("\"{}\"", escaped)
+}
+
+/// Text of the first `<h1>`, for the `{title}` of documents without one
+fn first_heading(html: &str) -> Option<String> {
+    let start = html.find("<h1")?;
+    let open_end = start + html[start..].find('>')? + 1;
+    let close = open_end + html[open_end..].find("</h1>")?;
+    let text = html_escape::decode_html_entities(&html_text(&html[open_end..close])).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// The text of an HTML fragment, without its tags, for detecting its direction
+fn html_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -441,34 +1817,305 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let service = ExportService::new().with_temp_dir(temp_dir.path().to_path_buf());
         
-        let html_content = "<h1>Test Document</h1><p>This is a test.</p>";
+        let html_content = "<h1>Test Document</h1><p onclick=\"steal()\">This is a test.</p>\
+            <pre><code class=\"language-python\">print(1)\n</code></pre><p>$x^2$</p>";
         let output_path = temp_dir.path().join("test.html");
-        let options = ExportOptions::default();
+        let options = ExportOptions { format: ExportFormat::Html, ..Default::default() };
 
-        let result = service.export(html_content, &output_path, options).await.unwrap();
+        let result = service.export(html_content, &output_path, options.clone()).await.unwrap();
 
         assert_eq!(result.output_path, output_path);
         assert!(result.file_size > 0);
-        assert!(output_path.exists());
+        let exported = std::fs::read_to_string(&output_path).unwrap();
+        assert!(exported.contains("<meta http-equiv=\"Content-Security-Policy\""));
+        assert!(exported.contains("<p>This is a test.</p>"));
+        assert!(exported.contains("<pre class=\"language-python hl-code\">"));
+        assert!(exported.contains("/* Code */"));
+        assert!(exported.contains("<annotation encoding=\"application/x-tex\">x^2</annotation>"));
+
+        service.set_content_security(false);
+        service.export(html_content, &output_path, options).await.unwrap();
+        let exported = std::fs::read_to_string(&output_path).unwrap();
+        assert!(!exported.contains("Content-Security-Policy"));
+        assert!(exported.contains("onclick"));
     }
 
     #[tokio::test]
-    async fn test_pdf_export_mock() {
+    async fn test_temp_file_cleanup() {
         let temp_dir = TempDir::new().unwrap();
-        let service = ExportService::new().with_temp_dir(temp_dir.path().to_path_buf());
+        let service = ExportService::new().with_temp_dir(temp_dir.path().join("scratch"));
+
+        let job = service.create_job_dir().await.unwrap();
+        let job_path = job.path().to_path_buf();
+        std::fs::write(job_path.join("export.html"), "<p>hi</p>").unwrap();
+        drop(job);
+        assert!(!job_path.exists());
+
+        let attachment_dir = service.temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&attachment_dir).unwrap();
+        std::fs::write(attachment_dir.join("notes.pdf"), "%PDF").unwrap();
+        std::fs::write(service.temp_dir().join(format!("{}.pdf", uuid::Uuid::new_v4())), "%PDF").unwrap();
+        std::fs::write(service.temp_dir().join("attachment.pdf"), "%PDF").unwrap();
+        std::fs::create_dir(service.temp_dir().join("job-photos")).unwrap();
+        assert_eq!(service.cleanup_stale_temp_files(Duration::from_secs(3600)).await.unwrap(), 0);
+        assert_eq!(service.cleanup_stale_temp_files(Duration::ZERO).await.unwrap(), 2);
+        // Not created by exports
+        assert_eq!(std::fs::read_dir(service.temp_dir()).unwrap().count(), 2);
+        assert!(service.temp_dir().join("attachment.pdf").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pdf_export() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+
+        // A stand-in for Chrome that keeps the page it was given and prints a two-page PDF
+        let fixture = temp_dir.path().join("fixture.pdf");
+        std::fs::write(&fixture, "%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n\
+            2 0 obj\n<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>\nendobj\n\
+            3 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n4 0 obj\n<< /Type /Page /Parent 2 0 R >>\nendobj\n\
+            trailer\n<< /Size 5 /Root 1 0 R >>\nstartxref\n0\n%%EOF\n").unwrap();
+        let page = temp_dir.path().join("page.html");
+        let browser = temp_dir.path().join("fake-chrome");
+        std::fs::write(&browser, format!(
+            "#!/bin/sh\nfor arg; do case \"$arg\" in\n  --print-to-pdf=*) cp '{}' \"${{arg#--print-to-pdf=}}\";;\n  file://*) cp \"${{arg#file://}}\" '{}';;\nesac; done\n",
+            fixture.display(), page.display(),
+        )).unwrap();
+        std::fs::set_permissions(&browser, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let service = ExportService::new()
+            .with_temp_dir(temp_dir.path().join("scratch"))
+            .with_browser_path(browser);
         
         let html_content = "<h1>Test Document</h1><p>This is a test.</p>";
         let output_path = temp_dir.path().join("test.pdf");
         let options = ExportOptions {
             format: ExportFormat::Pdf,
+            page_size: PageSize::Letter,
             ..Default::default()
         };
 
-        let result = service.export(html_content, &output_path, options).await.unwrap();
+        let stages = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = stages.clone();
+        let record = move |progress: ExportProgress| recorded.lock().unwrap().push((progress.stage, progress.fraction));
+        let result = service.export_job(html_content, &output_path, options.clone(), &record, &CancellationToken::new()).await.unwrap();
 
         assert_eq!(result.output_path, output_path);
+        assert_eq!(result.pages, 2);
+        assert_eq!(*stages.lock().unwrap(), vec![
+            (ExportStage::RenderingHtml, 0.0),
+            (ExportStage::GeneratingPages, 0.2),
+            (ExportStage::WritingFile, 0.9),
+            (ExportStage::WritingFile, 1.0),
+        ]);
         assert!(result.file_size > 0);
         assert!(output_path.exists());
+        let page = std::fs::read_to_string(&page).unwrap();
+        assert!(page.contains("<h1>Test Document</h1>"));
+        assert!(page.contains("size: letter;"), "{}", page);
+        // Rendered PDFs can take an incremental update for a signature
+        let pdf = crate::pdf_edit::PdfFile::parse(std::fs::read(&output_path).unwrap()).unwrap();
+        assert!(pdf.first_page().is_ok());
+
+        let signed = ExportOptions { sign: true, ..options };
+        let error = service.export(html_content, &output_path, signed).await.unwrap_err().to_string();
+        assert!(error.contains("signing certificate"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cancel_pdf_export() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        let browser = temp_dir.path().join("hung-chrome");
+        std::fs::write(&browser, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        std::fs::set_permissions(&browser, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let service = ExportService::new()
+            .with_temp_dir(temp_dir.path().join("scratch"))
+            .with_browser_path(browser);
+
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            canceller.cancel();
+        });
+        let output_path = temp_dir.path().join("long.pdf");
+        let export = service.export_job("<p>Long</p>", &output_path, ExportOptions::default(), &|_| {}, &cancel);
+        let error = tokio::time::timeout(Duration::from_secs(5), export).await.unwrap().unwrap_err();
+        assert_eq!(error.to_string(), EXPORT_CANCELLED);
+        assert_eq!(service.active_jobs().count(), 0);
+
+        // A job cancelled before it starts doesn't run at all
+        let error = service.export_job("<p>Long</p>", &output_path, ExportOptions::default(), &|_| {}, &cancel).await.unwrap_err();
+        assert_eq!(error.to_string(), EXPORT_CANCELLED);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_html_matches_in_memory_document() {
+        let temp_dir = TempDir::new().unwrap();
+        let service = ExportService::new();
+        let options = ExportOptions { include_toc: true, ..Default::default() };
+
+        // Several chunks long, with multi-byte characters straddling chunk boundaries
+        let content = format!("<h1>Big</h1>\n{}", "<p>Grüße, 世界 ✓</p>\n".repeat(40_000));
+        let path = temp_dir.path().join("big.html");
+        let written = service.write_complete_html(&content, &options, &path).await.unwrap();
+
+        let expected = service.create_complete_html(&content, &options).unwrap();
+        assert_eq!(written, expected.len() as u64);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_preview_uses_page_size() {
+        let service = ExportService::new();
+        let options = ExportOptions {
+            page_size: PageSize::Letter,
+            ..Default::default()
+        };
+
+        let preview = service.render_print_preview("<p>Hello</p>", &options).unwrap();
+
+        assert!(preview.contains("size: letter;"));
+        assert!(preview.contains("width: 8.5in;"));
+        assert!(preview.contains("data-page-size=\"letter\""));
+        assert!(preview.contains("<p>Hello</p>"));
+    }
+
+    #[test]
+    fn test_rtl_document_is_mirrored() {
+        let service = ExportService::new();
+        let content = "<h1 id=\"x\">שלום</h1><blockquote><p>ציטוט ארוך בעברית</p></blockquote>";
+
+        let html = service.create_complete_html(content, &ExportOptions::default()).unwrap();
+        assert!(html.contains("<html lang=\"en\" dir=\"rtl\">"));
+        assert!(html.contains("border-right: 4px solid"));
+
+        let options = ExportOptions { direction: Some(TextDirection::Ltr), ..Default::default() };
+        let html = service.create_complete_html(content, &options).unwrap();
+        assert!(html.contains("dir=\"ltr\">") && !html.contains("direction: rtl"));
+    }
+
+    #[test]
+    fn test_header_and_footer_templates() {
+        let service = ExportService::new();
+        let options = ExportOptions {
+            header: Some("{title} \"draft\" — {filename} {unknown}".to_string()),
+            file_name: Some("notes.md".to_string()),
+            ..Default::default()
+        };
+
+        let html = service.create_complete_html("<h1 id=\"a\">Field &amp; <em>Notes</em></h1>", &options).unwrap();
+        assert!(html.contains(r#"@top-center { content: "Field & Notes \"draft\" — notes.md {unknown}"; font-size: 9pt; color: #666; }"#));
+        assert!(html.contains(r#"@bottom-center { content: "Page " counter(page) " of " counter(pages); font-size: 9pt; color: #666; }"#));
+
+        let values = TemplateValues { title: "", date: "2024-05-01".to_string(), file_name: "" };
+        assert_eq!(margin_box_content("{page}/{pages}\n{date}", &values), r#"counter(page) "/" counter(pages) "\A 2024-05-01""#);
+        assert_eq!(margin_box_content("{title}", &values), "\"\"");
+
+        let values = TemplateValues { title: "</style><script>x</script>", date: String::new(), file_name: "" };
+        assert_eq!(margin_box_content("{title}", &values), r#""\3C /style>\3C script>x\3C /script>""#);
+    }
+
+    #[test]
+    fn test_export_themes() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("Dark.css"), "body { color: gold; }").unwrap();
+        std::fs::write(temp_dir.path().join("brand.css"), "h1 { color: teal; }").unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "").unwrap();
+        let service = ExportService::new().with_themes_dir(temp_dir.path().to_path_buf());
+
+        let names: Vec<_> = service.list_themes().into_iter().map(|theme| (theme.name, theme.built_in)).collect();
+        assert_eq!(names, vec![
+            ("github".to_string(), true),
+            ("academic".to_string(), true),
+            ("newspaper".to_string(), true),
+            ("brand".to_string(), false),
+            ("Dark".to_string(), false),
+        ]);
+        assert_eq!(service.theme_css("dark").unwrap(), "body { color: gold; }");
+        assert_eq!(service.theme_css("brand.css").unwrap(), "h1 { color: teal; }");
+        assert!(service.theme_css("Newspaper").unwrap().contains("column-count: 2"));
+        assert!(service.theme_css("missing").is_err());
+        assert!(service.theme_css("../secrets").is_err());
+
+        let options = ExportOptions { css_theme: Some("academic".to_string()), ..Default::default() };
+        let html = service.create_complete_html("<p>Body</p>", &options).unwrap();
+        assert!(html.contains("/* Theme */\n\n        body {\n            font-family: \"Latin Modern Roman\""));
+        let inline = ExportOptions { css_theme: Some("p { margin: 0; }".to_string()), ..Default::default() };
+        assert!(service.create_complete_html("<p>Body</p>", &inline).unwrap().contains("/* Theme */\np { margin: 0; }"));
+    }
+
+    #[test]
+    fn test_watermark() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("logo.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        let service = ExportService::new();
+
+        let draft = ExportOptions { watermark: Some(Watermark::default()), ..Default::default() };
+        let html = service.create_complete_html("<p>Body</p>", &draft).unwrap();
+        assert!(html.contains(r#"<div class="watermark" aria-hidden="true" style="opacity: 0.15; transform: translate(-50%, -50%) rotate(-45deg);">DRAFT</div>"#));
+
+        let logo = Watermark { image: Some("logo.png".to_string()), opacity: 2.0, rotation: 0.0, ..Default::default() };
+        let html = watermark_html(&logo, Some(temp_dir.path()));
+        assert!(html.contains("style=\"opacity: 1; transform: translate(-50%, -50%) rotate(0deg);\"><img src=\"data:image/png;base64,iVBORw0KGgo=\""));
+
+        let missing = Watermark { image: Some("missing.png".to_string()), text: Some("<Internal>".to_string()), ..Default::default() };
+        assert!(watermark_html(&missing, Some(temp_dir.path())).contains(">&lt;Internal&gt;</div>"));
+        assert_eq!(watermark_html(&Watermark { text: None, ..Default::default() }, None), "");
+    }
+
+    #[tokio::test]
+    async fn test_export_fonts() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("Serif.TTF"), b"\0\x01\0\0").unwrap();
+        let service = ExportService::new();
+        let mut options = ExportOptions {
+            fonts: ExportFonts {
+                body: Some(ExportFont { family: "Book \"Serif\"".to_string(), file: Some(PathBuf::from("Serif.TTF")), data: None }),
+                mono: Some(ExportFont { family: "Fira Code".to_string(), file: None, data: None }),
+                ..Default::default()
+            },
+            base_dir: Some(temp_dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        assert!(service.create_complete_html("<p>Body</p>", &options).is_err());
+        options.fonts.load(options.base_dir.as_deref()).await.unwrap();
+
+        let html = service.create_complete_html("<p>Body</p>", &options).unwrap();
+        assert!(html.contains(r#"@font-face { font-family: "Book \"Serif\""; src: url("data:font/ttf;base64,AAEAAA==") format("truetype"); }"#), "{}", html);
+        assert!(html.contains(r#"body { font-family: "Book \"Serif\"", sans-serif; }"#));
+        assert!(html.contains(r#"code, pre, kbd, samp { font-family: "Fira Code", monospace; }"#));
+        assert!(!html.contains("h1, h2, h3, h4, h5, h6 { font-family"));
+
+        let unsupported = ExportOptions {
+            fonts: ExportFonts { heading: Some(ExportFont { family: "Logo".to_string(), file: Some(PathBuf::from("logo.png")), data: None }), ..Default::default() },
+            ..Default::default()
+        };
+        assert!(unsupported.fonts.clone().load(None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_document_options_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let service = ExportService::new().with_temp_dir(temp_dir.path().to_path_buf());
+        let document = temp_dir.path().join("notes.md");
+
+        assert!(service.load_document_options(&document).await.unwrap().is_none());
+
+        let options = ExportOptions {
+            page_size: PageSize::Letter,
+            css_theme: Some("dark".to_string()),
+            ..ExportOptions::default()
+        };
+        service.save_document_options(&document, &options).await.unwrap();
+
+        let loaded = service.load_document_options(&document).await.unwrap().unwrap();
+        assert_eq!(loaded.page_size.css_name(), "letter");
+        assert_eq!(loaded.css_theme.as_deref(), Some("dark"));
+        assert!(temp_dir.path().join(".notes.md.export.json").exists());
     }
 
     #[test]
@@ -476,10 +2123,17 @@ mod tests {
         let service = ExportService::new();
         let html = "<h1>Chapter 1</h1><h2>Section 1.1</h2><h2>Section 1.2</h2>";
         
-        let toc = service.generate_toc_from_html(html).unwrap();
+        let toc = service.generate_toc_from_html(html, None).unwrap();
         
         assert!(toc.contains("Table of Contents"));
         assert!(toc.contains("Chapter 1"));
         assert!(toc.contains("Section 1.1"));
+
+        let html = "<h1 id=\"intro\">Intro</h1><h2 id=\"setup\">Setup &amp; use</h2>";
+        let pages = HashMap::from([("intro".to_string(), 1), ("setup".to_string(), 3)]);
+        let toc = service.generate_toc_from_html(html, Some(&pages)).unwrap();
+        assert!(toc.contains("<li class=\"toc-level-2\"><a href=\"#setup\"><span class=\"toc-title\">Setup &amp; use</span><span class=\"toc-leader\"></span><span class=\"toc-page\">3</span></a></li>"), "{}", toc);
+        let unnumbered = service.generate_toc_from_html(html, Some(&HashMap::new())).unwrap();
+        assert!(unnumbered.contains("<span class=\"toc-page\"></span>"));
     }
 }
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::export::ExportProgress;
+
+/// Payload of the `export-progress` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProgressEvent {
+    pub job_id: String,
+    #[serde(flatten)]
+    pub progress: ExportProgress,
+}
+
+/// Exports the UI started, by job id, so `cancel_export` can stop them
+#[derive(Default)]
+pub struct ExportJobs {
+    next_serial: AtomicU64,
+    /// Token of each running job, with a serial telling restarts of an id apart
+    active: Mutex<HashMap<String, (u64, CancellationToken)>>,
+}
+
+/// A running export; leaves the registry when dropped
+pub struct ExportJobTicket<'a> {
+    jobs: &'a ExportJobs,
+    id: String,
+    serial: u64,
+    token: CancellationToken,
+}
+
+impl ExportJobTicket<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Cancelled when the job is
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for ExportJobTicket<'_> {
+    fn drop(&mut self) {
+        let mut active = self.jobs.active.lock().unwrap();
+        if active.get(&self.id).is_some_and(|(serial, _)| *serial == self.serial) {
+            active.remove(&self.id);
+        }
+    }
+}
+
+impl ExportJobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an export under `id`, or a new id when the caller has none;
+    /// an export still running under the same id is cancelled
+    pub fn begin(&self, id: Option<String>) -> ExportJobTicket<'_> {
+        let id = id.filter(|id| !id.is_empty()).unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let serial = self.next_serial.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+        if let Some((_, previous)) = self.active.lock().unwrap().insert(id.clone(), (serial, token.clone())) {
+            debug!("Export job {} restarted", id);
+            previous.cancel();
+        }
+        ExportJobTicket { jobs: self, id, serial, token }
+    }
+
+    /// Stop the export running as `id`; false when there is none
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.active.lock().unwrap().remove(id) {
+            Some((_, token)) => {
+                debug!("Cancelling export job {}", id);
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ids of the exports running now
+    pub fn active(&self) -> Vec<String> {
+        let mut ids: Vec<_> = self.active.lock().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_job() {
+        let jobs = ExportJobs::new();
+        let first = jobs.begin(Some("report".to_string()));
+        let second = jobs.begin(None);
+        assert_eq!(first.id(), "report");
+        assert_eq!(jobs.active().len(), 2);
+
+        assert!(jobs.cancel("report"));
+        assert!(first.token().is_cancelled());
+        assert!(!second.token().is_cancelled());
+        assert!(!jobs.cancel("report"));
+
+        drop(second);
+        assert!(jobs.active().is_empty());
+    }
+
+    #[test]
+    fn test_restarted_job_cancels_previous() {
+        let jobs = ExportJobs::new();
+        let first = jobs.begin(Some("report".to_string()));
+        let second = jobs.begin(Some("report".to_string()));
+        assert!(first.token().is_cancelled());
+        assert!(!second.token().is_cancelled());
+        drop(first);
+        assert_eq!(jobs.active(), vec!["report"]);
+    }
+}
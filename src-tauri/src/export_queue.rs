@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::export::ExportFormat;
+use crate::settings::ExportOnSaveRule;
+
+/// One export of a saved document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub document: PathBuf,
+    pub output_path: PathBuf,
+    pub format: ExportFormat,
+}
+
+/// Sent as `export-on-save-failed` when a queued export fails
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJobFailure {
+    pub document: PathBuf,
+    pub output_path: PathBuf,
+    pub error: String,
+}
+
+/// The exports `rules` ask for when `document` is saved, with relative output
+/// folders and rule documents resolved against `base`
+pub fn export_jobs(document: &Path, rules: &[ExportOnSaveRule], base: &Path) -> Vec<ExportJob> {
+    let Some(stem) = document.file_stem() else { return Vec::new() };
+
+    rules.iter()
+        .filter(|rule| match &rule.document {
+            Some(only) => base.join(only) == document,
+            None => true,
+        })
+        .map(|rule| {
+            let mut file_name = stem.to_os_string();
+            file_name.push(".");
+            file_name.push(rule.format.extension());
+            ExportJob {
+                document: document.to_path_buf(),
+                output_path: base.join(&rule.output_dir).join(file_name),
+                format: rule.format.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Exports waiting to run one at a time in the background, so saving never
+/// waits on them
+///
+/// A job for an output that is already waiting is dropped: saving twice in a
+/// row exports once, from the latest content on disk.
+#[derive(Clone)]
+pub struct ExportQueue {
+    sender: mpsc::UnboundedSender<ExportJob>,
+    receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<ExportJob>>>>,
+    waiting: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl Default for ExportQueue {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+            waiting: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+}
+
+impl ExportQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `job`; false when the same output is already waiting
+    pub fn push(&self, job: ExportJob) -> bool {
+        if !self.waiting.lock().unwrap().insert(job.output_path.clone()) {
+            return false;
+        }
+        let output_path = job.output_path.clone();
+        if self.sender.send(job).is_err() {
+            self.waiting.lock().unwrap().remove(&output_path);
+            return false;
+        }
+        true
+    }
+
+    /// The receiving end, for the one task running the jobs; `None` once taken
+    pub fn take_jobs(&self) -> Option<ExportJobs> {
+        let receiver = self.receiver.lock().unwrap().take()?;
+        Some(ExportJobs { receiver, waiting: self.waiting.clone() })
+    }
+}
+
+pub struct ExportJobs {
+    receiver: mpsc::UnboundedReceiver<ExportJob>,
+    waiting: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl ExportJobs {
+    /// The next job to run, waiting for one to be queued
+    pub async fn next(&mut self) -> Option<ExportJob> {
+        let job = self.receiver.recv().await?;
+        // From here a new save needs a new export
+        self.waiting.lock().unwrap().remove(&job.output_path);
+        Some(job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(format: ExportFormat, output_dir: &str, document: Option<&str>) -> ExportOnSaveRule {
+        ExportOnSaveRule { format, output_dir: PathBuf::from(output_dir), document: document.map(PathBuf::from) }
+    }
+
+    #[test]
+    fn test_export_jobs_for_rules() {
+        let base = Path::new("/notes");
+        let rules = vec![
+            rule(ExportFormat::Html, "public", None),
+            rule(ExportFormat::Pdf, "/tmp/out", Some("posts/hello.md")),
+        ];
+
+        let jobs = export_jobs(Path::new("/notes/posts/hello.md"), &rules, base);
+        let outputs: Vec<_> = jobs.iter().map(|job| job.output_path.as_path()).collect();
+        assert_eq!(outputs, vec![Path::new("/notes/public/hello.html"), Path::new("/tmp/out/hello.pdf")]);
+
+        let jobs = export_jobs(Path::new("/notes/other.md"), &rules, base);
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].output_path, Path::new("/notes/public/other.html"));
+    }
+
+    #[tokio::test]
+    async fn test_queue_drops_waiting_duplicates() {
+        let queue = ExportQueue::new();
+        let job = |output: &str| ExportJob {
+            document: PathBuf::from("/notes/a.md"),
+            output_path: PathBuf::from(output),
+            format: ExportFormat::Html,
+        };
+
+        assert!(queue.push(job("/out/a.html")));
+        assert!(!queue.push(job("/out/a.html")));
+        assert!(queue.push(job("/out/b.html")));
+
+        let mut jobs = queue.take_jobs().unwrap();
+        assert!(queue.take_jobs().is_none());
+        assert_eq!(jobs.next().await.unwrap().output_path, Path::new("/out/a.html"));
+        // Once started, saving again queues another export
+        assert!(queue.push(job("/out/a.html")));
+        assert_eq!(jobs.next().await.unwrap().output_path, Path::new("/out/b.html"));
+        assert_eq!(jobs.next().await.unwrap().output_path, Path::new("/out/a.html"));
+    }
+}
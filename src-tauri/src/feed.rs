@@ -0,0 +1,264 @@
+use anyhow::{Result, Context};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+use pulldown_cmark::{html, Options, Parser};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::frontmatter::FrontMatter;
+use crate::settings::{FeedFormat, FeedSettings};
+use crate::static_site::slugify;
+
+/// Marks the end of a post's summary, as in Jekyll and Hugo
+const MORE_MARKER: &str = "<!--more-->";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedResult {
+    pub output_path: PathBuf,
+    pub item_count: usize,
+}
+
+/// A post as it appears in the feed
+#[derive(Debug, Clone)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: String,
+    pub date: DateTime<FixedOffset>,
+    pub summary_html: String,
+}
+
+/// Builds RSS 2.0 or Atom feeds from a folder of front-mattered posts
+#[derive(Default)]
+pub struct FeedGenerator;
+
+impl FeedGenerator {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Write a feed of the newest posts in `posts_dir`, by default to
+    /// `feed.xml` or `atom.xml` inside it
+    pub async fn generate(&self, posts_dir: &Path, output_path: Option<&Path>, settings: &FeedSettings) -> Result<FeedResult> {
+        debug!("Generating {:?} feed from {:?}", settings.format, posts_dir);
+
+        let mut items = self.collect_items(posts_dir, settings).await?;
+        items.sort_by(|a, b| b.date.cmp(&a.date));
+        items.truncate(settings.max_items.max(1));
+
+        let feed = match settings.format {
+            FeedFormat::Rss => render_rss(&items, settings),
+            FeedFormat::Atom => render_atom(&items, settings),
+        };
+
+        let output_path = output_path.map(Path::to_path_buf).unwrap_or_else(|| {
+            posts_dir.join(match settings.format {
+                FeedFormat::Rss => "feed.xml",
+                FeedFormat::Atom => "atom.xml",
+            })
+        });
+        tokio::fs::write(&output_path, feed).await
+            .with_context(|| format!("Failed to write feed: {:?}", output_path))?;
+
+        info!("Wrote feed with {} posts to {:?}", items.len(), output_path);
+        Ok(FeedResult { output_path, item_count: items.len() })
+    }
+
+    async fn collect_items(&self, posts_dir: &Path, settings: &FeedSettings) -> Result<Vec<FeedItem>> {
+        let mut items = Vec::new();
+        let mut entries = tokio::fs::read_dir(posts_dir).await
+            .with_context(|| format!("Failed to read posts folder: {:?}", posts_dir))?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_markdown = path.extension().and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown"));
+            if !is_markdown || !path.is_file() {
+                continue;
+            }
+
+            let markdown = tokio::fs::read_to_string(&path).await
+                .with_context(|| format!("Failed to read post: {:?}", path))?;
+            // A post with broken front matter shouldn't keep the rest of the blog out of the feed
+            match post_item(&path, &markdown, settings) {
+                Ok(Some(item)) => items.push(item),
+                Ok(None) => debug!("Skipping draft {:?}", path),
+                Err(e) => warn!("Skipping {:?} in feed: {}", path, e),
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+/// The feed entry for one post, or `None` for drafts
+fn post_item(path: &Path, markdown: &str, settings: &FeedSettings) -> Result<Option<FeedItem>> {
+    let (front_matter, body) = FrontMatter::parse(markdown)?;
+    if front_matter.get_bool("draft").unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let title = front_matter.title()
+        .or_else(|| first_heading(body))
+        .unwrap_or_else(|| stem.clone());
+
+    let date = match front_matter.get_str("date") {
+        Some(date) => parse_date(&date).ok_or_else(|| anyhow::anyhow!("Unrecognised date: {}", date))?,
+        None => {
+            let modified = std::fs::metadata(path)?.modified()?;
+            DateTime::<Local>::from(modified).fixed_offset()
+        }
+    };
+
+    let slug = front_matter.get_str("slug").unwrap_or_else(|| slugify(&stem));
+    let link = if settings.post_url.is_empty() {
+        format!("{}/{}/", settings.site_url.trim_end_matches('/'), slug)
+    } else {
+        settings.post_url.replace("{slug}", &slug)
+    };
+
+    let summary = front_matter.get_str("summary")
+        .or_else(|| front_matter.get_str("description"))
+        .unwrap_or_else(|| summary_markdown(body).to_string());
+
+    Ok(Some(FeedItem { title, link, date, summary_html: render_summary(&summary) }))
+}
+
+/// Front-matter dates as written by static site generators: RFC 3339, or a
+/// plain date or date-time taken as local time
+pub fn parse_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date);
+    }
+
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))?;
+    Local.from_local_datetime(&naive).earliest().map(|date| date.fixed_offset())
+}
+
+fn first_heading(body: &str) -> Option<String> {
+    body.lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+/// Everything before `<!--more-->`, or else the first paragraph
+fn summary_markdown(body: &str) -> &str {
+    if let Some((summary, _)) = body.split_once(MORE_MARKER) {
+        return summary.trim();
+    }
+    body.split("\n\n")
+        .map(str::trim)
+        .find(|block| !block.is_empty() && !block.starts_with('#'))
+        .unwrap_or_default()
+}
+
+fn render_summary(markdown: &str) -> String {
+    let mut output = String::new();
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_SMART_PUNCTUATION;
+    html::push_html(&mut output, Parser::new_ext(markdown, options));
+    output.trim().to_string()
+}
+
+pub fn render_rss(items: &[FeedItem], settings: &FeedSettings) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n<channel>\n");
+    xml.push_str(&format!("  <title>{}</title>\n", text(&settings.title)));
+    xml.push_str(&format!("  <link>{}</link>\n", text(&settings.site_url)));
+    xml.push_str(&format!("  <description>{}</description>\n", text(&settings.description)));
+    if let Some(latest) = items.first() {
+        xml.push_str(&format!("  <lastBuildDate>{}</lastBuildDate>\n", latest.date.to_rfc2822()));
+    }
+
+    for item in items {
+        xml.push_str("  <item>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", text(&item.title)));
+        xml.push_str(&format!("    <link>{}</link>\n", text(&item.link)));
+        xml.push_str(&format!("    <guid isPermaLink=\"true\">{}</guid>\n", text(&item.link)));
+        xml.push_str(&format!("    <pubDate>{}</pubDate>\n", item.date.to_rfc2822()));
+        xml.push_str(&format!("    <description>{}</description>\n", text(&item.summary_html)));
+        xml.push_str("  </item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+pub fn render_atom(items: &[FeedItem], settings: &FeedSettings) -> String {
+    let updated = items.first().map(|item| item.date).unwrap_or_else(|| Local::now().fixed_offset());
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", text(&settings.title)));
+    if !settings.description.is_empty() {
+        xml.push_str(&format!("  <subtitle>{}</subtitle>\n", text(&settings.description)));
+    }
+    xml.push_str(&format!("  <link href=\"{}\"/>\n", attr(&settings.site_url)));
+    xml.push_str(&format!("  <id>{}</id>\n", text(&settings.site_url)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    if !settings.author.is_empty() {
+        xml.push_str(&format!("  <author><name>{}</name></author>\n", text(&settings.author)));
+    }
+
+    for item in items {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", text(&item.title)));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", attr(&item.link)));
+        xml.push_str(&format!("    <id>{}</id>\n", text(&item.link)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", item.date.to_rfc3339()));
+        xml.push_str(&format!("    <summary type=\"html\">{}</summary>\n", text(&item.summary_html)));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn settings() -> FeedSettings {
+        FeedSettings {
+            title: "Notes & Drafts".to_string(),
+            site_url: "https://example.com/".to_string(),
+            post_url: "https://example.com/posts/{slug}/".to_string(),
+            ..FeedSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_post_item() {
+        let markdown = "---\ntitle: Hello\ndate: 2024-03-01T09:30:00+01:00\n---\n\n# Hello\n\nFirst *post*.\n\nMore text.\n";
+        let item = post_item(Path::new("Hello World.md"), markdown, &settings()).unwrap().unwrap();
+
+        assert_eq!(item.title, "Hello");
+        assert_eq!(item.link, "https://example.com/posts/hello-world/");
+        assert_eq!(item.date.to_rfc3339(), "2024-03-01T09:30:00+01:00");
+        assert_eq!(item.summary_html, "<p>First <em>post</em>.</p>");
+
+        let draft = "---\ntitle: Soon\ndraft: true\n---\nText";
+        assert!(post_item(Path::new("soon.md"), draft, &settings()).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_rss() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("old.md"), "---\ntitle: Old\ndate: 2023-01-01\n---\nOld <news>\n").unwrap();
+        std::fs::write(dir.path().join("new.md"), "---\ntitle: New\ndate: 2024-01-01\n---\nIntro\n<!--more-->\nRest\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not a post").unwrap();
+
+        let result = FeedGenerator::new().generate(dir.path(), None, &settings()).await.unwrap();
+        let xml = std::fs::read_to_string(&result.output_path).unwrap();
+
+        assert_eq!(result.item_count, 2);
+        assert_eq!(result.output_path, dir.path().join("feed.xml"));
+        assert!(xml.contains("<title>Notes &amp; Drafts</title>"));
+        assert!(xml.find("<title>New</title>").unwrap() < xml.find("<title>Old</title>").unwrap());
+        assert!(xml.contains("<description>&lt;p&gt;Intro&lt;/p&gt;</description>"));
+    }
+}
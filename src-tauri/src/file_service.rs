@@ -1,14 +1,22 @@
 use anyhow::{Result, Context};
-use notify::{Watcher, RecommendedWatcher, RecursiveMode, Event};
+use memmap2::Mmap;
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Watcher, RecommendedWatcher, RecursiveMode, Event, EventKind};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 use tracing::{debug, info, warn, error};
 
+use crate::encryption::{is_encrypted_path, DocumentCipher};
+use crate::parser::MarkdownParser;
+use crate::shutdown::InFlight;
+use crate::worker_pool::WorkerPool;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMetadata {
     pub path: PathBuf,
@@ -31,18 +39,247 @@ pub enum FileEventType {
     Renamed { from: PathBuf, to: PathBuf },
 }
 
+/// A line of a markdown file containing a search query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+}
+
+/// Task list completion of one file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileTaskProgress {
+    pub path: PathBuf,
+    pub total: usize,
+    pub completed: usize,
+}
+
+/// Task list completion across a folder, for a project overview
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TaskProgress {
+    /// Only files with at least one task
+    pub files: Vec<FileTaskProgress>,
+    pub total: usize,
+    pub completed: usize,
+}
+
+/// A window of lines from a file, as returned by `FileService::read_lines`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileLines {
+    pub path: PathBuf,
+    /// 1-based number of the first line returned
+    pub start_line: usize,
+    pub lines: Vec<String>,
+    /// Known once the file has been indexed to the end
+    pub total_lines: Option<usize>,
+    pub size: u64,
+}
+
+/// A memory-mapped file whose line starts are found only as far as anyone has asked
+pub struct MappedFile {
+    mmap: Mmap,
+    modified: Option<std::time::SystemTime>,
+    line_starts: Mutex<LineStarts>,
+}
+
+struct LineStarts {
+    starts: Vec<usize>,
+    /// Every newline before this offset has been indexed
+    scanned: usize,
+}
+
+impl MappedFile {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {:?}", path))?;
+        let modified = file.metadata().and_then(|metadata| metadata.modified()).ok();
+        // SAFETY: the map is read-only, and callers re-map when the file's size or
+        // modification time changes. A concurrent writer can still change bytes under
+        // us, which at worst yields garbled text, never unsafety in our own code.
+        let mmap = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("Failed to memory-map file: {:?}", path))?;
+
+        Ok(Self {
+            mmap,
+            modified,
+            line_starts: Mutex::new(LineStarts { starts: vec![0], scanned: 0 }),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mmap.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// Number of lines, once the whole file has been indexed
+    pub fn known_line_count(&self) -> Option<usize> {
+        let index = self.line_starts.lock().unwrap();
+        (index.scanned == self.mmap.len()).then(|| index.starts.len())
+    }
+
+    /// Up to `count` lines starting at the 0-based line `start`, decoded lossily
+    pub fn lines(&self, start: usize, count: usize) -> Vec<String> {
+        let mut index = self.line_starts.lock().unwrap();
+        self.index_through(&mut index, start + count);
+
+        (start..start + count)
+            .map_while(|line| {
+                let begin = *index.starts.get(line)?;
+                let end = index.starts.get(line + 1).map_or(self.mmap.len(), |next| next - 1);
+                let bytes = &self.mmap[begin..end];
+                Some(String::from_utf8_lossy(bytes.strip_suffix(b"\r").unwrap_or(bytes)).into_owned())
+            })
+            .collect()
+    }
+
+    /// Extend the index until it knows where line `line` starts, or hits the end
+    fn index_through(&self, index: &mut LineStarts, line: usize) {
+        let bytes = &self.mmap[..];
+        while index.starts.len() <= line && index.scanned < bytes.len() {
+            match bytes[index.scanned..].iter().position(|b| *b == b'\n') {
+                Some(newline) => {
+                    index.scanned += newline + 1;
+                    index.starts.push(index.scanned);
+                }
+                None => index.scanned = bytes.len(),
+            }
+        }
+    }
+}
+
+/// Identifies one `watch_file` or `watch_directory` subscription
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WatchHandle {
+    pub id: u64,
+    pub path: PathBuf,
+}
+
+/// How a subscription's changes are held back and combined before delivery
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoalesceMode {
+    /// One event per path, once that path has been quiet for the debounce delay
+    #[default]
+    Latest,
+    /// Every changed path at once, once the whole watch has been quiet for the delay
+    Batch,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchOptions {
+    /// Defaults to the service's debounce delay
+    pub debounce_ms: Option<u64>,
+    pub coalesce: CoalesceMode,
+}
+
+/// Receives each delivery: a single event in `Latest` mode, or a whole batch
+type WatchCallback = Arc<dyn Fn(Vec<FileChangeEvent>) + Send + Sync>;
+
+struct Subscription {
+    path: PathBuf,
+    recursive: bool,
+    debounce: Duration,
+    coalesce: CoalesceMode,
+    callback: WatchCallback,
+    /// Changes not yet delivered, with when each path last changed, in arrival order
+    pending: Vec<(PathBuf, FileEventType, Instant)>,
+}
+
+impl Subscription {
+    fn matches(&self, event_path: &Path) -> bool {
+        event_path == self.path
+            || (event_path.parent() == Some(self.path.as_path()))
+            || (self.recursive && event_path.starts_with(&self.path))
+    }
+
+    fn record(&mut self, path: &Path, event_type: FileEventType, now: Instant) {
+        self.pending.retain(|(pending, _, _)| pending != path);
+        self.pending.push((path.to_path_buf(), event_type, now));
+    }
+
+    /// Changes whose quiet period has passed, each inner list being one delivery
+    fn take_ready(&mut self, now: Instant) -> Vec<Vec<FileChangeEvent>> {
+        let debounce = self.debounce;
+        let quiet = |changed: &Instant| now.duration_since(*changed) >= debounce;
+
+        match self.coalesce {
+            CoalesceMode::Latest => {
+                let (ready, waiting) = std::mem::take(&mut self.pending)
+                    .into_iter()
+                    .partition(|(_, _, changed)| quiet(changed));
+                self.pending = waiting;
+                ready.into_iter()
+                    .map(|(path, event_type, _)| vec![FileChangeEvent { path, event_type }])
+                    .collect()
+            }
+            CoalesceMode::Batch => {
+                if self.pending.is_empty() || !self.pending.iter().all(|(_, _, changed)| quiet(changed)) {
+                    return Vec::new();
+                }
+                let batch = self.pending.drain(..)
+                    .map(|(path, event_type, _)| FileChangeEvent { path, event_type })
+                    .collect();
+                vec![batch]
+            }
+        }
+    }
+}
+
+/// The one OS watcher shared by every subscription, and what it's watching
+#[derive(Default)]
+struct WatcherState {
+    watcher: Option<RecommendedWatcher>,
+    /// Watched paths and whether they're watched recursively
+    watched: HashMap<PathBuf, bool>,
+}
+
+/// Which paths file commands may read and write
+///
+/// Unenforced by default; the app turns it on so a compromised webview can't
+/// reach files the user never opened, like `~/.ssh`.
+#[derive(Debug, Default)]
+struct AccessPolicy {
+    enforced: bool,
+    /// Extra folders from the settings, canonicalized
+    roots: Vec<PathBuf>,
+    workspace: Option<PathBuf>,
+    files: HashSet<PathBuf>,
+}
+
 pub struct FileService {
-    watchers: Arc<Mutex<HashMap<PathBuf, RecommendedWatcher>>>,
+    watcher: Mutex<WatcherState>,
+    subscriptions: Arc<Mutex<HashMap<u64, Subscription>>>,
+    next_watch_id: AtomicU64,
     debounce_delay: Duration,
-    pending_events: Arc<Mutex<HashMap<PathBuf, Instant>>>,
+    large_file_threshold: u64,
+    mapped_files: Mutex<HashMap<PathBuf, Arc<MappedFile>>>,
+    writes: InFlight,
+    access: RwLock<AccessPolicy>,
+    cipher: DocumentCipher,
 }
 
 impl Default for FileService {
     fn default() -> Self {
         Self {
-            watchers: Arc::new(Mutex::new(HashMap::new())),
+            watcher: Mutex::new(WatcherState::default()),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            next_watch_id: AtomicU64::new(1),
             debounce_delay: Duration::from_millis(300),
-            pending_events: Arc::new(Mutex::new(HashMap::new())),
+            large_file_threshold: 8 * 1024 * 1024,
+            mapped_files: Mutex::new(HashMap::new()),
+            writes: InFlight::new(),
+            access: RwLock::new(AccessPolicy::default()),
+            cipher: DocumentCipher::new(),
         }
     }
 }
@@ -57,24 +294,164 @@ impl FileService {
         self
     }
 
+    /// Files larger than this (in bytes) are memory-mapped rather than read into a buffer
+    pub fn with_large_file_threshold(mut self, threshold: u64) -> Self {
+        self.large_file_threshold = threshold;
+        self
+    }
+
+    /// Use `cipher` for encrypted documents, e.g. one with fewer key derivation rounds
+    pub fn with_cipher(mut self, cipher: DocumentCipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// The password that `.enc` documents are decrypted on read and encrypted on write with
+    pub fn set_encryption_password(&self, password: Option<String>) {
+        self.cipher.set_password(password);
+    }
+
+    /// Confine reads and writes to `roots`, the open workspace and files the user
+    /// opened, or lift every restriction when `enforced` is false
+    pub fn set_access_policy(&self, enforced: bool, roots: &[PathBuf]) {
+        let mut access = self.access.write().unwrap();
+        access.enforced = enforced;
+        access.roots = roots.iter().filter_map(|root| root.canonicalize().ok()).collect();
+    }
+
+    pub fn set_workspace_root(&self, root: Option<&Path>) {
+        self.access.write().unwrap().workspace = root.and_then(|root| root.canonicalize().ok());
+    }
+
+    /// Let a file the user picked be read and written wherever it is
+    pub fn allow_file(&self, path: &Path) {
+        match resolve_path(path) {
+            Ok(resolved) => {
+                self.access.write().unwrap().files.insert(resolved);
+            }
+            Err(e) => warn!("Can't allow access to {:?}: {}", path, e),
+        }
+    }
+
+    /// Fail unless the access policy lets file commands touch `path`
+    ///
+    /// The path is resolved first, so `..` and symlinks can't lead outside an allowed folder.
+    pub fn check_access(&self, path: &Path) -> Result<()> {
+        let access = self.access.read().unwrap();
+        if !access.enforced {
+            return Ok(());
+        }
+
+        let resolved = resolve_path(path)?;
+        let allowed = access.files.contains(&resolved)
+            || access.roots.iter().chain(&access.workspace).any(|root| resolved.starts_with(root));
+        if !allowed {
+            warn!("Refused access to {:?} outside the allowed folders", path);
+            anyhow::bail!("Access denied: {:?} is outside the workspace and the files you opened", path);
+        }
+        Ok(())
+    }
+
     /// Read a markdown file and return its content
     pub async fn read_file(&self, path: &Path) -> Result<String> {
         debug!("Reading file: {:?}", path);
-        
+        self.check_access(path)?;
+
         if !path.exists() {
             return Err(anyhow::anyhow!("File does not exist: {:?}", path));
         }
+        if is_encrypted_path(path) {
+            let content = self.read_encrypted(path).await?;
+            info!("Successfully read encrypted file: {:?}", path);
+            return Ok(content);
+        }
 
-        let content = tokio::fs::read_to_string(path).await
-            .with_context(|| format!("Failed to read file: {:?}", path))?;
+        let size = tokio::fs::metadata(path).await
+            .with_context(|| format!("Failed to get metadata for: {:?}", path))?
+            .len();
+        let content = if size > self.large_file_threshold {
+            // Decoding straight from the map costs one exact-size copy, where
+            // read_to_string keeps regrowing its buffer
+            let mapped = self.mapped_file(path).await?;
+            std::str::from_utf8(mapped.as_bytes())
+                .with_context(|| format!("File is not valid UTF-8: {:?}", path))?
+                .to_string()
+        } else {
+            tokio::fs::read_to_string(path).await
+                .with_context(|| format!("Failed to read file: {:?}", path))?
+        };
 
         info!("Successfully read file: {:?} ({} bytes)", path, content.len());
         Ok(content)
     }
 
+    /// Read `count` lines starting at the 1-based `start_line`
+    ///
+    /// Large files are memory-mapped and only indexed as far as the lines asked
+    /// for, so paging through a huge file keeps memory bounded.
+    pub async fn read_lines(&self, path: &Path, start_line: usize, count: usize) -> Result<FileLines> {
+        debug!("Reading {} lines of {:?} from line {}", count, path, start_line);
+        self.check_access(path)?;
+
+        let size = tokio::fs::metadata(path).await
+            .with_context(|| format!("Failed to get metadata for: {:?}", path))?
+            .len();
+        let start = start_line.max(1) - 1;
+
+        let (lines, total_lines) = if size > self.large_file_threshold && !is_encrypted_path(path) {
+            let mapped = self.mapped_file(path).await?;
+            (mapped.lines(start, count), mapped.known_line_count())
+        } else {
+            let content = if is_encrypted_path(path) {
+                self.read_encrypted(path).await?
+            } else {
+                tokio::fs::read_to_string(path).await
+                    .with_context(|| format!("Failed to read file: {:?}", path))?
+            };
+            let lines = content.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line));
+            (lines.clone().skip(start).take(count).map(str::to_string).collect(), Some(lines.count()))
+        };
+
+        Ok(FileLines { path: path.to_path_buf(), start_line: start + 1, lines, total_lines, size })
+    }
+
+    async fn read_encrypted(&self, path: &Path) -> Result<String> {
+        let data = tokio::fs::read(path).await
+            .with_context(|| format!("Failed to read file: {:?}", path))?;
+        let plaintext = self.cipher.decrypt(&data)
+            .with_context(|| format!("Failed to decrypt {:?}", path))?;
+        String::from_utf8(plaintext).with_context(|| format!("File is not valid UTF-8: {:?}", path))
+    }
+
+    /// The cached mapping of `path`, re-mapped if the file changed since
+    async fn mapped_file(&self, path: &Path) -> Result<Arc<MappedFile>> {
+        let metadata = tokio::fs::metadata(path).await
+            .with_context(|| format!("Failed to get metadata for: {:?}", path))?;
+        let modified = metadata.modified().ok();
+
+        if let Some(mapped) = self.mapped_files.lock().unwrap().get(path) {
+            if mapped.len() as u64 == metadata.len() && mapped.modified == modified {
+                return Ok(mapped.clone());
+            }
+        }
+
+        let owned = path.to_path_buf();
+        let mapped = Arc::new(tokio::task::spawn_blocking(move || MappedFile::open(&owned)).await??);
+        debug!("Memory-mapped {:?} ({} bytes)", path, mapped.len());
+        self.mapped_files.lock().unwrap().insert(path.to_path_buf(), mapped.clone());
+        Ok(mapped)
+    }
+
+    /// Drop the cached mapping of a file the user is done with
+    pub fn release_mapped_file(&self, path: &Path) {
+        self.mapped_files.lock().unwrap().remove(path);
+    }
+
     /// Write content to a file atomically
     pub async fn write_file(&self, path: &Path, content: &str) -> Result<()> {
         debug!("Writing file: {:?} ({} bytes)", path, content.len());
+        self.check_access(path)?;
+        let _write = self.writes.begin();
 
         // Create parent directories if they don't exist
         if let Some(parent) = path.parent() {
@@ -82,9 +459,18 @@ impl FileService {
                 .with_context(|| format!("Failed to create parent directories for: {:?}", path))?;
         }
 
+        let encrypted;
+        let bytes = if is_encrypted_path(path) {
+            encrypted = self.cipher.encrypt(content.as_bytes())
+                .with_context(|| format!("Failed to encrypt {:?}", path))?;
+            &encrypted[..]
+        } else {
+            content.as_bytes()
+        };
+
         // Write to a temporary file first, then rename (atomic operation)
         let temp_path = path.with_extension("tmp");
-        tokio::fs::write(&temp_path, content).await
+        tokio::fs::write(&temp_path, bytes).await
             .with_context(|| format!("Failed to write temporary file: {:?}", temp_path))?;
 
         tokio::fs::rename(&temp_path, path).await
@@ -98,135 +484,257 @@ impl FileService {
     pub async fn get_metadata(&self, path: &Path) -> Result<FileMetadata> {
         let metadata = tokio::fs::metadata(path).await
             .with_context(|| format!("Failed to get metadata for: {:?}", path))?;
-
-        let is_markdown = path.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown" | "mdown" | "mkd"))
-            .unwrap_or(false);
-
-        let modified = metadata.modified()
-            .with_context(|| format!("Failed to get modified time for: {:?}", path))?
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        Ok(FileMetadata {
-            path: path.to_path_buf(),
-            size: metadata.len(),
-            modified,
-            is_markdown,
-        })
+        to_file_metadata(path, &metadata)
     }
 
     /// Start watching a file for changes
-    pub async fn watch_file<F>(&self, path: PathBuf, callback: F) -> Result<()>
+    pub async fn watch_file<F>(&self, path: PathBuf, options: &WatchOptions, callback: F) -> Result<WatchHandle>
     where
-        F: Fn(FileChangeEvent) + Send + Sync + 'static,
+        F: Fn(Vec<FileChangeEvent>) + Send + Sync + 'static,
     {
         info!("Starting to watch file: {:?}", path);
+        self.subscribe(path, false, options, Arc::new(callback))
+    }
+
+    /// Start watching everything under a directory, e.g. a whole workspace
+    pub async fn watch_directory<F>(&self, dir: PathBuf, options: &WatchOptions, callback: F) -> Result<WatchHandle>
+    where
+        F: Fn(Vec<FileChangeEvent>) + Send + Sync + 'static,
+    {
+        info!("Starting to watch directory: {:?}", dir);
+        self.subscribe(dir, true, options, Arc::new(callback))
+    }
+
+    /// End one subscription, releasing the OS watch once nothing else needs it
+    pub fn unwatch(&self, handle: &WatchHandle) -> Result<()> {
+        debug!("Removing watch {} on {:?}", handle.id, handle.path);
+
+        let mut state = self.watcher.lock().unwrap();
+        self.subscriptions.lock().unwrap().remove(&handle.id);
+        self.sync_watch(&mut state, &handle.path)
+    }
+
+    /// Stop watching a file
+    pub fn unwatch_file(&self, path: &PathBuf) -> Result<()> {
+        debug!("Stopping watch for file: {:?}", path);
+
+        let mut state = self.watcher.lock().unwrap();
+        self.subscriptions.lock().unwrap().retain(|_, subscription| &subscription.path != path);
+        self.sync_watch(&mut state, path)?;
+
+        info!("Stopped watching file: {:?}", path);
+        Ok(())
+    }
+
+    /// Drop every subscription and the OS watcher with them, e.g. when the app quits
+    pub fn unwatch_all(&self) {
+        let mut state = self.watcher.lock().unwrap();
+        let count = {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            let count = subscriptions.len();
+            subscriptions.clear();
+            count
+        };
+        state.watched.clear();
+        state.watcher = None;
+        info!("Stopped {} file watches", count);
+    }
+
+    /// Atomic writes still in progress, so quitting can wait for them
+    pub fn pending_writes(&self) -> &InFlight {
+        &self.writes
+    }
 
+    fn subscribe(&self, path: PathBuf, recursive: bool, options: &WatchOptions, callback: WatchCallback) -> Result<WatchHandle> {
+        let id = self.next_watch_id.fetch_add(1, Ordering::Relaxed);
+        let mut state = self.watcher.lock().unwrap();
+        if state.watcher.is_none() {
+            state.watcher = Some(self.start_watcher()?);
+        }
+
+        let subscription = Subscription {
+            path: path.clone(),
+            recursive,
+            debounce: options.debounce_ms.map_or(self.debounce_delay, Duration::from_millis),
+            coalesce: options.coalesce,
+            callback,
+            pending: Vec::new(),
+        };
+        self.subscriptions.lock().unwrap().insert(id, subscription);
+        if let Err(e) = self.sync_watch(&mut state, &path) {
+            self.subscriptions.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        Ok(WatchHandle { id, path })
+    }
+
+    /// Make the OS watch on `path` match what its subscriptions need
+    fn sync_watch(&self, state: &mut WatcherState, path: &Path) -> Result<()> {
+        let wanted = self.subscriptions.lock().unwrap()
+            .values()
+            .filter(|subscription| subscription.path == path)
+            .map(|subscription| subscription.recursive)
+            .reduce(|a, b| a || b);
+        let current = state.watched.get(path).copied();
+        if wanted == current {
+            return Ok(());
+        }
+        let Some(watcher) = state.watcher.as_mut() else {
+            return Ok(());
+        };
+
+        if current.is_some() {
+            if let Err(e) = watcher.unwatch(path) {
+                warn!("Failed to unwatch {:?}: {}", path, e);
+            }
+            state.watched.remove(path);
+        }
+        if let Some(recursive) = wanted {
+            let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+            watcher.watch(path, mode)
+                .with_context(|| format!("Failed to watch {:?}", path))?;
+            state.watched.insert(path.to_path_buf(), recursive);
+        }
+
+        Ok(())
+    }
+
+    /// Create the shared OS watcher and the task that routes its events to subscriptions
+    fn start_watcher(&self) -> Result<RecommendedWatcher> {
         let (tx, mut rx) = mpsc::unbounded_channel::<notify::Result<Event>>();
-        let callback = Arc::new(callback);
-        
-        // Create debounced event handler
-        let debounce_delay = self.debounce_delay;
-        let _pending_events = self.pending_events.clone();
-        let callback_clone = callback.clone();
-        
+        let subscriptions = self.subscriptions.clone();
+
         tokio::spawn(async move {
-            let mut debounce_map: HashMap<PathBuf, Instant> = HashMap::new();
-            
             loop {
-                // Check for debounced events that are ready to fire
+                // Collect deliveries under the lock, then call back outside it, so
+                // callbacks may subscribe or unsubscribe
                 let now = Instant::now();
-                let ready_events: Vec<PathBuf> = debounce_map
-                    .iter()
-                    .filter(|(_, &time)| now.duration_since(time) >= debounce_delay)
-                    .map(|(path, _)| path.clone())
-                    .collect();
+                let (deliveries, tick) = {
+                    let mut subscriptions = subscriptions.lock().unwrap();
+                    let deliveries: Vec<(WatchCallback, Vec<FileChangeEvent>)> = subscriptions.values_mut()
+                        .flat_map(|subscription| {
+                            let callback = subscription.callback.clone();
+                            subscription.take_ready(now).into_iter().map(move |events| (callback.clone(), events))
+                        })
+                        .collect();
+                    // Check often enough for the shortest debounce
+                    let tick = subscriptions.values()
+                        .map(|subscription| subscription.debounce)
+                        .min()
+                        .unwrap_or(MAX_WATCH_TICK)
+                        .clamp(MIN_WATCH_TICK, MAX_WATCH_TICK);
+                    (deliveries, tick)
+                };
 
-                for event_path in ready_events {
-                    debounce_map.remove(&event_path);
-                    callback_clone(FileChangeEvent {
-                        path: event_path,
-                        event_type: FileEventType::Modified,
-                    });
+                for (callback, events) in deliveries {
+                    callback(events);
                 }
 
                 // Process new events or wait a bit
-                match tokio::time::timeout(Duration::from_millis(50), rx.recv()).await {
-                    Ok(Some(event)) => {
-                        if let Ok(event) = event {
-                            for event_path in event.paths {
-                                debounce_map.insert(event_path, now);
-                            }
+                match tokio::time::timeout(tick, rx.recv()).await {
+                    Ok(Some(Ok(event))) => {
+                        let now = Instant::now();
+                        let mut subscriptions = subscriptions.lock().unwrap();
+                        for event_path in &event.paths {
+                            let Some(event_type) = event_type(&event.kind, event_path) else { continue };
+                            subscriptions.values_mut()
+                                .filter(|subscription| subscription.matches(event_path))
+                                .for_each(|subscription| subscription.record(event_path, event_type.clone(), now));
                         }
                     }
-                    Ok(None) => break, // Channel closed
+                    Ok(Some(Err(e))) => warn!("File watcher error: {}", e),
+                    Ok(None) => break, // Watcher dropped
                     Err(_) => continue, // Timeout, check debounced events
                 }
             }
         });
 
-        // Create and configure the watcher
         let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
             if let Err(e) = tx.send(res) {
                 error!("Failed to send file event: {}", e);
             }
         })?;
 
-        let mut watchers = self.watchers.lock().unwrap();
-        watchers.insert(path.clone(), watcher);
-
-        // Start watching the file
-        if let Some(watcher) = watchers.get_mut(&path) {
-            watcher.watch(&path, RecursiveMode::NonRecursive)?;
-        }
-
-        Ok(())
-    }
-
-    /// Stop watching a file
-    pub fn unwatch_file(&self, path: &PathBuf) -> Result<()> {
-        debug!("Stopping watch for file: {:?}", path);
-
-        let mut watchers = self.watchers.lock().unwrap();
-        if let Some(mut watcher) = watchers.remove(path) {
-            if let Err(e) = watcher.unwatch(path) {
-                warn!("Failed to unwatch file {:?}: {}", path, e);
-            }
-        }
-
-        info!("Stopped watching file: {:?}", path);
-        Ok(())
+        Ok(watcher)
     }
 
     /// List markdown files in a directory
-    pub async fn list_markdown_files(&self, dir: &Path) -> Result<Vec<FileMetadata>> {
+    pub async fn list_markdown_files(&self, dir: &Path, workers: &WorkerPool) -> Result<Vec<FileMetadata>> {
         debug!("Listing markdown files in: {:?}", dir);
 
-        let mut files = Vec::new();
+        let mut paths = Vec::new();
         let mut entries = tokio::fs::read_dir(dir).await
             .with_context(|| format!("Failed to read directory: {:?}", dir))?;
 
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
-            
-            if path.is_file() {
-                if let Ok(metadata) = self.get_metadata(&path).await {
-                    if metadata.is_markdown {
-                        files.push(metadata);
-                    }
-                }
+            if is_markdown_path(&path) {
+                paths.push(path);
             }
         }
 
+        // Stat the files on the worker pool, so big folders don't hold up the runtime
+        let mut files: Vec<FileMetadata> = workers.map(paths, |path| {
+            if path.is_file() { file_metadata(&path).ok() } else { None }
+        }).await?
+            .into_iter()
+            .flatten()
+            .collect();
+
         files.sort_by(|a, b| b.modified.cmp(&a.modified)); // Sort by most recent first
         info!("Found {} markdown files in {:?}", files.len(), dir);
 
         Ok(files)
     }
 
+    /// Case-insensitive search of every markdown file under `dir`, skipping hidden folders
+    ///
+    /// Files are searched in parallel on `workers`; matches come back in path order.
+    pub async fn search_markdown_files(&self, dir: &Path, query: &str, limit: usize, workers: &WorkerPool) -> Result<Vec<SearchMatch>> {
+        debug!("Searching {:?} for {:?}", dir, query);
+
+        let needle = query.to_lowercase();
+        if needle.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let files = markdown_files_under(dir).await?;
+        let mut matches: Vec<SearchMatch> = workers.map(files, move |path| search_file(&path, &needle, limit)).await?
+            .into_iter()
+            .flatten()
+            .collect();
+        matches.truncate(limit);
+
+        info!("Found {} matches for {:?} in {:?}", matches.len(), query, dir);
+        Ok(matches)
+    }
+
+    /// Task list completion for every markdown file under `dir` that has tasks,
+    /// plus the totals across them
+    pub async fn task_progress(&self, dir: &Path, workers: &WorkerPool) -> Result<TaskProgress> {
+        debug!("Rolling up task progress in {:?}", dir);
+
+        let files = markdown_files_under(dir).await?;
+        let mut files: Vec<FileTaskProgress> = workers.map(files, |path| {
+            let content = std::fs::read_to_string(&path).ok()?;
+            let (total, completed) = MarkdownParser::new().task_counts(&content);
+            (total > 0).then_some(FileTaskProgress { path, total, completed })
+        }).await?
+            .into_iter()
+            .flatten()
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let progress = TaskProgress {
+            total: files.iter().map(|file| file.total).sum(),
+            completed: files.iter().map(|file| file.completed).sum(),
+            files,
+        };
+        info!("{} of {} tasks done in {:?}", progress.completed, progress.total, dir);
+        Ok(progress)
+    }
+
     /// Check if a file exists and is readable
     pub async fn is_file_accessible(&self, path: &Path) -> bool {
         match tokio::fs::metadata(path).await {
@@ -235,6 +743,96 @@ impl FileService {
         }
     }
 
+    /// Open the OS file manager with the given file selected
+    pub fn reveal_in_file_manager(&self, path: &Path) -> Result<()> {
+        debug!("Revealing in file manager: {:?}", path);
+
+        if !path.exists() {
+            return Err(anyhow::anyhow!("File does not exist: {:?}", path));
+        }
+
+        #[cfg(target_os = "macos")]
+        let mut command = {
+            let mut command = std::process::Command::new("open");
+            command.arg("-R").arg(path);
+            command
+        };
+
+        #[cfg(target_os = "windows")]
+        let mut command = {
+            let mut command = std::process::Command::new("explorer");
+            command.arg(format!("/select,{}", path.display()));
+            command
+        };
+
+        // Most Linux file managers implement the FileManager1 D-Bus interface; fall back
+        // to opening the containing folder when none is running
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        let mut command = {
+            let uri = url::Url::from_file_path(path.canonicalize()?)
+                .map_err(|_| anyhow::anyhow!("Can't build a file URI for: {:?}", path))?;
+            let shown = std::process::Command::new("dbus-send")
+                .args([
+                    "--session",
+                    "--dest=org.freedesktop.FileManager1",
+                    "--type=method_call",
+                    "/org/freedesktop/FileManager1",
+                    "org.freedesktop.FileManager1.ShowItems",
+                ])
+                .arg(format!("array:string:{}", uri))
+                .arg("string:")
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false);
+
+            if shown {
+                return Ok(());
+            }
+
+            let mut command = std::process::Command::new("xdg-open");
+            command.arg(path.parent().unwrap_or(path));
+            command
+        };
+
+        command.spawn()
+            .with_context(|| format!("Failed to open file manager for: {:?}", path))?;
+
+        info!("Revealed in file manager: {:?}", path);
+        Ok(())
+    }
+
+    /// Open a document or an export with the application registered for its type
+    ///
+    /// Other files are refused, so the webview can't use this to run programs.
+    pub fn open_with_default_app(&self, path: &Path) -> Result<()> {
+        debug!("Opening with default app: {:?}", path);
+
+        if !is_openable_path(path) {
+            anyhow::bail!("Only documents and exports can be opened with their app: {:?}", path);
+        }
+        self.check_access(path)?;
+        if !path.exists() {
+            return Err(anyhow::anyhow!("File does not exist: {:?}", path));
+        }
+
+        #[cfg(target_os = "macos")]
+        let mut command = std::process::Command::new("open");
+
+        // Explorer takes the path as a single argument, unlike `cmd /C start`,
+        // which would interpret `&` and friends in the file name
+        #[cfg(target_os = "windows")]
+        let mut command = std::process::Command::new("explorer.exe");
+
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        let mut command = std::process::Command::new("xdg-open");
+
+        command.arg(path).spawn()
+            .with_context(|| format!("Failed to open file with default app: {:?}", path))?;
+
+        info!("Opened with default app: {:?}", path);
+        Ok(())
+    }
+
     /// Get the size of a file in bytes
     pub async fn get_file_size(&self, path: &Path) -> Result<u64> {
         let metadata = tokio::fs::metadata(path).await
@@ -243,6 +841,142 @@ impl FileService {
     }
 }
 
+/// Bounds on how often the watcher task looks for debounced changes to deliver
+const MIN_WATCH_TICK: Duration = Duration::from_millis(5);
+const MAX_WATCH_TICK: Duration = Duration::from_millis(50);
+
+/// How a notify event is reported to subscribers; `None` for mere reads
+fn event_type(kind: &EventKind, path: &Path) -> Option<FileEventType> {
+    match kind {
+        EventKind::Create(_) => Some(FileEventType::Created),
+        EventKind::Remove(_) => Some(FileEventType::Deleted),
+        // Each side of a rename is reported on its own path
+        EventKind::Modify(ModifyKind::Name(_)) if path.exists() => Some(FileEventType::Created),
+        EventKind::Modify(ModifyKind::Name(_)) => Some(FileEventType::Deleted),
+        EventKind::Modify(_) | EventKind::Any => Some(FileEventType::Modified),
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some(FileEventType::Modified),
+        EventKind::Access(_) | EventKind::Other => None,
+    }
+}
+
+/// Resolve symlinks and `..` in `path`; a path that doesn't exist yet resolves
+/// through its nearest existing ancestor, so files can be checked before they're created
+fn resolve_path(path: &Path) -> Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return Ok(missing.iter().rev().fold(canonical, |resolved, name| resolved.join(name)));
+        }
+        // No file name means the path ends in `..`, which can't be resolved without the folder
+        let name = existing.file_name()
+            .with_context(|| format!("Can't resolve path: {:?}", path))?;
+        missing.push(name.to_os_string());
+        existing = existing.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .with_context(|| format!("Can't resolve path: {:?}", path))?;
+    }
+}
+
+fn to_file_metadata(path: &Path, metadata: &std::fs::Metadata) -> Result<FileMetadata> {
+    let modified = metadata.modified()
+        .with_context(|| format!("Failed to get modified time for: {:?}", path))?
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    Ok(FileMetadata {
+        path: path.to_path_buf(),
+        size: metadata.len(),
+        modified,
+        is_markdown: is_markdown_path(path),
+    })
+}
+
+/// Blocking counterpart of [`FileService::get_metadata`], for the worker pool
+fn file_metadata(path: &Path) -> Result<FileMetadata> {
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("Failed to get metadata for: {:?}", path))?;
+    to_file_metadata(path, &metadata)
+}
+
+/// Markdown files in `dir` and its subfolders, skipping hidden entries and symlinks
+///
+/// Only an unreadable `dir` fails; unreadable subfolders are logged and left out.
+async fn markdown_files_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = match read_dir_entries(&current).await {
+            Ok(entries) => entries,
+            Err(e) if current == dir => return Err(e),
+            Err(e) => {
+                warn!("Skipping unreadable folder: {:#}", e);
+                continue;
+            }
+        };
+
+        for (path, file_type) in entries {
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() && is_markdown_path(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The non-hidden entries of `dir` with their types, in name order; symlinks keep
+/// their own type, so a link to a folder is neither a folder nor a file
+async fn read_dir_entries(dir: &Path) -> Result<Vec<(PathBuf, std::fs::FileType)>> {
+    let mut entries = tokio::fs::read_dir(dir).await
+        .with_context(|| format!("Failed to read directory: {:?}", dir))?;
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next_entry().await
+        .with_context(|| format!("Failed to read directory: {:?}", dir))?
+    {
+        if !entry.file_name().to_string_lossy().starts_with('.') {
+            paths.push((entry.path(), entry.file_type().await?));
+        }
+    }
+    paths.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(paths)
+}
+
+/// Lines of `path` containing `needle` (already lowercased), at most `limit` of them
+fn search_file(path: &Path, needle: &str, limit: usize) -> Vec<SearchMatch> {
+    // Unreadable or non-UTF-8 files can't match a text query
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| line.to_lowercase().contains(needle))
+        .take(limit)
+        .map(|(index, line)| SearchMatch { path: path.to_path_buf(), line: index + 1, text: line.trim().to_string() })
+        .collect()
+}
+
+/// Markdown files, encrypted (`notes.md.enc`) or not
+fn is_markdown_path(path: &Path) -> bool {
+    let path = if is_encrypted_path(path) { Path::new(path.file_stem().unwrap_or_default()) } else { path };
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown" | "mdown" | "mkd"))
+}
+
+/// Documents and the formats they're exported to, which are safe to hand to
+/// their default app
+fn is_openable_path(path: &Path) -> bool {
+    is_markdown_path(path) || path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(
+            ext.to_lowercase().as_str(),
+            "txt" | "pdf" | "html" | "htm" | "docx" | "epub" | "tex" | "csv"
+        ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -293,4 +1027,231 @@ mod tests {
         
         // Note: This might still pass due to timing, but it's a basic test
     }
+
+    #[test]
+    fn test_reveal_missing_file() {
+        let service = FileService::new();
+        let missing = Path::new("/nonexistent/typolite/missing.md");
+
+        assert!(service.reveal_in_file_manager(missing).is_err());
+        assert!(service.open_with_default_app(missing).is_err());
+    }
+
+    #[test]
+    fn test_open_with_default_app_is_restricted() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let script = dir.path().join("run.sh");
+        let outside = dir.path().join("outside.pdf");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::write(&outside, "%PDF").unwrap();
+
+        let service = FileService::new();
+        service.set_access_policy(true, &[]);
+        let error = service.open_with_default_app(&script).unwrap_err().to_string();
+        assert!(error.contains("Only documents and exports"), "{}", error);
+        let error = service.open_with_default_app(&outside).unwrap_err().to_string();
+        assert!(error.contains("Access denied"), "{}", error);
+
+        assert!(is_openable_path(Path::new("notes.md.enc")));
+        assert!(is_openable_path(Path::new("Report.PDF")));
+        assert!(!is_openable_path(Path::new("setup.exe")));
+        assert!(!is_openable_path(Path::new("notes")));
+    }
+
+    #[tokio::test]
+    async fn test_read_lines_from_mapped_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("build.log");
+        let content: String = (1..=500).map(|i| format!("line {}\r\n", i)).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let service = FileService::new().with_large_file_threshold(1024);
+        let window = service.read_lines(&path, 10, 3).await.unwrap();
+
+        assert_eq!(window.lines, vec!["line 10", "line 11", "line 12"]);
+        assert_eq!(window.total_lines, None); // indexed only this far
+        assert_eq!(service.read_file(&path).await.unwrap(), content);
+
+        let tail = service.read_lines(&path, 499, 10).await.unwrap();
+        assert_eq!(tail.lines, vec!["line 499", "line 500", ""]);
+        assert_eq!(tail.total_lines, Some(501));
+
+        // The small-file path agrees
+        let small = FileService::new().read_lines(&path, 499, 10).await.unwrap();
+        assert_eq!(small.lines, tail.lines);
+        assert_eq!(small.total_lines, tail.total_lines);
+    }
+
+    #[tokio::test]
+    async fn test_shared_watcher_routes_events() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dir_path = dir.path().canonicalize().unwrap();
+        let note = dir_path.join("note.md");
+        std::fs::write(&note, "# Note").unwrap();
+
+        let service = FileService::new().with_debounce_delay(Duration::from_millis(50));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let file_tx = tx.clone();
+        let options = WatchOptions::default();
+        let file_watch = service.watch_file(note.clone(), &options, move |events| {
+            events.into_iter().for_each(|event| file_tx.send(("file", event.path)).unwrap());
+        }).await.unwrap();
+        service.watch_directory(dir_path.clone(), &options, move |events| {
+            events.into_iter().for_each(|event| tx.send(("dir", event.path)).unwrap());
+        }).await.unwrap();
+        assert_eq!(service.watcher.lock().unwrap().watched.len(), 2);
+
+        std::fs::write(&note, "# Changed").unwrap();
+        let mut seen = Vec::new();
+        while seen.len() < 2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            seen.push(event);
+        }
+        seen.sort();
+        assert_eq!(seen, vec![("dir", note.clone()), ("file", note.clone())]);
+
+        // Only the directory subscription is left
+        service.unwatch(&file_watch).unwrap();
+        assert_eq!(service.watcher.lock().unwrap().watched.len(), 1);
+        std::fs::write(dir_path.join("other.md"), "# Other").unwrap();
+        loop {
+            let (source, path) = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            assert_eq!(source, "dir");
+            if path == dir_path.join("other.md") {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_coalesce_modes() {
+        let subscription = |coalesce| Subscription {
+            path: PathBuf::from("/notes"),
+            recursive: true,
+            debounce: Duration::from_millis(100),
+            coalesce,
+            callback: Arc::new(|_| {}),
+            pending: Vec::new(),
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let mut latest = subscription(CoalesceMode::Latest);
+        latest.record(Path::new("/notes/a.md"), FileEventType::Created, at(0));
+        latest.record(Path::new("/notes/b.md"), FileEventType::Modified, at(50));
+        latest.record(Path::new("/notes/a.md"), FileEventType::Modified, at(60));
+        assert!(latest.take_ready(at(120)).is_empty());
+        let ready = latest.take_ready(at(155));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0][0].path, PathBuf::from("/notes/b.md"));
+        assert_eq!(latest.take_ready(at(160)).len(), 1);
+
+        // A batch waits until every path has settled, then delivers them together
+        let mut batch = subscription(CoalesceMode::Batch);
+        batch.record(Path::new("/notes/a.md"), FileEventType::Created, at(0));
+        batch.record(Path::new("/notes/b.md"), FileEventType::Modified, at(50));
+        assert!(batch.take_ready(at(120)).is_empty());
+        let ready = batch.take_ready(at(150));
+        let paths: Vec<_> = ready[0].iter().map(|event| event.path.clone()).collect();
+        assert_eq!(paths, vec![PathBuf::from("/notes/a.md"), PathBuf::from("/notes/b.md")]);
+        assert!(batch.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_access_policy() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let workspace = dir.path().join("notes");
+        let secrets = dir.path().join("secrets");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::create_dir_all(&secrets).unwrap();
+        std::fs::write(secrets.join("id_rsa"), "key").unwrap();
+        std::fs::write(secrets.join("picked.md"), "# Picked").unwrap();
+
+        let service = FileService::new();
+        service.set_access_policy(true, &[]);
+        service.set_workspace_root(Some(&workspace));
+
+        service.write_file(&workspace.join("new/todo.md"), "- [ ] a").await.unwrap();
+        assert_eq!(service.read_file(&workspace.join("new/todo.md")).await.unwrap(), "- [ ] a");
+        assert!(service.read_file(&secrets.join("id_rsa")).await.is_err());
+        assert!(service.read_file(&workspace.join("../secrets/id_rsa")).await.is_err());
+        assert!(service.write_file(&workspace.join("missing/../../secrets/x.md"), "x").await.is_err());
+
+        service.allow_file(&secrets.join("picked.md"));
+        assert!(service.read_file(&secrets.join("picked.md")).await.is_ok());
+        assert!(service.read_lines(&secrets.join("id_rsa"), 1, 1).await.is_err());
+
+        service.set_access_policy(false, &[]);
+        assert!(service.read_file(&secrets.join("id_rsa")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_search_markdown_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("notes")).unwrap();
+        std::fs::create_dir_all(dir.path().join(".trash")).unwrap();
+        std::fs::write(dir.path().join("notes/todo.md"), "# Todo\n\n- Call the Printer people\n").unwrap();
+        std::fs::write(dir.path().join(".trash/old.md"), "printer").unwrap();
+        std::fs::write(dir.path().join("printer.txt"), "printer").unwrap();
+
+        let service = FileService::new();
+        let matches = service.search_markdown_files(dir.path(), "printer", 10, &WorkerPool::with_parallelism(2)).await.unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].path, dir.path().join("notes/todo.md"));
+        assert_eq!(matches[0].line, 3);
+        assert_eq!(matches[0].text, "- Call the Printer people");
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_documents() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("diary.md.enc");
+        let service = FileService::new().with_cipher(DocumentCipher::new().with_iterations(1000));
+        assert!(service.write_file(&path, "# Private\nline two").await.is_err());
+
+        service.set_encryption_password(Some("hunter2".to_string()));
+        service.write_file(&path, "# Private\nline two").await.unwrap();
+
+        let on_disk = std::fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&on_disk).contains("Private"));
+        assert_eq!(service.read_file(&path).await.unwrap(), "# Private\nline two");
+        assert_eq!(service.read_lines(&path, 2, 5).await.unwrap().lines, vec!["line two"]);
+        assert!(is_markdown_path(&path));
+
+        service.set_encryption_password(Some("guess".to_string()));
+        assert!(service.read_file(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_task_progress() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("project")).unwrap();
+        std::fs::write(dir.path().join("plan.md"), "- [x] Draft\n- [ ] Review\n- [X] Budget\n").unwrap();
+        std::fs::write(dir.path().join("project/launch.md"), "1. [ ] Announce\n\n`- [x] not a task`\n").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "- plain item\n").unwrap();
+
+        let service = FileService::new();
+        let progress = service.task_progress(dir.path(), &WorkerPool::with_parallelism(2)).await.unwrap();
+
+        assert_eq!((progress.total, progress.completed), (4, 2));
+        assert_eq!(progress.files, vec![
+            FileTaskProgress { path: dir.path().join("plan.md"), total: 3, completed: 2 },
+            FileTaskProgress { path: dir.path().join("project/launch.md"), total: 1, completed: 0 },
+        ]);
+
+        // A symlink loop and an unreadable subfolder don't stop the rollup
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::os::unix::fs::symlink(dir.path(), dir.path().join("project/loop")).unwrap();
+            let locked = dir.path().join("locked");
+            std::fs::create_dir(&locked).unwrap();
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+            let progress = service.task_progress(dir.path(), &WorkerPool::with_parallelism(2)).await.unwrap();
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert_eq!((progress.total, progress.completed), (4, 2));
+        }
+    }
 }
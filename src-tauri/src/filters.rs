@@ -0,0 +1,132 @@
+use anyhow::{Result, Context};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::settings::{FilterStage, HtmlFilter};
+
+/// Runs the HTML filters declared in settings over rendered output
+pub struct HtmlFilterRunner {
+    timeout: Duration,
+}
+
+impl Default for HtmlFilterRunner {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl HtmlFilterRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pipe HTML through every enabled filter for `stage`, in declaration order
+    ///
+    /// A filter that fails or times out is logged and skipped, leaving the HTML
+    /// it was given untouched.
+    pub async fn apply(&self, filters: &[HtmlFilter], stage: FilterStage, html: String) -> String {
+        let mut html = html;
+
+        for filter in filters.iter().filter(|f| f.enabled && f.stage.includes(stage)) {
+            match self.run_filter(filter, stage, &html).await {
+                Ok(output) => html = output,
+                Err(e) => warn!("HTML filter '{}' failed: {:#}", filter.name, e),
+            }
+        }
+
+        html
+    }
+
+    async fn run_filter(&self, filter: &HtmlFilter, stage: FilterStage, html: &str) -> Result<String> {
+        debug!("Running HTML filter '{}' ({:?})", filter.name, stage);
+
+        let stage_name = match stage {
+            FilterStage::Export => "export",
+            _ => "preview",
+        };
+        let mut child = Command::new(&filter.command)
+            .args(&filter.args)
+            .env("TYPOLITE_FILTER_STAGE", stage_name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start filter command: {}", filter.command))?;
+
+        // Feed stdin concurrently so a filter that streams output can't deadlock us
+        let mut stdin = child.stdin.take().context("Filter stdin unavailable")?;
+        let input = html.to_string();
+        let writer = tokio::spawn(async move {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        });
+
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| anyhow::anyhow!("Filter timed out after {:?}", self.timeout))??;
+        let _ = writer.await;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Filter exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        String::from_utf8(output.stdout).context("Filter produced invalid UTF-8")
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn filter(name: &str, command: &str, args: &[&str], stage: FilterStage) -> HtmlFilter {
+        HtmlFilter {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            stage,
+            enabled: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filters_run_in_order_for_stage() {
+        let filters = vec![
+            filter("rewrite", "sed", &["s/old/new/"], FilterStage::Both),
+            filter("wrap", "sed", &["s/^/<div>/"], FilterStage::Export),
+        ];
+        let runner = HtmlFilterRunner::new();
+
+        let preview = runner.apply(&filters, FilterStage::Preview, "<p>old</p>".to_string()).await;
+        assert_eq!(preview.trim(), "<p>new</p>");
+
+        let export = runner.apply(&filters, FilterStage::Export, "<p>old</p>".to_string()).await;
+        assert_eq!(export.trim(), "<div><p>new</p>");
+    }
+
+    #[tokio::test]
+    async fn test_failing_filter_leaves_html_unchanged() {
+        let filters = vec![
+            filter("missing", "typolite-no-such-filter", &[], FilterStage::Both),
+            filter("fails", "sh", &["-c", "exit 3"], FilterStage::Both),
+            filter("slow", "sleep", &["5"], FilterStage::Both),
+        ];
+        let runner = HtmlFilterRunner::new().with_timeout(Duration::from_millis(200));
+
+        let html = runner.apply(&filters, FilterStage::Preview, "<p>x</p>".to_string()).await;
+        assert_eq!(html, "<p>x</p>");
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{Result, Context};
+use serde_yaml::{Mapping, Value};
+
+/// YAML front matter at the top of a document, delimited by `---` lines
+#[derive(Debug, Clone, Default)]
+pub struct FrontMatter {
+    fields: Mapping,
+}
+
+impl FrontMatter {
+    /// Split a document into its front matter and the remaining markdown body
+    ///
+    /// Documents without a leading `---` block get empty front matter and are
+    /// returned unchanged.
+    pub fn parse(markdown: &str) -> Result<(FrontMatter, &str)> {
+        let Some((yaml, body)) = split_front_matter(markdown) else {
+            return Ok((FrontMatter::default(), markdown));
+        };
+
+        let fields = if yaml.trim().is_empty() {
+            Mapping::new()
+        } else {
+            serde_yaml::from_str(yaml).context("Invalid YAML front matter")?
+        };
+        Ok((FrontMatter { fields }, body))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+
+    /// A scalar field as a string, e.g. `title` or `date`
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        match self.get(key)? {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key)?.as_bool()
+    }
+
+    /// A list field, accepting either a YAML sequence or a comma-separated string
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Value::Sequence(items)) => items
+                .iter()
+                .filter_map(|item| match item {
+                    Value::String(s) => Some(s.trim().to_string()),
+                    Value::Number(n) => Some(n.to_string()),
+                    _ => None,
+                })
+                .filter(|item| !item.is_empty())
+                .collect(),
+            Some(Value::String(s)) => s
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.fields.contains_key(key)
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<Value>) {
+        self.fields.insert(Value::String(key.to_string()), value.into());
+    }
+
+    /// Serialize back into a `---` delimited block followed by `body`
+    pub fn render(&self, body: &str) -> Result<String> {
+        if self.is_empty() {
+            return Ok(body.to_string());
+        }
+
+        let yaml = serde_yaml::to_string(&self.fields).context("Failed to serialize front matter")?;
+        Ok(format!("---\n{}---\n\n{}", yaml, body.trim_start()))
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.get_str("title").filter(|title| !title.trim().is_empty())
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        self.get_list("tags")
+    }
+}
+
+/// Find the raw YAML between the opening and closing `---` lines
+fn split_front_matter(markdown: &str) -> Option<(&str, &str)> {
+    let rest = markdown.strip_prefix("\u{feff}").unwrap_or(markdown);
+    let rest = rest.strip_prefix("---")?;
+    let rest = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_front_matter() {
+        let markdown = "---\ntitle: Hello World\ntags: [rust, tauri]\ndraft: true\n---\n# Body\n";
+        let (front_matter, body) = FrontMatter::parse(markdown).unwrap();
+
+        assert_eq!(front_matter.title().as_deref(), Some("Hello World"));
+        assert_eq!(front_matter.tags(), vec!["rust", "tauri"]);
+        assert_eq!(front_matter.get_bool("draft"), Some(true));
+        assert_eq!(body, "# Body\n");
+    }
+
+    #[test]
+    fn test_documents_without_front_matter() {
+        let (front_matter, body) = FrontMatter::parse("# Title\n\n---\n").unwrap();
+        assert!(front_matter.is_empty());
+        assert_eq!(body, "# Title\n\n---\n");
+
+        // An unterminated block is treated as ordinary content
+        let (front_matter, body) = FrontMatter::parse("---\ntitle: x\n").unwrap();
+        assert!(front_matter.is_empty());
+        assert_eq!(body, "---\ntitle: x\n");
+    }
+
+    #[test]
+    fn test_render_roundtrip() {
+        let (mut front_matter, body) = FrontMatter::parse("---\ntitle: Post\n---\nText\n").unwrap();
+        front_matter.set("draft", true);
+
+        let rendered = front_matter.render(body).unwrap();
+        assert_eq!(rendered, "---\ntitle: Post\ndraft: true\n---\n\nText\n");
+    }
+
+    #[test]
+    fn test_comma_separated_tags() {
+        let (front_matter, _) = FrontMatter::parse("---\ntags: writing, markdown ,\n---\n").unwrap();
+
+        assert_eq!(front_matter.tags(), vec!["writing", "markdown"]);
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Keychain entry holding the GitHub personal access token (needs the `gist` scope)
+pub const GITHUB_TOKEN_KEY: &str = "github-token";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GistRequest {
+    /// Name of the markdown file in the gist, e.g. `notes.md`
+    pub file_name: String,
+    pub markdown: String,
+    /// Rendered HTML uploaded alongside the markdown, if requested
+    pub html: Option<String>,
+    pub description: Option<String>,
+    pub public: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GistResult {
+    pub id: String,
+    pub url: String,
+}
+
+/// Client for creating gists through the GitHub REST API
+pub struct GistService {
+    client: reqwest::Client,
+    api_base: String,
+}
+
+impl Default for GistService {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("Typora-Lite/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            api_base: "https://api.github.com".to_string(),
+        }
+    }
+}
+
+impl GistService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_api_base(mut self, api_base: String) -> Self {
+        self.api_base = api_base;
+        self
+    }
+
+    /// Upload the document as a new gist and return its URL
+    pub async fn publish(&self, request: &GistRequest, token: &str) -> Result<GistResult> {
+        let url = format!("{}/gists", self.api_base.trim_end_matches('/'));
+        debug!("Publishing {} as a gist ({} chars)", request.file_name, request.markdown.len());
+
+        let response: Value = self.client
+            .post(&url)
+            .bearer_auth(token)
+            .header("Accept", "application/vnd.github+json")
+            .json(&build_gist_body(request))
+            .send()
+            .await
+            .context("Failed to reach GitHub")?
+            .error_for_status()
+            .context("GitHub rejected the gist")?
+            .json()
+            .await
+            .context("Invalid response from GitHub")?;
+
+        let result = GistResult {
+            id: response["id"].as_str().unwrap_or_default().to_string(),
+            url: response["html_url"].as_str()
+                .ok_or_else(|| anyhow::anyhow!("GitHub response did not include a gist URL"))?
+                .to_string(),
+        };
+
+        info!("Published gist {}", result.url);
+        Ok(result)
+    }
+}
+
+fn build_gist_body(request: &GistRequest) -> Value {
+    let file_name = if request.file_name.trim().is_empty() {
+        "document.md".to_string()
+    } else {
+        request.file_name.clone()
+    };
+
+    let mut files = Map::new();
+    if let Some(html) = &request.html {
+        let stem = file_name.rsplit_once('.').map_or(file_name.as_str(), |(stem, _)| stem);
+        files.insert(format!("{}.html", stem), json!({ "content": html }));
+    }
+    files.insert(file_name, json!({ "content": request.markdown }));
+
+    json!({
+        "description": request.description.clone().unwrap_or_default(),
+        "public": request.public,
+        "files": files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gist_body_includes_optional_html() {
+        let mut request = GistRequest {
+            file_name: "notes.md".to_string(),
+            markdown: "# Notes".to_string(),
+            html: None,
+            description: Some("Meeting notes".to_string()),
+            public: false,
+        };
+
+        let body = build_gist_body(&request);
+        assert_eq!(body["files"]["notes.md"]["content"], "# Notes");
+        assert_eq!(body["files"].as_object().unwrap().len(), 1);
+        assert_eq!(body["public"], false);
+
+        request.html = Some("<h1>Notes</h1>".to_string());
+        let body = build_gist_body(&request);
+        assert_eq!(body["files"]["notes.html"]["content"], "<h1>Notes</h1>");
+    }
+
+    #[test]
+    fn test_gist_body_defaults_file_name() {
+        let request = GistRequest {
+            file_name: " ".to_string(),
+            markdown: "text".to_string(),
+            html: None,
+            description: None,
+            public: true,
+        };
+
+        assert_eq!(build_gist_body(&request)["files"]["document.md"]["content"], "text");
+    }
+}
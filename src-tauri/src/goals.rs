@@ -0,0 +1,204 @@
+use anyhow::{Result, Context};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::frontmatter::FrontMatter;
+use crate::settings::{app_config_dir, GoalSettings};
+
+/// Front matter key overriding the document goal, e.g. `word_goal: 1500`
+pub const WORD_GOAL_KEY: &str = "word_goal";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GoalKind {
+    /// Words added across all documents today
+    Daily,
+    /// Length of the document being edited
+    Document,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoalProgress {
+    pub kind: GoalKind,
+    pub target: usize,
+    pub words: usize,
+    pub remaining: usize,
+    /// Capped at 100
+    pub percent: u32,
+    pub reached: bool,
+    /// Reached by this edit, for the one-off celebration; true at most once a day per goal
+    pub just_reached: bool,
+}
+
+impl GoalProgress {
+    fn new(kind: GoalKind, target: usize, words: usize, just_reached: bool) -> Self {
+        Self {
+            kind,
+            target,
+            words,
+            remaining: target.saturating_sub(words),
+            percent: (words.min(target) * 100 / target.max(1)) as u32,
+            reached: words >= target,
+            just_reached,
+        }
+    }
+}
+
+/// Payload of the `goal-progress` event sent after each parse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalProgressEvent {
+    pub document: String,
+    pub goals: Vec<GoalProgress>,
+}
+
+/// Word goal for a document: its `word_goal` front matter, else the configured default
+pub fn document_word_goal(markdown: &str, settings: &GoalSettings) -> Option<usize> {
+    let from_front_matter = FrontMatter::parse(markdown).ok()
+        .and_then(|(front_matter, _)| front_matter.get(WORD_GOAL_KEY)?.as_u64());
+    from_front_matter.map(|goal| goal as usize)
+        .or(settings.document_words)
+        .filter(|&goal| goal > 0)
+}
+
+/// Word counts seen today, for measuring the daily goal
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GoalDay {
+    /// `%Y-%m-%d`
+    date: String,
+    /// First and latest word count of each document edited today
+    documents: HashMap<String, (usize, usize)>,
+    /// Goals already celebrated today: `daily`, or `document:<key>`
+    reached: HashSet<String>,
+}
+
+/// Measures writing goals from the word counts of successive parses
+///
+/// The daily goal counts words added today: for each document, its latest word
+/// count minus the count it had when first edited today. The day's counts are
+/// saved so that restarting the app doesn't reset the goal.
+pub struct GoalTracker {
+    path: PathBuf,
+    day: Mutex<GoalDay>,
+}
+
+impl Default for GoalTracker {
+    fn default() -> Self {
+        Self::load(app_config_dir().join("goals.json"))
+    }
+}
+
+impl GoalTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let day = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Invalid goal progress {:?}, starting fresh: {}", path, e);
+                GoalDay::default()
+            }),
+            Err(_) => GoalDay::default(),
+        };
+        Self { path, day: Mutex::new(day) }
+    }
+
+    /// Record a document's word count and report progress on the configured goals;
+    /// empty when no goal applies
+    pub fn record(&self, document: &str, words: usize, document_goal: Option<usize>, daily_goal: Option<usize>) -> Vec<GoalProgress> {
+        self.record_on(document, words, document_goal, daily_goal, chrono::Local::now().date_naive())
+    }
+
+    pub fn record_on(&self, document: &str, words: usize, document_goal: Option<usize>, daily_goal: Option<usize>, today: NaiveDate) -> Vec<GoalProgress> {
+        let mut day = self.day.lock().unwrap();
+        let date = today.format("%Y-%m-%d").to_string();
+        if day.date != date {
+            *day = GoalDay { date, ..GoalDay::default() };
+        }
+        let counts = day.documents.entry(document.to_string()).or_insert((words, words));
+        counts.1 = words;
+
+        let mut goals = Vec::new();
+        if let Some(target) = daily_goal.filter(|&target| target > 0) {
+            let written = day.documents.values().map(|(first, latest)| latest.saturating_sub(*first)).sum();
+            let just_reached = written >= target && day.reached.insert("daily".to_string());
+            if just_reached {
+                info!("Daily goal of {} words reached", target);
+            }
+            goals.push(GoalProgress::new(GoalKind::Daily, target, written, just_reached));
+        }
+        if let Some(target) = document_goal.filter(|&target| target > 0) {
+            let just_reached = words >= target && day.reached.insert(format!("document:{}", document));
+            goals.push(GoalProgress::new(GoalKind::Document, target, words, just_reached));
+        }
+        goals
+    }
+
+    /// Persist today's counts to disk
+    pub async fn save(&self) -> Result<()> {
+        let json = serde_json::to_string(&*self.day.lock().unwrap())?;
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await
+                .with_context(|| format!("Failed to create goal directory: {:?}", parent))?;
+        }
+        tokio::fs::write(&self.path, json).await
+            .with_context(|| format!("Failed to write goal progress: {:?}", self.path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_daily_goal_counts_words_added_today() {
+        let temp_dir = TempDir::new().unwrap();
+        let tracker = GoalTracker::load(temp_dir.path().join("goals.json"));
+        let monday = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+
+        // Existing words don't count towards the day
+        let goals = tracker.record_on("a.md", 1_000, None, Some(500), monday);
+        assert_eq!(goals, vec![GoalProgress { kind: GoalKind::Daily, target: 500, words: 0, remaining: 500, percent: 0, reached: false, just_reached: false }]);
+        tracker.record_on("a.md", 1_300, None, Some(500), monday);
+        tracker.record_on("b.md", 0, None, Some(500), monday);
+
+        let goals = tracker.record_on("b.md", 250, None, Some(500), monday);
+        assert_eq!((goals[0].words, goals[0].percent, goals[0].just_reached), (550, 100, true));
+        let goals = tracker.record_on("b.md", 260, None, Some(500), monday);
+        assert!(goals[0].reached && !goals[0].just_reached);
+
+        let tuesday = monday.succ_opt().unwrap();
+        let goals = tracker.record_on("b.md", 260, None, Some(500), tuesday);
+        assert_eq!((goals[0].words, goals[0].reached), (0, false));
+    }
+
+    #[tokio::test]
+    async fn test_document_goal_and_persistence() {
+        let settings = GoalSettings { daily_words: None, document_words: Some(2_000) };
+        assert_eq!(document_word_goal("# Draft\n", &settings), Some(2_000));
+        assert_eq!(document_word_goal("---\nword_goal: 800\n---\n# Essay\n", &settings), Some(800));
+        assert_eq!(document_word_goal("# Draft\n", &GoalSettings::default()), None);
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("goals.json");
+        let day = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let tracker = GoalTracker::load(path.clone());
+        let goals = tracker.record_on("essay.md", 600, Some(800), None, day);
+        assert_eq!((goals[0].kind, goals[0].remaining, goals[0].percent), (GoalKind::Document, 200, 75));
+        tracker.record_on("essay.md", 800, Some(800), None, day);
+        tracker.save().await.unwrap();
+
+        let reloaded = GoalTracker::load(path);
+        let goals = reloaded.record_on("essay.md", 810, Some(800), Some(100), day);
+        assert_eq!(goals.len(), 2);
+        assert_eq!(goals[0].words, 210);
+        assert!(goals[1].reached && !goals[1].just_reached);
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{Result, Context};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::settings::GrammarSettings;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GrammarIssue {
+    pub message: String,
+    pub short_message: String,
+    pub rule_id: String,
+    pub category: String,
+    pub start: usize, // byte offset into the checked content
+    pub end: usize,
+    pub replacements: Vec<String>,
+}
+
+/// Client for a LanguageTool server's `/v2/check` endpoint
+pub struct GrammarChecker {
+    client: reqwest::Client,
+}
+
+impl Default for GrammarChecker {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(15))
+            .build()
+            .unwrap_or_default();
+
+        Self { client }
+    }
+}
+
+impl GrammarChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check markdown content for grammar and style issues
+    ///
+    /// Markdown syntax and code are sent to LanguageTool as markup so that only
+    /// prose is checked, while reported offsets still refer to the full source.
+    pub async fn check(&self, content: &str, language: &str, settings: &GrammarSettings) -> Result<Vec<GrammarIssue>> {
+        if !settings.enabled {
+            return Err(anyhow::anyhow!("Grammar checking is disabled in settings"));
+        }
+
+        let url = format!("{}/v2/check", settings.server_url.trim_end_matches('/'));
+        debug!("Checking grammar via {} ({} chars, {})", url, content.len(), language);
+
+        let data = serde_json::json!({ "annotation": annotate_markdown(content) });
+        let response = self.client
+            .post(&url)
+            .form(&[("language", language), ("data", &data.to_string())])
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach LanguageTool server at {}", settings.server_url))?
+            .error_for_status()
+            .context("LanguageTool server returned an error")?
+            .json::<CheckResponse>()
+            .await
+            .context("Invalid response from LanguageTool server")?;
+
+        let issues = response.into_issues(content);
+        info!("Grammar check complete: {} issues", issues.len());
+        Ok(issues)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+enum Annotation {
+    Text { text: String },
+    Markup { markup: String },
+}
+
+/// Split markdown into LanguageTool text/markup annotations covering the whole source
+fn annotate_markdown(content: &str) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+    let mut pos = 0;
+    let mut in_code_block = false;
+
+    for (event, span) in Parser::new_ext(content, Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(_) if !in_code_block && span.start >= pos => {
+                if span.start > pos {
+                    annotations.push(Annotation::Markup { markup: content[pos..span.start].to_string() });
+                }
+                annotations.push(Annotation::Text { text: content[span.clone()].to_string() });
+                pos = span.end;
+            }
+            _ => {}
+        }
+    }
+
+    if pos < content.len() {
+        annotations.push(Annotation::Markup { markup: content[pos..].to_string() });
+    }
+
+    annotations
+}
+
+/// Convert a UTF-16 code unit offset (as used by LanguageTool) to a byte offset
+fn utf16_to_byte_offset(content: &str, utf16_offset: usize) -> usize {
+    let mut units = 0;
+    for (byte_offset, c) in content.char_indices() {
+        if units >= utf16_offset {
+            return byte_offset;
+        }
+        units += c.len_utf16();
+    }
+    content.len()
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckResponse {
+    matches: Vec<Match>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Match {
+    message: String,
+    #[serde(default)]
+    short_message: String,
+    offset: usize,
+    length: usize,
+    replacements: Vec<Replacement>,
+    rule: Rule,
+}
+
+#[derive(Debug, Deserialize)]
+struct Replacement {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rule {
+    id: String,
+    category: Category,
+}
+
+#[derive(Debug, Deserialize)]
+struct Category {
+    name: String,
+}
+
+impl CheckResponse {
+    fn into_issues(self, content: &str) -> Vec<GrammarIssue> {
+        self.matches
+            .into_iter()
+            .map(|m| GrammarIssue {
+                start: utf16_to_byte_offset(content, m.offset),
+                end: utf16_to_byte_offset(content, m.offset + m.length),
+                message: m.message,
+                short_message: m.short_message,
+                rule_id: m.rule.id,
+                category: m.rule.category.name,
+                replacements: m.replacements.into_iter().map(|r| r.value).take(5).collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotate_markdown_covers_source() {
+        let content = "# Title\n\nSome **bold** text.\n\n```\ncode here\n```\n";
+
+        let annotations = annotate_markdown(content);
+        let rebuilt: String = annotations.iter().map(|a| match a {
+            Annotation::Text { text } => text.as_str(),
+            Annotation::Markup { markup } => markup.as_str(),
+        }).collect();
+
+        assert_eq!(rebuilt, content);
+        assert!(annotations.contains(&Annotation::Text { text: "bold".to_string() }));
+        assert!(!annotations.contains(&Annotation::Text { text: "code here\n".to_string() }));
+    }
+
+    #[test]
+    fn test_response_offsets_converted_to_bytes() {
+        let content = "Café is a  place.";
+        let response: CheckResponse = serde_json::from_str(r#"{"matches": [{
+            "message": "Possible typo: you repeated a whitespace",
+            "shortMessage": "",
+            "offset": 9, "length": 2,
+            "replacements": [{"value": " "}],
+            "rule": {"id": "WHITESPACE_RULE", "category": {"name": "Typography"}}
+        }]}"#).unwrap();
+
+        let issues = response.into_issues(content);
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(&content[issues[0].start..issues[0].end], "  ");
+        assert_eq!(issues[0].rule_id, "WHITESPACE_RULE");
+        assert_eq!(issues[0].replacements, vec![" "]);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_grammar_check() {
+        let checker = GrammarChecker::new();
+        let settings = GrammarSettings::default();
+
+        assert!(checker.check("Some text", "en-US", &settings).await.is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// How another application gave away that it has a document open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldKind {
+    /// The process has a handle on the file itself
+    OpenHandle,
+    /// An editor left its lock or swap file next to the document
+    LockFile,
+    /// The file is opened without sharing, so no one else can write it
+    Exclusive,
+}
+
+/// Another application that has the document open
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileHolder {
+    pub kind: HoldKind,
+    pub pid: Option<u32>,
+    /// Process or application name, where it can be told
+    pub process: Option<String>,
+    /// The lock file a `LockFile` holder was found through
+    pub lock_file: Option<PathBuf>,
+}
+
+/// Sent as `file-holders-changed` whenever the set of holders changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileHoldersEvent {
+    pub path: PathBuf,
+    pub holders: Vec<FileHolder>,
+}
+
+/// Other applications holding `path` open, best effort
+///
+/// Lock files left by Office, LibreOffice, Vim and Emacs are checked on every
+/// platform. Open handles are read from `/proc` on Linux and `lsof` on macOS;
+/// Windows only shows that some process opened the file without sharing.
+/// This process is never reported. Blocking, so call it off the async runtime.
+pub fn find_holders(path: &Path) -> Vec<FileHolder> {
+    let mut holders = lock_file_holders(path);
+    holders.extend(open_handle_holders(path));
+    holders
+}
+
+/// Sibling lock files other editors create while they have `path` open
+fn lock_file_holders(path: &Path) -> Vec<FileHolder> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|name| name.to_str())) else {
+        return Vec::new();
+    };
+    // Office shortens long names by dropping their first two characters
+    let office_short = name.char_indices().nth(2).map(|(at, _)| format!("~${}", &name[at..]));
+    let candidates = [
+        (Some(format!("~${}", name)), "Microsoft Office"),
+        (office_short.filter(|_| name.chars().count() > 8), "Microsoft Office"),
+        (Some(format!(".~lock.{}#", name)), "LibreOffice"),
+        (Some(format!(".{}.swp", name)), "Vim"),
+        (Some(format!(".#{}", name)), "Emacs"),
+    ];
+
+    let mut holders: Vec<FileHolder> = Vec::new();
+    for (lock_name, process) in candidates {
+        let Some(lock_name) = lock_name else { continue };
+        let lock_file = dir.join(lock_name);
+        // Emacs locks are dangling symlinks, so don't follow them
+        if std::fs::symlink_metadata(&lock_file).is_err() || holders.iter().any(|holder| holder.process.as_deref() == Some(process)) {
+            continue;
+        }
+        holders.push(FileHolder {
+            kind: HoldKind::LockFile,
+            pid: None,
+            process: Some(process.to_string()),
+            lock_file: Some(lock_file),
+        });
+    }
+    holders
+}
+
+#[cfg(target_os = "linux")]
+fn open_handle_holders(path: &Path) -> Vec<FileHolder> {
+    let Ok(target) = std::fs::canonicalize(path) else { return Vec::new() };
+    let Ok(processes) = std::fs::read_dir("/proc") else { return Vec::new() };
+    let own_pid = std::process::id();
+
+    processes
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| pid != own_pid)
+        .filter(|pid| {
+            // Processes of other users can't be read, and are skipped
+            std::fs::read_dir(format!("/proc/{}/fd", pid))
+                .map(|fds| fds.flatten().any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link == target)))
+                .unwrap_or(false)
+        })
+        .map(|pid| FileHolder {
+            kind: HoldKind::OpenHandle,
+            pid: Some(pid),
+            process: std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok().map(|comm| comm.trim().to_string()),
+            lock_file: None,
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn open_handle_holders(path: &Path) -> Vec<FileHolder> {
+    // `-F pc` prints a `p<pid>` line, then a `c<command>` line, per process
+    let output = match std::process::Command::new("lsof").arg("-w").arg("-F").arg("pc").arg("--").arg(path).output() {
+        Ok(output) => output,
+        Err(e) => {
+            tracing::debug!("Failed to run lsof: {}", e);
+            return Vec::new();
+        }
+    };
+    let own_pid = std::process::id();
+    let mut holders: Vec<FileHolder> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(pid) = line.strip_prefix('p').and_then(|pid| pid.parse::<u32>().ok()) {
+            holders.push(FileHolder { kind: HoldKind::OpenHandle, pid: Some(pid), process: None, lock_file: None });
+        } else if let (Some(command), Some(holder)) = (line.strip_prefix('c'), holders.last_mut()) {
+            holder.process = Some(command.to_string());
+        }
+    }
+    holders.retain(|holder| holder.pid != Some(own_pid));
+    holders
+}
+
+#[cfg(windows)]
+fn open_handle_holders(path: &Path) -> Vec<FileHolder> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    // Finding out who holds a file needs the Restart Manager; a sharing
+    // violation at least tells that someone has it locked
+    match std::fs::OpenOptions::new().read(true).share_mode(0).open(path) {
+        Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => vec![FileHolder {
+            kind: HoldKind::Exclusive,
+            pid: None,
+            process: None,
+            lock_file: None,
+        }],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn open_handle_holders(_path: &Path) -> Vec<FileHolder> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_file_holders() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("quarterly report.md");
+        std::fs::write(&path, "# Q3").unwrap();
+        assert!(lock_file_holders(&path).is_empty());
+
+        std::fs::write(dir.path().join("~$arterly report.md"), "").unwrap();
+        std::fs::write(dir.path().join(".quarterly report.md.swp"), "").unwrap();
+        std::fs::write(dir.path().join(".~lock.other.md#"), "").unwrap();
+
+        let holders = lock_file_holders(&path);
+        let processes: Vec<_> = holders.iter().map(|holder| holder.process.as_deref().unwrap()).collect();
+        assert_eq!(processes, vec!["Microsoft Office", "Vim"]);
+        assert!(holders.iter().all(|holder| holder.kind == HoldKind::LockFile && holder.pid.is_none()));
+        assert_eq!(holders[1].lock_file.as_deref(), Some(dir.path().join(".quarterly report.md.swp").as_path()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_open_handle_holders() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "notes").unwrap();
+
+        // Our own handles don't count
+        let _own = std::fs::File::open(&path).unwrap();
+        assert!(find_holders(&path).is_empty());
+
+        let mut child = std::process::Command::new("sh")
+            .arg("-c")
+            .arg("exec 3<\"$0\"; read _")
+            .arg(&path)
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut holders = Vec::new();
+        for _ in 0..50 {
+            holders = find_holders(&path);
+            if !holders.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].kind, HoldKind::OpenHandle);
+        assert_eq!(holders[0].pid, Some(child.id()));
+        assert_eq!(holders[0].process.as_deref(), Some("sh"));
+    }
+}
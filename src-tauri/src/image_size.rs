@@ -0,0 +1,178 @@
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Width and height of a PNG, JPEG, GIF, WebP or BMP image, read from its
+/// header without decoding the pixels
+pub fn image_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut header = [0u8; 32];
+    let mut len = 0;
+    while len < header.len() {
+        match reader.read(&mut header[len..]).ok()? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    let header = &header[..len];
+
+    if header.starts_with(&[0xFF, 0xD8]) {
+        reader.seek(SeekFrom::Start(2)).ok()?;
+        return jpeg_dimensions(&mut reader);
+    }
+    header_dimensions(header)
+}
+
+/// Like [`image_dimensions`], for an image already in memory
+pub fn image_data_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        return jpeg_dimensions(&mut Cursor::new(&data[2..]));
+    }
+    header_dimensions(&data[..data.len().min(32)])
+}
+
+/// The bytes of the image an exported page's `src` points at: a base64 `data:`
+/// URL, or a local file with relative paths resolved against `base_dir`.
+/// Remote images are not fetched.
+pub fn read_image_source(src: &str, base_dir: Option<&Path>) -> Result<Vec<u8>> {
+    if let Some(data_url) = src.strip_prefix("data:") {
+        let Some((_, encoded)) = data_url.split_once(";base64,") else {
+            bail!("only base64 data URLs are supported");
+        };
+        return base64::engine::general_purpose::STANDARD.decode(encoded.trim()).context("invalid base64 data URL");
+    }
+    if src.starts_with("http://") || src.starts_with("https://") || src.starts_with("//") {
+        bail!("remote images are not embedded");
+    }
+
+    let path = src.strip_prefix("file://").unwrap_or(src);
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let path = match base_dir {
+        Some(base_dir) => base_dir.join(path.as_ref()),
+        None => Path::new(path.as_ref()).to_path_buf(),
+    };
+    std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))
+}
+
+/// Extension and media type of a PNG, JPEG, GIF, WebP or SVG image, the formats
+/// EPUB readers have to support
+pub fn image_media_type(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    if data.starts_with(b"\x89PNG") {
+        Some(("png", "image/png"))
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        Some(("jpg", "image/jpeg"))
+    } else if data.starts_with(b"GIF8") {
+        Some(("gif", "image/gif"))
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        Some(("webp", "image/webp"))
+    } else if String::from_utf8_lossy(&data[..data.len().min(1024)]).contains("<svg") {
+        Some(("svg", "image/svg+xml"))
+    } else {
+        None
+    }
+}
+
+/// Formats that keep their size at a fixed offset near the start of the file
+fn header_dimensions(header: &[u8]) -> Option<(u32, u32)> {
+    let be32 = |at: usize| Some(u32::from_be_bytes(header.get(at..at + 4)?.try_into().ok()?));
+    let le16 = |at: usize| Some(u16::from_le_bytes(header.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le24 = |at: usize| Some(le16(at)? | (*header.get(at + 2)? as u32) << 16);
+    let le32 = |at: usize| Some(u32::from_le_bytes(header.get(at..at + 4)?.try_into().ok()?));
+
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") && header.get(12..16) == Some(b"IHDR") {
+        return Some((be32(16)?, be32(20)?));
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if header.starts_with(b"BM") {
+        return Some((le32(18)?, (le32(22)? as i32).unsigned_abs()));
+    }
+    if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+        return match header.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3FFF, le16(28)? & 0x3FFF)),
+            b"VP8L" => {
+                let bits = le32(21)?;
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        };
+    }
+    None
+}
+
+/// Walk the JPEG segments after the start marker up to the frame header
+fn jpeg_dimensions(reader: &mut (impl Read + Seek)) -> Option<(u32, u32)> {
+    let mut byte = [0u8; 1];
+    loop {
+        // Markers may be padded with any number of 0xFF bytes
+        reader.read_exact(&mut byte).ok()?;
+        if byte[0] != 0xFF {
+            return None;
+        }
+        while byte[0] == 0xFF {
+            reader.read_exact(&mut byte).ok()?;
+        }
+        let marker = byte[0];
+        if marker == 0xD9 || marker == 0xDA {
+            return None;
+        }
+        if (0xD0..=0xD7).contains(&marker) || marker == 0x01 {
+            continue;
+        }
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).ok()?;
+        let length = u16::from_be_bytes(length) as i64;
+        // SOF0-SOF15, except DHT, JPG and DAC which share the range
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let mut frame = [0u8; 5];
+            reader.read_exact(&mut frame).ok()?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]) as u32;
+            let width = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+            return Some((width, height));
+        }
+        reader.seek(SeekFrom::Current(length - 2)).ok()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_header_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(header_dimensions(&png), Some((640, 480)));
+
+        assert_eq!(header_dimensions(b"GIF89a\x20\x03\x58\x02"), Some((800, 600)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0\0\0\0\0".to_vec();
+        webp.extend_from_slice(&[0x7F, 0x07, 0x00, 0x37, 0x04, 0x00]);
+        assert_eq!(header_dimensions(&webp), Some((1920, 1080)));
+
+        assert_eq!(header_dimensions(b"<svg xmlns=\"http://www.w3.org/2000/svg\">"), None);
+    }
+
+    #[test]
+    fn test_jpeg_dimensions_skip_segments() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("photo.jpg");
+        let mut jpeg = vec![0xFF, 0xD8];
+        // An APP1 segment (e.g. EXIF) before the frame header
+        jpeg.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x06, 1, 2, 3, 4]);
+        jpeg.extend_from_slice(&[0xFF, 0xFF, 0xC2, 0x00, 0x11, 0x08, 0x02, 0xD0, 0x05, 0x00]);
+        std::fs::write(&path, &jpeg).unwrap();
+
+        assert_eq!(image_dimensions(&path), Some((1280, 720)));
+        assert_eq!(image_data_dimensions(&jpeg), Some((1280, 720)));
+        assert_eq!(image_dimensions(&dir.path().join("missing.png")), None);
+    }
+}
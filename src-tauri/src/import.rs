@@ -0,0 +1,140 @@
+use anyhow::Result;
+use tracing::debug;
+
+/// Elements whose contents never belong in the converted markdown
+const STRIPPED_ELEMENTS: &[&str] = &["head", "style", "script", "noscript", "template"];
+
+#[derive(Default)]
+pub struct ImportService;
+
+impl ImportService {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Convert an HTML fragment (e.g. pasted rich text) into clean markdown
+    pub fn html_to_markdown(&self, html: &str) -> Result<String> {
+        debug!("Converting HTML to markdown ({} bytes)", html.len());
+
+        let mut cleaned = strip_comments(html);
+        for element in STRIPPED_ELEMENTS {
+            cleaned = strip_element(&cleaned, element);
+        }
+
+        let markdown = html2md::parse_html(&cleaned);
+        Ok(tidy_markdown(&markdown))
+    }
+}
+
+/// Remove `<!-- ... -->` comments, including Office's StartFragment markers
+fn strip_comments(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find("<!--") {
+        output.push_str(&rest[..start]);
+        rest = match rest[start..].find("-->") {
+            Some(end) => &rest[start + end + 3..],
+            None => "",
+        };
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Remove every `<name ...>...</name>` block, matching tag names case-insensitively
+pub(crate) fn strip_element(html: &str, name: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+
+    let mut output = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(offset) = lower[pos..].find(&open) {
+        let start = pos + offset;
+        let after_name = lower.as_bytes().get(start + open.len()).copied();
+
+        // Skip tags that merely share a prefix, e.g. <header> when stripping <head>
+        if !matches!(after_name, Some(b'>' | b' ' | b'\t' | b'\n' | b'\r' | b'/')) {
+            output.push_str(&html[pos..start + open.len()]);
+            pos = start + open.len();
+            continue;
+        }
+
+        output.push_str(&html[pos..start]);
+        pos = match lower[start..].find(&close) {
+            Some(end) => start + end + close.len(),
+            None => html.len(),
+        };
+    }
+
+    output.push_str(&html[pos..]);
+    output
+}
+
+/// Normalize converter output: ATX headings, no trailing spaces, at most one blank line in a row
+fn tidy_markdown(markdown: &str) -> String {
+    let lines: Vec<&str> = markdown.lines().map(str::trim_end).collect();
+    let mut output: Vec<String> = Vec::with_capacity(lines.len());
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let underline = lines.get(i + 1).copied().unwrap_or("");
+        let is_underline = |c: char| underline.len() >= 3 && underline.chars().all(|ch| ch == c);
+
+        if !line.trim().is_empty() && is_underline('=') {
+            output.push(format!("# {}", line.trim()));
+            i += 2;
+            continue;
+        }
+        if !line.trim().is_empty() && is_underline('-') {
+            output.push(format!("## {}", line.trim()));
+            i += 2;
+            continue;
+        }
+
+        let line = line.replace('\u{a0}', " ");
+        let is_blank = line.trim().is_empty();
+        let previous_blank = output.last().map_or(true, String::is_empty);
+        if !(is_blank && previous_blank) {
+            output.push(if is_blank { String::new() } else { line });
+        }
+        i += 1;
+    }
+
+    output.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_markdown() {
+        let service = ImportService::new();
+        let html = r#"<html><head><style>p { color: red }</style></head><body>
+            <!--StartFragment--><h1>Title</h1><h2>Section</h2>
+            <p><b>Bold</b> and <a href="https://example.com">link</a></p>
+            <ul><li>one</li><li>two</li></ul>
+            <script>alert(1)</script><!--EndFragment--></body></html>"#;
+
+        let markdown = service.html_to_markdown(html).unwrap();
+
+        assert!(markdown.starts_with("# Title\n\n## Section"));
+        assert!(markdown.contains("**Bold** and [link](https://example.com)"));
+        assert!(markdown.contains("* one\n* two"));
+        assert!(!markdown.contains("color: red"));
+        assert!(!markdown.contains("alert"));
+        assert!(!markdown.contains("\n\n\n"));
+    }
+
+    #[test]
+    fn test_strip_element_ignores_prefixed_tags() {
+        let html = "<header>Keep</header><head><title>Drop</title></head>";
+
+        assert_eq!(strip_element(html, "head"), "<header>Keep</header>");
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Result, Context};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::settings::JournalSettings;
+
+/// Used when no template is configured
+const DEFAULT_TEMPLATE: &str = "# {{title}}\n\n← {{yesterday}} | {{tomorrow}} →\n\n";
+
+/// Appended to custom templates that don't link the neighbouring days themselves
+const NAVIGATION_FOOTER: &str = "\n\n---\n← {{yesterday}} | {{tomorrow}} →\n";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyNote {
+    pub path: PathBuf,
+    /// ISO date the note is for
+    pub date: String,
+    pub content: String,
+    /// Whether the note was just created from the template
+    pub created: bool,
+}
+
+#[derive(Default)]
+pub struct JournalService;
+
+impl JournalService {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Open the note for `date` in `folder`, creating it from the template first if needed
+    pub async fn open(&self, date: NaiveDate, folder: &Path, settings: &JournalSettings) -> Result<DailyNote> {
+        validate_journal_settings(settings)?;
+        let path = folder.join(note_file_name(date, settings));
+        debug!("Opening daily note {:?}", path);
+
+        if let Ok(content) = tokio::fs::read_to_string(&path).await {
+            return Ok(DailyNote { path, date: date.to_string(), content, created: false });
+        }
+
+        let template = match &settings.template {
+            Some(template) => {
+                let template = folder.join(template);
+                tokio::fs::read_to_string(&template).await
+                    .with_context(|| format!("Failed to read journal template: {:?}", template))?
+            }
+            None => DEFAULT_TEMPLATE.to_string(),
+        };
+        let content = render_journal_template(&template, date, settings);
+
+        tokio::fs::create_dir_all(folder).await
+            .with_context(|| format!("Failed to create journal folder: {:?}", folder))?;
+        tokio::fs::write(&path, &content).await
+            .with_context(|| format!("Failed to create daily note: {:?}", path))?;
+
+        info!("Created daily note {:?}", path);
+        Ok(DailyNote { path, date: date.to_string(), content, created: true })
+    }
+}
+
+/// Reject file name formats chrono can't render or that would leave the journal folder
+pub fn validate_journal_settings(settings: &JournalSettings) -> Result<()> {
+    let format = &settings.file_name_format;
+    let invalid = format.trim().is_empty()
+        || format.contains(['/', '\\'])
+        || StrftimeItems::new(format).any(|item| matches!(item, Item::Error));
+    if invalid {
+        return Err(anyhow::anyhow!("Invalid journal file name format: {}", format));
+    }
+    Ok(())
+}
+
+fn note_file_name(date: NaiveDate, settings: &JournalSettings) -> String {
+    format!("{}.md", date.format(&settings.file_name_format))
+}
+
+/// Fill in a template's `{{date}}`, `{{title}}`, `{{weekday}}`, `{{yesterday}}` and
+/// `{{tomorrow}}` placeholders; the last two become links to the neighbouring notes
+pub fn render_journal_template(template: &str, date: NaiveDate, settings: &JournalSettings) -> String {
+    let mut template = template.to_string();
+    if !template.contains("{{yesterday}}") && !template.contains("{{tomorrow}}") {
+        template.push_str(NAVIGATION_FOOTER);
+    }
+
+    let link = |day: Option<NaiveDate>| {
+        day.map(|day| {
+            let file_name = note_file_name(day, settings);
+            // Angle brackets keep file names with spaces valid link targets
+            let target = if file_name.contains(' ') { format!("<{}>", file_name) } else { file_name };
+            format!("[{}]({})", day, target)
+        })
+        .unwrap_or_default()
+    };
+
+    template
+        .replace("{{date}}", &date.to_string())
+        .replace("{{title}}", &date.format("%A, %-d %B %Y").to_string())
+        .replace("{{weekday}}", &date.format("%A").to_string())
+        .replace("{{yesterday}}", &link(date.checked_sub_days(Days::new(1))))
+        .replace("{{tomorrow}}", &link(date.checked_add_days(Days::new(1))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    #[test]
+    fn test_render_journal_template() {
+        let settings = JournalSettings::default();
+
+        assert_eq!(
+            render_journal_template(DEFAULT_TEMPLATE, date(), &settings),
+            "# Friday, 1 March 2024\n\n← [2024-02-29](2024-02-29.md) | [2024-03-02](2024-03-02.md) →\n\n"
+        );
+
+        let settings = JournalSettings { file_name_format: "%d %b %Y".to_string(), ..settings };
+        assert_eq!(
+            render_journal_template("Notes for {{weekday}}", date(), &settings),
+            "Notes for Friday\n\n---\n← [2024-02-29](<29 Feb 2024.md>) | [2024-03-02](<02 Mar 2024.md>) →\n"
+        );
+    }
+
+    #[test]
+    fn test_validate_journal_settings() {
+        assert!(validate_journal_settings(&JournalSettings::default()).is_ok());
+        for format in ["", "%Y/%m-%d", "%Q"] {
+            let settings = JournalSettings { file_name_format: format.to_string(), ..JournalSettings::default() };
+            assert!(validate_journal_settings(&settings).is_err(), "{:?} should be rejected", format);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_creates_note_once() {
+        let folder = TempDir::new().unwrap();
+        let journal = folder.path().join("Journal");
+        let service = JournalService::new();
+        let settings = JournalSettings::default();
+
+        let note = service.open(date(), &journal, &settings).await.unwrap();
+        assert!(note.created);
+        assert_eq!(note.path, journal.join("2024-03-01.md"));
+
+        std::fs::write(&note.path, "edited").unwrap();
+        let reopened = service.open(date(), &journal, &settings).await.unwrap();
+        assert!(!reopened.created);
+        assert_eq!(reopened.content, "edited");
+    }
+}
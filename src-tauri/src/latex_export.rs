@@ -0,0 +1,493 @@
+use percent_encoding::percent_decode_str;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::export::ExportOptions;
+use crate::frontmatter::FrontMatter;
+use crate::math::math_span;
+
+/// Marks where a footnote's text goes until its definition has been read
+const FOOTNOTE_MARK: char = '\u{1}';
+
+/// Build a LaTeX document from markdown source
+///
+/// Headings become numbered sections, code blocks `verbatim` environments
+/// and tables `tabular`s. `$...$`, `$$...$$` and ```` ```math ```` blocks are
+/// passed through to TeX untouched. Title and authors come from the options,
+/// else the front matter; the page size and margins set up `geometry`.
+pub fn markdown_to_latex(markdown: &str, options: &ExportOptions) -> String {
+    let (front_matter, body) = FrontMatter::parse(markdown).unwrap_or_else(|_| (FrontMatter::default(), markdown));
+    let title = options.title.clone().or_else(|| front_matter.title());
+    let authors = if options.authors.is_empty() {
+        [front_matter.get_list("author"), front_matter.get_list("authors")].concat()
+    } else {
+        options.authors.clone()
+    };
+
+    let mut parser_options = Options::empty();
+    parser_options.insert(Options::ENABLE_TABLES);
+    parser_options.insert(Options::ENABLE_FOOTNOTES);
+    parser_options.insert(Options::ENABLE_STRIKETHROUGH);
+    parser_options.insert(Options::ENABLE_TASKLISTS);
+    parser_options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+
+    let mut writer = LatexWriter::new(body, options);
+    for (event, range) in Parser::new_ext(body, parser_options).into_offset_iter() {
+        writer.event(event, range);
+    }
+    let content = writer.finish();
+
+    let (width, height) = options.page_size.dimensions();
+    let margins = &options.margins;
+    let mut latex = format!(
+        "\\documentclass[11pt]{{article}}\n\
+         \\usepackage[T1]{{fontenc}}\n\
+         \\usepackage[utf8]{{inputenc}}\n\
+         \\usepackage[paperwidth={}in,paperheight={}in,top={}in,right={}in,bottom={}in,left={}in]{{geometry}}\n\
+         \\usepackage{{amsmath,amssymb}}\n\
+         \\usepackage{{graphicx}}\n\
+         \\usepackage[export]{{adjustbox}}\n\
+         \\usepackage[normalem]{{ulem}}\n\
+         \\usepackage{{hyperref}}\n\n",
+        width, height, margins.top, margins.right, margins.bottom, margins.left,
+    );
+    if let Some(title) = &title {
+        latex.push_str(&format!("\\title{{{}}}\n", escape(title)));
+        let authors: Vec<String> = authors.iter().map(|author| escape(author)).collect();
+        latex.push_str(&format!("\\author{{{}}}\n\\date{{}}\n\n", authors.join(" \\and ")));
+    }
+    latex.push_str("\\begin{document}\n\n");
+    if title.is_some() {
+        latex.push_str("\\maketitle\n\n");
+    }
+    if options.include_toc {
+        latex.push_str("\\tableofcontents\n\n");
+    }
+    latex.push_str(content.trim());
+    latex.push_str("\n\n\\end{document}\n");
+    latex
+}
+
+struct Table {
+    cell: usize,
+    head: bool,
+}
+
+struct LatexWriter<'a> {
+    source: &'a str,
+    options: &'a ExportOptions,
+    /// Output, with a new buffer pushed while a footnote definition is read
+    buffers: Vec<String>,
+    footnotes: HashMap<String, String>,
+    /// `Some(start)` per open list, `None` for bulleted ones
+    lists: Vec<Option<u64>>,
+    /// An item was started and its `\item` not written yet, in case a task marker replaces it
+    item_pending: bool,
+    code_block: Option<bool>,
+    table: Option<Table>,
+    /// Destination and alt text of the image being read
+    image: Option<(String, String)>,
+    /// Label of the heading being read, from its id attribute or text
+    heading: Option<(Option<String>, String)>,
+    heading_count: HashMap<String, usize>,
+    /// Source range of the last math span, whose events are already written
+    math: Range<usize>,
+}
+
+impl<'a> LatexWriter<'a> {
+    fn new(source: &'a str, options: &'a ExportOptions) -> Self {
+        Self {
+            source,
+            options,
+            buffers: vec![String::new()],
+            footnotes: HashMap::new(),
+            lists: Vec::new(),
+            item_pending: false,
+            code_block: None,
+            table: None,
+            image: None,
+            heading: None,
+            heading_count: HashMap::new(),
+            math: 0..0,
+        }
+    }
+
+    /// Start environments on a line of their own
+    fn line(&mut self) {
+        let buffer = self.buffers.last().unwrap();
+        if !buffer.is_empty() && !buffer.ends_with('\n') {
+            self.write("\n");
+        }
+    }
+
+    fn write(&mut self, latex: &str) {
+        if self.item_pending {
+            self.item_pending = false;
+            self.buffers.last_mut().unwrap().push_str("\\item ");
+        }
+        self.buffers.last_mut().unwrap().push_str(latex);
+    }
+
+    fn event(&mut self, event: Event, range: Range<usize>) {
+        // Markdown inside math, such as `*` or `_`, was written as part of it
+        if range.start > self.math.start && range.end <= self.math.end {
+            return;
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text, range),
+            Event::Code(code) => {
+                if let Some((_, alt)) = &mut self.image {
+                    alt.push_str(&code);
+                } else {
+                    self.heading_text(&code);
+                    self.write(&format!("\\texttt{{{}}}", escape(&code)));
+                }
+            }
+            Event::FootnoteReference(label) => {
+                self.write(&format!("\\footnote{{{mark}{}{mark}}}", label, mark = FOOTNOTE_MARK));
+            }
+            Event::SoftBreak => self.write("\n"),
+            Event::HardBreak => self.write("\\\\\n"),
+            Event::Rule => self.write("\\par\\noindent\\rule{\\linewidth}{0.4pt}\\par\n\n"),
+            Event::TaskListMarker(checked) => {
+                self.item_pending = false;
+                self.write(if checked { "\\item[$\\boxtimes$] " } else { "\\item[$\\square$] " });
+            }
+            // Raw HTML has no LaTeX meaning
+            Event::Html(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {}
+            Tag::Heading(level, id, _) => {
+                let command = match level {
+                    HeadingLevel::H1 => "section",
+                    HeadingLevel::H2 => "subsection",
+                    HeadingLevel::H3 => "subsubsection",
+                    HeadingLevel::H4 => "paragraph",
+                    _ => "subparagraph",
+                };
+                self.write(&format!("\\{}{{", command));
+                self.heading = Some((id.map(str::to_string), String::new()));
+            }
+            Tag::BlockQuote => {
+                self.line();
+                self.write("\\begin{quote}\n");
+            }
+            Tag::CodeBlock(kind) => {
+                self.line();
+                let math = matches!(&kind, CodeBlockKind::Fenced(lang) if lang.split_whitespace().next() == Some("math"));
+                self.write(if math { "\\[\n" } else { "\\begin{verbatim}\n" });
+                self.code_block = Some(math);
+            }
+            Tag::List(start) => {
+                self.line();
+                self.write(if start.is_some() { "\\begin{enumerate}\n" } else { "\\begin{itemize}\n" });
+                if let Some(start) = start.filter(|&start| start != 1) {
+                    let counter = ["enumi", "enumii", "enumiii", "enumiv"][self.lists.iter().filter(|list| list.is_some()).count().min(3)];
+                    self.write(&format!("\\setcounter{{{}}}{{{}}}\n", counter, start.saturating_sub(1)));
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => self.item_pending = true,
+            Tag::FootnoteDefinition(_) => self.buffers.push(String::new()),
+            Tag::Table(alignments) => {
+                let columns: String = alignments.iter()
+                    .map(|alignment| match alignment {
+                        Alignment::Center => "c|",
+                        Alignment::Right => "r|",
+                        _ => "l|",
+                    })
+                    .collect();
+                self.line();
+                self.write(&format!("\\begin{{center}}\n\\begin{{tabular}}{{|{}}}\n\\hline\n", columns));
+                self.table = Some(Table { cell: 0, head: false });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.cell = 0;
+                    table.head = matches!(tag, Tag::TableHead);
+                }
+            }
+            Tag::TableCell => {
+                let Some(table) = &mut self.table else { return };
+                let separator = if table.cell > 0 { " & " } else { "" };
+                let head = table.head;
+                table.cell += 1;
+                self.write(separator);
+                if head {
+                    self.write("\\textbf{");
+                }
+            }
+            Tag::Emphasis => self.write("\\emph{"),
+            Tag::Strong => self.write("\\textbf{"),
+            Tag::Strikethrough => self.write("\\sout{"),
+            Tag::Link(_, dest, _) => {
+                let link = match dest.strip_prefix('#') {
+                    Some(anchor) => format!("\\hyperref[{}]{{", label(anchor)),
+                    None => format!("\\href{{{}}}{{", escape_url(&dest)),
+                };
+                self.write(&link);
+            }
+            Tag::Image(_, dest, _) => self.image = Some((dest.to_string(), String::new())),
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.write("\n\n"),
+            Tag::Heading(..) => {
+                let Some((id, text)) = self.heading.take() else { return };
+                let anchor = id.unwrap_or_else(|| self.anchor(&text));
+                self.write(&format!("}}\\label{{{}}}\n\n", label(&anchor)));
+            }
+            Tag::BlockQuote => self.write("\\end{quote}\n\n"),
+            Tag::CodeBlock(_) => {
+                let math = self.code_block.take() == Some(true);
+                if !self.buffers.last().unwrap().ends_with('\n') {
+                    self.write("\n");
+                }
+                self.write(if math { "\\]\n\n" } else { "\\end{verbatim}\n\n" });
+            }
+            Tag::List(start) => {
+                self.lists.pop();
+                self.write(if start.is_some() { "\\end{enumerate}\n\n" } else { "\\end{itemize}\n\n" });
+            }
+            Tag::Item => {
+                // An empty item still needs its `\item`
+                if self.item_pending {
+                    self.write("");
+                }
+                let buffer = self.buffers.last_mut().unwrap();
+                let trimmed = buffer.trim_end().len();
+                buffer.truncate(trimmed);
+                buffer.push('\n');
+            }
+            Tag::FootnoteDefinition(label) => {
+                let text = self.buffers.pop().unwrap_or_default();
+                self.footnotes.insert(label.to_string(), text.trim().to_string());
+            }
+            Tag::Table(_) => {
+                self.table = None;
+                self.write("\\hline\n\\end{tabular}\n\\end{center}\n\n");
+            }
+            Tag::TableHead => self.write(" \\\\\n\\hline\n"),
+            Tag::TableRow => self.write(" \\\\\n"),
+            Tag::TableCell => {
+                if self.table.as_ref().is_some_and(|table| table.head) {
+                    self.write("}");
+                }
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) => self.write("}"),
+            Tag::Image(..) => {
+                let Some((dest, alt)) = self.image.take() else { return };
+                let image = if dest.starts_with("http://") || dest.starts_with("https://") {
+                    // TeX can't fetch images, so link to it instead
+                    format!("\\href{{{}}}{{{}}}", escape_url(&dest), escape(&alt))
+                } else {
+                    format!("\\includegraphics[max width=\\linewidth]{{{}}}", self.image_path(&dest))
+                };
+                self.write(&image);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str, range: Range<usize>) {
+        if self.code_block.is_some() {
+            // Verbatim and math block content is passed as is
+            self.write(text);
+            return;
+        }
+        if let Some((_, alt)) = &mut self.image {
+            alt.push_str(text);
+            return;
+        }
+
+        let start = range.start.max(self.math.end);
+        if !text.contains('$') && start == range.start {
+            self.heading_text(text);
+            self.write(&escape(text));
+            return;
+        }
+        // Math is found in the source, as markdown may have split or reformatted it
+        self.text_with_math(start, range.end);
+    }
+
+    fn text_with_math(&mut self, start: usize, end: usize) {
+        let source = self.source;
+        let mut at = start;
+        let mut plain = String::new();
+        while at < end {
+            let rest = &source[at..];
+            let c = rest.chars().next().unwrap();
+            if c == '\\' && rest[1..].starts_with(|c: char| c.is_ascii_punctuation()) {
+                plain.push(rest[1..].chars().next().unwrap());
+                at += 2;
+                continue;
+            }
+            if c == '$' {
+                if let Some((latex, span_end)) = latex_math(source, at) {
+                    self.heading_text(&plain);
+                    self.write(&escape(&plain));
+                    plain.clear();
+                    self.heading_text(&latex);
+                    self.write(&latex);
+                    if span_end > end {
+                        self.math = at..span_end;
+                    }
+                    at = span_end;
+                    continue;
+                }
+            }
+            plain.push(c);
+            at += c.len_utf8();
+        }
+        self.heading_text(&plain);
+        self.write(&escape(&plain));
+    }
+
+    fn heading_text(&mut self, text: &str) {
+        if let Some((_, heading)) = &mut self.heading {
+            heading.push_str(text);
+        }
+    }
+
+    /// The id the parser gives a heading with this text
+    fn anchor(&mut self, title: &str) -> String {
+        let base = title.to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect::<String>()
+            .trim_matches('-')
+            .to_string();
+        let count = self.heading_count.entry(base.clone()).or_insert(0);
+        *count += 1;
+        if *count == 1 { base } else { format!("{}-{}", base, count) }
+    }
+
+    /// Relative images are resolved against the document's folder, so the
+    /// `.tex` file can be compiled from anywhere
+    fn image_path(&self, dest: &str) -> String {
+        let path = percent_decode_str(dest.strip_prefix("file://").unwrap_or(dest)).decode_utf8_lossy().to_string();
+        let path = match &self.options.base_dir {
+            Some(base_dir) if !std::path::Path::new(&path).is_absolute() => base_dir.join(&path).to_string_lossy().to_string(),
+            _ => path,
+        };
+        path.replace('\\', "/")
+    }
+
+    fn finish(mut self) -> String {
+        let mut latex = std::mem::take(&mut self.buffers[0]);
+        for (label, text) in &self.footnotes {
+            latex = latex.replace(&format!("{mark}{}{mark}", label, mark = FOOTNOTE_MARK), text);
+        }
+        // References to footnotes that were never defined
+        let opening = format!("\\footnote{{{}", FOOTNOTE_MARK);
+        while let Some(start) = latex.find(&opening) {
+            let end = latex[start + opening.len()..].find(FOOTNOTE_MARK).map_or(latex.len(), |end| start + opening.len() + end + 2);
+            latex.replace_range(start..end.min(latex.len()), "");
+        }
+        latex
+    }
+}
+
+/// The LaTeX for the math span opening at `at`, and where the span ends
+fn latex_math(source: &str, at: usize) -> Option<(String, usize)> {
+    let span = math_span(source, at)?;
+    let latex = if span.display {
+        format!("\\[ {} \\]", span.tex)
+    } else {
+        format!("${}$", span.tex)
+    };
+    Some((latex, span.end))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `\href` takes URLs as they are, except for these
+fn escape_url(url: &str) -> String {
+    url.replace('\\', "\\\\").replace('#', "\\#").replace('%', "\\%").replace('{', "\\{").replace('}', "\\}")
+}
+
+/// Labels can't hold TeX's special characters
+fn label(anchor: &str) -> String {
+    anchor.chars().filter(|c| !matches!(c, '\\' | '{' | '}' | '#' | '%' | '$' | '^' | '~' | '&' | '_' | ',')).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::PageSize;
+    use std::path::PathBuf;
+
+    fn body(latex: &str) -> &str {
+        let start = latex.find("\\begin{document}").unwrap() + "\\begin{document}".len();
+        latex[start..latex.find("\\end{document}").unwrap()].trim()
+    }
+
+    #[test]
+    fn test_markdown_to_latex_blocks() {
+        let markdown = "# Intro & *more*\n\nCosts $5 and 10% of \\$x, see [setup](#setup) or [site](https://example.com/a#b).\n\n\
+            ## Setup\n\nEnergy $E = m*c^2* + x_1$ and\n\n$$\n\\sum_{i=1}^n i\n$$\n\n\
+            ```rust\nfn main() {}\n```\n\n\
+            3. three\n4. [x] four\n   - nested `code`\n\n\
+            | Name | Qty |\n|:-----|----:|\n| Tea | 2 |\n| Milk |\n\n\
+            ![Chart](img/chart%201.png)\n";
+        let options = ExportOptions { include_toc: false, base_dir: Some(PathBuf::from("/notes")), ..Default::default() };
+        let latex = markdown_to_latex(markdown, &options);
+
+        assert_eq!(body(&latex), concat!(
+            "\\section{Intro \\& \\emph{more}}\\label{intro---more}\n\n",
+            "Costs \\$5 and 10\\% of \\$x, see \\hyperref[setup]{setup} or \\href{https://example.com/a\\#b}{site}.\n\n",
+            "\\subsection{Setup}\\label{setup}\n\n",
+            "Energy $E = m*c^2* + x_1$ and\n\n",
+            "\\[ \\sum_{i=1}^n i \\]\n\n",
+            "\\begin{verbatim}\nfn main() {}\n\\end{verbatim}\n\n",
+            "\\begin{enumerate}\n\\setcounter{enumi}{2}\n",
+            "\\item three\n",
+            "\\item[$\\boxtimes$] four\n\\begin{itemize}\n\\item nested \\texttt{code}\n\\end{itemize}\n",
+            "\\end{enumerate}\n\n",
+            "\\begin{center}\n\\begin{tabular}{|l|r|}\n\\hline\n",
+            "\\textbf{Name} & \\textbf{Qty} \\\\\n\\hline\n",
+            "Tea & 2 \\\\\nMilk &  \\\\\n",
+            "\\hline\n\\end{tabular}\n\\end{center}\n\n",
+            "\\includegraphics[max width=\\linewidth]{/notes/img/chart 1.png}",
+        ));
+    }
+
+    #[test]
+    fn test_markdown_to_latex_document() {
+        let markdown = "---\ntitle: Field Notes\nauthor: Ada, Grace\n---\n\nSee the note.[^1] And $\\$ 5$ here.[^missing]\n\n[^1]: It's *short*.\n";
+        let options = ExportOptions { page_size: PageSize::Letter, ..Default::default() };
+        let latex = markdown_to_latex(markdown, &options);
+
+        assert!(latex.starts_with("\\documentclass[11pt]{article}\n"));
+        assert!(latex.contains("\\usepackage[paperwidth=8.5in,paperheight=11in,top=1in,right=1in,bottom=1in,left=1in]{geometry}"));
+        assert!(latex.contains("\\title{Field Notes}\n\\author{Ada \\and Grace}\n"));
+        assert_eq!(body(&latex), concat!(
+            "\\maketitle\n\n\\tableofcontents\n\n",
+            "See the note.\\footnote{It's \\emph{short}.} And $\\$ 5$ here.",
+        ));
+    }
+}
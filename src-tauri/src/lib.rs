@@ -2,8 +2,158 @@ pub mod parser;
 pub mod export;
 pub mod file_service;
 pub mod commands;
+pub mod settings;
+pub mod shortcuts;
+pub mod clipboard;
+pub mod import;
+pub mod notion;
+pub mod docx;
+pub mod clipper;
+pub mod spellcheck;
+pub mod grammar;
+pub mod assistant;
+pub mod palette;
+pub mod plugins;
+pub mod filters;
+pub mod workspace;
+pub mod sessions;
+pub mod secrets;
+pub mod gist;
+pub mod frontmatter;
+pub mod publish;
+pub mod static_site;
+pub mod confluence;
+pub mod email;
+pub mod sync;
+pub mod cloud_sync;
+pub mod preview_server;
+pub mod preview_diff;
+pub mod parse_scheduler;
+pub mod documents;
+pub mod profiling;
+pub mod pdf_renderer;
+pub mod api_server;
+pub mod zotero;
+pub mod journal;
+pub mod feed;
+pub mod site_builder;
+pub mod worker_pool;
+pub mod logging;
+pub mod shutdown;
+pub mod metrics;
+pub mod csp;
+pub mod tables;
+pub mod image_size;
+pub mod encryption;
+pub mod pdf_edit;
+pub mod pdf_signing;
+pub mod links;
+pub mod stylesheets;
+pub mod text_diff;
+pub mod compare;
+pub mod conflicts;
+pub mod versions;
+pub mod goals;
+pub mod scratchpad;
+pub mod merge;
+pub mod book;
+pub mod citation_styles;
+pub mod bidi;
+pub mod emoji;
+pub mod accessibility;
+pub mod qr;
+pub mod lists;
+pub mod diagnostics;
+pub mod holders;
+pub mod export_queue;
+pub mod problems;
+pub mod docx_export;
+pub mod epub_export;
+pub mod latex_export;
+pub mod presets;
+pub mod pdf_metadata;
+pub mod pdf_outline;
+pub mod export_jobs;
+pub mod code_highlight;
+pub mod math;
+pub mod mermaid;
 
 pub use parser::*;
 pub use export::*;
 pub use file_service::*;
 pub use commands::*;
+pub use settings::*;
+pub use shortcuts::*;
+pub use clipboard::*;
+pub use import::*;
+pub use notion::*;
+pub use docx::*;
+pub use clipper::*;
+pub use spellcheck::*;
+pub use grammar::*;
+pub use assistant::*;
+pub use palette::*;
+pub use plugins::*;
+pub use filters::*;
+pub use workspace::*;
+pub use sessions::*;
+pub use secrets::*;
+pub use gist::*;
+pub use frontmatter::*;
+pub use publish::*;
+pub use static_site::*;
+pub use confluence::*;
+pub use email::*;
+pub use sync::*;
+pub use cloud_sync::*;
+pub use preview_server::*;
+pub use preview_diff::*;
+pub use parse_scheduler::*;
+pub use documents::*;
+pub use profiling::*;
+pub use pdf_renderer::*;
+pub use api_server::*;
+pub use zotero::*;
+pub use journal::*;
+pub use feed::*;
+pub use site_builder::*;
+pub use worker_pool::*;
+pub use logging::*;
+pub use shutdown::*;
+pub use metrics::*;
+pub use csp::*;
+pub use tables::*;
+pub use image_size::*;
+pub use encryption::*;
+pub use pdf_edit::*;
+pub use pdf_signing::*;
+pub use links::*;
+pub use stylesheets::*;
+pub use text_diff::*;
+pub use compare::*;
+pub use conflicts::*;
+pub use versions::*;
+pub use goals::*;
+pub use scratchpad::*;
+pub use merge::*;
+pub use book::*;
+pub use citation_styles::*;
+pub use bidi::*;
+pub use emoji::*;
+pub use accessibility::*;
+pub use qr::*;
+pub use lists::*;
+pub use diagnostics::*;
+pub use holders::*;
+pub use export_queue::*;
+pub use problems::*;
+pub use docx_export::*;
+pub use epub_export::*;
+pub use latex_export::*;
+pub use presets::*;
+pub use pdf_metadata::*;
+pub use pdf_outline::*;
+pub use export_jobs::*;
+pub use code_highlight::*;
+pub use math::*;
+pub use mermaid::*;
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+/// Ways to refer to a heading from elsewhere
+///
+/// Links that open the document in the app from other apps need a registered
+/// URL scheme, which the app doesn't have yet, so only the in-document anchor is
+/// offered.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HeadingLink {
+    /// `#anchor`, for links within the same document
+    pub anchor: String,
+}
+
+impl HeadingLink {
+    pub fn new(anchor: &str) -> Self {
+        Self { anchor: format!("#{}", anchor) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heading_link() {
+        assert_eq!(HeadingLink::new("next-steps").anchor, "#next-steps");
+    }
+}
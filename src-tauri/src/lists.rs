@@ -0,0 +1,192 @@
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// What Enter does in a list item: text to put in place of `start..end`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ListContinuation {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// Where the cursor goes once the edit is applied
+    pub cursor: usize,
+    /// The item was empty, so its marker is removed to end the list instead
+    pub ends_list: bool,
+}
+
+/// A list item's marker as written, e.g. `  3) [x] `
+#[derive(Debug, Clone, PartialEq)]
+struct ItemMarker {
+    /// Everything before the marker on its line: indentation and `>` quote markers
+    prefix: String,
+    bullet: Bullet,
+    /// Spaces between the marker and the content
+    spacing: usize,
+    task: bool,
+    /// Bytes of the line the marker, spacing and checkbox take up
+    len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Bullet {
+    Unordered(char),
+    Ordered { number: u64, width: usize, delimiter: char },
+}
+
+/// The edit continuing the list item under the cursor on Enter; `None` when
+/// the cursor isn't in a list item
+///
+/// The next item gets the same indentation and bullet, the next number for
+/// ordered lists, and an unchecked box for task lists. On an item with
+/// nothing after its marker, the marker is removed instead.
+pub fn list_continuation(content: &str, cursor_offset: usize) -> Option<ListContinuation> {
+    let cursor = floor_char_boundary(content, cursor_offset.min(content.len()));
+    let item = item_at(content, cursor)?;
+    let line_start = content[..item.start].rfind('\n').map_or(0, |i| i + 1);
+    let marker = parse_marker(&content[line_start..item.start], &content[item.start..])?;
+
+    let line_end = content[item.start..].find(['\r', '\n']).map_or(content.len(), |i| item.start + i);
+    let content_start = (item.start + marker.len).min(line_end);
+    if cursor < content_start {
+        // Before or inside the marker, where Enter is a plain line break
+        return None;
+    }
+    let has_more_lines = item.end > line_end && content[line_end..item.end].contains(|c: char| !c.is_whitespace());
+    if content[content_start..line_end].trim().is_empty() && !has_more_lines {
+        return Some(ListContinuation {
+            start: item.start,
+            end: line_end,
+            text: String::new(),
+            cursor: item.start,
+            ends_list: true,
+        });
+    }
+
+    let line_ending = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let text = format!("{}{}{}", line_ending, marker.prefix, marker.next());
+    Some(ListContinuation {
+        start: cursor,
+        end: cursor,
+        cursor: cursor + text.len(),
+        text,
+        ends_list: false,
+    })
+}
+
+impl ItemMarker {
+    /// Marker text of the item after this one
+    fn next(&self) -> String {
+        let bullet = match &self.bullet {
+            Bullet::Unordered(c) => c.to_string(),
+            Bullet::Ordered { number, width, delimiter } => format!("{:0width$}{}", number + 1, delimiter, width = width),
+        };
+        let checkbox = if self.task { "[ ] " } else { "" };
+        format!("{}{}{}", bullet, " ".repeat(self.spacing), checkbox)
+    }
+}
+
+/// Source range of the innermost list item containing `cursor`, counting the
+/// end of its last line as inside it
+fn item_at(content: &str, cursor: usize) -> Option<Range<usize>> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+
+    Parser::new_ext(content, options)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::Item) => {
+                let end = if content[..range.end].ends_with('\n') {
+                    range.end - 1
+                } else {
+                    content[range.end..].find(['\r', '\n']).map_or(content.len(), |i| range.end + i)
+                };
+                (range.start <= cursor && cursor <= end).then_some(range)
+            }
+            _ => None,
+        })
+        .last()
+}
+
+/// Read the marker at the start of `item`, whose line starts with `before`
+fn parse_marker(before: &str, item: &str) -> Option<ItemMarker> {
+    let prefix = if before.chars().all(|c| c.is_whitespace() || c == '>') {
+        before.to_string()
+    } else {
+        // The item follows another marker on the same line, as in `- 1. item`
+        " ".repeat(before.chars().count())
+    };
+
+    let first = item.chars().next()?;
+    let (bullet, mut len) = if matches!(first, '-' | '*' | '+') {
+        (Bullet::Unordered(first), 1)
+    } else {
+        let digits = item.find(|c: char| !c.is_ascii_digit()).unwrap_or(item.len());
+        let delimiter = item[digits..].chars().next().filter(|c| matches!(c, '.' | ')'))?;
+        let number = item[..digits].parse().ok()?;
+        let width = if item.starts_with('0') { digits } else { 0 };
+        (Bullet::Ordered { number, width, delimiter }, digits + 1)
+    };
+
+    let rest = &item[len..];
+    let spacing = rest.len() - rest.trim_start_matches(' ').len();
+    len += spacing;
+    let task = ["[ ]", "[x]", "[X]"].iter().any(|checkbox| item[len..].starts_with(checkbox));
+    if task {
+        len += 3;
+        len += item[len..].len() - item[len..].trim_start_matches(' ').len();
+    }
+
+    Some(ItemMarker { prefix, bullet, spacing: spacing.clamp(1, 4), task, len })
+}
+
+fn floor_char_boundary(text: &str, mut offset: usize) -> usize {
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enter(content: &str, after: &str) -> Option<ListContinuation> {
+        list_continuation(content, content.find(after).unwrap() + after.len())
+    }
+
+    #[test]
+    fn test_continue_list_markers() {
+        let content = "Intro\n\n- one\n  - [x] nested task\n- two\n\n9. nine\n10) ten\n\n> * quoted\n";
+
+        assert_eq!(enter(content, "Intro"), None);
+        assert_eq!(enter(content, "one").unwrap().text, "\n- ");
+        assert_eq!(enter(content, "nested task").unwrap().text, "\n  - [ ] ");
+        assert_eq!(enter(content, "nine").unwrap().text, "\n10. ");
+        assert_eq!(enter(content, "ten").unwrap().text, "\n11) ");
+        assert_eq!(enter(content, "quoted").unwrap().text, "\n> * ");
+
+        // Text after the cursor moves into the new item
+        let edit = enter(content, "- t").unwrap();
+        let at = content.find("wo\n").unwrap();
+        assert_eq!((edit.start, edit.end, edit.cursor), (at, at, at + 3));
+        assert_eq!(enter(content, "Intro\n\n"), None);
+
+        let padded = "07.  seven\r\n";
+        assert_eq!(enter(padded, "seven").unwrap().text, "\r\n08.  ");
+    }
+
+    #[test]
+    fn test_enter_on_empty_item_ends_list() {
+        let content = "- one\n- \n";
+        let edit = list_continuation(content, content.len() - 1).unwrap();
+        assert!(edit.ends_list);
+        assert_eq!((edit.start, edit.end, edit.text.as_str()), (6, 8, ""));
+
+        let task = "- [ ] done\n- [ ] ";
+        let edit = list_continuation(task, task.len()).unwrap();
+        assert!(edit.ends_list);
+        assert_eq!(&task[edit.start..edit.end], "- [ ] ");
+    }
+}
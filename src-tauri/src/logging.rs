@@ -0,0 +1,147 @@
+use anyhow::{Result, Context};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+use crate::metrics::CommandMetrics;
+use crate::settings::app_config_dir;
+
+/// Filter used when `RUST_LOG` isn't set
+const DEFAULT_DIRECTIVES: &str = "info,typolite=debug";
+
+/// Daily log files kept before the oldest is deleted
+const MAX_LOG_FILES: usize = 7;
+
+/// Where the rotating log files go
+pub fn default_log_dir() -> PathBuf {
+    app_config_dir().join("logs")
+}
+
+/// Changes what gets logged while the app runs, so a user can turn on debug
+/// output to capture an intermittent issue without restarting
+#[derive(Default)]
+pub struct LogController {
+    handle: Option<reload::Handle<EnvFilter, Registry>>,
+    directives: Mutex<String>,
+    log_dir: Option<PathBuf>,
+}
+
+impl LogController {
+    /// The filter in effect, in `RUST_LOG` syntax
+    pub fn directives(&self) -> String {
+        self.directives.lock().unwrap().clone()
+    }
+
+    /// Folder of the rotating log files, if file logging could be started
+    pub fn log_dir(&self) -> Option<&Path> {
+        self.log_dir.as_deref()
+    }
+
+    /// Replace the filter with `directives`, e.g. `debug` or `info,typolite::sync=trace`
+    pub fn set_level(&self, directives: &str) -> Result<()> {
+        let handle = self.handle.as_ref().context("Logging has not been initialized")?;
+        let filter = EnvFilter::try_new(directives)
+            .with_context(|| format!("Invalid log level: {}", directives))?;
+
+        handle.reload(filter).context("Failed to change the log level")?;
+        *self.directives.lock().unwrap() = directives.to_string();
+        info!("Log level set to {}", directives);
+        Ok(())
+    }
+}
+
+fn file_appender(log_dir: &Path) -> Result<RollingFileAppender> {
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("typolite")
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir)
+        .with_context(|| format!("Failed to open log folder: {:?}", log_dir))
+}
+
+/// Log to the console and to daily files under `log_dir`, starting from `RUST_LOG`,
+/// and time command handlers into `metrics`
+///
+/// The log filter only applies to the log output, so command timings are kept
+/// whatever the level. The returned guard flushes the file writer when dropped,
+/// so it must live as long as the app. If the log folder can't be used, only the
+/// console is logged to.
+pub fn init_logging(log_dir: &Path, metrics: &CommandMetrics) -> (LogController, Option<WorkerGuard>) {
+    let directives = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directives| EnvFilter::try_new(directives).is_ok())
+        .unwrap_or_else(|| DEFAULT_DIRECTIVES.to_string());
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(&directives));
+
+    let (file_layer, guard, file_error) = match file_appender(log_dir) {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(fmt::layer().with_ansi(false).with_writer(writer)), Some(guard), None)
+        }
+        Err(e) => (None, None, Some(e)),
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt::layer().and_then(file_layer).with_filter(filter))
+        .with(metrics.layer())
+        .init();
+
+    if let Some(e) = file_error {
+        warn!("Logging to the console only: {:#}", e);
+    }
+
+    let controller = LogController {
+        handle: Some(handle),
+        directives: Mutex::new(directives),
+        log_dir: guard.as_ref().map(|_| log_dir.to_path_buf()),
+    };
+    (controller, guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_set_level_reloads_filter() {
+        let (filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let subscriber = tracing_subscriber::registry().with(filter);
+        let controller = LogController {
+            handle: Some(handle.clone()),
+            directives: Mutex::new("info".to_string()),
+            log_dir: None,
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            controller.set_level("warn,typolite=trace").unwrap();
+            assert!(controller.set_level("typolite=loud").is_err());
+            assert_eq!(handle.with_current(|filter| filter.to_string()).unwrap(), "typolite=trace,warn");
+        });
+
+        assert_eq!(controller.directives(), "warn,typolite=trace");
+        assert!(LogController::default().set_level("debug").is_err());
+    }
+
+    #[test]
+    fn test_file_appender_writes_to_log_dir() {
+        use std::io::Write;
+
+        let dir = TempDir::new().unwrap();
+        let log_dir = dir.path().join("logs");
+        let mut appender = file_appender(&log_dir).unwrap();
+        writeln!(appender, "hello").unwrap();
+        appender.flush().unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&log_dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].starts_with("typolite.") && files[0].ends_with(".log"));
+    }
+}
@@ -1,25 +1,92 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use tauri::{CustomMenuItem, Menu, MenuItem, Submenu, WindowEvent};
-use tracing::info;
-use tracing_subscriber::EnvFilter;
+use tauri::{CustomMenuItem, Manager, Menu, MenuItem, Submenu, Window, WindowEvent};
+use tracing::{error, info, warn};
 
 mod parser;
 mod export;
 mod file_service;
 mod commands;
+mod settings;
+mod shortcuts;
+mod clipboard;
+mod import;
+mod notion;
+mod docx;
+mod clipper;
+mod spellcheck;
+mod grammar;
+mod assistant;
+mod palette;
+mod plugins;
+mod filters;
+mod workspace;
+mod sessions;
+mod secrets;
+mod gist;
+mod frontmatter;
+mod publish;
+mod static_site;
+mod confluence;
+mod email;
+mod sync;
+mod cloud_sync;
+mod preview_server;
+mod preview_diff;
+mod parse_scheduler;
+mod documents;
+mod profiling;
+mod pdf_renderer;
+mod api_server;
+mod zotero;
+mod journal;
+mod feed;
+mod site_builder;
+mod worker_pool;
+mod logging;
+mod shutdown;
+mod metrics;
+mod csp;
+mod tables;
+mod image_size;
+mod encryption;
+mod pdf_edit;
+mod pdf_signing;
+mod links;
+mod stylesheets;
+mod text_diff;
+mod compare;
+mod conflicts;
+mod versions;
+mod goals;
+mod scratchpad;
+mod merge;
+mod book;
+mod citation_styles;
+mod bidi;
+mod emoji;
+mod accessibility;
+mod qr;
+mod lists;
+mod diagnostics;
+mod holders;
+mod export_queue;
+mod problems;
+mod docx_export;
+mod epub_export;
+mod latex_export;
+mod presets;
+mod pdf_metadata;
+mod pdf_outline;
+mod export_jobs;
+mod code_highlight;
+mod math;
+mod mermaid;
 
 use commands::*;
 use crate::commands::AppState;
 
-/// Initialize logging for the application
-fn init_logging() {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("typolite=debug".parse().unwrap()))
-        .init();
-}
-
 /// Create application menu
 fn create_menu() -> Menu {
     let quit = CustomMenuItem::new("quit".to_string(), "Quit");
@@ -36,20 +103,96 @@ fn create_menu() -> Menu {
         .add_submenu(edit_menu)
 }
 
+/// Ask the user whether to discard unsaved changes, closing the window if they agree
+fn confirm_close_with_unsaved_changes(window: Window) {
+    let parent = window.clone();
+    tauri::api::dialog::ask(
+        Some(&parent),
+        "Unsaved Changes",
+        "This document has unsaved changes. Close without saving?",
+        move |discard| {
+            if discard {
+                info!("Discarding unsaved changes for window {}", window.label());
+                tauri::async_runtime::block_on(window.state::<AppState>().set_dirty(window.label(), false));
+                if let Err(e) = window.close() {
+                    error!("Failed to close window {}: {}", window.label(), e);
+                }
+            }
+        },
+    );
+}
+
+/// Quit through the shutdown pipeline, asking first if any window has unsaved changes
+fn request_quit(window: Window) {
+    let app = window.app_handle();
+    let unsaved = tauri::async_runtime::block_on(async {
+        !window.state::<AppState>().dirty_windows.read().await.is_empty()
+    });
+    if !unsaved {
+        tauri::async_runtime::spawn(graceful_shutdown(app));
+        return;
+    }
+
+    tauri::api::dialog::ask(
+        Some(&window),
+        "Unsaved Changes",
+        "Some documents have unsaved changes. Quit without saving?",
+        move |discard| {
+            if discard {
+                info!("Quitting with unsaved changes");
+                tauri::async_runtime::spawn(graceful_shutdown(app));
+            }
+        },
+    );
+}
+
 fn main() {
-    init_logging();
+    // The app binary doubles as the out-of-process PDF renderer
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(code) = pdf_renderer::render_pdf_from_args(&args) {
+        std::process::exit(code);
+    }
+
+    // Dropping the guard flushes the log file, so it lives until the app exits
+    let command_metrics = metrics::CommandMetrics::new();
+    let (log_controller, _log_guard) = logging::init_logging(&logging::default_log_dir(), &command_metrics);
     info!("Starting Typora-Lite v{}", env!("CARGO_PKG_VERSION"));
 
-    let app_state = AppState::default();
+    let mut app_state = AppState::default();
+    app_state.logging = log_controller;
+    app_state.command_metrics = command_metrics;
+    match pdf_renderer::PdfRendererProcess::current_exe() {
+        Ok(renderer) => app_state.export_service.set_pdf_renderer(Some(renderer)),
+        Err(e) => warn!("Rendering PDFs in-process: {}", e),
+    }
+    apply_file_access_settings(&app_state);
+    tauri::async_runtime::block_on(move_ai_key_to_keychain(&app_state));
+    match app_state.secrets.get(encryption::DOCUMENT_PASSWORD_KEY) {
+        Ok(password) => app_state.file_service.set_encryption_password(password),
+        Err(e) => warn!("Encrypted documents can't be opened until a password is set: {}", e),
+    }
+    app_state.parser.set_limits(parser_limits(&app_state.settings.get().parser));
+    app_state.parser.set_footnotes(app_state.settings.get().footnotes);
+    app_state.command_registry.set_export_presets(&app_state.export_presets.list());
+    app_state.command_registry.set_templates(&app_state.settings.get().journal);
+    match pdf_signer(&app_state.settings.get().pdf_signing) {
+        Ok(signer) => app_state.export_service.set_pdf_signer(signer),
+        Err(e) => warn!("Signed PDF exports are unavailable: {:#}", e),
+    }
+    app_state.export_service.set_content_security(app_state.settings.get().security.content_security_policy);
+    app_state.export_service.set_browser_path(app_state.settings.get().export.browser_path);
+    app_state.export_service.set_mermaid_cli(app_state.settings.get().export.mermaid_cli);
+    let parallelism = app_state.settings.get().workers.parallelism;
+    if let Err(e) = app_state.workers.set_parallelism(parallelism) {
+        warn!("Keeping the default worker pool size: {}", e);
+    }
 
     tauri::Builder::default()
         .manage(app_state)
         .menu(create_menu())
         .on_menu_event(|event| {
             match event.menu_item_id() {
-                "quit" => {
-                    std::process::exit(0);
-                }
+                "quit" => request_quit(event.window().clone()),
                 "close" => {
                     event.window().close().unwrap();
                 }
@@ -58,26 +201,187 @@ fn main() {
         })
         .on_window_event(|event| match event.event() {
             WindowEvent::CloseRequested { api, .. } => {
-                info!("Window close requested");
-                api.prevent_close();
+                let window = event.window().clone();
+                info!("Window close requested: {}", window.label());
+
+                // Window events arrive on the main thread, outside the async runtime
+                if tauri::async_runtime::block_on(window.state::<AppState>().is_dirty(window.label())) {
+                    api.prevent_close();
+                    confirm_close_with_unsaved_changes(window);
+                }
+            }
+            WindowEvent::Destroyed => {
+                let window = event.window();
+                tauri::async_runtime::block_on(window.state::<AppState>().set_dirty(window.label(), false));
+                window.state::<AppState>().preview_differ.reset(window.label());
+                window.state::<AppState>().parse_scheduler.cancel(window.label());
             }
             _ => {}
         })
         .invoke_handler(tauri::generate_handler![
             open_file_dialog,
             read_markdown_file,
+            read_file_lines,
             parse_markdown,
+            schedule_parse,
+            get_document_stats,
+            get_outline,
+            get_folding_ranges,
+            get_scroll_map,
+            get_heading_anchor,
+            get_task_progress,
+            format_table,
+            continue_list,
+            export_table_csv,
+            check_text,
+            check_document,
+            suggest,
+            add_to_dictionary,
+            remove_from_dictionary,
+            list_user_dictionary,
+            check_grammar,
+            set_grammar_settings,
+            ai_assist,
+            set_ai_settings,
+            set_html_filters,
+            publish_gist,
+            set_github_token,
+            list_publish_targets,
+            publish_document,
+            set_publish_credential,
+            set_publish_settings,
+            copy_as_html,
+            copy_as_plain,
+            convert_html_to_markdown,
+            import_notion_export,
+            import_docx,
+            import_html,
+            fetch_link_title,
             export_to_pdf,
+            export_merged_documents,
+            load_book_project,
+            reorder_book_chapters,
+            export_book,
+            compare_documents,
+            audit_accessibility,
+            get_document_diff,
+            create_snapshot,
+            list_snapshots,
+            get_document_export_options,
+            set_document_export_options,
+            export_to_static_site,
+            set_static_site_settings,
+            export_to_confluence,
+            set_confluence_settings,
+            email_document,
+            set_email_settings,
+            generate_print_preview,
+            start_preview_server,
+            stop_preview_server,
+            set_api_settings,
+            set_export_settings,
+            set_export_on_save_rules,
+            list_export_presets,
+            save_export_preset,
+            delete_export_preset,
+            list_export_themes,
+            list_code_themes,
+            get_theme_css,
+            cancel_export,
+            set_pdf_signing_settings,
+            set_goal_settings,
+            set_worker_settings,
+            set_file_access_settings,
+            set_security_settings,
+            set_parser_settings,
+            set_footnote_settings,
+            set_log_level,
+            get_command_metrics,
+            get_api_token,
+            regenerate_api_token,
+            search_zotero,
+            pick_zotero_citation,
+            list_citation_styles,
+            get_bibliography,
+            set_zotero_settings,
+            open_daily_note,
+            open_date_note,
+            set_journal_settings,
+            append_quick_note,
+            set_scratchpad_settings,
+            generate_feed,
+            set_feed_settings,
+            generate_site,
             get_app_config_dir,
             save_file,
+            set_document_password,
+            encrypt_document,
+            decrypt_document,
+            open_document,
+            apply_edit,
+            close_document,
+            replace_in_document,
+            toggle_task,
+            resolve_conflict,
+            save_document,
+            get_session_stats,
+            get_performance_profile,
+            mark_dirty,
+            mark_clean,
+            get_shortcuts,
+            set_shortcuts,
+            list_commands,
+            run_command,
+            list_plugins,
+            reload_plugins,
+            export_with_plugin,
             watch_file,
             unwatch_file,
+            watch_directory,
+            remove_watch,
             get_file_metadata,
+            reveal_in_explorer,
+            open_with_default_app,
             list_recent_files,
+            open_workspace,
+            list_workspaces,
+            switch_workspace,
+            set_workspace_settings,
+            sync_now,
+            set_sync_settings,
+            connect_sync_provider,
+            disconnect_sync_provider,
             get_app_version,
             get_system_info
         ])
-        .setup(|_app| {
+        .setup(|app| {
+            let overrides = app.state::<AppState>().settings.get().shortcuts;
+            if let Err(e) = register_global_shortcuts(&app.handle(), &shortcuts::effective_shortcuts(&overrides)) {
+                error!("Failed to register global shortcuts: {}", e);
+            }
+
+            if let Err(e) = app.state::<AppState>().plugins.load_plugins() {
+                error!("Failed to load plugins: {}", e);
+            }
+
+            tauri::async_runtime::spawn(run_background_sync(app.handle()));
+            tauri::async_runtime::spawn(run_holder_watch(app.handle()));
+            tauri::async_runtime::spawn(run_export_queue(app.handle()));
+
+            let handle = app.handle();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = apply_api_settings(&handle).await {
+                    error!("Failed to start automation API: {}", e);
+                }
+            });
+
+            let handle = app.handle();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = prepare_export_temp_dir(&handle.state::<AppState>()).await {
+                    warn!("Failed to clean up export scratch files: {}", e);
+                }
+            });
+
             info!("Typora-Lite setup complete");
             Ok(())
         })
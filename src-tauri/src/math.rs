@@ -0,0 +1,192 @@
+use tracing::warn;
+
+/// A `$...$` or `$$...$$` formula in text
+#[derive(Debug, Clone, PartialEq)]
+pub struct MathSpan<'a> {
+    /// The TeX between the dollar signs
+    pub tex: &'a str,
+    pub display: bool,
+    /// Offset just past the closing dollar sign
+    pub end: usize,
+}
+
+/// The formula starting at the `$` at `at` in `text`, if it opens one
+///
+/// Formulas don't run past the end of a paragraph. Inline math must not start or
+/// end with whitespace or be followed by a digit, so prices like "$5 and $10"
+/// stay text.
+pub fn math_span(text: &str, at: usize) -> Option<MathSpan<'_>> {
+    let rest = &text[at..];
+    let paragraph_end = rest.find("\n\n").unwrap_or(rest.len());
+    if let Some(display) = rest.strip_prefix("$$") {
+        let close = display[..paragraph_end - 2].find("$$")?;
+        let tex = display[..close].trim();
+        return (!tex.is_empty()).then_some(MathSpan { tex, display: true, end: at + 2 + close + 2 });
+    }
+
+    let inline = &rest[1..paragraph_end];
+    if inline.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut escaped = false;
+    for (offset, c) in inline.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '$' if !escaped => {
+                let tex = &inline[..offset];
+                let after = inline[offset + 1..].chars().next();
+                if tex.is_empty() || tex.ends_with(char::is_whitespace) || after.is_some_and(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                return Some(MathSpan { tex, display: false, end: at + 1 + offset + 1 });
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// `html` with its formulas and ```` ```math ```` blocks typeset as MathML by
+/// KaTeX, for documents read without the preview's scripts
+///
+/// Text inside code, preformatted blocks and existing MathML is left alone, as
+/// are formulas KaTeX fails on.
+pub fn render_math_html(html: &str) -> String {
+    let mut rendered = String::with_capacity(html.len());
+    let mut rest = html;
+    // Element whose text isn't math, until its end tag
+    let mut verbatim: Option<String> = None;
+    while let Some(lt) = rest.find('<') {
+        match verbatim {
+            Some(_) => rendered.push_str(&rest[..lt]),
+            None => rendered.push_str(&render_text(&rest[..lt])),
+        }
+        rest = &rest[lt..];
+
+        if verbatim.is_none() {
+            if let Some((tex, len)) = math_block(rest) {
+                match render_tex(&tex, true) {
+                    Some(math) => rendered.push_str(&math),
+                    None => rendered.push_str(&rest[..len]),
+                }
+                rest = &rest[len..];
+                continue;
+            }
+        }
+
+        let tag_len = rest.find('>').map_or(rest.len(), |gt| gt + 1);
+        let tag = &rest[..tag_len];
+        let name: String = tag.trim_start_matches(['<', '/']).chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match &verbatim {
+            Some(open) if tag.starts_with("</") && *open == name => verbatim = None,
+            None if !tag.starts_with("</") && !tag.ends_with("/>")
+                && ["code", "pre", "kbd", "math", "script", "style", "textarea"].contains(&name.as_str()) => {
+                verbatim = Some(name);
+            }
+            _ => {}
+        }
+        rendered.push_str(tag);
+        rest = &rest[tag_len..];
+    }
+    match verbatim {
+        Some(_) => rendered.push_str(rest),
+        None => rendered.push_str(&render_text(rest)),
+    }
+    rendered
+}
+
+/// HTML text with its formulas typeset
+fn render_text(text: &str) -> String {
+    if !text.contains('$') {
+        return text.to_string();
+    }
+    let mut rendered = String::with_capacity(text.len());
+    let mut at = 0;
+    while let Some(offset) = text[at..].find('$') {
+        let start = at + offset;
+        let math = math_span(text, start)
+            .and_then(|span| Some((render_tex(&html_escape::decode_html_entities(span.tex), span.display)?, span.end)));
+        match math {
+            Some((math, end)) => {
+                rendered.push_str(&text[at..start]);
+                rendered.push_str(&math);
+                at = end;
+            }
+            None => {
+                // Past both signs of an unrendered `$$`, so its second one doesn't open a formula
+                let skip = if text[start..].starts_with("$$") { 2 } else { 1 };
+                rendered.push_str(&text[at..start + skip]);
+                at = start + skip;
+            }
+        }
+    }
+    rendered.push_str(&text[at..]);
+    rendered
+}
+
+/// The TeX of the ```` ```math ```` block `html` starts with, and the block's length
+fn math_block(html: &str) -> Option<(String, usize)> {
+    const OPENINGS: [&str; 2] = [
+        "<pre class=\"language-math\"><code class=\"language-math\">",
+        "<pre><code class=\"language-math\">",
+    ];
+    let body = OPENINGS.iter().find_map(|opening| html.strip_prefix(opening))?;
+    let close = body.find("</code></pre>")?;
+    let len = html.len() - body.len() + close + "</code></pre>".len();
+    Some((html_escape::decode_html_entities(&body[..close]).trim().to_string(), len))
+}
+
+/// `tex` as KaTeX's MathML, with invalid TeX shown in red
+fn render_tex(tex: &str, display: bool) -> Option<String> {
+    let opts = katex::Opts::builder()
+        .display_mode(display)
+        .output_type(katex::OutputType::Mathml)
+        .throw_on_error(false)
+        .build()
+        .ok()?;
+    match katex::render_with_opts(tex, &opts) {
+        // Wrapped as KaTeX wraps its HTML output, which the DOCX writer and styles expect
+        Ok(math) if display => Some(format!("<span class=\"katex-display\">{}</span>", math)),
+        Ok(math) => Some(math),
+        Err(e) => {
+            warn!("Failed to render math {:?}: {}", tex, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_math_span() {
+        let text = "Costs $5 and $10, but $x^2$ and $$\n\\frac{a}{b}\n$$ are math";
+        assert_eq!(math_span(text, 6), None);
+        assert_eq!(math_span(text, 13), None);
+        let inline = math_span(text, 22).unwrap();
+        assert_eq!((inline.tex, inline.display, &text[inline.end..inline.end + 4]), ("x^2", false, " and"));
+        let display = math_span(text, 32).unwrap();
+        assert_eq!((display.tex, display.display, &text[display.end..]), ("\\frac{a}{b}", true, " are math"));
+        assert_eq!(math_span("$a\n\nb$", 0), None);
+    }
+
+    #[test]
+    fn test_render_math_html() {
+        let html = "<p>Area <span class=\"katex-inline\" data-math=\"r\">$\\pi r^2$</span> when $a &lt; b$</p>\
+            <p><code>$HOME$</code> costs $5</p>\
+            <pre class=\"language-math\"><code class=\"language-math\">E = mc^2\n</code></pre>";
+        let rendered = render_math_html(html);
+
+        assert!(rendered.contains("<span class=\"katex-inline\" data-math=\"r\"><span class=\"katex\"><math"));
+        assert!(rendered.contains("<annotation encoding=\"application/x-tex\">\\pi r^2</annotation>"));
+        assert!(rendered.contains("<annotation encoding=\"application/x-tex\">a &lt; b</annotation>"));
+        assert!(rendered.contains("<p><code>$HOME$</code> costs $5</p>"));
+        assert!(rendered.contains("<span class=\"katex-display\">"));
+        assert!(rendered.contains("<annotation encoding=\"application/x-tex\">E = mc^2</annotation>"));
+        assert!(!rendered.contains("language-math"));
+    }
+}
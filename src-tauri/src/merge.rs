@@ -0,0 +1,245 @@
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::frontmatter::FrontMatter;
+
+/// Placed between chapters when each should start on a new page
+const PAGE_BREAK: &str = "<div style=\"break-before: page; page-break-before: always;\"></div>\n\n";
+
+/// How files are joined into one document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MergeOptions {
+    /// Open each file with a `#` chapter heading, titled from its front matter,
+    /// its own leading `#` heading, or its file name; the file's other headings
+    /// move down a level to sit under it
+    pub chapter_headings: bool,
+    /// Number chapter headings in order across all files
+    pub number_chapters: bool,
+    /// Start each chapter on a new page
+    pub page_breaks: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            chapter_headings: true,
+            number_chapters: true,
+            page_breaks: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergedChapter {
+    pub path: PathBuf,
+    pub title: String,
+    /// 1-based position in the merged document
+    pub number: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedDocument {
+    pub markdown: String,
+    pub chapters: Vec<MergedChapter>,
+}
+
+/// Join `files` (path and markdown, in reading order) into one document
+///
+/// Front matter is dropped, and footnote labels are prefixed per chapter so
+/// `[^1]` in two files stays two footnotes. Heading anchors are made unique by
+/// the parser, so the merged table of contents links to the right chapter.
+pub fn merge_documents(files: &[(PathBuf, String)], options: &MergeOptions) -> MergedDocument {
+    let mut markdown = String::new();
+    let mut chapters = Vec::with_capacity(files.len());
+
+    for (index, (path, source)) in files.iter().enumerate() {
+        let number = index + 1;
+        let (front_matter, body) = FrontMatter::parse(source).unwrap_or_else(|_| (FrontMatter::default(), source.as_str()));
+        let (body, leading_title) = rewrite_chapter(body, number, options.chapter_headings);
+        let title = front_matter.title()
+            .or(leading_title)
+            .unwrap_or_else(|| file_title(path));
+
+        if options.page_breaks && index > 0 {
+            markdown.push_str(PAGE_BREAK);
+        }
+        if options.chapter_headings {
+            if options.number_chapters {
+                markdown.push_str(&format!("# {}. {}\n\n", number, title));
+            } else {
+                markdown.push_str(&format!("# {}\n\n", title));
+            }
+        }
+        markdown.push_str(body.trim_matches('\n'));
+        markdown.push_str("\n\n");
+
+        chapters.push(MergedChapter { path: path.clone(), title, number });
+    }
+
+    MergedDocument { markdown, chapters }
+}
+
+/// Sort files for merging by name, with numbers compared by value so
+/// `2-setup.md` comes before `10-appendix.md`
+pub fn sort_chapters(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| {
+        let name = |path: &PathBuf| path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+        natural_cmp(&name(a), &name(b))
+    });
+}
+
+/// Prefix footnote labels with the chapter number and, with chapter headings,
+/// take out a leading `#` heading (returning its text) and demote the rest
+fn rewrite_chapter(body: &str, chapter: usize, chapter_headings: bool) -> (String, Option<String>) {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let mut leading_title = None;
+    let mut heading: Option<(HeadingLevel, Range<usize>, String)> = None;
+    let mut first_block = true;
+
+    for (event, range) in Parser::new_ext(body, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => heading = Some((level, range, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, title)) = &mut heading {
+                    title.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                let Some((level, range, title)) = heading.take() else { continue };
+                if !chapter_headings {
+                    continue;
+                }
+                if first_block && level == HeadingLevel::H1 {
+                    leading_title = Some(title.trim().to_string()).filter(|title| !title.is_empty());
+                    edits.push((range, String::new()));
+                } else if let Some(edit) = demote_heading(body, range, level, &title) {
+                    edits.push(edit);
+                }
+            }
+            Event::FootnoteReference(label) => {
+                edits.push((range, format!("[^c{}-{}]", chapter, label)));
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let marker = format!("[^{}]", label);
+                if body[range.start..].starts_with(&marker) {
+                    edits.push((range.start..range.start + marker.len(), format!("[^c{}-{}]", chapter, label)));
+                }
+            }
+            _ => {}
+        }
+        if heading.is_none() {
+            first_block = false;
+        }
+    }
+
+    // Edits inside a replaced heading are dropped along with it
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut rewritten = String::with_capacity(body.len());
+    let mut offset = 0;
+    for (range, replacement) in edits {
+        if range.start < offset {
+            continue;
+        }
+        rewritten.push_str(&body[offset..range.start]);
+        rewritten.push_str(&replacement);
+        offset = range.end;
+    }
+    rewritten.push_str(&body[offset..]);
+    (rewritten, leading_title)
+}
+
+/// The edit moving a heading one level down: one more `#` for ATX headings, and
+/// setext headings (which only have two levels) rewritten as ATX
+fn demote_heading(body: &str, range: Range<usize>, level: HeadingLevel, title: &str) -> Option<(Range<usize>, String)> {
+    let level = level as usize;
+    if level >= 6 {
+        return None;
+    }
+    let source = &body[range.clone()];
+    let trimmed = source.trim_start();
+    if trimmed.starts_with('#') {
+        let at = range.start + source.len() - trimmed.len();
+        return Some((at..at, "#".to_string()));
+    }
+    let ending = if source.ends_with('\n') { "\n" } else { "" };
+    Some((range, format!("{} {}{}", "#".repeat(level + 1), title.trim(), ending)))
+}
+
+/// `02-getting-started.md` -> `getting started`, minus any leading number
+fn file_title(path: &Path) -> String {
+    let stem = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let stem = stem.split('.').next().unwrap_or_default();
+    let without_number = stem.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches(['-', '_', ' ', '.']);
+    let title = if without_number.is_empty() { stem } else { without_number };
+    title.replace(['-', '_'], " ")
+}
+
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+                let (a_len, b_len) = (digits(a), digits(b));
+                let (a_num, b_num) = (a[..a_len].trim_start_matches('0'), b[..b_len].trim_start_matches('0'));
+                let order = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_documents_numbers_chapters() {
+        let files = vec![
+            (PathBuf::from("01-intro.md"), "---\ntitle: Welcome\n---\nHello.[^1]\n\n## Scope\n\n[^1]: First note.\n".to_string()),
+            (PathBuf::from("02-setup.md"), "# Setting up\n\nInstall it.[^1]\n\nOptions\n-------\n\n[^1]: Second note.\n".to_string()),
+            (PathBuf::from("03-faq.md"), "Questions.\n\n# Why?\n".to_string()),
+        ];
+        let merged = merge_documents(&files, &MergeOptions::default());
+
+        let titles: Vec<_> = merged.chapters.iter().map(|chapter| chapter.title.as_str()).collect();
+        assert_eq!(titles, vec!["Welcome", "Setting up", "faq"]);
+        assert_eq!(merged.markdown, format!(
+            "# 1. Welcome\n\nHello.[^c1-1]\n\n### Scope\n\n[^c1-1]: First note.\n\n{}# 2. Setting up\n\nInstall it.[^c2-1]\n\n### Options\n\n[^c2-1]: Second note.\n\n{}# 3. faq\n\nQuestions.\n\n## Why?\n\n",
+            PAGE_BREAK, PAGE_BREAK,
+        ));
+    }
+
+    #[test]
+    fn test_plain_merge_and_chapter_order() {
+        let files = vec![(PathBuf::from("a.md"), "# A\n\ntext\n".to_string()), (PathBuf::from("b.md"), "# B\n".to_string())];
+        let options = MergeOptions { chapter_headings: false, number_chapters: false, page_breaks: false };
+        assert_eq!(merge_documents(&files, &options).markdown, "# A\n\ntext\n\n# B\n\n");
+
+        let mut paths: Vec<PathBuf> = ["10-appendix.md", "2-setup.md", "1-intro.md", "Notes.md"].iter().map(PathBuf::from).collect();
+        sort_chapters(&mut paths);
+        assert_eq!(paths, ["1-intro.md", "2-setup.md", "10-appendix.md", "Notes.md"].iter().map(PathBuf::from).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{Result, Context};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::code_highlight::code_block;
+
+/// Executable names of mermaid-cli, searched for on `PATH`
+#[cfg(windows)]
+const MERMAID_CLI_NAMES: &[&str] = &["mmdc.cmd", "mmdc.exe"];
+#[cfg(not(windows))]
+const MERMAID_CLI_NAMES: &[&str] = &["mmdc"];
+
+/// Longest mermaid-cli error output kept for the log
+const MAX_STDERR_CHARS: usize = 2000;
+
+/// The mermaid-cli executable diagrams are drawn with: `configured` when set,
+/// otherwise `mmdc` from `PATH`; `None` when it isn't installed
+pub fn find_mermaid_cli(configured: Option<&Path>) -> Result<Option<PathBuf>> {
+    if let Some(path) = configured {
+        if path.is_file() {
+            return Ok(Some(path.to_path_buf()));
+        }
+        anyhow::bail!("The mermaid-cli set for exports doesn't exist: {:?}", path);
+    }
+
+    let dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    Ok(MERMAID_CLI_NAMES.iter()
+        .flat_map(|name| dirs.iter().map(move |dir| dir.join(name)))
+        .find(|path| path.is_file()))
+}
+
+/// Whether `html` has any ```` ```mermaid ```` blocks
+pub fn has_mermaid_blocks(html: &str) -> bool {
+    html.contains("<code class=\"language-mermaid")
+}
+
+/// `html` with each ```` ```mermaid ```` block replaced by the SVG diagram `cli`
+/// draws from it, using `work_dir` for its files
+///
+/// mermaid-cli drives a headless browser; `browser` is handed to it when set so it
+/// doesn't need one of its own. Blocks it fails to draw stay code, so one broken
+/// diagram doesn't fail the export.
+pub async fn render_mermaid_blocks(html: &str, cli: &Path, browser: Option<&Path>, work_dir: &Path) -> Result<String> {
+    let puppeteer_config = match browser {
+        Some(browser) => {
+            let path = work_dir.join("puppeteer.json");
+            let config = serde_json::json!({ "executablePath": browser });
+            tokio::fs::write(&path, config.to_string()).await
+                .with_context(|| format!("Failed to write mermaid-cli browser config: {:?}", path))?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    let mut rendered = String::with_capacity(html.len());
+    let mut rest = html;
+    let mut diagrams = 0;
+    while let Some(at) = rest.find("<pre") {
+        rendered.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let block = match code_block(rest) {
+            Some(block) if block.language.split_whitespace().next() == Some("mermaid") => block,
+            _ => {
+                rendered.push_str("<pre");
+                rest = &rest["<pre".len()..];
+                continue;
+            }
+        };
+
+        diagrams += 1;
+        let id = format!("mermaid-{}", diagrams);
+        let source = work_dir.join(format!("{}.mmd", id));
+        tokio::fs::write(&source, html_escape::decode_html_entities(block.code).as_bytes()).await
+            .with_context(|| format!("Failed to write mermaid diagram: {:?}", source))?;
+        match draw(cli, &source, &id, puppeteer_config.as_deref()).await {
+            Ok(svg) => rendered.push_str(&format!("<figure class=\"mermaid-diagram\">{}</figure>", svg)),
+            Err(e) => {
+                warn!("Exporting mermaid diagram {} as code: {:#}", diagrams, e);
+                rendered.push_str(&rest[..block.len]);
+            }
+        }
+        rest = &rest[block.len..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// The SVG mermaid-cli draws from `source`, with `id` as the id its styles are
+/// scoped to, so diagrams on the same page don't restyle each other
+async fn draw(cli: &Path, source: &Path, id: &str, puppeteer_config: Option<&Path>) -> Result<String> {
+    let output = source.with_extension("svg");
+    let mut command = Command::new(cli);
+    command
+        .arg("--input").arg(source)
+        .arg("--output").arg(&output)
+        .arg("--backgroundColor").arg("transparent")
+        .arg("--svgId").arg(id)
+        .arg("--quiet")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(config) = puppeteer_config {
+        command.arg("--puppeteerConfigFile").arg(config);
+    }
+    debug!("Drawing mermaid diagram {:?}", source);
+
+    let result = command.output().await
+        .with_context(|| format!("Failed to start mermaid-cli {:?}", cli))?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        anyhow::bail!("mermaid-cli exited with {}: {}", result.status, stderr.trim().chars().take(MAX_STDERR_CHARS).collect::<String>());
+    }
+    let svg = tokio::fs::read_to_string(&output).await
+        .with_context(|| format!("mermaid-cli wrote no diagram to {:?}", output))?;
+    // Inlined into HTML, so without an XML declaration before the root element
+    let start = svg.find("<svg").context("mermaid-cli wrote a file that isn't SVG")?;
+    Ok(svg[start..].trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_mermaid_cli() {
+        let temp_dir = TempDir::new().unwrap();
+        let cli = temp_dir.path().join("mmdc");
+        std::fs::write(&cli, "").unwrap();
+        assert_eq!(find_mermaid_cli(Some(&cli)).unwrap(), Some(cli));
+        assert!(find_mermaid_cli(Some(&temp_dir.path().join("missing"))).is_err());
+        assert!(has_mermaid_blocks("<pre><code class=\"language-mermaid\">graph TD</code></pre>"));
+        assert!(!has_mermaid_blocks("<p>mermaid</p>"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_render_mermaid_blocks() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp_dir = TempDir::new().unwrap();
+        // Draws the diagram's first line as the SVG, failing on "broken"
+        let cli = temp_dir.path().join("mmdc");
+        std::fs::write(&cli, concat!(
+            "#!/bin/sh\n",
+            "while [ $# -gt 0 ]; do case $1 in --input) in=$2;; --output) out=$2;; --svgId) id=$2;; esac; shift; done\n",
+            "grep -q broken \"$in\" && { echo 'Parse error on line 1' >&2; exit 1; }\n",
+            "printf '<?xml version=\"1.0\"?>\\n<svg id=\"%s\">%s</svg>\\n' \"$id\" \"$(head -n 1 \"$in\")\" > \"$out\"\n",
+        )).unwrap();
+        std::fs::set_permissions(&cli, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let html = "<p>Flow</p><pre class=\"language-mermaid\"><code class=\"language-mermaid\">graph TD; A--&gt;B\n</code></pre>\
+            <pre><code class=\"language-mermaid\">broken\n</code></pre>\
+            <pre><code class=\"language-rust\">fn main() {}</code></pre>";
+        let rendered = render_mermaid_blocks(html, &cli, None, temp_dir.path()).await.unwrap();
+
+        assert!(rendered.starts_with("<p>Flow</p><figure class=\"mermaid-diagram\"><svg id=\"mermaid-1\">graph TD; A-->B</svg></figure>"), "{}", rendered);
+        assert!(rendered.contains("<pre><code class=\"language-mermaid\">broken\n</code></pre>"));
+        assert!(rendered.ends_with("<pre><code class=\"language-rust\">fn main() {}</code></pre>"));
+    }
+}
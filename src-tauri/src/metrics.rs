@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Target of the spans that time command handlers; every `#[command]` carries
+/// `#[instrument(target = "command", level = "trace", skip_all)]`
+pub const COMMAND_TARGET: &str = "command";
+
+/// Latencies kept per command for the percentiles
+const MAX_SAMPLES: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandMetric {
+    pub command: String,
+    /// Calls since the app started
+    pub count: u64,
+    /// Over the most recent calls
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Default)]
+struct CommandSamples {
+    count: u64,
+    recent: VecDeque<f64>,
+}
+
+/// How long each command handler takes, from invocation until its result is ready
+///
+/// Clones share the same figures.
+#[derive(Clone, Default)]
+pub struct CommandMetrics {
+    commands: Arc<Mutex<HashMap<String, CommandSamples>>>,
+}
+
+impl CommandMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, command: &str, elapsed: Duration) {
+        let mut commands = self.commands.lock().unwrap();
+        let samples = commands.entry(command.to_string()).or_default();
+        samples.count += 1;
+        if samples.recent.len() == MAX_SAMPLES {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back(elapsed.as_secs_f64() * 1000.0);
+    }
+
+    /// Latency figures for every command called so far, by command name
+    pub fn snapshot(&self) -> Vec<CommandMetric> {
+        let commands = self.commands.lock().unwrap();
+        let mut metrics: Vec<CommandMetric> = commands.iter()
+            .map(|(command, samples)| {
+                let mut sorted: Vec<f64> = samples.recent.iter().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                CommandMetric {
+                    command: command.clone(),
+                    count: samples.count,
+                    p50_ms: percentile(&sorted, 50.0),
+                    p95_ms: percentile(&sorted, 95.0),
+                    max_ms: sorted.last().copied().unwrap_or_default(),
+                }
+            })
+            .collect();
+        metrics.sort_by(|a, b| a.command.cmp(&b.command));
+        metrics
+    }
+
+    /// The tracing layer that feeds these metrics from command spans
+    pub fn layer(&self) -> CommandMetricsLayer {
+        CommandMetricsLayer { metrics: self.clone() }
+    }
+}
+
+/// Nearest-rank percentile of already sorted samples
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// When a command span was opened
+struct SpanStarted(Instant);
+
+/// Times spans targeted at [`COMMAND_TARGET`] from creation to close, so async
+/// commands are measured until they finish rather than until their first poll
+pub struct CommandMetricsLayer {
+    metrics: CommandMetrics,
+}
+
+impl<S> Layer<S> for CommandMetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().target() != COMMAND_TARGET {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStarted(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let started = span.extensions().get::<SpanStarted>().map(|SpanStarted(started)| *started);
+        if let Some(started) = started {
+            self.metrics.record(span.name(), started.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::Instrument;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_percentiles() {
+        let metrics = CommandMetrics::new();
+        for ms in 1..=100 {
+            metrics.record("parse_markdown", Duration::from_millis(ms));
+        }
+        metrics.record("get_app_version", Duration::from_millis(2));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].command, "get_app_version");
+        let parse = &snapshot[1];
+        assert_eq!(parse.count, 100);
+        assert!((parse.p50_ms - 50.0).abs() < 0.01);
+        assert!((parse.p95_ms - 95.0).abs() < 0.01);
+        assert!((parse.max_ms - 100.0).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn test_layer_times_command_spans() {
+        let metrics = CommandMetrics::new();
+        let subscriber = tracing_subscriber::registry().with(metrics.layer());
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let span = tracing::trace_span!(target: COMMAND_TARGET, "export_document");
+        async { tokio::time::sleep(Duration::from_millis(20)).await }.instrument(span).await;
+        let other = tracing::info_span!("not_a_command");
+        other.in_scope(|| {});
+        drop(other);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!((snapshot[0].command.as_str(), snapshot[0].count), ("export_document", 1));
+        assert!(snapshot[0].p50_ms >= 20.0);
+    }
+}
@@ -0,0 +1,440 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::static_site::rewrite_destinations;
+
+/// Most bytes a ZIP export may expand to, nested part archives included, since
+/// every file is held in memory while links are rewritten
+const MAX_EXPORT_BYTES: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotionImportResult {
+    pub output_dir: PathBuf,
+    pub documents: Vec<PathBuf>,
+    /// Databases converted from CSV to markdown tables
+    pub tables: usize,
+    pub assets: usize,
+}
+
+/// Imports a Notion "Markdown & CSV" export into a clean folder of markdown files
+///
+/// Notion appends a 32-digit hex id to every file and folder name, URL-encodes
+/// links between pages and exports databases as CSV. The importer strips the
+/// ids, rewrites links and image paths to the new names and turns each
+/// database into a markdown table linking to its row pages.
+#[derive(Default)]
+pub struct NotionImporter;
+
+impl NotionImporter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Import from an export ZIP or an already extracted export folder
+    pub async fn import(&self, source: &Path, output_dir: &Path) -> Result<NotionImportResult> {
+        let source = source.to_path_buf();
+        let output_dir = output_dir.to_path_buf();
+
+        tokio::task::spawn_blocking(move || import_blocking(&source, &output_dir))
+            .await
+            .context("Notion import task panicked")?
+    }
+}
+
+fn import_blocking(source: &Path, output_dir: &Path) -> Result<NotionImportResult> {
+    debug!("Importing Notion export from {:?}", source);
+
+    let entries = if source.is_dir() {
+        read_directory(source, source)?
+    } else {
+        let bytes = std::fs::read(source)
+            .with_context(|| format!("Failed to read Notion export: {:?}", source))?;
+        let mut budget = MAX_EXPORT_BYTES;
+        read_zip(bytes, &mut budget, true)?
+    };
+    if entries.is_empty() {
+        return Err(anyhow::anyhow!("No files found in Notion export: {:?}", source));
+    }
+
+    // Notion writes each database twice; `_all.csv` includes rows hidden by view filters
+    let originals: HashSet<&str> = entries.keys().map(String::as_str).collect();
+    let skipped: HashSet<String> = originals
+        .iter()
+        .filter_map(|path| path.strip_suffix("_all.csv"))
+        .map(|base| format!("{}.csv", base))
+        .filter(|path| originals.contains(path.as_str()))
+        .collect();
+
+    let mut renames: HashMap<String, String> = HashMap::new();
+    let mut taken = HashSet::new();
+    let mut paths: Vec<&String> = entries.keys().filter(|p| !skipped.contains(*p)).collect();
+    paths.sort();
+    for original in paths {
+        let mut clean = clean_path(original);
+        if let Some(stem) = clean.strip_suffix("_all.csv").or_else(|| clean.strip_suffix(".csv")) {
+            clean = format!("{}.md", stem);
+        }
+        // Stripping an id can leave `..` behind, which must not climb out of `output_dir`
+        if !is_relative_file_path(&clean) {
+            anyhow::bail!("Notion export has a file that would be written outside the import folder: {}", original);
+        }
+        let clean = unique_path(clean, &mut taken);
+        renames.insert(original.clone(), clean);
+    }
+
+    let mut result = NotionImportResult {
+        output_dir: output_dir.to_path_buf(),
+        documents: Vec::new(),
+        tables: 0,
+        assets: 0,
+    };
+
+    for (original, clean) in &renames {
+        let bytes = &entries[original];
+        let content = if original.ends_with(".md") {
+            let markdown = String::from_utf8_lossy(bytes);
+            result.documents.push(output_dir.join(clean));
+            rewrite_links(&markdown, original, clean, &renames).into_bytes()
+        } else if original.ends_with(".csv") {
+            let table = database_to_markdown(bytes, original, clean, &renames)
+                .with_context(|| format!("Failed to convert database {}", original))?;
+            result.tables += 1;
+            result.documents.push(output_dir.join(clean));
+            table.into_bytes()
+        } else {
+            result.assets += 1;
+            bytes.clone()
+        };
+
+        let target = output_dir.join(clean);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        std::fs::write(&target, content)
+            .with_context(|| format!("Failed to write {:?}", target))?;
+    }
+
+    result.documents.sort();
+    info!(
+        "Imported {} documents ({} databases, {} assets) from Notion into {:?}",
+        result.documents.len(), result.tables, result.assets, output_dir
+    );
+    Ok(result)
+}
+
+/// Read every file of a ZIP export, expanding nested part archives when
+/// `expand_parts` is set, with at most `budget` bytes left to expand
+fn read_zip(bytes: Vec<u8>, budget: &mut u64, expand_parts: bool) -> Result<HashMap<String, Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("Not a valid ZIP archive")?;
+    let mut entries = HashMap::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.enclosed_name()
+            .map(|path| path.to_string_lossy().replace('\\', "/"))
+            .filter(|name| is_relative_file_path(name))
+            .with_context(|| format!("Notion export has a file outside the export folder: {}", file.name()))?;
+
+        // The declared size can't be trusted, so read no more than what's left of the budget
+        let mut content = Vec::new();
+        (&mut file).take(*budget + 1).read_to_end(&mut content)?;
+        if content.len() as u64 > *budget {
+            anyhow::bail!("Notion export expands to more than {} MB", MAX_EXPORT_BYTES / (1024 * 1024));
+        }
+        *budget -= content.len() as u64;
+
+        // Large workspaces are exported as a ZIP of `Part-N.zip` archives
+        if expand_parts && name.ends_with(".zip") {
+            let len = content.len() as u64;
+            entries.extend(read_zip(content, budget, false)?);
+            // The part archive itself is no longer held
+            *budget += len;
+        } else {
+            entries.insert(name, content);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Whether `path` is `/`-separated names only, with no root, drive or `..`
+fn is_relative_file_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.split('/').any(|name| name.is_empty() || name == "." || name == "..")
+        && Path::new(path).components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn read_directory(root: &Path, dir: &Path) -> Result<HashMap<String, Vec<u8>>> {
+    let mut entries = HashMap::new();
+
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read directory: {:?}", dir))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            entries.extend(read_directory(root, &path)?);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let name = relative.to_string_lossy().replace('\\', "/");
+            entries.insert(name, std::fs::read(&path)?);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Strip Notion ids from every component of a `/`-separated path
+fn clean_path(path: &str) -> String {
+    path.split('/').map(strip_notion_id).collect::<Vec<_>>().join("/")
+}
+
+/// `Meeting Notes 0123456789abcdef0123456789abcdef.md` becomes `Meeting Notes.md`
+fn strip_notion_id(name: &str) -> String {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !extension.contains(' ') => (stem, Some(extension)),
+        _ => (name, None),
+    };
+
+    // Full database exports carry an extra `_all` after the id
+    let (stem, suffix) = match stem.strip_suffix("_all") {
+        Some(stem) => (stem, "_all"),
+        None => (stem, ""),
+    };
+    let stem = match stem.rsplit_once(' ') {
+        Some((base, id)) if !base.is_empty() && id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => base,
+        _ => stem,
+    };
+
+    match extension {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension),
+        None => format!("{}{}", stem, suffix),
+    }
+}
+
+fn unique_path(path: String, taken: &mut HashSet<String>) -> String {
+    let (stem, extension) = match path.rsplit_once('.') {
+        Some((stem, extension)) if !extension.contains('/') => (stem.to_string(), format!(".{}", extension)),
+        _ => (path.clone(), String::new()),
+    };
+
+    let mut candidate = path;
+    let mut counter = 2;
+    while !taken.insert(candidate.clone()) {
+        candidate = format!("{} {}{}", stem, counter, extension);
+        counter += 1;
+    }
+    candidate
+}
+
+/// Point links and images at the renamed files, relative to the renamed document
+fn rewrite_links(markdown: &str, original: &str, clean: &str, renames: &HashMap<String, String>) -> String {
+    rewrite_destinations(markdown, false, |dest| {
+        let target = resolve_link(original, dest)?;
+        let renamed = renames.get(&target).or_else(|| {
+            // Links to a database may point at either CSV variant
+            let base = target.strip_suffix("_all.csv").or_else(|| target.strip_suffix(".csv"))?;
+            renames.get(&format!("{}_all.csv", base)).or_else(|| renames.get(&format!("{}.csv", base)))
+        })?;
+        Some(encode_link(&relative_link(clean, renamed)))
+    })
+}
+
+/// Resolve a relative link inside the export to the original archive path
+fn resolve_link(from: &str, dest: &str) -> Option<String> {
+    if dest.contains("://") || dest.starts_with('#') || dest.starts_with('/') || dest.starts_with("mailto:") {
+        return None;
+    }
+
+    let decoded = percent_decode(dest.split('#').next()?);
+    let mut parts: Vec<&str> = from.split('/').collect();
+    parts.pop();
+    for part in decoded.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn relative_link(from: &str, to: &str) -> String {
+    let from_dirs: Vec<&str> = from.split('/').collect();
+    let from_dirs = &from_dirs[..from_dirs.len() - 1];
+    let to_parts: Vec<&str> = to.split('/').collect();
+
+    let common = from_dirs.iter().zip(&to_parts).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<&str> = vec![".."; from_dirs.len() - common];
+    parts.extend(&to_parts[common..]);
+    parts.join("/")
+}
+
+fn encode_link(path: &str) -> String {
+    path.replace('%', "%25").replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit()
+        {
+            let hex = |b: u8| (b as char).to_digit(16).unwrap_or(0) as u8;
+            decoded.push(hex(bytes[i + 1]) << 4 | hex(bytes[i + 2]));
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Flatten a database CSV into a markdown table, linking rows to their pages
+fn database_to_markdown(bytes: &[u8], original: &str, clean: &str, renames: &HashMap<String, String>) -> Result<String> {
+    // Notion prefixes its CSV files with a UTF-8 BOM
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes);
+
+    let headers: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+    if headers.is_empty() {
+        return Ok(String::new());
+    }
+
+    // Row pages live in a folder named like the CSV, e.g. `Tasks abc.csv` -> `Tasks abc/Row xyz.md`
+    let folder = original.strip_suffix("_all.csv").or_else(|| original.strip_suffix(".csv")).unwrap_or(original);
+    let row_pages: HashMap<String, &String> = renames
+        .iter()
+        .filter(|(path, _)| path.ends_with(".md") && path.starts_with(&format!("{}/", folder)))
+        .filter_map(|(path, renamed)| {
+            let name = path[folder.len() + 1..].strip_suffix(".md")?;
+            (!name.contains('/')).then(|| (strip_notion_id(name), renamed))
+        })
+        .collect();
+
+    let title = Path::new(clean).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let mut table = format!("# {}\n\n", title);
+    table.push_str(&format!("| {} |\n", headers.iter().map(|h| escape_cell(h)).collect::<Vec<_>>().join(" | ")));
+    table.push_str(&format!("|{}\n", " --- |".repeat(headers.len())));
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("Skipping malformed row in {}: {}", original, e);
+                continue;
+            }
+        };
+
+        let cells: Vec<String> = (0..headers.len())
+            .map(|i| {
+                let value = record.get(i).unwrap_or("");
+                match row_pages.get(value).filter(|_| i == 0 && !value.is_empty()) {
+                    Some(page) => format!("[{}]({})", escape_cell(value), encode_link(&relative_link(clean, page))),
+                    None => escape_cell(value),
+                }
+            })
+            .collect();
+        table.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+
+    Ok(table)
+}
+
+fn escape_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef";
+    const ID2: &str = "fedcba9876543210fedcba9876543210";
+
+    fn write_zip(path: &Path, files: &[(String, &str)]) {
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        for (name, content) in files {
+            zip.start_file(name.as_str(), zip::write::FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_strip_notion_id() {
+        assert_eq!(strip_notion_id(&format!("Meeting Notes {}.md", ID)), "Meeting Notes.md");
+        assert_eq!(strip_notion_id(&format!("Projects {}", ID)), "Projects");
+        assert_eq!(strip_notion_id(&format!("Tasks {}_all.csv", ID)), "Tasks_all.csv");
+        assert_eq!(strip_notion_id("image.png"), "image.png");
+        assert_eq!(strip_notion_id("Version 2.0 notes"), "Version 2.0 notes");
+    }
+
+    #[tokio::test]
+    async fn test_import_zip_export() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("export.zip");
+        let home = format!(
+            "# Home\n\nSee [Tasks](Home%20{id}/Tasks%20{id2}.csv) and [Plan](Home%20{id}/Plan%20{id}.md).\n\n![Logo](Home%20{id}/logo.png)\n",
+            id = ID, id2 = ID2
+        );
+        let plan = format!("# Plan\n\nBack to [Home](../Home%20{}.md)\n", ID);
+        write_zip(&archive, &[
+            (format!("Home {}.md", ID), home.as_str()),
+            (format!("Home {}/Plan {}.md", ID, ID), plan.as_str()),
+            (format!("Home {}/logo.png", ID), "png"),
+            (format!("Home {}/Tasks {}.csv", ID, ID2), "\u{feff}Name,Status\nWrite docs,Done\n"),
+            (format!("Home {}/Tasks {}_all.csv", ID, ID2), "\u{feff}Name,Status\nWrite docs,Done\nShip | release,Todo\n"),
+            (format!("Home {}/Tasks {}/Write docs {}.md", ID, ID2, ID), "# Write docs\n"),
+        ]);
+
+        let output = temp_dir.path().join("notes");
+        let result = NotionImporter::new().import(&archive, &output).await.unwrap();
+
+        assert_eq!(result.tables, 1);
+        assert_eq!(result.assets, 1);
+        let home = std::fs::read_to_string(output.join("Home.md")).unwrap();
+        assert!(home.contains("[Tasks](Home/Tasks.md)"));
+        assert!(home.contains("[Plan](Home/Plan.md)"));
+        assert!(home.contains("![Logo](Home/logo.png)"));
+
+        let plan = std::fs::read_to_string(output.join("Home/Plan.md")).unwrap();
+        assert!(plan.contains("[Home](../Home.md)"));
+
+        let tasks = std::fs::read_to_string(output.join("Home/Tasks.md")).unwrap();
+        assert!(tasks.contains("| Name | Status |"));
+        assert!(tasks.contains("| [Write docs](Tasks/Write%20docs.md) | Done |"));
+        assert!(tasks.contains("| Ship \\| release | Todo |"));
+        assert!(output.join("Home/Tasks/Write docs.md").exists());
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_paths_outside_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let output = temp_dir.path().join("notes");
+        for name in ["../escaped.md".to_string(), "/tmp/escaped.md".to_string(), format!("Home/.. {}/escaped.md", ID)] {
+            let archive = temp_dir.path().join("export.zip");
+            write_zip(&archive, &[("Home.md".to_string(), "# Home\n"), (name.clone(), "# Escaped\n")]);
+            assert!(NotionImporter::new().import(&archive, &output).await.is_err(), "{}", name);
+        }
+        assert!(!temp_dir.path().join("escaped.md").exists());
+
+        let mut budget = 8;
+        let archive = temp_dir.path().join("large.zip");
+        write_zip(&archive, &[("Home.md".to_string(), "# A home page longer than the budget\n")]);
+        assert!(read_zip(std::fs::read(&archive).unwrap(), &mut budget, true).is_err());
+    }
+}
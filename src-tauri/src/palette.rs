@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::presets::ExportPreset;
+use crate::settings::JournalSettings;
+use crate::shortcuts::effective_shortcuts;
+
+/// Id prefix of the commands exporting with a saved preset, followed by the preset name
+pub const PRESET_COMMAND_PREFIX: &str = "preset:";
+/// Id prefix of the commands creating a note from a template
+pub const TEMPLATE_COMMAND_PREFIX: &str = "template:";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PaletteCommand {
+    pub id: String,
+    pub title: String,
+    pub category: String,
+    pub shortcut: Option<String>,
+}
+
+impl PaletteCommand {
+    pub fn new(id: &str, title: &str, category: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            title: title.to_string(),
+            category: category.to_string(),
+            shortcut: None,
+        }
+    }
+}
+
+/// Built-in actions; ids match the shortcut action ids where a default binding exists
+fn builtin_commands() -> Vec<PaletteCommand> {
+    vec![
+        PaletteCommand::new("open-file", "Open File…", "File"),
+        PaletteCommand::new("save-file", "Save", "File"),
+        PaletteCommand::new("reveal-in-explorer", "Reveal in File Manager", "File"),
+        PaletteCommand::new("export-pdf", "Export as PDF…", "Export"),
+        PaletteCommand::new("export-html", "Export as HTML…", "Export"),
+        PaletteCommand::new("print-preview", "Print Preview", "Export"),
+        PaletteCommand::new("copy-as-html", "Copy as HTML", "Edit"),
+        PaletteCommand::new("copy-as-plain", "Copy as Plain Text", "Edit"),
+        PaletteCommand::new("find", "Find", "Edit"),
+        PaletteCommand::new("check-grammar", "Check Grammar", "Tools"),
+        PaletteCommand::new("ai-summarize", "AI: Summarize Selection", "Tools"),
+        PaletteCommand::new("ai-rewrite", "AI: Rewrite Selection", "Tools"),
+        PaletteCommand::new("toggle-sidebar", "Toggle Sidebar", "View"),
+        PaletteCommand::new("toggle-theme", "Toggle Theme", "View"),
+        PaletteCommand::new("quick-capture", "Quick Capture", "Tools"),
+    ]
+}
+
+/// All actions the command palette can invoke
+///
+/// Built-in commands are always present; other subsystems (export presets,
+/// templates) add their own entries at runtime.
+pub struct CommandRegistry {
+    extra: Mutex<BTreeMap<String, PaletteCommand>>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self {
+            extra: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace a dynamic command
+    pub fn register(&self, command: PaletteCommand) {
+        self.extra.lock().unwrap().insert(command.id.clone(), command);
+    }
+
+    /// Remove every dynamic command whose id starts with `prefix`
+    pub fn unregister_prefix(&self, prefix: &str) {
+        self.extra.lock().unwrap().retain(|id, _| !id.starts_with(prefix));
+    }
+
+    /// Replace the export preset commands with one per preset in `presets`
+    pub fn set_export_presets(&self, presets: &[ExportPreset]) {
+        self.unregister_prefix(PRESET_COMMAND_PREFIX);
+        for preset in presets {
+            self.register(PaletteCommand::new(
+                &format!("{}{}", PRESET_COMMAND_PREFIX, preset.name),
+                &format!("Export with \"{}\"", preset.name),
+                "Export",
+            ));
+        }
+    }
+
+    /// Replace the template commands with the templates configured in `journal`
+    pub fn set_templates(&self, journal: &JournalSettings) {
+        self.unregister_prefix(TEMPLATE_COMMAND_PREFIX);
+        if let Some(template) = journal.template.as_ref().and_then(|template| template.file_stem()) {
+            self.register(PaletteCommand::new(
+                &format!("{}journal", TEMPLATE_COMMAND_PREFIX),
+                &format!("New Journal Note from \"{}\"", template.to_string_lossy()),
+                "File",
+            ));
+        }
+    }
+
+    /// List all commands, with shortcuts resolved from the user's keybindings
+    pub fn list(&self, shortcut_overrides: &BTreeMap<String, String>) -> Vec<PaletteCommand> {
+        let shortcuts = effective_shortcuts(shortcut_overrides);
+        let mut commands = builtin_commands();
+        commands.extend(self.extra.lock().unwrap().values().cloned());
+
+        for command in &mut commands {
+            command.shortcut = shortcuts
+                .iter()
+                .find(|shortcut| shortcut.action == command.id)
+                .map(|shortcut| shortcut.accelerator.clone());
+        }
+
+        commands
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        builtin_commands().iter().any(|command| command.id == id) || self.extra.lock().unwrap().contains_key(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportOptions;
+    use crate::presets::ExportPresets;
+
+    #[test]
+    fn test_list_includes_shortcuts() {
+        let registry = CommandRegistry::new();
+        let mut overrides = BTreeMap::new();
+        overrides.insert("toggle-theme".to_string(), "Alt+T".to_string());
+
+        let commands = registry.list(&overrides);
+        let save = commands.iter().find(|c| c.id == "save-file").unwrap();
+        let theme = commands.iter().find(|c| c.id == "toggle-theme").unwrap();
+        let preview = commands.iter().find(|c| c.id == "print-preview").unwrap();
+
+        assert_eq!(save.shortcut.as_deref(), Some("CmdOrCtrl+S"));
+        assert_eq!(theme.shortcut.as_deref(), Some("Alt+T"));
+        assert!(preview.shortcut.is_none());
+    }
+
+    #[test]
+    fn test_dynamic_commands() {
+        let registry = CommandRegistry::new();
+        registry.register(PaletteCommand::new("preset:report", "Export: Report", "Export"));
+
+        assert!(registry.contains("preset:report"));
+        assert!(registry.contains("open-file"));
+
+        registry.unregister_prefix("preset:");
+        assert!(!registry.contains("preset:report"));
+    }
+
+    #[tokio::test]
+    async fn test_saved_presets_and_templates_are_listed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let presets = ExportPresets::load(dir.path().join("export_presets.json"));
+        let registry = CommandRegistry::new();
+
+        presets.save(ExportPreset { name: "Print A4".to_string(), options: ExportOptions::default() }).await.unwrap();
+        registry.set_export_presets(&presets.list());
+        let commands = registry.list(&BTreeMap::new());
+        let print = commands.iter().find(|c| c.id == "preset:Print A4").unwrap();
+        assert_eq!(print.title, "Export with \"Print A4\"");
+
+        presets.delete("Print A4").await.unwrap();
+        registry.set_export_presets(&presets.list());
+        assert!(!registry.contains("preset:Print A4"));
+
+        let journal = JournalSettings { template: Some("templates/daily.md".into()), ..JournalSettings::default() };
+        registry.set_templates(&journal);
+        assert!(registry.list(&BTreeMap::new()).iter().any(|c| c.id == "template:journal" && c.title.contains("daily")));
+        registry.set_templates(&JournalSettings::default());
+        assert!(!registry.contains("template:journal"));
+    }
+}
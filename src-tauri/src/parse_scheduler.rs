@@ -0,0 +1,128 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::parser::ParsedDocument;
+
+/// Payload of the `parse-complete` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseComplete {
+    pub request_id: u64,
+    pub document: String,
+    pub parsed: ParsedDocument,
+}
+
+/// Payload of the `parse-failed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseFailed {
+    pub request_id: u64,
+    pub document: String,
+    pub error: String,
+}
+
+/// A scheduled parse; cancelled as soon as a newer one for the same document starts
+pub struct ParseTicket {
+    pub id: u64,
+    pub document: String,
+    token: CancellationToken,
+}
+
+impl ParseTicket {
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+/// Runs at most one parse per document, dropping requests that newer edits supersede
+#[derive(Default)]
+pub struct ParseScheduler {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<String, (u64, CancellationToken)>>,
+}
+
+impl ParseScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new parse of `document`, cancelling the one in flight
+    pub fn begin(&self, document: &str) -> ParseTicket {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let token = CancellationToken::new();
+
+        let previous = self.active.lock().unwrap().insert(document.to_string(), (id, token.clone()));
+        if let Some((previous_id, previous_token)) = previous {
+            debug!("Parse {} of {} superseded by {}", previous_id, document, id);
+            previous_token.cancel();
+        }
+
+        ParseTicket { id, document: document.to_string(), token }
+    }
+
+    /// Run `job` for the ticket, giving up as soon as it's cancelled
+    ///
+    /// Returns `None` when the parse was superseded, so its result is never used.
+    pub async fn run<F: Future>(&self, ticket: &ParseTicket, job: F) -> Option<F::Output> {
+        let output = tokio::select! {
+            _ = ticket.token.cancelled() => None,
+            output = job => Some(output),
+        };
+        self.finish(ticket);
+        output.filter(|_| !ticket.is_cancelled())
+    }
+
+    /// Cancel whatever parse is running for `document`
+    pub fn cancel(&self, document: &str) {
+        if let Some((_, token)) = self.active.lock().unwrap().remove(document) {
+            token.cancel();
+        }
+    }
+
+    fn finish(&self, ticket: &ParseTicket) {
+        let mut active = self.active.lock().unwrap();
+        if active.get(&ticket.document).is_some_and(|(id, _)| *id == ticket.id) {
+            active.remove(&ticket.document);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_newer_parse_cancels_older() {
+        let scheduler = ParseScheduler::new();
+        let first = scheduler.begin("notes.md");
+        let other = scheduler.begin("todo.md");
+        let second = scheduler.begin("notes.md");
+
+        assert!(first.is_cancelled());
+        assert!(!other.is_cancelled());
+        assert!(second.id > first.id);
+
+        let slow = scheduler.run(&first, tokio::time::sleep(Duration::from_secs(60)));
+        assert_eq!(tokio::time::timeout(Duration::from_secs(1), slow).await.unwrap(), None);
+        assert_eq!(scheduler.run(&second, async { 42 }).await, Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_running_parse() {
+        let scheduler = std::sync::Arc::new(ParseScheduler::new());
+        let ticket = scheduler.begin("notes.md");
+
+        let canceller = scheduler.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel("notes.md");
+        });
+
+        let result = scheduler.run(&ticket, tokio::time::sleep(Duration::from_secs(60))).await;
+        assert!(result.is_none());
+    }
+}
@@ -1,9 +1,22 @@
 use anyhow::Result;
 use pulldown_cmark::{Parser, Options, html, Event, Tag, CodeBlockKind};
+use percent_encoding::percent_decode_str;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, info};
 
+use crate::bidi::{document_direction, first_strong_direction, is_bidi_control, TextDirection};
+use crate::conflicts::{find_conflicts, mark_conflicts, parse_conflict_comment, ConflictMarker, MergeConflict};
+use crate::image_size::image_dimensions;
+use crate::preview_diff::PreviewPatch;
+use crate::profiling::{PhaseTimer, PhaseTiming};
+use crate::qr::{render_qr_fence, QR_FENCE};
+use crate::settings::{FootnoteNumbering, FootnotePlacement, FootnoteSettings};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TocItem {
     pub level: u8,
@@ -12,6 +25,46 @@ pub struct TocItem {
     pub line: usize,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutlineItem {
+    pub level: u8,
+    pub title: String,
+    pub id: String,         // element id of the rendered heading
+    pub start: usize,       // source byte range of the heading
+    pub end: usize,
+    pub start_line: usize,  // 1-based source line range of the heading
+    pub end_line: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FoldKind {
+    Section,
+    List,
+    CodeBlock,
+}
+
+/// A source range the editor can fold away, leaving its first line visible
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FoldingRange {
+    pub kind: FoldKind,
+    pub start: usize,       // source byte range, without trailing blank lines
+    pub end: usize,
+    pub start_line: usize,  // 1-based source line range
+    pub end_line: usize,
+}
+
+/// A top-level block of the document, rendered with a matching `data-sourcepos` attribute
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SourceBlock {
+    pub index: usize,       // position among the rendered top-level blocks
+    pub kind: String,
+    pub start: usize,       // source byte range of the block
+    pub end: usize,
+    pub start_line: usize,  // 1-based source line range, as in `data-sourcepos="start-end"`
+    pub end_line: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParsedDocument {
     pub html: String,
@@ -19,10 +72,96 @@ pub struct ParsedDocument {
     pub toc: Vec<TocItem>,
     pub word_count: usize,
     pub reading_time: u32, // in minutes
+    /// The same HTML split into top-level blocks, for diffing successive renders
+    #[serde(skip)]
+    pub blocks: Vec<HtmlBlock>,
+    /// Changed blocks only; when set, `html` is left empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<PreviewPatch>,
+    /// Git merge conflicts left in the document, rendered as blocks with both sides
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<MergeConflict>,
+    /// The document's own stylesheet from its `css` front matter, for the preview to append
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub css: Option<String>,
+    /// From the `dir` front matter or detected; blocks written the other way get their own `dir`
+    #[serde(default)]
+    pub direction: TextDirection,
+    /// How long each parsing phase took, for performance profiles
+    #[serde(skip)]
+    pub timings: Vec<PhaseTiming>,
+}
+
+/// A rendered top-level block and its source line range
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlBlock {
+    pub sourcepos: String,  // as in `data-sourcepos="start-end"`
+    pub html: String,       // without the `data-sourcepos` attribute, with any `dir` attribute
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentStats {
+    pub word_count: usize,
+    pub char_count: usize,
+    pub char_count_no_spaces: usize,
+    pub sentence_count: usize,
+    pub paragraph_count: usize,
+    pub reading_time: u32, // in minutes
+    pub heading_count: usize,
+    pub link_count: usize,
+    pub image_count: usize,
+    pub task_count: usize,           // task list items, `- [ ]` and `- [x]`
+    pub completed_task_count: usize,
+    pub file_size: u64, // in bytes
+}
+
+/// Bounds that make pathological documents fail with a clear error instead of
+/// freezing the app
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParserLimits {
+    pub max_input_bytes: usize,
+    /// Deepest allowed nesting of blocks and inline elements, e.g. quotes in quotes
+    pub max_nesting_depth: usize,
+    /// Checked between parsing phases and while tokenizing
+    pub timeout: Duration,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_input_bytes: 64 * 1024 * 1024,
+            max_nesting_depth: 256,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Image dimensions by path, with the modification time they were read at
+type ImageSizes = HashMap<PathBuf, (SystemTime, Option<(u32, u32)>)>;
+
+/// A heading in an event stream, with the id its element is rendered with
+struct HeadingInfo {
+    /// Index of its start event
+    index: usize,
+    level: u8,
+    title: String,
+    /// `None` for headings without text
+    id: Option<String>,
+}
+
+/// How `<img>` tags are rendered
+#[derive(Clone, Copy)]
+enum ImageMode<'a> {
+    Plain,
+    /// Lazily loaded, and sized when the file is found relative to the directory
+    Preview(Option<&'a Path>),
 }
 
 pub struct MarkdownParser {
     options: Options,
+    limits: RwLock<ParserLimits>,
+    footnotes: RwLock<FootnoteSettings>,
+    image_sizes: Mutex<ImageSizes>,
 }
 
 impl Default for MarkdownParser {
@@ -34,7 +173,12 @@ impl Default for MarkdownParser {
         options.insert(Options::ENABLE_TASKLISTS);
         options.insert(Options::ENABLE_SMART_PUNCTUATION);
         
-        Self { options }
+        Self {
+            options,
+            limits: RwLock::new(ParserLimits::default()),
+            footnotes: RwLock::new(FootnoteSettings::default()),
+            image_sizes: Mutex::new(HashMap::new()),
+        }
     }
 }
 
@@ -43,39 +187,112 @@ impl MarkdownParser {
         Self::default()
     }
 
+    /// Markdown extensions this parser understands, for diagnostics
+    pub fn extensions(&self) -> Vec<&'static str> {
+        let flags = [
+            (Options::ENABLE_TABLES, "tables"),
+            (Options::ENABLE_FOOTNOTES, "footnotes"),
+            (Options::ENABLE_STRIKETHROUGH, "strikethrough"),
+            (Options::ENABLE_TASKLISTS, "tasklists"),
+            (Options::ENABLE_SMART_PUNCTUATION, "smart_punctuation"),
+            (Options::ENABLE_HEADING_ATTRIBUTES, "heading_attributes"),
+        ];
+        let mut extensions: Vec<_> = flags.iter()
+            .filter(|(flag, _)| self.options.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        // Rendered by the app on top of pulldown-cmark
+        extensions.extend(["math", QR_FENCE, "merge_conflicts", "rtl"]);
+        extensions
+    }
+
+    pub fn with_limits(self, limits: ParserLimits) -> Self {
+        self.set_limits(limits);
+        self
+    }
+
+    pub fn set_limits(&self, limits: ParserLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    pub fn limits(&self) -> ParserLimits {
+        *self.limits.read().unwrap()
+    }
+
+    pub fn with_footnotes(self, footnotes: FootnoteSettings) -> Self {
+        self.set_footnotes(footnotes);
+        self
+    }
+
+    pub fn set_footnotes(&self, footnotes: FootnoteSettings) {
+        *self.footnotes.write().unwrap() = footnotes;
+    }
+
+    pub fn footnotes(&self) -> FootnoteSettings {
+        self.footnotes.read().unwrap().clone()
+    }
+
     /// Parse markdown text into a structured document
+    ///
+    /// Fails instead of rendering when the document breaks one of the parser's limits.
     pub fn parse(&self, markdown: &str) -> Result<ParsedDocument> {
+        self.render(markdown, ImageMode::Plain)
+    }
+
+    /// Parse for the preview: images load lazily, and local images relative to
+    /// `base_dir` (or absolute) get their `width` and `height` so the page doesn't
+    /// shift as they arrive
+    pub fn parse_preview(&self, markdown: &str, base_dir: Option<&Path>) -> Result<ParsedDocument> {
+        self.render(markdown, ImageMode::Preview(base_dir))
+    }
+
+    fn render(&self, markdown: &str, images: ImageMode) -> Result<ParsedDocument> {
         debug!("Starting markdown parsing, length: {} chars", markdown.len());
+        let limits = self.limits();
+        check_size(markdown, &limits)?;
+        // Conflict marker lines become comments here, and the sides of one block below
+        let conflicts = find_conflicts(markdown);
+        let marked;
+        let markdown = if conflicts.is_empty() {
+            markdown
+        } else {
+            marked = mark_conflicts(markdown, &conflicts);
+            marked.as_str()
+        };
+        let started = Instant::now();
+        let check_time = || check_deadline(started, &limits);
+        let mut timer = PhaseTimer::start();
         
-        let parser = Parser::new_ext(markdown, self.options);
         let mut html_output = String::new();
         let mut line_map = Vec::new();
         let mut toc = Vec::new();
-        let mut current_line = 1usize;
         let mut current_pos = 0usize;
-        let mut heading_count = HashMap::new();
-        
-        // Process events to build line map and TOC
-        let events: Vec<_> = parser.collect();
+        let lines = LineIndex::new(markdown);
         
+        // Process events to build line map and TOC in a single pass
+        let offset_events = self.tokenize(markdown, &limits, started)?;
+        let blocks = top_level_blocks(&offset_events);
+        let (mut events, ranges): (Vec<_>, Vec<_>) = offset_events.into_iter().unzip();
+        timer.lap("tokenize");
+
+        // Ids are given here, before later passes move events around, so the TOC and
+        // the rendered elements always agree
+        for heading in self.headings(&events) {
+            let Some(anchor) = heading.id else {
+                continue;
+            };
+            events[heading.index] = Event::Html(format!("<h{} id=\"{}\">", heading.level, anchor).into());
+            toc.push(TocItem {
+                level: heading.level,
+                title: heading.title,
+                anchor,
+                line: lines.line_of(ranges[heading.index].start),
+            });
+        }
+
         for event in &events {
             match event {
-                Event::Start(Tag::Heading(level, _, _)) => {
-                    // Track heading for TOC
-                    if let Some(Event::Text(text)) = events.get(events.iter().position(|e| e == event).unwrap() + 1) {
-                        let title = text.to_string();
-                        let anchor = self.create_anchor(&title, &mut heading_count);
-                        
-                        toc.push(TocItem {
-                            level: *level as u8,
-                            title,
-                            anchor,
-                            line: current_line,
-                        });
-                    }
-                }
                 Event::SoftBreak | Event::HardBreak => {
-                    current_line += 1;
                     line_map.push(current_pos);
                 }
                 Event::Text(text) => {
@@ -85,13 +302,26 @@ impl MarkdownParser {
             }
         }
 
+        timer.lap("outline");
+        check_time()?;
+
         // Convert to HTML with syntax highlighting and math support
-        let processed_events = self.process_events(events);
+        let direction = document_direction(markdown);
+        let events = insert_sourcepos_markers(markdown, events, &blocks, direction);
+        let events = render_conflicts(events, &conflicts);
+        let events = render_footnotes(events, &self.footnotes());
+        let processed_events = self.process_events(events, images);
+        timer.lap("highlighting");
+        check_time()?;
         html::push_html(&mut html_output, processed_events.into_iter());
+        let blocks = split_blocks(&html_output);
+        let html_output = apply_sourcepos_markers(&html_output);
+        timer.lap("html");
 
         // Calculate reading statistics
         let word_count = self.count_words(markdown);
         let reading_time = (word_count / 200).max(1) as u32; // Average reading speed: 200 WPM
+        timer.lap("stats");
 
         let toc_len = toc.len();
         let parsed_doc = ParsedDocument {
@@ -100,6 +330,12 @@ impl MarkdownParser {
             toc,
             word_count,
             reading_time,
+            blocks,
+            patch: None,
+            conflicts,
+            css: None,
+            direction,
+            timings: timer.finish(),
         };
 
         info!("Markdown parsing complete: {} words, {} headings, {} min read", 
@@ -108,6 +344,179 @@ impl MarkdownParser {
         Ok(parsed_doc)
     }
 
+    /// Fail when `markdown` breaks one of the parser's limits, for checks that
+    /// walk the document with their own parser
+    pub fn check_limits(&self, markdown: &str) -> Result<()> {
+        let limits = self.limits();
+        self.tokenize(markdown, &limits, Instant::now()).map(drop)
+    }
+
+    /// Parse `markdown` into events with their source ranges, failing when it's too
+    /// large, nested too deeply or takes longer than the limits allow
+    fn tokenize<'a>(&self, markdown: &'a str, limits: &ParserLimits, started: Instant) -> Result<Vec<(Event<'a>, Range<usize>)>> {
+        check_size(markdown, limits)?;
+
+        let mut events = Vec::new();
+        let mut depth = 0usize;
+        for (index, (event, range)) in Parser::new_ext(markdown, self.options).into_offset_iter().enumerate() {
+            match event {
+                Event::Start(_) => {
+                    depth += 1;
+                    if depth > limits.max_nesting_depth {
+                        anyhow::bail!(
+                            "Document is nested too deeply to render (more than {} levels, near line {})",
+                            limits.max_nesting_depth,
+                            LineIndex::new(markdown).line_of(range.start)
+                        );
+                    }
+                }
+                Event::End(_) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            if index % 4096 == 0 {
+                check_deadline(started, limits)?;
+            }
+            events.push((event, range));
+        }
+        check_deadline(started, limits)?;
+
+        Ok(events)
+    }
+
+    /// Map each rendered top-level block to its source line range, for two-way scroll sync
+    pub fn scroll_map(&self, markdown: &str) -> Result<Vec<SourceBlock>> {
+        let events = self.tokenize(markdown, &self.limits(), Instant::now())?;
+        let lines = LineIndex::new(markdown);
+
+        let blocks = top_level_blocks(&events)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (event_index, range))| {
+                let kind = match &events[event_index].0 {
+                    Event::Start(tag) => block_kind(tag),
+                    _ => "rule",
+                };
+                let (start_line, end_line) = lines.line_range(markdown, &range);
+
+                SourceBlock {
+                    index,
+                    kind: kind.to_string(),
+                    start: range.start,
+                    end: range.end,
+                    start_line,
+                    end_line,
+                }
+            })
+            .collect();
+        Ok(blocks)
+    }
+
+    /// Extract the document's headings with their source ranges and rendered element ids
+    pub fn outline(&self, markdown: &str) -> Result<Vec<OutlineItem>> {
+        let lines = LineIndex::new(markdown);
+
+        let (events, spans): (Vec<_>, Vec<_>) = self.tokenize(markdown, &self.limits(), Instant::now())?.into_iter().unzip();
+
+        let outline = self.headings(&events)
+            .into_iter()
+            .map(|heading| {
+                let span = &spans[heading.index];
+                let source = markdown[span.clone()].trim_end();
+                let (start_line, end_line) = lines.line_range(markdown, span);
+
+                OutlineItem {
+                    level: heading.level,
+                    id: heading.id.unwrap_or_default(),
+                    title: heading.title,
+                    start: span.start,
+                    end: span.start + source.len(),
+                    start_line,
+                    end_line,
+                }
+            })
+            .collect();
+        Ok(outline)
+    }
+
+    /// The headings among `events` with the ids the renderer gives them, shared by
+    /// the renderer, the TOC and the outline so they can't disagree
+    fn headings(&self, events: &[Event]) -> Vec<HeadingInfo> {
+        let mut heading_count = HashMap::new();
+        let mut headings = Vec::new();
+        let mut current: Option<HeadingInfo> = None;
+
+        for (index, event) in events.iter().enumerate() {
+            match event {
+                Event::Start(Tag::Heading(level, _, _)) => {
+                    current = Some(HeadingInfo { index, level: *level as u8, title: String::new(), id: None });
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(heading) = &mut current {
+                        heading.title.push_str(text);
+                    }
+                }
+                Event::End(Tag::Heading(..)) => {
+                    if let Some(mut heading) = current.take() {
+                        if !heading.title.trim().is_empty() {
+                            heading.id = Some(self.create_anchor(&heading.title, &mut heading_count));
+                        }
+                        headings.push(heading);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        headings
+    }
+
+    /// Foldable ranges of the document: each heading's section up to the next
+    /// heading of the same or a higher level, and every list and code block
+    /// spanning more than one line, ordered by where they start
+    pub fn folding_ranges(&self, markdown: &str) -> Result<Vec<FoldingRange>> {
+        let lines = LineIndex::new(markdown);
+        let mut headings = Vec::new();
+        let mut blocks = Vec::new();
+
+        for (event, span) in self.tokenize(markdown, &self.limits(), Instant::now())? {
+            match event {
+                Event::Start(Tag::Heading(level, _, _)) => headings.push((level, span.start)),
+                Event::Start(Tag::List(_)) => blocks.push((FoldKind::List, span)),
+                Event::Start(Tag::CodeBlock(_)) => blocks.push((FoldKind::CodeBlock, span)),
+                _ => {}
+            }
+        }
+
+        let sections = headings.iter().enumerate().map(|(i, &(level, start))| {
+            let end = headings[i + 1..].iter()
+                .find(|&&(next_level, _)| next_level <= level)
+                .map_or(markdown.len(), |&(_, next_start)| next_start);
+            (FoldKind::Section, start..end)
+        });
+
+        let mut ranges: Vec<FoldingRange> = sections.chain(blocks)
+            .filter_map(|(kind, span)| {
+                let end = span.start + markdown[span.clone()].trim_end().len();
+                let (start_line, end_line) = lines.line_range(markdown, &span);
+                (end_line > start_line).then_some(FoldingRange { kind, start: span.start, end, start_line, end_line })
+            })
+            .collect();
+        ranges.sort_by_key(|range| (range.start, std::cmp::Reverse(range.end)));
+        Ok(ranges)
+    }
+
+    /// Anchor of the first heading whose text is `heading`, which may also be
+    /// given as its `#` source line or as the anchor itself; the same id the
+    /// heading is rendered with
+    pub fn heading_anchor(&self, markdown: &str, heading: &str) -> Result<Option<String>> {
+        let wanted = heading.trim().trim_start_matches('#').trim_end_matches('#').trim().to_lowercase();
+        let outline: Vec<_> = self.outline(markdown)?.into_iter().filter(|item| !item.id.is_empty()).collect();
+        Ok(outline.iter()
+            .find(|item| item.title.trim().to_lowercase() == wanted)
+            .or_else(|| outline.iter().find(|item| item.id == wanted))
+            .map(|item| item.id.clone()))
+    }
+
     /// Create a unique anchor for headings
     fn create_anchor(&self, title: &str, heading_count: &mut HashMap<String, usize>) -> String {
         let base_anchor = title
@@ -128,8 +537,8 @@ impl MarkdownParser {
         }
     }
 
-    /// Process events to add syntax highlighting and math support
-    fn process_events<'a>(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    /// Process events to add syntax highlighting, math support and QR codes
+    fn process_events<'a>(&self, events: Vec<Event<'a>>, images: ImageMode) -> Vec<Event<'a>> {
         let mut processed = Vec::new();
         let mut i = 0;
 
@@ -137,6 +546,20 @@ impl MarkdownParser {
             let event = &events[i];
             
             match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) if lang.split_whitespace().next() == Some(QR_FENCE) => {
+                    let len = events[i + 1..].iter()
+                        .position(|event| matches!(event, Event::End(Tag::CodeBlock(_))))
+                        .unwrap_or(events.len() - i - 1);
+                    let content: String = events[i + 1..i + 1 + len].iter()
+                        .filter_map(|event| match event {
+                            Event::Text(text) => Some(text.as_ref()),
+                            _ => None,
+                        })
+                        .collect();
+                    processed.push(Event::Html(render_qr_fence(&content).into()));
+                    i += len + 2; // Skip the content and the End event
+                    continue;
+                }
                 Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
                     // Handle syntax highlighting
                     if let Some(Event::Text(code)) = events.get(i + 1) {
@@ -160,14 +583,19 @@ impl MarkdownParser {
                         continue;
                     }
                 }
-                Event::Start(Tag::Heading(level, _, _)) => {
-                    // Add anchor IDs to headings
-                    if let Some(Event::Text(title)) = events.get(i + 1) {
-                        let mut heading_count = HashMap::new();
-                        let anchor = self.create_anchor(title, &mut heading_count);
-                        let html = format!("<h{} id=\"{}\">", level, anchor);
-                        processed.push(Event::Html(html.into()));
-                        i += 1;
+                Event::Start(Tag::Image(_, src, title)) => {
+                    if let ImageMode::Preview(base_dir) = images {
+                        let alt_len = events[i + 1..].iter()
+                            .position(|event| matches!(event, Event::End(Tag::Image(..))))
+                            .unwrap_or(events.len() - i - 1);
+                        let alt: String = events[i + 1..i + 1 + alt_len].iter()
+                            .filter_map(|event| match event {
+                                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                                _ => None,
+                            })
+                            .collect();
+                        processed.push(Event::Html(self.preview_image(src, title, &alt, base_dir).into()));
+                        i += alt_len + 2; // Skip the alt text and the End event
                         continue;
                     }
                 }
@@ -181,6 +609,38 @@ impl MarkdownParser {
         processed
     }
 
+    /// An `<img>` tag that loads lazily, sized when the image is a readable local file
+    fn preview_image(&self, src: &str, title: &str, alt: &str, base_dir: Option<&Path>) -> String {
+        let mut html = format!(
+            "<img src=\"{}\" alt=\"{}\"",
+            html_escape::encode_double_quoted_attribute(src),
+            html_escape::encode_double_quoted_attribute(alt)
+        );
+        if !title.is_empty() {
+            html.push_str(&format!(" title=\"{}\"", html_escape::encode_double_quoted_attribute(title)));
+        }
+        html.push_str(" loading=\"lazy\"");
+        if let Some((width, height)) = local_image_path(src, base_dir).and_then(|path| self.image_size(&path)) {
+            html.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+        }
+        html.push_str(" />");
+        html
+    }
+
+    /// Dimensions of a local image, remembered until the file changes
+    fn image_size(&self, path: &Path) -> Option<(u32, u32)> {
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        if let Some((seen, size)) = self.image_sizes.lock().unwrap().get(path) {
+            if *seen == modified {
+                return *size;
+            }
+        }
+
+        let size = image_dimensions(path);
+        self.image_sizes.lock().unwrap().insert(path.to_path_buf(), (modified, size));
+        size
+    }
+
     /// Apply syntax highlighting to code blocks
     fn highlight_code(&self, code: &str, lang: &str) -> String {
         // For now, return basic highlighted code
@@ -206,12 +666,529 @@ impl MarkdownParser {
         }
     }
 
+    /// Compute text and structure statistics for a document
+    pub fn document_stats(&self, markdown: &str) -> Result<DocumentStats> {
+        let mut stats = DocumentStats {
+            file_size: markdown.len() as u64,
+            ..Default::default()
+        };
+        let events: Vec<_> = self.tokenize(markdown, &self.limits(), Instant::now())?
+            .into_iter()
+            .map(|(event, _)| event)
+            .collect();
+
+        for event in &events {
+            match event {
+                Event::Start(Tag::Heading(..)) => stats.heading_count += 1,
+                Event::Start(Tag::Paragraph) => stats.paragraph_count += 1,
+                Event::Start(Tag::Link(..)) => stats.link_count += 1,
+                Event::Start(Tag::Image(..)) => stats.image_count += 1,
+                Event::TaskListMarker(checked) => {
+                    stats.task_count += 1;
+                    stats.completed_task_count += usize::from(*checked);
+                }
+                _ => {}
+            }
+        }
+
+        let plain_text = plain_text(events);
+        stats.word_count = self.count_words(&plain_text);
+        stats.char_count = plain_text.chars().filter(|c| *c != '\n').count();
+        stats.char_count_no_spaces = plain_text.chars().filter(|c| !c.is_whitespace()).count();
+        stats.sentence_count = count_sentences(&plain_text);
+        stats.reading_time = (stats.word_count / 200).max(1) as u32; // Average reading speed: 200 WPM
+
+        Ok(stats)
+    }
+
+    /// Task list items in a document, as (total, completed)
+    pub fn task_counts(&self, markdown: &str) -> (usize, usize) {
+        Parser::new_ext(markdown, self.options)
+            .filter_map(|event| match event {
+                Event::TaskListMarker(checked) => Some(checked),
+                _ => None,
+            })
+            .fold((0, 0), |(total, completed), checked| (total + 1, completed + usize::from(checked)))
+    }
+
+    /// Render markdown to plain text, dropping all formatting syntax
+    pub fn render_plain_text(&self, markdown: &str) -> String {
+        plain_text(Parser::new_ext(markdown, self.options))
+    }
+
     /// Count words in markdown text
+    ///
+    /// Bidi control marks separate words like spaces do, and runs without a
+    /// letter or digit (list bullets, a lone dash) aren't words.
     fn count_words(&self, text: &str) -> usize {
-        text.split_whitespace().count()
+        text.split(|c: char| c.is_whitespace() || is_bidi_control(c))
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count()
+    }
+}
+
+/// Marks the start of a top-level block in the event stream until the HTML is written
+const SOURCEPOS_MARKER: char = '\u{E000}';
+
+/// Maps byte offsets to 1-based line numbers
+struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self { line_starts }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    /// First and last line of a source range, ignoring trailing newlines
+    fn line_range(&self, text: &str, range: &Range<usize>) -> (usize, usize) {
+        let len = text[range.clone()].trim_end().len();
+        (self.line_of(range.start), self.line_of(range.start + len.saturating_sub(1)))
+    }
+}
+
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn check_size(markdown: &str, limits: &ParserLimits) -> Result<()> {
+    if markdown.len() > limits.max_input_bytes {
+        anyhow::bail!(
+            "Document is too large to render ({:.1} MB, the limit is {:.1} MB)",
+            megabytes(markdown.len()),
+            megabytes(limits.max_input_bytes)
+        );
+    }
+    Ok(())
+}
+
+fn check_deadline(started: Instant, limits: &ParserLimits) -> Result<()> {
+    if started.elapsed() > limits.timeout {
+        anyhow::bail!("Rendering took longer than {} seconds and was stopped", limits.timeout.as_secs_f64());
+    }
+    Ok(())
+}
+
+/// Markdown as plain text, with all formatting syntax dropped
+fn plain_text<'a>(events: impl IntoIterator<Item = Event<'a>>) -> String {
+    let mut output = String::new();
+
+    for event in events {
+        match event {
+            Event::Text(text) | Event::Code(text) => output.push_str(&text),
+            Event::SoftBreak => output.push(' '),
+            Event::HardBreak => output.push('\n'),
+            Event::TaskListMarker(checked) => output.push_str(if checked { "[x] " } else { "[ ] " }),
+            Event::End(Tag::TableCell) => output.push('\t'),
+            Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::Item | Tag::CodeBlock(_) | Tag::TableRow | Tag::TableHead) => {
+                if output.ends_with('\t') {
+                    output.pop();
+                }
+                if !output.ends_with('\n') {
+                    output.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    output.trim_end().to_string()
+}
+
+/// Find the top-level blocks of a document as (event index, source range) pairs
+fn top_level_blocks(events: &[(Event, Range<usize>)]) -> Vec<(usize, Range<usize>)> {
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+
+    for (i, (event, range)) in events.iter().enumerate() {
+        match event {
+            Event::Start(_) => {
+                if depth == 0 {
+                    blocks.push((i, range.clone()));
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            Event::Rule if depth == 0 => blocks.push((i, range.clone())),
+            _ => {}
+        }
+    }
+
+    blocks
+}
+
+fn block_kind(tag: &Tag) -> &'static str {
+    match tag {
+        Tag::Paragraph => "paragraph",
+        Tag::Heading(..) => "heading",
+        Tag::BlockQuote => "blockquote",
+        Tag::CodeBlock(_) => "code",
+        Tag::List(_) => "list",
+        Tag::FootnoteDefinition(_) => "footnote",
+        Tag::Table(_) => "table",
+        _ => "other",
+    }
+}
+
+/// Insert a marker before each top-level block carrying its source line range,
+/// and its direction when that differs from the document's, as in `3-7|rtl`
+fn insert_sourcepos_markers<'a>(markdown: &str, events: Vec<Event<'a>>, blocks: &[(usize, Range<usize>)], direction: TextDirection) -> Vec<Event<'a>> {
+    let lines = LineIndex::new(markdown);
+    let ends = blocks.iter().skip(1).map(|(index, _)| *index).chain(std::iter::once(events.len()));
+    let directions: Vec<_> = blocks.iter().zip(ends)
+        .map(|((start, _), end)| block_direction(&events[*start..end]).filter(|block| *block != direction))
+        .collect();
+    let mut blocks = blocks.iter().zip(directions).peekable();
+    let mut marked = Vec::with_capacity(events.len() + blocks.len());
+
+    for (i, event) in events.into_iter().enumerate() {
+        if let Some(((_, range), block_direction)) = blocks.next_if(|((index, _), _)| *index == i) {
+            let (start_line, end_line) = lines.line_range(markdown, range);
+            let marker = match block_direction {
+                Some(dir) => format!("{0}{1}-{2}|{3}{0}\n", SOURCEPOS_MARKER, start_line, end_line, dir.as_str()),
+                None => format!("{0}{1}-{2}{0}\n", SOURCEPOS_MARKER, start_line, end_line),
+            };
+            marked.push(Event::Html(marker.into()));
+        }
+        marked.push(event);
+    }
+
+    marked
+}
+
+/// Direction of a block's first strongly directional text; code doesn't count
+fn block_direction(events: &[Event]) -> Option<TextDirection> {
+    let mut in_code = false;
+    events.iter().find_map(|event| match event {
+        Event::Start(Tag::CodeBlock(_)) => {
+            in_code = true;
+            None
+        }
+        Event::End(Tag::CodeBlock(_)) => {
+            in_code = false;
+            None
+        }
+        Event::Text(text) if !in_code => first_strong_direction(text),
+        _ => None,
+    })
+}
+
+/// Add attributes (with a leading space) to the opening tag `html` starts with
+///
+/// Returns how much of `html` was consumed, which is nothing when it doesn't
+/// start with a tag.
+fn push_with_attributes(output: &mut String, html: &str, attributes: &str) -> usize {
+    if html.starts_with('<') && html[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        let name_end = html.find([' ', '>', '/']).unwrap_or(html.len());
+        output.push_str(&html[..name_end]);
+        output.push_str(attributes);
+        name_end
+    } else {
+        0
+    }
+}
+
+/// Replace block markers with `data-sourcepos` attributes on the element that follows them
+fn apply_sourcepos_markers(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(SOURCEPOS_MARKER) {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + SOURCEPOS_MARKER.len_utf8()..];
+        let Some(end) = after.find(SOURCEPOS_MARKER) else {
+            rest = after;
+            break;
+        };
+
+        let (lines, direction) = split_marker(&after[..end]);
+        rest = after[end + SOURCEPOS_MARKER.len_utf8()..].strip_prefix('\n').unwrap_or("");
+
+        // Attach to the block's opening tag, e.g. `<p>` or `<h1 id="...">`
+        let mut attributes = format!(" data-sourcepos=\"{}\"", lines);
+        if let Some(direction) = direction {
+            attributes.push_str(&format!(" dir=\"{}\"", direction));
+        }
+        rest = &rest[push_with_attributes(&mut output, rest, &attributes)..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Cut marked HTML into one piece per top-level block
+fn split_blocks(html: &str) -> Vec<HtmlBlock> {
+    // Markers come in pairs around the line range: [leading, lines, block, lines, block, ...]
+    let mut parts = html.split(SOURCEPOS_MARKER);
+    let leading = parts.next().unwrap_or_default();
+    let mut blocks = Vec::new();
+
+    while let (Some(marker), Some(block)) = (parts.next(), parts.next()) {
+        let (sourcepos, direction) = split_marker(marker);
+        let block = block.strip_prefix('\n').unwrap_or(block);
+        let html = match direction {
+            Some(direction) => {
+                let mut html = String::with_capacity(block.len() + 10);
+                let consumed = push_with_attributes(&mut html, block, &format!(" dir=\"{}\"", direction));
+                html.push_str(&block[consumed..]);
+                html
+            }
+            None => block.to_string(),
+        };
+        blocks.push(HtmlBlock { sourcepos: sourcepos.to_string(), html });
+    }
+    if let Some(first) = blocks.first_mut() {
+        first.html.insert_str(0, leading);
+    }
+
+    blocks
+}
+
+/// The file an image `src` points at, for relative and absolute paths; `None`
+/// for URLs
+fn local_image_path(src: &str, base_dir: Option<&Path>) -> Option<PathBuf> {
+    if src.is_empty() || src.contains("://") || src.starts_with("//") || src.starts_with("data:") {
+        return None;
+    }
+    let src = src.split(['?', '#']).next().unwrap_or(src);
+    let decoded = percent_decode_str(src).decode_utf8().ok()?;
+    let path = Path::new(decoded.as_ref());
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        base_dir.map(|dir| dir.join(path))
     }
 }
 
+/// Wrap each merge conflict's sides in labelled blocks, which together become one
+/// top-level block spanning the conflict's lines
+fn render_conflicts<'a>(events: Vec<Event<'a>>, conflicts: &[MergeConflict]) -> Vec<Event<'a>> {
+    if conflicts.is_empty() {
+        return events;
+    }
+
+    let side = |class: &str, label: &str| format!(
+        "<div class=\"conflict-side conflict-{}\"><div class=\"conflict-label\">{}</div>\n",
+        class, html_escape::encode_text(label),
+    );
+    let mut output = Vec::with_capacity(events.len());
+    let mut inside = false;
+    for event in events {
+        if let Event::Html(html) = &event {
+            if let Some((marker, conflict)) = parse_conflict_comment(html).and_then(|(marker, index)| Some((marker, conflicts.get(index)?))) {
+                let html = match marker {
+                    ConflictMarker::Open => {
+                        inside = true;
+                        format!(
+                            "{0}{1}-{2}{0}\n<div class=\"conflict\" data-conflict=\"{3}\">\n{4}",
+                            SOURCEPOS_MARKER, conflict.start_line, conflict.end_line, conflict.index, side("ours", &conflict.ours_label),
+                        )
+                    }
+                    ConflictMarker::Base => format!("</div>\n{}", side("base", "base")),
+                    ConflictMarker::Separator => format!("</div>\n{}", side("theirs", &conflict.theirs_label)),
+                    ConflictMarker::Close => {
+                        inside = false;
+                        "</div>\n</div>\n".to_string()
+                    }
+                };
+                output.push(Event::Html(html.into()));
+                continue;
+            }
+            // The blocks inside belong to the conflict's block
+            if inside && html.starts_with(SOURCEPOS_MARKER) {
+                continue;
+            }
+        }
+        output.push(event);
+    }
+    output
+}
+
+/// Replace footnote references and definitions with labelled, cross-linked HTML
+///
+/// With [`FootnotePlacement::End`] the definitions move into one footnote section
+/// after the last block, which takes the line range spanning their sources.
+fn render_footnotes<'a>(events: Vec<Event<'a>>, settings: &FootnoteSettings) -> Vec<Event<'a>> {
+    // Numbered in order of first reference; unreferenced definitions come last
+    let mut numbers: HashMap<String, usize> = HashMap::new();
+    let labels = events.iter()
+        .filter_map(|event| match event {
+            Event::FootnoteReference(label) => Some(label),
+            _ => None,
+        })
+        .chain(events.iter().filter_map(|event| match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => Some(label),
+            _ => None,
+        }));
+    for label in labels {
+        let next = numbers.len() + 1;
+        numbers.entry(label.to_string()).or_insert(next);
+    }
+    if numbers.is_empty() {
+        return events;
+    }
+
+    let at_end = settings.placement == FootnotePlacement::End;
+    let mut output = Vec::with_capacity(events.len());
+    let mut definitions: Vec<(usize, Vec<Event>)> = Vec::new();
+    let mut definition: Option<(usize, Vec<Event>)> = None;
+    let mut source_lines: Option<(usize, usize)> = None;
+    let mut referenced = HashSet::new();
+
+    for event in events {
+        let rendered = match event {
+            Event::FootnoteReference(label) => {
+                let id = footnote_id(&label);
+                // Only the first reference is the target of the back link
+                let anchor = if referenced.insert(id.clone()) { format!(" id=\"fnref-{}\"", id) } else { String::new() };
+                let marker = footnote_marker(numbers[label.as_ref()], settings.numbering);
+                Event::Html(format!("<sup class=\"footnote-reference\"><a href=\"#fn-{}\"{}>{}</a></sup>", id, anchor, marker).into())
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let number = numbers[label.as_ref()];
+                if at_end {
+                    let lines = match output.last() {
+                        Some(Event::Html(html)) => sourcepos_marker_lines(html),
+                        _ => None,
+                    };
+                    if let Some((start, end)) = lines {
+                        output.pop();
+                        source_lines = Some(source_lines.map_or((start, end), |(first, last)| (first.min(start), last.max(end))));
+                    }
+                    definition = Some((number, Vec::new()));
+                }
+                Event::Html(format!(
+                    "<div class=\"footnote-definition\" id=\"fn-{}\"><sup class=\"footnote-definition-label\">{}</sup>\n",
+                    footnote_id(&label),
+                    footnote_marker(number, settings.numbering)
+                ).into())
+            }
+            Event::End(Tag::FootnoteDefinition(label)) => {
+                let id = footnote_id(&label);
+                let back_link = if referenced.contains(&id) {
+                    format!("<a href=\"#fnref-{}\" class=\"footnote-backref\" aria-label=\"Back to reference\">↩</a>", id)
+                } else {
+                    String::new()
+                };
+                let close = Event::Html(format!("{}</div>\n", back_link).into());
+                match definition.take() {
+                    Some((number, mut body)) => {
+                        body.push(close);
+                        definitions.push((number, body));
+                        continue;
+                    }
+                    None => close,
+                }
+            }
+            other => other,
+        };
+
+        match &mut definition {
+            Some((_, body)) => body.push(rendered),
+            None => output.push(rendered),
+        }
+    }
+
+    if !definitions.is_empty() {
+        definitions.sort_by_key(|(number, _)| *number);
+        if let Some((start, end)) = source_lines {
+            output.push(Event::Html(format!("{0}{1}-{2}{0}\n", SOURCEPOS_MARKER, start, end).into()));
+        }
+
+        let mut open = String::from("<section class=\"footnotes\">\n");
+        if settings.separator {
+            open.push_str("<hr class=\"footnotes-separator\">\n");
+        }
+        if let Some(title) = settings.title.as_deref().filter(|title| !title.trim().is_empty()) {
+            open.push_str(&format!("<h2 class=\"footnotes-title\">{}</h2>\n", html_escape::encode_text(title)));
+        }
+        output.push(Event::Html(open.into()));
+        output.extend(definitions.into_iter().flat_map(|(_, body)| body));
+        output.push(Event::Html("</section>\n".into()));
+    }
+
+    output
+}
+
+/// A marker's line range and direction, e.g. `("3-7", Some("rtl"))` for `3-7|rtl`
+fn split_marker(marker: &str) -> (&str, Option<&str>) {
+    match marker.split_once('|') {
+        Some((lines, direction)) => (lines, Some(direction)),
+        None => (marker, None),
+    }
+}
+
+/// The line range of a block marker from `insert_sourcepos_markers`
+fn sourcepos_marker_lines(html: &str) -> Option<(usize, usize)> {
+    let marker = html.strip_prefix(SOURCEPOS_MARKER)?.strip_suffix('\n')?.strip_suffix(SOURCEPOS_MARKER)?;
+    let (start, end) = split_marker(marker).0.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+/// Element id suffix for a footnote label, e.g. `fn-my-note` for `[^my note]`
+fn footnote_id(label: &str) -> String {
+    label.chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '-' })
+        .collect()
+}
+
+/// The visible label of the `number`th footnote (1-based)
+fn footnote_marker(number: usize, numbering: FootnoteNumbering) -> String {
+    const SYMBOLS: [&str; 6] = ["*", "†", "‡", "§", "‖", "¶"];
+
+    match numbering {
+        FootnoteNumbering::Numeric => number.to_string(),
+        FootnoteNumbering::LowerAlpha => alphabetic(number),
+        FootnoteNumbering::UpperAlpha => alphabetic(number).to_uppercase(),
+        FootnoteNumbering::LowerRoman => roman(number).to_lowercase(),
+        FootnoteNumbering::UpperRoman => roman(number),
+        FootnoteNumbering::Symbols => SYMBOLS[(number - 1) % SYMBOLS.len()].repeat((number - 1) / SYMBOLS.len() + 1),
+    }
+}
+
+/// a, b, ..., z, aa, ab, ...
+fn alphabetic(mut number: usize) -> String {
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push((b'a' + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+
+    let mut output = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            output.push_str(numeral);
+            number -= value;
+        }
+    }
+    output
+}
+
+/// Count sentences as runs of text ending in terminal punctuation (or the end of a line)
+fn count_sentences(text: &str) -> usize {
+    text.lines()
+        .flat_map(|line| line.split_inclusive(['.', '!', '?']))
+        .filter(|sentence| sentence.chars().any(char::is_alphanumeric))
+        .count()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -230,6 +1207,42 @@ mod tests {
         assert_eq!(result.toc[0].title, "Hello World");
     }
 
+    #[test]
+    fn test_limits_reject_hostile_input() {
+        let parser = MarkdownParser::new().with_limits(ParserLimits {
+            max_input_bytes: 1024,
+            max_nesting_depth: 16,
+            ..ParserLimits::default()
+        });
+
+        let nested = format!("{} deep", ">".repeat(40));
+        let error = parser.parse(&nested).unwrap_err().to_string();
+        assert!(error.contains("nested too deeply"), "{}", error);
+        assert!(parser.parse(&format!("{} fine", ">".repeat(8))).is_ok());
+
+        let error = parser.parse(&"word ".repeat(1000)).unwrap_err().to_string();
+        assert!(error.contains("too large"), "{}", error);
+
+        // The editor's other views parse the same document and must stop on it too
+        assert!(parser.outline(&nested).is_err());
+        assert!(parser.folding_ranges(&nested).is_err());
+        assert!(parser.scroll_map(&nested).is_err());
+        assert!(parser.document_stats(&nested).is_err());
+        assert!(parser.check_limits(&nested).is_err());
+        assert!(parser.heading_anchor(&nested, "deep").is_err());
+    }
+
+    #[test]
+    fn test_limits_timeout() {
+        let parser = MarkdownParser::new();
+        parser.set_limits(ParserLimits { timeout: Duration::ZERO, ..ParserLimits::default() });
+        let table = "| a | b |\n|---|---|\n".to_string() + &"| 1 | 2 |\n".repeat(5000);
+
+        let error = parser.parse(&table).unwrap_err().to_string();
+        assert!(error.contains("took longer"), "{}", error);
+        assert_eq!(parser.limits().max_nesting_depth, 256);
+    }
+
     #[test]
     fn test_toc_generation() {
         let parser = MarkdownParser::new();
@@ -244,6 +1257,146 @@ mod tests {
         assert_eq!(result.toc[3].level, 2);
     }
 
+    #[test]
+    fn test_toc_with_repeated_headings() {
+        let parser = MarkdownParser::new();
+        let markdown = "## Notes\n\nOne\n\n## Notes\n\n## Summary\n\nTwo\n\n## Notes\n";
+
+        let result = parser.parse(markdown).unwrap();
+        let toc: Vec<_> = result.toc.iter().map(|item| (item.title.as_str(), item.anchor.as_str(), item.line)).collect();
+
+        assert_eq!(toc, vec![
+            ("Notes", "notes", 1),
+            ("Notes", "notes-2", 5),
+            ("Summary", "summary", 7),
+            ("Notes", "notes-3", 11),
+        ]);
+        assert!(result.html.contains("id=\"notes-3\">Notes</h2>"));
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let parser = MarkdownParser::new();
+        let markdown = "# One\n\nIntro\n\n## Two\n\n- a\n- b\n  - c\n\n```rust\nfn main() {}\n```\n\n# Three\nlast\n\n";
+        let ranges: Vec<_> = parser.folding_ranges(markdown).unwrap().iter()
+            .map(|range| (range.kind, range.start_line, range.end_line))
+            .collect();
+
+        assert_eq!(ranges, vec![
+            (FoldKind::Section, 1, 13),
+            (FoldKind::Section, 5, 13),
+            (FoldKind::List, 7, 9),
+            (FoldKind::CodeBlock, 11, 13),
+            (FoldKind::Section, 15, 16),
+        ]);
+        let last = parser.folding_ranges(markdown).unwrap().pop().unwrap();
+        assert_eq!(&markdown[last.start..last.end], "# Three\nlast");
+        // The nested list is on one line, so there's nothing to fold
+        assert!(parser.folding_ranges("- a\n  - b\n").unwrap().iter().all(|range| range.start == 0));
+    }
+
+    #[test]
+    fn test_outline_source_ranges() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Intro\n\nText\n\n## Setup `cli`\n\nMore\n\nIntro\n=====\n";
+
+        let outline = parser.outline(markdown).unwrap();
+
+        assert_eq!(outline.len(), 3);
+        assert_eq!(outline[0].id, "intro");
+        assert_eq!(&markdown[outline[0].start..outline[0].end], "# Intro");
+        assert_eq!((outline[0].start_line, outline[0].end_line), (1, 1));
+        assert_eq!(outline[1].title, "Setup cli");
+        assert_eq!(outline[1].start_line, 5);
+        assert_eq!(outline[2].id, "intro-2");
+        assert_eq!((outline[2].start_line, outline[2].end_line), (9, 10));
+
+        // Rendered element ids match the outline, also for headings that don't start with text
+        let html = parser.parse(markdown).unwrap().html;
+        assert!(html.contains("id=\"setup-cli\">"));
+        assert!(html.contains("id=\"intro-2\">"));
+
+        let markdown = "# *Big* news\n\n## `cli` flags\n\n#\n\n# Big news\n";
+        let ids: Vec<_> = parser.outline(markdown).unwrap().into_iter().map(|item| item.id).collect();
+        assert_eq!(ids, vec!["big-news", "cli-flags", "", "big-news-2"]);
+        let parsed = parser.parse(markdown).unwrap();
+        let anchors: Vec<_> = parsed.toc.iter().map(|item| item.anchor.as_str()).collect();
+        assert_eq!(anchors, vec!["big-news", "cli-flags", "big-news-2"]);
+        for id in ["big-news", "cli-flags", "big-news-2"] {
+            assert!(parsed.html.contains(&format!("id=\"{}\">", id)), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_conflict_blocks() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Plan\n\n<<<<<<< HEAD\nShip in **May**.\n=======\nShip in June.\n>>>>>>> feature/dates\n\nDone.\n";
+
+        let result = parser.parse(markdown).unwrap();
+        assert_eq!(result.conflicts.len(), 1);
+        assert!(!result.html.contains("<h1 id=\"ship"));
+        assert!(result.html.contains("<div data-sourcepos=\"3-7\" class=\"conflict\" data-conflict=\"0\">\n<div class=\"conflict-side conflict-ours\"><div class=\"conflict-label\">HEAD</div>\n<p>Ship in <strong>May</strong>.</p>\n</div>"));
+        assert!(result.html.contains("<div class=\"conflict-label\">feature/dates</div>\n<p>Ship in June.</p>\n</div>\n</div>"));
+        assert!(result.html.contains("<p data-sourcepos=\"9-9\">Done.</p>"));
+
+        let sourcepos: Vec<_> = result.blocks.iter().map(|block| block.sourcepos.as_str()).collect();
+        assert_eq!(sourcepos, vec!["1-1", "3-7", "9-9"]);
+    }
+
+    #[test]
+    fn test_rtl_blocks_and_word_count() {
+        let parser = MarkdownParser::new();
+        let markdown = "# مرحبا بالعالم\n\nهذه فقرة \u{200F}- قصيرة.\n\nAn English note.\n\n```\nlet x = 1;\n```\n";
+
+        let result = parser.parse(markdown).unwrap();
+        assert_eq!(result.direction, TextDirection::Rtl);
+        assert_eq!(result.word_count, 11);
+        assert!(result.html.contains("<p data-sourcepos=\"3-3\">هذه"));
+        assert!(result.html.contains("<p data-sourcepos=\"5-5\" dir=\"ltr\">An English note.</p>"));
+        assert!(result.html.contains("data-sourcepos=\"7-9\"") && !result.html.contains("data-sourcepos=\"7-9\" dir"));
+
+        assert_eq!(result.blocks[2].sourcepos, "5-5");
+        assert!(result.blocks[2].html.starts_with("<p dir=\"ltr\">An English"));
+        assert!(result.blocks[1].html.starts_with("<p>"));
+    }
+
+    #[test]
+    fn test_heading_anchor() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Intro\n\n## Größe & Form\n\n# Intro\n";
+
+        assert_eq!(parser.heading_anchor(markdown, "Intro").unwrap().as_deref(), Some("intro"));
+        assert_eq!(parser.heading_anchor(markdown, "## größe & form").unwrap().as_deref(), Some("größe---form"));
+        assert_eq!(parser.heading_anchor(markdown, "#intro-2").unwrap().as_deref(), Some("intro-2"));
+        assert_eq!(parser.heading_anchor(markdown, "Missing").unwrap(), None);
+
+        // Headings starting with formatting are linked to the id they're rendered with
+        let markdown = "# Intro\n\n## *Next* steps\n";
+        assert_eq!(parser.heading_anchor(markdown, "Next steps").unwrap().as_deref(), Some("next-steps"));
+        assert!(parser.parse(markdown).unwrap().html.contains("<h2 data-sourcepos=\"3-3\" id=\"next-steps\">"));
+        assert_eq!(parser.heading_anchor("#\n", "").unwrap(), None);
+    }
+
+    #[test]
+    fn test_scroll_map_matches_sourcepos() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Title\n\nFirst line\nsecond line\n\n---\n\n- a\n- b\n";
+
+        let blocks = parser.scroll_map(markdown).unwrap();
+        let kinds: Vec<_> = blocks.iter().map(|b| b.kind.as_str()).collect();
+
+        assert_eq!(kinds, vec!["heading", "paragraph", "rule", "list"]);
+        assert_eq!((blocks[1].start_line, blocks[1].end_line), (3, 4));
+        assert_eq!((blocks[3].start_line, blocks[3].end_line), (8, 9));
+
+        let html = parser.parse(markdown).unwrap().html;
+        assert!(html.contains("<h1 data-sourcepos=\"1-1\" id=\"title\">"));
+        assert!(html.contains("<p data-sourcepos=\"3-4\">"));
+        assert!(html.contains("<hr data-sourcepos=\"6-6\" />"));
+        assert!(html.contains("<ul data-sourcepos=\"8-9\">"));
+        assert!(!html.contains(SOURCEPOS_MARKER));
+    }
+
     #[test]
     fn test_math_processing() {
         let parser = MarkdownParser::new();
@@ -254,4 +1407,95 @@ mod tests {
         assert!(result.contains("katex-inline"));
         assert!(result.contains("x^2 + y^2 = z^2"));
     }
+
+    #[test]
+    fn test_qrcode_fence() {
+        let parser = MarkdownParser::new();
+        let markdown = "Scan:\n\n```qrcode\nhttps://example.com/talk\n```\n\nAfter.\n";
+
+        let html = parser.parse(markdown).unwrap().html;
+        assert!(html.contains("<div data-sourcepos=\"3-5\" class=\"qrcode\"><svg role=\"img\" aria-label=\"QR code: https://example.com/talk\""));
+        assert!(html.contains("<p data-sourcepos=\"7-7\">After.</p>"));
+        assert!(!html.contains("<code"));
+    }
+
+    #[test]
+    fn test_document_stats() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Title\n\nFirst sentence. Second one!\n\nSee [docs](https://example.com) and ![logo](logo.png).";
+
+        let stats = parser.document_stats(markdown).unwrap();
+
+        assert_eq!(stats.heading_count, 1);
+        assert_eq!(stats.paragraph_count, 2);
+        assert_eq!(stats.link_count, 1);
+        assert_eq!(stats.image_count, 1);
+        assert_eq!(stats.word_count, 9);
+        assert_eq!(stats.sentence_count, 4);
+        assert_eq!(stats.file_size, markdown.len() as u64);
+    }
+
+    #[test]
+    fn test_footnotes_collected_at_end() {
+        let parser = MarkdownParser::new();
+        let markdown = "Text[^a] and[^b] again[^a].\n\n[^b]: Bee.\n\n[^a]: Ay.\n\nAfter.";
+
+        let result = parser.parse(markdown).unwrap();
+        assert!(result.html.contains("<a href=\"#fn-a\" id=\"fnref-a\">1</a>"));
+        assert!(result.html.contains("<a href=\"#fn-b\" id=\"fnref-b\">2</a>"));
+        assert!(result.html.contains("again<sup class=\"footnote-reference\"><a href=\"#fn-a\">1</a></sup>"));
+        let section = result.html.find("<section data-sourcepos=\"3-5\" class=\"footnotes\">\n<hr class=\"footnotes-separator\">\n<h2 class=\"footnotes-title\">Notes</h2>").unwrap();
+        assert!(result.html.find("After.").unwrap() < section);
+        assert!(result.html.find("id=\"fn-a\"").unwrap() < result.html.find("id=\"fn-b\"").unwrap());
+        assert!(result.html.contains("<a href=\"#fnref-b\" class=\"footnote-backref\""));
+        assert_eq!(result.blocks.len(), 3);
+        assert!(result.blocks[2].html.ends_with("</section>\n"));
+    }
+
+    #[test]
+    fn test_footnotes_inline_and_numbering() {
+        let parser = MarkdownParser::new().with_footnotes(FootnoteSettings {
+            numbering: FootnoteNumbering::LowerRoman,
+            placement: FootnotePlacement::Inline,
+            ..FootnoteSettings::default()
+        });
+        let markdown = "One[^x] two[^y].\n\n[^y]: Why.\n\n[^x]: Ex.";
+
+        let html = parser.parse(markdown).unwrap().html;
+
+        assert!(html.contains("<a href=\"#fn-y\" id=\"fnref-y\">ii</a>"));
+        assert!(html.contains("<div data-sourcepos=\"3-3\" class=\"footnote-definition\" id=\"fn-y\"><sup class=\"footnote-definition-label\">ii</sup>"));
+        assert!(!html.contains("<section"));
+
+        assert_eq!(footnote_marker(28, FootnoteNumbering::UpperAlpha), "AB");
+        assert_eq!(footnote_marker(1994, FootnoteNumbering::UpperRoman), "MCMXCIV");
+        assert_eq!(footnote_marker(8, FootnoteNumbering::Symbols), "††");
+    }
+
+    #[test]
+    fn test_preview_images_lazy_and_sized() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("img")).unwrap();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 2, 128, 0, 0, 1, 224]);
+        std::fs::write(dir.path().join("img/logo v2.png"), png).unwrap();
+        let parser = MarkdownParser::new();
+        let markdown = "![The *logo*](img/logo%20v2.png \"Brand\") ![remote](https://example.com/a.png)";
+
+        let html = parser.parse_preview(markdown, Some(dir.path())).unwrap().html;
+
+        assert!(html.contains("<img src=\"img/logo%20v2.png\" alt=\"The logo\" title=\"Brand\" loading=\"lazy\" width=\"640\" height=\"480\" />"));
+        assert!(html.contains("<img src=\"https://example.com/a.png\" alt=\"remote\" loading=\"lazy\" />"));
+        assert!(!parser.parse(markdown).unwrap().html.contains("loading="));
+    }
+
+    #[test]
+    fn test_render_plain_text() {
+        let parser = MarkdownParser::new();
+        let markdown = "# Title\n\nSome **bold** and `code`\nwrapped.\n\n- one\n- two";
+
+        let result = parser.render_plain_text(markdown);
+
+        assert_eq!(result, "Title\nSome bold and code wrapped.\none\ntwo");
+    }
 }
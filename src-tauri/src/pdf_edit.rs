@@ -0,0 +1,440 @@
+use anyhow::{Result, Context};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// A PDF read just far enough to append an incremental update to it: the
+/// trailer, and the objects an update rewrites, found by their `N 0 obj` headers
+///
+/// The original bytes are never changed, so anything that signed or indexed
+/// them stays valid.
+pub struct PdfFile {
+    data: Vec<u8>,
+    startxref: usize,
+    trailer: Vec<u8>,
+}
+
+impl PdfFile {
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        if !data.starts_with(b"%PDF-") {
+            anyhow::bail!("Not a PDF file");
+        }
+        let tail = data.len().saturating_sub(4096);
+        let keyword = rfind(&data[tail..], b"startxref").map(|at| tail + at)
+            .context("PDF has no startxref")?;
+        let digits = skip_whitespace(&data, keyword + b"startxref".len());
+        let startxref: usize = std::str::from_utf8(&data[digits..token_end(&data, digits)])
+            .ok()
+            .and_then(|offset| offset.parse().ok())
+            .context("PDF has an invalid startxref")?;
+
+        let section = data.get(startxref..).unwrap_or_default();
+        let trailer = if section.starts_with(b"xref") {
+            find(section, b"trailer").map(|at| (section, at + b"trailer".len()))
+        } else if section.first().is_some_and(u8::is_ascii_digit) {
+            // A cross-reference stream keeps the trailer entries in its own dictionary
+            find(section, b"obj").map(|at| (section, at + b"obj".len()))
+        } else {
+            // A stale startxref; readers recover from these, so take the last trailer
+            rfind(&data, b"trailer").map(|at| (&data[..], at + b"trailer".len()))
+        };
+        let trailer = trailer
+            .and_then(|(section, at)| Some(section[dict_span(section, at)?].to_vec()))
+            .context("PDF trailer is missing or malformed")?;
+
+        if dict_value(&trailer, "Encrypt").is_some() {
+            anyhow::bail!("Encrypted PDFs can't be modified");
+        }
+        Ok(Self { data, startxref, trailer })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    /// Object number of the document catalog
+    pub fn root(&self) -> Result<u32> {
+        dict_value(&self.trailer, "Root").and_then(parse_ref).context("PDF trailer has no /Root")
+    }
+
+    /// Dictionary of object `number` as last defined in the file, `<<` to `>>`
+    pub fn object_dict(&self, number: u32) -> Result<Vec<u8>> {
+        let header = format!("{} 0 obj", number);
+        let mut end = self.data.len();
+        while let Some(at) = rfind(&self.data[..end], header.as_bytes()) {
+            // Don't take `11 0 obj` for `1 0 obj`
+            if at == 0 || is_delimiter(self.data[at - 1]) {
+                return dict_span(&self.data, at + header.len())
+                    .map(|span| self.data[span].to_vec())
+                    .with_context(|| format!("PDF object {} is not a dictionary", number));
+            }
+            end = at;
+        }
+        anyhow::bail!("PDF object {} not found (compressed object streams aren't supported)", number)
+    }
+
+    /// The first page, found by following `/Kids` down the catalog's page tree
+    pub fn first_page(&self) -> Result<u32> {
+        let catalog = self.object_dict(self.root()?)?;
+        let mut node = dict_value(&catalog, "Pages").and_then(parse_ref).context("PDF has no page tree")?;
+        for _ in 0..64 {
+            let dict = self.object_dict(node)?;
+            match dict_value(&dict, "Kids") {
+                Some(kids) => {
+                    let start = skip_whitespace(kids, 1);
+                    node = object_end(kids, start)
+                        .and_then(|end| parse_ref(&kids[start..end]))
+                        .context("PDF page tree has no pages")?;
+                }
+                None => return Ok(node),
+            }
+        }
+        anyhow::bail!("PDF page tree is too deep")
+    }
+
+    /// Object numbers of every page in order, from a walk of the catalog's page tree
+    pub fn pages(&self) -> Result<Vec<u32>> {
+        let catalog = self.object_dict(self.root()?)?;
+        let root = dict_value(&catalog, "Pages").and_then(parse_ref).context("PDF has no page tree")?;
+        let mut pages = Vec::new();
+        let mut pending = vec![(root, 0)];
+        while let Some((node, depth)) = pending.pop() {
+            if depth > 64 {
+                anyhow::bail!("PDF page tree is too deep");
+            }
+            let dict = self.object_dict(node)?;
+            match dict_value(&dict, "Kids") {
+                // Reversed, so the first kid is walked first
+                Some(kids) => pending.extend(array_refs(kids).into_iter().rev().map(|kid| (kid, depth + 1))),
+                None => pages.push(node),
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Number of pages, from the `/Count` of the catalog's page tree
+    pub fn page_count(&self) -> Result<usize> {
+        let catalog = self.object_dict(self.root()?)?;
+        let pages = dict_value(&catalog, "Pages").and_then(parse_ref).context("PDF has no page tree")?;
+        dict_value(&self.object_dict(pages)?, "Count")
+            .and_then(|count| std::str::from_utf8(count).ok()?.parse().ok())
+            .context("PDF page tree has no page count")
+    }
+
+    pub fn update(&self) -> Result<IncrementalUpdate<'_>> {
+        let size = dict_value(&self.trailer, "Size")
+            .and_then(|size| std::str::from_utf8(size).ok()?.parse().ok())
+            .context("PDF trailer has no /Size")?;
+        Ok(IncrementalUpdate { file: self, next: size, objects: BTreeMap::new(), info: None })
+    }
+}
+
+/// New and replaced objects to append to a [`PdfFile`]
+pub struct IncrementalUpdate<'a> {
+    file: &'a PdfFile,
+    next: u32,
+    objects: BTreeMap<u32, Vec<u8>>,
+    info: Option<u32>,
+}
+
+impl IncrementalUpdate<'_> {
+    /// Number for a new object, whose body is set later with `set_object`
+    pub fn reserve(&mut self) -> u32 {
+        self.next += 1;
+        self.next - 1
+    }
+
+    pub fn add_object(&mut self, body: impl Into<Vec<u8>>) -> u32 {
+        let number = self.reserve();
+        self.set_object(number, body);
+        number
+    }
+
+    /// Add or replace object `number`
+    pub fn set_object(&mut self, number: u32, body: impl Into<Vec<u8>>) {
+        self.objects.insert(number, body.into());
+    }
+
+    /// Point the trailer's `/Info` at object `number` instead of the previous one
+    pub fn set_info(&mut self, number: u32) {
+        self.info = Some(number);
+    }
+
+    /// The original file followed by the objects, a cross-reference section for
+    /// them and a trailer chained to the previous one
+    pub fn write(self) -> Vec<u8> {
+        let mut output = self.file.data.clone();
+        if !output.ends_with(b"\n") {
+            output.push(b'\n');
+        }
+
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (number, body) in &self.objects {
+            offsets.push((*number, output.len()));
+            output.extend_from_slice(format!("{} 0 obj\n", number).as_bytes());
+            output.extend_from_slice(body);
+            output.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref = output.len();
+        output.extend_from_slice(b"xref\n0 1\n0000000000 65535 f \n");
+        for (number, offset) in offsets {
+            output.extend_from_slice(format!("{} 1\n{:010} 00000 n \n", number, offset).as_bytes());
+        }
+
+        let trailer = self.file.trailer();
+        output.extend_from_slice(format!("trailer\n<< /Size {} /Root {} 0 R /Prev {}", self.next, self.file.root().unwrap_or(1), self.file.startxref).as_bytes());
+        if let Some(info) = self.info {
+            output.extend_from_slice(format!(" /Info {} 0 R", info).as_bytes());
+        }
+        for key in ["Info", "ID"] {
+            if key == "Info" && self.info.is_some() {
+                continue;
+            }
+            if let Some(value) = dict_value(trailer, key) {
+                output.extend_from_slice(format!(" /{} ", key).as_bytes());
+                output.extend_from_slice(value);
+            }
+        }
+        output.extend_from_slice(format!(" >>\nstartxref\n{}\n%%EOF\n", xref).as_bytes());
+        output
+    }
+}
+
+/// The value of `key` (without the slash) in a `<< ... >>` dictionary
+pub fn dict_value<'a>(dict: &'a [u8], key: &str) -> Option<&'a [u8]> {
+    entry_span(dict, key).map(|span| &dict[span])
+}
+
+/// `dict` with `key` set to `value`, replacing any value it had
+pub fn with_entry(dict: &[u8], key: &str, value: &str) -> Vec<u8> {
+    let mut output = Vec::with_capacity(dict.len() + key.len() + value.len() + 3);
+    match entry_span(dict, key) {
+        Some(span) => {
+            output.extend_from_slice(&dict[..span.start]);
+            output.extend_from_slice(value.as_bytes());
+            output.extend_from_slice(&dict[span.end..]);
+        }
+        None => {
+            let close = dict.len().saturating_sub(2);
+            output.extend_from_slice(trim_end(&dict[..close]));
+            output.extend_from_slice(format!(" /{} {} >>", key, value).as_bytes());
+        }
+    }
+    output
+}
+
+/// A string for a PDF text field such as `/Title`: literal when ASCII, otherwise
+/// UTF-16 with a byte order mark
+pub fn text_string(text: &str) -> String {
+    if text.is_ascii() {
+        let escaped: String = text.chars()
+            .flat_map(|c| match c {
+                '(' | ')' | '\\' => vec!['\\', c],
+                '\n' => vec!['\\', 'n'],
+                '\r' => vec!['\\', 'r'],
+                _ => vec![c],
+            })
+            .collect();
+        format!("({})", escaped)
+    } else {
+        let hex: String = text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect();
+        format!("<FEFF{}>", hex)
+    }
+}
+
+/// Object number of an indirect reference such as `12 0 R`
+pub fn parse_ref(value: &[u8]) -> Option<u32> {
+    let text = std::str::from_utf8(value).ok()?;
+    let mut parts = text.split_whitespace();
+    let number = parts.next()?.parse().ok()?;
+    parts.next()?.parse::<u32>().ok()?;
+    (parts.next()? == "R").then_some(number)
+}
+
+/// Object numbers of the indirect references in an array such as `[3 0 R 4 0 R]`
+pub fn array_refs(array: &[u8]) -> Vec<u32> {
+    let text = String::from_utf8_lossy(array);
+    let tokens: Vec<&str> = text.trim().trim_start_matches('[').trim_end_matches(']').split_whitespace().collect();
+    tokens.windows(3)
+        .filter(|window| window[2] == "R" && window[1].parse::<u32>().is_ok())
+        .filter_map(|window| window[0].parse().ok())
+        .collect()
+}
+
+fn entry_span(dict: &[u8], key: &str) -> Option<Range<usize>> {
+    let mut at = skip_whitespace(dict, 0);
+    if !dict[at..].starts_with(b"<<") {
+        return None;
+    }
+    at += 2;
+    loop {
+        at = skip_whitespace(dict, at);
+        if dict.get(at) != Some(&b'/') {
+            return None;
+        }
+        let name_end = token_end(dict, at + 1);
+        let name = &dict[at + 1..name_end];
+        let value_start = skip_whitespace(dict, name_end);
+        let value_end = object_end(dict, value_start)?;
+        if name == key.as_bytes() {
+            return Some(value_start..value_end);
+        }
+        at = value_end;
+    }
+}
+
+/// Span of the dictionary starting at or after `from`, `<<` to `>>`
+fn dict_span(data: &[u8], from: usize) -> Option<Range<usize>> {
+    let start = skip_whitespace(data, from);
+    if !data[start..].starts_with(b"<<") {
+        return None;
+    }
+    Some(start..object_end(data, start)?)
+}
+
+/// End of the object starting at `at`: a dictionary, array, string, name, or a
+/// number, which takes the `0 R` after it when it starts a reference
+fn object_end(data: &[u8], at: usize) -> Option<usize> {
+    match data.get(at)? {
+        b'<' if data.get(at + 1) == Some(&b'<') => {
+            let mut i = at + 2;
+            loop {
+                i = skip_whitespace(data, i);
+                if data[i..].starts_with(b">>") {
+                    return Some(i + 2);
+                }
+                i = object_end(data, i)?;
+            }
+        }
+        b'<' => data[at..].iter().position(|&b| b == b'>').map(|end| at + end + 1),
+        b'[' => {
+            let mut i = at + 1;
+            loop {
+                i = skip_whitespace(data, i);
+                if *data.get(i)? == b']' {
+                    return Some(i + 1);
+                }
+                i = object_end(data, i)?;
+            }
+        }
+        b'(' => {
+            let (mut depth, mut i) = (0usize, at);
+            loop {
+                match *data.get(i)? {
+                    b'\\' => i += 1,
+                    b'(' => depth += 1,
+                    b')' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+        }
+        b'/' => Some(token_end(data, at + 1)),
+        _ => {
+            let end = token_end(data, at);
+            if end == at {
+                return None;
+            }
+            // `12 0 R` is one value
+            let generation = skip_whitespace(data, end);
+            let generation_end = token_end(data, generation);
+            let r = skip_whitespace(data, generation_end);
+            let is_number = |range: Range<usize>| !range.is_empty() && data[range].iter().all(u8::is_ascii_digit);
+            if is_number(at..end) && is_number(generation..generation_end) && data.get(r) == Some(&b'R')
+                && !data.get(r + 1).is_some_and(|&b| !is_delimiter(b))
+            {
+                return Some(r + 1);
+            }
+            Some(end)
+        }
+    }
+}
+
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b)
+}
+
+fn token_end(data: &[u8], from: usize) -> usize {
+    data[from..].iter().position(|&b| is_delimiter(b)).map_or(data.len(), |end| from + end)
+}
+
+fn skip_whitespace(data: &[u8], from: usize) -> usize {
+    data[from.min(data.len())..].iter().position(|b| !b.is_ascii_whitespace()).map_or(data.len(), |skip| from + skip)
+}
+
+fn trim_end(data: &[u8]) -> &[u8] {
+    let end = data.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(0, |at| at + 1);
+    &data[..end]
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PDF: &[u8] = b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Annots [] >>\nendobj\nxref\n0 4\n0000000000 65535 f \n0000000009 00000 n \n0000000058 00000 n \n0000000115 00000 n \ntrailer\n<< /Size 4 /Root 1 0 R /ID [<AB> <CD>] >>\nstartxref\n197\n%%EOF\n";
+
+    #[test]
+    fn test_dictionary_values() {
+        let dict = b"<< /Type /Page /Kids [3 0 R 4 0 R] /Title (a (nested) \\) string) /Res << /Font << /F1 5 0 R >> >> /Count 12 >>";
+        assert_eq!(dict_value(dict, "Type"), Some(&b"/Page"[..]));
+        assert_eq!(dict_value(dict, "Kids"), Some(&b"[3 0 R 4 0 R]"[..]));
+        assert_eq!(dict_value(dict, "Title"), Some(&b"(a (nested) \\) string)"[..]));
+        assert_eq!(dict_value(dict, "Count"), Some(&b"12"[..]));
+        assert_eq!(dict_value(dict, "F1"), None);
+        assert_eq!(parse_ref(b"5 0 R"), Some(5));
+        assert_eq!(array_refs(b"[3 0 R /XYZ 72 720 0]"), vec![3]);
+        assert_eq!(array_refs(dict_value(dict, "Kids").unwrap()), vec![3, 4]);
+
+        let updated = with_entry(dict, "Count", "13");
+        assert_eq!(dict_value(&updated, "Count"), Some(&b"13"[..]));
+        let added = with_entry(b"<< /Type /Catalog >>", "Lang", "(en)");
+        assert_eq!(added, b"<< /Type /Catalog /Lang (en) >>");
+        assert_eq!(text_string("Q3 (draft)"), "(Q3 \\(draft\\))");
+        assert_eq!(text_string("Grüße"), "<FEFF0047007200FC00DF0065>");
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let file = PdfFile::parse(SAMPLE_PDF.to_vec()).unwrap();
+        assert_eq!(file.root().unwrap(), 1);
+        assert_eq!(file.first_page().unwrap(), 3);
+        assert_eq!(file.page_count().unwrap(), 1);
+        assert_eq!(file.pages().unwrap(), vec![3]);
+
+        let mut update = file.update().unwrap();
+        let info = update.add_object("<< /Title (Report) >>");
+        let catalog = with_entry(&file.object_dict(1).unwrap(), "Lang", "(en)");
+        update.set_object(1, catalog);
+        let updated = update.write();
+
+        assert!(updated.starts_with(SAMPLE_PDF));
+        assert_eq!(info, 4);
+        let reparsed = PdfFile::parse(updated).unwrap();
+        assert_eq!(dict_value(&reparsed.object_dict(1).unwrap(), "Lang"), Some(&b"(en)"[..]));
+        assert_eq!(dict_value(reparsed.trailer(), "Prev"), Some(&b"197"[..]));
+        assert_eq!(dict_value(reparsed.trailer(), "Size"), Some(&b"5"[..]));
+        assert_eq!(dict_value(reparsed.trailer(), "ID"), Some(&b"[<AB> <CD>]"[..]));
+
+        let text = String::from_utf8_lossy(reparsed.data());
+        let xref = text.rfind("\nxref\n").unwrap() + 1;
+        let offset: usize = text[xref..].lines().nth(4).unwrap()[..10].parse().unwrap();
+        assert!(text[offset..].starts_with("1 0 obj\n"));
+    }
+}
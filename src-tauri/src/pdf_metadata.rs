@@ -0,0 +1,177 @@
+use anyhow::{Result, Context};
+use chrono::{DateTime, FixedOffset};
+
+use crate::pdf_edit::{dict_value, parse_ref, text_string, with_entry, PdfFile};
+
+/// Shown as the creating application in PDF readers' document properties
+const CREATOR: &str = "Typolite";
+
+/// Document properties for the Info dictionary and XMP metadata of a PDF
+#[derive(Debug, Clone)]
+pub struct PdfMetadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub subject: Option<String>,
+    pub keywords: Vec<String>,
+    pub created: DateTime<FixedOffset>,
+}
+
+/// `pdf` with `metadata` written into its Info dictionary and an XMP metadata
+/// stream on the catalog, as an incremental update
+///
+/// Entries the renderer wrote, such as `/Producer`, are kept unless replaced.
+pub fn write_metadata(pdf: Vec<u8>, metadata: &PdfMetadata, now: DateTime<FixedOffset>) -> Result<Vec<u8>> {
+    let file = PdfFile::parse(pdf)?;
+    let mut update = file.update()?;
+
+    let previous_info = dict_value(file.trailer(), "Info").and_then(parse_ref);
+    let mut info = match previous_info {
+        Some(number) => file.object_dict(number).context("Failed to read the PDF's Info dictionary")?,
+        None => b"<< >>".to_vec(),
+    };
+    let mut entries = vec![("Creator", text_string(CREATOR))];
+    if let Some(title) = &metadata.title {
+        entries.push(("Title", text_string(title)));
+    }
+    if !metadata.authors.is_empty() {
+        entries.push(("Author", text_string(&metadata.authors.join("; "))));
+    }
+    if let Some(subject) = &metadata.subject {
+        entries.push(("Subject", text_string(subject)));
+    }
+    if !metadata.keywords.is_empty() {
+        entries.push(("Keywords", text_string(&metadata.keywords.join(", "))));
+    }
+    entries.push(("CreationDate", text_string(&pdf_date(&metadata.created))));
+    entries.push(("ModDate", text_string(&pdf_date(&now))));
+    for (key, value) in entries {
+        info = with_entry(&info, key, &value);
+    }
+    let info_number = previous_info.unwrap_or_else(|| update.reserve());
+    update.set_object(info_number, info);
+    update.set_info(info_number);
+
+    let xmp = xmp_packet(metadata, &now);
+    let mut stream = format!("<< /Type /Metadata /Subtype /XML /Length {} >>\nstream\n", xmp.len()).into_bytes();
+    stream.extend_from_slice(xmp.as_bytes());
+    stream.extend_from_slice(b"\nendstream");
+
+    let root = file.root()?;
+    let catalog = file.object_dict(root)?;
+    match dict_value(&catalog, "Metadata").and_then(parse_ref) {
+        Some(number) => update.set_object(number, stream),
+        None => {
+            let number = update.add_object(stream);
+            update.set_object(root, with_entry(&catalog, "Metadata", &format!("{} 0 R", number)));
+        }
+    }
+
+    Ok(update.write())
+}
+
+/// A PDF date string such as `D:20240315093000+01'00'`
+fn pdf_date(date: &DateTime<FixedOffset>) -> String {
+    let offset = date.offset().local_minus_utc();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.abs() / 60;
+    format!("D:{}{}{:02}'{:02}'", date.format("%Y%m%d%H%M%S"), sign, minutes / 60, minutes % 60)
+}
+
+fn xmp_packet(metadata: &PdfMetadata, now: &DateTime<FixedOffset>) -> String {
+    let mut properties = String::from("<dc:format>application/pdf</dc:format>\n");
+    if let Some(title) = &metadata.title {
+        properties.push_str(&format!("<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n", escape(title)));
+    }
+    if !metadata.authors.is_empty() {
+        let authors: String = metadata.authors.iter().map(|author| format!("<rdf:li>{}</rdf:li>", escape(author))).collect();
+        properties.push_str(&format!("<dc:creator><rdf:Seq>{}</rdf:Seq></dc:creator>\n", authors));
+    }
+    if let Some(subject) = &metadata.subject {
+        properties.push_str(&format!("<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n", escape(subject)));
+    }
+    if !metadata.keywords.is_empty() {
+        let keywords: String = metadata.keywords.iter().map(|keyword| format!("<rdf:li>{}</rdf:li>", escape(keyword))).collect();
+        properties.push_str(&format!("<dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>\n", keywords));
+        properties.push_str(&format!("<pdf:Keywords>{}</pdf:Keywords>\n", escape(&metadata.keywords.join(", "))));
+    }
+    properties.push_str(&format!("<xmp:CreatorTool>{}</xmp:CreatorTool>\n", CREATOR));
+    properties.push_str(&format!("<xmp:CreateDate>{}</xmp:CreateDate>\n", metadata.created.to_rfc3339()));
+    properties.push_str(&format!("<xmp:ModifyDate>{}</xmp:ModifyDate>\n", now.to_rfc3339()));
+    properties.push_str(&format!("<xmp:MetadataDate>{}</xmp:MetadataDate>\n", now.to_rfc3339()));
+
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" ",
+            "xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\">\n",
+            "{}",
+            "</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>"
+        ),
+        properties
+    )
+}
+
+fn escape(text: &str) -> String {
+    html_escape::encode_text(text).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PDF: &[u8] = b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>\nendobj\n4 0 obj\n<< /Producer (Skia/PDF) /Creator (Chromium) >>\nendobj\nxref\n0 5\n0000000000 65535 f \n0000000009 00000 n \n0000000058 00000 n \n0000000115 00000 n \n0000000186 00000 n \ntrailer\n<< /Size 5 /Root 1 0 R /Info 4 0 R >>\nstartxref\n248\n%%EOF\n";
+
+    fn metadata() -> PdfMetadata {
+        PdfMetadata {
+            title: Some("Quarterly report".to_string()),
+            authors: vec!["Ada Lovelace".to_string(), "Grace Hopper".to_string()],
+            subject: Some("Sales & marketing".to_string()),
+            keywords: vec!["sales".to_string(), "Q3".to_string()],
+            created: DateTime::parse_from_rfc3339("2024-03-15T09:30:00+01:00").unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_info_dictionary() {
+        let now = DateTime::parse_from_rfc3339("2024-03-16T10:00:00-05:30").unwrap();
+        let updated = write_metadata(SAMPLE_PDF.to_vec(), &metadata(), now).unwrap();
+        assert!(updated.starts_with(SAMPLE_PDF));
+
+        let file = PdfFile::parse(updated).unwrap();
+        assert_eq!(dict_value(file.trailer(), "Info"), Some(&b"4 0 R"[..]));
+        let info = file.object_dict(4).unwrap();
+        assert_eq!(dict_value(&info, "Title"), Some(&b"(Quarterly report)"[..]));
+        assert_eq!(dict_value(&info, "Author"), Some(&b"(Ada Lovelace; Grace Hopper)"[..]));
+        assert_eq!(dict_value(&info, "Subject"), Some(&b"(Sales & marketing)"[..]));
+        assert_eq!(dict_value(&info, "Keywords"), Some(&b"(sales, Q3)"[..]));
+        assert_eq!(dict_value(&info, "Creator"), Some(&b"(Typolite)"[..]));
+        assert_eq!(dict_value(&info, "Producer"), Some(&b"(Skia/PDF)"[..]));
+        assert_eq!(dict_value(&info, "CreationDate"), Some(&b"(D:20240315093000+01'00')"[..]));
+        assert_eq!(dict_value(&info, "ModDate"), Some(&b"(D:20240316100000-05'30')"[..]));
+    }
+
+    #[test]
+    fn test_xmp_metadata() {
+        let pdf = SAMPLE_PDF.to_vec();
+        let without_info = String::from_utf8(pdf).unwrap().replace(" /Info 4 0 R", "");
+        let now = DateTime::parse_from_rfc3339("2024-03-16T10:00:00Z").unwrap();
+        let updated = write_metadata(without_info.into_bytes(), &metadata(), now).unwrap();
+
+        let file = PdfFile::parse(updated).unwrap();
+        let info = dict_value(file.trailer(), "Info").and_then(parse_ref).unwrap();
+        assert_eq!(dict_value(&file.object_dict(info).unwrap(), "Title"), Some(&b"(Quarterly report)"[..]));
+        let stream = dict_value(&file.object_dict(1).unwrap(), "Metadata").and_then(parse_ref).unwrap();
+
+        let text = String::from_utf8_lossy(file.data());
+        let xmp = &text[text.find(&format!("\n{} 0 obj\n", stream)).unwrap()..];
+        assert!(xmp.contains("/Subtype /XML"));
+        assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">Quarterly report</rdf:li>"));
+        assert!(xmp.contains("<rdf:Seq><rdf:li>Ada Lovelace</rdf:li><rdf:li>Grace Hopper</rdf:li></rdf:Seq>"));
+        assert!(xmp.contains("Sales &amp; marketing"));
+        assert!(xmp.contains("<pdf:Keywords>sales, Q3</pdf:Keywords>"));
+        assert!(xmp.contains("<xmp:CreateDate>2024-03-15T09:30:00+01:00</xmp:CreateDate>"));
+    }
+}
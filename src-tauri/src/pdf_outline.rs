@@ -0,0 +1,251 @@
+use anyhow::Result;
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::pdf_edit::{array_refs, dict_value, parse_ref, text_string, with_entry, IncrementalUpdate, PdfFile};
+
+/// A heading of the exported document that a bookmark can point at
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineHeading {
+    pub level: u8,
+    pub title: String,
+    /// Element id of the heading; empty when it has none
+    pub anchor: String,
+}
+
+struct Bookmark {
+    title: String,
+    /// Explicit destination copied from the renderer's named destination
+    destination: Vec<u8>,
+    children: Vec<Bookmark>,
+}
+
+/// Headings with an id, in document order, the same ones the parser lists in the TOC
+pub fn outline_headings(html: &str) -> Vec<OutlineHeading> {
+    let mut headings = document_headings(html);
+    headings.retain(|heading| !heading.anchor.is_empty());
+    headings
+}
+
+/// Every heading, in document order, including those without an id
+pub fn document_headings(html: &str) -> Vec<OutlineHeading> {
+    let selector = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+    Html::parse_fragment(html)
+        .select(&selector)
+        .filter_map(|heading| {
+            let anchor = heading.value().id().unwrap_or_default().to_string();
+            let title = heading.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ");
+            let level = heading.value().name()[1..].parse().ok()?;
+            Some(OutlineHeading { level, title, anchor })
+        })
+        .collect()
+}
+
+/// Invisible links to every heading; the renderer only writes named destinations
+/// for elements something in the document links to
+pub fn outline_anchors(headings: &[OutlineHeading]) -> String {
+    if headings.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from("<nav class=\"pdf-outline\" aria-hidden=\"true\">");
+    for heading in headings {
+        let _ = write!(html, "<a href=\"#{}\" tabindex=\"-1\"></a>", html_escape::encode_double_quoted_attribute(&heading.anchor));
+    }
+    html.push_str("</nav>");
+    html
+}
+
+/// `pdf` with bookmarks for `headings`, nested by level, opening in the outline
+/// sidebar
+///
+/// Each bookmark takes the page and position of the named destination the
+/// renderer wrote for its heading's id; headings without one are left out. The
+/// PDF is returned unchanged when none of them has a destination.
+pub fn write_outline(pdf: Vec<u8>, headings: &[OutlineHeading]) -> Result<Vec<u8>> {
+    let file = PdfFile::parse(pdf)?;
+    let root = file.root()?;
+    let catalog = file.object_dict(root)?;
+    let destinations = named_destinations(&file, &catalog)?;
+
+    let located: Vec<_> = headings.iter()
+        .filter_map(|heading| {
+            let destination = dict_value(&destinations, &pdf_name(&heading.anchor))?;
+            Some((heading.level, &heading.title, destination.to_vec()))
+        })
+        .collect();
+    let bookmarks = nest(&located);
+    if bookmarks.is_empty() {
+        return Ok(file.data().to_vec());
+    }
+
+    let mut update = file.update()?;
+    let outlines = update.reserve();
+    let (numbers, count) = add_bookmarks(&mut update, &bookmarks, outlines);
+    update.set_object(outlines, format!(
+        "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+        numbers[0],
+        numbers[numbers.len() - 1],
+        count
+    ));
+    let catalog = with_entry(&catalog, "Outlines", &format!("{} 0 R", outlines));
+    update.set_object(root, with_entry(&catalog, "PageMode", "/UseOutlines"));
+
+    Ok(update.write())
+}
+
+/// The page each of `headings` starts on, counting from 1, by anchor; headings
+/// the renderer wrote no named destination for are left out
+pub fn heading_pages(pdf: Vec<u8>, headings: &[OutlineHeading]) -> Result<HashMap<String, u32>> {
+    let file = PdfFile::parse(pdf)?;
+    let catalog = file.object_dict(file.root()?)?;
+    let destinations = named_destinations(&file, &catalog)?;
+    let pages = file.pages()?;
+
+    Ok(headings.iter()
+        .filter_map(|heading| {
+            let destination = dict_value(&destinations, &pdf_name(&heading.anchor))?;
+            let page = array_refs(destination).first().copied()?;
+            let index = pages.iter().position(|&number| number == page)?;
+            Some((heading.anchor.clone(), index as u32 + 1))
+        })
+        .collect())
+}
+
+/// The catalog's `/Dests` dictionary, empty when it has none
+fn named_destinations(file: &PdfFile, catalog: &[u8]) -> Result<Vec<u8>> {
+    Ok(match dict_value(catalog, "Dests") {
+        Some(value) => match parse_ref(value) {
+            Some(number) => file.object_dict(number)?,
+            None => value.to_vec(),
+        },
+        None => Vec::new(),
+    })
+}
+
+/// Each heading with the deeper headings after it as its children
+fn nest(headings: &[(u8, &String, Vec<u8>)]) -> Vec<Bookmark> {
+    let mut bookmarks = Vec::new();
+    let mut at = 0;
+    while at < headings.len() {
+        let (level, title, destination) = &headings[at];
+        let end = headings[at + 1..].iter()
+            .position(|(next, _, _)| next <= level)
+            .map_or(headings.len(), |offset| at + 1 + offset);
+        bookmarks.push(Bookmark {
+            title: title.to_string(),
+            destination: destination.clone(),
+            children: nest(&headings[at + 1..end]),
+        });
+        at = end;
+    }
+    bookmarks
+}
+
+/// Add `bookmarks` as siblings under `parent`, returning their object numbers and
+/// how many items they show with every level open
+fn add_bookmarks(update: &mut IncrementalUpdate, bookmarks: &[Bookmark], parent: u32) -> (Vec<u32>, usize) {
+    let numbers: Vec<u32> = bookmarks.iter().map(|_| update.reserve()).collect();
+    let mut total = 0;
+    for (index, bookmark) in bookmarks.iter().enumerate() {
+        let mut dict = format!("<< /Title {} /Parent {} 0 R", text_string(&bookmark.title), parent);
+        if index > 0 {
+            let _ = write!(dict, " /Prev {} 0 R", numbers[index - 1]);
+        }
+        if let Some(next) = numbers.get(index + 1) {
+            let _ = write!(dict, " /Next {} 0 R", next);
+        }
+        let (children, count) = add_bookmarks(update, &bookmark.children, numbers[index]);
+        if let (Some(first), Some(last)) = (children.first(), children.last()) {
+            let _ = write!(dict, " /First {} 0 R /Last {} 0 R /Count {}", first, last, count);
+        }
+        let mut dict = dict.into_bytes();
+        dict.extend_from_slice(b" /Dest ");
+        dict.extend_from_slice(&bookmark.destination);
+        dict.extend_from_slice(b" >>");
+        update.set_object(numbers[index], dict);
+        total += 1 + count;
+    }
+    (numbers, total)
+}
+
+/// `name` as the renderer writes PDF name keys, with delimiters, `#` and bytes
+/// outside printable ASCII as `#XX`
+fn pdf_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if !(b'!'..=b'~').contains(&byte) || b"#/%()<>[]{}".contains(&byte) {
+            let _ = write!(escaped, "#{:02X}", byte);
+        } else {
+            escaped.push(byte as char);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PDF: &[u8] = b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R /Dests 4 0 R >>\nendobj\n2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>\nendobj\n4 0 obj\n<< /intro [3 0 R /XYZ 72 720 0] /setup [3 0 R /XYZ 72 500 0] /caf#C3#A9 [3 0 R /XYZ 72 300 0] /usage [3 0 R /XYZ 72 100 0] >>\nendobj\nxref\n0 5\n0000000000 65535 f \n0000000009 00000 n \n0000000071 00000 n \n0000000128 00000 n \n0000000199 00000 n \ntrailer\n<< /Size 5 /Root 1 0 R >>\nstartxref\n340\n%%EOF\n";
+
+    fn heading(level: u8, title: &str, anchor: &str) -> OutlineHeading {
+        OutlineHeading { level, title: title.to_string(), anchor: anchor.to_string() }
+    }
+
+    #[test]
+    fn test_outline_headings() {
+        let html = "<h1 id=\"intro\">Intro <em>to</em>\n  it</h1><p>Text</p><h2>No id</h2><h3 id=\"a&amp;b\">A &amp; B</h3>";
+        let headings = outline_headings(html);
+        assert_eq!(headings, vec![heading(1, "Intro to it", "intro"), heading(3, "A & B", "a&b")]);
+        assert_eq!(document_headings(html)[1], heading(2, "No id", ""));
+        assert_eq!(
+            outline_anchors(&headings),
+            "<nav class=\"pdf-outline\" aria-hidden=\"true\"><a href=\"#intro\" tabindex=\"-1\"></a><a href=\"#a&amp;b\" tabindex=\"-1\"></a></nav>"
+        );
+        assert_eq!(pdf_name("café (1)"), "caf#C3#A9#20#281#29");
+    }
+
+    #[test]
+    fn test_write_outline() {
+        let headings = vec![
+            heading(1, "Intro", "intro"),
+            heading(2, "Setup", "setup"),
+            heading(3, "Café", "café"),
+            heading(2, "Not rendered", "missing"),
+            heading(1, "Usage", "usage"),
+        ];
+        let updated = write_outline(SAMPLE_PDF.to_vec(), &headings).unwrap();
+        assert!(updated.starts_with(SAMPLE_PDF));
+
+        let file = PdfFile::parse(updated).unwrap();
+        let catalog = file.object_dict(1).unwrap();
+        assert_eq!(dict_value(&catalog, "PageMode"), Some(&b"/UseOutlines"[..]));
+        let outlines = file.object_dict(dict_value(&catalog, "Outlines").and_then(parse_ref).unwrap()).unwrap();
+        assert_eq!(dict_value(&outlines, "Count"), Some(&b"4"[..]));
+
+        let first = file.object_dict(dict_value(&outlines, "First").and_then(parse_ref).unwrap()).unwrap();
+        assert_eq!(dict_value(&first, "Title"), Some(&b"(Intro)"[..]));
+        assert_eq!(dict_value(&first, "Dest"), Some(&b"[3 0 R /XYZ 72 720 0]"[..]));
+        assert_eq!(dict_value(&first, "Count"), Some(&b"2"[..]));
+        let setup = file.object_dict(dict_value(&first, "First").and_then(parse_ref).unwrap()).unwrap();
+        assert_eq!(dict_value(&setup, "Title"), Some(&b"(Setup)"[..]));
+        assert_eq!(dict_value(&setup, "Next"), None);
+        let cafe = file.object_dict(dict_value(&setup, "First").and_then(parse_ref).unwrap()).unwrap();
+        assert_eq!(dict_value(&cafe, "Title"), Some(&b"<FEFF00430061006600E9>"[..]));
+        assert_eq!(dict_value(&cafe, "Dest"), Some(&b"[3 0 R /XYZ 72 300 0]"[..]));
+
+        let usage = file.object_dict(dict_value(&first, "Next").and_then(parse_ref).unwrap()).unwrap();
+        assert_eq!(dict_value(&usage, "Title"), Some(&b"(Usage)"[..]));
+        assert_eq!(dict_value(&usage, "Count"), None);
+        assert_eq!(dict_value(&outlines, "Last").and_then(parse_ref), dict_value(&first, "Next").and_then(parse_ref));
+
+        let pages = heading_pages(SAMPLE_PDF.to_vec(), &headings).unwrap();
+        assert_eq!(pages.len(), 4);
+        assert_eq!(pages["café"], 1);
+        assert!(!pages.contains_key("missing"));
+
+        let unmatched = write_outline(SAMPLE_PDF.to_vec(), &[heading(1, "Gone", "gone")]).unwrap();
+        assert_eq!(unmatched, SAMPLE_PDF);
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::{Result, Context};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tracing::{debug, error, info, warn};
+
+/// Argument that makes the app binary render one PDF and exit instead of starting the UI
+pub const RENDER_PDF_ARG: &str = "--render-pdf";
+
+/// Longest renderer error output kept for the export error message
+const MAX_STDERR_BYTES: u64 = 16 * 1024;
+
+/// Executable names of Chromium-based browsers, searched for on `PATH` in this order
+const BROWSER_NAMES: &[&str] = &[
+    "chromium", "chromium-browser", "google-chrome", "google-chrome-stable", "chrome", "microsoft-edge", "msedge",
+];
+
+/// Where browsers install themselves outside of `PATH`
+#[cfg(target_os = "macos")]
+const BROWSER_PATHS: &[&str] = &[
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+    "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge",
+];
+#[cfg(windows)]
+const BROWSER_PATHS: &[&str] = &[
+    r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe",
+    r"C:\Program Files\Microsoft\Edge\Application\msedge.exe",
+];
+#[cfg(not(any(target_os = "macos", windows)))]
+const BROWSER_PATHS: &[&str] = &["/snap/bin/chromium"];
+
+/// The Chrome, Chromium or Edge executable PDFs are printed with: `configured`
+/// when set, otherwise the first one found on `PATH` or in its usual place
+pub fn find_browser(configured: Option<&Path>) -> Result<PathBuf> {
+    if let Some(path) = configured {
+        if path.is_file() {
+            return Ok(path.to_path_buf());
+        }
+        anyhow::bail!("The browser set for PDF export doesn't exist: {:?}", path);
+    }
+
+    let dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    BROWSER_NAMES.iter()
+        .flat_map(|name| {
+            let executable = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+            dirs.iter().map(move |dir| dir.join(&executable))
+        })
+        .chain(BROWSER_PATHS.iter().map(PathBuf::from))
+        .find(|path| path.is_file())
+        .context("No Chrome, Chromium or Edge found to render PDFs with; install one or set its path in the export settings")
+}
+
+/// Render `html_path` to `output_path` by printing it with a headless `browser`
+///
+/// Page size and margins come from the document's `@page` rule, so the PDF
+/// matches the export options and the CSS theme's print styles.
+pub fn render_pdf(html_path: &Path, output_path: &Path, browser: &Path) -> Result<()> {
+    let output = print_command(html_path, output_path, browser)?
+        .output()
+        .with_context(|| format!("Failed to start {:?} to render the PDF", browser))?;
+    check_printed(&output, output_path, browser)
+}
+
+/// Like [`render_pdf`], without blocking; dropping the future kills the browser
+pub async fn render_pdf_async(html_path: &Path, output_path: &Path, browser: &Path) -> Result<()> {
+    let output = Command::from(print_command(html_path, output_path, browser)?)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Failed to start {:?} to render the PDF", browser))?;
+    check_printed(&output, output_path, browser)
+}
+
+fn print_command(html_path: &Path, output_path: &Path, browser: &Path) -> Result<std::process::Command> {
+    let url = url::Url::from_file_path(html_path)
+        .map_err(|_| anyhow::anyhow!("Can't open {:?} in a browser; the path must be absolute", html_path))?;
+    // A profile of its own, so a browser the user has open doesn't take the job over
+    let profile = html_path.parent().unwrap_or(Path::new(".")).join("browser-profile");
+    if output_path.exists() {
+        std::fs::remove_file(output_path)
+            .with_context(|| format!("Failed to replace PDF file: {:?}", output_path))?;
+    }
+
+    let mut command = std::process::Command::new(browser);
+    command
+        .arg("--headless")
+        .arg("--disable-gpu")
+        .arg("--disable-extensions")
+        .arg("--no-first-run")
+        .arg("--no-default-browser-check")
+        .arg(format!("--user-data-dir={}", profile.display()))
+        // Leave out the date, title and URL Chrome prints around pages; older
+        // versions only know the second flag
+        .arg("--no-pdf-header-footer")
+        .arg("--print-to-pdf-no-header")
+        .arg("--run-all-compositor-stages-before-draw")
+        .arg(format!("--print-to-pdf={}", output_path.display()))
+        .arg(url.as_str())
+        .stdin(Stdio::null());
+    Ok(command)
+}
+
+fn check_printed(output: &std::process::Output, output_path: &Path, browser: &Path) -> Result<()> {
+    let printed = std::fs::read(output_path).map(|pdf| pdf.starts_with(b"%PDF-")).unwrap_or(false);
+    if !output.status.success() || !printed {
+        // Browsers log a lot; the end is where the reason is
+        let tail = &output.stderr[output.stderr.len().saturating_sub(MAX_STDERR_BYTES as usize)..];
+        let stderr = String::from_utf8_lossy(tail);
+        anyhow::bail!("{:?} didn't print a PDF ({}): {}", browser, output.status, stderr.trim());
+    }
+    Ok(())
+}
+
+/// Entry point of the renderer child: when the arguments ask for a render, do it
+/// and return the exit code the process should end with
+pub fn render_pdf_from_args(args: &[String]) -> Option<i32> {
+    let [_, flag, html_path, output_path, browser] = args else {
+        return None;
+    };
+    if flag != RENDER_PDF_ARG {
+        return None;
+    }
+
+    match render_pdf(Path::new(html_path), Path::new(output_path), Path::new(browser)) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("{:#}", e);
+            Some(1)
+        }
+    }
+}
+
+/// A child process that renders PDFs, so a hung or crashing renderer can be
+/// killed without taking the app down with it
+#[derive(Debug, Clone)]
+pub struct PdfRendererProcess {
+    program: PathBuf,
+    /// Passed before `--render-pdf <html> <output> <browser>`
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl PdfRendererProcess {
+    pub fn new(program: PathBuf) -> Self {
+        Self {
+            program,
+            args: Vec::new(),
+            timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// Re-run the app's own binary as the renderer
+    pub fn current_exe() -> Result<Self> {
+        let program = std::env::current_exe().context("Failed to locate the app executable")?;
+        Ok(Self::new(program))
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Render in the child, killing it if it outlives the timeout
+    pub async fn render(&self, html_path: &Path, output_path: &Path, browser: &Path) -> Result<()> {
+        debug!("Starting PDF renderer {:?} for {:?}", self.program, output_path);
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .arg(RENDER_PDF_ARG)
+            .arg(html_path)
+            .arg(output_path)
+            .arg(browser)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start PDF renderer: {:?}", self.program))?;
+
+        let stderr = child.stderr.take();
+        let stderr = tokio::spawn(async move {
+            let mut output = String::new();
+            if let Some(stderr) = stderr {
+                if let Err(e) = stderr.take(MAX_STDERR_BYTES).read_to_string(&mut output).await {
+                    warn!("Failed to read PDF renderer output: {}", e);
+                }
+            }
+            output
+        });
+
+        let status = match tokio::time::timeout(self.timeout, child.wait()).await {
+            Ok(status) => status.context("Failed to wait for PDF renderer")?,
+            Err(_) => {
+                error!("PDF renderer timed out after {:?}, killing it", self.timeout);
+                if let Err(e) = child.kill().await {
+                    warn!("Failed to kill PDF renderer: {}", e);
+                }
+                anyhow::bail!("PDF rendering timed out after {} seconds", self.timeout.as_secs());
+            }
+        };
+
+        if !status.success() {
+            let output = stderr.await.unwrap_or_default();
+            anyhow::bail!("PDF renderer failed ({}): {}", status, output.trim());
+        }
+
+        info!("PDF renderer finished: {:?}", output_path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    fn shell(script: &str) -> PdfRendererProcess {
+        // The script sees `--render-pdf <html> <output> <browser>` as $1..$4
+        PdfRendererProcess::new(PathBuf::from("sh"))
+            .with_args(vec!["-c".to_string(), script.to_string(), "renderer".to_string()])
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_renderer_failures_are_isolated() {
+        let dir = TempDir::new().unwrap();
+        let html = dir.path().join("in.html");
+        let output = dir.path().join("out.pdf");
+
+        shell("cp \"$2\" \"$3\"").render(&html, &output, Path::new("chromium")).await.unwrap_err();
+        std::fs::write(&html, "<p>hi</p>").unwrap();
+        shell("cp \"$2\" \"$3\"").render(&html, &output, Path::new("chromium")).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "<p>hi</p>");
+
+        let crash = shell("echo 'renderer exploded' >&2; kill -SEGV $$").render(&html, &output, Path::new("chromium")).await.unwrap_err();
+        assert!(crash.to_string().contains("renderer exploded"));
+
+        let hang = shell("sleep 30").with_timeout(Duration::from_millis(200));
+        let started = std::time::Instant::now();
+        let error = hang.render(&html, &output, Path::new("chromium")).await.unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    /// A stand-in for Chrome that prints a fixed PDF wherever it's asked to,
+    /// failing on pages whose path contains "fail"
+    #[cfg(unix)]
+    fn fake_browser(dir: &Path) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join("fake-chrome");
+        let script = "#!/bin/sh\ncase \"$*\" in *fail.html*) echo 'page crashed' >&2; exit 3;; esac\n\
+            for arg; do case \"$arg\" in --print-to-pdf=*) printf '%%PDF-1.4 fake' > \"${arg#--print-to-pdf=}\";; esac; done\n";
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_from_args() {
+        let dir = TempDir::new().unwrap();
+        let browser = fake_browser(dir.path());
+        let output = dir.path().join("out.pdf");
+        let args = |flag: &str, html: &str| vec![
+            "typolite".to_string(),
+            flag.to_string(),
+            dir.path().join(html).to_string_lossy().to_string(),
+            output.to_string_lossy().to_string(),
+            browser.to_string_lossy().to_string(),
+        ];
+
+        assert_eq!(render_pdf_from_args(&args("--other", "in.html")), None);
+        assert_eq!(render_pdf_from_args(&["typolite".to_string()]), None);
+        assert_eq!(render_pdf_from_args(&args(RENDER_PDF_ARG, "in.html")), Some(0));
+        assert!(std::fs::read(&output).unwrap().starts_with(b"%PDF"));
+
+        let error = render_pdf(&dir.path().join("fail.html"), &output, &browser).unwrap_err().to_string();
+        assert!(error.contains("page crashed"), "{}", error);
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_find_browser() {
+        let dir = TempDir::new().unwrap();
+        let browser = dir.path().join("chrome");
+        std::fs::write(&browser, "").unwrap();
+
+        assert_eq!(find_browser(Some(&browser)).unwrap(), browser);
+        let missing = find_browser(Some(&dir.path().join("nope"))).unwrap_err();
+        assert!(missing.to_string().contains("doesn't exist"));
+    }
+}
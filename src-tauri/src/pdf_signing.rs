@@ -0,0 +1,445 @@
+use anyhow::{Result, Context};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::digest::{self, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, RsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING, RSA_PKCS1_SHA256};
+
+use crate::pdf_edit::{dict_value, text_string, with_entry, PdfFile};
+
+/// Bytes reserved for the signature in `/Contents`, enough for a 4096-bit key
+/// and a chain of a few certificates
+const SIGNATURE_SPACE: usize = 16 * 1024;
+
+/// `/ByteRange` until the real offsets are known, padded to their widest
+const BYTE_RANGE_PLACEHOLDER: &str = "[0 0000000000 0000000000 0000000000]";
+
+// Object identifiers, without their tag and length
+const OID_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x01];
+const OID_SIGNED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+const OID_CONTENT_TYPE: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x03];
+const OID_MESSAGE_DIGEST: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x04];
+const OID_SIGNING_TIME: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x05];
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+
+/// Who signed and why, shown by PDF readers in the signature panel
+#[derive(Debug, Clone, Default)]
+pub struct SignatureDetails {
+    pub name: Option<String>,
+    pub reason: Option<String>,
+    pub location: Option<String>,
+    pub contact_info: Option<String>,
+}
+
+enum SigningKey {
+    Rsa(RsaKeyPair),
+    Ecdsa(EcdsaKeyPair),
+}
+
+/// Signs PDFs with a certificate and its private key, as an incremental update
+/// holding a detached PKCS#7 signature (`adbe.pkcs7.detached`) over the whole file
+pub struct PdfSigner {
+    certificates: Vec<Vec<u8>>,
+    issuer_and_serial: Vec<u8>,
+    key: SigningKey,
+    details: SignatureDetails,
+    rng: SystemRandom,
+}
+
+impl PdfSigner {
+    /// `certificates` holds the signing certificate first, optionally followed by
+    /// its chain; `private_key` is a PKCS#8 key (RSA or P-256) or a PKCS#1 RSA key
+    pub fn from_pem(certificates: &str, private_key: &str) -> Result<Self> {
+        let certificates: Vec<Vec<u8>> = pem_blocks(certificates)?
+            .into_iter()
+            .filter(|(label, _)| label == "CERTIFICATE")
+            .map(|(_, der)| der)
+            .collect();
+        let certificate = certificates.first().context("No certificate found in the PEM file")?;
+        let identity = certificate_identity(certificate).context("Failed to read the certificate")?;
+
+        let rng = SystemRandom::new();
+        let (label, der) = pem_blocks(private_key)?.into_iter()
+            .find(|(label, _)| label.ends_with("PRIVATE KEY"))
+            .context("No private key found in the PEM file")?;
+        let key = match label.as_str() {
+            "PRIVATE KEY" => match RsaKeyPair::from_pkcs8(&der) {
+                Ok(key) => SigningKey::Rsa(key),
+                Err(_) => EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &der, &rng)
+                    .map(SigningKey::Ecdsa)
+                    .map_err(|e| anyhow::anyhow!("Unsupported private key, expected RSA or P-256: {}", e))?,
+            },
+            "RSA PRIVATE KEY" => SigningKey::Rsa(RsaKeyPair::from_der(&der)
+                .map_err(|e| anyhow::anyhow!("Invalid RSA private key: {}", e))?),
+            _ => anyhow::bail!("Unsupported private key format '{}', convert it to PKCS#8", label),
+        };
+
+        let public_key = match &key {
+            SigningKey::Rsa(key) => key.public_key().as_ref(),
+            SigningKey::Ecdsa(key) => key.public_key().as_ref(),
+        };
+        if public_key != identity.public_key {
+            anyhow::bail!("The private key doesn't belong to the certificate");
+        }
+
+        Ok(Self {
+            certificates,
+            issuer_and_serial: identity.issuer_and_serial,
+            key,
+            details: SignatureDetails::default(),
+            rng,
+        })
+    }
+
+    pub fn with_details(mut self, details: SignatureDetails) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// `pdf` with an invisible signature field, signed as of `signed_at`
+    pub fn sign(&self, pdf: Vec<u8>, signed_at: DateTime<Utc>) -> Result<Vec<u8>> {
+        let file = PdfFile::parse(pdf)?;
+        let root = file.root()?;
+        let catalog = file.object_dict(root)?;
+        if dict_value(&catalog, "AcroForm").is_some() {
+            anyhow::bail!("PDFs that already contain a form can't be signed");
+        }
+        let page = file.first_page()?;
+        let page_dict = file.object_dict(page)?;
+
+        let mut update = file.update()?;
+        let signature = update.reserve();
+        let field = update.reserve();
+
+        let mut signature_dict = format!(
+            "<< /Type /Sig /Filter /Adobe.PPKLite /SubFilter /adbe.pkcs7.detached /ByteRange {} /Contents <{}> /M {}",
+            BYTE_RANGE_PLACEHOLDER,
+            "0".repeat(SIGNATURE_SPACE * 2),
+            text_string(&signed_at.format("D:%Y%m%d%H%M%SZ").to_string()),
+        );
+        let details = [
+            ("Name", &self.details.name),
+            ("Reason", &self.details.reason),
+            ("Location", &self.details.location),
+            ("ContactInfo", &self.details.contact_info),
+        ];
+        for (key, value) in details {
+            if let Some(value) = value.as_deref().filter(|value| !value.is_empty()) {
+                signature_dict.push_str(&format!(" /{} {}", key, text_string(value)));
+            }
+        }
+        signature_dict.push_str(" >>");
+        update.set_object(signature, signature_dict);
+
+        update.set_object(field, format!(
+            "<< /Type /Annot /Subtype /Widget /FT /Sig /T (Signature1) /F 132 /Rect [0 0 0 0] /V {} 0 R /P {} 0 R >>",
+            signature, page,
+        ));
+
+        // The widget belongs on its page, unless the page keeps its annotations in
+        // a separate array object; readers find the signature through the form either way
+        match dict_value(&page_dict, "Annots") {
+            Some(annots) if annots.starts_with(b"[") => {
+                let existing = String::from_utf8_lossy(&annots[1..annots.len() - 1]).trim().to_string();
+                let annots = match existing.is_empty() {
+                    true => format!("[{} 0 R]", field),
+                    false => format!("[{} {} 0 R]", existing, field),
+                };
+                update.set_object(page, with_entry(&page_dict, "Annots", &annots));
+            }
+            Some(_) => {}
+            None => update.set_object(page, with_entry(&page_dict, "Annots", &format!("[{} 0 R]", field))),
+        }
+        update.set_object(root, with_entry(&catalog, "AcroForm", &format!("<< /Fields [{} 0 R] /SigFlags 3 >>", field)));
+
+        let original_len = file.data().len();
+        let mut output = update.write();
+        self.fill_signature(&mut output, original_len, signed_at)?;
+        Ok(output)
+    }
+
+    /// Replace the placeholders in the appended signature dictionary with the
+    /// real byte range and the signature over it
+    fn fill_signature(&self, output: &mut [u8], from: usize, signed_at: DateTime<Utc>) -> Result<()> {
+        let find = |needle: &[u8]| output[from..].windows(needle.len())
+            .position(|window| window == needle)
+            .map(|at| from + at);
+        let byte_range = find(BYTE_RANGE_PLACEHOLDER.as_bytes()).context("Signature byte range not found")?;
+        let contents = find(b"/Contents <").context("Signature contents not found")? + b"/Contents ".len();
+        let contents_end = contents + SIGNATURE_SPACE * 2 + 2;
+
+        let ranges = format!("[0 {:010} {:010} {:010}]", contents, contents_end, output.len() - contents_end);
+        output[byte_range..byte_range + ranges.len()].copy_from_slice(ranges.as_bytes());
+
+        let mut digest = digest::Context::new(&SHA256);
+        digest.update(&output[..contents]);
+        digest.update(&output[contents_end..]);
+        let cms = self.signed_data(digest.finish().as_ref(), signed_at)?;
+        if cms.len() > SIGNATURE_SPACE {
+            anyhow::bail!("The certificate chain is too large to embed ({} bytes)", cms.len());
+        }
+
+        let hex: String = cms.iter().map(|b| format!("{:02X}", b)).collect();
+        output[contents + 1..contents + 1 + hex.len()].copy_from_slice(hex.as_bytes());
+        Ok(())
+    }
+
+    /// A CMS `SignedData` with the certificates and one signer, whose signed
+    /// attributes carry the digest of the signed bytes
+    fn signed_data(&self, message_digest: &[u8], signed_at: DateTime<Utc>) -> Result<Vec<u8>> {
+        let mut attributes = [
+            attribute(OID_CONTENT_TYPE, &tlv(0x06, OID_DATA)),
+            attribute(OID_SIGNING_TIME, &tlv(0x17, signed_at.format("%y%m%d%H%M%SZ").to_string().as_bytes())),
+            attribute(OID_MESSAGE_DIGEST, &tlv(0x04, message_digest)),
+        ];
+        // DER orders a SET OF by its encoded elements
+        attributes.sort();
+        let attributes = attributes.concat();
+
+        // The signature covers the attributes encoded as a SET, not as the [0] they're stored as
+        let signed = tlv(0x31, &attributes);
+        let (signature, algorithm) = match &self.key {
+            SigningKey::Rsa(key) => {
+                let mut signature = vec![0u8; key.public().modulus_len()];
+                key.sign(&RSA_PKCS1_SHA256, &self.rng, &signed, &mut signature)
+                    .map_err(|_| anyhow::anyhow!("Failed to sign the PDF"))?;
+                (signature, tlv(0x30, &[tlv(0x06, OID_RSA_ENCRYPTION), vec![0x05, 0x00]].concat()))
+            }
+            SigningKey::Ecdsa(key) => {
+                let signature = key.sign(&self.rng, &signed)
+                    .map_err(|_| anyhow::anyhow!("Failed to sign the PDF"))?;
+                (signature.as_ref().to_vec(), tlv(0x30, &tlv(0x06, OID_ECDSA_WITH_SHA256)))
+            }
+        };
+
+        let sha256 = tlv(0x30, &tlv(0x06, OID_SHA256));
+        let signer_info = tlv(0x30, &[
+            tlv(0x02, &[1]),
+            self.issuer_and_serial.clone(),
+            sha256.clone(),
+            tlv(0xA0, &attributes),
+            algorithm,
+            tlv(0x04, &signature),
+        ].concat());
+
+        let signed_data = tlv(0x30, &[
+            tlv(0x02, &[1]),
+            tlv(0x31, &sha256),
+            tlv(0x30, &tlv(0x06, OID_DATA)),
+            tlv(0xA0, &self.certificates.concat()),
+            tlv(0x31, &signer_info),
+        ].concat());
+
+        Ok(tlv(0x30, &[tlv(0x06, OID_SIGNED_DATA), tlv(0xA0, &signed_data)].concat()))
+    }
+}
+
+/// What a signer needs from its certificate
+struct CertificateIdentity {
+    /// `IssuerAndSerialNumber`, naming the certificate in the signer info
+    issuer_and_serial: Vec<u8>,
+    /// Key from the subject public key info, as ring encodes its own public keys
+    public_key: Vec<u8>,
+}
+
+fn certificate_identity(certificate: &[u8]) -> Option<CertificateIdentity> {
+    let (certificate, _) = read_tlv(certificate, 0x30)?;
+    let (tbs, _) = read_tlv(certificate.content, 0x30)?;
+    let mut rest = tbs.content;
+    if rest.first() == Some(&0xA0) {
+        rest = read_tlv(rest, 0xA0)?.1;
+    }
+    let (serial, rest) = read_tlv(rest, 0x02)?;
+    let (_algorithm, rest) = read_tlv(rest, 0x30)?;
+    let (issuer, rest) = read_tlv(rest, 0x30)?;
+    let (_validity, rest) = read_tlv(rest, 0x30)?;
+    let (_subject, rest) = read_tlv(rest, 0x30)?;
+    let (key_info, _) = read_tlv(rest, 0x30)?;
+    let (_algorithm, rest) = read_tlv(key_info.content, 0x30)?;
+    let (key, _) = read_tlv(rest, 0x03)?;
+
+    Some(CertificateIdentity {
+        issuer_and_serial: tlv(0x30, &[issuer.raw, serial.raw].concat()),
+        // Skip the count of unused bits
+        public_key: key.content.get(1..)?.to_vec(),
+    })
+}
+
+struct Tlv<'a> {
+    content: &'a [u8],
+    raw: &'a [u8],
+}
+
+/// The DER element with `tag` at the start of `data`, and what follows it
+fn read_tlv(data: &[u8], tag: u8) -> Option<(Tlv<'_>, &[u8])> {
+    if *data.first()? != tag {
+        return None;
+    }
+    let (len, header) = match *data.get(1)? {
+        len @ 0..=0x7F => (len as usize, 2),
+        long @ 0x81..=0x84 => {
+            let count = (long & 0x7F) as usize;
+            let len = data.get(2..2 + count)?.iter().fold(0usize, |len, &b| len << 8 | b as usize);
+            (len, 2 + count)
+        }
+        _ => return None,
+    };
+    let end = header.checked_add(len).filter(|&end| end <= data.len())?;
+    Some((Tlv { content: &data[header..end], raw: &data[..end] }, &data[end..]))
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut output = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        output.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        output.push(0x80 | bytes.len() as u8);
+        output.extend_from_slice(&bytes);
+    }
+    output.extend_from_slice(content);
+    output
+}
+
+fn attribute(oid: &[u8], value: &[u8]) -> Vec<u8> {
+    tlv(0x30, &[tlv(0x06, oid), tlv(0x31, value)].concat())
+}
+
+/// `(label, DER)` for each `-----BEGIN label-----` block
+fn pem_blocks(pem: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut blocks = Vec::new();
+    let mut rest = pem;
+    while let Some(start) = rest.find("-----BEGIN ") {
+        let after = &rest[start + "-----BEGIN ".len()..];
+        let label_end = after.find("-----").context("Malformed PEM header")?;
+        let label = &after[..label_end];
+        let body = &after[label_end + 5..];
+        let end = body.find(&format!("-----END {}-----", label))
+            .with_context(|| format!("PEM block '{}' is not terminated", label))?;
+        let encoded: String = body[..end].chars().filter(|c| !c.is_whitespace()).collect();
+        let der = base64::engine::general_purpose::STANDARD.decode(encoded)
+            .with_context(|| format!("Invalid base64 in PEM block '{}'", label))?;
+        blocks.push((label.to_string(), der));
+        rest = &body[end..][label.len() + 14..];
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+
+    const SAMPLE_PDF: &[u8] = b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n2 0 obj\n<< /Type /Pages /Kids [3 0 R] /Count 1 >>\nendobj\n3 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] >>\nendobj\nxref\n0 4\n0000000000 65535 f \n0000000009 00000 n \n0000000058 00000 n \n0000000115 00000 n \ntrailer\n<< /Size 4 /Root 1 0 R >>\nstartxref\n186\n%%EOF\n";
+
+    fn pem(label: &str, der: &[u8]) -> String {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+        format!("-----BEGIN {}-----\n{}\n-----END {}-----\n", label, encoded, label)
+    }
+
+    /// A certificate with just the fields a signer reads, for `public_key`
+    fn certificate(public_key: &[u8]) -> Vec<u8> {
+        let name = tlv(0x30, &tlv(0x31, &tlv(0x30, &[tlv(0x06, &[0x55, 0x04, 0x03]), tlv(0x0C, b"Typolite Test")].concat())));
+        let key_info = tlv(0x30, &[
+            tlv(0x30, &[tlv(0x06, &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01]), tlv(0x06, &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07])].concat()),
+            tlv(0x03, &[&[0][..], public_key].concat()),
+        ].concat());
+        let algorithm = tlv(0x30, &tlv(0x06, OID_ECDSA_WITH_SHA256));
+        let tbs = tlv(0x30, &[
+            tlv(0xA0, &tlv(0x02, &[2])),
+            tlv(0x02, &[0x42]),
+            algorithm.clone(),
+            name.clone(),
+            tlv(0x30, &[tlv(0x17, b"260101000000Z"), tlv(0x17, b"360101000000Z")].concat()),
+            name,
+            key_info,
+        ].concat());
+        tlv(0x30, &[tbs, algorithm, tlv(0x03, &[0, 0])].concat())
+    }
+
+    fn test_signer() -> (PdfSigner, Vec<u8>) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let public_key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap().public_key().as_ref().to_vec();
+        let signer = PdfSigner::from_pem(&pem("CERTIFICATE", &certificate(&public_key)), &pem("PRIVATE KEY", pkcs8.as_ref())).unwrap();
+        (signer, public_key)
+    }
+
+    #[test]
+    fn test_signature_covers_file() {
+        let (signer, public_key) = test_signer();
+        let signer = signer.with_details(SignatureDetails { reason: Some("Approved".to_string()), ..Default::default() });
+        let signed_at = DateTime::parse_from_rfc3339("2026-03-01T09:30:00Z").unwrap().with_timezone(&Utc);
+        let signed = signer.sign(SAMPLE_PDF.to_vec(), signed_at).unwrap();
+        assert!(signed.starts_with(SAMPLE_PDF));
+
+        let file = PdfFile::parse(signed.clone()).unwrap();
+        let catalog = file.object_dict(1).unwrap();
+        assert_eq!(dict_value(&catalog, "AcroForm"), Some(&b"<< /Fields [5 0 R] /SigFlags 3 >>"[..]));
+        assert_eq!(dict_value(&file.object_dict(3).unwrap(), "Annots"), Some(&b"[5 0 R]"[..]));
+
+        let signature = file.object_dict(4).unwrap();
+        assert_eq!(dict_value(&signature, "Reason"), Some(&b"(Approved)"[..]));
+        assert_eq!(dict_value(&signature, "M"), Some(&b"(D:20260301093000Z)"[..]));
+        let ranges: Vec<usize> = String::from_utf8_lossy(dict_value(&signature, "ByteRange").unwrap())
+            .trim_matches(|c| c == '[' || c == ']')
+            .split_whitespace()
+            .map(|n| n.parse().unwrap())
+            .collect();
+        assert_eq!(ranges[0], 0);
+        assert_eq!(ranges[2] + ranges[3], signed.len());
+        assert_eq!(signed[ranges[1]], b'<');
+        assert_eq!(signed[ranges[2] - 1], b'>');
+
+        // The CMS is the first thing in the hex string; pull the signed
+        // attributes and the signature back out of it and check both
+        let hex = String::from_utf8_lossy(&signed[ranges[1] + 1..ranges[2] - 1]).to_string();
+        let cms: Vec<u8> = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+        let (content_info, _) = read_tlv(&cms, 0x30).unwrap();
+        let (_, rest) = read_tlv(content_info.content, 0x06).unwrap();
+        let (explicit, _) = read_tlv(rest, 0xA0).unwrap();
+        let (signed_data, _) = read_tlv(explicit.content, 0x30).unwrap();
+        let mut rest = signed_data.content;
+        for tag in [0x02, 0x31, 0x30, 0xA0] {
+            rest = read_tlv(rest, tag).unwrap().1;
+        }
+        let (signer_infos, _) = read_tlv(rest, 0x31).unwrap();
+        let (signer_info, _) = read_tlv(signer_infos.content, 0x30).unwrap();
+        let mut rest = signer_info.content;
+        for tag in [0x02, 0x30, 0x30] {
+            rest = read_tlv(rest, tag).unwrap().1;
+        }
+        let (attributes, rest) = read_tlv(rest, 0xA0).unwrap();
+        let (_, rest) = read_tlv(rest, 0x30).unwrap();
+        let (signature_value, _) = read_tlv(rest, 0x04).unwrap();
+
+        let mut digest = digest::Context::new(&SHA256);
+        digest.update(&signed[..ranges[1]]);
+        digest.update(&signed[ranges[2]..]);
+        let expected = tlv(0x04, digest.finish().as_ref());
+        assert!(attributes.content.windows(expected.len()).any(|window| window == expected));
+
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &public_key)
+            .verify(&tlv(0x31, attributes.content), signature_value.content)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_mismatched_key_and_existing_form() {
+        let rng = SystemRandom::new();
+        let (_, public_key) = test_signer();
+        let other = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let error = PdfSigner::from_pem(&pem("CERTIFICATE", &certificate(&public_key)), &pem("PRIVATE KEY", other.as_ref()))
+            .err().unwrap().to_string();
+        assert!(error.contains("doesn't belong"), "{}", error);
+
+        let (signer, _) = test_signer();
+        let signed = signer.sign(SAMPLE_PDF.to_vec(), Utc::now()).unwrap();
+        assert!(signer.sign(signed, Utc::now()).is_err());
+        assert!(signer.sign(b"not a pdf".to_vec(), Utc::now()).is_err());
+    }
+}
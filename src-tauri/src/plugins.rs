@@ -0,0 +1,348 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::{debug, info, warn};
+use wasmi::{Caller, Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::settings::app_config_dir;
+
+/// Instructions a plugin may execute per hook call before it is aborted
+const FUEL_PER_CALL: u64 = 50_000_000;
+
+/// Maximum linear memory a plugin instance may grow to
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Longest message a plugin may log at once
+const MAX_LOG_BYTES: usize = 64 * 1024;
+
+/// Points in the render/export pipeline where plugins can transform content
+///
+/// Each hook is an optional export of the plugin module taking `(ptr, len)` of a
+/// UTF-8 input string in plugin memory and returning `(ptr << 32) | len` of the
+/// output string, or a negative value to leave the input unchanged.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PluginHook {
+    /// Markdown source before parsing
+    PreParse,
+    /// Rendered HTML before it reaches the preview
+    PostHtml,
+    /// HTML before it is handed to an exporter
+    PreExport,
+    /// An exporter of its own: turns the HTML into the bytes of a file in the
+    /// plugin's format, through [`PluginManager::export_document`] rather than
+    /// [`PluginManager::run_hook`]
+    Export,
+}
+
+impl PluginHook {
+    pub const ALL: [PluginHook; 4] = [PluginHook::PreParse, PluginHook::PostHtml, PluginHook::PreExport, PluginHook::Export];
+
+    fn export_name(&self) -> &'static str {
+        match self {
+            PluginHook::PreParse => "pre_parse",
+            PluginHook::PostHtml => "post_html",
+            PluginHook::PreExport => "pre_export",
+            PluginHook::Export => "export_document",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub hooks: Vec<PluginHook>,
+}
+
+struct Plugin {
+    info: PluginInfo,
+    module: Module,
+}
+
+struct HostState {
+    plugin: String,
+    limits: StoreLimits,
+}
+
+/// Loads WASM plugins from the config dir and runs them in a sandbox
+///
+/// Plugins get no filesystem or network access: the only host function is
+/// `typolite.log(ptr, len)`. Every hook call runs in a fresh instance with
+/// bounded fuel and memory, so a misbehaving plugin can't hang or bloat the app.
+pub struct PluginManager {
+    engine: Engine,
+    plugin_dir: PathBuf,
+    plugins: RwLock<Vec<Plugin>>,
+}
+
+impl Default for PluginManager {
+    fn default() -> Self {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+
+        Self {
+            engine: Engine::new(&config),
+            plugin_dir: app_config_dir().join("plugins"),
+            plugins: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl PluginManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_plugin_dir(mut self, plugin_dir: PathBuf) -> Self {
+        self.plugin_dir = plugin_dir;
+        self
+    }
+
+    /// (Re)load every `*.wasm` module in the plugin directory, in file name order
+    pub fn load_plugins(&self) -> Result<Vec<PluginInfo>> {
+        let mut plugins = Vec::new();
+
+        if self.plugin_dir.is_dir() {
+            let mut paths: Vec<PathBuf> = std::fs::read_dir(&self.plugin_dir)
+                .with_context(|| format!("Failed to read plugin directory: {:?}", self.plugin_dir))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+                .collect();
+            paths.sort();
+
+            for path in paths {
+                match self.load_plugin(&path) {
+                    Ok(plugin) => plugins.push(plugin),
+                    Err(e) => warn!("Skipping plugin {:?}: {:#}", path, e),
+                }
+            }
+        }
+
+        info!("Loaded {} plugins from {:?}", plugins.len(), self.plugin_dir);
+        let infos = plugins.iter().map(|plugin| plugin.info.clone()).collect();
+        *self.plugins.write().unwrap() = plugins;
+        Ok(infos)
+    }
+
+    fn load_plugin(&self, path: &Path) -> Result<Plugin> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read plugin: {:?}", path))?;
+        let module = Module::new(&self.engine, &bytes[..])
+            .map_err(|e| anyhow::anyhow!("Invalid WASM module: {}", e))?;
+
+        let exports: Vec<&str> = module.exports().map(|export| export.name()).collect();
+        for required in ["memory", "alloc"] {
+            if !exports.contains(&required) {
+                return Err(anyhow::anyhow!("Plugin must export `{}`", required));
+            }
+        }
+
+        let hooks = PluginHook::ALL
+            .into_iter()
+            .filter(|hook| exports.contains(&hook.export_name()))
+            .collect();
+        let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("plugin").to_string();
+
+        debug!("Loaded plugin {} with hooks {:?}", name, hooks);
+        Ok(Plugin {
+            info: PluginInfo { name, path: path.to_path_buf(), hooks },
+            module,
+        })
+    }
+
+    pub fn list_plugins(&self) -> Vec<PluginInfo> {
+        self.plugins.read().unwrap().iter().map(|plugin| plugin.info.clone()).collect()
+    }
+
+    /// Whether any loaded plugin implements `hook`
+    pub fn has_hook(&self, hook: PluginHook) -> bool {
+        self.plugins.read().unwrap().iter().any(|plugin| plugin.info.hooks.contains(&hook))
+    }
+
+    /// Pass content through every plugin implementing `hook`, in load order
+    ///
+    /// A failing plugin is logged and skipped so it can't break rendering.
+    pub fn run_hook(&self, hook: PluginHook, input: String) -> String {
+        let plugins = self.plugins.read().unwrap();
+        let mut content = input;
+
+        for plugin in plugins.iter().filter(|plugin| plugin.info.hooks.contains(&hook)) {
+            let output = self.call_hook(plugin, hook, content.as_bytes())
+                .and_then(|output| output.map(String::from_utf8).transpose().context("Plugin returned invalid UTF-8"));
+            match output {
+                Ok(Some(output)) => content = output,
+                Ok(None) => {}
+                Err(e) => warn!("Plugin {} failed in {:?}: {:#}", plugin.info.name, hook, e),
+            }
+        }
+
+        content
+    }
+
+    /// The file the exporter plugin named `plugin` makes of `html`
+    pub fn export_document(&self, plugin: &str, html: &str) -> Result<Vec<u8>> {
+        let plugins = self.plugins.read().unwrap();
+        let exporter = plugins.iter()
+            .find(|candidate| candidate.info.name == plugin && candidate.info.hooks.contains(&PluginHook::Export))
+            .with_context(|| format!("No exporter plugin named {}", plugin))?;
+        self.call_hook(exporter, PluginHook::Export, html.as_bytes())?
+            .with_context(|| format!("Plugin {} exported nothing", plugin))
+    }
+
+    fn call_hook(&self, plugin: &Plugin, hook: PluginHook, input: &[u8]) -> Result<Option<Vec<u8>>> {
+        let state = HostState {
+            plugin: plugin.info.name.clone(),
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).build(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.add_fuel(FUEL_PER_CALL).map_err(wasm_error)?;
+
+        let mut linker = <Linker<HostState>>::new(&self.engine);
+        linker
+            .func_wrap("typolite", "log", |caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+                if let Some(memory) = caller.get_export("memory").and_then(|export| export.into_memory()) {
+                    // The length is the plugin's word, so it's checked before anything is allocated
+                    let len = (len.max(0) as usize).min(MAX_LOG_BYTES);
+                    if !in_memory(ptr as u32 as usize, len, memory.data(&caller).len()) {
+                        warn!("[plugin {}] tried to log outside its memory", caller.data().plugin);
+                        return;
+                    }
+                    let mut buffer = vec![0u8; len];
+                    if memory.read(&caller, ptr as u32 as usize, &mut buffer).is_ok() {
+                        info!("[plugin {}] {}", caller.data().plugin, String::from_utf8_lossy(&buffer));
+                    }
+                }
+            })
+            .map_err(wasm_error)?;
+
+        let instance = linker
+            .instantiate(&mut store, &plugin.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(wasm_error)?;
+
+        let memory = instance.get_memory(&store, "memory")
+            .ok_or_else(|| anyhow::anyhow!("Plugin does not export memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc").map_err(wasm_error)?;
+        let hook_fn = instance.get_typed_func::<(i32, i32), i64>(&store, hook.export_name()).map_err(wasm_error)?;
+
+        let input_len = i32::try_from(input.len()).context("Input too large for plugin")?;
+        let input_ptr = alloc.call(&mut store, input_len).map_err(wasm_error)?;
+        memory.write(&mut store, input_ptr as usize, input)
+            .map_err(|e| anyhow::anyhow!("Failed to write plugin input: {}", e))?;
+
+        let packed = hook_fn.call(&mut store, (input_ptr, input_len)).map_err(wasm_error)?;
+        if packed < 0 {
+            return Ok(None);
+        }
+
+        let (output_ptr, output_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if !in_memory(output_ptr, output_len, memory.data(&store).len().min(MAX_MEMORY_BYTES)) {
+            anyhow::bail!("Plugin output of {} bytes at {} is outside its memory", output_len, output_ptr);
+        }
+        let mut output = vec![0u8; output_len];
+        memory.read(&store, output_ptr, &mut output)
+            .map_err(|e| anyhow::anyhow!("Failed to read plugin output: {}", e))?;
+        Ok(Some(output))
+    }
+}
+
+/// Whether `len` bytes at `ptr` lie within a plugin memory of `size` bytes
+fn in_memory(ptr: usize, len: usize, size: usize) -> bool {
+    ptr.checked_add(len).is_some_and(|end| end <= size)
+}
+
+/// wasmi errors and traps aren't `std::error::Error` in every configuration
+fn wasm_error(e: impl std::fmt::Display) -> anyhow::Error {
+    anyhow::anyhow!("{}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const REPLACING_PLUGIN: &str = r#"(module
+        (import "typolite" "log" (func $log (param i32 i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 16) "<p>from plugin</p>")
+        (data (i32.const 64) "hello")
+        (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+        (func (export "post_html") (param i32 i32) (result i64)
+            (call $log (i32.const 64) (i32.const 5))
+            (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 18))))"#;
+
+    const PASSTHROUGH_PLUGIN: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+        (func (export "pre_parse") (param i32 i32) (result i64) (i64.const -1)))"#;
+
+    const LOOPING_PLUGIN: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+        (func (export "post_html") (param i32 i32) (result i64) (loop $l (br $l)) (i64.const -1)))"#;
+
+    /// Claims an output far larger than its memory, then exports five bytes
+    const EXPORTER_PLUGIN: &str = r#"(module
+        (import "typolite" "log" (func $log (param i32 i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 16) "%TXT1")
+        (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+        (func (export "post_html") (param i32 i32) (result i64)
+            (call $log (i32.const 16) (i32.const 2147483647))
+            (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 4294967295)))
+        (func (export "export_document") (param i32 i32) (result i64)
+            (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 5))))"#;
+
+    fn manager_with(plugins: &[(&str, &str)]) -> (TempDir, PluginManager) {
+        let temp_dir = TempDir::new().unwrap();
+        for (name, wat) in plugins {
+            std::fs::write(temp_dir.path().join(name), wat::parse_str(wat).unwrap()).unwrap();
+        }
+        let manager = PluginManager::new().with_plugin_dir(temp_dir.path().to_path_buf());
+        manager.load_plugins().unwrap();
+        (temp_dir, manager)
+    }
+
+    #[test]
+    fn test_hooks_transform_content() {
+        let (_dir, manager) = manager_with(&[("a.wasm", REPLACING_PLUGIN), ("b.wasm", PASSTHROUGH_PLUGIN)]);
+
+        let plugins = manager.list_plugins();
+        assert_eq!(plugins.len(), 2);
+        assert_eq!(plugins[0].hooks, vec![PluginHook::PostHtml]);
+        assert_eq!(plugins[1].hooks, vec![PluginHook::PreParse]);
+
+        assert_eq!(manager.run_hook(PluginHook::PostHtml, "<p>x</p>".to_string()), "<p>from plugin</p>");
+        assert_eq!(manager.run_hook(PluginHook::PreParse, "# Source".to_string()), "# Source");
+        assert_eq!(manager.run_hook(PluginHook::PreExport, "<p>x</p>".to_string()), "<p>x</p>");
+    }
+
+    #[test]
+    fn test_exporter_plugin_and_bounded_output() {
+        let (_dir, manager) = manager_with(&[("text.wasm", EXPORTER_PLUGIN), ("b.wasm", PASSTHROUGH_PLUGIN)]);
+
+        assert_eq!(manager.list_plugins()[1].hooks, vec![PluginHook::PostHtml, PluginHook::Export]);
+        assert_eq!(manager.export_document("text", "<p>x</p>").unwrap(), b"%TXT1");
+        assert!(manager.export_document("b", "<p>x</p>").is_err());
+        // Its oversized log and output are refused without allocating them
+        assert_eq!(manager.run_hook(PluginHook::PostHtml, "<p>x</p>".to_string()), "<p>x</p>");
+    }
+
+    #[test]
+    fn test_runaway_plugin_is_stopped() {
+        let (_dir, manager) = manager_with(&[("loop.wasm", LOOPING_PLUGIN)]);
+
+        assert_eq!(manager.run_hook(PluginHook::PostHtml, "<p>x</p>".to_string()), "<p>x</p>");
+    }
+
+    #[test]
+    fn test_invalid_plugins_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("broken.wasm"), b"not wasm").unwrap();
+        let manager = PluginManager::new().with_plugin_dir(temp_dir.path().to_path_buf());
+
+        assert!(manager.load_plugins().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::export::ExportOptions;
+use crate::settings::app_config_dir;
+
+/// Named export options kept for reuse, such as "Print A4" or "Web HTML"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportPreset {
+    pub name: String,
+    pub options: ExportOptions,
+}
+
+/// Export presets, saved to `export_presets.json` in the app config folder
+pub struct ExportPresets {
+    path: PathBuf,
+    presets: Mutex<Vec<ExportPreset>>,
+}
+
+impl Default for ExportPresets {
+    fn default() -> Self {
+        Self::load(app_config_dir().join("export_presets.json"))
+    }
+}
+
+impl ExportPresets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: PathBuf) -> Self {
+        let presets = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Invalid export presets {:?}, starting empty: {}", path, e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self { path, presets: Mutex::new(presets) }
+    }
+
+    /// All presets, by name
+    pub fn list(&self) -> Vec<ExportPreset> {
+        let mut presets = self.presets.lock().unwrap().clone();
+        presets.sort_by_key(|preset| preset.name.to_lowercase());
+        presets
+    }
+
+    /// The preset called `name`, ignoring case
+    pub fn get(&self, name: &str) -> Option<ExportPreset> {
+        self.presets.lock().unwrap().iter()
+            .find(|preset| preset.name.eq_ignore_ascii_case(name.trim()))
+            .cloned()
+    }
+
+    /// Save `preset`, replacing any preset with the same name
+    pub async fn save(&self, mut preset: ExportPreset) -> Result<ExportPreset> {
+        preset.name = preset.name.trim().to_string();
+        if preset.name.is_empty() {
+            anyhow::bail!("Export presets need a name");
+        }
+
+        let json = {
+            let mut presets = self.presets.lock().unwrap();
+            presets.retain(|existing| !existing.name.eq_ignore_ascii_case(&preset.name));
+            presets.push(preset.clone());
+            serde_json::to_string_pretty(&*presets)?
+        };
+        self.write(json).await?;
+        debug!("Saved export preset '{}'", preset.name);
+        Ok(preset)
+    }
+
+    /// Remove the preset called `name`; false when there is none
+    pub async fn delete(&self, name: &str) -> Result<bool> {
+        let json = {
+            let mut presets = self.presets.lock().unwrap();
+            let count = presets.len();
+            presets.retain(|preset| !preset.name.eq_ignore_ascii_case(name.trim()));
+            if presets.len() == count {
+                return Ok(false);
+            }
+            serde_json::to_string_pretty(&*presets)?
+        };
+        self.write(json).await?;
+        debug!("Deleted export preset '{}'", name);
+        Ok(true)
+    }
+
+    async fn write(&self, json: String) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await
+                .with_context(|| format!("Failed to create config directory: {:?}", dir))?;
+        }
+        tokio::fs::write(&self.path, json).await
+            .with_context(|| format!("Failed to write export presets: {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{ExportFormat, PageSize};
+    use tempfile::TempDir;
+
+    fn preset(name: &str, format: ExportFormat, page_size: PageSize) -> ExportPreset {
+        ExportPreset { name: name.to_string(), options: ExportOptions { format, page_size, ..Default::default() } }
+    }
+
+    #[tokio::test]
+    async fn test_presets_persist() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config").join("export_presets.json");
+        let presets = ExportPresets::load(path.clone());
+
+        presets.save(preset("Web HTML", ExportFormat::Html, PageSize::A4)).await.unwrap();
+        presets.save(preset(" Letter draft ", ExportFormat::Pdf, PageSize::Letter)).await.unwrap();
+        presets.save(preset("Print A4", ExportFormat::Pdf, PageSize::A4)).await.unwrap();
+
+        let reloaded = ExportPresets::load(path);
+        let names: Vec<_> = reloaded.list().into_iter().map(|preset| preset.name).collect();
+        assert_eq!(names, vec!["Letter draft", "Print A4", "Web HTML"]);
+        let letter = reloaded.get("letter DRAFT").unwrap();
+        assert!(matches!(letter.options.page_size, PageSize::Letter));
+    }
+
+    #[tokio::test]
+    async fn test_save_replaces_and_delete() {
+        let dir = TempDir::new().unwrap();
+        let presets = ExportPresets::load(dir.path().join("export_presets.json"));
+
+        presets.save(preset("Print", ExportFormat::Pdf, PageSize::A4)).await.unwrap();
+        presets.save(preset("print", ExportFormat::Pdf, PageSize::A3)).await.unwrap();
+        assert_eq!(presets.list().len(), 1);
+        assert!(matches!(presets.get("Print").unwrap().options.page_size, PageSize::A3));
+        assert!(presets.save(preset("  ", ExportFormat::Html, PageSize::A4)).await.is_err());
+
+        assert!(presets.delete("PRINT").await.unwrap());
+        assert!(!presets.delete("Print").await.unwrap());
+        assert!(presets.list().is_empty());
+    }
+}
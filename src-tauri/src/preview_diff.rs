@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use tracing::debug;
+
+use crate::parser::HtmlBlock;
+
+/// Share of new blocks above which re-sending everything beats a patch
+const MAX_PATCH_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PatchBlock {
+    /// Matches the `data-block` attribute of the block's element
+    pub id: String,
+    pub sourcepos: String,
+    /// Only sent for blocks the preview doesn't have yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+/// The new document as a keyed list: the preview keeps listed elements it already
+/// has (updating their `data-sourcepos`), inserts those that come with HTML, and
+/// removes the rest
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PreviewPatch {
+    pub blocks: Vec<PatchBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreviewUpdate {
+    /// Replace the whole preview with this HTML
+    Full(String),
+    Patch(PreviewPatch),
+}
+
+/// Remembers which blocks each preview shows, so re-renders can send only what changed
+#[derive(Default)]
+pub struct PreviewDiffer {
+    shown: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl PreviewDiffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cheapest update that brings `view` to `blocks`
+    ///
+    /// A view seen for the first time, or one where most blocks changed, gets the
+    /// full HTML; either way every element carries its `data-block` id.
+    pub fn update(&self, view: &str, blocks: &[HtmlBlock]) -> PreviewUpdate {
+        let keyed: Vec<(String, &HtmlBlock)> = block_ids(blocks).into_iter().zip(blocks).collect();
+        let ids: HashSet<String> = keyed.iter().map(|(id, _)| id.clone()).collect();
+
+        let previous = self.shown.lock().unwrap().insert(view.to_string(), ids);
+        let Some(previous) = previous else {
+            return PreviewUpdate::Full(full_html(&keyed));
+        };
+
+        let new_blocks = keyed.iter().filter(|(id, _)| !previous.contains(id)).count();
+        if new_blocks > 1 && new_blocks as f64 > keyed.len() as f64 * MAX_PATCH_RATIO {
+            debug!("{} of {} blocks changed in {}, sending full preview", new_blocks, keyed.len(), view);
+            return PreviewUpdate::Full(full_html(&keyed));
+        }
+
+        let blocks = keyed.into_iter()
+            .map(|(id, block)| PatchBlock {
+                html: (!previous.contains(&id)).then(|| tag_block(&block.html, &id, &block.sourcepos)),
+                sourcepos: block.sourcepos.clone(),
+                id,
+            })
+            .collect();
+        PreviewUpdate::Patch(PreviewPatch { blocks })
+    }
+
+    /// Forget what `view` shows, so its next update is a full refresh
+    pub fn reset(&self, view: &str) {
+        self.shown.lock().unwrap().remove(view);
+    }
+}
+
+/// Content-derived ids, numbered when identical blocks repeat (e.g. several `---` rules)
+fn block_ids(blocks: &[HtmlBlock]) -> Vec<String> {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    blocks.iter()
+        .map(|block| {
+            let mut hasher = DefaultHasher::new();
+            block.html.hash(&mut hasher);
+            let hash = hasher.finish();
+
+            let occurrence = seen.entry(hash).or_default();
+            *occurrence += 1;
+            match *occurrence {
+                1 => format!("b{:016x}", hash),
+                n => format!("b{:016x}-{}", hash, n),
+            }
+        })
+        .collect()
+}
+
+fn full_html(keyed: &[(String, &HtmlBlock)]) -> String {
+    keyed.iter().map(|(id, block)| tag_block(&block.html, id, &block.sourcepos)).collect()
+}
+
+/// Add `data-block` and `data-sourcepos` to the block's opening tag
+fn tag_block(html: &str, id: &str, sourcepos: &str) -> String {
+    if !(html.starts_with('<') && html[1..].starts_with(|c: char| c.is_ascii_alphabetic())) {
+        return html.to_string();
+    }
+    let name_end = html.find([' ', '>', '/']).unwrap_or(html.len());
+    format!(
+        "{} data-block=\"{}\" data-sourcepos=\"{}\"{}",
+        &html[..name_end], id, sourcepos, &html[name_end..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+
+    fn blocks(markdown: &str) -> Vec<HtmlBlock> {
+        MarkdownParser::new().parse(markdown).unwrap().blocks
+    }
+
+    #[test]
+    fn test_first_update_is_full() {
+        let differ = PreviewDiffer::new();
+
+        let PreviewUpdate::Full(html) = differ.update("main", &blocks("# Title\n\n---\n\n---\n")) else {
+            panic!("expected a full refresh");
+        };
+        assert!(html.contains("<h1 data-block=\"b"));
+        assert!(html.contains("data-sourcepos=\"1-1\" id=\"title\">"));
+        // Repeated blocks still get distinct ids
+        assert!(html.contains("-2\" data-sourcepos=\"5-5\" />"));
+    }
+
+    #[test]
+    fn test_patch_sends_only_new_blocks() {
+        let differ = PreviewDiffer::new();
+        differ.update("main", &blocks("# Title\n\nOne\n\nTwo\n\nThree\n"));
+
+        let PreviewUpdate::Patch(patch) = differ.update("main", &blocks("# Title\n\nNew\n\nOne\n\nTwo\n\nThree\n")) else {
+            panic!("expected a patch");
+        };
+        let sent: Vec<_> = patch.blocks.iter().filter_map(|block| block.html.as_deref()).collect();
+        assert_eq!(patch.blocks.len(), 5);
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].starts_with("<p data-block=") && sent[0].contains("data-sourcepos=\"3-3\">New</p>"));
+        // Blocks below the insertion keep their ids but move down a line range
+        assert_eq!(patch.blocks[4].sourcepos, "9-9");
+
+        differ.reset("main");
+        assert!(matches!(differ.update("main", &blocks("# Title\n")), PreviewUpdate::Full(_)));
+    }
+}
@@ -0,0 +1,353 @@
+use anyhow::{Result, Context};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{header, StatusCode, Uri};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error, info};
+
+const PLACEHOLDER_PAGE: &str = "<!DOCTYPE html><html><body><p>Nothing to preview yet.</p></body></html>";
+
+/// Path of the WebSocket that pushes re-rendered pages to open browsers
+const LIVE_RELOAD_PATH: &str = "/__live";
+
+/// Path the live-reload client is served from; a script file rather than an
+/// inline one, so pages with a Content-Security-Policy can still load it
+const LIVE_RELOAD_SCRIPT_PATH: &str = "/__live.js";
+
+/// Swaps in each pushed page without a reload, keeping the reader's scroll position
+const LIVE_RELOAD_SCRIPT: &str = r#"(function () {
+    function connect() {
+        var scheme = location.protocol === 'https:' ? 'wss://' : 'ws://';
+        var socket = new WebSocket(scheme + location.host + '/__live');
+        socket.onmessage = function (event) {
+            var next = new DOMParser().parseFromString(event.data, 'text/html');
+            var x = window.scrollX, y = window.scrollY;
+            document.title = next.title;
+            document.head.querySelectorAll('style').forEach(function (style) { style.remove(); });
+            next.head.querySelectorAll('style').forEach(function (style) { document.head.appendChild(style); });
+            document.body.innerHTML = next.body.innerHTML;
+            window.scrollTo(x, y);
+        };
+        socket.onclose = function () { setTimeout(connect, 1000); };
+    }
+    connect();
+})();
+"#;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreviewServerOptions {
+    /// Port to listen on; 0 or unset picks a free one
+    pub port: Option<u16>,
+    /// Listen on all interfaces so phones and other machines on the network can connect
+    pub lan: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewServerInfo {
+    pub port: u16,
+    /// URL for this machine
+    pub local_url: String,
+    /// URL for other devices, when listening on the network
+    pub network_url: Option<String>,
+}
+
+/// What the server currently shows
+#[derive(Default)]
+struct PreviewContent {
+    page: Option<String>,
+    /// Directory relative asset requests (images, CSS) are served from
+    asset_root: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    content: Arc<RwLock<PreviewContent>>,
+    updates: broadcast::Sender<String>,
+    /// Flips to true when the server stops, so open sockets close too
+    stopped: watch::Receiver<bool>,
+}
+
+struct RunningServer {
+    info: PreviewServerInfo,
+    shutdown: watch::Sender<bool>,
+}
+
+/// Serves the rendered document over HTTP for viewing in a real browser
+pub struct PreviewServer {
+    content: Arc<RwLock<PreviewContent>>,
+    /// Every new page, for connected live-reload sockets
+    updates: broadcast::Sender<String>,
+    running: tokio::sync::Mutex<Option<RunningServer>>,
+}
+
+impl Default for PreviewServer {
+    fn default() -> Self {
+        // Browsers only need the latest page, so a short queue is plenty
+        let (updates, _) = broadcast::channel(4);
+        Self {
+            content: Arc::default(),
+            updates,
+            running: tokio::sync::Mutex::default(),
+        }
+    }
+}
+
+impl PreviewServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start serving, or return the existing server's addresses if already running
+    pub async fn start(&self, options: &PreviewServerOptions) -> Result<PreviewServerInfo> {
+        let mut running = self.running.lock().await;
+        if let Some(server) = running.as_ref() {
+            return Ok(server.info.clone());
+        }
+
+        let ip = if options.lan { IpAddr::V4(Ipv4Addr::UNSPECIFIED) } else { IpAddr::V4(Ipv4Addr::LOCALHOST) };
+        let listener = std::net::TcpListener::bind(SocketAddr::new(ip, options.port.unwrap_or(0)))
+            .context("Failed to open preview server port")?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        let (shutdown, stopped) = watch::channel(false);
+        let mut shutdown_rx = stopped.clone();
+        let server = axum::Server::from_tcp(listener)?
+            .serve(self.router(stopped).into_make_service())
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.changed().await;
+            });
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!("Preview server stopped with an error: {}", e);
+            }
+        });
+
+        let info = PreviewServerInfo {
+            port,
+            local_url: format!("http://localhost:{}/", port),
+            network_url: options.lan.then(lan_address).flatten().map(|ip| format!("http://{}:{}/", ip, port)),
+        };
+        info!("Preview server listening on port {} (network: {})", port, options.lan);
+        *running = Some(RunningServer { info: info.clone(), shutdown });
+        Ok(info)
+    }
+
+    /// Stop the server; stopping a server that isn't running is a no-op
+    pub async fn stop(&self) {
+        if let Some(server) = self.running.lock().await.take() {
+            let _ = server.shutdown.send(true);
+            info!("Preview server on port {} stopped", server.info.port);
+        }
+    }
+
+    pub async fn info(&self) -> Option<PreviewServerInfo> {
+        self.running.lock().await.as_ref().map(|server| server.info.clone())
+    }
+
+    /// Replace the served page, and the folder its relative assets resolve against,
+    /// and push the page to every browser listening for live reloads
+    pub fn update(&self, page: String, asset_root: Option<PathBuf>) {
+        {
+            let mut content = self.content.write().unwrap();
+            content.page = Some(page.clone());
+            if asset_root.is_some() {
+                content.asset_root = asset_root;
+            }
+        }
+
+        // Sending only fails when no browser is connected
+        let _ = self.updates.send(page);
+    }
+
+    fn router(&self, stopped: watch::Receiver<bool>) -> Router {
+        Router::new()
+            .route("/", get(serve_page))
+            .route(LIVE_RELOAD_PATH, get(live_reload))
+            .route(LIVE_RELOAD_SCRIPT_PATH, get(live_reload_script))
+            .fallback(serve_asset)
+            .with_state(ServerState {
+                content: self.content.clone(),
+                updates: self.updates.clone(),
+                stopped,
+            })
+    }
+}
+
+async fn serve_page(State(state): State<ServerState>) -> Html<String> {
+    let page = state.content.read().unwrap().page.clone();
+    Html(with_live_reload(page.as_deref().unwrap_or(PLACEHOLDER_PAGE)))
+}
+
+/// Add the live-reload client just before `</body>`, or at the end of a fragment
+fn with_live_reload(page: &str) -> String {
+    let script = format!("<script src=\"{}\"></script>", LIVE_RELOAD_SCRIPT_PATH);
+    match page.rfind("</body>") {
+        Some(index) => format!("{}{}\n{}", &page[..index], script, &page[index..]),
+        None => format!("{}\n{}", page, script),
+    }
+}
+
+async fn live_reload_script() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/javascript")], LIVE_RELOAD_SCRIPT)
+}
+
+async fn live_reload(ws: WebSocketUpgrade, State(state): State<ServerState>) -> Response {
+    let updates = state.updates.subscribe();
+    ws.on_upgrade(move |socket| push_updates(socket, updates, state.stopped))
+}
+
+/// Forward new pages to one browser until it disconnects or the server stops
+async fn push_updates(
+    mut socket: WebSocket,
+    mut updates: broadcast::Receiver<String>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    debug!("Live-reload client connected");
+    loop {
+        tokio::select! {
+            _ = stopped.changed() => {
+                let _ = socket.send(Message::Close(None)).await;
+                break;
+            }
+            update = updates.recv() => match update {
+                Ok(page) => {
+                    if socket.send(Message::Text(page)).await.is_err() {
+                        break;
+                    }
+                }
+                // Pages skipped while this client was slow are stale anyway
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("Live-reload client disconnected");
+}
+
+async fn serve_asset(State(state): State<ServerState>, uri: Uri) -> Response {
+    let root = state.content.read().unwrap().asset_root.clone();
+    let Some(path) = root.and_then(|root| resolve_asset(&root, uri.path())) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    debug!("Serving preview asset {:?}", path);
+    match tokio::fs::read(&path).await {
+        Ok(data) => ([(header::CONTENT_TYPE, content_type(&path))], data).into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Map a request path onto a file under `root`, refusing anything that escapes it
+fn resolve_asset(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let decoded = percent_encoding::percent_decode_str(request_path).decode_utf8().ok()?;
+    let mut path = root.to_path_buf();
+    for segment in decoded.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        if segment == ".." || segment.contains('\\') || crate::sync::is_hidden(segment) {
+            return None;
+        }
+        path.push(segment);
+    }
+
+    // Symlinks could still point outside the root
+    let canonical = path.canonicalize().ok()?;
+    (canonical.starts_with(root.canonicalize().ok()?) && canonical.is_file()).then_some(canonical)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("md" | "txt") => "text/plain; charset=utf-8",
+        Some("pdf") => "application/pdf",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// This machine's address on the local network, found by asking the OS which
+/// interface it would route external traffic through (nothing is sent)
+fn lan_address() -> Option<IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolve_asset_stays_inside_root() {
+        let root = TempDir::new().unwrap();
+        std::fs::create_dir(root.path().join("img")).unwrap();
+        std::fs::write(root.path().join("img/my chart.png"), b"png").unwrap();
+        std::fs::write(root.path().join(".secret"), b"token").unwrap();
+
+        assert!(resolve_asset(root.path(), "/img/my%20chart.png").is_some());
+        assert!(resolve_asset(root.path(), "/img/../img/my%20chart.png").is_none());
+        assert!(resolve_asset(root.path(), "/%2e%2e/etc/passwd").is_none());
+        assert!(resolve_asset(root.path(), "/.secret").is_none());
+        assert!(resolve_asset(root.path(), "/img").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serves_page_and_assets() {
+        let root = TempDir::new().unwrap();
+        std::fs::write(root.path().join("photo.png"), b"png-bytes").unwrap();
+
+        let server = PreviewServer::new();
+        let info = server.start(&PreviewServerOptions::default()).await.unwrap();
+        server.update("<h1>Hello</h1>".to_string(), Some(root.path().to_path_buf()));
+
+        let page = reqwest::get(&info.local_url).await.unwrap().text().await.unwrap();
+        assert!(page.starts_with("<h1>Hello</h1>"));
+        assert!(page.contains(LIVE_RELOAD_PATH));
+
+        let asset = reqwest::get(format!("{}photo.png", info.local_url)).await.unwrap();
+        assert_eq!(asset.headers()["content-type"], "image/png");
+        assert_eq!(asset.bytes().await.unwrap().as_ref(), b"png-bytes");
+
+        server.stop().await;
+        assert!(server.info().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_live_reload_pushes_updates() {
+        use futures_util::StreamExt;
+
+        let server = PreviewServer::new();
+        let info = server.start(&PreviewServerOptions::default()).await.unwrap();
+        let url = format!("ws://localhost:{}{}", info.port, LIVE_RELOAD_PATH);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        // Give the server a moment to subscribe the new client
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        server.update("<html><body><p>Edited</p></body></html>".to_string(), None);
+
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await.unwrap().unwrap().unwrap();
+        assert_eq!(message.into_text().unwrap(), "<html><body><p>Edited</p></body></html>");
+
+        server.stop().await;
+    }
+}
@@ -0,0 +1,347 @@
+use anyhow::Result;
+use percent_encoding::percent_decode_str;
+use pulldown_cmark::{BrokenLink, CodeBlockKind, Event, LinkType, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::parser::MarkdownParser;
+use crate::spellcheck::SpellingIssue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemSource {
+    Spelling,
+    Lint,
+    Link,
+}
+
+/// One entry of the Problems panel
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub source: ProblemSource,
+    /// Rule id, e.g. `heading-increment` or `missing-file`
+    pub rule: String,
+    pub message: String,
+    pub start: usize,       // source byte range
+    pub end: usize,
+    pub start_line: usize,  // 1-based source line range
+    pub end_line: usize,
+}
+
+impl Problem {
+    fn new(severity: Severity, source: ProblemSource, rule: &str, message: String, span: Range<usize>) -> Self {
+        Self { severity, source, rule: rule.to_string(), message, start: span.start, end: span.end, start_line: 0, end_line: 0 }
+    }
+}
+
+/// Spelling issues as problems
+pub fn spelling_problems(issues: Vec<SpellingIssue>) -> Vec<Problem> {
+    issues.into_iter()
+        .map(|issue| Problem::new(
+            Severity::Info,
+            ProblemSource::Spelling,
+            "spelling",
+            format!("Unknown word \"{}\"", issue.word),
+            issue.start..issue.end,
+        ))
+        .collect()
+}
+
+/// Structural problems: skipped heading levels, repeated headings, more than
+/// one top-level heading, images without alt text, empty link text and code
+/// fences without a language
+pub fn lint_markdown(markdown: &str) -> Vec<Problem> {
+    let events: Vec<_> = Parser::new_ext(markdown, Options::all()).into_offset_iter().collect();
+    let mut problems = Vec::new();
+    let mut previous_level: Option<usize> = None;
+    let mut seen_headings: HashMap<(usize, String), usize> = HashMap::new();
+    let mut top_level_headings = 0;
+
+    for (i, (event, span)) in events.iter().enumerate() {
+        let span = trimmed(markdown, span);
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => {
+                let level = *level as usize;
+                if let Some(previous) = previous_level.filter(|previous| level > previous + 1) {
+                    problems.push(Problem::new(
+                        Severity::Warning,
+                        ProblemSource::Lint,
+                        "heading-increment",
+                        format!("Heading level jumps from h{} to h{}", previous, level),
+                        span.clone(),
+                    ));
+                }
+                previous_level = Some(level);
+
+                if level == 1 {
+                    top_level_headings += 1;
+                    if top_level_headings > 1 {
+                        problems.push(Problem::new(
+                            Severity::Warning,
+                            ProblemSource::Lint,
+                            "single-title",
+                            "The document has more than one top-level heading".to_string(),
+                            span.clone(),
+                        ));
+                    }
+                }
+
+                let text = inner_text(&events[i + 1..]).trim().to_lowercase();
+                let count = seen_headings.entry((level, text)).or_insert(0);
+                *count += 1;
+                if *count > 1 {
+                    problems.push(Problem::new(
+                        Severity::Warning,
+                        ProblemSource::Lint,
+                        "duplicate-heading",
+                        "Another heading at this level has the same text".to_string(),
+                        span,
+                    ));
+                }
+            }
+            Event::Start(Tag::Image(..)) if inner_text(&events[i + 1..]).trim().is_empty() => {
+                problems.push(Problem::new(
+                    Severity::Warning,
+                    ProblemSource::Lint,
+                    "image-alt-text",
+                    "Image has no alt text".to_string(),
+                    span,
+                ));
+            }
+            Event::Start(Tag::Link(..)) if matches!(events.get(i + 1), Some((Event::End(_), _))) => {
+                problems.push(Problem::new(
+                    Severity::Warning,
+                    ProblemSource::Lint,
+                    "empty-link-text",
+                    "Link has no text".to_string(),
+                    span,
+                ));
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) if info.trim().is_empty() => {
+                problems.push(Problem::new(
+                    Severity::Info,
+                    ProblemSource::Lint,
+                    "fenced-code-language",
+                    "Code block has no language, so it won't be highlighted".to_string(),
+                    span.start..span.start + markdown[span].find('\n').unwrap_or(0),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    problems
+}
+
+/// Links and images pointing nowhere: references without a definition,
+/// `#anchors` with no matching heading, and relative paths to missing files
+/// or to headings missing from another markdown file
+///
+/// Only local targets are checked, so the report works offline; relative
+/// paths are skipped when there's no `document` to resolve them against.
+pub async fn check_links(markdown: &str, document: Option<&Path>, parser: &MarkdownParser) -> Result<Vec<Problem>> {
+    let broken = RefCell::new(Vec::new());
+    let mut on_broken = |link: BrokenLink| {
+        // `[text]` on its own is usually just text in brackets
+        if link.link_type != LinkType::Shortcut {
+            broken.borrow_mut().push((link.span, link.reference.to_string()));
+        }
+        None
+    };
+    let targets: Vec<(String, Range<usize>)> = Parser::new_with_broken_link_callback(markdown, Options::all(), Some(&mut on_broken))
+        .into_offset_iter()
+        .filter_map(|(event, span)| match event {
+            Event::Start(Tag::Link(link_type, url, _) | Tag::Image(link_type, url, _))
+                if !matches!(link_type, LinkType::Autolink | LinkType::Email) => Some((url.to_string(), span)),
+            _ => None,
+        })
+        .collect();
+
+    let mut problems: Vec<Problem> = broken.into_inner().into_iter()
+        .map(|(span, reference)| Problem::new(
+            Severity::Error,
+            ProblemSource::Link,
+            "undefined-reference",
+            format!("No link definition for [{}]", reference),
+            span,
+        ))
+        .collect();
+
+    let anchors: HashSet<String> = parser.outline(markdown)?.into_iter().map(|item| item.id).collect();
+    let base_dir = document.and_then(Path::parent);
+    let mut file_anchors: HashMap<std::path::PathBuf, Option<HashSet<String>>> = HashMap::new();
+
+    for (url, span) in targets {
+        let span = trimmed(markdown, &span);
+        let url = url.trim();
+        if url.is_empty() {
+            problems.push(Problem::new(Severity::Error, ProblemSource::Link, "empty-link", "Link has no target".to_string(), span));
+            continue;
+        }
+        if is_external(url) {
+            continue;
+        }
+
+        let (path, fragment) = url.split_once('#').map_or((url, None), |(path, fragment)| (path, Some(fragment)));
+        let path = path.split('?').next().unwrap_or_default();
+        let fragment = fragment.map(|fragment| percent_decode_str(fragment).decode_utf8_lossy().to_string());
+        if path.is_empty() {
+            if let Some(fragment) = fragment.filter(|fragment| !anchors.contains(fragment)) {
+                problems.push(Problem::new(
+                    Severity::Error,
+                    ProblemSource::Link,
+                    "missing-anchor",
+                    format!("No heading with the anchor #{}", fragment),
+                    span,
+                ));
+            }
+            continue;
+        }
+
+        let Some(base_dir) = base_dir else { continue };
+        let target = base_dir.join(percent_decode_str(path).decode_utf8_lossy().as_ref());
+        if tokio::fs::metadata(&target).await.is_err() {
+            problems.push(Problem::new(
+                Severity::Error,
+                ProblemSource::Link,
+                "missing-file",
+                format!("{} doesn't exist", path),
+                span,
+            ));
+            continue;
+        }
+
+        let is_markdown = target.extension().is_some_and(|ext| ext == "md" || ext == "markdown");
+        let Some(fragment) = fragment.filter(|_| is_markdown) else { continue };
+        if !file_anchors.contains_key(&target) {
+            let anchors = tokio::fs::read_to_string(&target).await.ok()
+                .and_then(|content| parser.outline(&content).ok())
+                .map(|outline| outline.into_iter().map(|item| item.id).collect());
+            file_anchors.insert(target.clone(), anchors);
+        }
+        if file_anchors[&target].as_ref().is_some_and(|anchors| !anchors.contains(&fragment)) {
+            problems.push(Problem::new(
+                Severity::Warning,
+                ProblemSource::Link,
+                "missing-anchor",
+                format!("{} has no heading with the anchor #{}", path, fragment),
+                span,
+            ));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Fill in line numbers and order problems by position, then severity
+pub fn finish_problems(markdown: &str, mut problems: Vec<Problem>) -> Vec<Problem> {
+    problems.sort_by_key(|problem| (problem.start, problem.severity, problem.end));
+    let (mut offset, mut line) = (0, 1);
+    for problem in &mut problems {
+        let start = problem.start.min(markdown.len());
+        line += markdown.as_bytes()[offset..start].iter().filter(|&&b| b == b'\n').count();
+        offset = start;
+        problem.start_line = line;
+        let end = problem.end.clamp(start, markdown.len());
+        problem.end_line = line + markdown.as_bytes()[start..end].iter().filter(|&&b| b == b'\n').count();
+    }
+    problems
+}
+
+/// URLs with a scheme (`https:`, `mailto:`) or protocol-relative ones
+fn is_external(url: &str) -> bool {
+    if url.starts_with("//") {
+        return true;
+    }
+    // A Windows drive like `C:` is a path, not a scheme
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1 && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// The text inside the element whose start tag came just before `events`
+fn inner_text(events: &[(Event, Range<usize>)]) -> String {
+    let mut depth = 0;
+    let mut text = String::new();
+    for (event, _) in events {
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) if depth == 0 => break,
+            Event::End(_) => depth -= 1,
+            Event::Text(content) | Event::Code(content) => text.push_str(content),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// `span` without trailing newlines
+fn trimmed(markdown: &str, span: &Range<usize>) -> Range<usize> {
+    span.start..span.start + markdown[span.clone()].trim_end().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn rules(problems: &[Problem]) -> Vec<(&str, usize)> {
+        problems.iter().map(|problem| (problem.rule.as_str(), problem.start_line)).collect()
+    }
+
+    #[test]
+    fn test_lint_markdown() {
+        let markdown = "# Title\n\n### Deep\n\n## Part\n\n## part\n\n![](chart.png) [](https://example.com)\n\n```\ncode\n```\n\n# Again\n";
+        let problems = finish_problems(markdown, lint_markdown(markdown));
+
+        assert_eq!(rules(&problems), vec![
+            ("heading-increment", 3),
+            ("duplicate-heading", 7),
+            ("image-alt-text", 9),
+            ("empty-link-text", 9),
+            ("fenced-code-language", 11),
+            ("single-title", 15),
+        ]);
+        assert_eq!(&markdown[problems[0].start..problems[0].end], "### Deep");
+        assert_eq!(&markdown[problems[4].start..problems[4].end], "```");
+    }
+
+    #[tokio::test]
+    async fn test_check_links() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("other note.md"), "# Setup\n").unwrap();
+        let document = dir.path().join("index.md");
+        let markdown = "# Intro\n\n[ok](#intro) [bad](#nope) [web](https://example.com) [mail](mailto:a@b.c)\n\n\
+            [file](other%20note.md#setup) [anchor](other%20note.md#install) [gone](missing.md) [ref][nowhere] [just brackets]\n\n\
+            [empty]()\n";
+
+        let problems = finish_problems(markdown, check_links(markdown, Some(&document), &MarkdownParser::new()).await.unwrap());
+        let found: Vec<_> = problems.iter().map(|problem| (problem.rule.as_str(), problem.severity)).collect();
+        assert_eq!(found, vec![
+            ("missing-anchor", Severity::Error),
+            ("missing-anchor", Severity::Warning),
+            ("missing-file", Severity::Error),
+            ("undefined-reference", Severity::Error),
+            ("empty-link", Severity::Error),
+        ]);
+        assert_eq!(&markdown[problems[0].start..problems[0].end], "[bad](#nope)");
+        assert_eq!(problems[3].message, "No link definition for [nowhere]");
+        assert_eq!(problems[4].start_line, 7);
+
+        // Without a document, relative paths can't be checked
+        let problems = check_links(markdown, None, &MarkdownParser::new()).await.unwrap();
+        assert_eq!(problems.len(), 3);
+    }
+}
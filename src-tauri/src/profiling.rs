@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Time spent in one phase of a parse or export
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub ms: f64,
+}
+
+/// Splits an operation into named phases, each lasting from the previous lap
+pub struct PhaseTimer {
+    last: Instant,
+    phases: Vec<PhaseTiming>,
+}
+
+impl Default for PhaseTimer {
+    fn default() -> Self {
+        Self { last: Instant::now(), phases: Vec::new() }
+    }
+}
+
+impl PhaseTimer {
+    pub fn start() -> Self {
+        Self::default()
+    }
+
+    /// Close the current phase under `phase`, adding to it if it was seen before
+    pub fn lap(&mut self, phase: &str) {
+        let now = Instant::now();
+        let ms = now.duration_since(self.last).as_secs_f64() * 1000.0;
+        self.last = now;
+
+        match self.phases.iter_mut().find(|timing| timing.phase == phase) {
+            Some(timing) => timing.ms += ms,
+            None => self.phases.push(PhaseTiming { phase: phase.to_string(), ms }),
+        }
+    }
+
+    /// Append phases timed elsewhere, e.g. inside the parser
+    pub fn extend(&mut self, phases: &[PhaseTiming]) {
+        self.phases.extend_from_slice(phases);
+        self.last = Instant::now();
+    }
+
+    pub fn finish(self) -> Vec<PhaseTiming> {
+        self.phases
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationKind {
+    Parse,
+    Export,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationProfile {
+    pub kind: OperationKind,
+    /// The document or output file involved
+    pub label: String,
+    pub timestamp: u64, // Unix timestamp in milliseconds
+    pub input_bytes: usize,
+    pub total_ms: f64,
+    pub phases: Vec<PhaseTiming>,
+}
+
+/// Aggregate of one phase across the profiled operations
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PhaseSummary {
+    pub kind: OperationKind,
+    pub phase: String,
+    pub count: usize,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerformanceProfile {
+    pub app_version: String,
+    /// Newest first
+    pub operations: Vec<OperationProfile>,
+    pub summary: Vec<PhaseSummary>,
+}
+
+/// Keeps timing breakdowns of recent parses and exports for bug reports
+pub struct Profiler {
+    capacity: usize,
+    recent: Mutex<VecDeque<OperationProfile>>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            capacity: 100,
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn record(&self, kind: OperationKind, label: &str, input_bytes: usize, phases: Vec<PhaseTiming>) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(OperationProfile {
+            kind,
+            label: label.to_string(),
+            timestamp,
+            input_bytes,
+            total_ms: phases.iter().map(|timing| timing.ms).sum(),
+            phases,
+        });
+    }
+
+    /// The last `limit` operations, with per-phase averages over them
+    pub fn profile(&self, limit: usize) -> PerformanceProfile {
+        let operations: Vec<OperationProfile> = self.recent.lock().unwrap()
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect();
+
+        let mut summary: Vec<PhaseSummary> = Vec::new();
+        for operation in &operations {
+            for timing in &operation.phases {
+                match summary.iter_mut().find(|s| s.kind == operation.kind && s.phase == timing.phase) {
+                    Some(s) => {
+                        s.mean_ms += timing.ms;
+                        s.max_ms = s.max_ms.max(timing.ms);
+                        s.count += 1;
+                    }
+                    None => summary.push(PhaseSummary {
+                        kind: operation.kind,
+                        phase: timing.phase.clone(),
+                        count: 1,
+                        mean_ms: timing.ms,
+                        max_ms: timing.ms,
+                    }),
+                }
+            }
+        }
+        // Totals were accumulated in mean_ms until now
+        for s in &mut summary {
+            s.mean_ms /= s.count as f64;
+        }
+
+        PerformanceProfile {
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            operations,
+            summary,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(phase: &str, ms: f64) -> PhaseTiming {
+        PhaseTiming { phase: phase.to_string(), ms }
+    }
+
+    #[test]
+    fn test_phase_timer_merges_repeated_phases() {
+        let mut timer = PhaseTimer::start();
+        timer.lap("io");
+        timer.lap("render");
+        timer.extend(&[timing("highlighting", 2.0)]);
+        timer.lap("io");
+
+        let phases: Vec<_> = timer.finish().into_iter().map(|timing| timing.phase).collect();
+        assert_eq!(phases, vec!["io", "render", "highlighting"]);
+    }
+
+    #[test]
+    fn test_profile_keeps_recent_operations() {
+        let profiler = Profiler::new().with_capacity(3);
+        for i in 0..4 {
+            profiler.record(OperationKind::Parse, &format!("doc{}", i), 10, vec![timing("html", i as f64)]);
+        }
+        profiler.record(OperationKind::Export, "out.pdf", 10, vec![timing("pdf", 5.0), timing("io", 1.0)]);
+
+        let profile = profiler.profile(10);
+        let labels: Vec<_> = profile.operations.iter().map(|op| op.label.as_str()).collect();
+        assert_eq!(labels, vec!["out.pdf", "doc3", "doc2"]);
+        assert_eq!(profile.operations[0].total_ms, 6.0);
+
+        let html = profile.summary.iter().find(|s| s.phase == "html").unwrap();
+        assert_eq!((html.count, html.mean_ms, html.max_ms), (2, 2.5, 3.0));
+        assert_eq!(profiler.profile(1).operations.len(), 1);
+    }
+}
@@ -0,0 +1,379 @@
+use anyhow::{Result, Context};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::frontmatter::FrontMatter;
+use crate::settings::PublishSettings;
+
+/// Dev.to only accepts up to four tags per article
+const DEVTO_MAX_TAGS: usize = 4;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PublishTarget {
+    DevTo,
+    Ghost,
+    Medium,
+}
+
+impl PublishTarget {
+    pub const ALL: [PublishTarget; 3] = [PublishTarget::DevTo, PublishTarget::Ghost, PublishTarget::Medium];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            PublishTarget::DevTo => "DEV Community",
+            PublishTarget::Ghost => "Ghost",
+            PublishTarget::Medium => "Medium",
+        }
+    }
+
+    /// Keychain entry holding this target's API credential
+    pub fn credential_key(&self) -> &'static str {
+        match self {
+            PublishTarget::DevTo => "devto-api-key",
+            PublishTarget::Ghost => "ghost-admin-key",
+            PublishTarget::Medium => "medium-token",
+        }
+    }
+
+    /// Where `settings` send this target's credential; `None` for targets with a fixed host
+    pub fn site_url<'a>(&self, settings: &'a PublishSettings) -> Option<&'a str> {
+        match self {
+            PublishTarget::Ghost => settings.ghost_url.as_deref(),
+            PublishTarget::DevTo | PublishTarget::Medium => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishTargetInfo {
+    pub target: PublishTarget,
+    pub name: String,
+    pub configured: bool,
+}
+
+/// A document prepared for publishing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub canonical_url: Option<String>,
+    /// Markdown body with front matter and the title heading removed
+    pub markdown: String,
+    pub html: String,
+    /// Publish immediately instead of creating a draft
+    pub publish: bool,
+}
+
+impl Post {
+    /// Build a post from a document, taking title, tags and other metadata from front matter
+    ///
+    /// Without a front matter title, a leading `# Heading` is used and removed
+    /// from the body so it isn't shown twice. `render` turns the body into HTML.
+    pub fn from_document<F>(markdown: &str, publish: bool, render: F) -> Result<Post>
+    where
+        F: FnOnce(&str) -> Result<String>,
+    {
+        let (front_matter, body) = FrontMatter::parse(markdown)?;
+        let body = body.trim_start();
+
+        let (title, body) = match front_matter.title() {
+            Some(title) => (title, body),
+            None => match body.lines().next().and_then(|line| line.strip_prefix("# ")) {
+                Some(heading) => {
+                    let rest = body.split_once('\n').map_or("", |(_, rest)| rest);
+                    (heading.trim().to_string(), rest.trim_start())
+                }
+                None => ("Untitled".to_string(), body),
+            },
+        };
+
+        Ok(Post {
+            title,
+            tags: front_matter.tags(),
+            description: front_matter.get_str("description"),
+            canonical_url: front_matter.get_str("canonical_url"),
+            html: render(body)?,
+            markdown: body.to_string(),
+            publish: publish || front_matter.get_bool("published").unwrap_or(false),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishResult {
+    pub target: PublishTarget,
+    pub id: String,
+    pub url: Option<String>,
+}
+
+/// Pushes posts to blogging platforms through their APIs
+pub struct PublishService {
+    client: reqwest::Client,
+}
+
+impl Default for PublishService {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("Typora-Lite/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .unwrap_or_default();
+
+        Self { client }
+    }
+}
+
+impl PublishService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn publish(
+        &self,
+        target: PublishTarget,
+        post: &Post,
+        credential: &str,
+        settings: &PublishSettings,
+    ) -> Result<PublishResult> {
+        debug!("Publishing '{}' to {:?} (publish: {})", post.title, target, post.publish);
+
+        let result = match target {
+            PublishTarget::DevTo => self.publish_devto(post, credential).await,
+            PublishTarget::Ghost => self.publish_ghost(post, credential, settings).await,
+            PublishTarget::Medium => self.publish_medium(post, credential).await,
+        }
+        .with_context(|| format!("Failed to publish to {}", target.display_name()))?;
+
+        info!("Published '{}' to {:?}: {:?}", post.title, target, result.url);
+        Ok(result)
+    }
+
+    async fn publish_devto(&self, post: &Post, api_key: &str) -> Result<PublishResult> {
+        let response: Value = self.client
+            .post("https://dev.to/api/articles")
+            .header("api-key", api_key)
+            .json(&build_devto_body(post))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(PublishResult {
+            target: PublishTarget::DevTo,
+            id: response["id"].to_string(),
+            url: response["url"].as_str().map(String::from),
+        })
+    }
+
+    async fn publish_ghost(&self, post: &Post, admin_key: &str, settings: &PublishSettings) -> Result<PublishResult> {
+        let site = settings.ghost_url.as_deref()
+            .filter(|url| !url.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("No Ghost site URL configured"))?;
+        let url = format!("{}/ghost/api/admin/posts/?source=html", site.trim_end_matches('/'));
+
+        let response: Value = self.client
+            .post(&url)
+            .header("Authorization", format!("Ghost {}", ghost_admin_token(admin_key, unix_now())?))
+            .json(&build_ghost_body(post))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let created = &response["posts"][0];
+        Ok(PublishResult {
+            target: PublishTarget::Ghost,
+            id: created["id"].as_str().unwrap_or_default().to_string(),
+            url: created["url"].as_str().map(String::from),
+        })
+    }
+
+    async fn publish_medium(&self, post: &Post, token: &str) -> Result<PublishResult> {
+        // Posts are created under the author the token belongs to
+        let me: Value = self.client
+            .get("https://api.medium.com/v1/me")
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let author_id = me["data"]["id"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Medium did not return the author id"))?;
+
+        let response: Value = self.client
+            .post(format!("https://api.medium.com/v1/users/{}/posts", author_id))
+            .bearer_auth(token)
+            .json(&build_medium_body(post))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(PublishResult {
+            target: PublishTarget::Medium,
+            id: response["data"]["id"].as_str().unwrap_or_default().to_string(),
+            url: response["data"]["url"].as_str().map(String::from),
+        })
+    }
+}
+
+fn build_devto_body(post: &Post) -> Value {
+    // Dev.to tags are lowercase alphanumerics
+    let tags: Vec<String> = post.tags
+        .iter()
+        .map(|tag| tag.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .take(DEVTO_MAX_TAGS)
+        .collect();
+
+    json!({
+        "article": {
+            "title": post.title,
+            "body_markdown": post.markdown,
+            "published": post.publish,
+            "tags": tags,
+            "description": post.description,
+            "canonical_url": post.canonical_url,
+        }
+    })
+}
+
+fn build_ghost_body(post: &Post) -> Value {
+    json!({
+        "posts": [{
+            "title": post.title,
+            "html": post.html,
+            "status": if post.publish { "published" } else { "draft" },
+            "tags": post.tags,
+            "custom_excerpt": post.description,
+            "canonical_url": post.canonical_url,
+        }]
+    })
+}
+
+fn build_medium_body(post: &Post) -> Value {
+    // Medium renders the title from the content, so it is prepended as a heading
+    json!({
+        "title": post.title,
+        "contentFormat": "markdown",
+        "content": format!("# {}\n\n{}", post.title, post.markdown),
+        "tags": post.tags.iter().take(5).collect::<Vec<_>>(),
+        "publishStatus": if post.publish { "public" } else { "draft" },
+        "canonicalUrl": post.canonical_url,
+    })
+}
+
+/// Short-lived JWT for the Ghost Admin API, signed with an `id:secret` admin key
+fn ghost_admin_token(admin_key: &str, now: u64) -> Result<String> {
+    let (id, secret) = admin_key.trim().split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Ghost admin key must have the form <id>:<secret>"))?;
+    let secret = decode_hex(secret).context("Ghost admin key secret is not valid hex")?;
+
+    let header = json!({ "alg": "HS256", "typ": "JWT", "kid": id });
+    let claims = json!({ "iat": now, "exp": now + 5 * 60, "aud": "/admin/" });
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&secret)
+        .map_err(|e| anyhow::anyhow!("Invalid Ghost admin key: {}", e))?;
+    mac.update(signing_input.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+    Ok(format!("{}.{}", signing_input, signature))
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return Err(anyhow::anyhow!("Odd number of hex digits"));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(Into::into))
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(markdown: &str) -> Result<String> {
+        Ok(format!("<p>{}</p>", markdown.trim()))
+    }
+
+    #[test]
+    fn test_post_from_document() {
+        let markdown = "---\ntitle: Shipping Rust\ntags: [rust, Web-Dev]\ndescription: Notes\n---\nBody text\n";
+        let post = Post::from_document(markdown, false, render).unwrap();
+        assert_eq!(post.title, "Shipping Rust");
+        assert_eq!(post.tags, vec!["rust", "Web-Dev"]);
+        assert_eq!(post.markdown, "Body text\n");
+        assert_eq!(post.html, "<p>Body text</p>");
+
+        // Without front matter the first heading becomes the title
+        let post = Post::from_document("# Heading Title\n\nFirst paragraph", true, render).unwrap();
+        assert_eq!(post.title, "Heading Title");
+        assert_eq!(post.markdown, "First paragraph");
+        assert!(post.publish);
+    }
+
+    #[test]
+    fn test_request_bodies_per_target() {
+        let post = Post::from_document(
+            "---\ntitle: T\ntags: [rust, Web-Dev, a, b, c]\n---\nBody",
+            false,
+            render,
+        ).unwrap();
+
+        let devto = build_devto_body(&post);
+        assert_eq!(devto["article"]["tags"], json!(["rust", "webdev", "a", "b"]));
+        assert_eq!(devto["article"]["published"], false);
+
+        let ghost = build_ghost_body(&post);
+        assert_eq!(ghost["posts"][0]["status"], "draft");
+        assert_eq!(ghost["posts"][0]["html"], "<p>Body</p>");
+
+        let medium = build_medium_body(&post);
+        assert_eq!(medium["publishStatus"], "draft");
+        assert!(medium["content"].as_str().unwrap().starts_with("# T\n\nBody"));
+    }
+
+    #[test]
+    fn test_ghost_admin_token() {
+        let token = ghost_admin_token("abc123:00ff10", 1_700_000_000).unwrap();
+        let parts: Vec<&str> = token.split('.').collect();
+        assert_eq!(parts.len(), 3);
+
+        let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[0]).unwrap()).unwrap();
+        let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        assert_eq!(header["kid"], "abc123");
+        assert_eq!(claims["exp"], 1_700_000_300);
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(&[0x00, 0xff, 0x10]).unwrap();
+        mac.update(format!("{}.{}", parts[0], parts[1]).as_bytes());
+        assert!(mac.verify_slice(&URL_SAFE_NO_PAD.decode(parts[2]).unwrap()).is_ok());
+
+        assert!(ghost_admin_token("missing-secret", 0).is_err());
+    }
+}
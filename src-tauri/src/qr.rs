@@ -0,0 +1,61 @@
+use anyhow::{Result, Context};
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+
+/// Info string of fenced code blocks rendered as QR codes
+pub const QR_FENCE: &str = "qrcode";
+
+/// Smallest side of a rendered code in pixels, large enough to scan from paper
+const MIN_SIZE: u32 = 160;
+
+/// Render a ```` ```qrcode ```` fence to an inline SVG QR code of its trimmed
+/// content, or to an error block when the content can't be encoded
+pub fn render_qr_fence(content: &str) -> String {
+    match qr_code_svg(content) {
+        Ok(svg) => format!("<div class=\"qrcode\">{}</div>\n", svg),
+        Err(e) => format!(
+            "<pre class=\"qrcode-error\">{}</pre>\n",
+            html_escape::encode_text(&format!("{:#}", e))
+        ),
+    }
+}
+
+/// An SVG QR code encoding `content`, labelled with it for screen readers
+pub fn qr_code_svg(content: &str) -> Result<String> {
+    let content = content.trim();
+    if content.is_empty() {
+        anyhow::bail!("QR code block is empty");
+    }
+    // Medium error correction survives smudged prints without growing the code much
+    let code = QrCode::with_error_correction_level(content.as_bytes(), EcLevel::M)
+        .context("Content is too long for a QR code")?;
+    let svg = code.render::<svg::Color>()
+        .min_dimensions(MIN_SIZE, MIN_SIZE)
+        .quiet_zone(true)
+        .build();
+
+    // Inline SVG can't carry the XML declaration
+    let svg = svg.find("<svg").map_or(svg.as_str(), |start| &svg[start..]);
+    let label = html_escape::encode_double_quoted_attribute(content);
+    Ok(svg.replacen("<svg", &format!("<svg role=\"img\" aria-label=\"QR code: {}\"", label), 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qr_code_svg() {
+        let svg = qr_code_svg("  https://example.com/?a=1&b=2\n").unwrap();
+        assert!(svg.starts_with("<svg role=\"img\" aria-label=\"QR code: https://example.com/?a=1&amp;b=2\" xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        assert!(!svg.contains("<?xml"));
+    }
+
+    #[test]
+    fn test_render_qr_fence_errors() {
+        assert_eq!(render_qr_fence("\n"), "<pre class=\"qrcode-error\">QR code block is empty</pre>\n");
+        assert!(render_qr_fence(&"x".repeat(5000)).contains("too long"));
+        assert!(render_qr_fence("hello").starts_with("<div class=\"qrcode\"><svg "));
+    }
+}
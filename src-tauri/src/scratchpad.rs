@@ -0,0 +1,150 @@
+use anyhow::{Result, Context};
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::encryption::is_encrypted_path;
+use crate::settings::ScratchpadSettings;
+
+/// Heading of a newly created inbox
+const INBOX_HEADER: &str = "# Inbox\n\n";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuickNote {
+    pub path: PathBuf,
+    /// The markdown appended, without the separating newline
+    pub entry: String,
+    /// Whether the inbox was just created
+    pub created: bool,
+}
+
+/// Appends quick notes to the inbox file, one timestamped list item each
+#[derive(Default)]
+pub struct Scratchpad {
+    // Captures can arrive from several windows and the global shortcut at once
+    lock: Mutex<()>,
+}
+
+impl Scratchpad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn append(&self, inbox: &Path, text: &str, at: NaiveDateTime, settings: &ScratchpadSettings) -> Result<QuickNote> {
+        validate_scratchpad_settings(settings)?;
+        if text.trim().is_empty() {
+            anyhow::bail!("Quick note is empty");
+        }
+        // Appending plain text to an encrypted file would corrupt it
+        if is_encrypted_path(inbox) {
+            anyhow::bail!("The inbox can't be an encrypted document: {:?}", inbox);
+        }
+
+        let _guard = self.lock.lock().await;
+        let existing = match tokio::fs::read(inbox).await {
+            Ok(existing) => Some(existing),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read inbox: {:?}", inbox)),
+        };
+
+        let entry = format_entry(text, &at.format(&settings.timestamp_format).to_string());
+        let mut appended = match &existing {
+            None => INBOX_HEADER.to_string(),
+            Some(existing) if !existing.is_empty() && !existing.ends_with(b"\n") => "\n".to_string(),
+            Some(_) => String::new(),
+        };
+        appended.push_str(&entry);
+        appended.push('\n');
+
+        if let Some(parent) = inbox.parent().filter(|_| existing.is_none()) {
+            tokio::fs::create_dir_all(parent).await
+                .with_context(|| format!("Failed to create inbox folder: {:?}", parent))?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(inbox)
+            .await
+            .with_context(|| format!("Failed to open inbox: {:?}", inbox))?;
+        file.write_all(appended.as_bytes()).await
+            .with_context(|| format!("Failed to append to inbox: {:?}", inbox))?;
+        file.flush().await?;
+
+        if existing.is_none() {
+            info!("Created inbox {:?}", inbox);
+        }
+        debug!("Appended {} chars to inbox {:?}", entry.len(), inbox);
+        Ok(QuickNote { path: inbox.to_path_buf(), entry, created: existing.is_none() })
+    }
+}
+
+/// Reject timestamp formats chrono can't render
+pub fn validate_scratchpad_settings(settings: &ScratchpadSettings) -> Result<()> {
+    let format = &settings.timestamp_format;
+    if format.trim().is_empty() || StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow::anyhow!("Invalid quick note timestamp format: {}", format));
+    }
+    Ok(())
+}
+
+/// `- **timestamp** text`, with any further lines indented to stay in the list item
+fn format_entry(text: &str, timestamp: &str) -> String {
+    let mut lines = text.trim().lines();
+    let mut entry = format!("- **{}** {}", timestamp, lines.next().unwrap_or_default().trim_end());
+    for line in lines {
+        entry.push('\n');
+        if !line.trim().is_empty() {
+            entry.push_str("  ");
+            entry.push_str(line.trim_end());
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use tempfile::TempDir;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, 4).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_append_creates_inbox_and_adds_entries() {
+        let dir = TempDir::new().unwrap();
+        let inbox = dir.path().join("notes/Inbox.md");
+        let scratchpad = Scratchpad::new();
+        let settings = ScratchpadSettings::default();
+
+        let first = scratchpad.append(&inbox, "Call the printer", at(9, 5), &settings).await.unwrap();
+        assert!(first.created);
+        let second = scratchpad.append(&inbox, "  Idea:\nsplit chapter 3\n\ninto two\n", at(14, 30), &settings).await.unwrap();
+        assert!(!second.created);
+
+        let content = std::fs::read_to_string(&inbox).unwrap();
+        assert_eq!(content, "# Inbox\n\n- **2024-03-04 09:05** Call the printer\n- **2024-03-04 14:30** Idea:\n  split chapter 3\n\n  into two\n");
+    }
+
+    #[tokio::test]
+    async fn test_append_to_existing_file_and_rejects_bad_input() {
+        let dir = TempDir::new().unwrap();
+        let inbox = dir.path().join("inbox.md");
+        std::fs::write(&inbox, "My notes").unwrap();
+        let scratchpad = Scratchpad::new();
+        let settings = ScratchpadSettings { timestamp_format: "%H:%M".to_string(), ..ScratchpadSettings::default() };
+
+        scratchpad.append(&inbox, "milk", at(8, 0), &settings).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&inbox).unwrap(), "My notes\n- **08:00** milk\n");
+
+        assert!(scratchpad.append(&inbox, "  \n", at(8, 0), &settings).await.is_err());
+        assert!(scratchpad.append(&dir.path().join("inbox.md.enc"), "x", at(8, 0), &settings).await.is_err());
+        let bad = ScratchpadSettings { timestamp_format: "%Q".to_string(), ..ScratchpadSettings::default() };
+        assert!(scratchpad.append(&inbox, "x", at(8, 0), &bad).await.is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::{Result, Context};
+use tracing::debug;
+
+/// Keychain service name under which all Typora-Lite credentials are stored
+const KEYCHAIN_SERVICE: &str = "typolite";
+
+/// Stores API tokens in the OS keychain rather than in the settings file
+pub struct SecretStore {
+    service: String,
+}
+
+impl Default for SecretStore {
+    fn default() -> Self {
+        Self {
+            service: KEYCHAIN_SERVICE.to_string(),
+        }
+    }
+}
+
+impl SecretStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        match self.entry(key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read '{}' from the keychain", key)),
+        }
+    }
+
+    /// Store a secret, or remove it when `secret` is empty
+    pub fn set(&self, key: &str, secret: &str) -> Result<()> {
+        let entry = self.entry(key)?;
+
+        if secret.is_empty() {
+            debug!("Removing '{}' from the keychain", key);
+            return match entry.delete_password() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(e).with_context(|| format!("Failed to remove '{}' from the keychain", key)),
+            };
+        }
+
+        debug!("Storing '{}' in the keychain", key);
+        entry.set_password(secret)
+            .with_context(|| format!("Failed to store '{}' in the keychain", key))
+    }
+
+    fn entry(&self, key: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new(&self.service, key)
+            .with_context(|| format!("Invalid keychain entry: {}", key))
+    }
+}
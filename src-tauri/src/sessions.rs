@@ -0,0 +1,189 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::settings::app_config_dir;
+
+/// Time spent writing a single document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionStats {
+    pub path: PathBuf,
+    pub total_seconds: u64,
+    pub session_count: u32,
+    pub active_since: Option<u64>, // Unix timestamp of the open session, if any
+    pub last_active: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DocumentSessions {
+    total_seconds: u64,
+    session_count: u32,
+    last_active: u64,
+    /// Start and last activity of the session in progress
+    current: Option<(u64, u64)>,
+}
+
+impl DocumentSessions {
+    fn close_current(&mut self) {
+        if let Some((start, last)) = self.current.take() {
+            self.total_seconds += last.saturating_sub(start);
+        }
+    }
+}
+
+/// Tracks editing sessions per document from activity signals
+///
+/// Each save or re-render counts as activity. Activity within `idle_timeout`
+/// of the previous one extends the current session; a longer gap closes it
+/// and starts a new one, so time away from the keyboard isn't counted.
+pub struct SessionTracker {
+    path: PathBuf,
+    idle_timeout: Duration,
+    documents: Mutex<HashMap<PathBuf, DocumentSessions>>,
+}
+
+impl Default for SessionTracker {
+    fn default() -> Self {
+        Self::load(app_config_dir().join("sessions.json"))
+    }
+}
+
+impl SessionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load session history, closing any sessions left open by a previous run
+    pub fn load(path: PathBuf) -> Self {
+        let mut documents: HashMap<PathBuf, DocumentSessions> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Invalid session history {:?}, starting fresh: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        documents.values_mut().for_each(DocumentSessions::close_current);
+
+        Self {
+            path,
+            idle_timeout: Duration::from_secs(5 * 60),
+            documents: Mutex::new(documents),
+        }
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub fn record_activity(&self, document: &Path) {
+        self.record_activity_at(document, unix_now());
+    }
+
+    pub fn record_activity_at(&self, document: &Path, now: u64) {
+        let mut documents = self.documents.lock().unwrap();
+        let sessions = documents.entry(document.to_path_buf()).or_default();
+
+        match sessions.current {
+            Some((start, last)) if now.saturating_sub(last) <= self.idle_timeout.as_secs() => {
+                sessions.current = Some((start, now.max(last)));
+            }
+            _ => {
+                sessions.close_current();
+                sessions.current = Some((now, now));
+                sessions.session_count += 1;
+                debug!("Started editing session for {:?}", document);
+            }
+        }
+        sessions.last_active = now;
+    }
+
+    /// Stats for one document, or for every tracked document (most recently active first)
+    pub fn stats(&self, document: Option<&Path>) -> Vec<SessionStats> {
+        let documents = self.documents.lock().unwrap();
+        let mut stats: Vec<SessionStats> = documents
+            .iter()
+            .filter(|(path, _)| document.map_or(true, |d| d == path.as_path()))
+            .map(|(path, sessions)| SessionStats {
+                path: path.clone(),
+                total_seconds: sessions.total_seconds
+                    + sessions.current.map_or(0, |(start, last)| last.saturating_sub(start)),
+                session_count: sessions.session_count,
+                active_since: sessions.current.map(|(start, _)| start),
+                last_active: sessions.last_active,
+            })
+            .collect();
+
+        stats.sort_by(|a, b| b.last_active.cmp(&a.last_active));
+        stats
+    }
+
+    /// Persist session history to disk
+    pub async fn save(&self) -> Result<()> {
+        let json = serde_json::to_string(&*self.documents.lock().unwrap())?;
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await
+                .with_context(|| format!("Failed to create session directory: {:?}", parent))?;
+        }
+        tokio::fs::write(&self.path, json).await
+            .with_context(|| format!("Failed to write session history: {:?}", self.path))?;
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_idle_gap_starts_new_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let tracker = SessionTracker::load(temp_dir.path().join("sessions.json"))
+            .with_idle_timeout(Duration::from_secs(300));
+        let doc = Path::new("/notes/draft.md");
+
+        tracker.record_activity_at(doc, 1_000);
+        tracker.record_activity_at(doc, 1_200);
+        tracker.record_activity_at(doc, 1_400);
+        // Twenty minutes away from the document
+        tracker.record_activity_at(doc, 2_600);
+        tracker.record_activity_at(doc, 2_700);
+
+        let stats = tracker.stats(Some(doc));
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].total_seconds, 400 + 100);
+        assert_eq!(stats[0].session_count, 2);
+        assert_eq!(stats[0].active_since, Some(2_600));
+    }
+
+    #[tokio::test]
+    async fn test_history_persists_and_closes_open_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("sessions.json");
+        let tracker = SessionTracker::load(path.clone());
+        tracker.record_activity_at(Path::new("a.md"), 100);
+        tracker.record_activity_at(Path::new("a.md"), 160);
+        tracker.record_activity_at(Path::new("b.md"), 200);
+        tracker.save().await.unwrap();
+
+        let reloaded = SessionTracker::load(path);
+        let stats = reloaded.stats(None);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].path, PathBuf::from("b.md"));
+        assert_eq!(stats[1].total_seconds, 60);
+        assert!(stats.iter().all(|s| s.active_since.is_none()));
+    }
+}
@@ -0,0 +1,684 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::export::ExportFormat;
+
+/// User settings persisted as JSON in the app config directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Shortcut overrides keyed by action id
+    pub shortcuts: BTreeMap<String, String>,
+    pub grammar: GrammarSettings,
+    pub ai: AiSettings,
+    /// External HTML post-processing filters, run in order
+    pub html_filters: Vec<HtmlFilter>,
+    pub publishing: PublishSettings,
+    pub static_site: StaticSiteSettings,
+    pub confluence: ConfluenceSettings,
+    pub email: EmailSettings,
+    pub sync: SyncSettings,
+    pub api: ApiSettings,
+    pub zotero: ZoteroSettings,
+    pub journal: JournalSettings,
+    pub feed: FeedSettings,
+    pub export: ExportSettings,
+    /// Exports run after every save, for documents outside any workspace as well
+    pub export_on_save: Vec<ExportOnSaveRule>,
+    pub workers: WorkerSettings,
+    pub file_access: FileAccessSettings,
+    pub security: SecuritySettings,
+    pub parser: ParserSettings,
+    pub footnotes: FootnoteSettings,
+    pub pdf_signing: PdfSigningSettings,
+    pub goals: GoalSettings,
+    pub scratchpad: ScratchpadSettings,
+}
+
+/// Where exports keep their scratch files, and for how long
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    /// Defaults to `typolite-exports` in the system temp folder
+    pub temp_dir: Option<PathBuf>,
+    /// Scratch files older than this are removed at startup
+    pub temp_max_age_hours: u64,
+    /// Chrome, Chromium or Edge executable PDFs are printed with; found
+    /// automatically when unset
+    pub browser_path: Option<PathBuf>,
+    /// mermaid-cli (`mmdc`) executable diagrams are drawn with; found on `PATH`
+    /// when unset
+    pub mermaid_cli: Option<PathBuf>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            temp_dir: None,
+            temp_max_age_hours: 24,
+            browser_path: None,
+            mermaid_cli: None,
+        }
+    }
+}
+
+/// An export run after each successful save, like "on save, export HTML to ./public/"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportOnSaveRule {
+    pub format: ExportFormat,
+    /// Relative folders start at the workspace root for workspace rules and at
+    /// the saved document's folder otherwise
+    pub output_dir: PathBuf,
+    /// Only export this document, resolved like `output_dir`; every document when unset
+    #[serde(default)]
+    pub document: Option<PathBuf>,
+}
+
+/// Where quick notes are captured
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScratchpadSettings {
+    /// Absolute, or relative to the active workspace (or the Documents folder without one)
+    pub inbox: PathBuf,
+    /// chrono format for each note's timestamp
+    pub timestamp_format: String,
+}
+
+impl Default for ScratchpadSettings {
+    fn default() -> Self {
+        Self {
+            inbox: PathBuf::from("Inbox.md"),
+            timestamp_format: "%Y-%m-%d %H:%M".to_string(),
+        }
+    }
+}
+
+/// Word-count targets reported in `goal-progress` events; unset goals are off
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GoalSettings {
+    /// Words to add each day, across all documents
+    pub daily_words: Option<usize>,
+    /// Length to aim for in every document, unless its front matter sets `word_goal`
+    pub document_words: Option<usize>,
+}
+
+/// Certificate and key for signed PDF exports, and what the signature says
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfSigningSettings {
+    /// PEM file with the signing certificate, optionally followed by its chain
+    pub certificate_path: Option<PathBuf>,
+    /// PEM file with the unencrypted private key
+    pub key_path: Option<PathBuf>,
+    pub name: Option<String>,
+    pub reason: Option<String>,
+    pub location: Option<String>,
+    pub contact_info: Option<String>,
+}
+
+/// Hardening of rendered HTML against malicious markdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecuritySettings {
+    /// Strip inline event handlers from previews and exports, and give exported
+    /// and served pages a strict Content-Security-Policy
+    pub content_security_policy: bool,
+}
+
+impl Default for SecuritySettings {
+    fn default() -> Self {
+        Self { content_security_policy: true }
+    }
+}
+
+/// How footnote markers are labelled
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum FootnoteNumbering {
+    #[default]
+    Numeric,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+    /// *, †, ‡, §, ‖, ¶, then doubled
+    Symbols,
+}
+
+/// Where footnote definitions are rendered
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum FootnotePlacement {
+    /// Collected into a footnote section at the end of the document
+    #[default]
+    End,
+    /// Where they are written in the source
+    Inline,
+}
+
+/// Rendering of footnotes in the preview and in exports
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FootnoteSettings {
+    /// Heading of the footnote section; only used when placed at the end
+    pub title: Option<String>,
+    /// Rule above the footnote section; only used when placed at the end
+    pub separator: bool,
+    pub numbering: FootnoteNumbering,
+    pub placement: FootnotePlacement,
+}
+
+impl Default for FootnoteSettings {
+    fn default() -> Self {
+        Self {
+            title: Some("Notes".to_string()),
+            separator: true,
+            numbering: FootnoteNumbering::Numeric,
+            placement: FootnotePlacement::End,
+        }
+    }
+}
+
+/// Limits that stop pathological documents from freezing the renderer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParserSettings {
+    pub max_input_bytes: usize,
+    pub max_nesting_depth: usize,
+    pub timeout_ms: u64,
+}
+
+impl Default for ParserSettings {
+    fn default() -> Self {
+        Self {
+            max_input_bytes: 64 * 1024 * 1024,
+            max_nesting_depth: 256,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Which files the app may read and write on the webview's behalf
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileAccessSettings {
+    /// Limit file commands to the open workspace, opened files and `allowed_roots`
+    pub restrict: bool,
+    pub allowed_roots: Vec<PathBuf>,
+    /// Files picked in the open dialog, most recent first, so they can be reopened
+    /// after a restart wherever they are
+    pub opened_files: Vec<PathBuf>,
+}
+
+impl Default for FileAccessSettings {
+    fn default() -> Self {
+        Self {
+            restrict: true,
+            allowed_roots: Vec::new(),
+            opened_files: Vec::new(),
+        }
+    }
+}
+
+impl FileAccessSettings {
+    /// Most files remembered in `opened_files`
+    pub const MAX_OPENED_FILES: usize = 100;
+
+    /// Remember `path` as picked by the user
+    pub fn record_opened_file(&mut self, path: &Path) {
+        self.opened_files.retain(|opened| opened != path);
+        self.opened_files.insert(0, path.to_path_buf());
+        self.opened_files.truncate(Self::MAX_OPENED_FILES);
+    }
+
+    /// What `other` allows that these settings don't, described for the user;
+    /// empty when it allows nothing more
+    pub fn loosened_by(&self, other: &FileAccessSettings) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.restrict && !other.restrict {
+            changes.push("Let file commands read and write anywhere on this computer".to_string());
+        }
+        // Going from unrestricted to restricted only takes access away
+        if self.restrict && other.restrict {
+            changes.extend(other.allowed_roots.iter()
+                .filter(|root| !self.allowed_roots.contains(root))
+                .map(|root| format!("Allow access to {}", root.display())));
+        }
+        changes
+    }
+}
+
+/// Threads for batch work such as site builds, indexing and workspace search
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkerSettings {
+    /// Defaults to one less than the number of cores
+    pub parallelism: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum FeedFormat {
+    #[default]
+    Rss,
+    Atom,
+}
+
+/// Channel details for feeds generated from a posts folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeedSettings {
+    pub format: FeedFormat,
+    /// Folder of posts; defaults to the active workspace
+    pub posts_dir: Option<PathBuf>,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    /// Home page of the blog, e.g. `https://blog.example.com`
+    pub site_url: String,
+    /// Post URL with a `{slug}` placeholder; unset uses `<site_url>/<slug>/`
+    pub post_url: String,
+    pub max_items: usize,
+}
+
+impl Default for FeedSettings {
+    fn default() -> Self {
+        Self {
+            format: FeedFormat::Rss,
+            posts_dir: None,
+            title: String::new(),
+            description: String::new(),
+            author: String::new(),
+            site_url: String::new(),
+            post_url: String::new(),
+            max_items: 20,
+        }
+    }
+}
+
+/// Where daily notes live and how they're named
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalSettings {
+    /// Absolute, or relative to the active workspace
+    pub folder: PathBuf,
+    /// chrono format for note file names, without the `.md` extension
+    pub file_name_format: String,
+    /// Template for new notes, relative to the journal folder
+    pub template: Option<PathBuf>,
+}
+
+impl Default for JournalSettings {
+    fn default() -> Self {
+        Self {
+            folder: PathBuf::from("Journal"),
+            file_name_format: "%Y-%m-%d".to_string(),
+            template: None,
+        }
+    }
+}
+
+/// Zotero library access through the Better BibTeX plugin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZoteroSettings {
+    /// Better BibTeX endpoint of the running Zotero
+    pub endpoint: String,
+    /// CSL style id for bibliographies, e.g. `http://www.zotero.org/styles/apa`,
+    /// or the name of a `.csl` file in the styles folder; documents can pick
+    /// their own with `csl` front matter, and unset uses Zotero's default
+    pub style: Option<String>,
+    /// Append a References section for cited keys when exporting
+    pub bibliography_on_export: bool,
+}
+
+impl Default for ZoteroSettings {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://127.0.0.1:23119/better-bibtex".to_string(),
+            style: None,
+            bibliography_on_export: true,
+        }
+    }
+}
+
+/// Local automation API; the access token lives in the OS keychain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    /// Port on localhost; defaults to 27183 so scripts can rely on it
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum SyncProviderKind {
+    #[default]
+    WebDav,
+    Dropbox,
+    GoogleDrive,
+}
+
+/// Remote the active workspace syncs with; passwords and OAuth tokens live in the OS keychain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncSettings {
+    pub provider: SyncProviderKind,
+    /// WebDAV collection URL, e.g. `https://cloud.example.com/remote.php/dav/files/ada/Notes`
+    pub webdav_url: String,
+    pub username: String,
+    /// Dropbox path or Google Drive folder name the vault mirrors to, e.g. `/Notes`
+    pub remote_folder: String,
+    /// OAuth client registered by the user for Dropbox or Google Drive
+    pub oauth_client_id: String,
+    /// Google requires the client secret even for desktop apps; unused by Dropbox
+    pub oauth_client_secret: Option<String>,
+    /// Minutes between background syncs; 0 syncs only on demand
+    pub interval_minutes: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS, usually on port 587
+    #[default]
+    StartTls,
+    /// Implicit TLS, usually on port 465
+    Tls,
+    /// Unencrypted, for local relays only
+    None,
+}
+
+/// Outgoing mail server; the password lives in the OS keychain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSettings {
+    pub smtp_host: String,
+    /// Defaults to the standard port for `security`
+    pub smtp_port: Option<u16>,
+    pub security: SmtpSecurity,
+    /// Login name, if different from the sender address
+    pub username: String,
+    /// Sender address, e.g. `Ada Lovelace <ada@example.com>`
+    pub from: String,
+}
+
+/// Target space for Confluence publishing; the API token lives in the OS keychain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfluenceSettings {
+    /// Site URL including the context path, e.g. `https://acme.atlassian.net/wiki`
+    pub base_url: String,
+    pub space_key: String,
+    pub parent_page_id: Option<String>,
+    /// Account email used with the API token
+    pub username: String,
+}
+
+/// Non-secret publishing configuration; API keys live in the OS keychain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublishSettings {
+    /// Site URL of a Ghost blog, e.g. `https://blog.example.com`
+    pub ghost_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrammarSettings {
+    pub enabled: bool,
+    /// Base URL of a LanguageTool server, e.g. one started with `languagetool-server`
+    pub server_url: String,
+}
+
+impl Default for GrammarSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            server_url: "http://localhost:8081".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AiProviderKind {
+    /// Any endpoint implementing the OpenAI chat completions API
+    OpenAiCompatible,
+    Anthropic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AiSettings {
+    pub provider: AiProviderKind,
+    pub endpoint: String,
+    pub model: String,
+    /// Accepted from the settings page and read from settings written by older
+    /// versions, but never saved: the key lives in the keychain
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        Self {
+            provider: AiProviderKind::OpenAiCompatible,
+            endpoint: "https://api.openai.com/v1/chat/completions".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            api_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum FilterStage {
+    Preview,
+    Export,
+    Both,
+}
+
+impl FilterStage {
+    /// Whether a filter configured for this stage runs at `stage`
+    pub fn includes(&self, stage: FilterStage) -> bool {
+        *self == FilterStage::Both || *self == stage
+    }
+}
+
+/// A user-provided command that rewrites generated HTML
+///
+/// The command receives the HTML on stdin and must print the transformed HTML
+/// to stdout, in the style of pandoc filters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HtmlFilter {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub stage: FilterStage,
+    pub enabled: bool,
+}
+
+impl Default for HtmlFilter {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            command: String::new(),
+            args: Vec::new(),
+            stage: FilterStage::Both,
+            enabled: true,
+        }
+    }
+}
+
+impl HtmlFilter {
+    /// The command and its arguments as a shell would show them, for the user to review
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.command)
+            .chain(&self.args)
+            .map(|part| {
+                if part.is_empty() || part.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+                    format!("\"{}\"", part.replace('\\', "\\\\").replace('"', "\\\""))
+                } else {
+                    part.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum SiteGenerator {
+    #[default]
+    Hugo,
+    Jekyll,
+}
+
+/// Where static-site exports are written; unset directories use the generator's conventions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaticSiteSettings {
+    pub generator: SiteGenerator,
+    pub site_root: Option<PathBuf>,
+    /// Posts directory relative to the site root, e.g. `content/posts`
+    pub content_dir: Option<String>,
+    /// Image directory relative to the site root, e.g. `static/images`
+    pub static_dir: Option<String>,
+    /// URL under which `static_dir` is served, e.g. `/images`
+    pub image_url_prefix: Option<String>,
+}
+
+pub struct SettingsService {
+    path: PathBuf,
+    settings: Mutex<AppSettings>,
+}
+
+impl Default for SettingsService {
+    fn default() -> Self {
+        Self::load(app_config_dir().join("settings.json"))
+    }
+}
+
+impl SettingsService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load settings from the given file, falling back to defaults if it is missing or invalid
+    pub fn load(path: PathBuf) -> Self {
+        let settings = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Invalid settings file {:?}, using defaults: {}", path, e);
+                AppSettings::default()
+            }),
+            Err(_) => {
+                debug!("No settings file at {:?}, using defaults", path);
+                AppSettings::default()
+            }
+        };
+
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get a snapshot of the current settings
+    pub fn get(&self) -> AppSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Apply a change to the settings and persist them to disk
+    pub async fn update<F>(&self, change: F) -> Result<AppSettings>
+    where
+        F: FnOnce(&mut AppSettings),
+    {
+        let updated = {
+            let mut settings = self.settings.lock().unwrap();
+            change(&mut settings);
+            settings.clone()
+        };
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await
+                .with_context(|| format!("Failed to create settings directory: {:?}", parent))?;
+        }
+
+        let json = serde_json::to_string_pretty(&updated)?;
+        tokio::fs::write(&self.path, json).await
+            .with_context(|| format!("Failed to write settings file: {:?}", self.path))?;
+
+        info!("Settings saved to {:?}", self.path);
+        Ok(updated)
+    }
+}
+
+/// Directory where Typora-Lite stores its configuration
+pub fn app_config_dir() -> PathBuf {
+    directories::ProjectDirs::from("com", "typolite", "Typora-Lite")
+        .map(|proj_dirs| proj_dirs.config_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("./config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_settings_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("settings.json");
+        let service = SettingsService::load(path.clone());
+
+        service.update(|settings| {
+            settings.shortcuts.insert("save".to_string(), "CmdOrCtrl+S".to_string());
+        }).await.unwrap();
+
+        let reloaded = SettingsService::load(path);
+        assert_eq!(reloaded.get().shortcuts.get("save").map(String::as_str), Some("CmdOrCtrl+S"));
+    }
+
+    #[test]
+    fn test_missing_settings_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let service = SettingsService::load(temp_dir.path().join("missing.json"));
+
+        assert!(service.get().shortcuts.is_empty());
+    }
+
+    #[test]
+    fn test_html_filter_command_line() {
+        let filter = HtmlFilter {
+            command: "/usr/bin/sed".to_string(),
+            args: vec!["-e".to_string(), "s/a b/\"c\"/".to_string(), String::new()],
+            ..Default::default()
+        };
+        assert_eq!(filter.command_line(), r#"/usr/bin/sed -e "s/a b/\"c\"/" """#);
+    }
+
+    #[test]
+    fn test_file_access_loosening() {
+        let current = FileAccessSettings { allowed_roots: vec![PathBuf::from("/notes")], ..Default::default() };
+        let more_roots = FileAccessSettings { allowed_roots: vec![PathBuf::from("/notes"), PathBuf::from("/")], ..Default::default() };
+        assert_eq!(current.loosened_by(&more_roots), vec!["Allow access to /".to_string()]);
+        assert!(more_roots.loosened_by(&current).is_empty());
+
+        let unrestricted = FileAccessSettings { restrict: false, ..Default::default() };
+        assert_eq!(current.loosened_by(&unrestricted).len(), 1);
+        assert!(unrestricted.loosened_by(&more_roots).is_empty());
+
+        let mut opened = FileAccessSettings::default();
+        for n in 0..=FileAccessSettings::MAX_OPENED_FILES {
+            opened.record_opened_file(Path::new(&format!("/a/{}.md", n)));
+        }
+        opened.record_opened_file(Path::new("/a/1.md"));
+        assert_eq!(opened.opened_files.len(), FileAccessSettings::MAX_OPENED_FILES);
+        assert_eq!(opened.opened_files[0], PathBuf::from("/a/1.md"));
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Actions whose shortcuts are registered as OS-wide accelerators
+pub const GLOBAL_ACTIONS: &[&str] = &["quick-capture"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Shortcut {
+    pub action: String,
+    pub accelerator: String,
+    pub global: bool,
+}
+
+/// Built-in keybindings, keyed by action id
+pub fn default_shortcuts() -> BTreeMap<String, String> {
+    [
+        ("open-file", "CmdOrCtrl+O"),
+        ("save-file", "CmdOrCtrl+S"),
+        ("export-pdf", "CmdOrCtrl+Shift+E"),
+        ("toggle-sidebar", "CmdOrCtrl+\\"),
+        ("toggle-theme", "CmdOrCtrl+Shift+T"),
+        ("find", "CmdOrCtrl+F"),
+        ("quick-capture", "CmdOrCtrl+Shift+Space"),
+    ]
+    .into_iter()
+    .map(|(action, accelerator)| (action.to_string(), accelerator.to_string()))
+    .collect()
+}
+
+/// Merge user overrides on top of the built-in keybindings
+pub fn effective_shortcuts(overrides: &BTreeMap<String, String>) -> Vec<Shortcut> {
+    let mut merged = default_shortcuts();
+    merged.extend(overrides.iter().map(|(action, accelerator)| (action.clone(), accelerator.clone())));
+
+    merged
+        .into_iter()
+        .filter(|(_, accelerator)| !accelerator.is_empty())
+        .map(|(action, accelerator)| Shortcut {
+            global: GLOBAL_ACTIONS.contains(&action.as_str()),
+            action,
+            accelerator,
+        })
+        .collect()
+}
+
+/// Normalize an accelerator so equivalent spellings compare equal
+///
+/// Modifiers are mapped to their canonical names and ordered consistently,
+/// e.g. `shift+ctrl+p` becomes `Ctrl+Shift+P`.
+pub fn normalize_accelerator(accelerator: &str) -> Result<String> {
+    const MODIFIER_ORDER: &[&str] = &["CmdOrCtrl", "Ctrl", "Cmd", "Alt", "Shift"];
+
+    let mut modifiers = Vec::new();
+    let mut key = None;
+
+    for part in accelerator.split('+').map(str::trim) {
+        let modifier = match part.to_lowercase().as_str() {
+            "cmdorctrl" | "commandorcontrol" => Some("CmdOrCtrl"),
+            "ctrl" | "control" => Some("Ctrl"),
+            "cmd" | "command" | "super" | "meta" => Some("Cmd"),
+            "alt" | "option" => Some("Alt"),
+            "shift" => Some("Shift"),
+            _ => None,
+        };
+
+        match modifier {
+            Some(modifier) if !modifiers.contains(&modifier) => modifiers.push(modifier),
+            Some(_) => return Err(anyhow::anyhow!("Duplicate modifier in accelerator: {}", accelerator)),
+            None if part.is_empty() => {
+                return Err(anyhow::anyhow!("Empty key in accelerator: {}", accelerator));
+            }
+            None if key.is_some() => {
+                return Err(anyhow::anyhow!("Accelerator has more than one key: {}", accelerator));
+            }
+            None => key = Some(part.to_uppercase()),
+        }
+    }
+
+    let key = key.ok_or_else(|| anyhow::anyhow!("Accelerator has no key: {}", accelerator))?;
+    modifiers.sort_by_key(|modifier| MODIFIER_ORDER.iter().position(|m| m == modifier));
+
+    let mut parts: Vec<String> = modifiers.into_iter().map(String::from).collect();
+    parts.push(key);
+    Ok(parts.join("+"))
+}
+
+/// Normalize a set of shortcut overrides and reject bindings that collide
+///
+/// An empty accelerator unbinds the action and never conflicts.
+pub fn validate_shortcuts(overrides: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+    let mut normalized = BTreeMap::new();
+    for (action, accelerator) in overrides {
+        let accelerator = if accelerator.trim().is_empty() {
+            String::new()
+        } else {
+            normalize_accelerator(accelerator)?
+        };
+        normalized.insert(action.clone(), accelerator);
+    }
+
+    let mut merged = default_shortcuts();
+    for accelerator in merged.values_mut() {
+        *accelerator = normalize_accelerator(accelerator)?;
+    }
+    merged.extend(normalized.clone());
+
+    let mut seen: HashMap<&str, &str> = HashMap::new();
+    for (action, accelerator) in merged.iter().filter(|(_, accelerator)| !accelerator.is_empty()) {
+        if let Some(other) = seen.insert(accelerator, action) {
+            return Err(anyhow::anyhow!(
+                "Shortcut conflict: '{}' and '{}' are both bound to {}",
+                other, action, accelerator
+            ));
+        }
+    }
+
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_accelerator() {
+        assert_eq!(normalize_accelerator("shift+ctrl+p").unwrap(), "Ctrl+Shift+P");
+        assert_eq!(normalize_accelerator("CommandOrControl + s").unwrap(), "CmdOrCtrl+S");
+        assert!(normalize_accelerator("Ctrl+Shift").is_err());
+        assert!(normalize_accelerator("Ctrl+A+B").is_err());
+    }
+
+    #[test]
+    fn test_conflicting_shortcuts_rejected() {
+        let mut overrides = BTreeMap::new();
+        overrides.insert("find".to_string(), "cmdorctrl+o".to_string());
+
+        let err = validate_shortcuts(&overrides).unwrap_err();
+        assert!(err.to_string().contains("open-file"));
+
+        // Moving the conflicting binding out of the way resolves it
+        overrides.insert("open-file".to_string(), "CmdOrCtrl+Shift+O".to_string());
+        assert!(validate_shortcuts(&overrides).is_ok());
+    }
+
+    #[test]
+    fn test_effective_shortcuts_marks_global_actions() {
+        let shortcuts = effective_shortcuts(&BTreeMap::new());
+        let capture = shortcuts.iter().find(|s| s.action == "quick-capture").unwrap();
+
+        assert!(capture.global);
+        assert!(shortcuts.iter().filter(|s| s.action != "quick-capture").all(|s| !s.global));
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Counts operations that must not be cut off when the app quits, such as
+/// atomic file writes and exports
+///
+/// Clones share the same count.
+#[derive(Clone, Default)]
+pub struct InFlight {
+    inner: Arc<InFlightInner>,
+}
+
+#[derive(Default)]
+struct InFlightInner {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+/// Marks one operation as running until dropped
+pub struct InFlightGuard {
+    inner: Arc<InFlightInner>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+impl InFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin(&self) -> InFlightGuard {
+        self.inner.count.fetch_add(1, Ordering::AcqRel);
+        InFlightGuard { inner: self.inner.clone() }
+    }
+
+    pub fn count(&self) -> usize {
+        self.inner.count.load(Ordering::Acquire)
+    }
+
+    /// Wait until nothing is running, giving up after `timeout`; true if it got there
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                // Register before checking, so a guard dropped in between still wakes us
+                let idle = self.inner.idle.notified();
+                if self.count() == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_idle_waits_for_guards() {
+        let in_flight = InFlight::new();
+        assert!(in_flight.wait_idle(Duration::from_millis(10)).await);
+
+        let first = in_flight.begin();
+        let second = in_flight.clone().begin();
+        assert_eq!(in_flight.count(), 2);
+        assert!(!in_flight.wait_idle(Duration::from_millis(20)).await);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(first);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(second);
+        });
+        assert!(in_flight.wait_idle(Duration::from_secs(5)).await);
+        assert_eq!(in_flight.count(), 0);
+    }
+}
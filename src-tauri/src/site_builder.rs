@@ -0,0 +1,389 @@
+use anyhow::{Result, Context};
+use html_escape::encode_text;
+use pulldown_cmark::{html, Options, Parser};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::frontmatter::FrontMatter;
+use crate::static_site::{local_image_path, rewrite_destinations};
+use crate::worker_pool::WorkerPool;
+
+const SITE_CSS: &str = r#"
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, "Helvetica Neue", Arial, sans-serif;
+            line-height: 1.6;
+            color: #333;
+            margin: 0;
+        }
+        nav {
+            border-bottom: 1px solid #eaecef;
+            padding: 12px 24px;
+        }
+        nav a {
+            color: inherit;
+            font-weight: 600;
+            text-decoration: none;
+        }
+        main {
+            max-width: 760px;
+            margin: 0 auto;
+            padding: 24px;
+        }
+        a { color: #0366d6; }
+        img { max-width: 100%; height: auto; }
+        pre, code {
+            font-family: "SFMono-Regular", Consolas, "Liberation Mono", Menlo, monospace;
+            font-size: 85%;
+            background: #f6f8fa;
+        }
+        pre { border-radius: 6px; padding: 16px; overflow-x: auto; }
+        code { padding: 2px 4px; border-radius: 3px; }
+        pre code { padding: 0; }
+        blockquote { border-left: 4px solid #dfe2e5; padding: 0 16px; margin: 0 0 16px 0; color: #6a737d; }
+        table { border-collapse: collapse; }
+        th, td { border: 1px solid #dfe2e5; padding: 6px 12px; }
+        .pages { list-style: none; padding-left: 0; }
+        .pages .folder { color: #6a737d; }
+"#;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SiteBuildOptions {
+    /// Shown in the navigation bar and on the index; defaults to the folder name
+    pub title: Option<String>,
+    /// Extra CSS appended after the built-in styles
+    pub css_theme: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteBuildResult {
+    pub output_dir: PathBuf,
+    pub pages: usize,
+    pub assets: usize,
+}
+
+/// A note to publish, by path relative to the workspace root
+struct SitePage {
+    source: PathBuf,
+    relative: PathBuf,
+    title: String,
+    body: String,
+}
+
+/// Renders a whole folder of notes into a browsable HTML site
+#[derive(Default)]
+pub struct SiteBuilder;
+
+impl SiteBuilder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Render every markdown file under `root` into `output_dir`, mirroring the
+    /// folder layout, with an index page and the local files notes link to
+    pub async fn build(
+        &self,
+        root: &Path,
+        output_dir: &Path,
+        options: &SiteBuildOptions,
+        workers: &WorkerPool,
+    ) -> Result<SiteBuildResult> {
+        debug!("Building site from {:?} into {:?}", root, output_dir);
+
+        tokio::fs::create_dir_all(output_dir).await
+            .with_context(|| format!("Failed to create site folder: {:?}", output_dir))?;
+        let root = root.canonicalize().with_context(|| format!("Workspace not found: {:?}", root))?;
+        let output_dir = output_dir.canonicalize()?;
+        if root.starts_with(&output_dir) {
+            return Err(anyhow::anyhow!("The site folder can't contain the workspace"));
+        }
+
+        let site_title = options.title.clone().unwrap_or_else(|| {
+            root.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| "Notes".to_string())
+        });
+        let pages = Arc::new(collect_pages(&root, &output_dir)?);
+
+        // Pages render and write independently, so spread them over the worker pool
+        let rendered = {
+            let pages = pages.clone();
+            let root = root.clone();
+            let output_dir = output_dir.clone();
+            let site_title = site_title.clone();
+            let options = options.clone();
+            workers.map((0..pages.len()).collect(), move |index| {
+                write_page(&pages, index, &root, &output_dir, &site_title, &options)
+            }).await?
+        };
+
+        let mut assets = BTreeSet::new();
+        let mut wrote_index = false;
+        for page in rendered {
+            let (is_index, page_assets) = page?;
+            wrote_index |= is_index;
+            assets.extend(page_assets);
+        }
+
+        if !wrote_index {
+            let document = render_page(&site_title, &site_title, &page_list(&pages), 0, options);
+            tokio::fs::write(output_dir.join("index.html"), document).await
+                .context("Failed to write the index page")?;
+        }
+
+        for asset in &assets {
+            let target = output_dir.join(asset);
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            if let Err(e) = tokio::fs::copy(root.join(asset), &target).await {
+                warn!("Failed to copy site asset {:?}: {}", asset, e);
+            }
+        }
+
+        info!("Built site with {} pages and {} assets in {:?}", pages.len(), assets.len(), output_dir);
+        Ok(SiteBuildResult { output_dir, pages: pages.len(), assets: assets.len() })
+    }
+}
+
+/// Render and write `pages[index]`, returning whether it is the site's index page
+/// and the local files it links to
+fn write_page(
+    pages: &[SitePage],
+    index: usize,
+    root: &Path,
+    output_dir: &Path,
+    site_title: &str,
+    options: &SiteBuildOptions,
+) -> Result<(bool, BTreeSet<PathBuf>)> {
+    let page = &pages[index];
+    let mut assets = BTreeSet::new();
+    let directory = page.relative.parent().unwrap_or(Path::new(""));
+    let markdown = rewrite_destinations(&page.body, false, |dest| {
+        rewrite_link(dest, root, &page.source, directory, &mut assets)
+    });
+    let mut content = render_markdown(&markdown);
+
+    let html_path = page.relative.with_extension("html");
+    let is_index = html_path == Path::new("index.html");
+    if is_index {
+        content.push_str(&page_list(pages));
+    }
+
+    let output = output_dir.join(&html_path);
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let document = render_page(&page.title, site_title, &content, depth(&page.relative), options);
+    std::fs::write(&output, document)
+        .with_context(|| format!("Failed to write page: {:?}", output))?;
+
+    Ok((is_index, assets))
+}
+
+/// Every publishable note under `root`, sorted by path; hidden folders, drafts
+/// and the site's own output folder are left out
+fn collect_pages(root: &Path, output_dir: &Path) -> Result<Vec<SitePage>> {
+    let mut pages = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        if dir == output_dir {
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_markdown = path.extension().and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown"));
+            if !is_markdown {
+                continue;
+            }
+
+            let markdown = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {:?}", path))?;
+            let (front_matter, body) = match FrontMatter::parse(&markdown) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    warn!("Publishing {:?} without its front matter: {}", path, e);
+                    (FrontMatter::default(), markdown.as_str())
+                }
+            };
+            if front_matter.get_bool("draft").unwrap_or(false) {
+                continue;
+            }
+
+            let relative = path.strip_prefix(root)?.to_path_buf();
+            let title = front_matter.title().unwrap_or_else(|| {
+                relative.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+            });
+            pages.push(SitePage { source: path.clone(), relative, title, body: body.to_string() });
+        }
+    }
+
+    pages.sort_by(|a, b| a.relative.cmp(&b.relative));
+    Ok(pages)
+}
+
+/// Point links to other notes at their pages, and note local files that must be copied
+fn rewrite_link(
+    dest: &str,
+    root: &Path,
+    source: &Path,
+    directory: &Path,
+    assets: &mut BTreeSet<PathBuf>,
+) -> Option<String> {
+    let local = local_image_path(dest)?;
+    let is_note = local.extension().and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown"));
+
+    if is_note {
+        let (path, fragment) = match dest.split_once('#') {
+            Some((path, fragment)) => (path, format!("#{}", fragment)),
+            None => (dest, String::new()),
+        };
+        let stem = &path[..path.rfind('.')?];
+        return Some(format!("{}.html{}", stem, fragment));
+    }
+
+    // Only files inside the workspace are published
+    let target = source.parent()?.join(&local).canonicalize().ok()?;
+    if target.is_file() && target.starts_with(root) {
+        assets.insert(normalize(&directory.join(&local)));
+    }
+    None
+}
+
+/// Resolve `.` and `..` without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(part) => normalized.push(part),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+fn depth(relative: &Path) -> usize {
+    relative.components().count().saturating_sub(1)
+}
+
+fn render_markdown(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+
+    let mut output = String::with_capacity(markdown.len() * 2);
+    html::push_html(&mut output, Parser::new_ext(markdown, options));
+    output
+}
+
+/// Links to every page, labelled with their folder
+fn page_list(pages: &[SitePage]) -> String {
+    let mut list = String::from("<ul class=\"pages\">\n");
+    for page in pages {
+        let href = page.relative.with_extension("html").to_string_lossy().replace('\\', "/").replace(' ', "%20");
+        let folder = page.relative.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(|parent| format!("<span class=\"folder\">{}/</span>", encode_text(&parent.to_string_lossy())))
+            .unwrap_or_default();
+        list.push_str(&format!("<li><a href=\"{}\">{}{}</a></li>\n", href, folder, encode_text(&page.title)));
+    }
+    list.push_str("</ul>\n");
+    list
+}
+
+fn render_page(title: &str, site_title: &str, content: &str, depth: usize, options: &SiteBuildOptions) -> String {
+    let css = match &options.css_theme {
+        Some(theme) => format!("{}\n/* Custom Theme */\n{}", SITE_CSS, theme),
+        None => SITE_CSS.to_string(),
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{}</title>
+    <style>{}</style>
+</head>
+<body>
+    <nav><a href="{}index.html">{}</a></nav>
+    <main>
+{}
+    </main>
+</body>
+</html>
+"#,
+        encode_text(title),
+        css,
+        "../".repeat(depth),
+        encode_text(site_title),
+        content
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_build_site() {
+        let root = TempDir::new().unwrap();
+        std::fs::create_dir_all(root.path().join("projects/img")).unwrap();
+        std::fs::create_dir_all(root.path().join(".trash")).unwrap();
+        std::fs::write(root.path().join("Home.md"), "# Home\n\nSee [the plan](projects/Big%20Plan.md#goals).\n").unwrap();
+        std::fs::write(
+            root.path().join("projects/Big Plan.md"),
+            "---\ntitle: The Big Plan\n---\n![chart](img/chart.png) [home](../Home.md)\n",
+        ).unwrap();
+        std::fs::write(root.path().join("projects/img/chart.png"), b"png").unwrap();
+        std::fs::write(root.path().join("projects/Later.md"), "---\ndraft: true\n---\nWIP").unwrap();
+        std::fs::write(root.path().join(".trash/old.md"), "old").unwrap();
+        let output = root.path().join("site");
+
+        let result = SiteBuilder::new()
+            .build(root.path(), &output, &SiteBuildOptions::default(), &WorkerPool::with_parallelism(2))
+            .await
+            .unwrap();
+
+        assert_eq!(result.pages, 2);
+        assert_eq!(result.assets, 1);
+        assert!(output.join("projects/img/chart.png").is_file());
+
+        let home = std::fs::read_to_string(output.join("Home.html")).unwrap();
+        assert!(home.contains("href=\"projects/Big%20Plan.html#goals\""));
+
+        let plan = std::fs::read_to_string(output.join("projects/Big Plan.html")).unwrap();
+        assert!(plan.contains("<title>The Big Plan</title>"));
+        assert!(plan.contains("href=\"../Home.html\""));
+        assert!(plan.contains("<a href=\"../index.html\">"));
+
+        let index = std::fs::read_to_string(output.join("index.html")).unwrap();
+        assert!(index.contains("<a href=\"projects/Big%20Plan.html\"><span class=\"folder\">projects/</span>The Big Plan</a>"));
+        assert!(!index.contains("Later"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("notes/../img/./a.png")), PathBuf::from("img/a.png"));
+    }
+}
@@ -0,0 +1,443 @@
+use anyhow::{Result, Context};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+use crate::settings::app_config_dir;
+
+/// Maximum number of suggestions returned for a misspelled word
+const MAX_SUGGESTIONS: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SpellingIssue {
+    pub word: String,
+    pub start: usize, // byte offset into the checked content
+    pub end: usize,
+}
+
+/// A set of known words for one language
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Build a dictionary from a plain word list or a Hunspell `.dic` file
+    ///
+    /// Hunspell affix flags (`word/FLAGS`) and the leading entry count are ignored.
+    pub fn from_word_list(content: &str) -> Self {
+        let words = content
+            .lines()
+            .map(|line| line.split('/').next().unwrap_or("").trim())
+            .filter(|word| !word.is_empty() && !word.chars().all(|c| c.is_ascii_digit()))
+            .map(str::to_lowercase)
+            .collect();
+
+        Self { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Whether the word is known, allowing simple possessive and plural forms
+    pub fn contains(&self, word: &str) -> bool {
+        let word = word.to_lowercase().replace('\u{2019}', "'");
+        if self.words.contains(&word) {
+            return true;
+        }
+
+        ["'s", "s", "es"].iter().any(|suffix| {
+            word.strip_suffix(suffix)
+                .is_some_and(|stem| !stem.is_empty() && self.words.contains(stem))
+        })
+    }
+}
+
+pub struct SpellChecker {
+    dictionary_dirs: Vec<PathBuf>,
+    dictionaries: Mutex<HashMap<String, Arc<Dictionary>>>,
+    user_dictionary_dir: PathBuf,
+    user_words: Mutex<HashMap<String, BTreeSet<String>>>,
+}
+
+impl Default for SpellChecker {
+    fn default() -> Self {
+        let user_dictionary_dir = app_config_dir().join("dictionaries");
+
+        Self {
+            dictionary_dirs: vec![
+                user_dictionary_dir.clone(),
+                PathBuf::from("/usr/share/hunspell"),
+                PathBuf::from("/usr/share/myspell"),
+                PathBuf::from("/usr/share/myspell/dicts"),
+            ],
+            dictionaries: Mutex::new(HashMap::new()),
+            user_dictionary_dir,
+            user_words: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl SpellChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dictionary_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.dictionary_dirs = dirs;
+        self
+    }
+
+    pub fn with_user_dictionary_dir(mut self, dir: PathBuf) -> Self {
+        self.user_dictionary_dir = dir;
+        self
+    }
+
+    /// Get the dictionary for a language, loading it on first use
+    pub fn dictionary(&self, language: &str) -> Result<Arc<Dictionary>> {
+        if let Some(dictionary) = self.dictionaries.lock().unwrap().get(language) {
+            return Ok(dictionary.clone());
+        }
+
+        let path = self.find_dictionary(language)?
+            .ok_or_else(|| anyhow::anyhow!("No dictionary found for language: {}", language))?;
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read dictionary: {:?}", path))?;
+
+        let dictionary = Arc::new(Dictionary::from_word_list(&content));
+        info!("Loaded {} dictionary from {:?} ({} words)", language, path, dictionary.len());
+
+        self.dictionaries.lock().unwrap().insert(language.to_string(), dictionary.clone());
+        Ok(dictionary)
+    }
+
+    fn find_dictionary(&self, language: &str) -> Result<Option<PathBuf>> {
+        // The language comes from the webview and becomes part of a file name
+        validate_language(language)?;
+        let candidates = self.dictionary_dirs.iter().flat_map(|dir| {
+            ["dic", "txt"].iter().map(move |ext| dir.join(format!("{}.{}", language, ext)))
+        });
+
+        Ok(candidates
+            .chain(language.starts_with("en").then(|| PathBuf::from("/usr/share/dict/words")))
+            .find(|path| path.is_file()))
+    }
+
+    /// Words the user has added for a language, sorted alphabetically
+    pub fn user_dictionary(&self, language: &str) -> Result<Vec<String>> {
+        self.with_user_words(language, |words| words.iter().cloned().collect())
+    }
+
+    /// Add a word to the user dictionary, persisting it to the config dir
+    pub fn add_user_word(&self, language: &str, word: &str) -> Result<()> {
+        let word = normalize_user_word(word)?;
+        info!("Adding {:?} to the {} user dictionary", word, language);
+
+        self.with_user_words(language, |words| words.insert(word))?;
+        self.save_user_dictionary(language)
+    }
+
+    /// Remove a word from the user dictionary, persisting the change
+    pub fn remove_user_word(&self, language: &str, word: &str) -> Result<()> {
+        let word = normalize_user_word(word)?;
+        info!("Removing {:?} from the {} user dictionary", word, language);
+
+        self.with_user_words(language, |words| words.remove(&word))?;
+        self.save_user_dictionary(language)
+    }
+
+    /// Whether a word is known to either the language dictionary or the user dictionary
+    pub fn is_known_word(&self, language: &str, word: &str) -> Result<bool> {
+        if self.dictionary(language)?.contains(word) {
+            return Ok(true);
+        }
+
+        let word = word.to_lowercase().replace('\u{2019}', "'");
+        self.with_user_words(language, |words| words.contains(&word))
+    }
+
+    fn user_dictionary_path(&self, language: &str) -> Result<PathBuf> {
+        validate_language(language)?;
+        Ok(self.user_dictionary_dir.join(format!("{}.user.txt", language)))
+    }
+
+    /// Run `f` against the user words for a language, loading them from disk on first use
+    fn with_user_words<T, F>(&self, language: &str, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut BTreeSet<String>) -> T,
+    {
+        let mut user_words = self.user_words.lock().unwrap();
+
+        if !user_words.contains_key(language) {
+            let path = self.user_dictionary_path(language)?;
+            let words = match std::fs::read_to_string(&path) {
+                Ok(content) => content.lines().map(str::trim).filter(|w| !w.is_empty()).map(String::from).collect(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeSet::new(),
+                Err(e) => return Err(e).with_context(|| format!("Failed to read user dictionary: {:?}", path)),
+            };
+            user_words.insert(language.to_string(), words);
+        }
+
+        let words = user_words.get_mut(language).expect("user words loaded above");
+        Ok(f(words))
+    }
+
+    fn save_user_dictionary(&self, language: &str) -> Result<()> {
+        let path = self.user_dictionary_path(language)?;
+        let content = self.with_user_words(language, |words| {
+            words.iter().map(|word| format!("{}\n", word)).collect::<String>()
+        })?;
+
+        std::fs::create_dir_all(&self.user_dictionary_dir)
+            .with_context(|| format!("Failed to create dictionary directory: {:?}", self.user_dictionary_dir))?;
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write user dictionary: {:?}", path))?;
+
+        debug!("Saved user dictionary: {:?}", path);
+        Ok(())
+    }
+
+    /// Find misspelled words in markdown content
+    ///
+    /// Code spans, code blocks, and HTML are skipped. When `range` is given, only
+    /// words inside that byte range are reported. Offsets are relative to `content`.
+    pub fn check(&self, content: &str, language: &str, range: Option<(usize, usize)>) -> Result<Vec<SpellingIssue>> {
+        let dictionary = self.dictionary(language)?;
+        let user_words = self.with_user_words(language, |words| words.clone())?;
+        let is_known = |word: &str| {
+            dictionary.contains(word) || user_words.contains(&word.to_lowercase().replace('\u{2019}', "'"))
+        };
+        let (range_start, range_end) = range.unwrap_or((0, content.len()));
+        debug!("Spell-checking bytes {}..{} ({})", range_start, range_end, language);
+
+        let mut issues = Vec::new();
+        let mut in_code_block = false;
+
+        for (event, span) in Parser::new_ext(content, Options::all()).into_offset_iter() {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                Event::Text(_) if !in_code_block => {
+                    if span.end <= range_start || span.start >= range_end {
+                        continue;
+                    }
+
+                    for (start, word) in tokenize(&content[span.clone()]) {
+                        let start = span.start + start;
+                        let end = start + word.len();
+                        if start >= range_start && end <= range_end && should_check(word) && !is_known(word) {
+                            issues.push(SpellingIssue {
+                                word: word.to_string(),
+                                start,
+                                end,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Suggest corrections for a word, closest matches first
+    pub fn suggest(&self, word: &str, language: &str) -> Result<Vec<String>> {
+        let dictionary = self.dictionary(language)?;
+        let user_words = self.with_user_words(language, |words| words.clone())?;
+        let is_known = |candidate: &String| dictionary.words.contains(candidate) || user_words.contains(candidate);
+        let lower = word.to_lowercase();
+
+        let mut candidates: Vec<String> = edits(&lower)
+            .into_iter()
+            .filter(is_known)
+            .collect();
+
+        if candidates.is_empty() {
+            candidates = edits(&lower)
+                .iter()
+                .flat_map(|edit| edits(edit))
+                .filter(is_known)
+                .collect();
+        }
+
+        candidates.sort();
+        candidates.dedup();
+        candidates.truncate(MAX_SUGGESTIONS);
+
+        // Preserve the capitalization of the original word
+        if word.chars().next().is_some_and(char::is_uppercase) {
+            for candidate in &mut candidates {
+                let mut chars = candidate.chars();
+                if let Some(first) = chars.next() {
+                    *candidate = first.to_uppercase().chain(chars).collect();
+                }
+            }
+        }
+
+        Ok(candidates)
+    }
+}
+
+/// Validate and lowercase a word before storing it in the user dictionary
+fn normalize_user_word(word: &str) -> Result<String> {
+    let word = word.trim();
+    if word.is_empty() || word.contains(char::is_whitespace) {
+        return Err(anyhow::anyhow!("Invalid dictionary word: {:?}", word));
+    }
+
+    Ok(word.to_lowercase().replace('\u{2019}', "'"))
+}
+
+/// Split text into words, returning each word with its byte offset
+fn tokenize(text: &str) -> Vec<(usize, &str)> {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '\'' || c == '\u{2019}';
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (start, is_word_char(c)) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                let word = text[s..i].trim_matches(|c| c == '\'' || c == '\u{2019}');
+                if !word.is_empty() {
+                    let offset = s + text[s..i].find(word).unwrap_or(0);
+                    words.push((offset, word));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    words
+}
+
+/// Skip words that are unlikely to be prose: acronyms, numbers, and identifiers
+fn should_check(word: &str) -> bool {
+    word.chars().count() > 1
+        && !word.chars().any(|c| c.is_numeric() || c == '_')
+        && !word.chars().all(|c| !c.is_alphabetic() || c.is_uppercase())
+}
+
+/// All strings one edit (delete, transpose, replace, insert) away from `word`
+fn edits(word: &str) -> Vec<String> {
+    const LETTERS: &str = "abcdefghijklmnopqrstuvwxyz";
+    let chars: Vec<char> = word.chars().collect();
+    let mut results = Vec::new();
+
+    for i in 0..=chars.len() {
+        let (left, right) = chars.split_at(i);
+        let left: String = left.iter().collect();
+
+        if !right.is_empty() {
+            results.push(format!("{}{}", left, right[1..].iter().collect::<String>()));
+        }
+        if right.len() > 1 {
+            results.push(format!("{}{}{}{}", left, right[1], right[0], right[2..].iter().collect::<String>()));
+        }
+        for letter in LETTERS.chars() {
+            if !right.is_empty() {
+                results.push(format!("{}{}{}", left, letter, right[1..].iter().collect::<String>()));
+            }
+            results.push(format!("{}{}{}", left, letter, right.iter().collect::<String>()));
+        }
+    }
+
+    results
+}
+
+/// Check that `language` is a tag like `en`, `en_US` or `sr-Latn-RS`, so it
+/// can't name a file outside the dictionary folders
+pub fn validate_language(language: &str) -> Result<()> {
+    let mut parts = language.split(['_', '-']);
+    let primary = parts.next().unwrap_or_default();
+    let valid = (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !valid {
+        anyhow::bail!("Invalid language: {:?}", language);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn checker_with_words(words: &str) -> (TempDir, SpellChecker) {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("en_US.dic"), words).unwrap();
+        let checker = SpellChecker::new()
+            .with_dictionary_dirs(vec![temp_dir.path().to_path_buf()])
+            .with_user_dictionary_dir(temp_dir.path().join("user"));
+        (temp_dir, checker)
+    }
+
+    #[test]
+    fn test_check_reports_byte_ranges() {
+        let (_dir, checker) = checker_with_words("4\nthis/S\nis\na\ntest\n");
+        let content = "# This is a tset\n\n`speling` is ignored in code.";
+
+        let issues = checker.check(content, "en_US", Some((0, 17))).unwrap();
+
+        assert_eq!(issues, vec![SpellingIssue { word: "tset".to_string(), start: 12, end: 16 }]);
+        assert_eq!(&content[12..16], "tset");
+    }
+
+    #[test]
+    fn test_suggest() {
+        let (_dir, checker) = checker_with_words("hello\nhelp\nworld\n");
+
+        assert_eq!(checker.suggest("helo", "en_US").unwrap(), vec!["hello", "help"]);
+        assert_eq!(checker.suggest("Wrold", "en_US").unwrap(), vec!["World"]);
+    }
+
+    #[test]
+    fn test_user_dictionary() {
+        let (dir, checker) = checker_with_words("this\nis\n");
+        assert_eq!(checker.check("this is Typolite", "en_US", None).unwrap().len(), 1);
+
+        checker.add_user_word("en_US", "Typolite").unwrap();
+        assert!(checker.check("this is Typolite", "en_US", None).unwrap().is_empty());
+        assert_eq!(checker.user_dictionary("en_US").unwrap(), vec!["typolite"]);
+
+        // The word list persists across checker instances
+        let reloaded = SpellChecker::new()
+            .with_dictionary_dirs(vec![dir.path().to_path_buf()])
+            .with_user_dictionary_dir(dir.path().join("user"));
+        assert!(reloaded.is_known_word("en_US", "typolite").unwrap());
+
+        reloaded.remove_user_word("en_US", "typolite").unwrap();
+        assert!(reloaded.user_dictionary("en_US").unwrap().is_empty());
+        assert!(checker.add_user_word("en_US", "two words").is_err());
+        assert!(checker.add_user_word("../../en_US", "word").is_err());
+        assert!(checker.user_dictionary("en_US/..").is_err());
+    }
+
+    #[test]
+    fn test_missing_dictionary() {
+        let checker = SpellChecker::new().with_dictionary_dirs(vec![]);
+
+        assert!(checker.check("text", "xx_XX", None).is_err());
+    }
+
+    #[test]
+    fn test_validate_language() {
+        for language in ["en", "en_US", "sr-Latn-RS", "fil"] {
+            assert!(validate_language(language).is_ok(), "{}", language);
+        }
+        for language in ["", "e", "english", "en_", "../en", "en/../../etc", "en_US.dic", "/tmp/en"] {
+            assert!(validate_language(language).is_err(), "{}", language);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use anyhow::{Result, Context};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::frontmatter::FrontMatter;
+use crate::settings::{SiteGenerator, StaticSiteSettings};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteExportResult {
+    pub output_path: PathBuf,
+    /// Images copied into the site's static folder
+    pub images: Vec<PathBuf>,
+}
+
+impl SiteGenerator {
+    fn default_content_dir(&self) -> &'static str {
+        match self {
+            SiteGenerator::Hugo => "content/posts",
+            SiteGenerator::Jekyll => "_posts",
+        }
+    }
+
+    fn default_static_dir(&self) -> &'static str {
+        match self {
+            SiteGenerator::Hugo => "static/images",
+            SiteGenerator::Jekyll => "assets/images",
+        }
+    }
+
+    fn default_image_url_prefix(&self) -> &'static str {
+        match self {
+            SiteGenerator::Hugo => "/images",
+            SiteGenerator::Jekyll => "/assets/images",
+        }
+    }
+}
+
+/// Writes documents into a Hugo or Jekyll site with normalized front matter
+#[derive(Default)]
+pub struct StaticSiteExporter;
+
+impl StaticSiteExporter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Export a document into the site's content directory
+    ///
+    /// Relative image references are resolved against `document_path`, copied
+    /// into the site's static folder under the post slug and rewritten to
+    /// site-absolute URLs.
+    pub async fn export(
+        &self,
+        markdown: &str,
+        document_path: Option<&Path>,
+        settings: &StaticSiteSettings,
+    ) -> Result<SiteExportResult> {
+        let site_root = settings.site_root.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No static site directory configured"))?;
+        let generator = settings.generator;
+
+        let (mut front_matter, body) = FrontMatter::parse(markdown)?;
+        let now = chrono::Local::now();
+
+        let title = front_matter.title()
+            .or_else(|| body.lines().find_map(|line| line.strip_prefix("# ")).map(|t| t.trim().to_string()))
+            .or_else(|| document_path.and_then(|p| p.file_stem()).map(|s| s.to_string_lossy().to_string()))
+            .unwrap_or_else(|| "Untitled".to_string());
+        let slug = front_matter.get_str("slug").map(|s| slugify(&s)).unwrap_or_else(|| slugify(&title));
+        if slug.is_empty() {
+            return Err(anyhow::anyhow!("Could not derive a file name from the title '{}'", title));
+        }
+
+        normalize_front_matter(&mut front_matter, generator, &title, &now);
+        debug!("Exporting '{}' to {:?} site at {:?}", title, generator, site_root);
+
+        // Copy referenced local images and point the markdown at their new URLs
+        let document_dir = document_path.and_then(Path::parent);
+        let static_dir = site_root
+            .join(settings.static_dir.as_deref().unwrap_or(generator.default_static_dir()))
+            .join(&slug);
+        let url_prefix = settings.image_url_prefix.as_deref()
+            .unwrap_or(generator.default_image_url_prefix())
+            .trim_end_matches('/')
+            .to_string();
+
+        let mut copies = Vec::new();
+        let body = rewrite_image_paths(body, |dest| {
+            let source = document_dir?.join(local_image_path(dest)?);
+            let file_name = source.file_name()?.to_string_lossy().to_string();
+            if !source.is_file() {
+                warn!("Image not found, leaving reference as is: {:?}", source);
+                return None;
+            }
+            copies.push((source, static_dir.join(&file_name)));
+            Some(format!("{}/{}/{}", url_prefix, slug, file_name))
+        });
+
+        if !copies.is_empty() {
+            tokio::fs::create_dir_all(&static_dir).await
+                .with_context(|| format!("Failed to create image directory: {:?}", static_dir))?;
+        }
+        let mut images = Vec::new();
+        for (source, target) in copies {
+            tokio::fs::copy(&source, &target).await
+                .with_context(|| format!("Failed to copy image {:?}", source))?;
+            images.push(target);
+        }
+
+        let file_name = match generator {
+            SiteGenerator::Hugo => format!("{}.md", slug),
+            SiteGenerator::Jekyll => {
+                let date = front_matter.get_str("date")
+                    .filter(|d| d.len() >= 10 && d.is_char_boundary(10))
+                    .map(|d| d[..10].to_string())
+                    .unwrap_or_else(|| now.format("%Y-%m-%d").to_string());
+                format!("{}-{}.md", date, slug)
+            }
+        };
+        let content_dir = site_root.join(settings.content_dir.as_deref().unwrap_or(generator.default_content_dir()));
+        tokio::fs::create_dir_all(&content_dir).await
+            .with_context(|| format!("Failed to create content directory: {:?}", content_dir))?;
+
+        let output_path = content_dir.join(file_name);
+        tokio::fs::write(&output_path, front_matter.render(&body)?).await
+            .with_context(|| format!("Failed to write post: {:?}", output_path))?;
+
+        info!("Exported post to {:?} ({} images)", output_path, images.len());
+        Ok(SiteExportResult { output_path, images })
+    }
+}
+
+/// Fill in the fields each generator expects and normalize tag lists
+fn normalize_front_matter(
+    front_matter: &mut FrontMatter,
+    generator: SiteGenerator,
+    title: &str,
+    now: &chrono::DateTime<chrono::Local>,
+) {
+    if front_matter.title().is_none() {
+        front_matter.set("title", title);
+    }
+    if !front_matter.contains("date") {
+        let date = match generator {
+            SiteGenerator::Hugo => now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            SiteGenerator::Jekyll => now.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        };
+        front_matter.set("date", date);
+    }
+
+    for key in ["tags", "categories"] {
+        if front_matter.contains(key) {
+            let list = front_matter.get_list(key).into_iter().map(Value::String).collect::<Vec<_>>();
+            front_matter.set(key, Value::Sequence(list));
+        }
+    }
+
+    match generator {
+        SiteGenerator::Hugo if !front_matter.contains("draft") => front_matter.set("draft", true),
+        SiteGenerator::Jekyll if !front_matter.contains("layout") => front_matter.set("layout", "post"),
+        _ => {}
+    }
+}
+
+/// Replace image destinations in markdown, keeping everything else byte-for-byte
+///
+/// `rewrite` returns the new destination, or `None` to leave an image untouched.
+pub fn rewrite_image_paths<F>(markdown: &str, rewrite: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    rewrite_destinations(markdown, true, rewrite)
+}
+
+/// Like [`rewrite_image_paths`], optionally also rewriting link destinations
+pub fn rewrite_destinations<F>(markdown: &str, images_only: bool, mut rewrite: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut replacements = Vec::new();
+
+    for (event, range) in Parser::new_ext(markdown, Options::all()).into_offset_iter() {
+        let dest = match event {
+            Event::Start(Tag::Image(_, dest, _)) => dest,
+            Event::Start(Tag::Link(_, dest, _)) if !images_only => dest,
+            _ => continue,
+        };
+
+        // Reference-style destinations are defined elsewhere; skip those
+        let source = &markdown[range.clone()];
+        let Some(offset) = source.rfind(&*dest) else {
+            continue;
+        };
+        if let Some(new_dest) = rewrite(&dest) {
+            let start = range.start + offset;
+            replacements.push((start..start + dest.len(), new_dest));
+        }
+    }
+
+    // A link wrapping an image is reported before the image it contains
+    replacements.sort_by_key(|(range, _)| range.start);
+
+    let mut output = markdown.to_string();
+    for (range, new_dest) in replacements.into_iter().rev() {
+        output.replace_range(range, &new_dest);
+    }
+    output
+}
+
+/// The relative file path of an image destination, or `None` for URLs and absolute paths
+pub fn local_image_path(dest: &str) -> Option<PathBuf> {
+    let dest = dest.split(['?', '#']).next().unwrap_or(dest);
+    if dest.is_empty() || dest.contains("://") || dest.starts_with('/') || dest.starts_with("data:") {
+        return None;
+    }
+    Some(PathBuf::from(dest.replace("%20", " ")))
+}
+
+/// URL-friendly slug, e.g. `Hello, World!` becomes `hello-world`
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') && !slug.is_empty() {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_hugo_export_copies_images() {
+        let docs = TempDir::new().unwrap();
+        let site = TempDir::new().unwrap();
+        std::fs::create_dir(docs.path().join("img")).unwrap();
+        std::fs::write(docs.path().join("img/chart one.png"), b"png").unwrap();
+        let document = docs.path().join("post.md");
+
+        let markdown = "---\ntags: rust, blogging\n---\n# My First Post\n\n![Chart](img/chart%20one.png \"Chart\")\n![Remote](https://example.com/a.png)\n";
+        let settings = StaticSiteSettings {
+            generator: SiteGenerator::Hugo,
+            site_root: Some(site.path().to_path_buf()),
+            ..StaticSiteSettings::default()
+        };
+
+        let result = StaticSiteExporter::new().export(markdown, Some(&document), &settings).await.unwrap();
+
+        assert_eq!(result.output_path, site.path().join("content/posts/my-first-post.md"));
+        assert!(site.path().join("static/images/my-first-post/chart one.png").exists());
+
+        let exported = std::fs::read_to_string(&result.output_path).unwrap();
+        let (front_matter, body) = FrontMatter::parse(&exported).unwrap();
+        assert_eq!(front_matter.title().as_deref(), Some("My First Post"));
+        assert_eq!(front_matter.tags(), vec!["rust", "blogging"]);
+        assert_eq!(front_matter.get_bool("draft"), Some(true));
+        assert!(body.contains("![Chart](/images/my-first-post/chart one.png \"Chart\")"));
+        assert!(body.contains("![Remote](https://example.com/a.png)"));
+    }
+
+    #[tokio::test]
+    async fn test_jekyll_post_file_name() {
+        let site = TempDir::new().unwrap();
+        let settings = StaticSiteSettings {
+            generator: SiteGenerator::Jekyll,
+            site_root: Some(site.path().to_path_buf()),
+            ..StaticSiteSettings::default()
+        };
+
+        let markdown = "---\ntitle: Hello, World!\ndate: 2024-03-05 10:00:00 +0000\n---\nBody\n";
+        let result = StaticSiteExporter::new().export(markdown, None, &settings).await.unwrap();
+
+        assert_eq!(result.output_path, site.path().join("_posts/2024-03-05-hello-world.md"));
+        let (front_matter, _) = FrontMatter::parse(&std::fs::read_to_string(&result.output_path).unwrap()).unwrap();
+        assert_eq!(front_matter.get_str("layout").as_deref(), Some("post"));
+    }
+}
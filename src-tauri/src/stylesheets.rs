@@ -0,0 +1,92 @@
+use anyhow::{Result, Context};
+use std::path::{Path, PathBuf};
+
+use crate::frontmatter::FrontMatter;
+use crate::settings::app_config_dir;
+
+/// Front matter key naming a document's own stylesheet
+pub const CSS_KEY: &str = "css";
+
+/// Folder of user stylesheets that documents can name in their front matter
+pub fn themes_dir() -> PathBuf {
+    app_config_dir().join("themes")
+}
+
+/// Stylesheet named by the `css` key of a document's front matter, looked up
+/// next to the document first and then in `themes_dir`
+///
+/// Only `.css` files are used (`academic` means `academic.css`), so a shared
+/// document can't pull arbitrary local files into its exports.
+pub fn document_stylesheet(markdown: &str, document: Option<&Path>, themes_dir: &Path) -> Result<Option<PathBuf>> {
+    let (front_matter, _) = FrontMatter::parse(markdown)?;
+    let Some(name) = front_matter.get_str(CSS_KEY).map(|name| name.trim().to_string()).filter(|name| !name.is_empty()) else {
+        return Ok(None);
+    };
+
+    let mut file = PathBuf::from(&name);
+    match file.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("css") => {}
+        Some(_) => anyhow::bail!("Document stylesheet must be a .css file: {}", name),
+        None => {
+            file.set_extension("css");
+        }
+    }
+
+    let document_dir = document.and_then(Path::parent);
+    document_dir.into_iter()
+        .chain(std::iter::once(themes_dir))
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .map(Some)
+        .with_context(|| format!("Document stylesheet not found next to the document or in the themes folder: {}", name))
+}
+
+/// The document's own stylesheet, to append after the global styles
+pub async fn load_document_css(markdown: &str, document: Option<&Path>, themes_dir: &Path) -> Result<Option<String>> {
+    let Some(path) = document_stylesheet(markdown, document, themes_dir)? else {
+        return Ok(None);
+    };
+    let css = tokio::fs::read_to_string(&path).await
+        .with_context(|| format!("Failed to read document stylesheet: {:?}", path))?;
+    // It goes into a <style> element, which it mustn't be able to close
+    if css.to_ascii_lowercase().contains("</style") {
+        anyhow::bail!("Document stylesheet {:?} contains </style>", path);
+    }
+    Ok(Some(css))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_document_stylesheet_lookup() {
+        let dir = TempDir::new().unwrap();
+        let themes = dir.path().join("themes");
+        let notes = dir.path().join("notes");
+        std::fs::create_dir_all(&themes).unwrap();
+        std::fs::create_dir_all(notes.join("styles")).unwrap();
+        std::fs::write(themes.join("academic.css"), "body { font-family: serif; }").unwrap();
+        std::fs::write(notes.join("styles/report.css"), "h1 { color: navy; }").unwrap();
+        let document = notes.join("report.md");
+
+        let local = "---\ncss: styles/report.css\n---\n# Report\n";
+        assert_eq!(load_document_css(local, Some(&document), &themes).await.unwrap().as_deref(), Some("h1 { color: navy; }"));
+        let theme = "---\ncss: academic\n---\n# Paper\n";
+        assert_eq!(load_document_css(theme, Some(&document), &themes).await.unwrap().as_deref(), Some("body { font-family: serif; }"));
+        assert_eq!(load_document_css(theme, None, &themes).await.unwrap().as_deref(), Some("body { font-family: serif; }"));
+
+        assert_eq!(load_document_css("# No front matter\n", Some(&document), &themes).await.unwrap(), None);
+        assert!(load_document_css("---\ncss: missing.css\n---\n", Some(&document), &themes).await.is_err());
+        assert!(load_document_css("---\ncss: /etc/passwd\n---\n", Some(&document), &themes).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stylesheet_cannot_close_style_element() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("evil.css"), "p {}</STYLE><script>alert(1)</script>").unwrap();
+        let error = load_document_css("---\ncss: evil\n---\n", None, dir.path()).await.unwrap_err();
+        assert!(error.to_string().contains("</style>"), "{}", error);
+    }
+}
@@ -0,0 +1,590 @@
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use percent_encoding::percent_decode_str;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::cloud_sync::{DropboxProvider, GoogleDriveProvider};
+use crate::secrets::SecretStore;
+use crate::settings::{SyncProviderKind, SyncSettings};
+
+/// Keychain entry holding the WebDAV password (or app password)
+pub const WEBDAV_PASSWORD_KEY: &str = "webdav-password";
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>"#;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub uploaded: Vec<String>,
+    pub downloaded: Vec<String>,
+    pub deleted_local: Vec<String>,
+    pub deleted_remote: Vec<String>,
+    /// Conflict copies created when both sides changed
+    pub conflicts: Vec<String>,
+    /// Files that could not be synced this round
+    pub errors: Vec<String>,
+}
+
+/// A remote file store the sync engine can mirror a folder to
+///
+/// Paths are `/`-separated and relative to the synced folder. Revisions are
+/// opaque strings (ETags, revision ids, checksums) that change whenever a
+/// file's content does.
+#[async_trait]
+pub trait SyncProvider: Send + Sync {
+    /// Identifies the remote, so pointing a workspace elsewhere starts a fresh sync
+    fn remote_id(&self) -> String;
+
+    /// Revisions of every non-hidden file on the remote
+    async fn list(&self) -> Result<BTreeMap<String, String>>;
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// Upload a file, creating parent folders as needed, and return its new revision
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<String>;
+
+    /// Remove a file; files that are already gone are not an error
+    async fn delete(&self, path: &str) -> Result<()>;
+}
+
+/// Build the provider selected in `settings`, reading its credentials from the keychain
+pub fn provider_for(settings: &SyncSettings, secrets: &SecretStore) -> Result<Box<dyn SyncProvider>> {
+    Ok(match settings.provider {
+        SyncProviderKind::WebDav => Box::new(WebDavProvider::new(settings, secrets.get(WEBDAV_PASSWORD_KEY)?)?),
+        SyncProviderKind::Dropbox => Box::new(DropboxProvider::new(settings)?),
+        SyncProviderKind::GoogleDrive => Box::new(GoogleDriveProvider::new(settings)?),
+    })
+}
+
+/// What each side looked like after the last successful sync of a file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SyncState {
+    /// [`SyncProvider::remote_id`] of the remote this state belongs to
+    remote: String,
+    files: BTreeMap<String, SyncedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SyncedFile {
+    /// SHA-256 of the local content
+    hash: String,
+    /// Remote revision
+    #[serde(alias = "etag")]
+    revision: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SyncAction {
+    Upload(String),
+    Download(String),
+    DeleteLocal(String),
+    DeleteRemote(String),
+    /// Both sides changed (or appeared) since the last sync
+    Conflict(String),
+    /// Gone on both sides
+    Forget(String),
+}
+
+/// Decide what to do with each file by comparing both sides against the last sync
+///
+/// `local` maps relative paths to content hashes and `remote` maps them to
+/// revisions. A deletion on one side only wins if the other side is unchanged;
+/// otherwise the surviving edit is restored.
+fn plan(local: &BTreeMap<String, String>, remote: &BTreeMap<String, String>, state: &SyncState) -> Vec<SyncAction> {
+    let paths: BTreeSet<&String> = local.keys().chain(remote.keys()).chain(state.files.keys()).collect();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let synced = state.files.get(path);
+            let action = match (local.get(path), remote.get(path), synced) {
+                (Some(hash), Some(etag), Some(synced)) => {
+                    match (hash != &synced.hash, etag != &synced.revision) {
+                        (false, false) => return None,
+                        (true, false) => SyncAction::Upload(path.clone()),
+                        (false, true) => SyncAction::Download(path.clone()),
+                        (true, true) => SyncAction::Conflict(path.clone()),
+                    }
+                }
+                (Some(_), Some(_), None) => SyncAction::Conflict(path.clone()),
+                (Some(hash), None, Some(synced)) if hash == &synced.hash => SyncAction::DeleteLocal(path.clone()),
+                (Some(_), None, _) => SyncAction::Upload(path.clone()),
+                (None, Some(etag), Some(synced)) if etag == &synced.revision => SyncAction::DeleteRemote(path.clone()),
+                (None, Some(_), _) => SyncAction::Download(path.clone()),
+                (None, None, _) => SyncAction::Forget(path.clone()),
+            };
+            Some(action)
+        })
+        .collect()
+}
+
+/// Two-way sync between a local folder and a [`SyncProvider`]
+#[derive(Default)]
+pub struct SyncService {
+    /// Held while a sync runs so manual and background syncs never overlap
+    running: tokio::sync::Mutex<()>,
+}
+
+impl SyncService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sync `root` with `remote`, keeping bookkeeping in `state_path`
+    pub async fn sync(&self, root: &Path, state_path: &Path, remote: &dyn SyncProvider) -> Result<SyncReport> {
+        let _guard = self.running.try_lock()
+            .map_err(|_| anyhow::anyhow!("A sync is already in progress"))?;
+
+        let mut state: SyncState = match tokio::fs::read_to_string(state_path).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Invalid sync state {:?}, starting over: {}", state_path, e);
+                SyncState::default()
+            }),
+            Err(_) => SyncState::default(),
+        };
+        let remote_id = remote.remote_id();
+        if state.remote != remote_id {
+            // Bookkeeping for another remote says nothing about this one
+            state = SyncState { remote: remote_id, files: BTreeMap::new() };
+        }
+
+        let local_root = root.to_path_buf();
+        let local = tokio::task::spawn_blocking(move || hash_local_files(&local_root))
+            .await
+            .context("Local scan task panicked")??;
+        let remote_files = remote.list().await?;
+        let actions = plan(&local, &remote_files, &state);
+        debug!("Sync of {:?}: {} local, {} remote, {} actions", root, local.len(), remote_files.len(), actions.len());
+
+        let mut report = SyncReport::default();
+        for action in actions {
+            if let Err(e) = self.apply(&action, root, remote, &local, &remote_files, &mut state, &mut report).await {
+                warn!("Sync action {:?} failed: {}", action, e);
+                report.errors.push(format!("{:?}: {}", action, e));
+            }
+        }
+
+        if let Some(parent) = state_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(state_path, serde_json::to_string_pretty(&state)?).await
+            .with_context(|| format!("Failed to write sync state: {:?}", state_path))?;
+
+        info!(
+            "Synced {:?}: {} up, {} down, {} conflicts, {} errors",
+            root, report.uploaded.len(), report.downloaded.len(), report.conflicts.len(), report.errors.len()
+        );
+        Ok(report)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn apply(
+        &self,
+        action: &SyncAction,
+        root: &Path,
+        remote: &dyn SyncProvider,
+        local: &BTreeMap<String, String>,
+        remote_files: &BTreeMap<String, String>,
+        state: &mut SyncState,
+        report: &mut SyncReport,
+    ) -> Result<()> {
+        match action {
+            SyncAction::Upload(path) => {
+                let data = tokio::fs::read(local_path(root, path)).await?;
+                let hash = sha256_hex(&data);
+                let etag = remote.put(path, data).await?;
+                state.files.insert(path.clone(), SyncedFile { hash, revision: etag });
+                report.uploaded.push(path.clone());
+            }
+            SyncAction::Download(path) => {
+                let data = remote.get(path).await?;
+                write_local(root, path, &data).await?;
+                state.files.insert(path.clone(), SyncedFile { hash: sha256_hex(&data), revision: remote_files[path].clone() });
+                report.downloaded.push(path.clone());
+            }
+            SyncAction::DeleteLocal(path) => {
+                tokio::fs::remove_file(local_path(root, path)).await?;
+                state.files.remove(path);
+                report.deleted_local.push(path.clone());
+            }
+            SyncAction::DeleteRemote(path) => {
+                remote.delete(path).await?;
+                state.files.remove(path);
+                report.deleted_remote.push(path.clone());
+            }
+            SyncAction::Conflict(path) => {
+                let remote_data = remote.get(path).await?;
+                let remote_hash = sha256_hex(&remote_data);
+                if remote_hash == local[path] {
+                    // Same edit made on both sides
+                    state.files.insert(path.clone(), SyncedFile { hash: remote_hash, revision: remote_files[path].clone() });
+                    return Ok(());
+                }
+
+                // Keep the local version in place and preserve the remote one beside it
+                let copy = conflict_copy_name(path, &chrono::Local::now().format("%Y-%m-%d %H%M%S").to_string());
+                write_local(root, &copy, &remote_data).await?;
+                let copy_etag = remote.put(&copy, remote_data).await?;
+                state.files.insert(copy.clone(), SyncedFile { hash: remote_hash, revision: copy_etag });
+
+                let data = tokio::fs::read(local_path(root, path)).await?;
+                let hash = sha256_hex(&data);
+                let etag = remote.put(path, data).await?;
+                state.files.insert(path.clone(), SyncedFile { hash, revision: etag });
+                warn!("Sync conflict on {}, remote version saved as {}", path, copy);
+                report.conflicts.push(copy);
+            }
+            SyncAction::Forget(path) => {
+                state.files.remove(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `notes/plan.md` becomes `notes/plan (conflict 2024-03-05 101500).md`
+fn conflict_copy_name(path: &str, timestamp: &str) -> String {
+    let (dir, name) = match path.rfind('/') {
+        Some(index) => (&path[..=index], &path[index + 1..]),
+        None => ("", path),
+    };
+    match name.rfind('.').filter(|&index| index > 0) {
+        Some(index) => format!("{}{} (conflict {}){}", dir, &name[..index], timestamp, &name[index..]),
+        None => format!("{}{} (conflict {})", dir, name, timestamp),
+    }
+}
+
+fn local_path(root: &Path, relative: &str) -> PathBuf {
+    relative.split('/').fold(root.to_path_buf(), |path, segment| path.join(segment))
+}
+
+async fn write_local(root: &Path, relative: &str, data: &[u8]) -> Result<()> {
+    let path = local_path(root, relative);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&path, data).await
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+/// Hash every non-hidden file under `root`, keyed by `/`-separated relative path
+fn hash_local_files(root: &Path) -> Result<BTreeMap<String, String>> {
+    let mut files = BTreeMap::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+
+    while let Some((dir, prefix)) = pending.pop() {
+        let entries = std::fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))?;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_hidden(&name) {
+                continue;
+            }
+            let relative = format!("{}{}", prefix, name);
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push((entry.path(), format!("{}/", relative)));
+            } else if file_type.is_file() {
+                files.insert(relative, sha256_hex(&std::fs::read(entry.path())?));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// WebDAV collection, e.g. a Nextcloud or ownCloud folder
+pub struct WebDavProvider {
+    client: reqwest::Client,
+    base: Url,
+    username: String,
+    password: Option<String>,
+    created_dirs: tokio::sync::Mutex<HashSet<String>>,
+}
+
+impl WebDavProvider {
+    pub fn new(settings: &SyncSettings, password: Option<String>) -> Result<Self> {
+        let base_url = settings.webdav_url.trim();
+        if base_url.is_empty() {
+            return Err(anyhow::anyhow!("No WebDAV server configured"));
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .unwrap_or_default();
+
+        let mut base = Url::parse(base_url).with_context(|| format!("Invalid WebDAV URL: {}", base_url))?;
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+
+        Ok(Self {
+            client,
+            base,
+            username: settings.username.clone(),
+            password,
+            created_dirs: tokio::sync::Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn url(&self, relative: &str) -> Url {
+        let mut url = self.base.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(relative.split('/').filter(|s| !s.is_empty()));
+        }
+        url
+    }
+
+    fn request(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        if self.username.is_empty() {
+            request
+        } else {
+            request.basic_auth(&self.username, self.password.as_ref())
+        }
+    }
+
+    /// Path of an `href` relative to the base collection, without a trailing slash
+    fn relative_path(&self, href: &str) -> Option<String> {
+        let url = self.base.join(href).ok()?;
+        let path = percent_decode_str(url.path()).decode_utf8().ok()?.to_string();
+        let base = percent_decode_str(self.base.path()).decode_utf8().ok()?.to_string();
+        let path = format!("{}/", path.trim_end_matches('/'));
+        let relative = path.strip_prefix(&base)?;
+        Some(relative.trim_end_matches('/').to_string())
+    }
+
+    async fn create_parents(&self, path: &str) -> Result<()> {
+        let segments: Vec<&str> = path.split('/').collect();
+        for depth in 1..segments.len() {
+            let dir = segments[..depth].join("/");
+            if self.created_dirs.lock().await.contains(&dir) {
+                continue;
+            }
+
+            let response = self.request(Method::from_bytes(b"MKCOL")?, self.url(&format!("{}/", dir))).send().await?;
+            // 405 means the collection already exists
+            if !response.status().is_success() && response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(anyhow::anyhow!("Failed to create remote folder {}: {}", dir, response.status()));
+            }
+            self.created_dirs.lock().await.insert(dir);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SyncProvider for WebDavProvider {
+    fn remote_id(&self) -> String {
+        format!("webdav:{}", self.base)
+    }
+
+    /// Walks the base collection one level at a time, since many servers refuse
+    /// `Depth: infinity`.
+    async fn list(&self) -> Result<BTreeMap<String, String>> {
+        let mut files = BTreeMap::new();
+        let mut pending = vec![String::new()];
+
+        while let Some(dir) = pending.pop() {
+            let xml = self.request(Method::from_bytes(b"PROPFIND")?, self.url(&dir))
+                .header("Depth", "1")
+                .header("Content-Type", "application/xml")
+                .body(PROPFIND_BODY)
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach WebDAV server {}", self.base))?
+                .error_for_status()
+                .context("WebDAV server rejected the listing")?
+                .text()
+                .await?;
+
+            for entry in parse_multistatus(&xml)? {
+                let Some(relative) = self.relative_path(&entry.href) else {
+                    continue;
+                };
+                if relative == dir.trim_end_matches('/') || relative.split('/').any(is_hidden) {
+                    continue;
+                }
+                if entry.is_collection {
+                    self.created_dirs.lock().await.insert(relative.clone());
+                    pending.push(format!("{}/", relative));
+                } else {
+                    files.insert(relative, entry.etag);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>> {
+        let response = self.request(Method::GET, self.url(path)).send().await?
+            .error_for_status()
+            .with_context(|| format!("Failed to download {}", path))?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn put(&self, path: &str, data: Vec<u8>) -> Result<String> {
+        self.create_parents(path).await?;
+
+        let response = self.request(Method::PUT, self.url(path)).body(data).send().await?
+            .error_for_status()
+            .with_context(|| format!("Failed to upload {}", path))?;
+        if let Some(etag) = response.headers().get("etag").and_then(|v| v.to_str().ok()) {
+            return Ok(etag.to_string());
+        }
+
+        // Not every server returns an ETag from PUT; ask for it
+        let xml = self.request(Method::from_bytes(b"PROPFIND")?, self.url(path))
+            .header("Depth", "0")
+            .body(PROPFIND_BODY)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(parse_multistatus(&xml)?.into_iter().next().map(|entry| entry.etag).unwrap_or_default())
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        let response = self.request(Method::DELETE, self.url(path)).send().await?;
+        if response.status() != StatusCode::NOT_FOUND {
+            response.error_for_status().with_context(|| format!("Failed to delete {}", path))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct DavEntry {
+    href: String,
+    etag: String,
+    is_collection: bool,
+}
+
+/// Parse a PROPFIND `multistatus` response, ignoring namespace prefixes
+fn parse_multistatus(xml: &str) -> Result<Vec<DavEntry>> {
+    let mut reader = Reader::from_str(xml);
+    let mut entries = Vec::new();
+    let mut current: Option<DavEntry> = None;
+    let mut field: Option<&'static str> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"response" => current = Some(DavEntry { href: String::new(), etag: String::new(), is_collection: false }),
+                b"href" => field = Some("href"),
+                b"getetag" => field = Some("etag"),
+                b"collection" => {
+                    if let Some(entry) = current.as_mut() {
+                        entry.is_collection = true;
+                    }
+                }
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"collection" => {
+                if let Some(entry) = current.as_mut() {
+                    entry.is_collection = true;
+                }
+            }
+            Event::Text(text) => {
+                if let (Some(entry), Some(field)) = (current.as_mut(), field) {
+                    let text = text.unescape()?;
+                    match field {
+                        "href" => entry.href.push_str(text.trim()),
+                        _ => entry.etag.push_str(text.trim()),
+                    }
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"response" => entries.extend(current.take()),
+                b"href" | b"getetag" => field = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_plan_detects_changes_on_each_side() {
+        let mut state = SyncState::default();
+        for (path, hash, etag) in [("same.md", "h1", "e1"), ("edited.md", "h2", "e2"), ("remote.md", "h3", "e3"),
+                                   ("both.md", "h4", "e4"), ("gone-local.md", "h5", "e5"), ("gone-remote.md", "h6", "e6")] {
+            state.files.insert(path.to_string(), SyncedFile { hash: hash.to_string(), revision: etag.to_string() });
+        }
+
+        let local = map(&[("same.md", "h1"), ("edited.md", "h2b"), ("remote.md", "h3"), ("both.md", "h4b"),
+                          ("gone-remote.md", "h6"), ("new.md", "h7")]);
+        let remote = map(&[("same.md", "e1"), ("edited.md", "e2"), ("remote.md", "e3b"), ("both.md", "e4b"),
+                           ("gone-local.md", "e5"), ("incoming.md", "e8")]);
+
+        assert_eq!(plan(&local, &remote, &state), vec![
+            SyncAction::Conflict("both.md".to_string()),
+            SyncAction::Upload("edited.md".to_string()),
+            SyncAction::DeleteRemote("gone-local.md".to_string()),
+            SyncAction::DeleteLocal("gone-remote.md".to_string()),
+            SyncAction::Download("incoming.md".to_string()),
+            SyncAction::Upload("new.md".to_string()),
+            SyncAction::Download("remote.md".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_multistatus() {
+        let xml = r#"<?xml version="1.0"?>
+            <d:multistatus xmlns:d="DAV:">
+              <d:response><d:href>/dav/Notes/</d:href>
+                <d:propstat><d:prop><d:getetag>"dir"</d:getetag><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+              </d:response>
+              <d:response><d:href>/dav/Notes/My%20Plan.md</d:href>
+                <d:propstat><d:prop><d:getetag>"abc"</d:getetag><d:resourcetype/></d:prop></d:propstat>
+              </d:response>
+            </d:multistatus>"#;
+
+        let entries = parse_multistatus(xml).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_collection);
+        assert_eq!(entries[1], DavEntry { href: "/dav/Notes/My%20Plan.md".to_string(), etag: "\"abc\"".to_string(), is_collection: false });
+
+        let settings = SyncSettings { webdav_url: "https://cloud.example.com/dav/Notes".to_string(), ..SyncSettings::default() };
+        let client = WebDavProvider::new(&settings, None).unwrap();
+        assert_eq!(client.relative_path(&entries[1].href).as_deref(), Some("My Plan.md"));
+        assert_eq!(client.relative_path(&entries[0].href).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_conflict_copy_name() {
+        assert_eq!(conflict_copy_name("notes/plan.md", "2024-03-05 101500"), "notes/plan (conflict 2024-03-05 101500).md");
+        assert_eq!(conflict_copy_name(".env", "t"), ".env (conflict t)");
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use unicode_width::UnicodeWidthStr;
+
+/// Text to put in place of the byte range `start..end` of a document
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TableEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnAlignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// A GFM pipe table as written in a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceTable {
+    pub start: usize, // source byte range of the table, without the trailing line break
+    pub end: usize,
+    /// Header first; cells are trimmed markdown, with `\|` escapes left in place
+    pub rows: Vec<Vec<String>>,
+    alignments: Vec<ColumnAlignment>,
+    indent: String,
+    line_ending: &'static str,
+}
+
+impl SourceTable {
+    /// The table re-aligned: every column padded to its widest cell, the
+    /// delimiter row rebuilt to match, and missing cells filled in
+    pub fn format(&self) -> String {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0).max(self.alignments.len());
+        let alignment = |column: usize| self.alignments.get(column).copied().unwrap_or(ColumnAlignment::None);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                self.rows.iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.width())
+                    .max()
+                    .unwrap_or(0)
+                    .max(3)
+            })
+            .collect();
+
+        let render_row = |cells: Vec<String>| format!("{}| {} |", self.indent, cells.join(" | "));
+        let mut lines = Vec::with_capacity(self.rows.len() + 1);
+        for (index, row) in self.rows.iter().enumerate() {
+            let cells = (0..columns)
+                .map(|column| pad_cell(row.get(column).map_or("", String::as_str), widths[column], alignment(column)))
+                .collect();
+            lines.push(render_row(cells));
+
+            if index == 0 {
+                let delimiters = (0..columns)
+                    .map(|column| {
+                        let width = widths[column];
+                        match alignment(column) {
+                            ColumnAlignment::None => "-".repeat(width),
+                            ColumnAlignment::Left => format!(":{}", "-".repeat(width - 1)),
+                            ColumnAlignment::Center => format!(":{}:", "-".repeat(width - 2)),
+                            ColumnAlignment::Right => format!("{}:", "-".repeat(width - 1)),
+                        }
+                    })
+                    .collect();
+                lines.push(render_row(delimiters));
+            }
+        }
+        lines.join(self.line_ending)
+    }
+
+    /// The table as CSV, header first, with cells unescaped and every row
+    /// padded to the same number of columns
+    ///
+    /// Starts with a UTF-8 byte order mark, which Excel needs to read anything
+    /// beyond ASCII correctly.
+    pub fn to_csv(&self) -> Result<String> {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0).max(self.alignments.len());
+        let mut writer = csv::Writer::from_writer(UTF8_BOM.as_bytes().to_vec());
+        for row in &self.rows {
+            let cells: Vec<String> = (0..columns)
+                .map(|column| row.get(column).map_or_else(String::new, |cell| unescape_cell(cell)))
+                .collect();
+            writer.write_record(&cells).context("Failed to write CSV row")?;
+        }
+
+        let bytes = writer.into_inner().context("Failed to finish CSV")?;
+        String::from_utf8(bytes).context("CSV is not valid UTF-8")
+    }
+}
+
+const UTF8_BOM: &str = "\u{feff}";
+
+/// Turn a cell's markdown back into plain text: `\|` becomes `|` and `<br>` a line break
+fn unescape_cell(cell: &str) -> String {
+    cell.replace("\\|", "|").replace("<br>", "\n").replace("<br/>", "\n")
+}
+
+fn pad_cell(cell: &str, width: usize, alignment: ColumnAlignment) -> String {
+    let padding = width.saturating_sub(cell.width());
+    match alignment {
+        ColumnAlignment::Right => format!("{}{}", " ".repeat(padding), cell),
+        ColumnAlignment::Center => {
+            let left = padding / 2;
+            format!("{}{}{}", " ".repeat(left), cell, " ".repeat(padding - left))
+        }
+        ColumnAlignment::None | ColumnAlignment::Left => format!("{}{}", cell, " ".repeat(padding)),
+    }
+}
+
+/// Byte ranges of the lines in `content`, without their line breaks
+fn line_ranges(content: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        ranges.push(start..start + text.len());
+        start += line.len();
+    }
+    if content.is_empty() || content.ends_with('\n') {
+        ranges.push(start..start);
+    }
+    ranges
+}
+
+/// Split a table row into its trimmed cells; the outer pipes are optional and
+/// `\|` doesn't end a cell
+fn split_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                cell.push(c);
+                if let Some(next) = chars.next() {
+                    cell.push(next);
+                }
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(c),
+        }
+    }
+    if !cell.trim().is_empty() {
+        cells.push(cell.trim().to_string());
+    }
+    cells
+}
+
+fn is_table_row(line: &str) -> bool {
+    !line.trim().is_empty() && line.replace("\\|", "").contains('|')
+}
+
+fn delimiter_row(line: &str) -> Option<Vec<ColumnAlignment>> {
+    let cells = split_row(line);
+    if cells.is_empty() {
+        return None;
+    }
+    cells.iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => ColumnAlignment::Center,
+                (true, false) => ColumnAlignment::Left,
+                (false, true) => ColumnAlignment::Right,
+                (false, false) => ColumnAlignment::None,
+            })
+        })
+        .collect()
+}
+
+/// The pipe table whose lines include byte `offset`, if there is one
+///
+/// A table is a header row, a delimiter row such as `| --- | :-: |` and the
+/// rows that follow up to the first line without a pipe.
+pub fn find_table(content: &str, offset: usize) -> Option<SourceTable> {
+    let lines = line_ranges(content);
+    let text = |index: usize| &content[lines[index].clone()];
+    let current = lines.iter().position(|line| offset <= line.end)?;
+    if !is_table_row(text(current)) {
+        return None;
+    }
+
+    let mut first = current;
+    while first > 0 && is_table_row(text(first - 1)) {
+        first -= 1;
+    }
+    let mut last = current;
+    while last + 1 < lines.len() && is_table_row(text(last + 1)) {
+        last += 1;
+    }
+
+    // The header is the nearest row at or above the cursor that has a delimiter row under it
+    let (header, alignments) = (first..=current)
+        .rev()
+        .filter(|&header| header < last)
+        .find_map(|header| {
+            let alignments = delimiter_row(text(header + 1))?;
+            (split_row(text(header)).len() == alignments.len()).then_some((header, alignments))
+        })?;
+
+    let rows = std::iter::once(header)
+        .chain(header + 2..=last)
+        .map(|index| split_row(text(index)))
+        .collect();
+    let header_line = text(header);
+    let indent = header_line[..header_line.len() - header_line.trim_start().len()].to_string();
+    let line_ending = if content[lines[header].end..].starts_with("\r\n") { "\r\n" } else { "\n" };
+
+    Some(SourceTable {
+        start: lines[header].start,
+        end: lines[last].end,
+        rows,
+        alignments,
+        indent,
+        line_ending,
+    })
+}
+
+/// The table starting in the source range `start..end`, e.g. a table block
+/// from the scroll map
+pub fn table_in_range(content: &str, start: usize, end: usize) -> Result<SourceTable> {
+    find_table(content, start)
+        .filter(|table| table.start < end && start <= table.end)
+        .context("No table found in the selected range")
+}
+
+/// Re-align the table under the cursor; `None` when the cursor isn't in a table
+pub fn format_table_at(content: &str, cursor_offset: usize) -> Option<TableEdit> {
+    let table = find_table(content, cursor_offset)?;
+    Some(TableEdit {
+        start: table.start,
+        end: table.end,
+        text: table.format(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table_realigns_cells() {
+        let content = "Intro | not a table\n\n|Name|Qty|Note|\n|:-|--:|:-:|\n|apple|3|\n|kiwi \\| lime|12|ripe|x|\n\nAfter";
+        let cursor = content.find("kiwi").unwrap();
+
+        let edit = format_table_at(content, cursor).unwrap();
+        assert_eq!(&content[edit.start..edit.end], "|Name|Qty|Note|\n|:-|--:|:-:|\n|apple|3|\n|kiwi \\| lime|12|ripe|x|");
+        assert_eq!(
+            edit.text,
+            "| Name         | Qty | Note |     |\n\
+             | :----------- | --: | :--: | --- |\n\
+             | apple        |   3 |      |     |\n\
+             | kiwi \\| lime |  12 | ripe | x   |"
+        );
+
+        assert!(format_table_at(content, 0).is_none());
+        assert!(format_table_at(content, content.len()).is_none());
+    }
+
+    #[test]
+    fn test_table_to_csv() {
+        let content = "# Data\n\n| City | Note |\n| --- | --- |\n| Zürich | a \\| b, \"c\" |\n| Oslo |\n";
+        let start = content.find('|').unwrap();
+
+        let csv = table_in_range(content, start, content.len()).unwrap().to_csv().unwrap();
+        assert_eq!(csv, "\u{feff}City,Note\nZürich,\"a | b, \"\"c\"\"\"\nOslo,\n");
+        assert!(table_in_range(content, 0, 6).is_err());
+    }
+
+    #[test]
+    fn test_find_table_needs_delimiter_row() {
+        assert!(find_table("a | b\nc | d\n", 0).is_none());
+
+        let content = "  x | y\r\n  --|--\r\n  1 | 2\r\n";
+        let table = find_table(content, content.len() - 3).unwrap();
+        assert_eq!((table.start, table.end), (0, content.len() - 2));
+        assert_eq!(table.rows, vec![vec!["x", "y"], vec!["1", "2"]]);
+        assert_eq!(table.format(), "  | x   | y   |\r\n  | --- | --- |\r\n  | 1   | 2   |");
+    }
+}
@@ -0,0 +1,327 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Edit distance past which the changed middle of two texts is treated as
+/// rewritten wholesale, which bounds the time and memory a diff can take
+const MAX_EDITS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffTag {
+    Equal,
+    Delete,
+    Insert,
+    /// Tokens deleted and others inserted in their place
+    Replace,
+}
+
+/// A run of tokens; `old` and `new` index the two token lists
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffOp {
+    pub tag: DiffTag,
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Shortest edit from `old` to `new` (Myers' algorithm), as alternating runs of
+/// equal tokens and changes
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let steps = shortest_edit(a, b).unwrap_or_else(|| {
+        let mut steps = vec![Step::Delete; a.len()];
+        steps.resize(a.len() + b.len(), Step::Insert);
+        steps
+    });
+
+    let mut ops = Vec::new();
+    push_op(&mut ops, DiffTag::Equal, 0..prefix, 0..prefix);
+    let (mut i, mut j) = (prefix, prefix);
+    let mut change_start = None;
+    for step in steps {
+        match step {
+            Step::Equal => {
+                if let Some((old_start, new_start)) = change_start.take() {
+                    push_op(&mut ops, DiffTag::Replace, old_start..i, new_start..j);
+                }
+                push_op(&mut ops, DiffTag::Equal, i..i + 1, j..j + 1);
+                i += 1;
+                j += 1;
+            }
+            Step::Delete => {
+                change_start.get_or_insert((i, j));
+                i += 1;
+            }
+            Step::Insert => {
+                change_start.get_or_insert((i, j));
+                j += 1;
+            }
+        }
+    }
+    if let Some((old_start, new_start)) = change_start {
+        push_op(&mut ops, DiffTag::Replace, old_start..i, new_start..j);
+    }
+    push_op(&mut ops, DiffTag::Equal, i..i + suffix, j..j + suffix);
+    ops
+}
+
+/// Append a run, merging it into the last one when they're both equal runs
+fn push_op(ops: &mut Vec<DiffOp>, tag: DiffTag, old: Range<usize>, new: Range<usize>) {
+    if old.is_empty() && new.is_empty() {
+        return;
+    }
+    let tag = match tag {
+        DiffTag::Replace if new.is_empty() => DiffTag::Delete,
+        DiffTag::Replace if old.is_empty() => DiffTag::Insert,
+        tag => tag,
+    };
+    match ops.last_mut() {
+        Some(last) if last.tag == DiffTag::Equal && tag == DiffTag::Equal => {
+            last.old.end = old.end;
+            last.new.end = new.end;
+        }
+        _ => ops.push(DiffOp { tag, old, new }),
+    }
+}
+
+fn shortest_edit<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Step>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let limit = (a.len() + b.len()).min(MAX_EDITS) as isize;
+    let offset = limit + 1;
+    // Furthest x reached on each diagonal k = x - y
+    let mut v = vec![0isize; 2 * limit as usize + 3];
+    // v as it was at the start of each round, for the diagonals that round reads
+    let mut trace = Vec::new();
+
+    for d in 0..=limit {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| v[(offset + k) as usize];
+            let mut x = if k == -d || (k != d && at(k - 1) < at(k + 1)) { at(k + 1) } else { at(k - 1) + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Step> {
+    let (mut x, mut y) = (n, m);
+    let mut steps = Vec::new();
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            steps.push(Step::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            steps.push(if x == prev_x { Step::Insert } else { Step::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    steps.reverse();
+    steps
+}
+
+/// Lines without their endings, so `\r\n` and `\n` files compare equal
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.lines().collect()
+}
+
+/// Words, runs of whitespace, and single other characters; joined they give back `text`
+pub fn split_words(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let same_run = |next: char| {
+            (c.is_alphanumeric() && next.is_alphanumeric()) || (c.is_whitespace() && next.is_whitespace())
+        };
+        if !chars.peek().is_some_and(|&(_, next)| same_run(next)) {
+            tokens.push(&text[start..i + c.len_utf8()]);
+            start = i + c.len_utf8();
+        }
+    }
+    tokens
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+/// One line of a hunk; line numbers are 1-based, and absent on the side the line isn't in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+}
+
+/// A group of nearby changes with the unchanged lines around them, as in a unified diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffHunk {
+    /// 1-based first line and line count on each side
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+impl DiffHunk {
+    fn push(&mut self, kind: DiffLineKind, text: &str, old_line: Option<usize>, new_line: Option<usize>) {
+        self.old_lines += usize::from(old_line.is_some());
+        self.new_lines += usize::from(new_line.is_some());
+        self.lines.push(DiffLine { kind, text: text.to_string(), old_line, new_line });
+    }
+
+    fn context(&mut self, old_lines: &[&str], old: Range<usize>, new_start: usize) {
+        for (i, line) in old.clone().enumerate() {
+            self.push(DiffLineKind::Context, old_lines[line], Some(line + 1), Some(new_start + i + 1));
+        }
+    }
+}
+
+/// Line changes from `old` to `new` grouped into hunks with `context` unchanged
+/// lines on either side; changes closer than twice that share a hunk
+pub fn line_hunks(old: &str, new: &str, context: usize) -> Vec<DiffHunk> {
+    let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+    let ops = diff(&old_lines, &new_lines);
+
+    let mut hunks = Vec::new();
+    let mut current: Option<DiffHunk> = None;
+    for (index, op) in ops.iter().enumerate() {
+        if op.tag == DiffTag::Equal {
+            if let Some(mut hunk) = current.take() {
+                let last = index == ops.len() - 1;
+                let shown = if !last && op.old.len() <= 2 * context { op.old.len() } else { context.min(op.old.len()) };
+                hunk.context(&old_lines, op.old.start..op.old.start + shown, op.new.start);
+                if shown == op.old.len() && !last {
+                    current = Some(hunk);
+                } else {
+                    hunks.push(hunk);
+                }
+            }
+            continue;
+        }
+
+        let hunk = current.get_or_insert_with(|| {
+            let lead = match index.checked_sub(1).map(|i| &ops[i]) {
+                Some(previous) => context.min(previous.old.len()),
+                None => 0,
+            };
+            let (old_start, new_start) = (op.old.start - lead, op.new.start - lead);
+            let mut hunk = DiffHunk { old_start: old_start + 1, old_lines: 0, new_start: new_start + 1, new_lines: 0, lines: Vec::new() };
+            hunk.context(&old_lines, old_start..op.old.start, new_start);
+            hunk
+        });
+        for line in op.old.clone() {
+            hunk.push(DiffLineKind::Removed, old_lines[line], Some(line + 1), None);
+        }
+        for line in op.new.clone() {
+            hunk.push(DiffLineKind::Added, new_lines[line], None, Some(line + 1));
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &[&str], new: &[&str], ops: &[DiffOp]) -> Vec<String> {
+        let mut output = Vec::new();
+        for op in ops {
+            match op.tag {
+                DiffTag::Equal => {
+                    assert_eq!(old[op.old.clone()], new[op.new.clone()]);
+                    output.extend(old[op.old.clone()].iter().map(|s| s.to_string()));
+                }
+                _ => output.extend(new[op.new.clone()].iter().map(|s| s.to_string())),
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_line_diff() {
+        let old = split_lines("# Draft\n\nThe quick fox.\nIt jumps.\nThe end.\n");
+        let new = split_lines("# Draft\r\n\r\nThe quick brown fox.\nThe end.\nP.S.\n");
+        let ops = diff(&old, &new);
+
+        let tags: Vec<_> = ops.iter().map(|op| op.tag).collect();
+        assert_eq!(tags, vec![DiffTag::Equal, DiffTag::Replace, DiffTag::Equal, DiffTag::Insert]);
+        assert_eq!((ops[1].old.clone(), ops[1].new.clone()), (2..4, 2..3));
+        assert_eq!(apply(&old, &new, &ops), new);
+
+        assert_eq!(diff(&old, &old).len(), 1);
+        assert_eq!(diff::<&str>(&[], &[]), vec![]);
+        assert_eq!(diff(&[], &new)[0].tag, DiffTag::Insert);
+    }
+
+    #[test]
+    fn test_word_diff_and_large_rewrites() {
+        assert_eq!(split_words("Grüße, world  2x!"), vec!["Grüße", ",", " ", "world", "  ", "2x", "!"]);
+
+        let old = split_words("The quick fox jumps");
+        let new = split_words("The quick brown fox leaps");
+        let ops = diff(&old, &new);
+        assert_eq!(apply(&old, &new, &ops).concat(), "The quick brown fox leaps");
+        assert!(ops.iter().any(|op| op.tag == DiffTag::Insert && new[op.new.clone()].concat() == "brown "));
+
+        // Past the edit limit the changed middle becomes one replacement
+        let old: Vec<String> = (0..3000).map(|i| format!("a{}", i)).collect();
+        let new: Vec<String> = (0..3000).map(|i| format!("b{}", i)).collect();
+        let ops = diff(&old, &new);
+        assert_eq!(ops, vec![DiffOp { tag: DiffTag::Replace, old: 0..3000, new: 0..3000 }]);
+    }
+
+    #[test]
+    fn test_line_hunks() {
+        let old: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let new = old.replace("line 3\n", "line three\n").replace("line 6\n", "").replace("line 18\n", "line 18\nline 18b\n");
+        let hunks = line_hunks(&old, &new, 2);
+
+        assert_eq!(hunks.len(), 2);
+        // The changes at lines 3 and 6 are close enough to share a hunk
+        assert_eq!((hunks[0].old_start, hunks[0].old_lines, hunks[0].new_start, hunks[0].new_lines), (1, 8, 1, 7));
+        assert_eq!(hunks[0].lines[2], DiffLine { kind: DiffLineKind::Removed, text: "line 3".to_string(), old_line: Some(3), new_line: None });
+        assert_eq!(hunks[0].lines[3], DiffLine { kind: DiffLineKind::Added, text: "line three".to_string(), old_line: None, new_line: Some(3) });
+        assert_eq!((hunks[1].old_start, hunks[1].old_lines, hunks[1].new_start, hunks[1].new_lines), (17, 4, 16, 5));
+        assert_eq!(hunks[1].lines[2].new_line, Some(18));
+        assert_eq!(hunks[1].lines.last().unwrap().text, "line 20");
+
+        assert!(line_hunks(&old, &old, 3).is_empty());
+        let created = line_hunks("", "a\nb\n", 3);
+        assert_eq!((created[0].old_start, created[0].old_lines, created[0].new_lines), (1, 0, 2));
+    }
+}
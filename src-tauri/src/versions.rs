@@ -0,0 +1,204 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::process::Command;
+
+use crate::encryption::is_encrypted_path;
+use crate::settings::app_config_dir;
+use crate::text_diff::{line_hunks, DiffHunk, DiffLineKind};
+
+/// Unchanged lines shown around each change
+pub const DIFF_CONTEXT_LINES: usize = 3;
+
+/// How long `git show` may take before the Git base is given up on
+const GIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Earlier version of a document to compare the editor's content against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiffBase {
+    /// The file as last saved
+    Disk,
+    /// A snapshot taken with `SnapshotStore::create`
+    Snapshot { id: String },
+    /// The file as committed at HEAD in its Git repository
+    GitHead,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub created_at: u64, // Unix timestamp
+    pub size: u64,
+}
+
+/// Changes in the editor's content relative to an earlier version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentDiff {
+    pub base: DiffBase,
+    pub hunks: Vec<DiffHunk>,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl DocumentDiff {
+    pub fn new(base: DiffBase, old: &str, new: &str, context: usize) -> Self {
+        let hunks = line_hunks(old, new, context);
+        let count = |kind| hunks.iter().flat_map(|hunk| &hunk.lines).filter(|line| line.kind == kind).count();
+        let (added, removed) = (count(DiffLineKind::Added), count(DiffLineKind::Removed));
+        Self { base, hunks, added, removed }
+    }
+}
+
+/// Point-in-time copies of documents, kept outside the documents themselves
+///
+/// Each document gets a folder named after a hash of its path, holding one
+/// `<id>.md` file per snapshot. Ids start with the creation time in
+/// milliseconds, so they sort oldest first.
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl Default for SnapshotStore {
+    fn default() -> Self {
+        Self { dir: app_config_dir().join("snapshots") }
+    }
+}
+
+impl SnapshotStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_dir(mut self, dir: PathBuf) -> Self {
+        self.dir = dir;
+        self
+    }
+
+    pub async fn create(&self, document: &Path, content: &str) -> Result<SnapshotInfo> {
+        // Snapshots are stored in plain text, which would undo the encryption
+        if is_encrypted_path(document) {
+            anyhow::bail!("Snapshots aren't available for encrypted documents");
+        }
+
+        let dir = self.document_dir(document);
+        tokio::fs::create_dir_all(&dir).await
+            .with_context(|| format!("Failed to create snapshot folder: {:?}", dir))?;
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let id = format!("{:013}-{}", millis, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+        let path = dir.join(format!("{}.md", id));
+        tokio::fs::write(&path, content).await
+            .with_context(|| format!("Failed to write snapshot: {:?}", path))?;
+
+        Ok(SnapshotInfo { created_at: (millis / 1000) as u64, size: content.len() as u64, id })
+    }
+
+    /// The document's snapshots, newest first
+    pub async fn list(&self, document: &Path) -> Result<Vec<SnapshotInfo>> {
+        let dir = self.document_dir(document);
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read snapshot folder: {:?}", dir)),
+        };
+
+        let mut snapshots = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()).filter(|_| path.extension().is_some_and(|ext| ext == "md")) else {
+                continue;
+            };
+            let Some(millis) = id.split('-').next().and_then(|millis| millis.parse::<u64>().ok()) else {
+                continue;
+            };
+            let size = entry.metadata().await.map(|metadata| metadata.len()).unwrap_or(0);
+            snapshots.push(SnapshotInfo { id: id.to_string(), created_at: millis / 1000, size });
+        }
+        snapshots.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(snapshots)
+    }
+
+    pub async fn read(&self, document: &Path, id: &str) -> Result<String> {
+        // Ids come from the frontend and become file names
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            anyhow::bail!("Invalid snapshot id: {}", id);
+        }
+        let path = self.document_dir(document).join(format!("{}.md", id));
+        tokio::fs::read_to_string(&path).await
+            .with_context(|| format!("Snapshot not found: {}", id))
+    }
+
+    fn document_dir(&self, document: &Path) -> PathBuf {
+        let digest = Sha256::digest(document.to_string_lossy().as_bytes());
+        let name: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(name)
+    }
+}
+
+/// The document as committed at HEAD in the Git repository it belongs to
+pub async fn git_head_version(document: &Path) -> Result<String> {
+    let dir = document.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = document.file_name().and_then(|name| name.to_str()).context("Document has no file name")?;
+
+    // `HEAD:./name` is resolved relative to the working directory given by -C
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["show", &format!("HEAD:./{}", name)])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(GIT_TIMEOUT, output)
+        .await
+        .map_err(|_| anyhow::anyhow!("git show timed out after {:?}", GIT_TIMEOUT))?
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "No committed version of {:?}: {}",
+            document,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).context("Committed version is not valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_snapshots_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new().with_dir(dir.path().to_path_buf());
+        let document = Path::new("/notes/plan.md");
+
+        let first = store.create(document, "# Plan\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let second = store.create(document, "# Plan\n\nShip it.\n").await.unwrap();
+
+        let listed = store.list(document).await.unwrap();
+        assert_eq!(listed, vec![second.clone(), first.clone()]);
+        assert_eq!(store.read(document, &first.id).await.unwrap(), "# Plan\n");
+        assert!(store.list(Path::new("/notes/other.md")).await.unwrap().is_empty());
+
+        assert!(store.read(document, "../../secrets").await.is_err());
+        assert!(store.create(Path::new("/notes/diary.md.enc"), "x").await.is_err());
+    }
+
+    #[test]
+    fn test_document_diff_and_base_serialization() {
+        let diff = DocumentDiff::new(DiffBase::Disk, "a\nb\nc\n", "a\nB\nc\nd\n", DIFF_CONTEXT_LINES);
+        assert_eq!((diff.hunks.len(), diff.added, diff.removed), (1, 2, 1));
+
+        let base: DiffBase = serde_json::from_str(r#"{"kind":"snapshot","id":"1700000000000-abcd1234"}"#).unwrap();
+        assert_eq!(base, DiffBase::Snapshot { id: "1700000000000-abcd1234".to_string() });
+        assert_eq!(serde_json::from_str::<DiffBase>(r#"{"kind":"git_head"}"#).unwrap(), DiffBase::GitHead);
+    }
+}
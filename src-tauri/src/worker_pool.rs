@@ -0,0 +1,128 @@
+use anyhow::{Result, Context};
+use rayon::prelude::*;
+use std::sync::{Arc, RwLock};
+use tokio::sync::oneshot;
+use tracing::{error, info};
+
+/// Threads to use when the settings leave it unset: every core but one, which
+/// stays free for the UI and the async runtime
+pub fn default_parallelism() -> usize {
+    std::thread::available_parallelism()
+        .map(|cores| cores.get().saturating_sub(1))
+        .unwrap_or(1)
+        .max(1)
+}
+
+fn build_pool(threads: usize) -> Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|index| format!("typolite-worker-{}", index))
+        // Without a handler rayon aborts the app when a job panics
+        .panic_handler(|_| error!("A worker pool job panicked"))
+        .build()
+        .context("Failed to start the worker pool")
+}
+
+/// Shared CPU pool for batch work (site builds, indexing, multi-file search), kept
+/// off the async runtime so a large batch can't stall commands or the UI
+///
+/// Clones share the same threads; resizing swaps in a new pool while jobs
+/// already running finish on the old one.
+#[derive(Clone)]
+pub struct WorkerPool {
+    pool: Arc<RwLock<Arc<rayon::ThreadPool>>>,
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self {
+        Self::with_parallelism(default_parallelism())
+    }
+}
+
+impl WorkerPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_parallelism(threads: usize) -> Self {
+        let pool = build_pool(threads.max(1)).expect("Failed to start the worker pool");
+        Self { pool: Arc::new(RwLock::new(Arc::new(pool))) }
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.pool.read().unwrap().current_num_threads()
+    }
+
+    /// Resize the pool; `None` goes back to the default for this machine
+    pub fn set_parallelism(&self, threads: Option<usize>) -> Result<()> {
+        let threads = threads.unwrap_or_else(default_parallelism).max(1);
+        if threads == self.parallelism() {
+            return Ok(());
+        }
+
+        let pool = build_pool(threads)?;
+        *self.pool.write().unwrap() = Arc::new(pool);
+        info!("Worker pool resized to {} threads", threads);
+        Ok(())
+    }
+
+    /// Run `job` over every item in parallel, returning the results in item order
+    pub async fn map<T, R, F>(&self, items: Vec<T>, job: F) -> Result<Vec<R>>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let pool = self.pool.read().unwrap().clone();
+        let (sender, receiver) = oneshot::channel();
+        pool.spawn(move || {
+            let results = items.into_par_iter().map(job).collect();
+            // Nobody is waiting if the caller was dropped
+            let _ = sender.send(results);
+        });
+
+        receiver.await.context("A worker pool job failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_map_keeps_item_order() {
+        let pool = WorkerPool::with_parallelism(4);
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+
+        let seen = threads.clone();
+        let squares = pool.map((0..200u64).collect(), move |n| {
+            seen.lock().unwrap().insert(std::thread::current().name().map(str::to_string));
+            n * n
+        }).await.unwrap();
+
+        assert_eq!(squares, (0..200u64).map(|n| n * n).collect::<Vec<_>>());
+        let threads = threads.lock().unwrap();
+        assert!(threads.iter().all(|name| name.as_deref().is_some_and(|name| name.starts_with("typolite-worker-"))));
+    }
+
+    #[tokio::test]
+    async fn test_resize_is_shared_by_clones() {
+        let pool = WorkerPool::with_parallelism(2);
+        let clone = pool.clone();
+
+        clone.set_parallelism(Some(3)).unwrap();
+        assert_eq!(pool.parallelism(), 3);
+        pool.set_parallelism(None).unwrap();
+        assert_eq!(clone.parallelism(), default_parallelism());
+
+        let panicked = pool.map(vec![1], |_: i32| -> i32 { panic!("job failed") }).await;
+        assert!(panicked.is_err());
+        assert_eq!(pool.map(vec![1, 2], |n| n + 1).await.unwrap(), vec![2, 3]);
+    }
+}
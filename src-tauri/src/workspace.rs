@@ -0,0 +1,288 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::file_service::FileMetadata;
+use crate::settings::{app_config_dir, ExportOnSaveRule};
+
+/// Most recently opened files remembered per workspace
+const MAX_RECENT_FILES: usize = 20;
+
+/// A root directory with its own recent files, settings and file index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    pub root: PathBuf,
+    #[serde(default)]
+    pub settings: WorkspaceSettings,
+    #[serde(default)]
+    pub recent_files: Vec<PathBuf>,
+    pub last_opened: u64, // Unix timestamp
+}
+
+impl Workspace {
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+}
+
+/// Settings that override the global ones while the workspace is active
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceSettings {
+    pub spellcheck_language: Option<String>,
+    pub export_dir: Option<PathBuf>,
+    /// Exports run after saving documents in the workspace
+    pub export_on_save: Vec<ExportOnSaveRule>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct WorkspaceRegistry {
+    active: Option<String>,
+    workspaces: Vec<Workspace>,
+}
+
+/// Keeps track of known workspaces and which one is active
+///
+/// The registry lives in `workspaces.json`; each workspace also gets its own
+/// directory (keyed by id) holding its file index.
+pub struct WorkspaceManager {
+    dir: PathBuf,
+    registry: Mutex<WorkspaceRegistry>,
+}
+
+impl Default for WorkspaceManager {
+    fn default() -> Self {
+        Self::load(app_config_dir().join("workspaces"))
+    }
+}
+
+impl WorkspaceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the workspace registry from `dir`, starting empty if it is missing or invalid
+    pub fn load(dir: PathBuf) -> Self {
+        let path = dir.join("workspaces.json");
+        let registry = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Invalid workspace registry {:?}, starting empty: {}", path, e);
+                WorkspaceRegistry::default()
+            }),
+            Err(_) => WorkspaceRegistry::default(),
+        };
+
+        Self {
+            dir,
+            registry: Mutex::new(registry),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Workspace> {
+        let mut workspaces = self.registry.lock().unwrap().workspaces.clone();
+        workspaces.sort_by(|a, b| b.last_opened.cmp(&a.last_opened));
+        workspaces
+    }
+
+    pub fn active(&self) -> Option<Workspace> {
+        let registry = self.registry.lock().unwrap();
+        let active = registry.active.as_ref()?;
+        registry.workspaces.iter().find(|w| &w.id == active).cloned()
+    }
+
+    /// Open the workspace rooted at `root`, registering it on first use, and make it active
+    pub async fn open(&self, root: &Path, name: Option<String>) -> Result<Workspace> {
+        let root = tokio::fs::canonicalize(root).await
+            .with_context(|| format!("Workspace root not found: {:?}", root))?;
+        if !root.is_dir() {
+            return Err(anyhow::anyhow!("Workspace root is not a directory: {:?}", root));
+        }
+
+        let workspace = self.modify(|registry| {
+            let index = match registry.workspaces.iter().position(|w| w.root == root) {
+                Some(index) => index,
+                None => {
+                    let default_name = root.file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_else(|| root.to_string_lossy().to_string());
+                    registry.workspaces.push(Workspace {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        name: default_name,
+                        root: root.clone(),
+                        settings: WorkspaceSettings::default(),
+                        recent_files: Vec::new(),
+                        last_opened: 0,
+                    });
+                    registry.workspaces.len() - 1
+                }
+            };
+
+            let workspace = &mut registry.workspaces[index];
+            if let Some(name) = name {
+                workspace.name = name;
+            }
+            workspace.last_opened = unix_now();
+            registry.active = Some(workspace.id.clone());
+            Ok(workspace.clone())
+        }).await?;
+
+        info!("Opened workspace '{}' at {:?}", workspace.name, workspace.root);
+        Ok(workspace)
+    }
+
+    /// Make a previously opened workspace active
+    pub async fn switch(&self, id: &str) -> Result<Workspace> {
+        let workspace = self.modify(|registry| {
+            let workspace = registry.workspaces.iter_mut()
+                .find(|w| w.id == id)
+                .ok_or_else(|| anyhow::anyhow!("Unknown workspace: {}", id))?;
+            workspace.last_opened = unix_now();
+            let workspace = workspace.clone();
+            registry.active = Some(workspace.id.clone());
+            Ok(workspace)
+        }).await?;
+
+        info!("Switched to workspace '{}'", workspace.name);
+        Ok(workspace)
+    }
+
+    pub async fn update_settings(&self, id: &str, settings: WorkspaceSettings) -> Result<Workspace> {
+        self.modify(|registry| {
+            let workspace = registry.workspaces.iter_mut()
+                .find(|w| w.id == id)
+                .ok_or_else(|| anyhow::anyhow!("Unknown workspace: {}", id))?;
+            workspace.settings = settings;
+            Ok(workspace.clone())
+        }).await
+    }
+
+    /// Remember a file as recently opened in the active workspace, if it belongs to it
+    pub async fn record_recent_file(&self, path: &Path) -> Result<()> {
+        let Some(active) = self.active() else {
+            return Ok(());
+        };
+        if !active.contains(path) {
+            return Ok(());
+        }
+
+        self.modify(|registry| {
+            if let Some(workspace) = registry.workspaces.iter_mut().find(|w| w.id == active.id) {
+                workspace.recent_files.retain(|p| p != path);
+                workspace.recent_files.insert(0, path.to_path_buf());
+                workspace.recent_files.truncate(MAX_RECENT_FILES);
+            }
+            Ok(())
+        }).await
+    }
+
+    /// Cached markdown file index of a workspace, empty until first indexed
+    pub async fn load_index(&self, id: &str) -> Vec<FileMetadata> {
+        match tokio::fs::read_to_string(self.index_path(id)).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub async fn save_index(&self, id: &str, files: &[FileMetadata]) -> Result<()> {
+        let path = self.index_path(id);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await
+                .with_context(|| format!("Failed to create workspace directory: {:?}", parent))?;
+        }
+
+        tokio::fs::write(&path, serde_json::to_string(files)?).await
+            .with_context(|| format!("Failed to write workspace index: {:?}", path))?;
+        debug!("Saved index of {} files for workspace {}", files.len(), id);
+        Ok(())
+    }
+
+    /// Where sync bookkeeping for a workspace is kept
+    pub fn sync_state_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).join("sync.json")
+    }
+
+    fn index_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).join("index.json")
+    }
+
+    /// Apply a change to the registry and persist it
+    async fn modify<T, F>(&self, change: F) -> Result<T>
+    where
+        F: FnOnce(&mut WorkspaceRegistry) -> Result<T>,
+    {
+        let (value, json) = {
+            let mut registry = self.registry.lock().unwrap();
+            let value = change(&mut registry)?;
+            (value, serde_json::to_string_pretty(&*registry)?)
+        };
+
+        tokio::fs::create_dir_all(&self.dir).await
+            .with_context(|| format!("Failed to create workspace directory: {:?}", self.dir))?;
+        let path = self.dir.join("workspaces.json");
+        tokio::fs::write(&path, json).await
+            .with_context(|| format!("Failed to write workspace registry: {:?}", path))?;
+
+        Ok(value)
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_open_and_switch_workspaces() {
+        let config = TempDir::new().unwrap();
+        let work = TempDir::new().unwrap();
+        let personal = TempDir::new().unwrap();
+        let manager = WorkspaceManager::load(config.path().to_path_buf());
+
+        let first = manager.open(work.path(), Some("Work".to_string())).await.unwrap();
+        let second = manager.open(personal.path(), None).await.unwrap();
+        assert_eq!(manager.active().unwrap().id, second.id);
+
+        // Reopening the same root reuses the existing workspace
+        let reopened = manager.open(work.path(), None).await.unwrap();
+        assert_eq!(reopened.id, first.id);
+        assert_eq!(reopened.name, "Work");
+
+        manager.switch(&second.id).await.unwrap();
+        let reloaded = WorkspaceManager::load(config.path().to_path_buf());
+        assert_eq!(reloaded.list().len(), 2);
+        assert_eq!(reloaded.active().unwrap().id, second.id);
+        assert!(manager.switch("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recent_files_are_per_workspace() {
+        let config = TempDir::new().unwrap();
+        let work = TempDir::new().unwrap();
+        let personal = TempDir::new().unwrap();
+        let manager = WorkspaceManager::load(config.path().to_path_buf());
+
+        let work_ws = manager.open(work.path(), None).await.unwrap();
+        let note = work_ws.root.join("note.md");
+        manager.record_recent_file(&note).await.unwrap();
+        manager.record_recent_file(&personal.path().join("other.md")).await.unwrap();
+
+        let personal_ws = manager.open(personal.path(), None).await.unwrap();
+        assert!(manager.active().unwrap().recent_files.is_empty());
+
+        manager.switch(&work_ws.id).await.unwrap();
+        assert_eq!(manager.active().unwrap().recent_files, vec![note]);
+        assert!(manager.load_index(&personal_ws.id).await.is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::settings::ZoteroSettings;
+
+/// How long to wait for the user to finish in Zotero's citation picker
+const PICKER_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoteroReference {
+    pub citekey: String,
+    pub title: String,
+    /// "Family, Given" for each author
+    pub authors: Vec<String>,
+    pub year: Option<i64>,
+    /// Pandoc citation ready to insert, e.g. `[@doe2020]`
+    pub citation: String,
+}
+
+/// CSL-JSON item as returned by Better BibTeX's `item.search`
+#[derive(Debug, Deserialize)]
+struct CslItem {
+    #[serde(alias = "citationKey", alias = "citation-key")]
+    citekey: Option<String>,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    author: Vec<CslName>,
+    issued: Option<CslDate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CslName {
+    family: Option<String>,
+    given: Option<String>,
+    /// Institutional authors have a single literal name
+    literal: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CslDate {
+    #[serde(rename = "date-parts", default)]
+    date_parts: Vec<Vec<Value>>,
+}
+
+/// Talks to Zotero through the Better BibTeX plugin's local HTTP endpoints
+pub struct ZoteroService {
+    client: reqwest::Client,
+}
+
+impl Default for ZoteroService {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+
+        Self { client }
+    }
+}
+
+impl ZoteroService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether Zotero is running with Better BibTeX installed
+    pub async fn is_available(&self, settings: &ZoteroSettings) -> bool {
+        let url = format!("{}/cayw?probe=true", endpoint(settings));
+        match self.client.get(&url).timeout(Duration::from_secs(2)).send().await {
+            Ok(response) => response.text().await.is_ok_and(|body| body.trim() == "ready"),
+            Err(_) => false,
+        }
+    }
+
+    /// Search the Zotero library by title, author, year or citation key
+    pub async fn search(&self, query: &str, settings: &ZoteroSettings) -> Result<Vec<ZoteroReference>> {
+        debug!("Searching Zotero for {:?}", query);
+
+        let result = self.call(settings, "item.search", json!([query])).await?;
+        let items: Vec<CslItem> = serde_json::from_value(result)
+            .context("Unexpected search results from Better BibTeX")?;
+
+        let references: Vec<ZoteroReference> = items.into_iter().filter_map(to_reference).collect();
+        info!("Zotero search for {:?} found {} references", query, references.len());
+        Ok(references)
+    }
+
+    /// Open Zotero's own citation picker and return the chosen citation, or
+    /// `None` if the user cancelled
+    pub async fn pick_citation(&self, settings: &ZoteroSettings) -> Result<Option<String>> {
+        let url = format!("{}/cayw?format=pandoc&brackets=1", endpoint(settings));
+        debug!("Opening Zotero citation picker");
+
+        let citation = self.client
+            .get(&url)
+            .timeout(PICKER_TIMEOUT)
+            .send()
+            .await
+            .context("Failed to reach Zotero; is it running with Better BibTeX installed?")?
+            .error_for_status()
+            .context("Zotero rejected the citation request")?
+            .text()
+            .await?;
+
+        let citation = citation.trim();
+        Ok((!citation.is_empty()).then(|| citation.to_string()))
+    }
+
+    /// Formatted bibliography HTML for the given citation keys
+    pub async fn bibliography(&self, citekeys: &[String], settings: &ZoteroSettings) -> Result<String> {
+        debug!("Fetching Zotero bibliography for {} references", citekeys.len());
+
+        let mut format = json!({ "contentType": "html" });
+        if let Some(style) = settings.style.as_deref().filter(|style| !style.is_empty()) {
+            format["id"] = json!(style);
+        }
+        let result = self.call(settings, "item.bibliography", json!([citekeys, format])).await?;
+
+        result.as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected bibliography from Better BibTeX"))
+    }
+
+    async fn call(&self, settings: &ZoteroSettings, method: &str, params: Value) -> Result<Value> {
+        let url = format!("{}/json-rpc", endpoint(settings));
+        let mut response: Value = self.client
+            .post(&url)
+            .json(&json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }))
+            .send()
+            .await
+            .context("Failed to reach Zotero; is it running with Better BibTeX installed?")?
+            .error_for_status()
+            .with_context(|| format!("Zotero rejected {}", method))?
+            .json()
+            .await
+            .context("Invalid response from Better BibTeX")?;
+
+        if let Some(error) = response.get("error") {
+            let message = error["message"].as_str().unwrap_or("unknown error");
+            return Err(anyhow::anyhow!("Better BibTeX {} failed: {}", method, message));
+        }
+        Ok(response["result"].take())
+    }
+}
+
+fn endpoint(settings: &ZoteroSettings) -> &str {
+    settings.endpoint.trim().trim_end_matches('/')
+}
+
+fn to_reference(item: CslItem) -> Option<ZoteroReference> {
+    // Items without a key can't be cited
+    let citekey = item.citekey.filter(|key| !key.is_empty())?;
+    let authors = item.author.into_iter()
+        .filter_map(|name| match (name.family, name.given, name.literal) {
+            (_, _, Some(literal)) => Some(literal),
+            (Some(family), Some(given), _) => Some(format!("{}, {}", family, given)),
+            (Some(family), None, _) => Some(family),
+            _ => None,
+        })
+        .collect();
+    let year = item.issued
+        .and_then(|date| date.date_parts.into_iter().next())
+        .and_then(|parts| parts.into_iter().next())
+        .and_then(|year| year.as_i64().or_else(|| year.as_str()?.parse().ok()));
+
+    Some(ZoteroReference {
+        citation: format!("[@{}]", citekey),
+        citekey,
+        title: item.title,
+        authors,
+        year,
+    })
+}
+
+/// Citation keys cited pandoc-style (`[@doe2020, p. 4; -@roe]` or `@doe2020`) in
+/// the visible text of rendered HTML, in order of first use
+pub fn cited_keys(html: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut in_tag = false;
+    let mut previous = ' ';
+
+    for (index, c) in html.char_indices() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            // Email addresses and the like have a word character before the `@`
+            '@' if !in_tag && (previous.is_whitespace() || matches!(previous, '[' | ';' | '-' | '(')) => {
+                let rest = &html[index + 1..];
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || "_:.#$%&+?~/-".contains(c)))
+                    .unwrap_or(rest.len());
+                // Trailing punctuation ends the sentence rather than the key
+                let key = rest[..end].trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_'));
+                if key.starts_with(|c: char| c.is_alphanumeric() || c == '_') && !keys.iter().any(|k| k == key) {
+                    keys.push(key.to_string());
+                }
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+
+    keys
+}
+
+/// Append a References section to rendered HTML
+pub fn append_bibliography(html: &str, bibliography: &str) -> String {
+    format!(
+        "{}\n<section class=\"references\">\n<h2>References</h2>\n{}\n</section>\n",
+        html, bibliography.trim()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cited_keys() {
+        let html = "<p>As shown [@doe2020, p. 4; -@roe:1999] and by @smith_j.</p>\
+                    <p><a href=\"mailto:ada@example.com\">ada@example.com</a> [@doe2020]</p>";
+
+        assert_eq!(cited_keys(html), vec!["doe2020", "roe:1999", "smith_j"]);
+    }
+
+    #[test]
+    fn test_to_reference() {
+        let item: CslItem = serde_json::from_value(json!({
+            "citationKey": "lovelace1843",
+            "title": "Notes on the Analytical Engine",
+            "author": [{ "family": "Lovelace", "given": "Ada" }, { "literal": "Royal Society" }],
+            "issued": { "date-parts": [["1843", 1]] }
+        })).unwrap();
+
+        let reference = to_reference(item).unwrap();
+        assert_eq!(reference.citation, "[@lovelace1843]");
+        assert_eq!(reference.authors, vec!["Lovelace, Ada", "Royal Society"]);
+        assert_eq!(reference.year, Some(1843));
+
+        let keyless: CslItem = serde_json::from_value(json!({ "title": "Draft" })).unwrap();
+        assert!(to_reference(keyless).is_none());
+    }
+}
@@ -0,0 +1,46 @@
+import { invoke } from '@tauri-apps/api/tauri';
+import { appWindow } from '@tauri-apps/api/window';
+import type { CommandResult } from '$lib/types';
+
+// The window opened by the quick-capture global shortcut; it's hidden rather
+// than closed so the next capture shows up instantly
+const text = document.getElementById('text') as HTMLTextAreaElement;
+const status = document.getElementById('status')!;
+
+async function dismiss() {
+  text.value = '';
+  status.textContent = 'Ctrl+Enter to save, Esc to dismiss';
+  status.classList.remove('error');
+  await appWindow.hide();
+}
+
+async function save() {
+  if (!text.value.trim()) {
+    await dismiss();
+    return;
+  }
+
+  const result = await invoke<CommandResult<unknown>>('append_quick_note', { text: text.value });
+  if (result.success) {
+    await dismiss();
+  } else {
+    status.textContent = result.error || 'Failed to save the note';
+    status.classList.add('error');
+  }
+}
+
+text.addEventListener('keydown', (event) => {
+  if (event.key === 'Escape') {
+    event.preventDefault();
+    dismiss();
+  } else if (event.key === 'Enter' && (event.ctrlKey || event.metaKey)) {
+    event.preventDefault();
+    save();
+  }
+});
+
+appWindow.onFocusChanged(({ payload: focused }) => {
+  if (focused) {
+    text.focus();
+  }
+});
@@ -28,6 +28,13 @@ export default defineConfig(async () => ({
     minify: !process.env.TAURI_DEBUG ? "esbuild" : false,
     // produce sourcemaps for debug builds
     sourcemap: !!process.env.TAURI_DEBUG,
+    // The quick capture window is its own small page
+    rollupOptions: {
+      input: {
+        main: path.resolve('index.html'),
+        'quick-capture': path.resolve('quick-capture.html'),
+      },
+    },
   },
 
   // Performance optimizations